@@ -71,6 +71,18 @@ pub struct GraphMessage {
     pub internet_message_id: Option<String>,
     pub body: Option<GraphBody>,
     pub internet_message_headers: Option<Vec<GraphHeader>>,
+    /// Follow-up flag; `flagStatus: "flagged"` is IMAP `\Flagged`.
+    #[serde(default)]
+    pub flag: Option<GraphFollowupFlag>,
+    /// Outlook categories — the closest Graph has to IMAP keywords.
+    #[serde(default)]
+    pub categories: Option<Vec<String>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GraphFollowupFlag {
+    pub flag_status: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -105,11 +117,112 @@ pub struct GraphListResponse<T> {
     pub next_link: Option<String>,
 }
 
+/// One page of `/messages/delta`. Exactly one of `next_link` (more pages in
+/// this round) and `delta_link` (round done — keep it for the next one) is set.
+#[derive(Debug, Default)]
+pub struct GraphDeltaPage {
+    /// Messages added or changed since the last round — every message on the
+    /// first one.
+    pub messages: Vec<GraphMessage>,
+    /// Ids deleted from, or moved out of, the folder.
+    pub removed: Vec<String>,
+    pub next_link: Option<String>,
+    pub delta_link: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GraphDeltaResponse {
+    value: Vec<GraphDeltaItem>,
+    #[serde(rename = "@odata.nextLink")]
+    next_link: Option<String>,
+    #[serde(rename = "@odata.deltaLink")]
+    delta_link: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GraphDeltaItem {
+    #[serde(flatten)]
+    message: GraphMessage,
+    #[serde(rename = "@removed")]
+    removed: Option<serde_json::Value>,
+}
+
+impl From<GraphDeltaResponse> for GraphDeltaPage {
+    fn from(resp: GraphDeltaResponse) -> Self {
+        let mut page = GraphDeltaPage {
+            next_link: resp.next_link,
+            delta_link: resp.delta_link,
+            ..Default::default()
+        };
+        for item in resp.value {
+            if item.removed.is_some() {
+                page.removed.push(item.message.id);
+            } else {
+                page.messages.push(item.message);
+            }
+        }
+        page
+    }
+}
+
+/// Well-known folders under the names the app gives them (IMAP-style), as
+/// `normalizeGraphFolderName` in graphConfig.js does.
+const APP_FOLDER_NAMES: &[(&str, &str)] = &[
+    ("Inbox", "INBOX"),
+    ("Sent Items", "Sent"),
+    ("Drafts", "Drafts"),
+    ("Deleted Items", "Trash"),
+    ("Junk Email", "Junk"),
+    ("Archive", "Archive"),
+];
+
+/// The app's mailbox name for a Graph folder — the cache and the vault are
+/// keyed by it.
+pub fn app_folder_name(display_name: &str) -> String {
+    APP_FOLDER_NAMES
+        .iter()
+        .find(|(graph, _)| *graph == display_name)
+        .map(|(_, app)| app.to_string())
+        .unwrap_or_else(|| display_name.to_string())
+}
+
+/// The special-use attribute an IMAP server would give the folder.
+pub fn special_use(display_name: &str) -> Option<&'static str> {
+    match display_name {
+        "Inbox" => Some("\\Inbox"),
+        "Sent Items" => Some("\\Sent"),
+        "Drafts" => Some("\\Drafts"),
+        "Deleted Items" => Some("\\Trash"),
+        "Junk Email" => Some("\\Junk"),
+        "Archive" => Some("\\Archive"),
+        _ => None,
+    }
+}
+
 // ---------------------------------------------------------------------------
 // GraphMessage → EmailHeader conversion
 // ---------------------------------------------------------------------------
 
 impl GraphMessage {
+    /// The IMAP flags this message should carry when appended to an IMAP
+    /// server: `\Seen`, `\Flagged`, and each category as a keyword.
+    pub fn append_flags(&self) -> Vec<String> {
+        let mut flags = Vec::new();
+        if self.is_read == Some(true) {
+            flags.push("\\Seen".to_string());
+        }
+        if self.flag.as_ref().and_then(|f| f.flag_status.as_deref()) == Some("flagged") {
+            flags.push("\\Flagged".to_string());
+        }
+        for category in self.categories.iter().flatten() {
+            let keyword = crate::imap::keyword_atom(category);
+            if !keyword.is_empty() && !flags.contains(&keyword) {
+                flags.push(keyword);
+            }
+        }
+        flags
+    }
+
     /// Convert a Graph API message into the app's standard `EmailHeader`.
     /// `uid` is a synthetic UID assigned by the caller (Graph messages use
     /// opaque string IDs, so callers map them to sequential u32 UIDs).
@@ -191,6 +304,11 @@ impl GraphMessage {
             list_unsubscribe: self.get_header("List-Unsubscribe"),
             list_id: self.get_header("List-Id"),
             precedence: self.get_header("Precedence"),
+            labels: Vec::new(),
+            gmail_msg_id: None,
+            gmail_thread_id: None,
+            email_id: None,
+            object_thread_id: None,
         }
     }
 
@@ -209,33 +327,51 @@ impl GraphMessage {
 // Graph API client
 // ---------------------------------------------------------------------------
 
-const GRAPH_BASE: &str = "https://graph.microsoft.com/v1.0";
+pub const GRAPH_BASE: &str = "https://graph.microsoft.com/v1.0";
 
 pub struct GraphClient {
     // pub (not pub(crate)) so src-tauri's migration.rs can issue custom
     // authenticated Graph requests not covered by GraphClient's own methods.
     pub client: Client,
     pub access_token: String,
+    /// `GRAPH_BASE`, or a mock server's in tests.
+    pub base: String,
 }
 
 impl GraphClient {
     pub fn new(access_token: &str) -> Self {
+        Self::with_base(access_token, GRAPH_BASE)
+    }
+
+    pub fn with_base(access_token: &str, base: &str) -> Self {
         Self {
             client: Client::new(),
             access_token: access_token.to_string(),
+            base: base.trim_end_matches('/').to_string(),
         }
     }
 
-    /// List all mail folders for the authenticated user.
+    /// List the top-level mail folders of the authenticated user. Their
+    /// subfolders come from `list_child_folders`.
     pub async fn list_folders(&self) -> Result<Vec<GraphMailFolder>, String> {
-        let url = format!("{}/me/mailFolders?$top=100", GRAPH_BASE);
+        let url = format!("{}/me/mailFolders?$top=100", self.base);
+        self.get_folders(&url, "list_folders").await
+    }
+
+    /// List the direct subfolders of a mail folder.
+    pub async fn list_child_folders(&self, parent_id: &str) -> Result<Vec<GraphMailFolder>, String> {
+        let url = format!("{}/me/mailFolders/{}/childFolders?$top=100", self.base, parent_id);
+        self.get_folders(&url, "list_child_folders").await
+    }
+
+    async fn get_folders(&self, url: &str, op: &str) -> Result<Vec<GraphMailFolder>, String> {
         let resp = self
             .client
-            .get(&url)
+            .get(url)
             .bearer_auth(&self.access_token)
             .send()
             .await
-            .map_err(|e| format!("Graph list_folders request failed: {}", e))?;
+            .map_err(|e| format!("Graph {} request failed: {}", op, e))?;
 
         let status = resp.status();
         if !status.is_success() {
@@ -246,11 +382,12 @@ impl GraphClient {
                     .and_then(|v| v.parse::<u64>().ok())
                     .unwrap_or(30);
                 let body = resp.text().await.unwrap_or_default();
-                return Err(format!("Graph list_folders failed (429:retry_after={}) {}", retry_after, body));
+                return Err(format!("Graph {} failed (429:retry_after={}) {}", op, retry_after, body));
             }
             let body = resp.text().await.unwrap_or_default();
             return Err(format!(
-                "Graph list_folders failed ({}) {}",
+                "Graph {} failed ({}) {}",
+                op,
                 status.as_u16(),
                 body
             ));
@@ -259,11 +396,51 @@ impl GraphClient {
         let list: GraphListResponse<GraphMailFolder> = resp
             .json()
             .await
-            .map_err(|e| format!("Graph list_folders parse error: {}", e))?;
+            .map_err(|e| format!("Graph {} parse error: {}", op, e))?;
 
         Ok(list.value)
     }
 
+    /// Bytes the mailbox holds: the sum of its top-level folders' `sizeInBytes`
+    /// (each counts its child folders), hidden folders included. Graph has
+    /// no mailbox limit a user token can read — see `quota::MailboxQuota`.
+    pub async fn mailbox_usage(&self) -> Result<u64, String> {
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct FolderSize {
+            #[serde(default)]
+            size_in_bytes: u64,
+        }
+
+        let mut url = format!("{}/me/mailFolders?includeHiddenFolders=true&$select=sizeInBytes&$top=100", self.base);
+        let mut total = 0u64;
+        loop {
+            let resp = self
+                .client
+                .get(&url)
+                .bearer_auth(&self.access_token)
+                .send()
+                .await
+                .map_err(|e| format!("Graph mailbox_usage request failed: {}", e))?;
+
+            let status = resp.status();
+            if !status.is_success() {
+                let body = resp.text().await.unwrap_or_default();
+                return Err(format!("Graph mailbox_usage failed ({}) {}", status.as_u16(), body));
+            }
+
+            let page: GraphListResponse<FolderSize> = resp
+                .json()
+                .await
+                .map_err(|e| format!("Graph mailbox_usage parse error: {}", e))?;
+            total += page.value.iter().map(|f| f.size_in_bytes).sum::<u64>();
+            match page.next_link {
+                Some(next) => url = next,
+                None => return Ok(total),
+            }
+        }
+    }
+
     /// List messages in a folder with pagination.
     /// Returns the messages and an optional next-link URL for the next page.
     pub async fn list_messages(
@@ -273,8 +450,8 @@ impl GraphClient {
         skip: u32,
     ) -> Result<(Vec<GraphMessage>, Option<String>), String> {
         let url = format!(
-            "{}/me/mailFolders/{}/messages?$top={}&$skip={}&$select=id,subject,from,toRecipients,ccRecipients,receivedDateTime,isRead,hasAttachments,internetMessageId&$orderby=receivedDateTime desc",
-            GRAPH_BASE, folder_id, top, skip
+            "{}/me/mailFolders/{}/messages?$top={}&$skip={}&$select=id,subject,from,toRecipients,ccRecipients,receivedDateTime,isRead,hasAttachments,internetMessageId,flag,categories&$orderby=receivedDateTime desc",
+            self.base, folder_id, top, skip
         );
 
         let resp = self
@@ -312,11 +489,66 @@ impl GraphClient {
         Ok((list.value, list.next_link))
     }
 
+    /// One page of the folder's message delta. `link` is the `next_link` or
+    /// `delta_link` of an earlier page; `None` starts a new round from nothing,
+    /// which lists every message in the folder.
+    ///
+    /// A delta link Graph no longer honours fails with `(410)` — start over
+    /// with `None` (see `is_sync_reset`).
+    pub async fn messages_delta(
+        &self,
+        folder_id: &str,
+        link: Option<&str>,
+    ) -> Result<GraphDeltaPage, String> {
+        let url = match link {
+            Some(link) => link.to_string(),
+            None => format!(
+                "{}/me/mailFolders/{}/messages/delta?$select=id,subject,from,toRecipients,ccRecipients,receivedDateTime,isRead,hasAttachments,internetMessageId,flag,categories",
+                self.base, folder_id
+            ),
+        };
+
+        let resp = self
+            .client
+            .get(&url)
+            .bearer_auth(&self.access_token)
+            .header("Prefer", "odata.maxpagesize=200")
+            .send()
+            .await
+            .map_err(|e| format!("Graph messages_delta request failed: {}", e))?;
+
+        let status = resp.status();
+        if !status.is_success() {
+            if status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+                let retry_after = resp.headers()
+                    .get("retry-after")
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(|v| v.parse::<u64>().ok())
+                    .unwrap_or(30);
+                let body = resp.text().await.unwrap_or_default();
+                return Err(format!("Graph messages_delta failed (429:retry_after={}) {}", retry_after, body));
+            }
+            let body = resp.text().await.unwrap_or_default();
+            return Err(format!(
+                "Graph messages_delta failed ({}) {}",
+                status.as_u16(),
+                body
+            ));
+        }
+
+        let delta: GraphDeltaResponse = resp
+            .json()
+            .await
+            .map_err(|e| format!("Graph messages_delta parse error: {}", e))?;
+
+        Ok(delta.into())
+    }
+
     /// Get a single message with full body and internet headers.
     pub async fn get_message(&self, message_id: &str) -> Result<GraphMessage, String> {
         let url = format!(
             "{}/me/messages/{}?$select=id,subject,from,toRecipients,ccRecipients,receivedDateTime,isRead,hasAttachments,internetMessageId,body,internetMessageHeaders",
-            GRAPH_BASE, message_id
+            self.base, message_id
         );
 
         let resp = self
@@ -358,7 +590,7 @@ impl GraphClient {
         message_id: &str,
         is_read: bool,
     ) -> Result<(), String> {
-        let url = format!("{}/me/messages/{}", GRAPH_BASE, message_id);
+        let url = format!("{}/me/messages/{}", self.base, message_id);
 
         let resp = self
             .client
@@ -393,7 +625,7 @@ impl GraphClient {
 
     /// Delete a message (moves to Deleted Items by default in Graph API).
     pub async fn delete_message(&self, message_id: &str) -> Result<(), String> {
-        let url = format!("{}/me/messages/{}", GRAPH_BASE, message_id);
+        let url = format!("{}/me/messages/{}", self.base, message_id);
 
         let resp = self
             .client
@@ -431,7 +663,7 @@ impl GraphClient {
         message_id: &str,
         destination_folder_id: &str,
     ) -> Result<String, String> {
-        let url = format!("{}/me/messages/{}/move", GRAPH_BASE, message_id);
+        let url = format!("{}/me/messages/{}/move", self.base, message_id);
 
         let resp = self
             .client
@@ -471,7 +703,7 @@ impl GraphClient {
 
     /// Download the raw MIME (.eml) content of a message.
     pub async fn get_mime_content(&self, message_id: &str) -> Result<Vec<u8>, String> {
-        let url = format!("{}/me/messages/{}/$value", GRAPH_BASE, message_id);
+        let url = format!("{}/me/messages/{}/$value", self.base, message_id);
 
         let resp = self
             .client
@@ -514,7 +746,7 @@ impl GraphClient {
     /// Graph API requires the MIME content to be base64-encoded with Content-Type: text/plain.
     pub async fn create_message_from_mime(&self, mime_bytes: &[u8]) -> Result<String, String> {
         let encoded = base64::engine::general_purpose::STANDARD.encode(mime_bytes);
-        let url = format!("{}/me/messages", GRAPH_BASE);
+        let url = format!("{}/me/messages", self.base);
 
         let resp = self
             .client
@@ -563,9 +795,9 @@ impl GraphClient {
         let url = match parent_folder_id {
             Some(parent_id) => format!(
                 "{}/me/mailFolders/{}/childFolders",
-                GRAPH_BASE, parent_id
+                self.base, parent_id
             ),
-            None => format!("{}/me/mailFolders", GRAPH_BASE),
+            None => format!("{}/me/mailFolders", self.base),
         };
 
         let resp = self
@@ -627,6 +859,18 @@ impl GraphClient {
     pub fn is_rate_limited(error: &str) -> bool {
         error.contains("(429)")
     }
+
+    /// The delta link expired or the server dropped its sync state: the
+    /// round has to start over from a full listing.
+    pub fn is_sync_reset(error: &str) -> bool {
+        error.contains("(410)")
+    }
+
+    /// The folder or message the request named is gone — deleted, or a
+    /// remembered id went stale.
+    pub fn is_not_found(error: &str) -> bool {
+        error.contains("(404)")
+    }
 }
 
 // ---------------------------------------------------------------------------
@@ -770,6 +1014,8 @@ mod tests {
             internet_message_id: Some("<test-123@outlook.com>".to_string()),
             body: None,
             internet_message_headers: None,
+            flag: None,
+            categories: None,
         };
 
         let header = msg.to_email_header(42);
@@ -811,6 +1057,27 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_delta_page_splits_removals_from_changes() {
+        let json = r#"{
+            "value": [
+                { "id": "AAMk1", "subject": "Hi", "isRead": true },
+                { "id": "AAMk2", "@removed": { "reason": "deleted" } }
+            ],
+            "@odata.deltaLink": "https://graph.microsoft.com/v1.0/me/mailFolders/inbox/messages/delta?$deltatoken=abc"
+        }"#;
+
+        let page: GraphDeltaPage = serde_json::from_str::<GraphDeltaResponse>(json).unwrap().into();
+        assert_eq!(page.messages.len(), 1);
+        assert_eq!(page.messages[0].is_read, Some(true));
+        assert_eq!(page.removed, vec!["AAMk2"]);
+        assert!(page.next_link.is_none());
+        assert!(page.delta_link.unwrap().ends_with("$deltatoken=abc"));
+
+        assert_eq!(app_folder_name("Sent Items"), "Sent");
+        assert_eq!(app_folder_name("Projects"), "Projects");
+    }
+
     // -- Error classification -----------------------------------------------
 
     #[test]
@@ -845,6 +1112,8 @@ mod tests {
             internet_message_id: None,
             body: None,
             internet_message_headers: None,
+            flag: None,
+            categories: None,
         };
 
         let header = msg.to_email_header(1);
@@ -889,6 +1158,8 @@ mod tests {
                     value: "<root-000@outlook.com> <original-123@outlook.com>".to_string(),
                 },
             ]),
+            flag: None,
+            categories: None,
         };
 
         let header = msg.to_email_header(99);
@@ -905,4 +1176,19 @@ mod tests {
         // is_read false → no \\Seen flag
         assert!(header.flags.is_empty());
     }
+
+    #[test]
+    fn test_graph_message_append_flags() {
+        let msg: GraphMessage = serde_json::from_value(serde_json::json!({
+            "id": "msg-id-4",
+            "isRead": true,
+            "flag": { "flagStatus": "flagged" },
+            "categories": ["Red category", "$Work", "Büro"],
+        }))
+        .unwrap();
+        assert_eq!(msg.append_flags(), vec!["\\Seen", "\\Flagged", "Red_category", "$Work", "B_ro"]);
+
+        let bare: GraphMessage = serde_json::from_value(serde_json::json!({ "id": "msg-id-5" })).unwrap();
+        assert!(bare.append_flags().is_empty());
+    }
 }
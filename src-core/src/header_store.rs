@@ -0,0 +1,840 @@
+//! Per-mailbox header cache: one append-only log instead of a JSON file per
+//! message.
+//!
+//! The daemon and the app both keep the headers of every cached message in
+//! `email_cache/<account>_<mailbox>/`. One `<uid>.json` per header made a cold
+//! load of a big mailbox mostly `read_dir` and small file opens. This keeps
+//! them in two files:
+//!
+//! - `headers.log` — a 16-byte file header (magic, version, generation), then
+//!   records `[len u32][crc32 u32][json]`. A record puts a whole header,
+//!   replaces a message's flag list, or removes a uid. Replaying it front to
+//!   back gives the current set; a torn or corrupt tail is where replay stops
+//!   and the next writer truncates.
+//! - `headers.flags` — fixed 16-byte slots `[uid u32][flag bits u32][at u64]`,
+//!   one per message, rewritten in place. Marking a message read or starred
+//!   is the common change, and it costs one slot write instead of a record.
+//!   Keyword flags (`$Forwarded`, `$Junk`) don't fit the bits; changing those
+//!   appends a flags record as well.
+//!
+//! Compaction rewrites both once superseded records outweigh live ones, under
+//! a new generation so a reader holding offsets into the old log notices.
+//! `headers.lock` serialises writers across processes; readers take it shared.
+//!
+//! A directory still holding `<uid>.json` sidecars is folded into the log the
+//! first time it is opened, and the sidecars removed.
+//!
+//! In an encrypted vault each record's json is sealed (see
+//! `maildir::encryption`) — the vault being the directory `email_cache/` is
+//! in. A record that is whole but can't be opened stops replay with an error
+//! instead of reading as a torn tail, so a locked process never truncates it.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+use crate::imap::utf7;
+use crate::maildir::compression::Compression;
+use crate::maildir::encryption::{self, Sealer};
+use crate::maildir::NameMigrationStats;
+
+pub const LOG_FILE: &str = "headers.log";
+pub const FLAGS_FILE: &str = "headers.flags";
+const LOCK_FILE: &str = "headers.lock";
+/// In `email_cache/` once `migrate_decode_cache_dirs` has run.
+const DECODED_NAMES_MARKER: &str = ".decoded_names";
+
+const MAGIC: &[u8; 4] = b"MVHL";
+const VERSION: u32 = 1;
+const LOG_HEADER_LEN: u64 = 16;
+const SLOT_LEN: u64 = 16;
+
+/// Don't bother compacting a log smaller than this, whatever its garbage.
+const COMPACT_MIN_BYTES: u64 = 1 << 20;
+
+/// The system flags a slot holds as bits. Anything else is a keyword.
+const SYSTEM_FLAGS: [&str; 5] = ["\\Seen", "\\Answered", "\\Flagged", "\\Deleted", "\\Draft"];
+
+#[derive(Serialize, Deserialize)]
+struct Record {
+    uid: u32,
+    /// Epoch ms.
+    at: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    slot: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    header: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    flags: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    removed: bool,
+}
+
+struct Item {
+    header: Value,
+    slot: u32,
+    /// Last change, epoch ms.
+    at: u64,
+    /// Size of the put record this item came from — the live part of the log.
+    bytes: u64,
+}
+
+pub struct HeaderStore {
+    dir: PathBuf,
+    items: BTreeMap<u32, Item>,
+    generation: u64,
+    /// How far into the log this copy has replayed.
+    log_len: u64,
+    /// Live bytes: the put records `items` came from.
+    live: u64,
+    next_slot: u32,
+    /// How the write in progress seals its records.
+    sealer: Sealer,
+}
+
+fn now_ms() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+fn flag_bits(flags: &[String]) -> u32 {
+    flags.iter().fold(0, |bits, f| {
+        match SYSTEM_FLAGS.iter().position(|s| s.eq_ignore_ascii_case(f)) {
+            Some(i) => bits | (1 << i),
+            None => bits,
+        }
+    })
+}
+
+fn keywords(flags: &[String]) -> Vec<&String> {
+    flags.iter().filter(|f| !SYSTEM_FLAGS.iter().any(|s| s.eq_ignore_ascii_case(f))).collect()
+}
+
+fn header_flags(header: &Value) -> Vec<String> {
+    header
+        .get("flags")
+        .and_then(|f| f.as_array())
+        .map(|a| a.iter().filter_map(|v| v.as_str().map(String::from)).collect())
+        .unwrap_or_default()
+}
+
+/// `flags` with its system flags brought in line with `bits`: ones still set
+/// keep their place, cleared ones go, newly set ones are appended.
+fn apply_bits(flags: &[String], bits: u32) -> Vec<String> {
+    let mut out: Vec<String> = flags
+        .iter()
+        .filter(|f| match SYSTEM_FLAGS.iter().position(|s| s.eq_ignore_ascii_case(f)) {
+            Some(i) => bits & (1 << i) != 0,
+            None => true,
+        })
+        .cloned()
+        .collect();
+    let have = flag_bits(&out);
+    for (i, name) in SYSTEM_FLAGS.iter().enumerate() {
+        if bits & (1 << i) != 0 && have & (1 << i) == 0 {
+            out.push(name.to_string());
+        }
+    }
+    out
+}
+
+fn encode(record: &Record, out: &mut Vec<u8>, sealer: &Sealer) -> Result<u64, String> {
+    let json = serde_json::to_vec(record).map_err(|e| format!("Serialize header {}: {}", record.uid, e))?;
+    let payload = sealer.seal(&json, Compression::None)?;
+    out.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    out.extend_from_slice(&crc32fast::hash(&payload).to_le_bytes());
+    out.extend_from_slice(&payload);
+    Ok(8 + payload.len() as u64)
+}
+
+fn encode_slot(uid: u32, bits: u32, at: u64) -> [u8; SLOT_LEN as usize] {
+    let mut slot = [0u8; SLOT_LEN as usize];
+    slot[..4].copy_from_slice(&uid.to_le_bytes());
+    slot[4..8].copy_from_slice(&bits.to_le_bytes());
+    slot[8..].copy_from_slice(&at.to_le_bytes());
+    slot
+}
+
+fn log_header(generation: u64) -> [u8; LOG_HEADER_LEN as usize] {
+    let mut head = [0u8; LOG_HEADER_LEN as usize];
+    head[..4].copy_from_slice(MAGIC);
+    head[4..8].copy_from_slice(&VERSION.to_le_bytes());
+    head[8..].copy_from_slice(&generation.to_le_bytes());
+    head
+}
+
+/// `<uid>.json` sidecars left from before the log.
+fn sidecar_files(dir: &Path) -> Vec<(u32, PathBuf)> {
+    let Ok(entries) = fs::read_dir(dir) else { return Vec::new() };
+    entries
+        .flatten()
+        .filter_map(|e| {
+            let name = e.file_name().to_string_lossy().to_string();
+            let uid = name.strip_suffix(".json")?.parse::<u32>().ok()?;
+            Some((uid, e.path()))
+        })
+        .collect()
+}
+
+/// The directory under `email_cache/` holding a mailbox's headers, as the
+/// app and the daemon both name it.
+pub fn cache_base_name(account_id: &str, mailbox: &str) -> String {
+    format!(
+        "{}_{}",
+        account_id.replace(|c: char| !c.is_alphanumeric(), "_"),
+        mailbox.replace(|c: char| !c.is_alphanumeric(), "_"),
+    )
+}
+
+/// Whether `dir` holds any cached headers, in either format.
+pub fn exists(dir: &Path) -> bool {
+    dir.join(LOG_FILE).exists() || !sidecar_files(dir).is_empty()
+}
+
+/// One-time migration: move each mailbox's headers from the directory its
+/// modified UTF-7 wire name gave it (`acc_Entw_APw_rfe`) to the one its
+/// decoded name gives (`acc_Entwürfe`), which is what sync writes now.
+/// `cache_base_name` is lossy, so an old directory can't be decoded from its
+/// own name: `mailboxes` lists the `(account, mailbox)` pairs to look for,
+/// under either name. Runs once per `email_cache/`; a decoded directory that
+/// already exists is left alone, as `maildir::migrate_decode_mailbox_names`
+/// does.
+pub fn migrate_decode_cache_dirs(cache_root: &Path, mailboxes: &[(String, String)]) -> NameMigrationStats {
+    let mut stats = NameMigrationStats::default();
+    let marker = cache_root.join(DECODED_NAMES_MARKER);
+    if !cache_root.is_dir() || marker.exists() {
+        return stats;
+    }
+    for (account_id, mailbox) in mailboxes {
+        let decoded = utf7::decode(mailbox);
+        let src = cache_root.join(cache_base_name(account_id, &utf7::encode(&decoded)));
+        let dst = cache_root.join(cache_base_name(account_id, &decoded));
+        if src == dst || !src.is_dir() {
+            continue;
+        }
+        if dst.exists() {
+            warn!("migrate_decode_cache_dirs: {:?} already exists, keeping {:?}", dst, src);
+            stats.errors += 1;
+            continue;
+        }
+        match fs::rename(&src, &dst) {
+            Ok(()) => stats.renamed += 1,
+            Err(e) => {
+                warn!("migrate_decode_cache_dirs: rename {:?} failed: {}", src, e);
+                stats.errors += 1;
+            }
+        }
+    }
+    if let Err(e) = fs::write(&marker, b"") {
+        warn!("migrate_decode_cache_dirs: write marker failed: {}", e);
+    }
+    info!("migrate_decode_cache_dirs: renamed={} errors={}", stats.renamed, stats.errors);
+    stats
+}
+
+/// Carry cached headers to the UIDs a server moved or copied their messages
+/// to (UIDPLUS): the header at each old uid in `from_dir` is written into
+/// `to_dir` at its new uid, and dropped from `from_dir` unless `keep_source`.
+/// A `to_dir` with nothing cached yet is left alone — its first sync fetches
+/// a whole page anyway. Returns how many headers moved.
+pub fn remap(from_dir: &Path, to_dir: &Path, pairs: &[(u32, u32)], keep_source: bool) -> Result<usize, String> {
+    let mut from = HeaderStore::open(from_dir)?;
+    let mut moved = Vec::new();
+    let mut headers = Vec::new();
+    for &(old_uid, new_uid) in pairs {
+        if let Some(header) = from.get(old_uid) {
+            let mut header = header.clone();
+            header["uid"] = Value::from(new_uid);
+            moved.push(old_uid);
+            headers.push(header);
+        }
+    }
+    if headers.is_empty() {
+        return Ok(0);
+    }
+    if from_dir == to_dir {
+        from.put(&headers)?;
+        if !keep_source {
+            let reused: Vec<u32> = pairs.iter().map(|(_, new_uid)| *new_uid).collect();
+            moved.retain(|uid| !reused.contains(uid));
+            from.remove(&moved)?;
+        }
+    } else {
+        if exists(to_dir) {
+            HeaderStore::open(to_dir)?.put(&headers)?;
+        }
+        if !keep_source {
+            from.remove(&moved)?;
+        }
+    }
+    Ok(headers.len())
+}
+
+struct Lock(File);
+
+impl Lock {
+    fn take(dir: &Path, exclusive: bool) -> Result<Lock, String> {
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(dir.join(LOCK_FILE))
+            .map_err(|e| format!("Failed to open {}: {}", LOCK_FILE, e))?;
+        let locked = if exclusive { file.lock() } else { file.lock_shared() };
+        locked.map_err(|e| format!("Failed to lock {}: {}", LOCK_FILE, e))?;
+        Ok(Lock(file))
+    }
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        let _ = self.0.unlock();
+    }
+}
+
+impl HeaderStore {
+    /// Read the mailbox's headers, migrating sidecars first if there are any.
+    /// A directory with neither opens empty and is created on first write.
+    pub fn open(dir: &Path) -> Result<HeaderStore, String> {
+        let mut store = HeaderStore {
+            dir: dir.to_path_buf(),
+            items: BTreeMap::new(),
+            generation: 0,
+            log_len: 0,
+            live: 0,
+            next_slot: 0,
+            sealer: Sealer::plain(),
+        };
+        if !dir.exists() {
+            return Ok(store);
+        }
+        if !sidecar_files(dir).is_empty() {
+            let _lock = Lock::take(dir, true)?;
+            store.catch_up()?;
+            store.migrate_sidecars()?;
+            return Ok(store);
+        }
+        let _lock = Lock::take(dir, false)?;
+        store.catch_up()?;
+        Ok(store)
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    /// The vault this cache belongs to: `{vault}/email_cache/{dir}`.
+    fn vault(&self) -> &Path {
+        self.dir.parent().and_then(Path::parent).unwrap_or(&self.dir)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn contains(&self, uid: u32) -> bool {
+        self.items.contains_key(&uid)
+    }
+
+    pub fn get(&self, uid: u32) -> Option<&Value> {
+        self.items.get(&uid).map(|i| &i.header)
+    }
+
+    /// Every cached uid, ascending.
+    pub fn uids(&self) -> impl DoubleEndedIterator<Item = u32> + '_ {
+        self.items.keys().copied()
+    }
+
+    /// Every cached header, by ascending uid.
+    pub fn headers(&self) -> impl DoubleEndedIterator<Item = &Value> + '_ {
+        self.items.values().map(|i| &i.header)
+    }
+
+    /// Uids whose header or flags changed after `since_ms`.
+    pub fn changed_since(&self, since_ms: u64) -> Vec<u32> {
+        self.items.iter().filter(|(_, i)| i.at > since_ms).map(|(uid, _)| *uid).collect()
+    }
+
+    /// Write whole headers, replacing any already cached under the same uid.
+    /// Each must carry a numeric `uid`. Returns how many were written.
+    pub fn put(&mut self, headers: &[Value]) -> Result<usize, String> {
+        let at = now_ms();
+        self.write(|store, log, slots| {
+            let mut written = 0;
+            for header in headers {
+                let Some(uid) = header.get("uid").and_then(|u| u.as_u64()).map(|u| u as u32) else { continue };
+                store.put_one(uid, header.clone(), at, log, slots)?;
+                written += 1;
+            }
+            Ok(written)
+        })
+    }
+
+    /// Replace the flag list of cached messages. Uids that aren't cached are
+    /// skipped — they arrive with their header. Returns how many changed.
+    pub fn set_flags(&mut self, changes: &[(u32, Vec<String>)]) -> Result<usize, String> {
+        let at = now_ms();
+        self.write(|store, log, slots| {
+            let mut changed = 0;
+            for (uid, flags) in changes {
+                let Some(item) = store.items.get_mut(uid) else { continue };
+                let old = header_flags(&item.header);
+                if old == *flags {
+                    continue;
+                }
+                if keywords(&old) != keywords(flags) {
+                    let record = Record { uid: *uid, at, slot: None, header: None, flags: Some(flags.clone()), removed: false };
+                    encode(&record, log, &store.sealer)?;
+                }
+                item.header["flags"] = serde_json::json!(flags);
+                item.at = at;
+                slots.push((item.slot, encode_slot(*uid, flag_bits(flags), at)));
+                changed += 1;
+            }
+            Ok(changed)
+        })
+    }
+
+    /// Set fields on cached headers, leaving the rest as they are. Uids that
+    /// aren't cached are skipped. Returns how many were written.
+    pub fn merge(&mut self, changes: &[(u32, serde_json::Map<String, Value>)]) -> Result<usize, String> {
+        let at = now_ms();
+        self.write(|store, log, slots| {
+            let mut written = 0;
+            for (uid, fields) in changes {
+                let Some(mut header) = store.items.get(uid).map(|i| i.header.clone()) else { continue };
+                let Some(obj) = header.as_object_mut() else { continue };
+                for (k, v) in fields {
+                    obj.insert(k.clone(), v.clone());
+                }
+                store.put_one(*uid, header, at, log, slots)?;
+                written += 1;
+            }
+            Ok(written)
+        })
+    }
+
+    /// Rewrite the log sealed as the vault is now — after encryption was
+    /// turned on or off, or its key rotated.
+    pub fn reseal(&mut self) -> Result<(), String> {
+        self.write(|store, _, _| store.compact())
+    }
+
+    /// Drop these uids. Returns how many were cached.
+    pub fn remove(&mut self, uids: &[u32]) -> Result<usize, String> {
+        let at = now_ms();
+        self.write(|store, log, slots| {
+            let mut removed = 0;
+            for uid in uids {
+                if let Some(item) = store.items.remove(uid) {
+                    store.live -= item.bytes;
+                    let record = Record { uid: *uid, at, slot: None, header: None, flags: None, removed: true };
+                    encode(&record, log, &store.sealer)?;
+                    slots.push((item.slot, encode_slot(0, 0, at)));
+                    removed += 1;
+                }
+            }
+            Ok(removed)
+        })
+    }
+
+    /// Drop every uid `keep` says no to. Returns how many went.
+    pub fn retain(&mut self, keep: impl Fn(u32) -> bool) -> Result<usize, String> {
+        let gone: Vec<u32> = self.items.keys().copied().filter(|u| !keep(*u)).collect();
+        if gone.is_empty() {
+            return Ok(0);
+        }
+        self.remove(&gone)
+    }
+
+    fn put_one(
+        &mut self,
+        uid: u32,
+        header: Value,
+        at: u64,
+        log: &mut Vec<u8>,
+        slots: &mut Vec<(u32, [u8; SLOT_LEN as usize])>,
+    ) -> Result<(), String> {
+        let slot = match self.items.get(&uid) {
+            Some(item) => item.slot,
+            None => {
+                self.next_slot += 1;
+                self.next_slot - 1
+            }
+        };
+        let bits = flag_bits(&header_flags(&header));
+        let record = Record { uid, at, slot: Some(slot), header: Some(header), flags: None, removed: false };
+        let bytes = encode(&record, log, &self.sealer)?;
+        let header = record.header.unwrap_or_default();
+        if let Some(old) = self.items.insert(uid, Item { header, slot, at, bytes }) {
+            self.live -= old.bytes;
+        }
+        self.live += bytes;
+        slots.push((slot, encode_slot(uid, bits, at)));
+        Ok(())
+    }
+
+    /// Run one change under the writer lock: catch up with whatever another
+    /// process wrote, let `f` stage records and slot writes, then write them.
+    fn write<T>(
+        &mut self,
+        f: impl FnOnce(&mut Self, &mut Vec<u8>, &mut Vec<(u32, [u8; SLOT_LEN as usize])>) -> Result<T, String>,
+    ) -> Result<T, String> {
+        fs::create_dir_all(&self.dir).map_err(|e| format!("Failed to create cache dir: {}", e))?;
+        let _lock = Lock::take(&self.dir, true)?;
+        self.catch_up()?;
+        self.sealer = encryption::sealer(self.vault())?;
+
+        let mut log = Vec::new();
+        let mut slots = Vec::new();
+        let out = f(self, &mut log, &mut slots)?;
+        self.append(&log, &slots)?;
+
+        let garbage = self.log_len.saturating_sub(LOG_HEADER_LEN + self.live);
+        if self.log_len > COMPACT_MIN_BYTES && garbage > self.live {
+            self.compact()?;
+        }
+        Ok(out)
+    }
+
+    fn append(&mut self, log: &[u8], slots: &[(u32, [u8; SLOT_LEN as usize])]) -> Result<(), String> {
+        if !log.is_empty() {
+            let path = self.dir.join(LOG_FILE);
+            let mut file = OpenOptions::new()
+                .create(true)
+                .truncate(false)
+                .read(true)
+                .write(true)
+                .open(&path)
+                .map_err(|e| format!("Failed to open {}: {}", LOG_FILE, e))?;
+            if self.log_len == 0 {
+                self.generation = rand::random();
+                file.set_len(0).map_err(|e| format!("Failed to reset {}: {}", LOG_FILE, e))?;
+                file.write_all(&log_header(self.generation))
+                    .map_err(|e| format!("Failed to write {}: {}", LOG_FILE, e))?;
+                self.log_len = LOG_HEADER_LEN;
+            } else {
+                // Drop a torn tail a crashed writer left behind.
+                file.set_len(self.log_len).map_err(|e| format!("Failed to trim {}: {}", LOG_FILE, e))?;
+            }
+            file.seek(SeekFrom::Start(self.log_len)).map_err(|e| format!("Failed to seek {}: {}", LOG_FILE, e))?;
+            file.write_all(log).map_err(|e| format!("Failed to append to {}: {}", LOG_FILE, e))?;
+            self.log_len += log.len() as u64;
+        }
+        if !slots.is_empty() {
+            let mut file = OpenOptions::new()
+                .create(true)
+                .truncate(false)
+                .write(true)
+                .open(self.dir.join(FLAGS_FILE))
+                .map_err(|e| format!("Failed to open {}: {}", FLAGS_FILE, e))?;
+            for (slot, bytes) in slots {
+                file.seek(SeekFrom::Start(*slot as u64 * SLOT_LEN))
+                    .and_then(|_| file.write_all(bytes))
+                    .map_err(|e| format!("Failed to write {}: {}", FLAGS_FILE, e))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Bring this copy up to date with the files: replay whatever was appended
+    /// since the last read (or everything, if the log was compacted or
+    /// replaced meanwhile), then overlay the flag slots.
+    fn catch_up(&mut self) -> Result<(), String> {
+        let data = match fs::read(self.dir.join(LOG_FILE)) {
+            Ok(d) => d,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(format!("Failed to read {}: {}", LOG_FILE, e)),
+        };
+        let generation = (data.len() as u64 >= LOG_HEADER_LEN && &data[..4] == MAGIC)
+            .then(|| u64::from_le_bytes(data[8..16].try_into().unwrap_or_default()));
+        if generation.is_none() && !data.is_empty() {
+            warn!("header_store: {:?} has no valid log header; starting over", self.dir);
+        }
+        if generation != Some(self.generation) || (data.len() as u64) < self.log_len {
+            self.items.clear();
+            self.live = 0;
+            self.next_slot = 0;
+            self.generation = generation.unwrap_or(0);
+            self.log_len = if generation.is_some() { LOG_HEADER_LEN } else { 0 };
+        }
+
+        let mut pos = self.log_len as usize;
+        while pos + 8 <= data.len() {
+            let len = u32::from_le_bytes(data[pos..pos + 4].try_into().unwrap_or_default()) as usize;
+            let crc = u32::from_le_bytes(data[pos + 4..pos + 8].try_into().unwrap_or_default());
+            let Some(payload) = data.get(pos + 8..pos + 8 + len) else { break };
+            if crc32fast::hash(payload) != crc {
+                break;
+            }
+            let payload = encryption::open(Some(self.vault()), payload).map_err(|e| format!("{:?}: {}", self.dir, e))?;
+            let Ok(record) = serde_json::from_slice::<Record>(&payload) else { break };
+            self.replay(record, 8 + len as u64);
+            pos += 8 + len;
+        }
+        if pos < data.len() {
+            warn!("header_store: {:?} ends in {} unreadable bytes; ignoring them", self.dir, data.len() - pos);
+        }
+        self.log_len = pos as u64;
+
+        let slots = fs::read(self.dir.join(FLAGS_FILE)).unwrap_or_default();
+        self.next_slot = self.next_slot.max((slots.len() as u64 / SLOT_LEN) as u32);
+        for (uid, item) in self.items.iter_mut() {
+            let start = item.slot as usize * SLOT_LEN as usize;
+            let Some(slot) = slots.get(start..start + SLOT_LEN as usize) else { continue };
+            if u32::from_le_bytes(slot[..4].try_into().unwrap_or_default()) != *uid {
+                continue;
+            }
+            let bits = u32::from_le_bytes(slot[4..8].try_into().unwrap_or_default());
+            let flags = header_flags(&item.header);
+            if flag_bits(&flags) != bits {
+                item.header["flags"] = serde_json::json!(apply_bits(&flags, bits));
+            }
+            item.at = item.at.max(u64::from_le_bytes(slot[8..].try_into().unwrap_or_default()));
+        }
+        Ok(())
+    }
+
+    fn replay(&mut self, record: Record, bytes: u64) {
+        if record.removed {
+            if let Some(old) = self.items.remove(&record.uid) {
+                self.live -= old.bytes;
+            }
+        } else if let Some(header) = record.header {
+            let slot = record.slot.unwrap_or(u32::MAX);
+            self.next_slot = self.next_slot.max(slot.saturating_add(1));
+            if let Some(old) = self.items.insert(record.uid, Item { header, slot, at: record.at, bytes }) {
+                self.live -= old.bytes;
+            }
+            self.live += bytes;
+        } else if let (Some(flags), Some(item)) = (record.flags, self.items.get_mut(&record.uid)) {
+            item.header["flags"] = serde_json::json!(flags);
+            item.at = item.at.max(record.at);
+        }
+    }
+
+    /// Rewrite the log as one put per live header and the slots densely.
+    /// The log is renamed into place before the slots: a crash between the
+    /// two leaves slots that don't match their uids, which readers ignore in
+    /// favour of the flags the compacted puts carry.
+    fn compact(&mut self) -> Result<(), String> {
+        let generation: u64 = rand::random();
+        let mut log = log_header(generation).to_vec();
+        let mut slots = Vec::with_capacity(self.items.len() * SLOT_LEN as usize);
+        let mut live = 0;
+        for (n, (uid, item)) in self.items.iter_mut().enumerate() {
+            item.slot = n as u32;
+            let flags = header_flags(&item.header);
+            let record = Record { uid: *uid, at: item.at, slot: Some(item.slot), header: Some(item.header.clone()), flags: None, removed: false };
+            item.bytes = encode(&record, &mut log, &self.sealer)?;
+            live += item.bytes;
+            slots.extend_from_slice(&encode_slot(*uid, flag_bits(&flags), item.at));
+        }
+
+        let before = self.log_len;
+        for (name, bytes) in [(LOG_FILE, &log), (FLAGS_FILE, &slots)] {
+            let tmp = self.dir.join(format!("{}.tmp", name));
+            fs::write(&tmp, bytes)
+                .and_then(|()| fs::rename(&tmp, self.dir.join(name)))
+                .map_err(|e| format!("Failed to compact {}: {}", name, e))?;
+        }
+        self.generation = generation;
+        self.log_len = log.len() as u64;
+        self.live = live;
+        self.next_slot = self.items.len() as u32;
+        info!("header_store: compacted {:?} — {} → {} bytes, {} headers", self.dir, before, self.log_len, self.items.len());
+        Ok(())
+    }
+
+    /// Fold `<uid>.json` sidecars into the log, then delete them. A sidecar
+    /// keeps its file's mtime as its change time, so `changed_since` doesn't
+    /// report the whole mailbox as new.
+    fn migrate_sidecars(&mut self) -> Result<(), String> {
+        let files = sidecar_files(&self.dir);
+        let mut log = Vec::new();
+        let mut slots = Vec::new();
+        let mut migrated = 0;
+        for (uid, path) in &files {
+            let Some(header) = fs::read(path).ok().and_then(|b| serde_json::from_slice::<Value>(&b).ok()) else {
+                continue;
+            };
+            let at = fs::metadata(path)
+                .and_then(|m| m.modified())
+                .ok()
+                .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or_else(now_ms);
+            self.put_one(*uid, header, at, &mut log, &mut slots)?;
+            migrated += 1;
+        }
+        self.append(&log, &slots)?;
+        for (_, path) in &files {
+            let _ = fs::remove_file(path);
+        }
+        info!("header_store: migrated {} sidecars in {:?}", migrated, self.dir);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("mailvault-test-headers-{}", name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn raw_log(dir: &Path) -> Vec<u8> {
+        fs::read(dir.join(LOG_FILE)).unwrap_or_default()
+    }
+
+    fn header(uid: u32, flags: &[&str]) -> Value {
+        json!({ "uid": uid, "subject": format!("msg {}", uid), "flags": flags })
+    }
+
+    #[test]
+    fn round_trips_puts_flags_merges_and_removals() {
+        let dir = scratch("roundtrip");
+        let mut store = HeaderStore::open(&dir).unwrap();
+        store.put(&[header(1, &[]), header(2, &["\\Seen"]), header(3, &["$Junk"])]).unwrap();
+        let log_after_put = raw_log(&dir).len();
+
+        // System flags only: in place, the log doesn't grow.
+        assert_eq!(store.set_flags(&[(1, vec!["\\Seen".into(), "\\Flagged".into()]), (9, vec![])]).unwrap(), 1);
+        assert_eq!(raw_log(&dir).len(), log_after_put);
+        // A keyword change needs a record.
+        assert_eq!(store.set_flags(&[(3, vec!["\\Seen".into()])]).unwrap(), 1);
+        assert!(raw_log(&dir).len() > log_after_put);
+
+        let mut labels = serde_json::Map::new();
+        labels.insert("labels".into(), json!(["\\Inbox"]));
+        assert_eq!(store.merge(&[(2, labels)]).unwrap(), 1);
+        assert_eq!(store.remove(&[2, 7]).unwrap(), 1);
+        store.put(&[header(4, &["\\Draft"])]).unwrap();
+
+        let again = HeaderStore::open(&dir).unwrap();
+        assert_eq!(again.uids().collect::<Vec<_>>(), vec![1, 3, 4]);
+        assert_eq!(again.get(1).unwrap()["flags"], json!(["\\Seen", "\\Flagged"]));
+        assert_eq!(again.get(3).unwrap()["flags"], json!(["\\Seen"]));
+        assert_eq!(again.get(4).unwrap()["flags"], json!(["\\Draft"]));
+        assert_eq!(again.get(1).unwrap()["subject"], "msg 1");
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn two_writers_see_each_other() {
+        let dir = scratch("two-writers");
+        let mut a = HeaderStore::open(&dir).unwrap();
+        let mut b = HeaderStore::open(&dir).unwrap();
+        a.put(&[header(1, &[])]).unwrap();
+        b.put(&[header(2, &[])]).unwrap();
+        a.set_flags(&[(2, vec!["\\Seen".into()])]).unwrap();
+        b.set_flags(&[(1, vec!["\\Flagged".into()])]).unwrap();
+
+        let fresh = HeaderStore::open(&dir).unwrap();
+        assert_eq!(fresh.get(1).unwrap()["flags"], json!(["\\Flagged"]));
+        assert_eq!(fresh.get(2).unwrap()["flags"], json!(["\\Seen"]));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn torn_tail_is_dropped_and_overwritten() {
+        let dir = scratch("torn");
+        let mut store = HeaderStore::open(&dir).unwrap();
+        store.put(&[header(1, &[]), header(2, &[])]).unwrap();
+
+        // A writer died halfway through the next record.
+        let mut data = raw_log(&dir);
+        data.extend_from_slice(&[200, 0, 0, 0, 1, 2, 3, 4, b'{']);
+        fs::write(dir.join(LOG_FILE), &data).unwrap();
+
+        let mut store = HeaderStore::open(&dir).unwrap();
+        assert_eq!(store.len(), 2);
+        store.put(&[header(3, &[])]).unwrap();
+        assert_eq!(HeaderStore::open(&dir).unwrap().uids().collect::<Vec<_>>(), vec![1, 2, 3]);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn compacts_once_garbage_outweighs_live_data() {
+        let dir = scratch("compact");
+        let mut store = HeaderStore::open(&dir).unwrap();
+        let big = "x".repeat(4096);
+        let headers: Vec<Value> = (1..=100).map(|u| json!({ "uid": u, "subject": big, "flags": [] })).collect();
+        for _ in 0..7 {
+            store.put(&headers).unwrap();
+        }
+        assert!(raw_log(&dir).len() < 2 * 100 * 4200, "log was not compacted: {}", raw_log(&dir).len());
+        store.set_flags(&[(50, vec!["\\Seen".into()])]).unwrap();
+
+        let again = HeaderStore::open(&dir).unwrap();
+        assert_eq!(again.len(), 100);
+        assert_eq!(again.get(50).unwrap()["flags"], json!(["\\Seen"]));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn migrates_sidecars_once() {
+        let dir = scratch("migrate");
+        fs::write(dir.join("_meta.json"), r#"{"totalEmails":2}"#).unwrap();
+        fs::write(dir.join("10.json"), header(10, &["\\Seen"]).to_string()).unwrap();
+        fs::write(dir.join("11.json"), header(11, &[]).to_string()).unwrap();
+        fs::write(dir.join("graph_id_map.json"), "{}").unwrap();
+        assert!(exists(&dir));
+
+        let store = HeaderStore::open(&dir).unwrap();
+        assert_eq!(store.uids().collect::<Vec<_>>(), vec![10, 11]);
+        assert_eq!(store.get(10).unwrap()["flags"], json!(["\\Seen"]));
+        assert!(!dir.join("10.json").exists() && !dir.join("11.json").exists());
+        assert!(dir.join("_meta.json").exists() && dir.join("graph_id_map.json").exists());
+        assert!(store.changed_since(now_ms() - 60_000).len() == 2);
+        assert_eq!(HeaderStore::open(&dir).unwrap().len(), 2);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn utf7_named_caches_move_to_the_decoded_directory_once() {
+        let root = scratch("utf7-dirs");
+        let old = root.join(cache_base_name("acc1", "Entw&APw-rfe"));
+        HeaderStore::open(&old).unwrap().put(&[header(7, &[])]).unwrap();
+        HeaderStore::open(&root.join(cache_base_name("acc1", "&BB4EQgQ,BEAEMAQyBDsENQQ9BD0ESwQ1-"))).unwrap()
+            .put(&[header(8, &[])]).unwrap();
+        HeaderStore::open(&root.join(cache_base_name("acc1", "Tom &- Jerry"))).unwrap().put(&[header(9, &[])]).unwrap();
+        HeaderStore::open(&root.join(cache_base_name("acc1", "Tom & Jerry"))).unwrap().put(&[header(10, &[])]).unwrap();
+
+        let mailboxes: Vec<(String, String)> = [
+            "Entwürfe",                            // decoded, from the vault
+            "&BB4EQgQ,BEAEMAQyBDsENQQ9BD0ESwQ1-", // raw, from an old folder list
+            "Tom & Jerry",
+            "INBOX",
+        ]
+        .iter()
+        .map(|m| ("acc1".to_string(), m.to_string()))
+        .collect();
+        let stats = migrate_decode_cache_dirs(&root, &mailboxes);
+        assert_eq!(stats.renamed, 2);
+        assert_eq!(stats.errors, 1, "Tom & Jerry was cached under both names");
+        assert!(!old.exists());
+        assert!(HeaderStore::open(&root.join(cache_base_name("acc1", "Entwürfe"))).unwrap().contains(7));
+        assert!(HeaderStore::open(&root.join(cache_base_name("acc1", "Отправленные"))).unwrap().contains(8));
+
+        HeaderStore::open(&old).unwrap().put(&[header(11, &[])]).unwrap();
+        assert_eq!(migrate_decode_cache_dirs(&root, &mailboxes).renamed, 0, "runs once per cache");
+        let _ = fs::remove_dir_all(&root);
+    }
+}
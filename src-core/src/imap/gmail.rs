@@ -0,0 +1,252 @@
+//! Gmail's IMAP extensions (X-GM-EXT-1): one message, many labels.
+//!
+//! Gmail shows every label as a folder, so a message labelled `Work` and left
+//! in the inbox is listed in `INBOX`, `Work` and `[Gmail]/All Mail` under three
+//! different UIDs. `X-GM-MSGID` is the one identity the copies share, and
+//! `X-GM-LABELS` says where else a message shows up. Sync keeps each message
+//! once, under All Mail, with its labels beside it.
+//!
+//! async-imap exposes `X-GM-MSGID` and `X-GM-LABELS` on a `Fetch` but not
+//! `X-GM-THRID`, so the FETCH here is sent raw and read like `qresync`'s SELECT.
+
+use imap_proto::types::{AttributeValue, Response, Status};
+use serde::{Deserialize, Serialize};
+
+use super::{keyword_atom, utf7, ImapSession, MailboxInfo};
+
+pub const GMAIL_EXT: &str = "X-GM-EXT-1";
+
+/// Where `\All` lives when the server doesn't flag it (older Gmail, or the
+/// "Google Mail" branding some locales get).
+const ALL_MAIL_NAMES: &[&str] = &["[Gmail]/All Mail", "[Google Mail]/All Mail"];
+
+/// The Gmail identity and labels of one All Mail message.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GmailMeta {
+    pub uid: u32,
+    pub msg_id: u64,
+    pub thread_id: Option<u64>,
+    /// System labels as Gmail names them (`\Inbox`, `\Starred`), user labels
+    /// decoded like any mailbox name.
+    pub labels: Vec<String>,
+}
+
+/// The All Mail folder from a LIST: `\All` first, then the well-known names.
+pub fn all_mail_path(mailboxes: &[MailboxInfo]) -> Option<String> {
+    mailboxes
+        .iter()
+        .find(|m| m.special_use.as_deref() == Some("\\All"))
+        .or_else(|| {
+            mailboxes
+                .iter()
+                .find(|m| ALL_MAIL_NAMES.iter().any(|n| m.path.eq_ignore_ascii_case(n)))
+        })
+        .map(|m| m.path.clone())
+}
+
+/// Where a Gmail account's messages are stored: All Mail, plus Trash and
+/// Spam, which All Mail leaves out. Every other folder is a label view.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Storage {
+    pub all_mail: String,
+    /// Trash and Spam.
+    pub outside: Vec<String>,
+}
+
+impl Storage {
+    /// From a LIST. `None` when it shows no All Mail.
+    pub fn from_list(mailboxes: &[MailboxInfo]) -> Option<Storage> {
+        let all_mail = all_mail_path(mailboxes)?;
+        let outside = mailboxes
+            .iter()
+            .filter(|m| !m.noselect && matches!(m.special_use.as_deref(), Some("\\Trash") | Some("\\Junk")))
+            .map(|m| m.path.clone())
+            .collect();
+        Some(Storage { all_mail, outside })
+    }
+
+    /// Does `mailbox` hold messages of its own, rather than show All Mail's?
+    pub fn stores(&self, mailbox: &str) -> bool {
+        mailbox == self.all_mail || self.outside.iter().any(|m| m == mailbox)
+    }
+
+    /// The folder whose sync covers `mailbox`: itself, unless it is a label.
+    pub fn folder_for<'a>(&'a self, mailbox: &'a str) -> &'a str {
+        if self.stores(mailbox) {
+            mailbox
+        } else {
+            &self.all_mail
+        }
+    }
+}
+
+/// `UID FETCH 1:* (UID X-GM-MSGID X-GM-THRID X-GM-LABELS)` on `mailbox`,
+/// restricted to messages whose MODSEQ moved past `changed_since` when given —
+/// Gmail bumps MODSEQ on a label change as on a flag change.
+pub async fn fetch_gmail_meta(
+    session: &mut ImapSession,
+    mailbox: &str,
+    changed_since: Option<u64>,
+) -> Result<Vec<GmailMeta>, String> {
+    super::select_mailbox(session, mailbox).await?;
+
+    let modifier = changed_since
+        .map(|m| format!(" (CHANGEDSINCE {})", m))
+        .unwrap_or_default();
+    let tag = session
+        .run_command(format!("UID FETCH 1:* (UID X-GM-MSGID X-GM-THRID X-GM-LABELS){}", modifier))
+        .await
+        .map_err(|e| format!("UID FETCH X-GM-LABELS {} failed: {}", mailbox, e))?;
+
+    let mut out = Vec::new();
+    loop {
+        let resp = session
+            .read_response()
+            .await
+            .map_err(|e| format!("UID FETCH X-GM-LABELS {} failed: {}", mailbox, e))?
+            .ok_or_else(|| format!("UID FETCH X-GM-LABELS {}: connection closed", mailbox))?;
+
+        match resp.parsed() {
+            Response::Done { tag: t, status, information, .. } if *t == tag => {
+                if *status != Status::Ok {
+                    return Err(format!(
+                        "UID FETCH X-GM-LABELS {} failed: {:?} {}",
+                        mailbox,
+                        status,
+                        information.as_deref().unwrap_or("")
+                    ));
+                }
+                break;
+            }
+            Response::Fetch(_, attrs) => {
+                let mut uid = None;
+                let mut msg_id = None;
+                let mut thread_id = None;
+                let mut labels = Vec::new();
+                for attr in attrs {
+                    match attr {
+                        AttributeValue::Uid(u) => uid = Some(*u),
+                        AttributeValue::GmailMsgId(m) => msg_id = Some(*m),
+                        AttributeValue::GmailThrId(t) => thread_id = Some(*t),
+                        AttributeValue::GmailLabels(l) => labels = l.iter().map(|s| decode_label(s)).collect(),
+                        _ => {}
+                    }
+                }
+                if let (Some(uid), Some(msg_id)) = (uid, msg_id) {
+                    out.push(GmailMeta { uid, msg_id, thread_id, labels });
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(out)
+}
+
+/// System labels (`\Inbox`) are atoms; user labels are mailbox names and
+/// arrive modified-UTF-7 encoded like any other.
+pub fn decode_label(label: &str) -> String {
+    if label.starts_with('\\') {
+        label.to_string()
+    } else {
+        utf7::decode(label)
+    }
+}
+
+/// How a Gmail migration to a non-Gmail server represents labels.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum LabelMapping {
+    /// Each user label becomes a folder holding a copy of its messages, as
+    /// Gmail's own IMAP view shows it.
+    #[default]
+    Folders,
+    /// Each message is copied once; user labels become keywords on it.
+    Keywords,
+}
+
+/// Labels that are folders of their own on any server.
+const SYSTEM_FOLDER_LABELS: &[&str] = &["\\Inbox", "\\Sent", "\\Draft"];
+
+fn is_user_label(label: &str) -> bool {
+    !label.starts_with('\\')
+}
+
+/// Migration of one message from a Gmail source. `None`: skip it — it's an
+/// All Mail copy of a message that also arrives through a folder being
+/// migrated. Otherwise the extra flags and keywords to APPEND it with.
+pub fn migration_flags(labels: &[String], from_all_mail: bool, mapping: LabelMapping) -> Option<Vec<String>> {
+    let folder_backed = |l: &String| {
+        SYSTEM_FOLDER_LABELS.iter().any(|s| l.eq_ignore_ascii_case(s))
+            || (mapping == LabelMapping::Folders && is_user_label(l))
+    };
+    if from_all_mail && labels.iter().any(folder_backed) {
+        return None;
+    }
+
+    let mut extra = Vec::new();
+    for label in labels {
+        let flag = if label.eq_ignore_ascii_case("\\Starred") {
+            "\\Flagged".to_string()
+        } else if label.eq_ignore_ascii_case("\\Important") {
+            "$Important".to_string()
+        } else if mapping == LabelMapping::Keywords && is_user_label(label) {
+            keyword_atom(label)
+        } else {
+            continue;
+        };
+        if !flag.is_empty() && !extra.contains(&flag) {
+            extra.push(flag);
+        }
+    }
+    Some(extra)
+}
+
+/// Gmail folders a migration to a non-Gmail server leaves out: Starred and
+/// Important are views, carried as `\Flagged` / `$Important` on the messages;
+/// with `Keywords`, user-label folders are carried as keywords the same way.
+pub fn skip_folder_in_migration(mailbox: &MailboxInfo, mapping: LabelMapping) -> bool {
+    let special = mailbox.special_use.as_deref().unwrap_or("");
+    if special.eq_ignore_ascii_case("\\Flagged") || special.eq_ignore_ascii_case("\\Important") {
+        return true;
+    }
+    let system = mailbox.path.eq_ignore_ascii_case("INBOX")
+        || mailbox.path.starts_with("[Gmail]")
+        || mailbox.path.starts_with("[Google Mail]");
+    mapping == LabelMapping::Keywords && !system
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels(l: &[&str]) -> Vec<String> {
+        l.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn all_mail_copies_are_skipped_when_a_folder_carries_them() {
+        let inbox_and_work = labels(&["\\Inbox", "Work", "\\Starred"]);
+        assert_eq!(migration_flags(&inbox_and_work, true, LabelMapping::Folders), None);
+        assert_eq!(
+            migration_flags(&inbox_and_work, false, LabelMapping::Folders),
+            Some(labels(&["\\Flagged"]))
+        );
+
+        // Archived with only a user label: Folders sends it through `Work`,
+        // Keywords copies it from All Mail with the label as a keyword.
+        let archived = labels(&["Work", "\\Important", "Reise Pläne"]);
+        assert_eq!(migration_flags(&archived, true, LabelMapping::Folders), None);
+        assert_eq!(
+            migration_flags(&archived, true, LabelMapping::Keywords),
+            Some(labels(&["Work", "$Important", "Reise_Pl_ne"]))
+        );
+        assert_eq!(migration_flags(&[], true, LabelMapping::Folders), Some(vec![]));
+    }
+
+    #[test]
+    fn decodes_user_labels_but_not_system_ones() {
+        assert_eq!(decode_label("\\Inbox"), "\\Inbox");
+        assert_eq!(decode_label("Entw&APw-rfe"), "Entwürfe");
+    }
+}
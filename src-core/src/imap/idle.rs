@@ -0,0 +1,108 @@
+//! Push notifications for a selected mailbox: IMAP IDLE (RFC 2177), with a
+//! NOOP-polling fallback for servers that don't advertise it.
+//!
+//! Both run on a dedicated session the caller owns outright — never one checked
+//! out of `ImapPool`. An idling connection can't carry any other command, so
+//! parking it in a pool slot would starve the background workers of a session
+//! for up to half an hour at a time.
+
+use std::time::Duration;
+
+use async_imap::extensions::idle::IdleResponse;
+use async_imap::types::UnsolicitedResponse;
+use imap_proto::types::{MailboxDatum, Response};
+
+use super::ImapSession;
+
+/// How long one IDLE round may last before it is re-issued. RFC 2177 lets a
+/// server log an idler off after 30 minutes of inactivity, and the RFC asks
+/// clients to refresh "at least every 29 minutes" — stay clear of both.
+pub const IDLE_REFRESH: Duration = Duration::from_secs(25 * 60);
+
+/// What one IDLE round or NOOP poll saw.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdleEvent {
+    /// EXISTS, EXPUNGE, VANISHED or FETCH arrived — the mailbox changed.
+    Changed,
+    /// Nothing that affects the cache: the round timed out, or the server only
+    /// sent keepalives and status chatter.
+    Quiet,
+}
+
+/// Does this untagged response mean the selected mailbox changed?
+fn is_mailbox_change(resp: &Response) -> bool {
+    matches!(
+        resp,
+        Response::MailboxData(MailboxDatum::Exists(_))
+            | Response::MailboxData(MailboxDatum::Recent(_))
+            | Response::Expunge(_)
+            | Response::Vanished { .. }
+            | Response::Fetch(..)
+    )
+}
+
+/// Run one IDLE round on the already-selected mailbox and hand the session back.
+///
+/// Returns as soon as the server reports a change, or after `max_wait` with
+/// `Quiet`. The wait is bounded here rather than by async-imap's own timeout:
+/// that one resets on every `* OK Still here` keepalive, so against a chatty
+/// server it would never let the round end and the 29-minute refresh would
+/// never happen.
+///
+/// IDLE consumes the session, so any error loses it — the caller reconnects.
+pub async fn idle_wait(
+    session: ImapSession,
+    max_wait: Duration,
+) -> Result<(ImapSession, IdleEvent), String> {
+    let mut handle = session.idle();
+    handle.init().await.map_err(|e| format!("IDLE failed: {}", e))?;
+
+    let event = {
+        let (wait, _interrupt) = handle.wait_with_timeout(max_wait);
+        match async_std::future::timeout(max_wait, wait).await {
+            Ok(Ok(IdleResponse::NewData(data))) if is_mailbox_change(data.parsed()) => IdleEvent::Changed,
+            Ok(Ok(_)) | Err(_) => IdleEvent::Quiet,
+            Ok(Err(e)) => return Err(format!("IDLE wait failed: {}", e)),
+        }
+    };
+
+    let session = handle
+        .done()
+        .await
+        .map_err(|e| format!("IDLE DONE failed: {}", e))?;
+    Ok((session, event))
+}
+
+/// NOOP on the already-selected mailbox and report whether the server used it
+/// to announce a change. The fallback for servers without IDLE: RFC 3501 lets a
+/// server send pending EXISTS/EXPUNGE/FETCH updates in reply to any command,
+/// and NOOP is the cheapest one to ask with.
+pub async fn noop_poll(session: &mut ImapSession) -> Result<IdleEvent, String> {
+    // Anything queued before this poll — SELECT's own EXISTS/RECENT included —
+    // is already reflected in the sync that follows a (re)select.
+    drain_unsolicited(session);
+
+    session.noop().await.map_err(|e| format!("NOOP failed: {}", e))?;
+
+    Ok(if drain_unsolicited(session) {
+        IdleEvent::Changed
+    } else {
+        IdleEvent::Quiet
+    })
+}
+
+/// Empty the session's unsolicited-response queue. True when any of it was a
+/// mailbox change.
+fn drain_unsolicited(session: &mut ImapSession) -> bool {
+    let mut changed = false;
+    while let Ok(resp) = session.unsolicited_responses.try_recv() {
+        changed |= match resp {
+            UnsolicitedResponse::Exists(_)
+            | UnsolicitedResponse::Expunge(_)
+            | UnsolicitedResponse::Recent(_) => true,
+            UnsolicitedResponse::Other(data) => is_mailbox_change(data.parsed()),
+            _ => false,
+        };
+    }
+    changed
+}
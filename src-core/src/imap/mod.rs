@@ -1,15 +1,31 @@
+pub mod idle;
 pub mod pool;
+pub mod gmail;
+pub mod objectid;
+pub mod parts;
+pub mod qresync;
+pub mod quota;
+mod tagged;
+pub mod uidplus;
+pub mod utf7;
+pub mod utf8_accept;
 
 use async_imap::types::{Fetch, Flag, Mailbox, Name};
+use chrono::{DateTime, FixedOffset};
 use async_native_tls::TlsConnector;
 use async_std::net::TcpStream;
 use futures::StreamExt;
 use serde::{Deserialize, Serialize};
 use tracing::{info, warn};
 
+use crate::search::query::{Query, Term};
 use crate::transfer_stats::CountingStream;
 
+pub use idle::{idle_wait, noop_poll, IdleEvent, IDLE_REFRESH};
 pub use pool::{ImapPool, ImapSession, ImapTransport};
+pub use qresync::{enable_qresync, select_qresync, QresyncSelect};
+pub use objectid::ObjectIds;
+pub use uidplus::{AppendUid, CopyUid};
 
 // ── Config ──────────────────────────────────────────────────────────────────
 
@@ -148,6 +164,22 @@ pub struct EmailHeader {
     pub list_id: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub precedence: Option<String>,
+    /// Gmail labels (X-GM-LABELS): system ones as `\\Inbox`, `\\Starred`, user
+    /// ones by name. Empty on other servers.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub labels: Vec<String>,
+    /// X-GM-MSGID / X-GM-THRID. Strings, because they overflow a JS number.
+    #[serde(rename = "gmailMsgId", skip_serializing_if = "Option::is_none")]
+    pub gmail_msg_id: Option<String>,
+    #[serde(rename = "gmailThreadId", skip_serializing_if = "Option::is_none")]
+    pub gmail_thread_id: Option<String>,
+    /// EMAILID / THREADID (RFC 8474), filled in by `objectid::apply` — the
+    /// header FETCH can't ask for them. Named apart from the app's own
+    /// conversation `threadId`.
+    #[serde(rename = "emailId", skip_serializing_if = "Option::is_none")]
+    pub email_id: Option<String>,
+    #[serde(rename = "objectThreadId", skip_serializing_if = "Option::is_none")]
+    pub object_thread_id: Option<String>,
 }
 
 #[derive(Debug, Serialize, Clone)]
@@ -444,6 +476,9 @@ pub async fn create_imap_session(config: &ImapConfig, pool: &ImapPool) -> Result
     let (transport, greeting_consumed) = connect_transport(config, &addrs).await?;
     info!("[IMAP] Transport established, authenticating...");
 
+    // UTF8=ACCEPT rewrites what the server sends, so its wrapper has to sit
+    // on top of everything else — COMPRESS below re-wraps the inflated stream.
+    let transport: Box<dyn ImapTransport> = Box::new(utf8_accept::Utf8Accept::new(transport));
     let mut session =
         authenticate_client(async_imap::Client::new(transport), config, greeting_consumed).await?;
 
@@ -456,30 +491,58 @@ pub async fn create_imap_session(config: &ImapConfig, pool: &ImapPool) -> Result
         async_imap::types::Capability::Atom(s) => s.clone(),
     }).collect();
     info!("[IMAP] Capabilities for {}: {:?}", config.email, &cap_list[..cap_list.len().min(15)]);
-    pool.set_capabilities(config, cap_list).await;
+    pool.set_capabilities(config, cap_list.clone()).await;
 
     // ── Negotiate COMPRESS=DEFLATE ──────────────────────────────────────
     let has_compress = caps.has_str("COMPRESS=DEFLATE");
     if has_compress {
         // compress() consumes the session, so on failure we create a new one
         let result = session.compress(|deflate_stream| {
-            Box::new(deflate_stream) as Box<dyn ImapTransport>
+            Box::new(utf8_accept::Utf8Accept::new(Box::new(deflate_stream))) as Box<dyn ImapTransport>
         }).await;
-        match result {
+        session = match result {
             Ok(compressed_session) => {
                 info!("[IMAP] COMPRESS=DEFLATE enabled for {}", config.email);
-                info!("[IMAP] Session established for {}", config.email);
-                return Ok(compressed_session);
+                compressed_session
             }
             Err(e) => {
                 warn!("[IMAP] COMPRESS=DEFLATE failed for {}: {}, reconnecting without compression", config.email, e);
                 // Session was consumed by compress() — create a new uncompressed session
-                let session = connect_and_auth(config)
+                connect_and_auth(config)
                     .await
-                    .map_err(|e| format!("Reconnect after COMPRESS failure: {}", e))?;
-                info!("[IMAP] Session established for {} (no compression)", config.email);
-                return Ok(session);
+                    .map_err(|e| format!("Reconnect after COMPRESS failure: {}", e))?
             }
+        };
+    }
+
+    // ── ENABLE QRESYNC ──────────────────────────────────────────────────
+    // Must happen before the first SELECT. A refusal is not fatal: forget the
+    // capability so the sync engine keeps to its UID SEARCH reconcile.
+    if caps.has_str("QRESYNC") {
+        if let Err(e) = enable_qresync(&mut session).await {
+            warn!("[IMAP] {} for {} — using UID SEARCH reconcile instead", e, config.email);
+            let without: Vec<String> = cap_list.into_iter().filter(|c| !c.eq_ignore_ascii_case("QRESYNC")).collect();
+            pool.set_capabilities(config, without).await;
+        }
+    }
+
+    // ── ENABLE UTF8=ACCEPT ──────────────────────────────────────────────
+    // Same rule as QRESYNC: before the first SELECT. On refusal names keep
+    // travelling as modified UTF-7.
+    if caps.has_str("UTF8=ACCEPT") {
+        if let Err(e) = utf8_accept::enable(&mut session).await {
+            warn!("[IMAP] {} for {} — mailbox names stay modified UTF-7", e, config.email);
+        }
+    }
+
+    // ── Resolve Gmail's All Mail ────────────────────────────────────────
+    // Every sync entry point maps label folders onto it, so it has to be known
+    // from the first session on, not only after an all-folders run.
+    if caps.has_str(gmail::GMAIL_EXT) && pool.gmail_storage(config).await.is_none() {
+        let listed = list_mailboxes(&mut session).await?;
+        match gmail::Storage::from_list(&listed) {
+            Some(storage) => pool.set_gmail_storage(config, storage).await,
+            None => warn!("[IMAP] {} offers {} but lists no All Mail", config.email, gmail::GMAIL_EXT),
         }
     }
 
@@ -616,7 +679,7 @@ pub async fn list_mailboxes(session: &mut ImapSession) -> Result<Vec<MailboxInfo
 
     let mut all: Vec<MailboxInfo> = Vec::new();
     for name in &names {
-        let path = name.name().to_string();
+        let path = utf8_accept::name_from_wire(session, name.name());
         let delimiter = name.delimiter().map(|d| d.to_string());
         let short_name = if let Some(ref delim) = delimiter {
             path.rsplit(delim.as_str()).next().unwrap_or(&path).to_string()
@@ -690,8 +753,9 @@ fn detect_special_use(attrs: &[String], path: &str) -> Option<String> {
 
 /// Select a mailbox and return its status
 pub async fn select_mailbox(session: &mut ImapSession, mailbox: &str) -> Result<Mailbox, String> {
+    let wire = utf8_accept::wire_name(session, mailbox);
     session
-        .select(mailbox)
+        .select(wire)
         .await
         .map_err(|e| format!("SELECT {} failed: {}", mailbox, e))
 }
@@ -816,7 +880,8 @@ pub async fn check_mailbox_status(
 ) -> Result<(u32, Option<u32>, Option<u32>, Option<u64>), String> {
     if has_condstore {
         // Use CONDSTORE SELECT to get HIGHESTMODSEQ
-        let mbox = session.select_condstore(mailbox).await
+        let wire = utf8_accept::wire_name(session, mailbox);
+        let mbox = session.select_condstore(wire).await
             .map_err(|e| format!("SELECT CONDSTORE {} failed: {}", mailbox, e))?;
         Ok((mbox.exists, mbox.uid_validity, mbox.uid_next, mbox.highest_modseq))
     } else {
@@ -999,6 +1064,77 @@ pub async fn fetch_headers_by_uids(
     Ok((emails, total))
 }
 
+/// A message exactly as the server stores it, for the Maildir.
+#[derive(Debug, Clone)]
+pub struct RawMessage {
+    pub uid: u32,
+    pub flags: Vec<String>,
+    pub raw: Vec<u8>,
+}
+
+/// Raw bodies for a set of UIDs, in chunks of `RAW_FETCH_CHUNK`.
+///
+/// `BODY.PEEK[]`, so archiving in the background never marks mail read. A UID
+/// the server no longer has is simply missing from the result; a stream error
+/// fails the whole call, as in `fetch_email_by_uid` — and the session must
+/// then be discarded.
+pub async fn fetch_raw_by_uids(
+    session: &mut ImapSession,
+    mailbox: &str,
+    uids: &[u32],
+) -> Result<Vec<RawMessage>, String> {
+    let _mbox = select_mailbox(session, mailbox).await?;
+
+    let mut out = Vec::new();
+    for chunk in uids.chunks(RAW_FETCH_CHUNK) {
+        let uid_set = compress_uid_ranges(chunk);
+        let fetch_stream = session
+            .uid_fetch(&uid_set, "(UID FLAGS BODY.PEEK[])")
+            .await
+            .map_err(|e| format!("UID FETCH {} failed: {}", uid_set, e))?;
+
+        for item in fetch_stream.collect::<Vec<_>>().await {
+            let fetch = item.map_err(|e| format!("UID FETCH {} failed: {}", uid_set, e))?;
+            if let (Some(uid), Some(body)) = (fetch.uid, fetch.body()) {
+                out.push(RawMessage { uid, flags: extract_flags(&fetch), raw: body.to_vec() });
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// `(uid, RFC822.SIZE)` for a set of UIDs — what a size-limited archive needs
+/// to know before committing to the download. The list-view header fetch
+/// leaves the size out, so sidecars usually don't have it.
+pub async fn fetch_sizes(
+    session: &mut ImapSession,
+    mailbox: &str,
+    uids: &[u32],
+) -> Result<Vec<(u32, u32)>, String> {
+    let _mbox = select_mailbox(session, mailbox).await?;
+
+    let mut out = Vec::new();
+    for chunk in uids.chunks(500) {
+        let uid_set = compress_uid_ranges(chunk);
+        let fetch_stream = session
+            .uid_fetch(&uid_set, "(UID RFC822.SIZE)")
+            .await
+            .map_err(|e| format!("UID FETCH {} sizes failed: {}", uid_set, e))?;
+
+        for item in fetch_stream.collect::<Vec<_>>().await {
+            let fetch = item.map_err(|e| format!("UID FETCH {} sizes failed: {}", uid_set, e))?;
+            if let (Some(uid), Some(size)) = (fetch.uid, fetch.size) {
+                out.push((uid, size));
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// Bodies per UID FETCH: small enough to hold in memory, large enough that
+/// the round trip isn't the bottleneck.
+const RAW_FETCH_CHUNK: usize = 20;
+
 /// Fetch a single email by UID with full content
 pub async fn fetch_email_by_uid(
     session: &mut ImapSession,
@@ -1178,14 +1314,15 @@ pub async fn delete_email(
         )
         .await?;
         info!("[delete_email] uid={} resolved trash='{}'", uid, trash);
+        let trash_wire = utf8_accept::wire_name(session, &trash);
 
-        match session.uid_mv(uid.to_string(), &trash).await {
+        match session.uid_mv(uid.to_string(), &trash_wire).await {
             Ok(_) => info!("[delete_email] uid={} moved to '{}'", uid, trash),
             Err(e) => {
                 // No MOVE capability: COPY + \Deleted + UID EXPUNGE.
                 tracing::warn!("[delete_email] UID MOVE to '{}' failed ({}), falling back to COPY+EXPUNGE", trash, e);
                 session
-                    .uid_copy(uid.to_string(), &trash)
+                    .uid_copy(uid.to_string(), &trash_wire)
                     .await
                     .map_err(|e| format!("UID COPY to '{}' failed: {}", trash, e))?;
                 let _: Vec<_> = session
@@ -1207,6 +1344,88 @@ pub async fn delete_email(
     Ok(())
 }
 
+/// Move emails between folders: `UID MOVE` (RFC 6851) when the server has
+/// MOVE, otherwise `UID COPY` + `\Deleted` + `UID EXPUNGE`. Returns the UIDs
+/// the messages got in `target_mailbox` when the server reports them (UIDPLUS),
+/// or, failing that, when OBJECTID lets us look them up by EMAILID.
+pub async fn move_emails(
+    session: &mut ImapSession,
+    source_mailbox: &str,
+    target_mailbox: &str,
+    uids: &[u32],
+    has_move: bool,
+    has_objectid: bool,
+) -> Result<Option<CopyUid>, String> {
+    if uids.is_empty() {
+        return Ok(None);
+    }
+    let uid_set = compress_uid_ranges(uids);
+    // Taken before the move: afterwards the source UIDs are gone.
+    let object_ids = if has_objectid {
+        objectid::fetch_object_ids(session, source_mailbox, &uid_set).await?
+    } else {
+        select_mailbox(session, source_mailbox).await?;
+        Vec::new()
+    };
+
+    let reported = move_or_copy(session, source_mailbox, target_mailbox, uids, &uid_set, has_move).await?;
+    if reported.is_some() || object_ids.is_empty() {
+        return Ok(reported);
+    }
+
+    // EMAILID survives the move (RFC 8474 §5.1), so the target can say where
+    // each message went.
+    let target = select_mailbox(session, target_mailbox).await?;
+    let mut mapping = CopyUid { uid_validity: target.uid_validity.unwrap_or(0), uids: Vec::new() };
+    for ids in &object_ids {
+        if let Some(new_uid) = objectid::find_by_email_id(session, &ids.email_id).await? {
+            mapping.uids.push((ids.uid, new_uid));
+        }
+    }
+    info!(
+        "[move] Found {} of {} moved messages in '{}' by EMAILID",
+        mapping.uids.len(), object_ids.len(), target_mailbox
+    );
+    Ok(Some(mapping))
+}
+
+async fn move_or_copy(
+    session: &mut ImapSession,
+    source_mailbox: &str,
+    target_mailbox: &str,
+    uids: &[u32],
+    uid_set: &str,
+    has_move: bool,
+) -> Result<Option<CopyUid>, String> {
+    if has_move {
+        info!(
+            "[move] Using UID MOVE for {} UIDs from '{}' to '{}' (range: {})",
+            uids.len(), source_mailbox, target_mailbox, uid_set
+        );
+        return uidplus::copy(session, uid_set, uids.len(), target_mailbox, true).await;
+    }
+
+    info!(
+        "[move] Using COPY+DELETE fallback for {} UIDs from '{}' to '{}' (range: {})",
+        uids.len(), source_mailbox, target_mailbox, uid_set
+    );
+    let copied = uidplus::copy(session, uid_set, uids.len(), target_mailbox, false).await?;
+    let _: Vec<_> = session
+        .uid_store(uid_set, "+FLAGS (\\Deleted)")
+        .await
+        .map_err(|e| format!("STORE \\Deleted failed: {}", e))?
+        .collect::<Vec<_>>()
+        .await;
+    // UID EXPUNGE removes only the flagged UIDs (RFC 4315 UIDPLUS).
+    let _: Vec<_> = session
+        .uid_expunge(uid_set)
+        .await
+        .map_err(|e| format!("UID EXPUNGE failed: {}", e))?
+        .collect::<Vec<_>>()
+        .await;
+    Ok(copied)
+}
+
 async fn ensure_role_mailbox(
     session: &mut ImapSession,
     attr_substring: &str,
@@ -1218,7 +1437,7 @@ async fn ensure_role_mailbox(
     let entries: Vec<(String, Vec<String>)> = names
         .iter()
         .map(|n| {
-            let path = n.name().to_string();
+            let path = utf8_accept::name_from_wire(session, n.name());
             let attrs: Vec<String> = n.attributes().iter().map(|a| format!("{:?}", a)).collect();
             (path, attrs)
         })
@@ -1241,11 +1460,12 @@ async fn ensure_role_mailbox(
         }
     }
 
+    let wire = utf8_accept::wire_name(session, create_name);
     session
-        .create(create_name)
+        .create(&wire)
         .await
         .map_err(|e| format!("CREATE {} failed: {}", create_name, e))?;
-    let _ = session.subscribe(create_name).await;
+    let _ = session.subscribe(&wire).await;
     tracing::info!("[ensure_role_mailbox] Created '{}' (no existing match for attr '{}')", create_name, attr_substring);
     Ok(create_name.to_string())
 }
@@ -1268,21 +1488,68 @@ pub async fn ensure_sent_mailbox(session: &mut ImapSession) -> Result<String, St
     .await
 }
 
-/// Append a raw email (RFC 5322) to a mailbox via IMAP APPEND
+/// Append a raw email (RFC 5322) to a mailbox via IMAP APPEND. Returns the
+/// message's new UID, and the mailbox's UIDVALIDITY, when the server reports
+/// them (UIDPLUS).
 pub async fn append_email(
     session: &mut ImapSession,
     mailbox: &str,
     raw_email: &[u8],
     flags: &str,
-) -> Result<(), String> {
-    let flags_opt: Option<&str> = if flags.is_empty() { None } else { Some(flags) };
+) -> Result<Option<AppendUid>, String> {
+    append_email_at(session, mailbox, raw_email, flags, None).await
+}
 
-    session
-        .append(mailbox, flags_opt, None, raw_email)
-        .await
-        .map_err(|e| format!("IMAP APPEND to '{}' failed: {}", mailbox, e))?;
+/// APPEND with an explicit INTERNALDATE. Migration and restore pass the
+/// message's original arrival time; without it the server stamps "now" and
+/// every copied message sorts as if it arrived today.
+///
+/// `flags` is a space-separated list — system flags and keywords alike.
+pub async fn append_email_at(
+    session: &mut ImapSession,
+    mailbox: &str,
+    raw_email: &[u8],
+    flags: &str,
+    internal_date: Option<DateTime<FixedOffset>>,
+) -> Result<Option<AppendUid>, String> {
+    // Both arguments go into the command verbatim; the grammar wants a
+    // parenthesized flag-list and a quoted date-time. An RPC caller may
+    // already have parenthesized it.
+    let flags = flags.trim().trim_start_matches('(').trim_end_matches(')').trim();
+    let flag_list = (!flags.is_empty()).then(|| format!("({})", flags));
+    let date = internal_date.map(|d| format!("\"{}\"", format_internal_date(&d)));
+
+    uidplus::append(session, mailbox, flag_list.as_deref(), date.as_deref(), raw_email).await
+}
 
-    Ok(())
+/// RFC 3501 `date-time`, without the surrounding quotes.
+pub fn format_internal_date(date: &DateTime<FixedOffset>) -> String {
+    date.format("%d-%b-%Y %H:%M:%S %z").to_string()
+}
+
+/// A free-form name (a Gmail label, an Outlook category) as an IMAP keyword.
+/// Keywords are atoms, so characters an atom can't hold (spaces, parens,
+/// quotes, non-ASCII) become `_`: "Red category" is stored as `Red_category`.
+pub fn keyword_atom(name: &str) -> String {
+    name.trim()
+        .chars()
+        .map(|c| if c.is_ascii_graphic() && !"(){%*\"\\]".contains(c) { c } else { '_' })
+        .collect()
+}
+
+/// A fetched flag as it should be re-applied on another server: system flags
+/// and keywords (`$Forwarded`, `$Label1`, `NonJunk`) pass through; `\Recent`
+/// is session state the server sets itself and cannot be stored.
+pub fn flag_for_append(flag: &Flag) -> Option<String> {
+    match flag {
+        Flag::Seen => Some("\\Seen".to_string()),
+        Flag::Answered => Some("\\Answered".to_string()),
+        Flag::Flagged => Some("\\Flagged".to_string()),
+        Flag::Deleted => Some("\\Deleted".to_string()),
+        Flag::Draft => Some("\\Draft".to_string()),
+        Flag::Custom(c) if !c.starts_with('\\') => Some(c.to_string()),
+        _ => None,
+    }
 }
 
 /// Dedicated fresh IMAP session that explicitly DOES NOT enable COMPRESS=DEFLATE.
@@ -1329,7 +1596,7 @@ pub async fn append_email_verified(
     // Raw bytes are embedded via `from_utf8_unchecked`; the encoder does not
     // rely on UTF-8 validity, just copies the bytes to the wire. For plain
     // test emails the bytes are ASCII anyway.
-    let quoted_mailbox = format!("\"{}\"", mailbox.replace('\\', "\\\\").replace('"', "\\\""));
+    let quoted_mailbox = format!("\"{}\"", utf8_accept::wire_name(session, mailbox).replace('\\', "\\\\").replace('"', "\\\""));
     let flags_clause: String = if flags.is_empty() {
         String::new()
     } else {
@@ -1343,7 +1610,7 @@ pub async fn append_email_verified(
     cmd_bytes.extend_from_slice(header.as_bytes());
     cmd_bytes.extend_from_slice(raw_email);
     // SAFETY: the underlying encoder treats the command as raw bytes. We cast
-    // to &str only because `run_command_and_check_ok` requires `S: AsRef<str>`.
+    // to &str only because `run_command` requires `S: AsRef<str>`.
     // `run_command` immediately calls `.as_bytes()` on the &str without any
     // UTF-8 validation on the write path.
     let cmd_str: &str = unsafe { std::str::from_utf8_unchecked(&cmd_bytes) };
@@ -1351,12 +1618,16 @@ pub async fn append_email_verified(
         "[append_verified:append_start] mailbox={} bytes={} flags={} mode=LITERAL+",
         mailbox, raw_email.len(), flags
     );
-    match tokio::time::timeout(
-        std::time::Duration::from_secs(20),
-        session.run_command_and_check_ok(cmd_str),
-    ).await {
-        Ok(Ok(())) => {
-            tracing::info!("[append_verified:append_ok] mailbox={}", mailbox);
+    // The tagged reply is read here rather than checked and dropped: with
+    // UIDPLUS it carries APPENDUID, which makes the search below unnecessary.
+    let append = async {
+        let tag = session.run_command(cmd_str).await.map_err(|e| e.to_string())?;
+        uidplus::read_codes(session, &tag, "APPEND", |code| uidplus::append_uid(code).map(|a| a.uid)).await
+    };
+    let append_uid = match tokio::time::timeout(std::time::Duration::from_secs(20), append).await {
+        Ok(Ok(uid)) => {
+            tracing::info!("[append_verified:append_ok] mailbox={} appenduid={:?}", mailbox, uid);
+            uid
         }
         Ok(Err(e)) => {
             return Err(format!("IMAP APPEND to '{}' failed: {}", mailbox, e));
@@ -1368,7 +1639,7 @@ pub async fn append_email_verified(
             );
             return Err(format!("IMAP APPEND to '{}' inner timeout after 20s (LITERAL+ path)", mailbox));
         }
-    }
+    };
 
     // Re-SELECT to get the fresh EXISTS count. Some servers update the
     // selected mailbox's status mid-session; others require a fresh SELECT.
@@ -1377,8 +1648,12 @@ pub async fn append_email_verified(
     let exists_after = after.exists;
     tracing::info!("[append_verified:select_after_ok] mailbox={} exists={}", mailbox, exists_after);
 
-    // Try to locate the new message via UID SEARCH HEADER Message-ID.
-    let found_uid = if let Some(mid) = message_id.filter(|s| !s.is_empty()) {
+    // Try to locate the new message via UID SEARCH HEADER Message-ID, unless
+    // the server already said where it is.
+    let found_uid = if let Some(uid) = append_uid {
+        tracing::info!("[append_verified:search_skip] reason=appenduid uid={}", uid);
+        Some(uid)
+    } else if let Some(mid) = message_id.filter(|s| !s.is_empty()) {
         let escaped = mid.replace('\\', "\\\\").replace('"', "\\\"");
         let criteria = format!("HEADER Message-ID \"{}\"", escaped);
         tracing::info!("[append_verified:search_start] criteria=\"{}\"", criteria);
@@ -1401,7 +1676,12 @@ pub async fn append_email_verified(
     Ok((exists_before, exists_after, found_uid))
 }
 
-/// Search emails using IMAP SEARCH
+/// Search emails using IMAP SEARCH.
+///
+/// `query` is the search box language (`search::query`): `from:bob is:unread`,
+/// `"exact phrase" OR invoice`, … A top-level `in:` searches that mailbox
+/// instead of `mailbox`. The other filters are ANDed onto it; dates are
+/// `YYYY-MM-DD`.
 pub async fn search_emails(
     session: &mut ImapSession,
     mailbox: &str,
@@ -1411,45 +1691,25 @@ pub async fn search_emails(
     since: Option<&str>,
     before: Option<&str>,
 ) -> Result<(Vec<EmailHeader>, u32), String> {
-    let _mbox = select_mailbox(session, mailbox).await?;
-
-    let mut criteria_parts: Vec<String> = Vec::new();
-
-    if let Some(q) = query {
-        if !q.is_empty() {
-            criteria_parts.push(format!("TEXT \"{}\"", q.replace('"', "\\\"")));
-        }
-    }
-    if let Some(f) = from_filter {
-        if !f.is_empty() {
-            criteria_parts.push(format!("FROM \"{}\"", f.replace('"', "\\\"")));
-        }
+    let mut q = Query::parse(query.unwrap_or(""))?;
+    if let Some(f) = from_filter.filter(|f| !f.is_empty()) {
+        q = q.and(Query::Term(Term::From(f.to_string())));
     }
-    if let Some(s) = subject_filter {
-        if !s.is_empty() {
-            criteria_parts.push(format!("SUBJECT \"{}\"", s.replace('"', "\\\"")));
-        }
+    if let Some(s) = subject_filter.filter(|s| !s.is_empty()) {
+        q = q.and(Query::Term(Term::Subject(s.to_string())));
     }
-    if let Some(s) = since {
-        if !s.is_empty() {
-            if let Ok(dt) = chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d") {
-                criteria_parts.push(format!("SINCE {}", dt.format("%d-%b-%Y")));
-            }
-        }
+    if let Some(dt) = since.and_then(|s| chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d").ok()) {
+        q = q.and(Query::Term(Term::After(dt)));
     }
-    if let Some(b) = before {
-        if !b.is_empty() {
-            if let Ok(dt) = chrono::NaiveDate::parse_from_str(b, "%Y-%m-%d") {
-                criteria_parts.push(format!("BEFORE {}", dt.format("%d-%b-%Y")));
-            }
-        }
+    if let Some(dt) = before.and_then(|b| chrono::NaiveDate::parse_from_str(b, "%Y-%m-%d").ok()) {
+        q = q.and(Query::Term(Term::Before(dt)));
     }
 
-    if criteria_parts.is_empty() {
+    if q.is_empty() {
         return Ok((Vec::new(), 0));
     }
-
-    let search_str = criteria_parts.join(" ");
+    let search_str = utf8_accept::search_criteria(session, q.to_imap()?);
+    let _mbox = select_mailbox(session, q.folder().unwrap_or(mailbox)).await?;
 
     // Use UID SEARCH
     let uids: Vec<u32> = session
@@ -1690,6 +1950,14 @@ fn parse_header_from_fetch(fetch: &Fetch) -> Result<EmailHeader, String> {
         list_unsubscribe,
         list_id,
         precedence,
+        labels: fetch
+            .gmail_labels()
+            .map(|l| l.iter().map(|s| gmail::decode_label(s)).collect())
+            .unwrap_or_default(),
+        gmail_msg_id: fetch.gmail_msg_id().map(|id| id.to_string()),
+        gmail_thread_id: None,
+        email_id: None,
+        object_thread_id: None,
     })
 }
 
@@ -2052,7 +2320,16 @@ pub async fn fetch_email_by_uid_light(
     let parsed = mailparse::parse_mail(body)
         .map_err(|e| format!("Failed to parse email: {}", e))?;
 
-    let headers = &parsed.headers;
+    let mut email = light_email_from_headers(fetch.uid.unwrap_or(uid), &parsed.headers, fetch);
+    walk_mime_parts_light(&parsed, &mut email.text, &mut email.html, &mut email.attachments);
+    email.has_attachments = !email.attachments.is_empty();
+    email.raw_source_bytes = body.to_vec();
+    Ok(Some(email))
+}
+
+/// A `LightFullEmail` with its envelope filled in from `headers` and its flags
+/// and internal date from `fetch` — no body, attachments or raw source yet.
+fn light_email_from_headers(uid: u32, headers: &[mailparse::MailHeader], fetch: &Fetch) -> LightFullEmail {
     let get_header = |name: &str| -> Option<String> {
         headers
             .iter()
@@ -2066,44 +2343,26 @@ pub async fn fetch_email_by_uid_light(
             .map(|h| h.get_value_raw().to_vec())
     };
 
-    let subject = get_header_raw("Subject")
-        .map(|raw| decode_rfc2047(&raw))
-        .unwrap_or_else(|| "(No Subject)".to_string());
-    let message_id = get_header("Message-ID");
-    let date = get_header("Date");
-
-    let from = parse_address_header(get_header("From").as_deref());
-    let to = parse_address_list(get_header("To").as_deref());
-    let cc = parse_address_list(get_header("Cc").as_deref());
-    let bcc = parse_address_list(get_header("Bcc").as_deref());
-    let reply_to = parse_address_list(get_header("Reply-To").as_deref());
-
-    let mut text_body: Option<String> = None;
-    let mut html_body: Option<String> = None;
-    let mut attachments: Vec<LightEmailAttachment> = Vec::new();
-    walk_mime_parts_light(&parsed, &mut text_body, &mut html_body, &mut attachments);
-
-    let flags = extract_flags(fetch);
-    let internal_date = fetch.internal_date().map(|d| d.to_rfc3339());
-
-    Ok(Some(LightFullEmail {
-        uid: fetch.uid.unwrap_or(uid),
-        message_id,
-        subject,
-        from,
-        to,
-        cc,
-        bcc,
-        reply_to,
-        date,
-        internal_date,
-        flags,
-        text: text_body,
-        html: html_body,
-        has_attachments: !attachments.is_empty(),
-        attachments,
-        raw_source_bytes: body.to_vec(),
-    }))
+    LightFullEmail {
+        uid,
+        message_id: get_header("Message-ID"),
+        subject: get_header_raw("Subject")
+            .map(|raw| decode_rfc2047(&raw))
+            .unwrap_or_else(|| "(No Subject)".to_string()),
+        from: parse_address_header(get_header("From").as_deref()),
+        to: parse_address_list(get_header("To").as_deref()),
+        cc: parse_address_list(get_header("Cc").as_deref()),
+        bcc: parse_address_list(get_header("Bcc").as_deref()),
+        reply_to: parse_address_list(get_header("Reply-To").as_deref()),
+        date: get_header("Date"),
+        internal_date: fetch.internal_date().map(|d| d.to_rfc3339()),
+        flags: extract_flags(fetch),
+        text: None,
+        html: None,
+        attachments: Vec::new(),
+        has_attachments: false,
+        raw_source_bytes: Vec::new(),
+    }
 }
 
 // ── Unit tests ──────────────────────────────────────────────────────────────
@@ -0,0 +1,124 @@
+//! OBJECTID (RFC 8474): identities that outlive a UID.
+//!
+//! A server with OBJECTID gives every message an `EMAILID` that stays the same
+//! through a MOVE, a COPY and a UIDVALIDITY reset, and a `THREADID` shared by
+//! its conversation. Sync keeps both beside the headers and in the vault's uid
+//! index, so a generation repair or a move can find a message by what it is
+//! rather than by a Message-ID that can be missing or shared.
+//!
+//! imap-proto has no parser for `EMAILID (...)`, so the FETCH here goes
+//! through `tagged`, written to the transport directly.
+
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use super::{tagged, EmailHeader, ImapSession};
+
+pub const OBJECTID: &str = "OBJECTID";
+
+/// The object identifiers of one message.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ObjectIds {
+    pub uid: u32,
+    pub email_id: String,
+    /// `None` when the server answered `THREADID NIL` — it doesn't thread.
+    pub thread_id: Option<String>,
+}
+
+/// The ids in one untagged `* n FETCH (UID u EMAILID (e) THREADID (t))` line.
+/// `None` for any other line, or a FETCH without both UID and EMAILID.
+pub fn parse_fetch_line(line: &str) -> Option<ObjectIds> {
+    let rest = line.strip_prefix("* ")?;
+    let (_, items) = rest.split_once(" FETCH ")?;
+    // Objectids are atoms of `A-Za-z0-9_-`, so the parentheses carry no
+    // structure a split on whitespace would lose.
+    let cleaned = items.replace(['(', ')'], " ");
+    let mut tokens = cleaned.split_whitespace();
+    let (mut uid, mut email_id, mut thread_id) = (None, None, None);
+    while let Some(key) = tokens.next() {
+        let value = tokens.next();
+        match key.to_ascii_uppercase().as_str() {
+            "UID" => uid = value.and_then(|v| v.parse().ok()),
+            "EMAILID" => email_id = value.map(str::to_string),
+            "THREADID" => thread_id = value.filter(|v| !v.eq_ignore_ascii_case("NIL")).map(str::to_string),
+            _ => {}
+        }
+    }
+    Some(ObjectIds { uid: uid?, email_id: email_id?, thread_id })
+}
+
+/// Copy the ids onto the headers they belong to.
+pub fn apply(headers: &mut [EmailHeader], ids: &[ObjectIds]) -> usize {
+    let by_uid: std::collections::HashMap<u32, &ObjectIds> = ids.iter().map(|i| (i.uid, i)).collect();
+    let mut applied = 0;
+    for h in headers.iter_mut() {
+        if let Some(ids) = by_uid.get(&h.uid) {
+            h.email_id = Some(ids.email_id.clone());
+            h.object_thread_id = ids.thread_id.clone();
+            applied += 1;
+        }
+    }
+    applied
+}
+
+/// `UID FETCH uid_set (UID EMAILID THREADID)` on `mailbox`.
+///
+/// `Err` leaves the session in an unknown state; discard it, as after any
+/// other failed command.
+pub async fn fetch_object_ids(
+    session: &mut ImapSession,
+    mailbox: &str,
+    uid_set: &str,
+) -> Result<Vec<ObjectIds>, String> {
+    super::select_mailbox(session, mailbox).await?;
+
+    let tag = tagged::next_tag("OBJ");
+    let what = format!("UID FETCH EMAILID {}", mailbox);
+    let command = format!("UID FETCH {} (UID EMAILID THREADID)", uid_set);
+    let mut reply = tagged::send(session.get_mut(), &tag, &command, &what).await?;
+
+    let mut out = Vec::new();
+    while let Some(line) = reply.next().await? {
+        let line = String::from_utf8_lossy(&line);
+        match parse_fetch_line(&line) {
+            Some(ids) => out.push(ids),
+            None if line.contains(" FETCH ") => warn!("[IMAP] {}: no EMAILID in {:?}", what, line),
+            None => {}
+        }
+    }
+    Ok(out)
+}
+
+/// The UID of the message with `email_id` in the selected mailbox, when
+/// exactly one has it.
+pub async fn find_by_email_id(session: &mut ImapSession, email_id: &str) -> Result<Option<u32>, String> {
+    let hits = session
+        .uid_search(format!("EMAILID {}", email_id))
+        .await
+        .map_err(|e| format!("UID SEARCH EMAILID failed: {}", e))?;
+    Ok(match hits.len() {
+        1 => hits.into_iter().next(),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_ids_from_a_fetch_line() {
+        assert_eq!(
+            parse_fetch_line("* 3 FETCH (UID 12 EMAILID (M6d99ac3275bb4e) THREADID (T64b478a75b7ea9))"),
+            Some(ObjectIds { uid: 12, email_id: "M6d99ac3275bb4e".into(), thread_id: Some("T64b478a75b7ea9".into()) })
+        );
+        // Any order; a server that doesn't thread answers NIL.
+        assert_eq!(
+            parse_fetch_line("* 1 FETCH (EMAILID (Mx-1) THREADID NIL UID 4)"),
+            Some(ObjectIds { uid: 4, email_id: "Mx-1".into(), thread_id: None })
+        );
+        assert_eq!(parse_fetch_line("* 2 FETCH (UID 5 FLAGS (\\Seen))"), None);
+        assert_eq!(parse_fetch_line("* 7 EXISTS"), None);
+    }
+}
@@ -0,0 +1,552 @@
+//! Single MIME parts of a message, fetched on their own.
+//!
+//! `fetch_email_by_uid_light` downloads the whole message, which for a short
+//! mail with a 40 MB attachment is 40 MB to read a paragraph. Here the
+//! BODYSTRUCTURE comes first; after it, reading fetches only the text parts,
+//! saving an attachment fetches only that part, and archiving fetches the
+//! message — each in `<offset.length>` chunks written to disk as they arrive
+//! when large.
+//!
+//! With BINARY (RFC 3516) the server decodes the part; without it the part
+//! comes transfer-encoded and is decoded here. BINARY replies are `~{n}`
+//! literals, which imap-proto can't parse any more than it can EMAILID, so
+//! part fetches go through `tagged` like those of `objectid`.
+
+use async_imap::types::Fetch;
+use async_std::io::prelude::*;
+use futures::StreamExt;
+use imap_proto::types::{BodyParams, BodyStructure, ContentEncoding};
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+use tracing::{info, warn};
+
+use super::tagged::{self, Reply};
+use super::{ImapSession, LightEmailAttachment, LightFullEmail};
+use crate::mime::decode_rfc2047;
+
+pub const BINARY: &str = "BINARY";
+
+/// Parts larger than this are fetched in `<offset.length>` pieces of this size.
+pub const CHUNK_SIZE: usize = 1 << 20;
+
+/// One leaf of a message's BODYSTRUCTURE. A `message/rfc822` part is a leaf
+/// too: it is saved, not opened.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MimePart {
+    /// The part specifier for `BODY[...]` — `"1"`, `"2.3"`.
+    pub section: String,
+    /// Lowercase `type/subtype`.
+    pub content_type: String,
+    pub charset: Option<String>,
+    /// Lowercase Content-Transfer-Encoding.
+    pub encoding: String,
+    /// Octets as stored on the server, still transfer-encoded.
+    pub size: u32,
+    pub filename: Option<String>,
+    /// Lowercase disposition when the part has one.
+    pub disposition: Option<String>,
+    pub content_id: Option<String>,
+}
+
+impl MimePart {
+    /// The rule `fetch_email_by_uid_light` applies to a parsed part: an
+    /// attachment, or anything inline that isn't text. No disposition reads
+    /// as inline.
+    pub fn is_attachment(&self) -> bool {
+        match self.disposition.as_deref() {
+            Some("attachment") => true,
+            _ => !self.content_type.starts_with("text/"),
+        }
+    }
+
+    /// Roughly the size once decoded: base64 carries 57 bytes in each line of
+    /// 78.
+    pub fn decoded_size(&self) -> usize {
+        match self.encoding.as_str() {
+            "base64" => self.size as usize * 57 / 78,
+            _ => self.size as usize,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageStructure {
+    pub uid: u32,
+    /// RFC822.SIZE.
+    pub size: u32,
+    pub parts: Vec<MimePart>,
+}
+
+/// A message as reading needs it: envelope, text and attachment metadata,
+/// with the parts it left on the server listed for fetching later.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PartialEmail {
+    #[serde(flatten)]
+    pub email: LightFullEmail,
+    pub size: u32,
+    pub parts: Vec<MimePart>,
+}
+
+/// The leaves of `bs`, numbered as RFC 3501 numbers them for `BODY[...]`: a
+/// single-part message is part `1`, the children of a multipart are `1`, `2`,
+/// ... and theirs `2.1`, `2.2`, ...
+pub fn flatten(bs: &BodyStructure) -> Vec<MimePart> {
+    let mut parts = Vec::new();
+    match bs {
+        BodyStructure::Multipart { bodies, .. } => walk(bodies, "", &mut parts),
+        leaf => parts.extend(leaf_part(leaf, "1".to_string())),
+    }
+    parts
+}
+
+fn walk(bodies: &[BodyStructure], prefix: &str, parts: &mut Vec<MimePart>) {
+    for (i, body) in bodies.iter().enumerate() {
+        let section = format!("{}{}", prefix, i + 1);
+        match body {
+            BodyStructure::Multipart { bodies, .. } => walk(bodies, &format!("{}.", section), parts),
+            leaf => parts.extend(leaf_part(leaf, section)),
+        }
+    }
+}
+
+fn leaf_part(bs: &BodyStructure, section: String) -> Option<MimePart> {
+    let (common, other) = match bs {
+        BodyStructure::Basic { common, other, .. }
+        | BodyStructure::Text { common, other, .. }
+        | BodyStructure::Message { common, other, .. } => (common, other),
+        BodyStructure::Multipart { .. } => return None,
+    };
+    let disposition = common.disposition.as_ref();
+    Some(MimePart {
+        section,
+        content_type: format!("{}/{}", common.ty.ty, common.ty.subtype).to_ascii_lowercase(),
+        charset: param(&common.ty.params, "charset"),
+        encoding: match &other.transfer_encoding {
+            ContentEncoding::SevenBit => "7bit".to_string(),
+            ContentEncoding::EightBit => "8bit".to_string(),
+            ContentEncoding::Binary => "binary".to_string(),
+            ContentEncoding::Base64 => "base64".to_string(),
+            ContentEncoding::QuotedPrintable => "quoted-printable".to_string(),
+            ContentEncoding::Other(e) => e.to_ascii_lowercase(),
+        },
+        size: other.octets,
+        filename: disposition
+            .and_then(|d| param(&d.params, "filename"))
+            .or_else(|| param(&common.ty.params, "name")),
+        disposition: disposition.map(|d| d.ty.to_ascii_lowercase()),
+        content_id: other.id.as_ref().map(|id| id.to_string()),
+    })
+}
+
+fn param(params: &BodyParams, name: &str) -> Option<String> {
+    params
+        .as_ref()?
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case(name))
+        .map(|(_, v)| decode_rfc2047(v.as_bytes()))
+}
+
+/// `UID FETCH uid (UID RFC822.SIZE BODYSTRUCTURE)` on `mailbox`. `None` when
+/// the message is gone.
+pub async fn fetch_structure(
+    session: &mut ImapSession,
+    mailbox: &str,
+    uid: u32,
+) -> Result<Option<MessageStructure>, String> {
+    super::select_mailbox(session, mailbox).await?;
+    let Some(fetch) = fetch_one(session, mailbox, uid, "(UID RFC822.SIZE BODYSTRUCTURE)").await? else {
+        return Ok(None);
+    };
+    structure_of(&fetch, uid).map(Some)
+}
+
+fn structure_of(fetch: &Fetch, uid: u32) -> Result<MessageStructure, String> {
+    let bs = fetch
+        .bodystructure()
+        .ok_or_else(|| format!("No BODYSTRUCTURE for UID {}", uid))?;
+    Ok(MessageStructure {
+        uid: fetch.uid.unwrap_or(uid),
+        size: fetch.size.unwrap_or(0),
+        parts: flatten(bs),
+    })
+}
+
+/// One message's FETCH through the session, telling a refused FETCH from a
+/// deleted message the way `fetch_email_by_uid_light` does.
+async fn fetch_one(session: &mut ImapSession, mailbox: &str, uid: u32, query: &str) -> Result<Option<Fetch>, String> {
+    let stream = session
+        .uid_fetch(uid.to_string(), query)
+        .await
+        .map_err(|e| format!("UID FETCH {} failed: {}", uid, e))?;
+    let mut fetches = Vec::new();
+    for item in stream.collect::<Vec<_>>().await {
+        fetches.push(item.map_err(|e| format!("UID FETCH {} failed: {}", uid, e))?);
+    }
+    match fetches.into_iter().next() {
+        Some(f) => Ok(Some(f)),
+        None if super::uid_still_present(session, uid).await => Err(format!(
+            "Server returned nothing for UID {}, but the message is still in {}",
+            uid, mailbox
+        )),
+        None => Ok(None),
+    }
+}
+
+/// The message for reading: header, BODYSTRUCTURE, and only the first
+/// text/plain and text/html parts that aren't attachments. Attachment sizes
+/// are estimates from the structure; `raw_source_bytes` stays empty, so this
+/// is no source for the vault.
+pub async fn fetch_email_for_reading(
+    session: &mut ImapSession,
+    mailbox: &str,
+    uid: u32,
+    has_binary: bool,
+) -> Result<Option<PartialEmail>, String> {
+    super::select_mailbox(session, mailbox).await?;
+    let query = "(UID FLAGS INTERNALDATE RFC822.SIZE BODYSTRUCTURE BODY.PEEK[HEADER])";
+    let Some(fetch) = fetch_one(session, mailbox, uid, query).await? else {
+        return Ok(None);
+    };
+    let structure = structure_of(&fetch, uid)?;
+    let (headers, _) = mailparse::parse_headers(fetch.header().unwrap_or_default())
+        .map_err(|e| format!("Failed to parse headers of UID {}: {}", uid, e))?;
+    let mut email = super::light_email_from_headers(structure.uid, &headers, &fetch);
+
+    for part in &structure.parts {
+        if part.is_attachment() {
+            email.attachments.push(LightEmailAttachment {
+                filename: part.filename.clone(),
+                content_type: part.content_type.clone(),
+                content_disposition: Some(if part.disposition.as_deref() == Some("attachment") {
+                    "Attachment".to_string()
+                } else {
+                    "Inline".to_string()
+                }),
+                size: part.decoded_size(),
+                content_id: part.content_id.clone(),
+            });
+            continue;
+        }
+        let slot = match part.content_type.as_str() {
+            "text/plain" => &mut email.text,
+            "text/html" => &mut email.html,
+            _ => continue,
+        };
+        if slot.is_none() {
+            let bytes = fetch_part(session, uid, part, has_binary).await?;
+            *slot = Some(text_of(part, &bytes));
+        }
+    }
+    email.has_attachments = !email.attachments.is_empty();
+    Ok(Some(PartialEmail { email, size: structure.size, parts: structure.parts }))
+}
+
+/// Decoded `bytes` of a text part as a string, by its charset.
+fn text_of(part: &MimePart, bytes: &[u8]) -> String {
+    let mut entity = match &part.charset {
+        Some(cs) => format!("Content-Type: {}; charset=\"{}\"\r\n\r\n", part.content_type, cs),
+        None => format!("Content-Type: {}\r\n\r\n", part.content_type),
+    }
+    .into_bytes();
+    entity.extend_from_slice(bytes);
+    mailparse::parse_mail(&entity)
+        .and_then(|m| m.get_body())
+        .unwrap_or_else(|_| String::from_utf8_lossy(bytes).into_owned())
+}
+
+/// One part of the message, decoded, in a single FETCH — for parts small
+/// enough to hold in memory. The caller has selected the mailbox.
+pub async fn fetch_part(
+    session: &mut ImapSession,
+    uid: u32,
+    part: &MimePart,
+    has_binary: bool,
+) -> Result<Vec<u8>, String> {
+    if has_binary {
+        match fetch_section(session, uid, "BINARY", &part.section, None).await {
+            Ok(Some(bytes)) => return Ok(bytes),
+            Ok(None) => return Err(format!("UID {} has no part {}", uid, part.section)),
+            Err(e) if e.contains("UNKNOWN-CTE") => {
+                info!("[IMAP] UID {} part {}: {}, decoding here", uid, part.section, e)
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    let raw = fetch_section(session, uid, "BODY", &part.section, None)
+        .await?
+        .ok_or_else(|| format!("UID {} has no part {}", uid, part.section))?;
+    Ok(decode(&part.encoding, &raw))
+}
+
+/// Stream `part` — or with `None` the whole message as stored, for the vault
+/// — to `dest`, `CHUNK_SIZE` at a time, decoding as it goes. Written to a
+/// `.partial` file beside `dest` and renamed over it once complete. Returns
+/// the bytes written.
+pub async fn download(
+    session: &mut ImapSession,
+    mailbox: &str,
+    uid: u32,
+    part: Option<&MimePart>,
+    has_binary: bool,
+    dest: &Path,
+) -> Result<u64, String> {
+    super::select_mailbox(session, mailbox).await?;
+    if has_binary && part.is_some() {
+        match download_as(session, uid, part, true, dest).await {
+            Err(e) if e.contains("UNKNOWN-CTE") => {
+                info!("[IMAP] UID {} part {}: {}, decoding here", uid, part.map_or("", |p| &p.section), e)
+            }
+            result => return result,
+        }
+    }
+    download_as(session, uid, part, false, dest).await
+}
+
+async fn download_as(
+    session: &mut ImapSession,
+    uid: u32,
+    part: Option<&MimePart>,
+    binary: bool,
+    dest: &Path,
+) -> Result<u64, String> {
+    let (kind, section) = match part {
+        Some(p) => (if binary { "BINARY" } else { "BODY" }, p.section.as_str()),
+        None => ("BODY", ""),
+    };
+    let mut decoder = Decoder::new(match part {
+        Some(p) if !binary => &p.encoding,
+        _ => "binary",
+    });
+
+    let partial = dest.with_extension(match dest.extension() {
+        Some(ext) => format!("{}.partial", ext.to_string_lossy()),
+        None => "partial".to_string(),
+    });
+    let mut file = fs::File::create(&partial)
+        .map_err(|e| format!("Failed to create {}: {}", partial.display(), e))?;
+    let mut written = 0u64;
+    let mut write = |bytes: &[u8]| -> Result<(), String> {
+        std::io::Write::write_all(&mut file, bytes)
+            .map_err(|e| format!("Failed to write {}: {}", partial.display(), e))?;
+        written += bytes.len() as u64;
+        Ok(())
+    };
+
+    let mut offset = 0usize;
+    let result = loop {
+        let chunk = match fetch_section(session, uid, kind, section, Some((offset, CHUNK_SIZE))).await {
+            Ok(Some(chunk)) => chunk,
+            Ok(None) if offset == 0 => break Err(format!("UID {} has no part {}", uid, section)),
+            Ok(None) => break Ok(()),
+            Err(e) => break Err(e),
+        };
+        offset += chunk.len();
+        if let Err(e) = write(&decoder.push(&chunk)) {
+            break Err(e);
+        }
+        if chunk.len() < CHUNK_SIZE {
+            break Ok(());
+        }
+    };
+    // Whatever the decoder still holds — a last line without its CRLF — is
+    // content too, however the part ended: a short chunk, or NIL past the end.
+    let result = result.and_then(|()| write(&decoder.finish()));
+    drop(file);
+    match result {
+        Ok(()) => {
+            fs::rename(&partial, dest).map_err(|e| format!("Failed to move {} into place: {}", dest.display(), e))?;
+            info!("[IMAP] UID {} {}[{}]: {} bytes to {}", uid, kind, section, written, dest.display());
+            Ok(written)
+        }
+        Err(e) => {
+            let _ = fs::remove_file(&partial);
+            Err(e)
+        }
+    }
+}
+
+/// Undo a Content-Transfer-Encoding. Anything but base64 and
+/// quoted-printable is already the content.
+fn decode(encoding: &str, data: &[u8]) -> Vec<u8> {
+    if encoding != "base64" && encoding != "quoted-printable" {
+        return data.to_vec();
+    }
+    let mut entity = format!("Content-Transfer-Encoding: {}\r\n\r\n", encoding).into_bytes();
+    entity.extend_from_slice(data);
+    match mailparse::parse_mail(&entity).and_then(|m| m.get_body_raw()) {
+        Ok(decoded) => decoded,
+        Err(e) => {
+            warn!("[IMAP] Could not decode {} content: {}", encoding, e);
+            data.to_vec()
+        }
+    }
+}
+
+/// `decode` over a part that arrives in chunks cut anywhere: each chunk is
+/// decoded up to the last point where decoding can stop — a whole base64
+/// quad, the end of a quoted-printable line — and the rest carried over.
+struct Decoder {
+    encoding: String,
+    carry: Vec<u8>,
+}
+
+impl Decoder {
+    fn new(encoding: &str) -> Self {
+        Self { encoding: encoding.to_string(), carry: Vec::new() }
+    }
+
+    fn push(&mut self, chunk: &[u8]) -> Vec<u8> {
+        self.carry.extend_from_slice(chunk);
+        let cut = match self.encoding.as_str() {
+            "base64" => {
+                let mut significant = 0;
+                let mut cut = 0;
+                for (i, b) in self.carry.iter().enumerate() {
+                    if b.is_ascii_alphanumeric() || matches!(b, b'+' | b'/' | b'=') {
+                        significant += 1;
+                        if significant % 4 == 0 {
+                            cut = i + 1;
+                        }
+                    }
+                }
+                cut
+            }
+            "quoted-printable" => self.carry.iter().rposition(|b| *b == b'\n').map_or(0, |i| i + 1),
+            _ => self.carry.len(),
+        };
+        let ready: Vec<u8> = self.carry.drain(..cut).collect();
+        decode(&self.encoding, &ready)
+    }
+
+    fn finish(self) -> Vec<u8> {
+        decode(&self.encoding, &self.carry)
+    }
+}
+
+/// `UID FETCH uid (<kind>.PEEK[section]<offset.length>)` written to the
+/// transport, and the section's bytes out of the reply. `None` when the
+/// server answered NIL or sent no such item. A tagged NO is an `Err` carrying
+/// the server's text, `UNKNOWN-CTE` included.
+///
+/// `Err` on a broken transport leaves the session in an unknown state;
+/// discard it, as after any other failed command.
+async fn fetch_section(
+    session: &mut ImapSession,
+    uid: u32,
+    kind: &str,
+    section: &str,
+    partial: Option<(usize, usize)>,
+) -> Result<Option<Vec<u8>>, String> {
+    let tag = tagged::next_tag("PRT");
+    let range = partial.map(|(o, l)| format!("<{}.{}>", o, l)).unwrap_or_default();
+    let what = format!("UID FETCH {} {}[{}]{}", uid, kind, section, range);
+    let command = format!("UID FETCH {} ({}.PEEK[{}]{})", uid, kind, section, range);
+    let reply = tagged::send(session.get_mut(), &tag, &command, &what).await?;
+    read_section(reply, &format!("{}[{}]", kind, section)).await
+}
+
+/// The section out of the reply behind `fetch_section`, on any byte stream.
+/// `name` is the item as the server echoes it, `BINARY[2]`.
+async fn read_section<S: Read + Unpin>(mut reply: Reply<'_, S>, name: &str) -> Result<Option<Vec<u8>>, String> {
+    let mut data = None;
+    while let Some(line) = reply.next().await? {
+        // An untagged line, continued after each literal it announces.
+        let mut text = line;
+        loop {
+            let upper = String::from_utf8_lossy(&text).to_ascii_uppercase();
+            let ours = upper.starts_with("* ") && upper.contains(name);
+            match literal_at_end(&text) {
+                Some(len) => {
+                    let bytes = reply.take(len).await?;
+                    if ours {
+                        data = Some(bytes);
+                    }
+                    text = reply.line().await?;
+                }
+                None => {
+                    if ours {
+                        data = data.or_else(|| quoted_after(&text, &upper, name));
+                    }
+                    break;
+                }
+            }
+        }
+    }
+    Ok(data)
+}
+
+/// `n` when `line` ends by announcing a literal, `{n}` or BINARY's `~{n}`.
+fn literal_at_end(line: &[u8]) -> Option<usize> {
+    let inner = line.strip_suffix(b"}")?;
+    let open = inner.iter().rposition(|b| *b == b'{')?;
+    std::str::from_utf8(&inner[open + 1..]).ok()?.parse().ok()
+}
+
+/// A section sent as a quoted string rather than a literal, as servers may
+/// for short ones: `BODY[1] "hello"`.
+fn quoted_after(line: &[u8], upper: &str, name: &str) -> Option<Vec<u8>> {
+    let mut i = upper.find(name)? + name.len();
+    if line.get(i) == Some(&b'<') {
+        i += line[i..].iter().position(|b| *b == b'>')? + 1;
+    }
+    let rest = line.get(i..)?.strip_prefix(b" \"")?;
+    let mut out = Vec::new();
+    let mut bytes = rest.iter();
+    while let Some(b) = bytes.next() {
+        match b {
+            b'"' => return Some(out),
+            b'\\' => out.push(*bytes.next()?),
+            b => out.push(*b),
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(reply: &[u8], name: &str) -> Result<Option<Vec<u8>>, String> {
+        let mut stream = async_std::io::Cursor::new(reply.to_vec());
+        async_std::task::block_on(read_section(Reply::new(&mut stream, "PRT1", "test"), name))
+    }
+
+    #[test]
+    fn reads_a_section_out_of_literals_of_either_kind() {
+        let reply = b"* 3 FETCH (UID 7 BINARY[2]<0> ~{5}\r\nab\r\nc)\r\nPRT1 OK done\r\n";
+        assert_eq!(read(reply, "BINARY[2]").unwrap(), Some(b"ab\r\nc".to_vec()));
+
+        // Untagged chatter with its own literal, then ours as a quoted string.
+        let reply = b"* 1 FETCH (BODY[HEADER] {3}\r\nx: )\r\n* 3 FETCH (UID 7 BODY[1] \"h\\\"i\")\r\nPRT1 OK\r\n";
+        assert_eq!(read(reply, "BODY[1]").unwrap(), Some(b"h\"i".to_vec()));
+
+        assert_eq!(read(b"* 3 FETCH (UID 7 BODY[9] NIL)\r\nPRT1 OK\r\n", "BODY[9]").unwrap(), None);
+        let refused = read(b"PRT1 NO [UNKNOWN-CTE] can't decode\r\n", "BINARY[1]").unwrap_err();
+        assert!(refused.contains("UNKNOWN-CTE"));
+        // What follows our completion belonged to someone else.
+        assert!(read(b"PRT1 OK\r\n* 4 EXISTS\r\n", "BODY[1]").is_err());
+    }
+
+    #[test]
+    fn chunks_cut_anywhere_decode_as_the_whole() {
+        let base64 = b"SGVsbG8sIGJpbmFy\r\neSB3b3JsZCE=\r\n";
+        let qp = b"caf=C3=A9 soft=\r\nbreak\r\nline two=3D\r\n";
+        for (encoding, data) in [("base64", &base64[..]), ("quoted-printable", &qp[..])] {
+            let whole = decode(encoding, data);
+            for size in 1..data.len() {
+                let mut decoder = Decoder::new(encoding);
+                let mut out = Vec::new();
+                for chunk in data.chunks(size) {
+                    out.extend(decoder.push(chunk));
+                }
+                out.extend(decoder.finish());
+                assert_eq!(out, whole, "{} in chunks of {}", encoding, size);
+            }
+        }
+        assert_eq!(decode("base64", base64), b"Hello, binary world!");
+    }
+}
@@ -1,3 +1,4 @@
+use std::any::Any;
 use std::collections::HashMap;
 use std::fmt;
 use std::sync::Arc;
@@ -6,17 +7,24 @@ use tokio::sync::{Mutex, OwnedSemaphorePermit, Semaphore};
 use tokio::time::Instant;
 use tracing::{info, warn};
 
-use super::{ImapConfig, create_imap_session};
+use super::{gmail, ImapConfig, create_imap_session};
 
 /// Trait alias for any stream type that can back an IMAP session.
 /// Using a trait object allows the pool to store both plain TLS and
 /// COMPRESS=DEFLATE sessions under the same `ImapSession` type.
 pub trait ImapTransport:
-    async_std::io::Read + async_std::io::Write + Unpin + fmt::Debug + Send {}
+    async_std::io::Read + async_std::io::Write + Unpin + fmt::Debug + Send
+{
+    /// For finding a wrapper (`Utf8Accept`) under the trait object.
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
 
-impl<T: async_std::io::Read + async_std::io::Write + Unpin + fmt::Debug + Send> ImapTransport
+impl<T: async_std::io::Read + async_std::io::Write + Unpin + fmt::Debug + Send + 'static> ImapTransport
     for T
 {
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
 }
 
 pub type ImapSession = async_imap::Session<Box<dyn ImapTransport>>;
@@ -97,6 +105,9 @@ pub struct ImapPool {
     priority: Arc<Mutex<HashMap<String, Vec<PooledSession>>>>,
     /// Cached server capabilities per connection key (e.g. CONDSTORE, ESEARCH)
     capabilities: Arc<Mutex<HashMap<String, Vec<String>>>>,
+    /// Gmail only: where each account's messages live, resolved with the
+    /// first session (see `create_imap_session`)
+    gmail_storage: Arc<Mutex<HashMap<String, gmail::Storage>>>,
     /// Per-account semaphores for background pool — prevents connection proliferation
     background_sem: Arc<Mutex<HashMap<String, Arc<Semaphore>>>>,
     /// Per-account semaphores for priority pool — separate from background to avoid blocking
@@ -109,6 +120,7 @@ impl ImapPool {
             background: Arc::new(Mutex::new(HashMap::new())),
             priority: Arc::new(Mutex::new(HashMap::new())),
             capabilities: Arc::new(Mutex::new(HashMap::new())),
+            gmail_storage: Arc::new(Mutex::new(HashMap::new())),
             background_sem: Arc::new(Mutex::new(HashMap::new())),
             priority_sem: Arc::new(Mutex::new(HashMap::new())),
         }
@@ -191,6 +203,16 @@ impl ImapPool {
         self.capabilities.lock().await.insert(key, caps);
     }
 
+    /// Gmail's All Mail, Trash and Spam for this account. `None` until a
+    /// session has been opened, and always for other servers.
+    pub async fn gmail_storage(&self, config: &ImapConfig) -> Option<gmail::Storage> {
+        self.gmail_storage.lock().await.get(&conn_key(config)).cloned()
+    }
+
+    pub async fn set_gmail_storage(&self, config: &ImapConfig, storage: gmail::Storage) {
+        self.gmail_storage.lock().await.insert(conn_key(config), storage);
+    }
+
     /// Disconnect a specific account from both pools
     pub async fn disconnect(&self, config: &ImapConfig) {
         let key = conn_key(config);
@@ -202,6 +224,7 @@ impl ImapPool {
             }
         }
         self.capabilities.lock().await.remove(&key);
+        self.gmail_storage.lock().await.remove(&key);
         self.background_sem.lock().await.remove(&key);
         self.priority_sem.lock().await.remove(&key);
 
@@ -221,6 +244,7 @@ impl ImapPool {
             }
         }
         self.capabilities.lock().await.clear();
+        self.gmail_storage.lock().await.clear();
 
         if to_logout.is_empty() {
             return;
@@ -0,0 +1,170 @@
+//! QRESYNC (RFC 7162): resynchronize a mailbox in the SELECT itself.
+//!
+//! `SELECT mailbox (QRESYNC (uidvalidity modseq known-uids))` makes the server
+//! answer with `* VANISHED (EARLIER)` for every known UID expunged since
+//! `modseq`, plus a FETCH for every flag change. That replaces the full
+//! `UID SEARCH ALL` diff the delta sync otherwise needs to notice deletions.
+//!
+//! async-imap has no QRESYNC SELECT and keeps its mailbox parser private, so
+//! the command is sent raw and its responses read here.
+
+use async_imap::types::Mailbox;
+use imap_proto::types::{AttributeValue, MailboxDatum, Response, ResponseCode, Status};
+use tracing::info;
+
+use super::ImapSession;
+
+/// What a QRESYNC SELECT reported.
+#[derive(Debug, Default)]
+pub struct QresyncSelect {
+    pub mailbox: Mailbox,
+    /// Known UIDs expunged since the given modseq. Empty when the server's
+    /// UIDVALIDITY no longer matches — it then ignores the QRESYNC parameters.
+    pub vanished: Vec<u32>,
+    /// `(uid, flags)` for messages whose flags changed since the given modseq.
+    pub changed_flags: Vec<(u32, Vec<String>)>,
+}
+
+/// ENABLE QRESYNC. RFC 5161 only allows ENABLE in the authenticated state, so
+/// this runs once right after login — a pooled session is usually already
+/// sitting in some selected mailbox.
+pub async fn enable_qresync(session: &mut ImapSession) -> Result<(), String> {
+    session
+        .run_command_and_check_ok("ENABLE QRESYNC")
+        .await
+        .map_err(|e| format!("ENABLE QRESYNC failed: {}", e))
+}
+
+/// Longest known-uids set sent with the SELECT. A sparse mailbox compresses
+/// badly — 100k scattered UIDs would make a command line hundreds of KB long,
+/// which servers refuse — so past this the set is left out (RFC 7162 §3.2.5
+/// makes it optional) and the server reports every UID expunged since the
+/// modseq, known to us or not.
+const KNOWN_UIDS_MAX: usize = 1000;
+
+/// SELECT with QRESYNC parameters. Requires `enable_qresync` on this session.
+///
+/// `Ok(None)` means the server answered the SELECT with NO/BAD: the tagged
+/// reply was read, the session is clean, and the caller can fall back to a
+/// plain SELECT on it. `Err` is a transport or parse failure — discard the
+/// session, exactly as for any other failed command.
+pub async fn select_qresync(
+    session: &mut ImapSession,
+    mailbox: &str,
+    uid_validity: u32,
+    modseq: u64,
+    known_uids: &[u32],
+) -> Result<Option<QresyncSelect>, String> {
+    let known = match uid_set(known_uids) {
+        set if set.is_empty() || set.len() > KNOWN_UIDS_MAX => String::new(),
+        set => format!(" {}", set),
+    };
+    let quoted = format!("\"{}\"", super::utf8_accept::wire_name(session, mailbox).replace('\\', "\\\\").replace('"', "\\\""));
+    let tag = session
+        .run_command(format!(
+            "SELECT {} (QRESYNC ({} {}{}))",
+            quoted, uid_validity, modseq, known
+        ))
+        .await
+        .map_err(|e| format!("SELECT QRESYNC {} failed: {}", mailbox, e))?;
+
+    let mut out = QresyncSelect::default();
+    loop {
+        let resp = session
+            .read_response()
+            .await
+            .map_err(|e| format!("SELECT QRESYNC {} failed: {}", mailbox, e))?
+            .ok_or_else(|| format!("SELECT QRESYNC {}: connection closed", mailbox))?;
+
+        match resp.parsed() {
+            Response::Done { tag: t, status, information, .. } if *t == tag => {
+                if *status != Status::Ok {
+                    info!(
+                        "[IMAP] SELECT QRESYNC {} refused ({:?} {:?}) — falling back",
+                        mailbox, status, information
+                    );
+                    return Ok(None);
+                }
+                break;
+            }
+            Response::Data { status: Status::Ok, code: Some(code), .. } => match code {
+                ResponseCode::UidValidity(v) => out.mailbox.uid_validity = Some(*v),
+                ResponseCode::UidNext(v) => out.mailbox.uid_next = Some(*v),
+                ResponseCode::HighestModSeq(v) => out.mailbox.highest_modseq = Some(*v),
+                _ => {}
+            },
+            Response::MailboxData(MailboxDatum::Exists(n)) => out.mailbox.exists = *n,
+            Response::MailboxData(MailboxDatum::Recent(n)) => out.mailbox.recent = *n,
+            Response::Vanished { uids, .. } => {
+                out.vanished.extend(uids.iter().flat_map(|r| r.clone()));
+            }
+            Response::Fetch(_, attrs) => {
+                let uid = attrs.iter().find_map(|a| match a {
+                    AttributeValue::Uid(u) => Some(*u),
+                    _ => None,
+                });
+                let flags = attrs.iter().find_map(|a| match a {
+                    AttributeValue::Flags(f) => Some(f.iter().map(|s| s.to_string()).collect()),
+                    _ => None,
+                });
+                if let (Some(uid), Some(flags)) = (uid, flags) {
+                    out.changed_flags.push((uid, flags));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    info!(
+        "[IMAP] SELECT QRESYNC {}: {} vanished, {} flag changes",
+        mailbox,
+        out.vanished.len(),
+        out.changed_flags.len()
+    );
+    Ok(Some(out))
+}
+
+/// Compress UIDs into an IMAP sequence set: `[1,2,3,7,9,10]` → `1:3,7,9:10`.
+pub fn uid_set(uids: &[u32]) -> String {
+    let mut sorted = uids.to_vec();
+    sorted.sort_unstable();
+    sorted.dedup();
+
+    let mut parts = Vec::new();
+    let mut iter = sorted.into_iter();
+    let Some(mut start) = iter.next() else {
+        return String::new();
+    };
+    let mut end = start;
+    for uid in iter {
+        if uid == end + 1 {
+            end = uid;
+            continue;
+        }
+        parts.push(range(start, end));
+        start = uid;
+        end = uid;
+    }
+    parts.push(range(start, end));
+    parts.join(",")
+}
+
+fn range(start: u32, end: u32) -> String {
+    if start == end {
+        start.to_string()
+    } else {
+        format!("{}:{}", start, end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uid_set_compresses_runs() {
+        assert_eq!(uid_set(&[9, 1, 2, 3, 7, 10, 3]), "1:3,7,9:10");
+        assert_eq!(uid_set(&[5]), "5");
+        assert_eq!(uid_set(&[]), "");
+    }
+}
@@ -0,0 +1,48 @@
+//! QUOTA (RFC 2087 / RFC 9208): how much the server lets the account hold.
+
+use async_imap::types::{Quota, QuotaResourceName};
+
+use super::ImapSession;
+use crate::quota::{MailboxQuota, QuotaResource, QuotaRoot};
+
+pub const QUOTA: &str = "QUOTA";
+
+fn root(quota: Quota) -> QuotaRoot {
+    QuotaRoot {
+        name: quota.root_name,
+        resources: quota
+            .resources
+            .into_iter()
+            .map(|r| QuotaResource {
+                name: match r.name {
+                    QuotaResourceName::Storage => "STORAGE".to_string(),
+                    QuotaResourceName::Message => "MESSAGE".to_string(),
+                    QuotaResourceName::Atom(a) => a.to_uppercase(),
+                },
+                usage: r.usage,
+                limit: r.limit,
+            })
+            .collect(),
+    }
+}
+
+/// GETQUOTAROOT INBOX, and GETQUOTA for any root it named without sending
+/// that root's QUOTA along. `None` when INBOX falls under no limit.
+pub async fn get_quota(session: &mut ImapSession) -> Result<Option<MailboxQuota>, String> {
+    let (names, quotas) = session
+        .get_quota_root("INBOX")
+        .await
+        .map_err(|e| format!("GETQUOTAROOT failed: {}", e))?;
+    let mut roots: Vec<QuotaRoot> = quotas.into_iter().map(root).collect();
+    for name in names.into_iter().flat_map(|r| r.quota_root_names) {
+        if roots.iter().any(|r| r.name == name) {
+            continue;
+        }
+        let quota = session
+            .get_quota(&name)
+            .await
+            .map_err(|e| format!("GETQUOTA '{}' failed: {}", name, e))?;
+        roots.push(root(quota));
+    }
+    Ok(MailboxQuota::from_imap_roots(roots))
+}
@@ -0,0 +1,103 @@
+//! Commands written to the transport directly, for replies imap-proto can't
+//! parse: a FETCH item it doesn't know poisons the session's response stream.
+//!
+//! A command goes out under a tag of its own and its reply is read off the
+//! transport line by line, up to and including the tagged completion —
+//! nothing the session's own reader would have seen is taken from it.
+//! `objectid` and `parts` both fetch this way.
+
+use async_std::io::prelude::*;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// A tag no other command on any session has used, `prefix` first.
+pub(crate) fn next_tag(prefix: &str) -> String {
+    static NEXT_TAG: AtomicU32 = AtomicU32::new(1);
+    format!("{}{}", prefix, NEXT_TAG.fetch_add(1, Ordering::Relaxed))
+}
+
+/// Write `command` under `tag` and flush it. `what` names the command in
+/// errors.
+pub(crate) async fn send<'a, S: Read + Write + Unpin>(
+    stream: &'a mut S,
+    tag: &str,
+    command: &str,
+    what: &'a str,
+) -> Result<Reply<'a, S>, String> {
+    stream
+        .write_all(format!("{} {}\r\n", tag, command).as_bytes())
+        .await
+        .map_err(|e| format!("{} failed: {}", what, e))?;
+    stream.flush().await.map_err(|e| format!("{} failed: {}", what, e))?;
+    Ok(Reply::new(stream, tag, what))
+}
+
+/// The reply to one tagged command, read off `stream`.
+pub(crate) struct Reply<'a, S> {
+    stream: &'a mut S,
+    pending: Vec<u8>,
+    tag: String,
+    what: &'a str,
+}
+
+impl<'a, S: Read + Unpin> Reply<'a, S> {
+    pub(crate) fn new(stream: &'a mut S, tag: &str, what: &'a str) -> Self {
+        Reply { stream, pending: Vec::new(), tag: tag.to_string(), what }
+    }
+
+    /// The next untagged line, or `None` once the command completed OK. A
+    /// NO or BAD is an `Err` carrying the server's text.
+    pub(crate) async fn next(&mut self) -> Result<Option<Vec<u8>>, String> {
+        let line = self.line().await?;
+        let Some(status) = line.strip_prefix(self.tag.as_bytes()).and_then(|r| r.strip_prefix(b" ")) else {
+            return Ok(Some(line));
+        };
+        let status = String::from_utf8_lossy(status);
+        if !status.get(..2).is_some_and(|s| s.eq_ignore_ascii_case("OK")) {
+            return Err(format!("{} failed: {}", self.what, status));
+        }
+        // Anything after our completion was meant for the session's reader,
+        // and it's gone now.
+        if !self.pending.is_empty() {
+            return Err(format!("{}: {} unread bytes after the reply", self.what, self.pending.len()));
+        }
+        Ok(None)
+    }
+
+    /// The next line as it comes, without its CRLF — the rest of a response
+    /// after a literal.
+    pub(crate) async fn line(&mut self) -> Result<Vec<u8>, String> {
+        let mut searched = 0;
+        loop {
+            if let Some(end) = self.pending[searched..].windows(2).position(|w| w == b"\r\n") {
+                let end = searched + end;
+                let line = self.pending[..end].to_vec();
+                self.pending.drain(..end + 2);
+                return Ok(line);
+            }
+            searched = self.pending.len().saturating_sub(1);
+            self.fill().await?;
+        }
+    }
+
+    /// The next `n` bytes — a literal's content.
+    pub(crate) async fn take(&mut self, n: usize) -> Result<Vec<u8>, String> {
+        while self.pending.len() < n {
+            self.fill().await?;
+        }
+        Ok(self.pending.drain(..n).collect())
+    }
+
+    async fn fill(&mut self) -> Result<(), String> {
+        let mut chunk = [0u8; 8192];
+        let n = self
+            .stream
+            .read(&mut chunk)
+            .await
+            .map_err(|e| format!("{} failed: {}", self.what, e))?;
+        if n == 0 {
+            return Err(format!("{}: connection closed", self.what));
+        }
+        self.pending.extend_from_slice(&chunk[..n]);
+        Ok(())
+    }
+}
@@ -0,0 +1,224 @@
+//! UIDPLUS (RFC 4315): the UIDs a server gives what we put there.
+//!
+//! A server with UIDPLUS answers APPEND with `[APPENDUID validity uid]` and
+//! COPY/MOVE with `[COPYUID validity source-uids dest-uids]`, so the vault's
+//! copy of a message can follow it to its new UID instead of being left
+//! behind under the old one and downloaded again.
+//!
+//! async-imap's `append`, `uid_copy` and `uid_mv` read the tagged reply and
+//! drop its response code, so these send the commands raw and read the
+//! replies here, as `qresync` does for its SELECT.
+
+use imap_proto::types::{RequestId, Response, ResponseCode, Status, UidSetMember};
+use tracing::info;
+
+use super::ImapSession;
+
+/// Where COPY or MOVE put each message.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CopyUid {
+    /// UIDVALIDITY of the destination mailbox.
+    pub uid_validity: u32,
+    /// `(source uid, destination uid)`, in the server's order.
+    pub uids: Vec<(u32, u32)>,
+}
+
+/// Where APPEND put the message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AppendUid {
+    /// UIDVALIDITY of the mailbox appended to.
+    pub uid_validity: u32,
+    pub uid: u32,
+}
+
+/// How many uids `set` names, without listing them: a server can name four
+/// billion in a dozen bytes.
+fn count(set: &[UidSetMember]) -> u64 {
+    set.iter()
+        .map(|m| match m {
+            UidSetMember::UidRange(r) => (*r.end() as u64 + 1).saturating_sub(*r.start() as u64),
+            UidSetMember::Uid(_) => 1,
+        })
+        .sum()
+}
+
+/// Every uid in `set`. Only for a set `count` has bounded.
+fn expand(set: &[UidSetMember]) -> Vec<u32> {
+    set.iter()
+        .flat_map(|m| match m {
+            UidSetMember::UidRange(r) => r.clone().collect::<Vec<_>>(),
+            UidSetMember::Uid(u) => vec![*u],
+        })
+        .collect()
+}
+
+/// The `COPYUID` in a response code, for a command that named `requested`
+/// uids. `None` when the two sets don't pair up or name more than that — a
+/// server that miscounts gets no benefit of the doubt.
+pub fn copy_uid(code: &ResponseCode, requested: usize) -> Option<CopyUid> {
+    let ResponseCode::CopyUid(validity, from, to) = code else { return None };
+    let n = count(from);
+    if n != count(to) || n > requested as u64 {
+        return None;
+    }
+    Some(CopyUid { uid_validity: *validity, uids: expand(from).into_iter().zip(expand(to)).collect() })
+}
+
+/// The `APPENDUID` in a response code. One message was appended, so a set
+/// naming more than one uid is no answer.
+pub fn append_uid(code: &ResponseCode) -> Option<AppendUid> {
+    match code {
+        ResponseCode::AppendUid(validity, uids) if count(uids) == 1 => {
+            Some(AppendUid { uid_validity: *validity, uid: *expand(uids).first()? })
+        }
+        _ => None,
+    }
+}
+
+fn quoted(session: &mut ImapSession, mailbox: &str) -> String {
+    format!("\"{}\"", super::utf8_accept::wire_name(session, mailbox).replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Read responses up to the tagged reply to `tag`, collecting every response
+/// code on the way — MOVE reports COPYUID untagged, ahead of its expunges.
+pub(super) async fn read_codes<T>(
+    session: &mut ImapSession,
+    tag: &RequestId,
+    what: &str,
+    pick: impl Fn(&ResponseCode) -> Option<T>,
+) -> Result<Option<T>, String> {
+    let mut found = None;
+    loop {
+        let resp = session
+            .read_response()
+            .await
+            .map_err(|e| format!("{} failed: {}", what, e))?
+            .ok_or_else(|| format!("{}: connection closed", what))?;
+        match resp.parsed() {
+            Response::Done { tag: t, status, code, information } if t == tag => {
+                if *status != Status::Ok {
+                    return Err(format!("{} failed: {:?} {}", what, status, information.as_deref().unwrap_or("")));
+                }
+                return Ok(code.as_ref().and_then(&pick).or(found));
+            }
+            Response::Data { code: Some(code), .. } => {
+                if let Some(v) = pick(code) {
+                    found = Some(v);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// APPEND `raw` to `mailbox`. Returns where the message went when the server
+/// says. `flags` and `date` are the command's parenthesized flag list
+/// and quoted date-time, as `append_email_at` builds them.
+pub async fn append(
+    session: &mut ImapSession,
+    mailbox: &str,
+    flags: Option<&str>,
+    date: Option<&str>,
+    raw: &[u8],
+) -> Result<Option<AppendUid>, String> {
+    let what = format!("IMAP APPEND to '{}'", mailbox);
+    let mut command = format!("APPEND {}", quoted(session, mailbox));
+    for arg in [flags, date].into_iter().flatten() {
+        command.push(' ');
+        command.push_str(arg);
+    }
+    let tag = session
+        .run_command(format!("{} {{{}}}", command, raw.len()))
+        .await
+        .map_err(|e| format!("{} failed: {}", what, e))?;
+
+    // A synchronizing literal: the server says go ahead, or refuses outright.
+    loop {
+        let resp = session
+            .read_response()
+            .await
+            .map_err(|e| format!("{} failed: {}", what, e))?
+            .ok_or_else(|| format!("{}: connection closed", what))?;
+        match resp.parsed() {
+            Response::Continue { .. } => break,
+            Response::Done { tag: t, status, information, .. } if *t == tag => {
+                return Err(format!("{} failed: {:?} {}", what, status, information.as_deref().unwrap_or("")));
+            }
+            _ => {}
+        }
+    }
+    // SAFETY: the literal goes to the wire as bytes. `run_command_untagged`
+    // only wants `&str` to call `.as_bytes()` on it, as in
+    // `append_email_verified`.
+    let literal: &str = unsafe { std::str::from_utf8_unchecked(raw) };
+    session
+        .run_command_untagged(literal)
+        .await
+        .map_err(|e| format!("{} failed: {}", what, e))?;
+    read_codes(session, &tag, &what, append_uid).await
+}
+
+/// UID COPY, or UID MOVE when `is_move`, of `uid_set` — `count` uids — from
+/// the selected mailbox to `target`. Returns where the messages went when the
+/// server says.
+pub async fn copy(
+    session: &mut ImapSession,
+    uid_set: &str,
+    count: usize,
+    target: &str,
+    is_move: bool,
+) -> Result<Option<CopyUid>, String> {
+    let verb = if is_move { "UID MOVE" } else { "UID COPY" };
+    let what = format!("{} to '{}'", verb, target);
+    let target_wire = quoted(session, target);
+    let tag = session
+        .run_command(format!("{} {} {}", verb, uid_set, target_wire))
+        .await
+        .map_err(|e| format!("{} failed: {}", what, e))?;
+    let mapping = read_codes(session, &tag, &what, |code| copy_uid(code, count)).await?;
+    if let Some(m) = &mapping {
+        info!("[IMAP] {}: {} UIDs reported (validity {})", what, m.uids.len(), m.uid_validity);
+    }
+    Ok(mapping)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use imap_proto::parser::parse_response;
+
+    fn code_of(line: &[u8]) -> ResponseCode<'_> {
+        match parse_response(line).unwrap().1 {
+            Response::Done { code: Some(code), .. } | Response::Data { code: Some(code), .. } => code,
+            other => panic!("no response code in {:?}", other),
+        }
+    }
+
+    #[test]
+    fn copyuid_pairs_ranges_in_order() {
+        let code = code_of(b"A3 OK [COPYUID 38505 304,319:320 3956:3958] Done\r\n");
+        assert_eq!(
+            copy_uid(&code, 3),
+            Some(CopyUid { uid_validity: 38505, uids: vec![(304, 3956), (319, 3957), (320, 3958)] })
+        );
+        // Untagged, as MOVE sends it.
+        let code = code_of(b"* OK [COPYUID 7 5 12] Moved\r\n");
+        assert_eq!(copy_uid(&code, 1).unwrap().uids, vec![(5, 12)]);
+        // Sets that don't pair up are no answer at all.
+        assert_eq!(copy_uid(&code_of(b"A4 OK [COPYUID 7 1:3 9] Done\r\n"), 3), None);
+
+        assert_eq!(
+            append_uid(&code_of(b"A5 OK [APPENDUID 38505 3955] APPEND completed\r\n")),
+            Some(AppendUid { uid_validity: 38505, uid: 3955 })
+        );
+        assert_eq!(append_uid(&code_of(b"A6 OK [READ-WRITE] done\r\n")), None);
+    }
+
+    #[test]
+    fn sets_larger_than_the_command_are_refused_unexpanded() {
+        let huge = code_of(b"A7 OK [COPYUID 7 1:4294967295 1:4294967295] Done\r\n");
+        assert_eq!(copy_uid(&huge, 2), None);
+        assert_eq!(copy_uid(&code_of(b"A8 OK [COPYUID 7 1:3 7:9] Done\r\n"), 2), None);
+        assert_eq!(append_uid(&code_of(b"A9 OK [APPENDUID 7 1:4294967295] Done\r\n")), None);
+    }
+}
@@ -0,0 +1,125 @@
+//! IMAP modified UTF-7 (RFC 3501 §5.1.3) — how mailbox names travel on a
+//! server that hasn't enabled `UTF8=ACCEPT`.
+//!
+//! Printable ASCII stands for itself except `&`, which becomes `&-`. Anything
+//! else is UTF-16BE, base64'd with `,` in place of `/` and no padding, between
+//! `&` and `-`: `Отправленные` is `&BB4EQgQ,BEAEMAQyBDsENQQ9BD0ESwQ1-`.
+//!
+//! Everything above the wire — `list_mailboxes`, the Maildir paths, the app —
+//! uses the decoded name; each command that takes a mailbox encodes it back.
+//! A session that enabled `UTF8=ACCEPT` sends and receives names as plain
+//! UTF-8 instead; see `utf8_accept`.
+
+use base64::alphabet::Alphabet;
+use base64::engine::general_purpose::{GeneralPurpose, NO_PAD};
+use base64::Engine;
+
+const MODIFIED_BASE64: GeneralPurpose = GeneralPurpose::new(
+    &match Alphabet::new("ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+,") {
+        Ok(a) => a,
+        Err(_) => panic!("invalid modified base64 alphabet"),
+    },
+    NO_PAD,
+);
+
+/// Encode a mailbox name for the wire. Plain ASCII names come back unchanged,
+/// apart from `&`.
+pub fn encode(name: &str) -> String {
+    let mut out = String::with_capacity(name.len());
+    let mut pending: Vec<u16> = Vec::new();
+    for c in name.chars() {
+        if (' '..='~').contains(&c) {
+            flush(&mut out, &mut pending);
+            if c == '&' {
+                out.push_str("&-");
+            } else {
+                out.push(c);
+            }
+        } else {
+            let mut buf = [0u16; 2];
+            pending.extend_from_slice(c.encode_utf16(&mut buf));
+        }
+    }
+    flush(&mut out, &mut pending);
+    out
+}
+
+fn flush(out: &mut String, pending: &mut Vec<u16>) {
+    if pending.is_empty() {
+        return;
+    }
+    let bytes: Vec<u8> = pending.iter().flat_map(|u| u.to_be_bytes()).collect();
+    out.push('&');
+    out.push_str(&MODIFIED_BASE64.encode(bytes));
+    out.push('-');
+    pending.clear();
+}
+
+/// Decode a name as the server sent it. `None` if it is not valid modified
+/// UTF-7 — an unterminated `&`, bad base64, or broken UTF-16.
+pub fn try_decode(name: &str) -> Option<String> {
+    let mut out = String::with_capacity(name.len());
+    let mut rest = name;
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let end = after.find('-')?;
+        let chunk = &after[..end];
+        if chunk.is_empty() {
+            out.push('&');
+        } else {
+            let bytes = MODIFIED_BASE64.decode(chunk).ok()?;
+            if bytes.len() % 2 != 0 {
+                return None;
+            }
+            let units: Vec<u16> = bytes.chunks(2).map(|b| u16::from_be_bytes([b[0], b[1]])).collect();
+            out.push_str(&String::from_utf16(&units).ok()?);
+        }
+        rest = &after[end + 1..];
+    }
+    out.push_str(rest);
+    Some(out)
+}
+
+/// Decode for display and storage. A name that isn't valid modified UTF-7 is
+/// kept exactly as the server sent it — some servers hand out raw UTF-8
+/// without being asked, and that is already what we want.
+pub fn decode(name: &str) -> String {
+    try_decode(name).unwrap_or_else(|| name.to_string())
+}
+
+/// Does this name carry any encoded run (or an escaped `&`)? Used to find
+/// vault directories written under the encoded name.
+pub fn is_encoded(name: &str) -> bool {
+    name.contains('&') && try_decode(name).is_some_and(|d| d != name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_the_rfc_and_real_world_names() {
+        let cases = [
+            ("INBOX", "INBOX"),
+            ("Tom & Jerry", "Tom &- Jerry"),
+            ("Отправленные", "&BB4EQgQ,BEAEMAQyBDsENQQ9BD0ESwQ1-"),
+            ("~peter/mail/台北/日本語", "~peter/mail/&U,BTFw-/&ZeVnLIqe-"),
+            ("Entwürfe", "Entw&APw-rfe"),
+            ("📬 Later", "&2D3c7A- Later"),
+        ];
+        for (plain, wire) in cases {
+            assert_eq!(encode(plain), wire, "encode {plain}");
+            assert_eq!(decode(wire), plain, "decode {wire}");
+        }
+    }
+
+    #[test]
+    fn invalid_input_is_left_as_sent() {
+        assert_eq!(try_decode("Broken &AB"), None);
+        assert_eq!(decode("Broken &AB"), "Broken &AB");
+        assert_eq!(decode("Отправленные"), "Отправленные", "raw UTF-8 passes through");
+        assert!(is_encoded("&BB4EQgQ,BEAEMAQyBDsENQQ9BD0ESwQ1-"));
+        assert!(!is_encoded("Sent Items"));
+    }
+}
@@ -0,0 +1,345 @@
+//! UTF8=ACCEPT (RFC 6855): mailbox names and header fields as raw UTF-8.
+//!
+//! Once enabled, the server stops encoding mailbox names as modified UTF-7
+//! and may send any string — LIST names, ENVELOPE fields — as a quoted string
+//! holding raw UTF-8. imap-proto only parses 7-bit quoted strings, so the
+//! first non-ASCII folder would fail the whole LIST. `Utf8Accept` sits on top
+//! of the session's stream and rewrites each such quoted string into the
+//! literal carrying the same bytes, which imap-proto does accept.
+//!
+//! Only strings where the grammar has one are rewritten: in LIST, LSUB and
+//! STATUS replies (the mailbox name) and in FETCH data (ENVELOPE fields,
+//! BODYSTRUCTURE parameters). A quote in resp-text — `* OK [ALERT] "café" …`
+//! — is prose, and a literal there would split the line.
+//!
+//! The rewrite only starts after a successful `enable`; until then the stream
+//! passes through untouched. Commands then carry names as UTF-8 too
+//! (`wire_name`), and SEARCH drops its `CHARSET` — RFC 6855 forbids it.
+
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+use tracing::info;
+
+use super::{utf7, ImapSession, ImapTransport};
+
+/// Stream wrapper that turns 8-bit quoted strings into literals once enabled.
+#[derive(Debug)]
+pub struct Utf8Accept {
+    inner: Box<dyn ImapTransport>,
+    on: bool,
+    scan: Scan,
+    /// Rewritten bytes not yet handed to the reader.
+    out: Vec<u8>,
+    at: usize,
+}
+
+impl Utf8Accept {
+    pub fn new(inner: Box<dyn ImapTransport>) -> Self {
+        Self { inner, on: false, scan: Scan::default(), out: Vec::new(), at: 0 }
+    }
+}
+
+fn shim(session: &mut ImapSession) -> Option<&mut Utf8Accept> {
+    // Deref the box first: `Box<dyn ImapTransport>` is itself an
+    // `ImapTransport`, and its `as_any_mut` would hand back the box.
+    let transport: &mut dyn ImapTransport = session.get_mut().as_mut();
+    transport.as_any_mut().downcast_mut::<Utf8Accept>()
+}
+
+/// Has this session enabled UTF8=ACCEPT?
+pub fn enabled(session: &mut ImapSession) -> bool {
+    shim(session).is_some_and(|s| s.on)
+}
+
+/// ENABLE UTF8=ACCEPT. Like ENABLE QRESYNC, this must run in the
+/// authenticated state, right after login. A session whose stream isn't
+/// wrapped in `Utf8Accept` could not parse the replies, so it is refused
+/// without asking the server.
+pub async fn enable(session: &mut ImapSession) -> Result<(), String> {
+    if shim(session).is_none() {
+        return Err("ENABLE UTF8=ACCEPT skipped: stream cannot rewrite UTF-8 strings".to_string());
+    }
+    session
+        .run_command_and_check_ok("ENABLE UTF8=ACCEPT")
+        .await
+        .map_err(|e| format!("ENABLE UTF8=ACCEPT failed: {}", e))?;
+    if let Some(s) = shim(session) {
+        s.on = true;
+    }
+    info!("[IMAP] UTF8=ACCEPT enabled");
+    Ok(())
+}
+
+/// A mailbox name as this session's commands must carry it.
+pub fn wire_name(session: &mut ImapSession, name: &str) -> String {
+    if enabled(session) {
+        name.to_string()
+    } else {
+        utf7::encode(name)
+    }
+}
+
+/// A mailbox name as this session's LIST reported it, decoded.
+pub fn name_from_wire(session: &mut ImapSession, wire: &str) -> String {
+    if enabled(session) {
+        wire.to_string()
+    } else {
+        utf7::decode(wire)
+    }
+}
+
+/// UID SEARCH criteria for this session: `CHARSET UTF-8` is implied, and
+/// forbidden, once UTF8=ACCEPT is on.
+pub fn search_criteria(session: &mut ImapSession, criteria: String) -> String {
+    match criteria.strip_prefix("CHARSET UTF-8 ") {
+        Some(rest) if enabled(session) => rest.to_string(),
+        _ => criteria,
+    }
+}
+
+// ── Rewriting ───────────────────────────────────────────────────────────────
+
+#[derive(Debug, Default)]
+enum State {
+    #[default]
+    Line,
+    Quoted,
+    Escaped,
+    Literal(usize),
+}
+
+/// Where the reader is in the server's response stream.
+#[derive(Debug, Default)]
+struct Scan {
+    state: State,
+    /// The last few bytes of the current line, to spot a `{n}` literal.
+    tail: Vec<u8>,
+    /// The first bytes of the current response, to tell whether its quoted
+    /// strings are strings. Kept across the literals inside one response.
+    head: Vec<u8>,
+    /// The quoted string being read, escapes and all.
+    quoted: Vec<u8>,
+}
+
+const TAIL: usize = 24;
+const HEAD: usize = 24;
+
+impl Scan {
+    fn feed(&mut self, mut input: &[u8], out: &mut Vec<u8>) {
+        while let Some(&b) = input.first() {
+            match self.state {
+                State::Literal(n) => {
+                    let run = n.min(input.len());
+                    out.extend_from_slice(&input[..run]);
+                    input = &input[run..];
+                    self.state = if run == n { State::Line } else { State::Literal(n - run) };
+                    continue;
+                }
+                State::Line => self.line(b, out),
+                State::Escaped => {
+                    self.quoted.push(b);
+                    self.state = State::Quoted;
+                }
+                State::Quoted => match b {
+                    b'\\' => {
+                        self.quoted.push(b);
+                        self.state = State::Escaped;
+                    }
+                    b'"' => {
+                        self.close_quoted(out);
+                        self.state = State::Line;
+                    }
+                    b'\r' | b'\n' => {
+                        // Not a quoted string after all; hand it on as it came.
+                        self.abandon(out);
+                        self.line(b, out);
+                    }
+                    _ => self.quoted.push(b),
+                },
+            }
+            input = &input[1..];
+        }
+    }
+
+    fn line(&mut self, b: u8, out: &mut Vec<u8>) {
+        if b == b'"' && has_strings(&self.head) {
+            self.quoted.clear();
+            self.state = State::Quoted;
+            return;
+        }
+        out.push(b);
+        self.remember(b);
+        if self.head.len() < HEAD {
+            self.head.push(b);
+        }
+        self.state = State::Line;
+        if b == b'\n' {
+            match literal_len(&self.tail).filter(|&n| n > 0) {
+                Some(n) => self.state = State::Literal(n),
+                None => self.head.clear(),
+            }
+            self.tail.clear();
+        }
+    }
+
+    fn remember(&mut self, b: u8) {
+        if self.tail.len() == TAIL {
+            self.tail.remove(0);
+        }
+        self.tail.push(b);
+    }
+
+    fn close_quoted(&mut self, out: &mut Vec<u8>) {
+        if self.quoted.is_ascii() {
+            out.push(b'"');
+            out.extend_from_slice(&self.quoted);
+            out.push(b'"');
+        } else {
+            let mut bytes = Vec::with_capacity(self.quoted.len());
+            let mut escaped = false;
+            for &c in &self.quoted {
+                if c == b'\\' && !escaped {
+                    escaped = true;
+                } else {
+                    bytes.push(c);
+                    escaped = false;
+                }
+            }
+            out.extend_from_slice(format!("{{{}}}\r\n", bytes.len()).as_bytes());
+            out.extend_from_slice(&bytes);
+        }
+        // Whatever follows a string, it is not the `{n}` of a literal.
+        self.remember(b'"');
+        self.quoted.clear();
+    }
+
+    fn abandon(&mut self, out: &mut Vec<u8>) {
+        out.push(b'"');
+        out.extend_from_slice(&self.quoted);
+        self.quoted.clear();
+        self.state = State::Line;
+    }
+}
+
+/// Does a response starting with `head` carry strings a quote can open:
+/// `* LIST`, `* LSUB`, `* STATUS` or `* n FETCH`?
+fn has_strings(head: &[u8]) -> bool {
+    let Some(rest) = head.strip_prefix(b"* ") else { return false };
+    let mut words = rest.split(|&c| c == b' ');
+    let first = words.next().unwrap_or_default();
+    let keyword = if !first.is_empty() && first.iter().all(u8::is_ascii_digit) {
+        words.next().unwrap_or_default()
+    } else {
+        first
+    };
+    [&b"LIST"[..], b"LSUB", b"STATUS", b"FETCH"].iter().any(|k| keyword.eq_ignore_ascii_case(k))
+}
+
+/// `n` when a line ends in `{n}\r\n` (or the literal8 `~{n}\r\n`).
+fn literal_len(tail: &[u8]) -> Option<usize> {
+    let body = tail.strip_suffix(b"}\r\n")?;
+    let open = body.iter().rposition(|&c| c == b'{')?;
+    let digits = &body[open + 1..];
+    if digits.is_empty() || !digits.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    std::str::from_utf8(digits).ok()?.parse().ok()
+}
+
+impl async_std::io::Read for Utf8Accept {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<std::io::Result<usize>> {
+        let this = &mut *self;
+        if !this.on {
+            return Pin::new(&mut this.inner).poll_read(cx, buf);
+        }
+        loop {
+            if this.at < this.out.len() {
+                let n = buf.len().min(this.out.len() - this.at);
+                buf[..n].copy_from_slice(&this.out[this.at..this.at + n]);
+                this.at += n;
+                if this.at == this.out.len() {
+                    this.out.clear();
+                    this.at = 0;
+                }
+                return Poll::Ready(Ok(n));
+            }
+            let mut raw = [0u8; 8192];
+            let n = ready!(Pin::new(&mut this.inner).poll_read(cx, &mut raw))?;
+            if n == 0 {
+                if matches!(this.scan.state, State::Quoted | State::Escaped) {
+                    this.scan.abandon(&mut this.out);
+                    continue;
+                }
+                return Poll::Ready(Ok(0));
+            }
+            this.scan.feed(&raw[..n], &mut this.out);
+        }
+    }
+}
+
+impl async_std::io::Write for Utf8Accept {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<std::io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_close(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rewrite(chunks: &[&[u8]]) -> Vec<u8> {
+        let mut scan = Scan::default();
+        let mut out = Vec::new();
+        for chunk in chunks {
+            scan.feed(chunk, &mut out);
+        }
+        out
+    }
+
+    #[test]
+    fn utf8_quoted_strings_become_literals() {
+        let line = "* LIST () \"/\" \"Entwürfe\"\r\n".as_bytes();
+        assert_eq!(rewrite(&[line]), "* LIST () \"/\" {9}\r\nEntwürfe\r\n".as_bytes());
+        // Escapes are undone inside the literal; ASCII strings stay quoted.
+        let line = "* LIST () \"/\" \"Ü \\\"x\\\"\"\r\n".as_bytes();
+        assert_eq!(rewrite(&[line]), "* LIST () \"/\" {6}\r\nÜ \"x\"\r\n".as_bytes());
+        assert_eq!(rewrite(&[b"* LIST () \"/\" \"INBOX\"\r\n"]), b"* LIST () \"/\" \"INBOX\"\r\n");
+    }
+
+    #[test]
+    fn literals_pass_through_untouched_even_across_reads() {
+        let body = "Subject: \"Grüße\"\r\n";
+        let wire = format!("* 1 FETCH (UID 5 BODY[] {{{}}}\r\n{}) \"ü\"\r\n", body.len(), body);
+        let expected = format!("* 1 FETCH (UID 5 BODY[] {{{}}}\r\n{}) {{2}}\r\nü\r\n", body.len(), body);
+        assert_eq!(rewrite(&[wire.as_bytes()]), expected.as_bytes());
+        let bytes = wire.as_bytes();
+        let split: Vec<&[u8]> = bytes.chunks(3).collect();
+        assert_eq!(rewrite(&split), expected.as_bytes());
+    }
+
+    #[test]
+    fn an_unterminated_quote_is_left_as_it_came() {
+        let line = "* LIST () \"/\" \"hä\r\nA1 OK done\r\n".as_bytes();
+        assert_eq!(rewrite(&[line]), line);
+    }
+
+    #[test]
+    fn quotes_in_resp_text_are_prose_not_strings() {
+        let lines = "* OK [ALERT] \"café\" is over quota\r\nA1 NO \"Entwürfe\" locked\r\n".as_bytes();
+        assert_eq!(rewrite(&[lines]), lines);
+        // A STATUS reply after them is still rewritten, a numbered FETCH too.
+        let line = "* OK \"ü\"\r\n* STATUS \"Entwürfe\" (MESSAGES 2)\r\n".as_bytes();
+        assert_eq!(rewrite(&[line]), "* OK \"ü\"\r\n* STATUS {9}\r\nEntwürfe (MESSAGES 2)\r\n".as_bytes());
+        let line = "* 3 FETCH (ENVELOPE (NIL \"Grüße\"))\r\n".as_bytes();
+        assert_eq!(rewrite(&[line]), "* 3 FETCH (ENVELOPE (NIL {7}\r\nGrüße))\r\n".as_bytes());
+    }
+}
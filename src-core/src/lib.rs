@@ -5,10 +5,16 @@
 //! of depending on Tauri's AppHandle.
 
 pub mod maildir;
+pub mod header_store;
 pub mod mime;
 pub mod types;
 pub mod imap;
 pub mod graph;
 pub mod oauth2;
 pub mod dns;
+pub mod search;
+pub mod uid_index;
+pub mod message_index;
+pub mod threading;
 pub mod transfer_stats;
+pub mod quota;
@@ -0,0 +1,588 @@
+//! Attachment deduplication: an opt-in mode where large base64 attachment
+//! parts are moved out of vault-layout messages into a content-addressed
+//! store, `attachment_store/` in the vault, so a file attached to many
+//! messages — a forwarded PDF, the same deck in every folder — is kept once.
+//!
+//! A deduplicated message file holds a recipe: `MVD1`, then the message as a
+//! run of literal byte ranges and references to stored parts. A part is keyed
+//! by the SHA-256 of its decoded bytes, so the same file sent by two mail
+//! programs that wrap base64 differently is still stored once; the recipe
+//! notes the line length and line ending the message used. A part is only
+//! moved out when re-encoding it that way gives back exactly the bytes it
+//! replaced, so every read rebuilds the message byte for byte and its
+//! manifest hash still holds.
+//!
+//! The recipe is what gets compressed and sealed (see `compression`,
+//! `encryption`); stored parts are compressed and sealed the same way, on
+//! their own. Part names are the hashes, in the clear like file names are.
+//!
+//! Nothing counts references. `sweep` lists what every message refers to,
+//! deletes the parts nothing does, and records the totals `storage_stats`
+//! reports. A write touches the part it stores or reuses, and a sweep never
+//! deletes a part touched after it began.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::{info, warn};
+
+use super::compression::{self, Compression, CompressionConversion};
+use super::{encryption, integrity, standard};
+
+/// Present in the Maildir root while new messages are deduplicated.
+pub const DEDUP_FILE: &str = ".maildir_dedup";
+/// The part store, in the vault beside `Maildir/`.
+pub const STORE_DIR: &str = "attachment_store";
+/// What the last sweep found, in the store.
+const STATS_FILE: &str = ".stats.json";
+
+const MAGIC: [u8; 4] = *b"MVD1";
+/// Parts smaller than this (decoded) stay in the message.
+pub const MIN_PART: usize = 32 * 1024;
+
+const LITERAL: u8 = 0;
+const SHARED: u8 = 1;
+
+pub fn is_enabled(maildir_root: &Path) -> bool {
+    maildir_root.join(DEDUP_FILE).exists()
+}
+
+/// Whether a write to this mailbox deduplicates. Never for a standard one.
+pub fn dedup_of(mailbox_dir: &Path) -> bool {
+    !standard::is_standard(mailbox_dir)
+        && compression::vault_of(mailbox_dir).is_some_and(|vault| is_enabled(&vault.join("Maildir")))
+}
+
+/// Turn deduplication on or off and mark the existing files for conversion;
+/// `compression::convert_pending` does the work.
+pub fn set_enabled(data_dir: &Path, enabled: bool) -> Result<(), String> {
+    let root = data_dir.join("Maildir");
+    fs::create_dir_all(&root).map_err(|e| format!("Failed to create Maildir: {}", e))?;
+    let path = root.join(DEDUP_FILE);
+    let result = if enabled { fs::write(&path, "on") } else { fs::remove_file(&path).or(Ok(())) };
+    result.map_err(|e| format!("Failed to update {}: {}", DEDUP_FILE, e))?;
+    compression::request_conversion(data_dir, false)
+}
+
+fn store_dir(data_dir: &Path) -> PathBuf {
+    data_dir.join(STORE_DIR)
+}
+
+/// Whether the vault has ever deduplicated — recipes may exist.
+pub fn store_exists(data_dir: &Path) -> bool {
+    store_dir(data_dir).exists()
+}
+
+fn part_path(data_dir: &Path, hash: &str) -> PathBuf {
+    store_dir(data_dir).join(&hash[..2]).join(hash)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// ── Recipes ─────────────────────────────────────────────────────────────────
+
+/// How a stored part was wrapped where the message held it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Wrapping {
+    line_len: u32,
+    crlf: bool,
+    /// The part ended with a line ending.
+    trailing: bool,
+}
+
+impl Wrapping {
+    fn eol(self) -> &'static [u8] {
+        if self.crlf {
+            b"\r\n"
+        } else {
+            b"\n"
+        }
+    }
+
+    /// `decoded` base64-encoded and wrapped this way.
+    fn encode(self, decoded: &[u8]) -> Vec<u8> {
+        let encoded = base64::engine::general_purpose::STANDARD.encode(decoded);
+        let mut out = Vec::with_capacity(encoded.len() + encoded.len() / self.line_len.max(1) as usize * 2 + 2);
+        for (i, line) in encoded.as_bytes().chunks(self.line_len as usize).enumerate() {
+            if i > 0 {
+                out.extend_from_slice(self.eol());
+            }
+            out.extend_from_slice(line);
+        }
+        if self.trailing {
+            out.extend_from_slice(self.eol());
+        }
+        out
+    }
+
+    /// The decoded bytes of a base64 part and how it is wrapped — if wrapping
+    /// them that way again gives back exactly `segment`.
+    fn detect(segment: &[u8]) -> Option<(Vec<u8>, Wrapping)> {
+        let crlf = segment.windows(2).any(|w| w == b"\r\n");
+        let eol: &[u8] = if crlf { b"\r\n" } else { b"\n" };
+        let trailing = segment.ends_with(eol);
+        let line_len = segment.windows(eol.len()).position(|w| w == eol).unwrap_or(segment.len());
+        if line_len == 0 {
+            return None;
+        }
+        let compact: Vec<u8> = segment.iter().copied().filter(|b| !b.is_ascii_whitespace()).collect();
+        let decoded = base64::engine::general_purpose::STANDARD.decode(compact).ok()?;
+        let wrapping = Wrapping { line_len: u32::try_from(line_len).ok()?, crlf, trailing };
+        (wrapping.encode(&decoded) == segment).then_some((decoded, wrapping))
+    }
+}
+
+enum Segment {
+    Literal(Vec<u8>),
+    Shared { hash: [u8; 32], decoded_len: u64, wrapping: Wrapping, encoded_len: u64 },
+}
+
+fn encode_recipe(segments: &[Segment]) -> Vec<u8> {
+    let mut out = MAGIC.to_vec();
+    for segment in segments {
+        match segment {
+            Segment::Literal(bytes) => {
+                out.push(LITERAL);
+                out.extend_from_slice(&(bytes.len() as u64).to_le_bytes());
+                out.extend_from_slice(bytes);
+            }
+            Segment::Shared { hash, decoded_len, wrapping, encoded_len } => {
+                out.push(SHARED);
+                out.extend_from_slice(hash);
+                out.extend_from_slice(&decoded_len.to_le_bytes());
+                out.extend_from_slice(&wrapping.line_len.to_le_bytes());
+                out.push(wrapping.crlf as u8 | (wrapping.trailing as u8) << 1);
+                out.extend_from_slice(&encoded_len.to_le_bytes());
+            }
+        }
+    }
+    out
+}
+
+fn take<'a>(bytes: &mut &'a [u8], n: usize) -> Result<&'a [u8], String> {
+    if bytes.len() < n {
+        return Err("Deduplicated message is cut short".to_string());
+    }
+    let (head, rest) = bytes.split_at(n);
+    *bytes = rest;
+    Ok(head)
+}
+
+fn take_u64(bytes: &mut &[u8]) -> Result<u64, String> {
+    Ok(u64::from_le_bytes(take(bytes, 8)?.try_into().unwrap_or_default()))
+}
+
+fn decode_recipe(recipe: &[u8]) -> Result<Vec<Segment>, String> {
+    let mut rest = recipe.strip_prefix(&MAGIC).ok_or("Not a deduplicated message")?;
+    let mut segments = Vec::new();
+    while !rest.is_empty() {
+        match take(&mut rest, 1)?[0] {
+            LITERAL => {
+                let len = take_u64(&mut rest)? as usize;
+                segments.push(Segment::Literal(take(&mut rest, len)?.to_vec()));
+            }
+            SHARED => {
+                let hash: [u8; 32] = take(&mut rest, 32)?.try_into().unwrap_or_default();
+                let decoded_len = take_u64(&mut rest)?;
+                let line_len = u32::from_le_bytes(take(&mut rest, 4)?.try_into().unwrap_or_default());
+                let bits = take(&mut rest, 1)?[0];
+                let wrapping = Wrapping { line_len, crlf: bits & 1 != 0, trailing: bits & 2 != 0 };
+                let encoded_len = take_u64(&mut rest)?;
+                segments.push(Segment::Shared { hash, decoded_len, wrapping, encoded_len });
+            }
+            tag => return Err(format!("Unknown segment {} in deduplicated message", tag)),
+        }
+    }
+    Ok(segments)
+}
+
+/// `bytes` — opened and decompressed — are a recipe, not a message.
+pub fn is_recipe(bytes: &[u8]) -> bool {
+    bytes.starts_with(&MAGIC)
+}
+
+/// The parts a recipe refers to: (hash, bytes of the message each stands for).
+pub fn references(recipe: &[u8]) -> Result<Vec<(String, u64)>, String> {
+    Ok(decode_recipe(recipe)?
+        .into_iter()
+        .filter_map(|s| match s {
+            Segment::Shared { hash, encoded_len, .. } => Some((hex(&hash), encoded_len)),
+            Segment::Literal(_) => None,
+        })
+        .collect())
+}
+
+/// Byte ranges of the base64 leaf parts of `raw` large enough to move out.
+fn candidates(raw: &[u8]) -> Vec<(usize, usize)> {
+    fn walk(raw: &[u8], part: &mailparse::ParsedMail, out: &mut Vec<(usize, usize)>) {
+        if !part.subparts.is_empty() {
+            for sub in &part.subparts {
+                walk(raw, sub, out);
+            }
+            return;
+        }
+        if let mailparse::body::Body::Base64(body) = part.get_body_encoded() {
+            let segment = body.get_raw();
+            // Base64 is 4/3 the size of what it holds.
+            let start = (segment.as_ptr() as usize).checked_sub(raw.as_ptr() as usize);
+            if let Some(start) = start.filter(|s| s + segment.len() <= raw.len()) {
+                if segment.len() >= MIN_PART / 3 * 4 {
+                    out.push((start, segment.len()));
+                }
+            }
+        }
+    }
+    let mut out = Vec::new();
+    if let Ok(parsed) = mailparse::parse_mail(raw) {
+        walk(raw, &parsed, &mut out);
+    }
+    out.sort_unstable();
+    out
+}
+
+/// How a part is stored in a vault set to `compression`. A part that is
+/// itself gzip or zstd data — a `.gz` attachment — can't be stored as it is,
+/// or reading it would decompress it, so it's compressed once more.
+fn part_compression(compression: Compression, decoded: &[u8]) -> Compression {
+    match (compression, Compression::detect(decoded)) {
+        (Compression::None, Compression::None) => Compression::None,
+        (Compression::None, _) => Compression::Zstd,
+        (compression, _) => compression,
+    }
+}
+
+/// Put `decoded` in the store under `hash`, compressed and sealed as given,
+/// or mark the copy already there as in use.
+fn put_part(data_dir: &Path, hash: &str, decoded: &[u8], compression: Compression, sealer: &encryption::Sealer) -> Result<(), String> {
+    let path = part_path(data_dir, hash);
+    if path.exists() {
+        return File::options()
+            .append(true)
+            .open(&path)
+            .and_then(|f| f.set_modified(SystemTime::now()))
+            .map_err(|e| format!("Failed to touch {:?}: {}", path, e));
+    }
+    let compression = part_compression(compression, decoded);
+    let compressed = compression::compress(decoded, compression)?;
+    let bytes = sealer.seal(&compressed, compression)?;
+    let dir = path.parent().unwrap_or(&path);
+    integrity::write_atomic(dir, &path, &bytes)
+}
+
+/// `raw` as a recipe, with its large attachment parts put in the store —
+/// `None` when it has none worth moving out.
+pub fn split(data_dir: &Path, raw: &[u8], compression: Compression, sealer: &encryption::Sealer) -> Result<Option<Vec<u8>>, String> {
+    let mut segments = Vec::new();
+    let mut at = 0;
+    for (start, len) in candidates(raw) {
+        if start < at {
+            continue;
+        }
+        let Some((decoded, wrapping)) = Wrapping::detect(&raw[start..start + len]) else { continue };
+        if decoded.len() < MIN_PART {
+            continue;
+        }
+        let hash: [u8; 32] = Sha256::digest(&decoded).into();
+        put_part(data_dir, &hex(&hash), &decoded, compression, sealer)?;
+        if start > at {
+            segments.push(Segment::Literal(raw[at..start].to_vec()));
+        }
+        segments.push(Segment::Shared { hash, decoded_len: decoded.len() as u64, wrapping, encoded_len: len as u64 });
+        at = start + len;
+    }
+    if segments.is_empty() {
+        return Ok(None);
+    }
+    if at < raw.len() {
+        segments.push(Segment::Literal(raw[at..].to_vec()));
+    }
+    Ok(Some(encode_recipe(&segments)))
+}
+
+/// A stored part, opened and decompressed, checked against its hash.
+fn read_part(data_dir: &Path, hash: &str) -> Result<Vec<u8>, String> {
+    let path = part_path(data_dir, hash);
+    let bytes = fs::read(&path).map_err(|e| format!("Stored attachment {} is missing: {}", hash, e))?;
+    let opened = encryption::open(Some(data_dir), &bytes)?.into_owned();
+    let decoded = compression::decompress(opened)?;
+    if integrity::sha256_hex(&decoded) != hash {
+        return Err(format!("Stored attachment {} is damaged", hash));
+    }
+    Ok(decoded)
+}
+
+/// The message a recipe stands for, rebuilt from the store of the vault at
+/// `data_dir`.
+pub fn join(data_dir: Option<&Path>, recipe: &[u8]) -> Result<Vec<u8>, String> {
+    let data_dir = data_dir.ok_or("A deduplicated message outside its vault can't be read")?;
+    let mut out = Vec::new();
+    for segment in decode_recipe(recipe)? {
+        match segment {
+            Segment::Literal(bytes) => out.extend_from_slice(&bytes),
+            Segment::Shared { hash, decoded_len, wrapping, .. } => {
+                let decoded = read_part(data_dir, &hex(&hash))?;
+                if decoded.len() as u64 != decoded_len {
+                    return Err(format!("Stored attachment {} has the wrong length", hex(&hash)));
+                }
+                out.extend_from_slice(&wrapping.encode(&decoded));
+            }
+        }
+    }
+    Ok(out)
+}
+
+// ── Store upkeep ────────────────────────────────────────────────────────────
+
+fn part_files(data_dir: &Path) -> Vec<PathBuf> {
+    walkdir::WalkDir::new(store_dir(data_dir))
+        .min_depth(2)
+        .max_depth(2)
+        .into_iter()
+        .flatten()
+        .filter(|e| e.file_type().is_file() && e.file_name().len() == 64)
+        .map(|e| e.into_path())
+        .collect()
+}
+
+/// Re-store every part in `compression`, sealed by `sealer` — the store's
+/// half of a compression or encryption conversion.
+pub fn convert_store(data_dir: &Path, compression: Compression, sealer: &encryption::Sealer, report: &mut CompressionConversion) {
+    for path in part_files(data_dir) {
+        let sealed_under = encryption::sealed_header_of_file(&path).map(|h| h.0);
+        let stored_in = compression::compression_of_file(&path);
+        if stored_in == compression && sealed_under == sealer.key() {
+            continue;
+        }
+        let hash = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+        let result = read_part(data_dir, &hash).and_then(|decoded| {
+            let compression = part_compression(compression, &decoded);
+            if stored_in == compression && sealed_under == sealer.key() {
+                return Ok(false);
+            }
+            let compressed = compression::compress(&decoded, compression)?;
+            let bytes = sealer.seal(&compressed, compression)?;
+            integrity::write_atomic(path.parent().unwrap_or(data_dir), &path, &bytes).map(|()| true)
+        });
+        match result {
+            Ok(rewritten) => report.parts += rewritten as u64,
+            Err(e) => {
+                warn!("convert_compression: {}", e);
+                report.errors += 1;
+            }
+        }
+    }
+}
+
+/// What the last sweep found.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DedupStats {
+    /// Unix seconds the sweep began.
+    pub swept_at: u64,
+    pub parts: u64,
+    /// Size of the store on disk.
+    pub store_bytes: u64,
+    /// Bytes of messages that are references into the store instead.
+    pub referenced_bytes: u64,
+    /// `referenced_bytes` by account.
+    pub accounts: BTreeMap<String, u64>,
+    /// Parts nothing referred to, deleted.
+    pub removed: u64,
+    pub errors: u64,
+}
+
+impl DedupStats {
+    /// What deduplication saves: references, less the store they point into.
+    pub fn saved_bytes(&self) -> u64 {
+        self.referenced_bytes.saturating_sub(self.store_bytes)
+    }
+}
+
+/// The last sweep's totals, if the vault has a store.
+pub fn stats(data_dir: &Path) -> Option<DedupStats> {
+    let bytes = fs::read(store_dir(data_dir).join(STATS_FILE)).ok()?;
+    serde_json::from_slice(&bytes).ok()
+}
+
+/// List what every message refers to, delete the parts nothing does, and
+/// record the totals. A message that can't be read — damaged, or the vault
+/// locked — counts as an error, and nothing is deleted while there are any.
+pub fn sweep(data_dir: &Path) -> DedupStats {
+    let root = data_dir.join("Maildir");
+    let started = SystemTime::now();
+    let mut report = DedupStats {
+        swept_at: started.duration_since(std::time::UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+        ..Default::default()
+    };
+    if !store_exists(data_dir) {
+        return report;
+    }
+    let mut referenced = HashSet::new();
+    for mailbox_dir in super::mailbox_dirs(&root) {
+        if standard::is_standard(&mailbox_dir) {
+            continue;
+        }
+        let account_id = super::mailbox_key(&root, &mailbox_dir).map(|(a, _)| a).unwrap_or_default();
+        let cur_dir = mailbox_dir.join("cur");
+        for (uid, entry) in crate::uid_index::entries(&cur_dir) {
+            let stored = match compression::read_stored(&entry.path(&cur_dir)) {
+                Ok(stored) => stored,
+                Err(e) => {
+                    warn!("dedup_sweep: UID {} in {:?}: {}", uid, mailbox_dir, e);
+                    report.errors += 1;
+                    continue;
+                }
+            };
+            if !is_recipe(&stored) {
+                continue;
+            }
+            match references(&stored) {
+                Ok(refs) => {
+                    for (hash, bytes) in refs {
+                        report.referenced_bytes += bytes;
+                        *report.accounts.entry(account_id.clone()).or_default() += bytes;
+                        referenced.insert(hash);
+                    }
+                }
+                Err(e) => {
+                    warn!("dedup_sweep: UID {} in {:?}: {}", uid, mailbox_dir, e);
+                    report.errors += 1;
+                }
+            }
+        }
+    }
+
+    for path in part_files(data_dir) {
+        let hash = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+        let meta = fs::metadata(&path).ok();
+        let touched_since = meta.as_ref().and_then(|m| m.modified().ok()).is_some_and(|t| t >= started);
+        if report.errors == 0 && !referenced.contains(&hash) && !touched_since {
+            match fs::remove_file(&path) {
+                Ok(()) => {
+                    report.removed += 1;
+                    continue;
+                }
+                Err(e) => warn!("dedup_sweep: failed to remove {:?}: {}", path, e),
+            }
+        }
+        report.parts += 1;
+        report.store_bytes += meta.map(|m| m.len()).unwrap_or(0);
+    }
+
+    // Turned off and emptied: no recipes left to look for.
+    if report.errors == 0 && report.parts == 0 && !is_enabled(&root) {
+        if let Err(e) = fs::remove_dir_all(store_dir(data_dir)) {
+            warn!("dedup_sweep: failed to remove {}: {}", STORE_DIR, e);
+        }
+        return report;
+    }
+    match serde_json::to_vec(&report) {
+        Ok(json) => {
+            if let Err(e) = fs::write(store_dir(data_dir).join(STATS_FILE), json) {
+                warn!("dedup_sweep: failed to write {}: {}", STATS_FILE, e);
+            }
+        }
+        Err(e) => warn!("dedup_sweep: {}", e),
+    }
+    info!(
+        "dedup_sweep: {} parts ({} bytes) stand in for {} bytes of messages, {} unreferenced removed, {} errors",
+        report.parts, report.store_bytes, report.referenced_bytes, report.removed, report.errors,
+    );
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wrapped(decoded: &[u8], line_len: usize, eol: &str) -> Vec<u8> {
+        let b64 = base64::engine::general_purpose::STANDARD.encode(decoded);
+        let lines: Vec<&str> = b64.as_bytes().chunks(line_len).map(|c| std::str::from_utf8(c).unwrap()).collect();
+        format!("{}{}", lines.join(eol), eol).into_bytes()
+    }
+
+    fn with_part(decoded: &[u8]) -> Vec<u8> {
+        let mut raw = b"Subject: Deck\r\nContent-Type: multipart/mixed; boundary=\"b\"\r\n\r\n\
+            --b\r\nContent-Type: text/plain\r\n\r\nSee attached.\r\n\
+            --b\r\nContent-Type: application/pdf\r\nContent-Transfer-Encoding: base64\r\n\r\n"
+            .to_vec();
+        raw.extend_from_slice(&wrapped(decoded, 76, "\r\n"));
+        raw.extend_from_slice(b"--b--\r\n");
+        raw
+    }
+
+    #[test]
+    fn wrapping_is_only_detected_when_it_rebuilds_exactly() {
+        let decoded: Vec<u8> = (0..1000u32).map(|i| (i % 251) as u8).collect();
+        for (line_len, eol) in [(76, "\r\n"), (72, "\n")] {
+            let segment = wrapped(&decoded, line_len, eol);
+            let (back, wrapping) = Wrapping::detect(&segment).unwrap();
+            assert_eq!(back, decoded);
+            assert_eq!((wrapping.line_len as usize, wrapping.crlf, wrapping.trailing), (line_len, eol == "\r\n", true));
+            assert_eq!(wrapping.encode(&back), segment);
+        }
+        // A short line mid-part can't be rebuilt from a line length.
+        let mut ragged = wrapped(&decoded, 76, "\r\n");
+        ragged.splice(10..10, b"\r\n".iter().copied());
+        assert!(Wrapping::detect(&ragged).is_none());
+        assert!(Wrapping::detect(b"not base64 at all!\r\n").is_none());
+    }
+
+    #[test]
+    fn recipes_round_trip_and_reject_a_cut_short_one() {
+        let wrapping = Wrapping { line_len: 76, crlf: true, trailing: false };
+        let recipe = encode_recipe(&[
+            Segment::Literal(b"head".to_vec()),
+            Segment::Shared { hash: [7; 32], decoded_len: 3, wrapping, encoded_len: 4 },
+            Segment::Literal(b"tail".to_vec()),
+        ]);
+        assert!(is_recipe(&recipe));
+        assert_eq!(references(&recipe).unwrap(), vec![(hex(&[7; 32]), 4)]);
+        match &decode_recipe(&recipe).unwrap()[1] {
+            Segment::Shared { wrapping: back, decoded_len, .. } => assert_eq!((*back, *decoded_len), (wrapping, 3)),
+            Segment::Literal(_) => panic!("expected a shared part"),
+        }
+        assert!(decode_recipe(&recipe[..recipe.len() - 3]).is_err());
+        assert!(!is_recipe(b"Subject: plain\r\n\r\nbody"));
+    }
+
+    #[test]
+    fn split_and_join_rebuild_the_message_byte_for_byte() {
+        let dir = tempfile::tempdir().unwrap();
+        let sealer = encryption::Sealer::plain();
+        let deck: Vec<u8> = (0..MIN_PART as u32 * 2).map(|i| (i * 7 % 251) as u8).collect();
+        let raw = with_part(&deck);
+        let recipe = split(dir.path(), &raw, Compression::None, &sealer).unwrap().unwrap();
+        assert!(recipe.len() < raw.len() / 10);
+        assert_eq!(join(Some(dir.path()), &recipe).unwrap(), raw);
+        assert!(join(None, &recipe).is_err());
+        // Stored once, whoever refers to it.
+        assert_eq!(split(dir.path(), &raw, Compression::None, &sealer).unwrap().unwrap(), recipe);
+        assert_eq!(part_files(dir.path()).len(), 1);
+
+        // A part too small to share stays in the message.
+        assert!(split(dir.path(), &with_part(b"tiny"), Compression::None, &sealer).unwrap().is_none());
+
+        // A damaged part is reported, not passed off as the message.
+        let part = &part_files(dir.path())[0];
+        let mut bytes = fs::read(part).unwrap();
+        bytes[0] ^= 1;
+        fs::write(part, bytes).unwrap();
+        assert!(join(Some(dir.path()), &recipe).unwrap_err().contains("damaged"));
+    }
+
+    #[test]
+    fn a_compressed_attachment_is_compressed_again_to_store_it() {
+        let gz = compression::compress(b"already packed", Compression::Gzip).unwrap();
+        assert_eq!(part_compression(Compression::None, &gz), Compression::Zstd);
+        assert_eq!(part_compression(Compression::None, b"plain"), Compression::None);
+        assert_eq!(part_compression(Compression::Gzip, b"plain"), Compression::Gzip);
+    }
+}
@@ -0,0 +1,901 @@
+//! Encryption at rest: an opt-in mode where every message, and the sidecars
+//! that hold what messages say — header store records, the search index, the
+//! contacts index and snapshots — are sealed with XChaCha20-Poly1305 under a
+//! random vault key.
+//!
+//! The vault key never reaches the disk in the clear. `.vault_key.json` in the
+//! Maildir root holds it wrapped by a passphrase (Argon2id), and/or notes that
+//! it is kept in the OS keyring under `keyring_account(id)`. This crate has no
+//! keyring access: the daemon and the app each read the keyring themselves
+//! and unlock with what they find. Once a process has unlocked the vault,
+//! reads and writes go through the same paths as before.
+//!
+//! A sealed file is `MVX1`, the 4-byte id of its key, how the message inside
+//! is compressed, a 24-byte random nonce, then the ciphertext; the first nine
+//! bytes are authenticated along with it. As with compression, what a file
+//! holds is read from its bytes, so plain and sealed files sit side by side
+//! while a conversion runs.
+//!
+//! Rotating the key installs a new one and re-seals every file under it in
+//! the background — the compression converter does the work. The retired key
+//! stays in the key file sealed under the new one, so a backup copy made
+//! before still opens. Turning encryption off decrypts everything the same
+//! way, then removes the key file.
+//!
+//! Uid indexes, which hold Message-IDs and the server's ids, are sealed like
+//! the other sidecars. Manifests stay plain text so the app and the daemon
+//! can append to them, but the hash each line records is keyed (`digest`):
+//! a bare SHA-256 would let anyone holding the manifest confirm a guess at
+//! what a message says. File names and folder lists stay in the clear: they
+//! say which messages exist, not what they say. Standard-layout mailboxes are
+//! for other mail programs, which can't read a sealed file, so a vault has to
+//! be in the vault layout to be encrypted.
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::Engine;
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use tracing::{info, warn};
+
+use super::compression::{self, Compression};
+use super::{integrity, manifest, standard, Layout};
+
+/// The wrapped vault keys, in the Maildir root.
+pub const KEY_FILE: &str = ".vault_key.json";
+pub const LOCKED: &str = "The vault is locked";
+
+const MAGIC: [u8; 4] = *b"MVX1";
+const NONCE_LEN: usize = 24;
+/// Magic, key id and compression: authenticated, not encrypted.
+const AAD_LEN: usize = 9;
+const HEADER_LEN: usize = AAD_LEN + NONCE_LEN;
+/// Argon2id cost for the passphrase wrap — OWASP's recommended minimum.
+const ARGON2_M_KIB: u32 = 19 * 1024;
+const ARGON2_T: u32 = 2;
+const ARGON2_P: u32 = 1;
+
+pub type KeyId = [u8; 4];
+type Key = [u8; 32];
+/// Stores a secret in the OS keyring under an account: the caller's keyring.
+pub type KeyringStore<'a> = &'a dyn Fn(&str, &str) -> Result<(), String>;
+
+pub fn key_id_hex(id: KeyId) -> String {
+    id.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn parse_key_id(s: &str) -> Option<KeyId> {
+    let mut id = [0u8; 4];
+    if s.len() != 8 {
+        return None;
+    }
+    for (i, b) in id.iter_mut().enumerate() {
+        *b = u8::from_str_radix(s.get(i * 2..i * 2 + 2)?, 16).ok()?;
+    }
+    Some(id)
+}
+
+/// The OS keyring account a key is kept under, in the app's keyring service.
+pub fn keyring_account(id: &str) -> String {
+    format!("vault-key:{}", id)
+}
+
+fn b64(bytes: &[u8]) -> String {
+    base64::engine::general_purpose::STANDARD.encode(bytes)
+}
+
+fn unb64(s: &str) -> Result<Vec<u8>, String> {
+    base64::engine::general_purpose::STANDARD
+        .decode(s)
+        .map_err(|e| format!("Bad base64 in {}: {}", KEY_FILE, e))
+}
+
+// ── Sealing ─────────────────────────────────────────────────────────────────
+
+fn compression_tag(compression: Compression) -> u8 {
+    match compression {
+        Compression::None => 0,
+        Compression::Zstd => 1,
+        Compression::Gzip => 2,
+    }
+}
+
+fn seal_with(key: &Key, id: KeyId, compression: Compression, plain: &[u8]) -> Result<Vec<u8>, String> {
+    let nonce: [u8; NONCE_LEN] = rand::random();
+    let mut out = Vec::with_capacity(HEADER_LEN + plain.len() + 16);
+    out.extend_from_slice(&MAGIC);
+    out.extend_from_slice(&id);
+    out.push(compression_tag(compression));
+    out.extend_from_slice(&nonce);
+    let sealed = XChaCha20Poly1305::new(chacha20poly1305::Key::from_slice(key))
+        .encrypt(XNonce::from_slice(&nonce), Payload { msg: plain, aad: &out[..AAD_LEN] })
+        .map_err(|_| "Encryption failed".to_string())?;
+    out.extend_from_slice(&sealed);
+    Ok(out)
+}
+
+fn open_with(key: &Key, bytes: &[u8]) -> Result<Vec<u8>, String> {
+    if bytes.len() < HEADER_LEN {
+        return Err("Sealed data is cut short".to_string());
+    }
+    XChaCha20Poly1305::new(chacha20poly1305::Key::from_slice(key))
+        .decrypt(
+            XNonce::from_slice(&bytes[AAD_LEN..HEADER_LEN]),
+            Payload { msg: &bytes[HEADER_LEN..], aad: &bytes[..AAD_LEN] },
+        )
+        .map_err(|_| "Sealed data failed authentication — damaged, or sealed under another key".to_string())
+}
+
+/// The key `bytes` — the start of a file is enough — were sealed under, and
+/// how what's inside is compressed. `None` for plain bytes.
+pub fn sealed_header(bytes: &[u8]) -> Option<(KeyId, Compression)> {
+    if bytes.len() < AAD_LEN || !bytes.starts_with(&MAGIC) {
+        return None;
+    }
+    // A plain message may start with the magic too; its next bytes are
+    // text, never the control byte that names a compression.
+    let compression = match bytes[8] {
+        0 => Compression::None,
+        1 => Compression::Zstd,
+        2 => Compression::Gzip,
+        _ => return None,
+    };
+    Some((bytes[4..8].try_into().ok()?, compression))
+}
+
+/// `sealed_header` of the file at `path`.
+pub fn sealed_header_of_file(path: &Path) -> Option<(KeyId, Compression)> {
+    use std::io::Read;
+    let mut head = [0u8; AAD_LEN];
+    let n = fs::File::open(path).and_then(|mut f| f.read(&mut head)).unwrap_or(0);
+    sealed_header(&head[..n])
+}
+
+/// Seals for one vault as it is now: under its current key, or not at all.
+pub struct Sealer(Option<(KeyId, Key)>);
+
+impl Sealer {
+    /// Seals nothing: for files that must stay plain whatever the vault does.
+    pub fn plain() -> Sealer {
+        Sealer(None)
+    }
+
+    /// The key this seals under; `None` when the vault isn't encrypted.
+    pub fn key(&self) -> Option<KeyId> {
+        self.0.as_ref().map(|(id, _)| *id)
+    }
+
+    /// What the manifest and the uid index record for a message whose
+    /// SHA-256 (hex) is `sha256`: the hash itself, or under a key
+    /// `{key id}:{HMAC-SHA256 of the hash}`.
+    pub fn digest(&self, sha256: &str) -> String {
+        match &self.0 {
+            None => sha256.to_string(),
+            Some((id, key)) => format!("{}:{}", key_id_hex(*id), mac_hex(key, sha256)),
+        }
+    }
+
+    /// `recorded`, a `digest`, as this sealer would record it. One keyed
+    /// under another key can only be re-keyed from `indexed`, the uid index's
+    /// hash of the same message; `None` when that isn't the hash under it, and
+    /// the next verify records the message afresh.
+    fn rekeyed(&self, data_dir: &Path, recorded: &str, indexed: Option<&str>) -> Option<String> {
+        let Some((id, _)) = recorded.split_once(':') else { return Some(self.digest(recorded)) };
+        if self.key().map(key_id_hex).as_deref() == Some(id) {
+            return Some(recorded.to_string());
+        }
+        let sha256 = indexed.filter(|sha256| digest_matches(Some(data_dir), recorded, sha256) == Some(true))?;
+        Some(self.digest(sha256))
+    }
+
+    /// `bytes` as the vault stores them. `compression` says how they are
+    /// already compressed, for `sealed_header`.
+    pub fn seal<'a>(&self, bytes: &'a [u8], compression: Compression) -> Result<Cow<'a, [u8]>, String> {
+        match &self.0 {
+            None => Ok(Cow::Borrowed(bytes)),
+            Some((id, key)) => seal_with(key, *id, compression, bytes).map(Cow::Owned),
+        }
+    }
+}
+
+/// What writes to the vault at `data_dir` seal with. Errors while the vault
+/// is encrypted and this process hasn't unlocked it — never a silent plain
+/// write.
+pub fn sealer(data_dir: &Path) -> Result<Sealer, String> {
+    let Some(id) = read_key_file(data_dir)?.and_then(|f| f.current_id()) else { return Ok(Sealer(None)) };
+    let key = key_for(Some(data_dir), id).ok_or_else(|| LOCKED.to_string())?;
+    Ok(Sealer(Some((id, key))))
+}
+
+/// `Sealer::digest` for a message in the vault at `data_dir`, or outside any
+/// vault for `None`.
+pub fn digest(data_dir: Option<&Path>, sha256: &str) -> Result<String, String> {
+    match data_dir {
+        Some(data_dir) => Ok(sealer(data_dir)?.digest(sha256)),
+        None => Ok(sha256.to_string()),
+    }
+}
+
+/// Whether `recorded`, a `digest`, is of a message whose SHA-256 is `sha256`.
+/// `None` when it is keyed under a key this process can't get.
+pub fn digest_matches(data_dir: Option<&Path>, recorded: &str, sha256: &str) -> Option<bool> {
+    let Some((id, mac)) = recorded.split_once(':') else { return Some(recorded == sha256) };
+    let key = key_for(data_dir, parse_key_id(id)?)?;
+    Some(mac_hex(&key, sha256) == mac)
+}
+
+fn hmac_sha256(key: &[u8], msg: &[u8]) -> [u8; 32] {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key).expect("HMAC takes a key of any length");
+    mac.update(msg);
+    mac.finalize().into_bytes().into()
+}
+
+fn mac_hex(key: &Key, sha256: &str) -> String {
+    // A key of its own, so the cipher's key never signs anything directly.
+    let digest_key = hmac_sha256(key, b"MVX1 message digest");
+    hmac_sha256(&digest_key, sha256.as_bytes()).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// `bytes` opened, if they are sealed. `data_dir` is the vault they came
+/// from, for a key another process installed since this one unlocked; a
+/// backup copy outside any vault passes `None`.
+pub fn open<'a>(data_dir: Option<&Path>, bytes: &'a [u8]) -> Result<Cow<'a, [u8]>, String> {
+    let Some((id, _)) = sealed_header(bytes) else { return Ok(Cow::Borrowed(bytes)) };
+    let key = key_for(data_dir, id).ok_or_else(|| LOCKED.to_string())?;
+    open_with(&key, bytes).map(Cow::Owned)
+}
+
+/// Read a sidecar file, opening it if it is sealed.
+pub fn read_sidecar(data_dir: &Path, path: &Path) -> Result<Vec<u8>, String> {
+    let bytes = fs::read(path).map_err(|e| format!("Failed to read {:?}: {}", path, e))?;
+    match open(Some(data_dir), &bytes)? {
+        Cow::Borrowed(_) => Ok(bytes),
+        Cow::Owned(plain) => Ok(plain),
+    }
+}
+
+/// Write a sidecar file, sealed if the vault is encrypted, through a
+/// temporary file beside it.
+pub fn write_sidecar(data_dir: &Path, path: &Path, bytes: &[u8]) -> Result<(), String> {
+    let sealed = sealer(data_dir)?.seal(bytes, Compression::None)?.into_owned();
+    let tmp = PathBuf::from(format!("{}.tmp", path.display()));
+    fs::write(&tmp, sealed)
+        .and_then(|()| fs::rename(&tmp, path))
+        .map_err(|e| format!("Failed to write {:?}: {}", path, e))
+}
+
+// ── Keys ────────────────────────────────────────────────────────────────────
+
+/// Keys this process holds, by id, across every vault it has unlocked.
+fn unlocked() -> &'static Mutex<HashMap<KeyId, Key>> {
+    static KEYS: OnceLock<Mutex<HashMap<KeyId, Key>>> = OnceLock::new();
+    KEYS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+fn known(id: KeyId) -> Option<Key> {
+    unlocked().lock().ok()?.get(&id).copied()
+}
+
+fn remember(id: KeyId, key: Key) {
+    if let Ok(mut keys) = unlocked().lock() {
+        keys.insert(id, key);
+    }
+}
+
+/// The key `id`, following the vault's key file from the keys already held
+/// when this process hasn't seen it yet — one another process rotated in.
+fn key_for(data_dir: Option<&Path>, id: KeyId) -> Option<Key> {
+    if let Some(key) = known(id) {
+        return Some(key);
+    }
+    let file = read_key_file(data_dir?).ok()??;
+    file.expand();
+    known(id)
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct KeyFile {
+    /// What new writes are sealed under; `None` while encryption is being
+    /// turned off.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    current: Option<String>,
+    #[serde(default)]
+    keys: BTreeMap<String, WrappedKey>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct WrappedKey {
+    /// Unix seconds.
+    created_at: i64,
+    /// Nothing, sealed under this key: tells the right key from a wrong one.
+    check: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    passphrase: Option<PassphraseWrap>,
+    /// Kept in the OS keyring under `keyring_account(id)`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    keyring: bool,
+    /// Sealed under another key in the file: a retired key under the one that
+    /// replaced it, and a new key under the one it replaces until re-sealing
+    /// finishes, so a process that unlocked before the rotation follows it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sealed_by: Option<SealedBy>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PassphraseWrap {
+    salt: String,
+    m_cost: u32,
+    t_cost: u32,
+    p_cost: u32,
+    sealed: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SealedBy {
+    key: String,
+    sealed: String,
+}
+
+fn key_file_path(data_dir: &Path) -> PathBuf {
+    data_dir.join("Maildir").join(KEY_FILE)
+}
+
+fn read_key_file(data_dir: &Path) -> Result<Option<KeyFile>, String> {
+    let path = key_file_path(data_dir);
+    match fs::read(&path) {
+        Ok(bytes) => serde_json::from_slice(&bytes)
+            .map(Some)
+            .map_err(|e| format!("Unreadable {}: {}", KEY_FILE, e)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(format!("Failed to read {}: {}", KEY_FILE, e)),
+    }
+}
+
+fn write_key_file(data_dir: &Path, file: &KeyFile) -> Result<(), String> {
+    let root = data_dir.join("Maildir");
+    let json = serde_json::to_vec_pretty(file).map_err(|e| format!("Failed to serialize {}: {}", KEY_FILE, e))?;
+    integrity::write_atomic(&root, &root.join(KEY_FILE), &json)
+}
+
+fn derive(passphrase: &str, salt: &[u8], m_cost: u32, t_cost: u32, p_cost: u32) -> Result<Key, String> {
+    let params = Params::new(m_cost, t_cost, p_cost, Some(32)).map_err(|e| format!("Bad key parameters: {}", e))?;
+    let mut kek = [0u8; 32];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), salt, &mut kek)
+        .map_err(|e| format!("Failed to derive key from passphrase: {}", e))?;
+    Ok(kek)
+}
+
+fn wrap_with_passphrase(key: &Key, passphrase: &str) -> Result<PassphraseWrap, String> {
+    let salt: [u8; 16] = rand::random();
+    let kek = derive(passphrase, &salt, ARGON2_M_KIB, ARGON2_T, ARGON2_P)?;
+    Ok(PassphraseWrap {
+        salt: b64(&salt),
+        m_cost: ARGON2_M_KIB,
+        t_cost: ARGON2_T,
+        p_cost: ARGON2_P,
+        sealed: b64(&seal_with(&kek, [0; 4], Compression::None, key)?),
+    })
+}
+
+fn to_key(bytes: Vec<u8>) -> Result<Key, String> {
+    bytes.try_into().map_err(|_| format!("A key in {} has the wrong length", KEY_FILE))
+}
+
+impl KeyFile {
+    fn current_id(&self) -> Option<KeyId> {
+        self.current.as_deref().and_then(parse_key_id)
+    }
+
+    /// Open every key sealed under one already held, until no more open.
+    fn expand(&self) {
+        loop {
+            let mut opened = false;
+            for (id, wrapped) in &self.keys {
+                let Some(id) = parse_key_id(id) else { continue };
+                let Some(by) = wrapped.sealed_by.as_ref().filter(|_| known(id).is_none()) else { continue };
+                let Some(outer) = parse_key_id(&by.key).and_then(known) else { continue };
+                if let Some(key) = unb64(&by.sealed).ok().and_then(|s| open_with(&outer, &s).ok()) {
+                    if let Ok(key) = to_key(key) {
+                        remember(id, key);
+                        opened = true;
+                    }
+                }
+            }
+            if !opened {
+                return;
+            }
+        }
+    }
+
+    /// A new key, wrapped for the given ways in and stored in the keyring
+    /// first when that is one of them, then installed as current. Every other
+    /// key this process holds is retired under it. Returns the new key's id
+    /// and the ids taken out of the keyring.
+    fn install(
+        &mut self,
+        passphrase: Option<&str>,
+        keyring: Option<KeyringStore>,
+    ) -> Result<(KeyId, Key, Vec<String>), String> {
+        let key: Key = rand::random();
+        let id: KeyId = rand::random();
+        let hex = key_id_hex(id);
+        let mut wrapped = WrappedKey {
+            created_at: chrono::Utc::now().timestamp(),
+            check: b64(&seal_with(&key, id, Compression::None, b"")?),
+            passphrase: passphrase.map(|p| wrap_with_passphrase(&key, p)).transpose()?,
+            keyring: keyring.is_some(),
+            sealed_by: None,
+        };
+        if let Some((old_id, old_key)) = self.current_id().and_then(|old| Some((old, known(old)?))) {
+            wrapped.sealed_by = Some(SealedBy {
+                key: key_id_hex(old_id),
+                sealed: b64(&seal_with(&old_key, old_id, Compression::None, &key)?),
+            });
+        }
+        if let Some(store) = keyring {
+            store(&keyring_account(&hex), &b64(&key))?;
+        }
+
+        let mut out_of_keyring = Vec::new();
+        for (other, w) in self.keys.iter_mut() {
+            let Some(other_key) = parse_key_id(other).and_then(known) else { continue };
+            if w.keyring {
+                out_of_keyring.push(other.clone());
+            }
+            w.passphrase = None;
+            w.keyring = false;
+            w.sealed_by = Some(SealedBy { key: hex.clone(), sealed: b64(&seal_with(&key, id, Compression::None, &other_key)?) });
+        }
+        self.keys.insert(hex.clone(), wrapped);
+        self.current = Some(hex);
+        Ok((id, key, out_of_keyring))
+    }
+}
+
+/// Whether new writes to the vault are sealed.
+pub fn is_encrypted(data_dir: &Path) -> bool {
+    read_key_file(data_dir).ok().flatten().and_then(|f| f.current_id()).is_some()
+}
+
+/// Whether this process holds every key the vault's files may be sealed
+/// under. True for a vault that has never been encrypted.
+pub fn is_unlocked(data_dir: &Path) -> bool {
+    let Ok(Some(file)) = read_key_file(data_dir) else { return true };
+    file.expand();
+    file.keys.keys().all(|id| parse_key_id(id).and_then(known).is_some())
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EncryptionStatus {
+    pub encrypted: bool,
+    pub unlocked: bool,
+    /// The current key's id.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key_id: Option<String>,
+    /// Unix seconds the current key was made.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key_created_at: Option<i64>,
+    pub passphrase: bool,
+    pub keyring: bool,
+    /// Keys in the file, current and retired.
+    pub keys: usize,
+}
+
+pub fn status(data_dir: &Path) -> Result<EncryptionStatus, String> {
+    let Some(file) = read_key_file(data_dir)? else {
+        return Ok(EncryptionStatus { unlocked: true, ..Default::default() });
+    };
+    let current = file.current.as_ref().and_then(|id| file.keys.get(id));
+    Ok(EncryptionStatus {
+        encrypted: file.current_id().is_some(),
+        unlocked: is_unlocked(data_dir),
+        key_id: file.current.clone(),
+        key_created_at: current.map(|k| k.created_at),
+        passphrase: current.is_some_and(|k| k.passphrase.is_some()),
+        keyring: current.is_some_and(|k| k.keyring),
+        keys: file.keys.len(),
+    })
+}
+
+/// Ids of the keys the key file says are in the OS keyring — what to look up
+/// to unlock without a passphrase.
+pub fn keyring_keys(data_dir: &Path) -> Vec<String> {
+    let Ok(Some(file)) = read_key_file(data_dir) else { return Vec::new() };
+    file.keys.iter().filter(|(_, w)| w.keyring).map(|(id, _)| id.clone()).collect()
+}
+
+/// Unlock with the passphrase.
+pub fn unlock_with_passphrase(data_dir: &Path, passphrase: &str) -> Result<(), String> {
+    let file = read_key_file(data_dir)?.ok_or("The vault isn't encrypted")?;
+    let mut wrapped: Vec<(&String, &PassphraseWrap)> =
+        file.keys.iter().filter_map(|(id, w)| Some((id, w.passphrase.as_ref()?))).collect();
+    if wrapped.is_empty() {
+        return Err("The vault key isn't protected by a passphrase".to_string());
+    }
+    // The current key first; one passphrase normally opens it and the rest follow.
+    wrapped.sort_by_key(|(id, _)| file.current.as_ref() != Some(*id));
+    for (id, w) in wrapped {
+        let Some(key_id) = parse_key_id(id) else { continue };
+        let kek = derive(passphrase, &unb64(&w.salt)?, w.m_cost, w.t_cost, w.p_cost)?;
+        if let Ok(key) = open_with(&kek, &unb64(&w.sealed)?) {
+            remember(key_id, to_key(key)?);
+            file.expand();
+            info!("encryption: vault unlocked with passphrase (key {})", id);
+            return Ok(());
+        }
+    }
+    Err("Wrong passphrase".to_string())
+}
+
+/// Unlock with a key read from the OS keyring under `keyring_account(id)`.
+pub fn unlock_with_secret(data_dir: &Path, id: &str, secret: &str) -> Result<(), String> {
+    let file = read_key_file(data_dir)?.ok_or("The vault isn't encrypted")?;
+    let wrapped = file.keys.get(id).ok_or_else(|| format!("No key {} in {}", id, KEY_FILE))?;
+    let key_id = parse_key_id(id).ok_or_else(|| format!("Bad key id {}", id))?;
+    let key = to_key(unb64(secret.trim())?)?;
+    open_with(&key, &unb64(&wrapped.check)?).map_err(|_| format!("The keyring holds the wrong key for {}", id))?;
+    remember(key_id, key);
+    file.expand();
+    info!("encryption: vault unlocked from the keyring (key {})", id);
+    Ok(())
+}
+
+/// Forget the vault's keys in this process.
+pub fn lock(data_dir: &Path) {
+    let Ok(Some(file)) = read_key_file(data_dir) else { return };
+    if let Ok(mut keys) = unlocked().lock() {
+        for id in file.keys.keys().filter_map(|id| parse_key_id(id)) {
+            keys.remove(&id);
+        }
+    }
+}
+
+/// A key just installed. `out_of_keyring` are retired keys whose keyring
+/// entries the caller should delete; they now open through the new key.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewKey {
+    pub key_id: String,
+    pub out_of_keyring: Vec<String>,
+}
+
+/// A vault-layout vault: nothing standard, and no standard default.
+fn check_layout(data_dir: &Path) -> Result<(), String> {
+    let root = data_dir.join("Maildir");
+    if standard::default_layout(&root) == Layout::Standard || super::mailbox_dirs(&root).iter().any(|d| standard::is_standard(d)) {
+        return Err(
+            "Other mail programs can't read encrypted messages — switch the vault to the vault layout first".to_string(),
+        );
+    }
+    Ok(())
+}
+
+fn check_unlocked(data_dir: &Path) -> Result<(), String> {
+    if is_unlocked(data_dir) {
+        Ok(())
+    } else {
+        Err(LOCKED.to_string())
+    }
+}
+
+/// Turn encryption on: make a vault key, wrap it with `passphrase` and/or
+/// hand it to `keyring` (given the keyring account and the secret; it must
+/// store it before the key goes into use), and mark every file for sealing.
+pub fn enable(
+    data_dir: &Path,
+    passphrase: Option<&str>,
+    keyring: Option<KeyringStore>,
+) -> Result<NewKey, String> {
+    if passphrase.is_none() && keyring.is_none() {
+        return Err("Give a passphrase or keep the key in the keyring — otherwise it can't be unlocked".to_string());
+    }
+    if is_encrypted(data_dir) {
+        return Err("The vault is already encrypted; rotate the key to change it".to_string());
+    }
+    check_layout(data_dir)?;
+    check_unlocked(data_dir)?;
+    let mut file = read_key_file(data_dir)?.unwrap_or_default();
+    let (id, key, out_of_keyring) = file.install(passphrase, keyring)?;
+    write_key_file(data_dir, &file)?;
+    remember(id, key);
+    compression::request_conversion(data_dir, true)?;
+    info!("encryption: enabled with key {}", key_id_hex(id));
+    Ok(NewKey { key_id: key_id_hex(id), out_of_keyring })
+}
+
+/// Replace the vault key and mark every file for re-sealing under the new
+/// one. The new key is wrapped with `passphrase` — required when the current
+/// key has one, and may differ from it — and kept in the keyring when the
+/// current one is.
+pub fn rotate(
+    data_dir: &Path,
+    passphrase: Option<&str>,
+    keyring: Option<KeyringStore>,
+) -> Result<NewKey, String> {
+    let mut file = read_key_file(data_dir)?.ok_or("The vault isn't encrypted")?;
+    let current = file.current.clone().and_then(|id| file.keys.get(&id).cloned()).ok_or("The vault isn't encrypted")?;
+    check_unlocked(data_dir)?;
+    if current.passphrase.is_some() && passphrase.is_none() {
+        return Err("Enter a passphrase for the new key".to_string());
+    }
+    let keyring = keyring.filter(|_| current.keyring);
+    if passphrase.is_none() && keyring.is_none() {
+        return Err("Give a passphrase for the new key".to_string());
+    }
+    let (id, key, out_of_keyring) = file.install(passphrase, keyring)?;
+    write_key_file(data_dir, &file)?;
+    remember(id, key);
+    compression::request_conversion(data_dir, true)?;
+    info!("encryption: rotated to key {}", key_id_hex(id));
+    Ok(NewKey { key_id: key_id_hex(id), out_of_keyring })
+}
+
+/// Turn encryption off and mark every file for decrypting. The key file goes
+/// once that finishes (`conversion_done`).
+pub fn disable(data_dir: &Path) -> Result<(), String> {
+    let mut file = read_key_file(data_dir)?.ok_or("The vault isn't encrypted")?;
+    if file.current.is_none() {
+        return Err("The vault isn't encrypted".to_string());
+    }
+    check_unlocked(data_dir)?;
+    file.current = None;
+    write_key_file(data_dir, &file)?;
+    compression::request_conversion(data_dir, true)?;
+    info!("encryption: disabled; decrypting the vault");
+    Ok(())
+}
+
+/// Called once every file is in the vault's current state. A rotation's new
+/// key stops being reachable from the old one; a vault no longer encrypted
+/// loses its key file. Returns the keyring entries to delete.
+pub fn conversion_done(data_dir: &Path) -> Result<Vec<String>, String> {
+    let Some(mut file) = read_key_file(data_dir)? else { return Ok(Vec::new()) };
+    match file.current.clone() {
+        None => {
+            fs::remove_file(key_file_path(data_dir)).map_err(|e| format!("Failed to remove {}: {}", KEY_FILE, e))?;
+            info!("encryption: vault decrypted; key file removed");
+            Ok(file.keys.iter().filter(|(_, w)| w.keyring).map(|(id, _)| id.clone()).collect())
+        }
+        Some(current) => {
+            if let Some(w) = file.keys.get_mut(&current).filter(|w| w.sealed_by.is_some()) {
+                w.sealed_by = None;
+                write_key_file(data_dir, &file)?;
+            }
+            Ok(Vec::new())
+        }
+    }
+}
+
+// ── Sidecars ────────────────────────────────────────────────────────────────
+
+/// Whole-file sidecars that hold what messages say, relative to the vault:
+/// `(dir, depths, suffix)` — files ending in `suffix`, within `depths` levels
+/// below. The app's `local-index.json` sits under the raw mailbox name, which
+/// nests as deep as the server's hierarchy does.
+const SIDECARS: [(&str, RangeInclusive<usize>, &str); 6] = [
+    ("search_index", 1..=1, ".json"),
+    ("contacts_index", 1..=1, ".json"),
+    ("thread_index", 1..=1, ".json"),
+    ("snapshots", 2..=2, ".json.gz"),
+    ("Maildir", 1..=1, crate::message_index::INDEX_FILE),
+    ("maildir", 3..=usize::MAX, "local-index.json"),
+];
+
+fn sidecar_files(data_dir: &Path) -> Vec<PathBuf> {
+    let mut out = Vec::new();
+    for (dir, depths, suffix) in SIDECARS {
+        let walk = walkdir::WalkDir::new(data_dir.join(dir)).min_depth(*depths.start()).max_depth(*depths.end());
+        for entry in walk.into_iter().flatten() {
+            if entry.file_type().is_file() && entry.file_name().to_string_lossy().ends_with(suffix) {
+                out.push(entry.into_path());
+            }
+        }
+    }
+    out
+}
+
+/// Bring every sidecar into the vault's current state: header store logs are
+/// rewritten, whole-file sidecars re-sealed, and each mailbox's uid index
+/// re-sealed and its manifest re-keyed. Returns (files rewritten, errors).
+pub fn reseal_sidecars(data_dir: &Path) -> (u64, u64) {
+    let sealer = match sealer(data_dir) {
+        Ok(s) => s,
+        Err(e) => {
+            warn!("reseal_sidecars: {}", e);
+            return (0, 1);
+        }
+    };
+    let (mut resealed, mut errors) = (0, 0);
+    if let Ok(dirs) = fs::read_dir(data_dir.join("email_cache")) {
+        for dir in dirs.flatten().map(|d| d.path()).filter(|d| crate::header_store::exists(d)) {
+            match crate::header_store::HeaderStore::open(&dir).and_then(|mut store| store.reseal()) {
+                Ok(()) => resealed += 1,
+                Err(e) => {
+                    warn!("reseal_sidecars: {:?}: {}", dir, e);
+                    errors += 1;
+                }
+            }
+        }
+    }
+    for mailbox_dir in super::mailbox_dirs(&data_dir.join("Maildir")) {
+        match reseal_mailbox(data_dir, &sealer, &mailbox_dir) {
+            Ok(n) => resealed += n,
+            Err(e) => {
+                warn!("reseal_sidecars: {:?}: {}", mailbox_dir, e);
+                errors += 1;
+            }
+        }
+    }
+    for path in sidecar_files(data_dir) {
+        let Ok(bytes) = fs::read(&path) else { continue };
+        if sealed_header(&bytes).map(|h| h.0) == sealer.key() {
+            continue;
+        }
+        let result = open(Some(data_dir), &bytes)
+            .and_then(|plain| Ok(sealer.seal(&plain, Compression::None)?.into_owned()))
+            .and_then(|out| integrity::write_atomic(path.parent().unwrap_or(data_dir), &path, &out));
+        match result {
+            Ok(()) => resealed += 1,
+            Err(e) => {
+                warn!("reseal_sidecars: {:?}: {}", path, e);
+                errors += 1;
+            }
+        }
+    }
+    (resealed, errors)
+}
+
+/// A mailbox's uid index and manifest as `sealer` would write them. Returns
+/// how many of the two were rewritten.
+fn reseal_mailbox(data_dir: &Path, sealer: &Sealer, mailbox_dir: &Path) -> Result<u64, String> {
+    let cur_dir = mailbox_dir.join("cur");
+    let mut rewritten = 0;
+    let indexed: HashMap<u32, String> = crate::uid_index::entries(&cur_dir)
+        .into_iter()
+        .filter_map(|(uid, e)| Some((uid, e.sha256?)))
+        .collect();
+    if crate::uid_index::reseal(&cur_dir, sealer.key())? {
+        rewritten += 1;
+    }
+    let recorded = manifest::read(mailbox_dir);
+    let rekeyed: BTreeMap<u32, manifest::Recorded> = recorded
+        .iter()
+        .filter_map(|(uid, r)| {
+            let sha256 = sealer.rekeyed(data_dir, &r.sha256, indexed.get(uid).map(String::as_str))?;
+            Some((*uid, manifest::Recorded { size: r.size, sha256 }))
+        })
+        .collect();
+    if rekeyed != recorded {
+        manifest::write(mailbox_dir, &rekeyed)?;
+        rewritten += 1;
+    }
+    Ok(rewritten)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An empty vault-layout vault.
+    fn vault() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("Maildir")).unwrap();
+        dir
+    }
+
+    #[test]
+    fn tampered_data_or_header_fails_to_open() {
+        let key: Key = rand::random();
+        let id: KeyId = rand::random();
+        let sealed = seal_with(&key, id, Compression::None, b"Subject: hi\r\n\r\nbody\r\n").unwrap();
+        assert_eq!(open_with(&key, &sealed).unwrap(), b"Subject: hi\r\n\r\nbody\r\n");
+
+        let mut body = sealed.clone();
+        *body.last_mut().unwrap() ^= 1;
+        assert!(open_with(&key, &body).is_err());
+        // The key id and compression byte are bound in as associated data.
+        for at in [4, 8] {
+            let mut header = sealed.clone();
+            header[at] ^= 1;
+            assert!(open_with(&key, &header).is_err(), "byte {}", at);
+        }
+        let other: Key = rand::random();
+        assert!(open_with(&other, &sealed).is_err());
+        assert!(open_with(&key, &sealed[..HEADER_LEN - 1]).is_err());
+    }
+
+    #[test]
+    fn wrong_passphrase_leaves_the_vault_locked() {
+        let dir = vault();
+        enable(dir.path(), Some("correct horse"), None).unwrap();
+        lock(dir.path());
+        assert_eq!(unlock_with_passphrase(dir.path(), "Correct horse").unwrap_err(), "Wrong passphrase");
+        assert!(!is_unlocked(dir.path()));
+        assert_eq!(sealer(dir.path()).err().as_deref(), Some(LOCKED));
+        unlock_with_passphrase(dir.path(), "correct horse").unwrap();
+        assert!(is_unlocked(dir.path()));
+    }
+
+    #[test]
+    fn a_rotated_out_key_opens_through_the_new_one() {
+        let dir = vault();
+        enable(dir.path(), Some("first"), None).unwrap();
+        let sealed = sealer(dir.path()).unwrap().seal(b"under the first key", Compression::None).unwrap().into_owned();
+        let first = sealed_header(&sealed).unwrap().0;
+        rotate(dir.path(), Some("second"), None).unwrap();
+        assert_ne!(sealer(dir.path()).unwrap().key(), Some(first));
+
+        lock(dir.path());
+        assert_eq!(open(Some(dir.path()), &sealed).unwrap_err(), LOCKED);
+        // The old key's passphrase went with it; the new one opens both.
+        assert_eq!(unlock_with_passphrase(dir.path(), "first").unwrap_err(), "Wrong passphrase");
+        unlock_with_passphrase(dir.path(), "second").unwrap();
+        assert_eq!(&*open(Some(dir.path()), &sealed).unwrap(), b"under the first key");
+    }
+
+    #[test]
+    fn a_plain_message_starting_with_the_magic_is_not_sealed() {
+        let raw = b"MVX1-Trace: relay.host.test\r\nSubject: hi\r\n\r\nbody\r\n";
+        assert!(sealed_header(raw).is_none());
+        assert_eq!(&*open(None, raw).unwrap(), raw);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("1:seen:1700000000.eml");
+        fs::write(&path, raw).unwrap();
+        assert!(sealed_header_of_file(&path).is_none());
+        assert_eq!(compression::read_message(&path).unwrap(), raw);
+    }
+
+    #[test]
+    fn digests_are_keyed_once_the_vault_is_encrypted() {
+        let dir = vault();
+        let sha256 = integrity::sha256_hex(b"the launch code is 0000");
+        assert_eq!(digest(Some(dir.path()), &sha256).unwrap(), sha256);
+
+        enable(dir.path(), Some("correct horse"), None).unwrap();
+        let keyed = digest(Some(dir.path()), &sha256).unwrap();
+        assert!(!keyed.contains(&sha256));
+        assert_eq!(digest_matches(Some(dir.path()), &keyed, &sha256), Some(true));
+        assert_eq!(digest_matches(Some(dir.path()), &keyed, &integrity::sha256_hex(b"other")), Some(false));
+        let current = sealer(dir.path()).unwrap();
+        assert_eq!(current.rekeyed(dir.path(), &sha256, None), Some(keyed.clone()));
+
+        // Re-keying a digest under an old key needs the hash it was taken of.
+        rotate(dir.path(), Some("battery staple"), None).unwrap();
+        let rotated = sealer(dir.path()).unwrap();
+        assert_eq!(rotated.rekeyed(dir.path(), &keyed, None), None);
+        assert_eq!(rotated.rekeyed(dir.path(), &keyed, Some(&sha256)), Some(rotated.digest(&sha256)));
+        assert_eq!(Sealer::plain().rekeyed(dir.path(), &keyed, Some(&sha256)), Some(sha256.clone()));
+
+        lock(dir.path());
+        assert_eq!(digest_matches(Some(dir.path()), &keyed, &sha256), None);
+    }
+
+    #[test]
+    fn resealing_covers_local_indexes_under_nested_mailboxes() {
+        let dir = vault();
+        let index = dir.path().join("maildir/acct/[Gmail]/Sent Mail/local-index.json");
+        fs::create_dir_all(index.parent().unwrap()).unwrap();
+        fs::write(&index, br#"[{"uid":7,"subject":"the launch code"}]"#).unwrap();
+
+        enable(dir.path(), Some("correct horse"), None).unwrap();
+        let (_, errors) = reseal_sidecars(dir.path());
+        assert_eq!(errors, 0);
+        assert!(sealed_header_of_file(&index).is_some());
+        assert_eq!(read_sidecar(dir.path(), &index).unwrap(), br#"[{"uid":7,"subject":"the launch code"}]"#);
+    }
+}
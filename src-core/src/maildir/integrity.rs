@@ -0,0 +1,356 @@
+//! Crash safety: how a message reaches the disk, and what startup does about
+//! one that didn't make it whole.
+//!
+//! A message file is written in the mailbox's `tmp/`, fsynced, renamed to its
+//! final name and the directory fsynced after it, so a power cut leaves either
+//! the whole message or none of it. The size on disk is checked after the
+//! rename, and the uid index records it with a SHA-256 of the message.
+//!
+//! Files written before this — by `fs::write` in place — can still be cut
+//! short. `quarantine_scan` runs at daemon startup and moves any message that
+//! is empty, no longer the size it was recorded at, or visibly truncated into
+//! `{mailbox}/quarantine/`. It then stops counting as archived, and the next
+//! sync fetches it again.
+
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{info, warn};
+
+use super::{compression, dedup, encryption, standard};
+
+pub const QUARANTINE_DIR: &str = "quarantine";
+/// Start time (unix seconds) of the last scan that finished, in the Maildir root.
+const SCAN_FILE: &str = ".integrity_scan";
+/// A `tmp/` file this old belongs to a write that will never finish — the
+/// Maildir spec's own cutoff.
+const STALE_TMP: Duration = Duration::from_secs(36 * 60 * 60);
+/// How far past its boundary the tail read looks for a multipart's closing
+/// delimiter; an epilogue longer than this reads as truncated.
+const TAIL: u64 = 64 * 1024;
+
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    Sha256::digest(bytes).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Size and SHA-256 (hex) of the file at `path`, read in chunks.
+pub fn hash_file(path: &Path) -> Result<(u64, String), String> {
+    let mut file = File::open(path).map_err(|e| format!("Failed to open {:?}: {}", path, e))?;
+    let mut hasher = Sha256::new();
+    let mut buf = [0u8; 64 * 1024];
+    let mut size = 0u64;
+    loop {
+        let n = file.read(&mut buf).map_err(|e| format!("Failed to read {:?}: {}", path, e))?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+        size += n as u64;
+    }
+    Ok((size, hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect()))
+}
+
+/// Size and SHA-256 (hex) of the message stored at `path` — of what it
+/// opens and decompresses to, when the vault seals or compresses.
+pub fn hash_message(path: &Path) -> Result<(u64, String), String> {
+    if compression::is_plain(path) {
+        return hash_file(path);
+    }
+    let raw = compression::read_message(path)?;
+    Ok((raw.len() as u64, sha256_hex(&raw)))
+}
+
+/// Write `bytes` to a new file at `path` and fsync it. The name isn't durable
+/// until the directory is synced too.
+pub(crate) fn write_synced(path: &Path, bytes: &[u8]) -> Result<(), String> {
+    let mut file = File::create(path).map_err(|e| format!("Failed to create {:?}: {}", path, e))?;
+    file.write_all(bytes)
+        .and_then(|()| file.sync_all())
+        .map_err(|e| format!("Failed to write {:?}: {}", path, e))
+}
+
+/// fsync a directory, so the renames and creates inside it survive a crash.
+pub fn sync_dir(dir: &Path) -> Result<(), String> {
+    #[cfg(unix)]
+    File::open(dir)
+        .and_then(|d| d.sync_all())
+        .map_err(|e| format!("Failed to sync {:?}: {}", dir, e))?;
+    // Windows can't open a directory this way; NTFS journals the rename.
+    #[cfg(not(unix))]
+    let _ = dir;
+    Ok(())
+}
+
+/// The post-write check: `path` holds all `len` bytes. A file that doesn't is
+/// removed, so nothing reads it as stored.
+pub(crate) fn check_size(path: &Path, len: usize) -> Result<(), String> {
+    let found = fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+    if found == len as u64 {
+        return Ok(());
+    }
+    let _ = fs::remove_file(path);
+    Err(format!("Short write: {:?} holds {} of {} bytes", path, found, len))
+}
+
+/// Write `bytes` at `dest` through a file in `tmp_dir`: fsync, rename over
+/// `dest`, fsync its directory, then check the size. A crash at any point
+/// leaves `dest` as it was or whole, never partly written.
+pub fn write_atomic(tmp_dir: &Path, dest: &Path, bytes: &[u8]) -> Result<(), String> {
+    fs::create_dir_all(tmp_dir).map_err(|e| format!("Failed to create {:?}: {}", tmp_dir, e))?;
+    let tmp = tmp_dir.join(standard::new_base());
+    let written = write_synced(&tmp, bytes)
+        .and_then(|()| fs::rename(&tmp, dest).map_err(|e| format!("Failed to move {:?} into place: {}", dest, e)));
+    if let Err(e) = written {
+        let _ = fs::remove_file(&tmp);
+        return Err(e);
+    }
+    if let Some(dir) = dest.parent() {
+        sync_dir(dir)?;
+    }
+    check_size(dest, bytes.len())
+}
+
+// ── Startup scan ────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Default, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QuarantineScan {
+    pub mailboxes: u64,
+    /// Files read to look for truncation; the rest only had their size checked.
+    pub inspected: u64,
+    pub quarantined: u64,
+    pub stale_tmp_removed: u64,
+    pub errors: u64,
+}
+
+/// Why a message file can't be trusted, if it can't.
+fn defect(path: &Path, entry: &crate::uid_index::Entry, inspect: bool) -> Option<&'static str> {
+    let len = fs::metadata(path).ok()?.len();
+    if len == 0 {
+        return Some("empty");
+    }
+    if entry.sha256.is_some() {
+        // Written and checked through `write_atomic`; only a size change says
+        // something has been at it since.
+        return (len != entry.size).then_some("size changed since it was written");
+    }
+    if inspect && looks_truncated(path, len) {
+        return Some("truncated");
+    }
+    None
+}
+
+/// A message cut short by a crash mid-write: no blank line ending the header
+/// section, or a multipart body without its closing delimiter.
+fn looks_truncated(path: &Path, len: u64) -> bool {
+    if !compression::is_plain(path) {
+        // Nothing to go on until the vault is unlocked.
+        if encryption::sealed_header_of_file(path).is_some()
+            && compression::vault_of(path).is_some_and(|vault| !encryption::is_unlocked(vault))
+        {
+            return false;
+        }
+        // A sealed file or compressed stream cut short doesn't open at all.
+        let Ok(stored) = compression::read_stored(path) else { return true };
+        let raw = if dedup::is_recipe(&stored) {
+            // A missing stored part is for `verify` to report; the file
+            // itself is whole.
+            let Ok(raw) = dedup::join(compression::vault_of(path), &stored) else { return false };
+            raw
+        } else {
+            stored
+        };
+        let len = raw.len() as u64;
+        return truncated(Cursor::new(raw), len);
+    }
+    let Ok(file) = File::open(path) else { return false };
+    truncated(file, len)
+}
+
+fn truncated(mut file: impl Read + Seek, len: u64) -> bool {
+    const LIMIT: usize = 128 * 1024;
+    let mut head = Vec::new();
+    if (&mut file).take(LIMIT as u64).read_to_end(&mut head).is_err() {
+        return false;
+    }
+    let header_len = super::header_section(&head).len();
+    if header_len == head.len() {
+        // A header section this long isn't a crash — leave it be.
+        return head.len() < LIMIT;
+    }
+    let Ok((headers, _)) = mailparse::parse_headers(&head[..header_len]) else { return false };
+    let Some(content_type) = super::get_header(&headers, "Content-Type") else { return false };
+    let ct = mailparse::parse_content_type(&content_type);
+    let Some(boundary) = ct.params.get("boundary").filter(|_| ct.mimetype.starts_with("multipart/")) else {
+        return false;
+    };
+    let from = len.saturating_sub(TAIL + boundary.len() as u64);
+    let mut tail = Vec::new();
+    if file.seek(SeekFrom::Start(from)).is_err() || file.read_to_end(&mut tail).is_err() {
+        return false;
+    }
+    let closing = format!("--{}--", boundary);
+    !tail.windows(closing.len()).any(|w| w == closing.as_bytes())
+}
+
+/// A free name in `quarantine/`; the same uid can be cut short twice.
+fn free_quarantine_path(dir: &Path, name: &str) -> PathBuf {
+    let mut path = dir.join(name);
+    let mut n = 1;
+    while path.exists() {
+        path = dir.join(format!("{}.{}", name, n));
+        n += 1;
+    }
+    path
+}
+
+/// Remove `tmp/` files left by writes that never finished.
+fn clean_tmp(mailbox_dir: &Path, report: &mut QuarantineScan) {
+    let Ok(files) = fs::read_dir(mailbox_dir.join("tmp")) else { return };
+    for file in files.flatten() {
+        let stale = file
+            .metadata()
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|t| t.elapsed().ok())
+            .is_some_and(|age| age > STALE_TMP);
+        if stale && fs::remove_file(file.path()).is_ok() {
+            report.stale_tmp_removed += 1;
+        }
+    }
+}
+
+/// Move every message that didn't survive its write into its mailbox's
+/// `quarantine/`, and out of the uid index, so sync fetches it again. The
+/// file is kept: if the server no longer has the message, it is still all
+/// there is.
+///
+/// Empty files and recorded-size mismatches are caught in every mailbox on
+/// every run. Looking inside a file for truncation is only done for files
+/// with no recorded hash that changed since the last scan finished — the
+/// first scan reads every older file once.
+pub fn quarantine_scan(data_dir: &Path) -> QuarantineScan {
+    let root = data_dir.join("Maildir");
+    let mut report = QuarantineScan::default();
+    if !root.exists() {
+        return report;
+    }
+    let started = SystemTime::now();
+    let since = fs::read_to_string(root.join(SCAN_FILE))
+        .ok()
+        .and_then(|s| s.trim().parse::<u64>().ok())
+        // A minute of slack for coarse mtimes.
+        .map(|secs| UNIX_EPOCH + Duration::from_secs(secs.saturating_sub(60)));
+
+    for mailbox_dir in super::mailbox_dirs(&root) {
+        report.mailboxes += 1;
+        clean_tmp(&mailbox_dir, &mut report);
+        let cur_dir = mailbox_dir.join("cur");
+        for (uid, entry) in crate::uid_index::entries(&cur_dir) {
+            let path = entry.path(&cur_dir);
+            let inspect = entry.sha256.is_none()
+                && since.is_none_or(|since| {
+                    fs::metadata(&path).and_then(|m| m.modified()).is_ok_and(|t| t >= since)
+                });
+            if inspect {
+                report.inspected += 1;
+            }
+            let Some(reason) = defect(&path, &entry, inspect) else { continue };
+            match quarantine(&root, &mailbox_dir, uid, &path) {
+                Ok(to) => {
+                    warn!("quarantine_scan: UID {} {} — moved to {:?}", uid, reason, to);
+                    report.quarantined += 1;
+                }
+                Err(e) => {
+                    warn!("quarantine_scan: {}", e);
+                    report.errors += 1;
+                }
+            }
+        }
+    }
+
+    let secs = started.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    if let Err(e) = fs::write(root.join(SCAN_FILE), secs.to_string()) {
+        warn!("quarantine_scan: failed to write {}: {}", SCAN_FILE, e);
+    }
+    info!(
+        "quarantine_scan: {} mailboxes, {} files inspected, {} quarantined, {} stale tmp files removed, {} errors",
+        report.mailboxes, report.inspected, report.quarantined, report.stale_tmp_removed, report.errors,
+    );
+    report
+}
+
+/// Move one message aside, through the uid index so it reads as not stored.
+pub(crate) fn quarantine(root: &Path, mailbox_dir: &Path, uid: u32, path: &Path) -> Result<PathBuf, String> {
+    let dir = mailbox_dir.join(QUARANTINE_DIR);
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create {:?}: {}", dir, e))?;
+    let name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+    let to = free_quarantine_path(&dir, &name);
+    crate::uid_index::change(&mailbox_dir.join("cur"), uid, || {
+        fs::rename(path, &to).map_err(|e| format!("Failed to quarantine {:?}: {}", path, e))?;
+        Ok(None)
+    })?;
+    // The search index drops it too, until the re-fetch stores it again.
+    if let Some((account_id, mailbox)) = super::mailbox_key(root, mailbox_dir) {
+        crate::search::journal_delete(root.parent().unwrap_or(root), &account_id, &mailbox, uid);
+    }
+    Ok(to)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MULTIPART: &[u8] =
+        b"Subject: parts\r\nContent-Type: multipart/mixed; boundary=\"XX\"\r\n\r\n--XX\r\n\r\nfirst\r\n--XX--\r\n";
+
+    fn is_truncated(bytes: &[u8]) -> bool {
+        truncated(Cursor::new(bytes), bytes.len() as u64)
+    }
+
+    #[test]
+    fn hash_file_matches_the_in_memory_hash() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("m.eml");
+        let bytes: Vec<u8> = (0..200_000u32).map(|i| (i % 253) as u8).collect();
+        fs::write(&path, &bytes).unwrap();
+        assert_eq!(hash_file(&path).unwrap(), (bytes.len() as u64, sha256_hex(&bytes)));
+        assert_eq!(hash_message(&path).unwrap(), hash_file(&path).unwrap());
+        assert_eq!(sha256_hex(b""), "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+    }
+
+    #[test]
+    fn write_atomic_replaces_whole_and_leaves_no_temp_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let tmp = dir.path().join("tmp");
+        let dest = dir.path().join("cur").join("1:2,S.eml");
+        fs::create_dir_all(dest.parent().unwrap()).unwrap();
+        write_atomic(&tmp, &dest, b"first").unwrap();
+        write_atomic(&tmp, &dest, b"second, longer").unwrap();
+        assert_eq!(fs::read(&dest).unwrap(), b"second, longer");
+        assert_eq!(fs::read_dir(&tmp).unwrap().count(), 0);
+
+        // A size that doesn't match removes the file rather than keep it.
+        assert!(check_size(&dest, 3).is_err());
+        assert!(!dest.exists());
+    }
+
+    #[test]
+    fn truncation_is_a_missing_header_end_or_closing_delimiter() {
+        assert!(!is_truncated(MULTIPART));
+        assert!(!is_truncated(b"Subject: plain\r\n\r\nno boundary to close"));
+        assert!(is_truncated(&MULTIPART[..MULTIPART.len() - 8]));
+        assert!(is_truncated(b"Subject: cut mid-head\r\nFrom: a@"));
+    }
+
+    #[test]
+    fn quarantine_names_never_collide() {
+        let dir = tempfile::tempdir().unwrap();
+        let first = free_quarantine_path(dir.path(), "5:2,S.eml");
+        fs::write(&first, b"").unwrap();
+        let second = free_quarantine_path(dir.path(), "5:2,S.eml");
+        assert_eq!(second.file_name().unwrap(), "5:2,S.eml.1");
+    }
+}
@@ -0,0 +1,152 @@
+//! Per-mailbox record of what each message hashed to when it was stored.
+//!
+//! `{mailbox}/.manifest.sha256` gets a line per store — `{uid} {size} {sha256}`
+//! — and a `{uid} -` line when the message is deleted. In an encrypted vault
+//! the hash is keyed, `{key id}:{hmac}` (`encryption::digest`), and
+//! `encryption::reseal_sidecars` re-keys the lines when the vault's key does. The last line for a
+//! uid wins. Appends are single short writes, so the app and the daemon can
+//! both add to it; `vault.verify` compacts it.
+//!
+//! The uid index keeps a hash too, but it is a cache — a rescan or a layout
+//! conversion drops it. This is the record `verify` holds the files to.
+
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+use super::integrity;
+
+pub const MANIFEST_FILE: &str = ".manifest.sha256";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Recorded {
+    pub size: u64,
+    /// The SHA-256 (hex), or in an encrypted vault its keyed digest.
+    pub sha256: String,
+}
+
+fn append(mailbox_dir: &Path, text: &str) -> Result<(), String> {
+    let path = mailbox_dir.join(MANIFEST_FILE);
+    // Not fsynced: a line lost to a crash only means the next verify hashes
+    // that message afresh instead of checking it.
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .and_then(|mut f| f.write_all(text.as_bytes()))
+        .map_err(|e| format!("Failed to append to {:?}: {}", path, e))
+}
+
+/// Record what `uid` hashed to as it was written.
+pub fn record(mailbox_dir: &Path, uid: u32, size: u64, sha256: &str) -> Result<(), String> {
+    append(mailbox_dir, &format!("{} {} {}\n", uid, size, sha256))
+}
+
+/// Drop `uids` from the record — they were deleted, not lost.
+pub fn forget(mailbox_dir: &Path, uids: &[u32]) -> Result<(), String> {
+    if uids.is_empty() || !mailbox_dir.join(MANIFEST_FILE).exists() {
+        return Ok(());
+    }
+    let text: String = uids.iter().map(|uid| format!("{} -\n", uid)).collect();
+    append(mailbox_dir, &text)
+}
+
+/// Every uid with a recorded hash. A line that doesn't parse — the torn tail
+/// of an append a crash cut short — is skipped.
+pub fn read(mailbox_dir: &Path) -> BTreeMap<u32, Recorded> {
+    let mut out = BTreeMap::new();
+    let Ok(text) = fs::read_to_string(mailbox_dir.join(MANIFEST_FILE)) else { return out };
+    for line in text.lines() {
+        let mut parts = line.split(' ');
+        let Some(uid) = parts.next().and_then(|u| u.parse::<u32>().ok()) else { continue };
+        match (parts.next(), parts.next()) {
+            (Some("-"), None) => {
+                out.remove(&uid);
+            }
+            (Some(size), Some(sha256)) if sha256.rsplit(':').next().is_some_and(|h| h.len() == 64) => {
+                if let Ok(size) = size.parse() {
+                    out.insert(uid, Recorded { size, sha256: sha256.to_string() });
+                }
+            }
+            _ => {}
+        }
+    }
+    out
+}
+
+/// Replace the manifest with one line per entry. An append that lands while
+/// this runs is lost, and its message reads as unrecorded until the next
+/// verify hashes it.
+pub fn write(mailbox_dir: &Path, entries: &BTreeMap<u32, Recorded>) -> Result<(), String> {
+    let text: String = entries
+        .iter()
+        .map(|(uid, r)| format!("{} {} {}\n", uid, r.size, r.sha256))
+        .collect();
+    integrity::write_atomic(&mailbox_dir.join("tmp"), &mailbox_dir.join(MANIFEST_FILE), text.as_bytes())
+}
+
+/// Follow a generation repair: move each rebound uid's record to its new uid
+/// and drop the orphaned ones.
+pub fn remap(mailbox_dir: &Path, rebound: &[(u32, u32)], orphaned: &[u32]) -> Result<(), String> {
+    if (rebound.is_empty() && orphaned.is_empty()) || !mailbox_dir.join(MANIFEST_FILE).exists() {
+        return Ok(());
+    }
+    let mut entries = read(mailbox_dir);
+    let moved: Vec<(u32, Option<Recorded>)> = rebound.iter().map(|(old, new)| (*new, entries.remove(old))).collect();
+    for uid in orphaned {
+        entries.remove(uid);
+    }
+    for (uid, recorded) in moved {
+        match recorded {
+            Some(r) => entries.insert(uid, r),
+            None => entries.remove(&uid),
+        };
+    }
+    write(mailbox_dir, &entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sha(c: char) -> String {
+        c.to_string().repeat(64)
+    }
+
+    #[test]
+    fn the_last_line_for_a_uid_wins_and_a_torn_tail_is_skipped() {
+        let dir = tempfile::tempdir().unwrap();
+        record(dir.path(), 1, 10, &sha('a')).unwrap();
+        record(dir.path(), 2, 20, &sha('b')).unwrap();
+        record(dir.path(), 1, 11, &sha('c')).unwrap();
+        forget(dir.path(), &[2]).unwrap();
+        record(dir.path(), 3, 30, &format!("0a0b0c0d:{}", sha('d'))).unwrap();
+        append(dir.path(), "4 40 eeee").unwrap();
+
+        let entries = read(dir.path());
+        assert_eq!(entries.keys().copied().collect::<Vec<_>>(), vec![1, 3]);
+        assert_eq!(entries[&1], Recorded { size: 11, sha256: sha('c') });
+        assert_eq!(entries[&3].sha256, format!("0a0b0c0d:{}", sha('d')));
+    }
+
+    #[test]
+    fn write_compacts_and_remap_follows_rebound_uids() {
+        let dir = tempfile::tempdir().unwrap();
+        // Nothing recorded yet: forgetting doesn't start a manifest.
+        forget(dir.path(), &[1]).unwrap();
+        assert!(!dir.path().join(MANIFEST_FILE).exists());
+        fs::create_dir_all(dir.path().join("tmp")).unwrap();
+        for uid in 1..=3 {
+            record(dir.path(), uid, uid as u64, &sha('a')).unwrap();
+            record(dir.path(), uid, uid as u64 * 10, &sha('b')).unwrap();
+        }
+        write(dir.path(), &read(dir.path())).unwrap();
+        assert_eq!(fs::read_to_string(dir.path().join(MANIFEST_FILE)).unwrap().lines().count(), 3);
+
+        remap(dir.path(), &[(1, 7), (2, 1)], &[3]).unwrap();
+        let entries = read(dir.path());
+        assert_eq!(entries.keys().copied().collect::<Vec<_>>(), vec![1, 7]);
+        assert_eq!((entries[&7].size, entries[&1].size), (10, 20));
+    }
+}
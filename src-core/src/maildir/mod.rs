@@ -0,0 +1,2359 @@
+//! Maildir operations — store, read, list, delete .eml files.
+//!
+//! All functions take an explicit `data_dir` path (no Tauri dependency).
+//! Layout: {data_dir}/Maildir/{account_id}/{mailbox}/cur/{uid}:{flags}:{timestamp}.eml
+//!
+//! A mailbox can instead be in the standard Maildir layout (see `standard`),
+//! for other mail programs to read. Everything here takes either; the uid
+//! index resolves a uid to its file in both.
+//!
+//! Every message write goes through `integrity::write_atomic` or its standard
+//! layout equivalent, so a crash never leaves a partly written file behind.
+//! A vault set to compress (see `compression`) writes `.eml.zst`/`.eml.gz`;
+//! every read here decompresses. An encrypted vault (see `encryption`) seals
+//! each file after that, and every read opens it. With deduplication on (see
+//! `dedup`), large attachments live in a shared part store and every read
+//! puts them back.
+
+pub mod compression;
+pub mod dedup;
+pub mod encryption;
+pub mod integrity;
+pub mod manifest;
+pub mod standard;
+pub mod verify;
+
+pub use compression::Compression;
+pub use integrity::QUARANTINE_DIR;
+pub use standard::Layout;
+
+use crate::types::{EmailHeader, EmailAddress, ParsedEmail, Attachment};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+/// Get the Maildir/cur path for an account + mailbox.
+pub fn cur_path(data_dir: &Path, account_id: &str, mailbox: &str) -> PathBuf {
+    data_dir.join("Maildir").join(account_id).join(mailbox).join("cur")
+}
+
+/// Build a Maildir filename from UID and flags.
+///
+/// Filename format: `{uid}:{flags}:{timestamp}.eml`. The `.eml` suffix makes
+/// the file double-clickable in the user's OS and keeps the zip export usable
+/// without a rename step.
+pub fn build_filename(uid: u32, flags: &[String]) -> String {
+    let ts = chrono::Utc::now().timestamp();
+    let flags_str = if flags.is_empty() {
+        String::new()
+    } else {
+        flags.iter()
+            .map(|f| f.trim_start_matches('\\').to_lowercase())
+            .collect::<Vec<_>>()
+            .join(",")
+    };
+    format!("{}:{}:{}.eml", uid, flags_str, ts)
+}
+
+/// The uid a vault-layout filename starts with. `None` for a standard name,
+/// whose uid is in the mailbox's uidlist instead.
+pub(crate) fn vault_uid(name: &str) -> Option<u32> {
+    name.split(':').next()?.parse().ok()
+}
+
+/// Find a file by UID in a Maildir/cur directory. Goes through the mailbox's
+/// uid index (see `crate::uid_index`), so it doesn't list the directory. In a
+/// standard mailbox the file may be in `new/`.
+pub fn find_by_uid(cur_dir: &Path, uid: u32) -> Option<PathBuf> {
+    crate::uid_index::lookup(cur_dir, uid)
+}
+
+/// List all UIDs in a Maildir/cur directory.
+pub fn list_uids(data_dir: &Path, account_id: &str, mailbox: &str) -> Vec<u32> {
+    crate::uid_index::uids(&cur_path(data_dir, account_id, mailbox))
+}
+
+/// Keep the server's `(uid, EMAILID, THREADID)` beside the vault copies that
+/// have those uids, for `repair_generation` to find them by after a reset.
+pub fn note_object_ids(data_dir: &Path, account_id: &str, mailbox: &str, ids: &[(u32, String, Option<String>)]) -> usize {
+    crate::uid_index::note_object_ids(&cur_path(data_dir, account_id, mailbox), ids)
+}
+
+/// Store a raw email (bytes) to Maildir.
+pub fn store(
+    data_dir: &Path,
+    account_id: &str,
+    mailbox: &str,
+    uid: u32,
+    raw_bytes: &[u8],
+    flags: &[String],
+) -> Result<PathBuf, String> {
+    let dir = cur_path(data_dir, account_id, mailbox);
+
+    // Skip if already exists
+    if let Some(existing) = find_by_uid(&dir, uid) {
+        return Ok(existing);
+    }
+
+    let path = store_in(&dir, uid, raw_bytes, flags)?;
+    crate::search::journal_store(data_dir, account_id, mailbox, uid);
+
+    info!("Stored UID {} ({} bytes) → {:?}", uid, raw_bytes.len(), path);
+    Ok(path)
+}
+
+/// Write a message into the mailbox that owns `cur_dir`, in that mailbox's
+/// layout, compression and encryption, and record it in the uid index with its size and
+/// hash. The write is atomic (see `integrity`); an existing file of the same
+/// name is replaced. Doesn't check for an existing copy under another name.
+pub fn store_in(cur_dir: &Path, uid: u32, raw_bytes: &[u8], flags: &[String]) -> Result<PathBuf, String> {
+    let mailbox_dir = cur_dir.parent().ok_or_else(|| format!("No mailbox for {:?}", cur_dir))?;
+    let stored = match standard::layout_of(mailbox_dir) {
+        Layout::Vault => {
+            fs::create_dir_all(cur_dir).map_err(|e| format!("Failed to create Maildir: {}", e))?;
+            let (bytes, suffix) = compression::encode_message(mailbox_dir, raw_bytes)?;
+            let path = cur_dir.join(format!("{}{}", build_filename(uid, flags), suffix));
+            crate::uid_index::change(cur_dir, uid, || {
+                integrity::write_atomic(&mailbox_dir.join("tmp"), &path, &bytes)?;
+                Ok(Some(path.clone()))
+            })?
+            .map(|path| (path, bytes.len()))
+        }
+        Layout::Standard => {
+            standard::create(mailbox_dir)?;
+            crate::uid_index::change(cur_dir, uid, || {
+                standard::deliver(mailbox_dir, uid, raw_bytes, flags).map(Some)
+            })?
+            .map(|path| (path, raw_bytes.len()))
+        }
+    };
+    let (stored, stored_len) = stored.ok_or_else(|| format!("UID {} was not stored", uid))?;
+    record_written(cur_dir, uid, raw_bytes, stored_len as u64);
+    Ok(stored)
+}
+
+/// Record the hash of a message just written at `uid`, in the uid index and in
+/// the mailbox manifest `verify` checks it against later — keyed there, in an
+/// encrypted vault (`encryption::digest`). The hash is of the message,
+/// `raw_bytes`; `stored_len` is the size of the file it went into, which
+/// differs when the vault compresses.
+pub fn record_written(cur_dir: &Path, uid: u32, raw_bytes: &[u8], stored_len: u64) {
+    let sha256 = integrity::sha256_hex(raw_bytes);
+    if let Some(mailbox_dir) = cur_dir.parent() {
+        let recorded = encryption::digest(compression::vault_of(mailbox_dir), &sha256)
+            .and_then(|digest| manifest::record(mailbox_dir, uid, raw_bytes.len() as u64, &digest));
+        if let Err(e) = recorded {
+            warn!("{}", e);
+        }
+    }
+    crate::uid_index::verified(cur_dir, uid, stored_len, sha256);
+}
+
+/// Read a raw .eml file by UID, decompressed.
+pub fn read_raw(data_dir: &Path, account_id: &str, mailbox: &str, uid: u32) -> Result<Vec<u8>, String> {
+    let dir = cur_path(data_dir, account_id, mailbox);
+    let path = find_by_uid(&dir, uid)
+        .ok_or_else(|| format!("Email UID {} not found in {}/{}", uid, account_id, mailbox))?;
+    compression::read_message(&path).map_err(|e| format!("Failed to read .eml: {}", e))
+}
+
+/// Parse an .eml file into a lightweight header (no body/attachments).
+pub fn parse_header(raw: &[u8]) -> Result<EmailHeader, String> {
+    let parsed = mailparse::parse_mail(raw)
+        .map_err(|e| format!("Failed to parse email: {}", e))?;
+
+    let headers = &parsed.headers;
+
+    let message_id = get_header(headers, "Message-ID");
+    let subject = get_header(headers, "Subject").unwrap_or_default();
+    let from = get_header(headers, "From").map(|s| parse_address(&s));
+    let to = get_header(headers, "To")
+        .map(|s| parse_address_list(&s))
+        .unwrap_or_default();
+    let date = get_header(headers, "Date").unwrap_or_default();
+    let in_reply_to = get_header(headers, "In-Reply-To");
+    let references = get_header(headers, "References")
+        .map(|s| s.split_whitespace().map(String::from).collect());
+
+    Ok(EmailHeader {
+        uid: 0, // Caller must set
+        message_id,
+        subject,
+        from,
+        to,
+        date,
+        flags: vec![],
+        size: raw.len() as u64,
+        in_reply_to,
+        references,
+        snippet: extract_snippet(&parsed),
+    })
+}
+
+/// Delete an email from Maildir by UID.
+pub fn delete(data_dir: &Path, account_id: &str, mailbox: &str, uid: u32) -> Result<(), String> {
+    let dir = cur_path(data_dir, account_id, mailbox);
+    match find_by_uid(&dir, uid) {
+        Some(path) => {
+            crate::uid_index::change(&dir, uid, || {
+                fs::remove_file(&path).map_err(|e| format!("Failed to delete: {}", e))?;
+                Ok(None)
+            })?;
+            if let Some(mailbox_dir) = dir.parent() {
+                let _ = manifest::forget(mailbox_dir, &[uid]);
+            }
+            crate::search::journal_delete(data_dir, account_id, mailbox, uid);
+            info!("Deleted UID {} from {}/{}", uid, account_id, mailbox);
+            Ok(())
+        }
+        None => Ok(()), // Already deleted
+    }
+}
+
+/// Get storage stats for an account.
+pub fn storage_stats(data_dir: &Path, account_id: &str) -> StorageStats {
+    let root = data_dir.join("Maildir").join(account_id);
+    let mut total_size: u64 = 0;
+    let mut total_emails: u64 = 0;
+    let mut mailbox_count: u64 = 0;
+
+    if root.exists() {
+        for entry in walkdir::WalkDir::new(&root).min_depth(1).max_depth(1) {
+            if let Ok(entry) = entry {
+                if entry.file_type().is_dir() {
+                    mailbox_count += 1;
+                    // `new/` only holds mail in a standard-layout mailbox.
+                    for sub in ["cur", "new"] {
+                        if let Ok(files) = fs::read_dir(entry.path().join(sub)) {
+                            for file in files.flatten() {
+                                total_emails += 1;
+                                total_size += file.metadata().map(|m| m.len()).unwrap_or(0);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // Figures from the last part store sweep, if dedup has ever been on.
+    let dedup = dedup::stats(data_dir).unwrap_or_default();
+    StorageStats {
+        total_size,
+        total_emails,
+        mailbox_count,
+        attachment_bytes: dedup.accounts.get(account_id).copied().unwrap_or(0),
+        dedup_saved_bytes: dedup.saved_bytes(),
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct StorageStats {
+    pub total_size: u64,
+    pub total_emails: u64,
+    pub mailbox_count: u64,
+    /// Bytes of this account's attachments kept in the shared part store,
+    /// not counted in `total_size`.
+    pub attachment_bytes: u64,
+    /// Bytes the whole vault saves by storing shared attachments once.
+    pub dedup_saved_bytes: u64,
+}
+
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct EmlMigrationStats {
+    pub renamed: u64,
+    pub already_ok: u64,
+    pub skipped_non_message: u64,
+    pub errors: u64,
+}
+
+const MAILDIR_VERSION_FILE: &str = ".maildir_version";
+/// Message files carry the `.eml` suffix.
+const MAILDIR_VERSION_EML: u32 = 2;
+/// Mailbox directories use decoded names, not IMAP modified UTF-7.
+const MAILDIR_VERSION_DECODED_NAMES: u32 = 3;
+
+fn maildir_version(maildir_root: &Path) -> u32 {
+    fs::read_to_string(maildir_root.join(MAILDIR_VERSION_FILE))
+        .ok()
+        .and_then(|s| s.trim().parse::<u32>().ok())
+        .unwrap_or(0)
+}
+
+/// One-time migration: append `.eml` to every Maildir message file that lacks
+/// the extension. Idempotent — guarded by `{data_dir}/Maildir/.maildir_version`.
+///
+/// Walks `{data_dir}/Maildir/*/*/{cur,new,tmp}/` and renames files whose name
+/// looks like a Maildir message (`{uid}:...`) but does not already end in
+/// `.eml`. Files that don't match the pattern (e.g. `local-index.json`) are
+/// left alone.
+pub fn migrate_add_eml_extension(data_dir: &Path) -> EmlMigrationStats {
+    let mut stats = EmlMigrationStats::default();
+    let maildir_root = data_dir.join("Maildir");
+    if !maildir_root.exists() {
+        return stats;
+    }
+
+    let version_path = maildir_root.join(MAILDIR_VERSION_FILE);
+    if maildir_version(&maildir_root) >= MAILDIR_VERSION_EML {
+        return stats;
+    }
+
+    let account_dirs = match fs::read_dir(&maildir_root) {
+        Ok(d) => d,
+        Err(e) => {
+            warn!("migrate_add_eml_extension: read Maildir root failed: {}", e);
+            return stats;
+        }
+    };
+
+    for account_entry in account_dirs.flatten() {
+        if !account_entry.file_type().map(|t| t.is_dir()).unwrap_or(false) {
+            continue;
+        }
+        let mailbox_dirs = match fs::read_dir(account_entry.path()) {
+            Ok(d) => d,
+            Err(_) => continue,
+        };
+        for mailbox_entry in mailbox_dirs.flatten() {
+            if !mailbox_entry.file_type().map(|t| t.is_dir()).unwrap_or(false) {
+                continue;
+            }
+            // Standard names aren't ours to suffix; other programs read them.
+            if standard::is_standard(&mailbox_entry.path()) {
+                continue;
+            }
+            for sub in ["cur", "new", "tmp"] {
+                let dir = mailbox_entry.path().join(sub);
+                if !dir.exists() {
+                    continue;
+                }
+                rename_dir_add_eml(&dir, &mut stats);
+            }
+        }
+    }
+
+    if let Err(e) = fs::write(&version_path, MAILDIR_VERSION_EML.to_string()) {
+        warn!("migrate_add_eml_extension: write version file failed: {}", e);
+    }
+
+    info!(
+        "migrate_add_eml_extension: renamed={} already_ok={} skipped={} errors={}",
+        stats.renamed, stats.already_ok, stats.skipped_non_message, stats.errors
+    );
+    stats
+}
+
+fn rename_dir_add_eml(dir: &Path, stats: &mut EmlMigrationStats) {
+    let entries = match fs::read_dir(dir) {
+        Ok(e) => e,
+        Err(_) => return,
+    };
+    for entry in entries.flatten() {
+        if !entry.file_type().map(|t| t.is_file()).unwrap_or(false) {
+            continue;
+        }
+        let name = entry.file_name().to_string_lossy().to_string();
+
+        // Heuristic: Maildir message filenames start with `{uid}:`.
+        // Anything else (local-index.json, hidden files, etc.) is left alone.
+        let looks_like_message = name
+            .split(':')
+            .next()
+            .and_then(|s| s.parse::<u64>().ok())
+            .is_some();
+        if !looks_like_message {
+            stats.skipped_non_message += 1;
+            continue;
+        }
+
+        if name.ends_with(".eml") {
+            stats.already_ok += 1;
+            continue;
+        }
+
+        let src = entry.path();
+        let dst = dir.join(format!("{}.eml", name));
+        if dst.exists() {
+            // Collision: a sibling already has the `.eml` variant. Leave the
+            // extension-less file in place — readers match by `{uid}:` prefix,
+            // so the first hit still resolves. Don't silently overwrite.
+            warn!("migrate_add_eml_extension: collision, skipping: {:?}", src);
+            stats.errors += 1;
+            continue;
+        }
+        match fs::rename(&src, &dst) {
+            Ok(()) => stats.renamed += 1,
+            Err(e) => {
+                warn!("migrate_add_eml_extension: rename {:?} failed: {}", src, e);
+                stats.errors += 1;
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct NameMigrationStats {
+    pub renamed: u64,
+    pub errors: u64,
+}
+
+/// One-time migration: rename mailbox directories written under their raw
+/// IMAP modified UTF-7 name (`&BB4EQgQ,BEAEMAQyBDsENQQ9BD0ESwQ1-`) to the
+/// decoded one (`Отправленные`), which is what sync now uses. Guarded by the
+/// same version file as the `.eml` migration; run it after that one.
+///
+/// Every level of a nested mailbox path is checked on its own, since the
+/// encoding never spans a `/`. A decoded name that already exists is left
+/// alone — both directories stay, nothing is merged or overwritten.
+pub fn migrate_decode_mailbox_names(data_dir: &Path) -> NameMigrationStats {
+    let mut stats = NameMigrationStats::default();
+    let maildir_root = data_dir.join("Maildir");
+    if !maildir_root.exists() || maildir_version(&maildir_root) >= MAILDIR_VERSION_DECODED_NAMES {
+        return stats;
+    }
+
+    let account_dirs = match fs::read_dir(&maildir_root) {
+        Ok(d) => d,
+        Err(e) => {
+            warn!("migrate_decode_mailbox_names: read Maildir root failed: {}", e);
+            return stats;
+        }
+    };
+    for account_entry in account_dirs.flatten() {
+        if account_entry.file_type().map(|t| t.is_dir()).unwrap_or(false) {
+            decode_mailbox_dirs(&account_entry.path(), &mut stats);
+        }
+    }
+
+    if let Err(e) = fs::write(maildir_root.join(MAILDIR_VERSION_FILE), MAILDIR_VERSION_DECODED_NAMES.to_string()) {
+        warn!("migrate_decode_mailbox_names: write version file failed: {}", e);
+    }
+
+    info!("migrate_decode_mailbox_names: renamed={} errors={}", stats.renamed, stats.errors);
+    stats
+}
+
+fn decode_mailbox_dirs(parent: &Path, stats: &mut NameMigrationStats) {
+    let entries = match fs::read_dir(parent) {
+        Ok(e) => e,
+        Err(_) => return,
+    };
+    for entry in entries.flatten() {
+        if !entry.file_type().map(|t| t.is_dir()).unwrap_or(false) {
+            continue;
+        }
+        let name = entry.file_name().to_string_lossy().to_string();
+        if matches!(name.as_str(), "cur" | "new" | "tmp" | ORPHAN_DIR | QUARANTINE_DIR) {
+            continue;
+        }
+
+        let mut dir = entry.path();
+        if crate::imap::utf7::is_encoded(&name) {
+            let decoded = crate::imap::utf7::decode(&name);
+            let dst = parent.join(&decoded);
+            if dst.exists() {
+                warn!("migrate_decode_mailbox_names: {:?} already exists, keeping {:?}", dst, dir);
+                stats.errors += 1;
+            } else {
+                match fs::rename(&dir, &dst) {
+                    Ok(()) => {
+                        stats.renamed += 1;
+                        dir = dst;
+                    }
+                    Err(e) => {
+                        warn!("migrate_decode_mailbox_names: rename {:?} failed: {}", dir, e);
+                        stats.errors += 1;
+                    }
+                }
+            }
+        }
+        decode_mailbox_dirs(&dir, stats);
+    }
+}
+
+/// Check if a UID exists in the Maildir.
+pub fn email_exists(data_dir: &Path, account_id: &str, mailbox: &str, uid: u32) -> bool {
+    let dir = cur_path(data_dir, account_id, mailbox);
+    find_by_uid(&dir, uid).is_some()
+}
+
+/// Parse a full email (headers + body + attachments) from raw bytes.
+pub fn parse_full(raw: &[u8], uid: u32) -> Result<ParsedEmail, String> {
+    let parsed = mailparse::parse_mail(raw)
+        .map_err(|e| format!("Failed to parse email: {}", e))?;
+
+    let headers = &parsed.headers;
+    let message_id = get_header(headers, "Message-ID");
+    let subject = get_header(headers, "Subject").unwrap_or_default();
+    let from = get_header(headers, "From").map(|s| parse_address(&s));
+    let to = get_header(headers, "To").map(|s| parse_address_list(&s)).unwrap_or_default();
+    let cc = get_header(headers, "Cc").map(|s| parse_address_list(&s)).unwrap_or_default();
+    let date = get_header(headers, "Date").unwrap_or_default();
+    let in_reply_to = get_header(headers, "In-Reply-To");
+    let references = get_header(headers, "References")
+        .map(|s| s.split_whitespace().map(String::from).collect());
+
+    let (text, html, attachments) = extract_body_and_attachments(&parsed);
+
+    Ok(ParsedEmail {
+        uid,
+        message_id,
+        subject,
+        from,
+        to,
+        cc,
+        date,
+        flags: vec![],
+        text,
+        html,
+        attachments,
+        in_reply_to,
+        references,
+    })
+}
+
+/// Read and parse a full email by UID.
+pub fn read_full(data_dir: &Path, account_id: &str, mailbox: &str, uid: u32) -> Result<ParsedEmail, String> {
+    let raw = read_raw(data_dir, account_id, mailbox, uid)?;
+    let mut email = parse_full(&raw, uid)?;
+
+    // Flags come from the filename, as the uid index read it
+    let dir = cur_path(data_dir, account_id, mailbox);
+    if let Some(entry) = crate::uid_index::entry(&dir, uid) {
+        email.flags = entry.flags;
+    }
+    Ok(email)
+}
+
+/// Read and parse a light email (header only) by UID.
+pub fn read_light(data_dir: &Path, account_id: &str, mailbox: &str, uid: u32) -> Result<EmailHeader, String> {
+    let raw = read_raw(data_dir, account_id, mailbox, uid)?;
+    let mut header = parse_header(&raw)?;
+    header.uid = uid;
+
+    let dir = cur_path(data_dir, account_id, mailbox);
+    if let Some(entry) = crate::uid_index::entry(&dir, uid) {
+        header.flags = entry.flags;
+    }
+    Ok(header)
+}
+
+/// Batch read light headers for multiple UIDs.
+pub fn read_light_batch(data_dir: &Path, account_id: &str, mailbox: &str, uids: &[u32]) -> Vec<EmailHeader> {
+    uids.iter().filter_map(|&uid| {
+        read_light(data_dir, account_id, mailbox, uid).ok()
+    }).collect()
+}
+
+/// Update flags for an email (renames the file).
+pub fn set_flags(data_dir: &Path, account_id: &str, mailbox: &str, uid: u32, flags: &[String]) -> Result<(), String> {
+    let dir = cur_path(data_dir, account_id, mailbox);
+    if set_flags_in(&dir, uid, flags)? {
+        crate::search::journal_store(data_dir, account_id, mailbox, uid);
+    }
+    Ok(())
+}
+
+/// Rename `uid`'s file in `cur_dir` for new flags, in whichever layout its
+/// name is in. False when the name already said so.
+pub fn set_flags_in(cur_dir: &Path, uid: u32, flags: &[String]) -> Result<bool, String> {
+    let old_path = find_by_uid(cur_dir, uid)
+        .ok_or_else(|| format!("Email UID {} not found", uid))?;
+    let name = old_path.file_name().unwrap_or_default().to_string_lossy().to_string();
+
+    if vault_uid(&name).is_none() {
+        let mailbox_dir = cur_dir.parent().ok_or_else(|| format!("No mailbox for {:?}", cur_dir))?;
+        crate::uid_index::change(cur_dir, uid, || {
+            standard::set_flags(mailbox_dir, &old_path, flags).map(Some)
+        })?;
+        return Ok(true);
+    }
+
+    let new_path = cur_dir.join(format!("{}{}", build_filename(uid, flags), compression_suffix(&name)));
+    if old_path == new_path {
+        return Ok(false);
+    }
+    crate::uid_index::change(cur_dir, uid, || {
+        fs::rename(&old_path, &new_path)
+            .map_err(|e| format!("Failed to rename for flag update: {}", e))?;
+        integrity::sync_dir(cur_dir)?;
+        Ok(Some(new_path.clone()))
+    })?;
+    Ok(true)
+}
+
+/// Follow messages the server moved or copied to new UIDs (UIDPLUS): `pairs`
+/// maps each uid in `from` to the one it now has in `to`. The vault's copy
+/// goes with it instead of staying behind under a uid the server has dropped
+/// — renamed when both mailboxes name files by uid, rewritten in `to`'s
+/// layout otherwise. `keep_source` leaves the original, for a copy. Returns
+/// how many messages were carried over.
+pub fn remap(
+    data_dir: &Path,
+    account_id: &str,
+    from: &str,
+    to: &str,
+    pairs: &[(u32, u32)],
+    keep_source: bool,
+) -> Result<usize, String> {
+    let from_cur = cur_path(data_dir, account_id, from);
+    let to_cur = cur_path(data_dir, account_id, to);
+    let to_dir = to_cur.parent().ok_or_else(|| format!("No mailbox for {:?}", to_cur))?;
+    let mut carried = 0;
+    for &(old_uid, new_uid) in pairs {
+        if from == to && old_uid == new_uid {
+            continue;
+        }
+        let Some(old_path) = find_by_uid(&from_cur, old_uid) else { continue };
+        if find_by_uid(&to_cur, new_uid).is_some() {
+            // A sync got there first.
+            if !keep_source {
+                delete(data_dir, account_id, from, old_uid)?;
+            }
+            continue;
+        }
+        let raw = compression::read_message(&old_path)?;
+        let name = old_path.file_name().unwrap_or_default().to_string_lossy().to_string();
+        // EMAILID survives a move; the new file's entry should know it too.
+        let object_ids = crate::uid_index::entry(&from_cur, old_uid)
+            .and_then(|e| Some((new_uid, e.email_id?, e.thread_id)));
+        if !keep_source && vault_uid(&name).is_some() && standard::layout_of(to_dir) == Layout::Vault {
+            fs::create_dir_all(&to_cur).map_err(|e| format!("Failed to create Maildir: {}", e))?;
+            let new_path = to_cur.join(with_uid(&name, new_uid));
+            crate::uid_index::change(&to_cur, new_uid, || {
+                fs::rename(&old_path, &new_path).map_err(|e| format!("Failed to move UID {}: {}", old_uid, e))?;
+                integrity::sync_dir(&to_cur)?;
+                Ok(Some(new_path.clone()))
+            })?;
+            crate::uid_index::change(&from_cur, old_uid, || Ok(None))?;
+            let stored_len = fs::metadata(&new_path).map(|m| m.len()).unwrap_or(0);
+            record_written(&to_cur, new_uid, &raw, stored_len);
+            if let Some(from_dir) = from_cur.parent() {
+                let _ = manifest::forget(from_dir, &[old_uid]);
+            }
+            crate::search::journal_delete(data_dir, account_id, from, old_uid);
+        } else {
+            store_in(&to_cur, new_uid, &raw, &message_flags(&old_path))?;
+            if !keep_source {
+                delete(data_dir, account_id, from, old_uid)?;
+            }
+        }
+        if let Some(ids) = object_ids {
+            crate::uid_index::note_object_ids(&to_cur, &[ids]);
+        }
+        crate::search::journal_store(data_dir, account_id, to, new_uid);
+        carried += 1;
+    }
+    if carried > 0 {
+        info!("Remapped {} messages {}/{} → {}", carried, account_id, from, to);
+    }
+    Ok(carried)
+}
+
+/// Read a single attachment by index from an email.
+pub fn read_attachment(data_dir: &Path, account_id: &str, mailbox: &str, uid: u32, index: usize) -> Result<(String, String, Vec<u8>), String> {
+    let raw = read_raw(data_dir, account_id, mailbox, uid)?;
+    let parsed = mailparse::parse_mail(&raw)
+        .map_err(|e| format!("Failed to parse: {}", e))?;
+
+    let mut att_index = 0;
+    for part in parsed.subparts.iter() {
+        let disposition = get_header(&part.headers, "Content-Disposition").unwrap_or_default();
+        if disposition.starts_with("attachment") || disposition.starts_with("inline") {
+            if att_index == index {
+                let filename = extract_attachment_filename(part);
+                let content_type = get_header(&part.headers, "Content-Type").unwrap_or_else(|| "application/octet-stream".into());
+                let body = part.get_body_raw().map_err(|e| format!("Failed to read attachment body: {}", e))?;
+                return Ok((filename, content_type, body));
+            }
+            att_index += 1;
+        }
+    }
+    Err(format!("Attachment index {} not found", index))
+}
+
+// ── Helpers ────────────────────────────────────────────────────────────────
+
+/// The compression suffix a message filename ends in, if any, so a rename
+/// keeps it.
+fn compression_suffix(name: &str) -> &str {
+    &name[compression::strip_suffix(name).len()..]
+}
+
+pub(crate) fn extract_flags_from_filename(fname: &str) -> Vec<String> {
+    // `{uid}:2,{letters}`, the name the app's own Maildir code writes — `A`
+    // is its letter for archived — and the standard layout's info.
+    if let Some((_, letters)) = fname.split_once(":2,") {
+        let mut flags = standard::flags_of(fname, &standard::Keywords::default());
+        if letters.contains('A') {
+            flags.push("archived".to_string());
+        }
+        return flags;
+    }
+    let parts: Vec<&str> = fname.splitn(3, ':').collect();
+    parts.get(1)
+        .map(|f| f.split(',').filter(|s| !s.is_empty()).map(|s| {
+            let mut flag = String::from("\\");
+            let mut chars = s.chars();
+            if let Some(first) = chars.next() {
+                flag.push(first.to_uppercase().next().unwrap_or(first));
+                flag.extend(chars);
+            }
+            flag
+        }).collect())
+        .unwrap_or_default()
+}
+
+fn extract_body_and_attachments(parsed: &mailparse::ParsedMail) -> (Option<String>, Option<String>, Vec<Attachment>) {
+    let mut text = None;
+    let mut html = None;
+    let mut attachments = Vec::new();
+
+    if parsed.subparts.is_empty() {
+        // Single-part message
+        let ct = get_header(&parsed.headers, "Content-Type").unwrap_or_default();
+        if let Ok(body) = parsed.get_body() {
+            if ct.starts_with("text/html") {
+                html = Some(body);
+            } else {
+                text = Some(body);
+            }
+        }
+    } else {
+        for part in &parsed.subparts {
+            let ct = get_header(&part.headers, "Content-Type").unwrap_or_default();
+            let disposition = get_header(&part.headers, "Content-Disposition").unwrap_or_default();
+
+            if disposition.starts_with("attachment") {
+                let filename = extract_attachment_filename(part);
+                let size = part.get_body_raw().map(|b| b.len() as u64).unwrap_or(0);
+                attachments.push(Attachment {
+                    filename,
+                    content_type: ct.split(';').next().unwrap_or("application/octet-stream").trim().to_string(),
+                    size,
+                    content_id: get_header(&part.headers, "Content-ID"),
+                });
+            } else if ct.starts_with("text/html") && html.is_none() {
+                html = part.get_body().ok();
+            } else if ct.starts_with("text/plain") && text.is_none() {
+                text = part.get_body().ok();
+            } else if ct.starts_with("multipart/") {
+                // Recurse into nested multipart
+                let (t, h, a) = extract_body_and_attachments(part);
+                if text.is_none() { text = t; }
+                if html.is_none() { html = h; }
+                attachments.extend(a);
+            }
+        }
+    }
+
+    (text, html, attachments)
+}
+
+fn extract_attachment_filename(part: &mailparse::ParsedMail) -> String {
+    // Try Content-Disposition filename
+    if let Some(disp) = get_header(&part.headers, "Content-Disposition") {
+        if let Some(idx) = disp.find("filename=") {
+            let rest = &disp[idx + 9..];
+            let name = rest.trim_start_matches('"').split('"').next()
+                .or_else(|| rest.split(';').next())
+                .unwrap_or("attachment")
+                .trim();
+            if !name.is_empty() { return name.to_string(); }
+        }
+    }
+    // Try Content-Type name
+    if let Some(ct) = get_header(&part.headers, "Content-Type") {
+        if let Some(idx) = ct.find("name=") {
+            let rest = &ct[idx + 5..];
+            let name = rest.trim_start_matches('"').split('"').next()
+                .or_else(|| rest.split(';').next())
+                .unwrap_or("attachment")
+                .trim();
+            if !name.is_empty() { return name.to_string(); }
+        }
+    }
+    "attachment".to_string()
+}
+
+pub(crate) fn get_header(headers: &[mailparse::MailHeader], name: &str) -> Option<String> {
+    headers.iter()
+        .find(|h| h.get_key().eq_ignore_ascii_case(name))
+        .map(|h| h.get_value().trim().to_string())
+        .filter(|v| !v.is_empty())
+}
+
+fn parse_address(s: &str) -> EmailAddress {
+    // Simple parse: "Name <email>" or just "email"
+    if let Some(start) = s.find('<') {
+        if let Some(end) = s.find('>') {
+            let addr = s[start + 1..end].trim().to_string();
+            let name = s[..start].trim().trim_matches('"').to_string();
+            return EmailAddress {
+                address: addr,
+                name: if name.is_empty() { None } else { Some(name) },
+            };
+        }
+    }
+    EmailAddress { address: s.trim().to_string(), name: None }
+}
+
+fn parse_address_list(s: &str) -> Vec<EmailAddress> {
+    s.split(',').map(|part| parse_address(part.trim())).collect()
+}
+
+fn extract_snippet(parsed: &mailparse::ParsedMail) -> Option<String> {
+    // Try text body first
+    if let Ok(body) = parsed.get_body() {
+        let clean: String = body.chars().take(200).collect();
+        let trimmed = clean.split_whitespace().collect::<Vec<_>>().join(" ");
+        if !trimmed.is_empty() {
+            return Some(trimmed);
+        }
+    }
+    // Try subparts
+    for part in &parsed.subparts {
+        if let Some(ct) = part.headers.iter().find(|h| h.get_key().eq_ignore_ascii_case("Content-Type")) {
+            let val = ct.get_value();
+            {
+                if val.starts_with("text/plain") {
+                    if let Ok(body) = part.get_body() {
+                        let clean: String = body.chars().take(200).collect();
+                        let trimmed = clean.split_whitespace().collect::<Vec<_>>().join(" ");
+                        if !trimmed.is_empty() {
+                            return Some(trimmed);
+                        }
+                    }
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Flags from a message filename in either layout. `keywords` resolves the
+/// lowercase letters of a standard name; vault names spell their flags out.
+pub(crate) fn flags_of(name: &str, keywords: &standard::Keywords) -> Vec<String> {
+    if vault_uid(name).is_some() {
+        extract_flags_from_filename(name)
+    } else {
+        standard::flags_of(name, keywords)
+    }
+}
+
+/// Flags of the message file at `path`, in either layout.
+pub fn message_flags(path: &Path) -> Vec<String> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+    match path.parent().and_then(Path::parent) {
+        Some(mailbox_dir) if vault_uid(&name).is_none() => flags_of(&name, &standard::Keywords::load(mailbox_dir)),
+        _ => extract_flags_from_filename(&name),
+    }
+}
+
+/// Every mailbox under the Maildir root — any directory holding a `cur/`,
+/// outside `orphaned/` and `quarantine/`. Doesn't list the message
+/// directories themselves, so it costs a few stats per mailbox.
+pub(crate) fn mailbox_dirs(maildir_root: &Path) -> Vec<PathBuf> {
+    let is_mailbox = |dir: &Path| dir.join("cur").is_dir();
+    walkdir::WalkDir::new(maildir_root)
+        .min_depth(2)
+        .into_iter()
+        .filter_entry(|e| {
+            let name = e.file_name();
+            if name == ORPHAN_DIR || name == QUARANTINE_DIR || name == "cur" {
+                return false;
+            }
+            // A mailbox's `new/` and `tmp/`, unless a nested mailbox took the name.
+            let message_dir = (name == "new" || name == "tmp") && e.path().parent().is_some_and(is_mailbox);
+            !message_dir || is_mailbox(e.path())
+        })
+        .flatten()
+        .filter(|e| e.file_type().is_dir() && is_mailbox(e.path()))
+        .map(|e| e.into_path())
+        .collect()
+}
+
+/// Every `(account, mailbox)` with a directory in the vault.
+pub fn mailbox_keys(data_dir: &Path) -> Vec<(String, String)> {
+    let maildir_root = data_dir.join("Maildir");
+    mailbox_dirs(&maildir_root).iter().filter_map(|dir| mailbox_key(&maildir_root, dir)).collect()
+}
+
+/// The account and mailbox a mailbox directory under the Maildir root holds.
+pub(crate) fn mailbox_key(maildir_root: &Path, mailbox_dir: &Path) -> Option<(String, String)> {
+    let rel = mailbox_dir.strip_prefix(maildir_root).ok()?;
+    let mut parts = rel.iter().map(|p| p.to_string_lossy().to_string());
+    let account_id = parts.next()?;
+    Some((account_id, parts.collect::<Vec<_>>().join("/")))
+}
+
+/// The uid of the message file at `path`, in either layout.
+pub fn uid_of_file(path: &Path) -> Option<u32> {
+    let name = path.file_name()?.to_string_lossy().to_string();
+    if let Some(uid) = vault_uid(&name) {
+        return Some(uid);
+    }
+    let mailbox_dir = path.parent()?.parent()?;
+    standard::Uidlist::read(mailbox_dir)?.uids.get(standard::base_of(&name)).copied()
+}
+
+// ── Layout conversion ───────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Default, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LayoutConversion {
+    pub layout: Layout,
+    pub mailboxes: u64,
+    pub renamed: u64,
+    pub errors: u64,
+}
+
+/// Move one mailbox into `layout`. Safe to re-run: a conversion cut short
+/// leaves every file readable, and the next run picks up where it stopped.
+/// Returns the number of files renamed.
+pub fn convert_mailbox(mailbox_dir: &Path, layout: Layout) -> Result<u64, String> {
+    let renamed = match layout {
+        Layout::Standard => {
+            // Other mail programs can't read compressed or sealed files.
+            let mut report = compression::CompressionConversion::default();
+            compression::convert_mailbox(mailbox_dir, Compression::None, &encryption::Sealer::plain(), None, &mut report);
+            if report.errors > 0 {
+                return Err(format!("{} files could not be decompressed", report.errors));
+            }
+            standard::to_standard(mailbox_dir)?
+        }
+        Layout::Vault => standard::to_vault(mailbox_dir)?,
+    };
+    // Every name changed; a rescan is cheaper than patching each entry.
+    crate::uid_index::invalidate(&mailbox_dir.join("cur"));
+    Ok(renamed)
+}
+
+/// Switch the whole vault to `layout`: new mailboxes are created in it from
+/// now on, and every existing one — any directory holding a `cur/`, outside
+/// `orphaned/` and `quarantine/` — is converted.
+pub fn convert_vault(data_dir: &Path, layout: Layout) -> Result<LayoutConversion, String> {
+    let root = data_dir.join("Maildir");
+    if layout == Layout::Standard && encryption::is_encrypted(data_dir) {
+        return Err("Other mail programs can't read an encrypted vault — turn encryption off first".to_string());
+    }
+    standard::set_default_layout(&root, layout)?;
+    let mut report = LayoutConversion { layout, ..Default::default() };
+    for mailbox_dir in mailbox_dirs(&root) {
+        report.mailboxes += 1;
+        match convert_mailbox(&mailbox_dir, layout) {
+            Ok(n) => report.renamed += n,
+            Err(e) => {
+                warn!("convert_vault: {:?}: {}", mailbox_dir, e);
+                report.errors += 1;
+            }
+        }
+    }
+    info!(
+        "convert_vault: {} — {} mailboxes, {} files renamed, {} errors",
+        layout.as_str(), report.mailboxes, report.renamed, report.errors,
+    );
+    Ok(report)
+}
+
+// ── Vault generation (UIDVALIDITY) ──────────────────────────────────────────
+//
+// The vault is keyed (account_id, mailbox, uid) and, until this file existed,
+// recorded nothing about *which* UID space that uid came from. A mailbox's UID
+// space is only meaningful within one UIDVALIDITY generation: when the server
+// reissues it — a change-server migration, or a reissue the server does on its
+// own — every uid the vault holds names a different message, or no message at
+// all. The read still lands on a real file, so nothing errors: `find_by_uid`
+// hands back a message that was archived under that number by the *previous*
+// server, and every caller that asks "is uid N archived?" gets a yes about
+// some other message.
+//
+// `.uidvalidity` (a sibling of `cur/`, alongside `local-index.json`) records
+// the generation the files in `cur/` are keyed under. When it disagrees with
+// the server's current UIDVALIDITY, `repair_generation` re-binds what it can
+// by Message-ID and moves the rest out of the uid namespace into `orphaned/`.
+//
+// Nothing here deletes mail. A message that isn't on the new server is exactly
+// the message the vault is *for*; it moves to `orphaned/` (still on disk,
+// still exportable) rather than being destroyed to reclaim space.
+
+use std::collections::{HashMap, HashSet};
+use std::io::Read;
+
+pub const GENERATION_FILE: &str = ".uidvalidity";
+pub const ORPHAN_DIR: &str = "orphaned";
+
+/// Two-phase rename marker. A rebind can target a uid that an as-yet-unvisited
+/// file still occupies, so every rebind lands on `{final}.regen` first and the
+/// whole set is un-suffixed once `cur/` holds no old-generation names.
+const REGEN_SUFFIX: &str = ".regen";
+
+#[derive(Debug, Clone, Default, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GenerationRepair {
+    /// False when the stamp already matched — the hot path, one small file read.
+    pub ran: bool,
+    /// Files re-keyed to the uid the current generation gives their EMAILID
+    /// or Message-ID.
+    pub rebound: Vec<(u32, u32)>,
+    /// How many files were placed by EMAILID rather than Message-ID.
+    pub by_email_id: u32,
+    /// Old uids moved to `orphaned/` — no Message-ID, or none the server has.
+    pub orphaned: Vec<u32>,
+    /// Files an earlier repair set aside that this one could bind after all.
+    pub recovered: Vec<u32>,
+    /// Files whose Message-ID still resolves to the uid they already had, plus
+    /// the locally-created ones that were never the server's to renumber.
+    pub kept: u32,
+    pub errors: u32,
+    /// The generation now recorded for this mailbox.
+    pub generation: u32,
+}
+
+/// Strip the angle brackets and surrounding space so both sides of the join
+/// agree. `parse_header` keeps `<...>`; a sidecar written by the frontend may
+/// not, and neither side is worth rewriting for this.
+pub fn normalize_message_id(raw: &str) -> String {
+    raw.trim().trim_start_matches('<').trim_end_matches('>').trim().to_string()
+}
+
+/// The header section of an RFC 5322 message — everything before the first
+/// blank line, or the whole slice when there isn't one.
+fn header_section(bytes: &[u8]) -> &[u8] {
+    let end = bytes
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .map(|i| i + 2)
+        .or_else(|| bytes.windows(2).position(|w| w == b"\n\n").map(|i| i + 1))
+        .unwrap_or(bytes.len());
+    &bytes[..end]
+}
+
+/// The header section of an `.eml`, read without the body. Reads at most
+/// 128 KiB.
+pub(crate) fn read_head(path: &Path) -> Option<Vec<u8>> {
+    const LIMIT: usize = 128 * 1024;
+    let mut file = compression::open_message(path).ok()?;
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 8 * 1024];
+    while buf.len() < LIMIT {
+        let n = file.read(&mut chunk).ok()?;
+        if n == 0 {
+            break;
+        }
+        buf.extend_from_slice(&chunk[..n]);
+        if header_section(&buf).len() < buf.len() {
+            break;
+        }
+    }
+    buf.truncate(LIMIT);
+    let len = header_section(&buf).len();
+    buf.truncate(len);
+    Some(buf)
+}
+
+/// Read just the Message-ID of an `.eml`, without parsing the message.
+///
+/// Stops at the end of the headers (see `read_head`): this runs once per
+/// vault file during a repair or a uid index rebuild, and a full
+/// `parse_header` (addresses, snippet extraction, MIME walk) over a
+/// 14k-message mailbox is minutes of work to answer one question.
+pub fn read_message_id(path: &Path) -> Option<String> {
+    let head = read_head(path)?;
+    let text = String::from_utf8_lossy(&head);
+
+    let mut value: Option<String> = None;
+    for line in text.split('\n') {
+        let line = line.trim_end_matches('\r');
+        if value.is_some() {
+            // Folded continuation — a header line that starts with WSP.
+            if line.starts_with(' ') || line.starts_with('\t') {
+                value.as_mut().unwrap().push_str(line.trim());
+                continue;
+            }
+            break;
+        }
+        const KEY: &str = "message-id:";
+        // `get`, not a slice: a header line can start mid-way through a
+        // multi-byte character once the bytes go through `from_utf8_lossy`, and
+        // slicing off a non-boundary panics.
+        if matches!(line.get(..KEY.len()), Some(head) if head.eq_ignore_ascii_case(KEY)) {
+            value = Some(line[KEY.len()..].trim().to_string());
+        }
+    }
+
+    let v = value?;
+    let inner = match (v.find('<'), v.rfind('>')) {
+        (Some(a), Some(b)) if b > a => &v[a + 1..b],
+        _ => v.trim(),
+    };
+    let id = normalize_message_id(inner);
+    if id.is_empty() { None } else { Some(id) }
+}
+
+/// The generation the files in this mailbox's `cur/` are keyed under, if it was
+/// ever recorded. `None` means a vault written before this stamp existed — the
+/// caller must verify rather than assume, since that is exactly the vault a
+/// reissue may already have silently invalidated.
+pub fn read_generation(mailbox_dir: &Path) -> Option<u32> {
+    fs::read_to_string(mailbox_dir.join(GENERATION_FILE))
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+pub fn write_generation(mailbox_dir: &Path, uid_validity: u32) -> Result<(), String> {
+    fs::create_dir_all(mailbox_dir).map_err(|e| format!("Failed to create mailbox dir: {}", e))?;
+    fs::write(mailbox_dir.join(GENERATION_FILE), uid_validity.to_string())
+        .map_err(|e| format!("Failed to write {}: {}", GENERATION_FILE, e))
+}
+
+/// Swap the uid on a Maildir filename, leaving the rest — flags, timestamp,
+/// `.eml` — exactly as it was. Both shipped filename formats (`{uid}:2,{flags}`
+/// and `{uid}:{flags}:{ts}`) put the uid first and a `:` right after it.
+fn with_uid(name: &str, new_uid: u32) -> String {
+    match name.find(':') {
+        Some(i) => format!("{}{}", new_uid, &name[i..]),
+        None => new_uid.to_string(),
+    }
+}
+
+/// Undo `free_orphan_path`'s dedupe suffix, so a recovered file goes back with
+/// the `.eml` extension the OS and the zip export need.
+fn strip_orphan_suffix(name: &str) -> String {
+    match name.rsplit_once('.') {
+        Some((head, tail)) if !tail.is_empty() && tail.chars().all(|c| c.is_ascii_digit()) => head.to_string(),
+        _ => name.to_string(),
+    }
+}
+
+/// A free name in `orphaned/` — repeated repairs can orphan the same uid twice.
+fn free_orphan_path(orphan_dir: &Path, name: &str) -> PathBuf {
+    let direct = orphan_dir.join(name);
+    if !direct.exists() {
+        return direct;
+    }
+    for n in 1..1000 {
+        let candidate = orphan_dir.join(format!("{}.{}", name, n));
+        if !candidate.exists() {
+            return candidate;
+        }
+    }
+    orphan_dir.join(format!("{}.dup", name))
+}
+
+/// Re-key a mailbox's vault files onto the current UID generation.
+///
+/// `id_to_uid` maps normalized Message-ID → the uid the *current* generation
+/// gives it. It must cover the whole mailbox: a hit is proof a file belongs at
+/// that uid, but a miss is only proof of absence if the map was complete to
+/// begin with. The caller owns that check — pass a partial map and every
+/// unlisted message reads as gone from the server.
+///
+/// `email_id_to_uid` does the same by EMAILID, on servers with OBJECTID. It is
+/// tried first, against the EMAILID the uid index noted for each file: it
+/// names exactly one message, where a Message-ID can be missing or shared by
+/// duplicates. Empty when the server doesn't have OBJECTID.
+///
+/// `protected` holds uids the server never issued: messages composed here that
+/// live only in the vault. A UID reissue says nothing about them, so they keep
+/// their uid and are never moved aside for missing from a server they were
+/// never on.
+///
+/// No-ops when the recorded generation already matches, so this is cheap to
+/// call on every mailbox load.
+pub fn repair_generation(
+    mailbox_dir: &Path,
+    current_uid_validity: u32,
+    id_to_uid: &HashMap<String, u32>,
+    email_id_to_uid: &HashMap<String, u32>,
+    protected: &HashSet<u32>,
+) -> GenerationRepair {
+    if read_generation(mailbox_dir) == Some(current_uid_validity) {
+        return GenerationRepair { generation: current_uid_validity, ..Default::default() };
+    }
+    // Read before anything renames a file: the ids are keyed by the old uids.
+    let noted: HashMap<u32, (String, Option<String>)> = if email_id_to_uid.is_empty() {
+        HashMap::new()
+    } else {
+        crate::uid_index::entries(&mailbox_dir.join("cur"))
+            .into_iter()
+            .filter_map(|(uid, e)| Some((uid, (e.email_id?, e.thread_id))))
+            .collect()
+    };
+    let keys = RepairKeys { id_to_uid, email_id_to_uid, noted: &noted };
+    if !standard::is_standard(mailbox_dir) {
+        return repair_vault_layout(mailbox_dir, current_uid_validity, &keys, protected);
+    }
+    // A standard name doesn't carry its uid, so there is nothing to re-key in
+    // place. Re-key in the vault layout, where the names do, and convert back:
+    // the rewritten uidlist then takes the new generation as its UIDVALIDITY.
+    if let Err(e) = convert_mailbox(mailbox_dir, Layout::Vault) {
+        warn!("repair_generation: {}", e);
+        return GenerationRepair { generation: current_uid_validity, ran: true, errors: 1, ..Default::default() };
+    }
+    let mut report = repair_vault_layout(mailbox_dir, current_uid_validity, &keys, protected);
+    if let Err(e) = convert_mailbox(mailbox_dir, Layout::Standard) {
+        warn!("repair_generation: {}", e);
+        report.errors += 1;
+    }
+    report
+}
+
+/// What a repair places files by: the current generation's uid for each
+/// Message-ID and EMAILID, and the EMAILIDs noted under the old uids.
+struct RepairKeys<'a> {
+    id_to_uid: &'a HashMap<String, u32>,
+    email_id_to_uid: &'a HashMap<String, u32>,
+    noted: &'a HashMap<u32, (String, Option<String>)>,
+}
+
+fn repair_vault_layout(
+    mailbox_dir: &Path,
+    current_uid_validity: u32,
+    keys: &RepairKeys,
+    protected: &HashSet<u32>,
+) -> GenerationRepair {
+    let mut report = GenerationRepair { generation: current_uid_validity, ..Default::default() };
+
+    if read_generation(mailbox_dir) == Some(current_uid_validity) {
+        return report;
+    }
+    report.ran = true;
+
+    let cur = mailbox_dir.join("cur");
+    if !cur.exists() {
+        if let Err(e) = write_generation(mailbox_dir, current_uid_validity) {
+            warn!("repair_generation: {}", e);
+            report.errors += 1;
+        }
+        return report;
+    }
+
+    // ── Plan ──
+    // A uid is claimed by the first file that resolves to it. Two vault files
+    // can carry the same Message-ID (a duplicate archive); only one of them can
+    // hold the uid, and the loser is orphaned rather than silently overwriting.
+    // Locally-created uids are reserved before anything else can claim them:
+    // their files stay where they are, so a rebind must not be handed the same
+    // number.
+    //
+    // Files an EMAILID places claim first: the EMAILID is the message itself,
+    // so where it and a duplicate's Message-ID want the same uid, it wins.
+    let mut claimed: HashSet<u32> = protected.clone();
+    let mut found: Vec<(PathBuf, String, u32, Option<u32>)> = Vec::new();
+
+    let entries = match fs::read_dir(&cur) {
+        Ok(e) => e,
+        Err(e) => {
+            warn!("repair_generation: read {:?} failed: {}", cur, e);
+            report.errors += 1;
+            return report;
+        }
+    };
+    for entry in entries.flatten() {
+        if !entry.file_type().map(|t| t.is_file()).unwrap_or(false) {
+            continue;
+        }
+        let name = entry.file_name().to_string_lossy().to_string();
+        // Same heuristic the `.eml` migration uses: a message filename starts
+        // with `{uid}:`. Anything else in here is not ours to move.
+        let old_uid: u32 = match name.split(':').next().and_then(|s| s.parse().ok()) {
+            Some(u) => u,
+            None => continue,
+        };
+        if protected.contains(&old_uid) {
+            report.kept += 1;
+            continue;
+        }
+        let by_email_id = keys
+            .noted
+            .get(&old_uid)
+            .and_then(|(email_id, _)| keys.email_id_to_uid.get(email_id).copied())
+            .filter(|u| claimed.insert(*u));
+        if by_email_id.is_some() {
+            report.by_email_id += 1;
+        }
+        found.push((entry.path(), name, old_uid, by_email_id));
+    }
+    let mut plan: Vec<(PathBuf, String, u32, Option<u32>)> = Vec::with_capacity(found.len());
+    for (path, name, old_uid, by_email_id) in found {
+        let new_uid = by_email_id.or_else(|| {
+            read_message_id(&path)
+                .and_then(|id| keys.id_to_uid.get(&id).copied())
+                .filter(|u| claimed.insert(*u))
+        });
+        plan.push((path, name, old_uid, new_uid));
+    }
+
+    // ── Plan: files an earlier repair set aside get another chance ──
+    //
+    // "The server does not have this" is only ever as good as the cache that
+    // said so. A later generation, read against a fuller cache, can find the
+    // message after all — so `orphaned/` is a holding area the repair reads
+    // back, not a one-way door. Without this, one repair run against a cache
+    // that was complete-looking but stale would set a message aside for good.
+    let orphan_dir = mailbox_dir.join(ORPHAN_DIR);
+    let mut recover: Vec<(PathBuf, String, u32)> = Vec::new();
+    if let Ok(entries) = fs::read_dir(&orphan_dir) {
+        for entry in entries.flatten() {
+            if !entry.file_type().map(|t| t.is_file()).unwrap_or(false) {
+                continue;
+            }
+            let name = strip_orphan_suffix(&entry.file_name().to_string_lossy());
+            if name.split(':').next().and_then(|s| s.parse::<u32>().ok()).is_none() {
+                continue;
+            }
+            if let Some(nu) = read_message_id(&entry.path())
+                .and_then(|id| keys.id_to_uid.get(&id).copied())
+                .filter(|u| claimed.insert(*u))
+            {
+                recover.push((entry.path(), name, nu));
+            }
+        }
+    }
+
+    // ── Apply, phase 1: every file leaves its old-generation name ──
+    let mut staged: Vec<(PathBuf, PathBuf)> = Vec::new();
+    let mut placed: Vec<(u32, u32)> = Vec::new();
+    for (path, name, new_uid) in recover {
+        let final_name = with_uid(&name, new_uid);
+        let tmp = cur.join(format!("{}{}", final_name, REGEN_SUFFIX));
+        match fs::rename(&path, &tmp) {
+            Ok(()) => {
+                staged.push((tmp, cur.join(final_name)));
+                report.recovered.push(new_uid);
+            }
+            Err(e) => {
+                warn!("repair_generation: recover {:?} failed: {}", path, e);
+                report.errors += 1;
+            }
+        }
+    }
+    for (path, name, old_uid, new_uid) in plan {
+        match new_uid {
+            Some(nu) => {
+                let final_name = with_uid(&name, nu);
+                let tmp = cur.join(format!("{}{}", final_name, REGEN_SUFFIX));
+                match fs::rename(&path, &tmp) {
+                    Ok(()) => {
+                        staged.push((tmp, cur.join(final_name)));
+                        placed.push((old_uid, nu));
+                        if nu == old_uid {
+                            report.kept += 1;
+                        } else {
+                            report.rebound.push((old_uid, nu));
+                        }
+                    }
+                    Err(e) => {
+                        warn!("repair_generation: rebind {:?} failed: {}", path, e);
+                        report.errors += 1;
+                    }
+                }
+            }
+            None => {
+                if let Err(e) = fs::create_dir_all(&orphan_dir) {
+                    warn!("repair_generation: mkdir {:?} failed: {}", orphan_dir, e);
+                    report.errors += 1;
+                    continue;
+                }
+                let dst = free_orphan_path(&orphan_dir, &name);
+                match fs::rename(&path, &dst) {
+                    Ok(()) => report.orphaned.push(old_uid),
+                    Err(e) => {
+                        warn!("repair_generation: orphan {:?} failed: {}", path, e);
+                        report.errors += 1;
+                    }
+                }
+            }
+        }
+    }
+
+    // ── Apply, phase 2: `cur/` holds no old-generation names, so the final
+    // names are free ──
+    for (tmp, final_path) in staged {
+        if let Err(e) = fs::rename(&tmp, &final_path) {
+            warn!("repair_generation: unstage {:?} failed: {}", tmp, e);
+            report.errors += 1;
+        }
+    }
+    // Every uid may have moved; a rescan is cheaper than patching each entry.
+    crate::uid_index::invalidate(&cur);
+    // ...but the EMAILIDs aren't in the files, so they go back by hand.
+    let renoted: Vec<(u32, String, Option<String>)> = placed
+        .iter()
+        .filter_map(|(old, new)| keys.noted.get(old).map(|(e, t)| (*new, e.clone(), t.clone())))
+        .collect();
+    if !renoted.is_empty() {
+        crate::uid_index::note_object_ids(&cur, &renoted);
+    }
+
+    if let Err(e) = write_generation(mailbox_dir, current_uid_validity) {
+        warn!("repair_generation: {}", e);
+        report.errors += 1;
+    }
+    if let Err(e) = manifest::remap(mailbox_dir, &report.rebound, &report.orphaned) {
+        warn!("repair_generation: {}", e);
+        report.errors += 1;
+    }
+
+    info!(
+        "repair_generation: {:?} → UIDVALIDITY {} — {} rebound ({} by EMAILID), {} recovered, {} kept, {} orphaned, {} errors",
+        mailbox_dir, current_uid_validity, report.rebound.len(), report.by_email_id, report.recovered.len(),
+        report.kept, report.orphaned.len(), report.errors,
+    );
+    report
+}
+
+#[derive(Debug, Clone, Default, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OrphanStats {
+    pub count: u64,
+    pub bytes: u64,
+}
+
+/// Count what a mailbox's repair moved out of the uid namespace.
+pub fn orphan_stats(mailbox_dir: &Path) -> OrphanStats {
+    let mut stats = OrphanStats::default();
+    if let Ok(entries) = fs::read_dir(mailbox_dir.join(ORPHAN_DIR)) {
+        for entry in entries.flatten() {
+            if entry.file_type().map(|t| t.is_file()).unwrap_or(false) {
+                stats.count += 1;
+                stats.bytes += entry.metadata().map(|m| m.len()).unwrap_or(0);
+            }
+        }
+    }
+    stats
+}
+
+/// Delete a mailbox's orphan folder. Only ever called for a user who asked —
+/// these are messages the current server does not have, so this is the one
+/// place in the vault where deleting can lose the last copy.
+pub fn purge_orphans(mailbox_dir: &Path) -> Result<u64, String> {
+    let dir = mailbox_dir.join(ORPHAN_DIR);
+    if !dir.exists() {
+        return Ok(0);
+    }
+    let removed = orphan_stats(mailbox_dir).count;
+    fs::remove_dir_all(&dir).map_err(|e| format!("Failed to remove {:?}: {}", dir, e))?;
+    info!("purge_orphans: removed {} files from {:?}", removed, dir);
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_filename() {
+        let name = build_filename(12345, &["\\Seen".into(), "\\Flagged".into()]);
+        assert!(name.starts_with("12345:"));
+        assert!(name.contains("seen"));
+        assert!(name.contains("flagged"));
+    }
+
+    #[test]
+    fn test_list_uids_empty() {
+        let dir = std::env::temp_dir().join("mailvault-test-core-list");
+        let uids = list_uids(&dir, "nonexistent", "INBOX");
+        assert!(uids.is_empty());
+    }
+
+    #[test]
+    fn test_store_and_read() {
+        let dir = std::env::temp_dir().join("mailvault-test-core-store");
+        let _ = fs::remove_dir_all(&dir);
+
+        let raw = b"From: test@example.com\r\nSubject: Hello\r\n\r\nBody text";
+        store(&dir, "acc1", "INBOX", 42, raw, &["\\Seen".into()]).unwrap();
+
+        let uids = list_uids(&dir, "acc1", "INBOX");
+        assert_eq!(uids, vec![42]);
+
+        let read_back = read_raw(&dir, "acc1", "INBOX", 42).unwrap();
+        assert_eq!(read_back, raw);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_parse_header() {
+        let raw = b"From: Alice <alice@example.com>\r\nTo: Bob <bob@test.com>\r\nSubject: Hello World\r\nDate: Mon, 1 Apr 2026 10:00:00 +0000\r\nMessage-ID: <msg1@example.com>\r\n\r\nBody here";
+        let header = parse_header(raw).unwrap();
+        assert_eq!(header.subject, "Hello World");
+        assert_eq!(header.from.as_ref().unwrap().address, "alice@example.com");
+        assert_eq!(header.message_id, Some("<msg1@example.com>".into()));
+    }
+
+    #[test]
+    fn test_delete() {
+        let dir = std::env::temp_dir().join("mailvault-test-core-delete");
+        let _ = fs::remove_dir_all(&dir);
+
+        let raw = b"Subject: Delete me\r\n\r\n";
+        store(&dir, "acc1", "INBOX", 99, raw, &[]).unwrap();
+        assert_eq!(list_uids(&dir, "acc1", "INBOX").len(), 1);
+
+        delete(&dir, "acc1", "INBOX", 99).unwrap();
+        assert!(list_uids(&dir, "acc1", "INBOX").is_empty());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_migrate_decode_mailbox_names() {
+        let dir = std::env::temp_dir().join("mailvault-test-core-utf7-names");
+        let _ = fs::remove_dir_all(&dir);
+
+        let raw = b"Subject: Hi\r\n\r\n";
+        store(&dir, "acc1", "&BB4EQgQ,BEAEMAQyBDsENQQ9BD0ESwQ1-", 1, raw, &[]).unwrap();
+        store(&dir, "acc1", "~peter/mail/&U,BTFw-/&ZeVnLIqe-", 2, raw, &[]).unwrap();
+        store(&dir, "acc1", "Entw&APw-rfe", 3, raw, &[]).unwrap();
+        store(&dir, "acc1", "Entwürfe", 4, raw, &[]).unwrap();
+        store(&dir, "acc1", "INBOX", 5, raw, &[]).unwrap();
+
+        let stats = migrate_decode_mailbox_names(&dir);
+        assert_eq!(stats.renamed, 3);
+        assert_eq!(stats.errors, 1, "Entwürfe already existed");
+        assert_eq!(list_uids(&dir, "acc1", "Отправленные"), vec![1]);
+        assert_eq!(list_uids(&dir, "acc1", "~peter/mail/台北/日本語"), vec![2]);
+        assert_eq!(list_uids(&dir, "acc1", "Entw&APw-rfe"), vec![3]);
+        assert_eq!(list_uids(&dir, "acc1", "Entwürfe"), vec![4]);
+        assert_eq!(list_uids(&dir, "acc1", "INBOX"), vec![5]);
+
+        store(&dir, "acc1", "&U,BTFw-", 6, raw, &[]).unwrap();
+        assert_eq!(migrate_decode_mailbox_names(&dir).renamed, 0, "runs once per vault");
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_build_filename_has_eml_extension() {
+        let name = build_filename(7, &["\\Seen".into()]);
+        assert!(name.ends_with(".eml"), "got {}", name);
+    }
+
+    #[test]
+    fn test_migrate_add_eml_extension_renames_and_is_idempotent() {
+        let dir = std::env::temp_dir().join("mailvault-test-migrate-eml");
+        let _ = fs::remove_dir_all(&dir);
+
+        let cur = dir.join("Maildir").join("acc1").join("INBOX").join("cur");
+        fs::create_dir_all(&cur).unwrap();
+        // Pre-migration files (no `.eml`) in both filename formats we ship.
+        fs::write(cur.join("101:2,S"), b"A").unwrap();
+        fs::write(cur.join("102:seen:1700000000"), b"B").unwrap();
+        // Already-migrated sibling — must be left alone.
+        fs::write(cur.join("103:2,S.eml"), b"C").unwrap();
+        // Non-message file — must be left alone.
+        fs::write(cur.join("local-index.json"), b"{}").unwrap();
+
+        let s1 = migrate_add_eml_extension(&dir);
+        assert_eq!(s1.renamed, 2);
+        assert_eq!(s1.already_ok, 1);
+        assert_eq!(s1.skipped_non_message, 1);
+        assert_eq!(s1.errors, 0);
+        assert!(cur.join("101:2,S.eml").exists());
+        assert!(cur.join("102:seen:1700000000.eml").exists());
+        assert!(cur.join("103:2,S.eml").exists());
+        assert!(cur.join("local-index.json").exists());
+
+        // Second run — version marker must short-circuit it.
+        let s2 = migrate_add_eml_extension(&dir);
+        assert_eq!(s2.renamed, 0);
+        assert_eq!(s2.already_ok, 0);
+
+        // Readers still resolve by UID prefix after migration.
+        assert!(find_by_uid(&cur, 101).is_some());
+        assert!(find_by_uid(&cur, 102).is_some());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_parse_address() {
+        let addr = parse_address("Alice Smith <alice@example.com>");
+        assert_eq!(addr.address, "alice@example.com");
+        assert_eq!(addr.name, Some("Alice Smith".into()));
+
+        let addr2 = parse_address("bob@test.com");
+        assert_eq!(addr2.address, "bob@test.com");
+        assert!(addr2.name.is_none());
+    }
+
+    // ── Vault generation (UIDVALIDITY) ──────────────────────────────────────
+
+    fn eml(message_id: &str, body: &str) -> Vec<u8> {
+        format!(
+            "From: a@b.test\r\nTo: c@d.test\r\nSubject: s\r\nMessage-ID: <{}>\r\nDate: Mon, 1 Apr 2026 10:00:00 +0000\r\n\r\n{}",
+            message_id, body
+        ).into_bytes()
+    }
+
+    #[test]
+    fn test_read_message_id() {
+        let dir = std::env::temp_dir().join("mailvault-test-read-msgid");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        fs::write(dir.join("plain.eml"), eml("m1@host.test", "body")).unwrap();
+        assert_eq!(read_message_id(&dir.join("plain.eml")), Some("m1@host.test".into()));
+
+        // Folded across two lines, and the header name in a casing no server uses.
+        fs::write(
+            dir.join("folded.eml"),
+            b"Subject: s\r\nMESSAGE-id:\r\n <folded@host.test>\r\n\r\nbody".to_vec(),
+        ).unwrap();
+        assert_eq!(read_message_id(&dir.join("folded.eml")), Some("folded@host.test".into()));
+
+        // LF-only line endings — plenty of .eml files on disk have them.
+        fs::write(dir.join("lf.eml"), b"Message-ID: <lf@host.test>\n\nbody".to_vec()).unwrap();
+        assert_eq!(read_message_id(&dir.join("lf.eml")), Some("lf@host.test".into()));
+
+        // A Message-ID-less message is the case the read-time guard cannot
+        // catch, so it must come back None and be treated as unbindable.
+        fs::write(dir.join("none.eml"), b"Subject: s\r\n\r\nbody".to_vec()).unwrap();
+        assert_eq!(read_message_id(&dir.join("none.eml")), None);
+
+        // A header line whose first bytes are multi-byte UTF-8. Slicing the
+        // prefix off this panics; `get` returns None and moves on.
+        fs::write(
+            dir.join("utf8.eml"),
+            "Subject: \u{4f60}\u{597d}\u{4e16}\u{754c}\r\nMessage-ID: <utf8@host.test>\r\n\r\nbody".as_bytes().to_vec(),
+        ).unwrap();
+        assert_eq!(read_message_id(&dir.join("utf8.eml")), Some("utf8@host.test".into()));
+
+        // The body must not be searched — a quoted reply carries the parent's
+        // Message-ID and would bind the file to the wrong message.
+        fs::write(
+            dir.join("bodyid.eml"),
+            b"Subject: s\r\n\r\nOn Mon someone wrote:\r\nMessage-ID: <quoted@host.test>\r\n".to_vec(),
+        ).unwrap();
+        assert_eq!(read_message_id(&dir.join("bodyid.eml")), None);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_repair_generation_rebinds_orphans_and_stamps() {
+        let dir = std::env::temp_dir().join("mailvault-test-repair-gen");
+        let _ = fs::remove_dir_all(&dir);
+        let mailbox = dir.join("Maildir").join("acc1").join("INBOX");
+        let cur = mailbox.join("cur");
+        fs::create_dir_all(&cur).unwrap();
+
+        // uid 1 and uid 5 both need to move, and 1's new uid is 5 — the
+        // collision the two-phase rename exists for.
+        fs::write(cur.join("1:2,S.eml"), eml("moved@host.test", "one")).unwrap();
+        fs::write(cur.join("5:seen:1700000000.eml"), eml("stays@host.test", "five")).unwrap();
+        fs::write(cur.join("9:2,.eml"), eml("gone@host.test", "nine")).unwrap();
+        fs::write(cur.join("12:2,.eml"), b"Subject: no id\r\n\r\nbody".to_vec()).unwrap();
+        // Not a message — must be left exactly where it is.
+        fs::write(cur.join("notes.txt"), b"keep me").unwrap();
+
+        let id_to_uid: HashMap<String, u32> = [
+            ("moved@host.test".to_string(), 5u32),
+            ("stays@host.test".to_string(), 7u32),
+        ].into_iter().collect();
+
+        let r = repair_generation(&mailbox, 605297894, &id_to_uid, &HashMap::new(), &HashSet::new());
+        assert!(r.ran);
+        assert_eq!(r.errors, 0);
+        assert_eq!(r.generation, 605297894);
+        assert_eq!(r.rebound.len(), 2);
+        assert!(r.rebound.contains(&(1, 5)));
+        assert!(r.rebound.contains(&(5, 7)));
+        // No Message-ID and no match both mean "cannot prove this is that uid".
+        assert_eq!(r.orphaned.len(), 2);
+        assert!(r.orphaned.contains(&9));
+        assert!(r.orphaned.contains(&12));
+
+        // Flags and timestamp survive the re-key; only the uid changes.
+        assert!(cur.join("5:2,S.eml").exists());
+        assert!(cur.join("7:seen:1700000000.eml").exists());
+        assert!(!cur.join("1:2,S.eml").exists());
+        assert!(cur.join("notes.txt").exists());
+        // No half-renamed leftovers.
+        assert!(fs::read_dir(&cur).unwrap().flatten()
+            .all(|e| !e.file_name().to_string_lossy().ends_with(REGEN_SUFFIX)));
+
+        // The re-keyed file is the one that moved, not the one that was already there.
+        assert_eq!(read_message_id(&cur.join("5:2,S.eml")), Some("moved@host.test".into()));
+
+        assert_eq!(orphan_stats(&mailbox).count, 2);
+        assert_eq!(read_generation(&mailbox), Some(605297894));
+
+        // Second call with the same generation must not touch the mailbox again.
+        let r2 = repair_generation(&mailbox, 605297894, &id_to_uid, &HashMap::new(), &HashSet::new());
+        assert!(!r2.ran);
+        assert!(r2.rebound.is_empty());
+
+        assert_eq!(purge_orphans(&mailbox).unwrap(), 2);
+        assert_eq!(orphan_stats(&mailbox).count, 0);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_repair_generation_duplicate_message_id_keeps_one() {
+        let dir = std::env::temp_dir().join("mailvault-test-repair-dupe");
+        let _ = fs::remove_dir_all(&dir);
+        let mailbox = dir.join("Maildir").join("acc1").join("INBOX");
+        let cur = mailbox.join("cur");
+        fs::create_dir_all(&cur).unwrap();
+
+        fs::write(cur.join("1:2,.eml"), eml("dupe@host.test", "first")).unwrap();
+        fs::write(cur.join("2:2,.eml"), eml("dupe@host.test", "second")).unwrap();
+
+        let id_to_uid: HashMap<String, u32> =
+            [("dupe@host.test".to_string(), 4u32)].into_iter().collect();
+
+        let r = repair_generation(&mailbox, 2, &id_to_uid, &HashMap::new(), &HashSet::new());
+        // One uid, one file. The loser is kept, not overwritten.
+        assert_eq!(r.rebound.len(), 1);
+        assert_eq!(r.orphaned.len(), 1);
+        assert_eq!(r.errors, 0);
+        assert!(cur.join("4:2,.eml").exists());
+        assert_eq!(orphan_stats(&mailbox).count, 1);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_repair_generation_places_by_emailid_first() {
+        let dir = std::env::temp_dir().join("mailvault-test-repair-emailid");
+        let _ = fs::remove_dir_all(&dir);
+        let mailbox = dir.join("Maildir").join("acc1").join("INBOX");
+        let cur = mailbox.join("cur");
+        fs::create_dir_all(&cur).unwrap();
+
+        // Two copies sharing a Message-ID, and one with none at all.
+        fs::write(cur.join("1:2,.eml"), eml("dupe@host.test", "first")).unwrap();
+        fs::write(cur.join("2:2,.eml"), eml("dupe@host.test", "second")).unwrap();
+        fs::write(cur.join("3:2,.eml"), b"Subject: no id\r\n\r\nbody").unwrap();
+        let noted = [(1, "Ma".to_string(), None), (2, "Mb".to_string(), Some("T1".to_string())), (3, "Mc".to_string(), None)];
+        assert_eq!(crate::uid_index::note_object_ids(&cur, &noted), 3);
+
+        let id_to_uid: HashMap<String, u32> = [("dupe@host.test".to_string(), 10u32)].into_iter().collect();
+        let email_id_to_uid: HashMap<String, u32> =
+            [("Ma".to_string(), 11u32), ("Mb".to_string(), 10), ("Mc".to_string(), 12)].into_iter().collect();
+
+        let r = repair_generation(&mailbox, 5, &id_to_uid, &email_id_to_uid, &HashSet::new());
+        assert_eq!(r.by_email_id, 3);
+        assert!(r.orphaned.is_empty());
+        let mut rebound = r.rebound.clone();
+        rebound.sort();
+        assert_eq!(rebound, vec![(1, 11), (2, 10), (3, 12)]);
+        assert!(String::from_utf8_lossy(&fs::read(cur.join("10:2,.eml")).unwrap()).contains("second"));
+        // The ids moved with the files.
+        let e = crate::uid_index::entry(&cur, 10).unwrap();
+        assert_eq!((e.email_id.as_deref(), e.thread_id.as_deref()), (Some("Mb"), Some("T1")));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+
+
+    #[test]
+    fn test_repair_generation_recovers_an_orphan_a_later_cache_can_place() {
+        let dir = std::env::temp_dir().join("mailvault-test-repair-recover");
+        let _ = fs::remove_dir_all(&dir);
+        let mailbox = dir.join("Maildir").join("acc1").join("INBOX");
+        let cur = mailbox.join("cur");
+        fs::create_dir_all(&cur).unwrap();
+
+        fs::write(cur.join("1:2,S.eml"), eml("later@host.test", "one")).unwrap();
+
+        // Generation 2, read against a cache that did not know this message.
+        let r1 = repair_generation(&mailbox, 2, &HashMap::new(), &HashMap::new(), &HashSet::new());
+        assert_eq!(r1.orphaned, vec![1]);
+        assert_eq!(orphan_stats(&mailbox).count, 1);
+
+        // Generation 3, read against a cache that does. "Not on the server" was
+        // only ever as good as the cache that said it, so `orphaned/` has to be
+        // a holding area the repair reads back.
+        let id_to_uid: HashMap<String, u32> =
+            [("later@host.test".to_string(), 11u32)].into_iter().collect();
+        let r2 = repair_generation(&mailbox, 3, &id_to_uid, &HashMap::new(), &HashSet::new());
+        assert_eq!(r2.recovered, vec![11]);
+        assert_eq!(r2.errors, 0);
+        assert!(cur.join("11:2,S.eml").exists(), "recovered file keeps its .eml name");
+        assert_eq!(orphan_stats(&mailbox).count, 0);
+        assert_eq!(read_message_id(&cur.join("11:2,S.eml")), Some("later@host.test".into()));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_repair_generation_never_orphans_a_locally_created_message() {
+        let dir = std::env::temp_dir().join("mailvault-test-repair-protected");
+        let _ = fs::remove_dir_all(&dir);
+        let mailbox = dir.join("Maildir").join("acc1").join("Sent");
+        let cur = mailbox.join("cur");
+        fs::create_dir_all(&cur).unwrap();
+
+        // A message composed here and never accepted by a server. It is in no
+        // sidecar and never will be, so the Message-ID join can only miss it.
+        fs::write(cur.join("900:2,S.eml"), eml("composed-here@mailvault", "draft")).unwrap();
+        fs::write(cur.join("3:2,.eml"), eml("fromserver@host.test", "archived")).unwrap();
+
+        let id_to_uid: HashMap<String, u32> =
+            [("fromserver@host.test".to_string(), 900u32)].into_iter().collect();
+        let protected: HashSet<u32> = [900u32].into_iter().collect();
+
+        let r = repair_generation(&mailbox, 8, &id_to_uid, &HashMap::new(), &protected);
+
+        // The composed message keeps its uid and its place in the mailbox.
+        assert!(cur.join("900:2,S.eml").exists());
+        assert_eq!(read_message_id(&cur.join("900:2,S.eml")), Some("composed-here@mailvault".into()));
+        assert!(!r.orphaned.contains(&900));
+
+        // Its uid was reserved, so the server message that wanted 900 could not
+        // take it — and rather than overwrite, that file is set aside intact.
+        assert!(r.rebound.is_empty());
+        assert_eq!(r.orphaned, vec![3]);
+        assert_eq!(r.errors, 0);
+        assert_eq!(orphan_stats(&mailbox).count, 1);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_repair_generation_stamps_an_empty_mailbox_without_scanning() {
+        let dir = std::env::temp_dir().join("mailvault-test-repair-empty");
+        let _ = fs::remove_dir_all(&dir);
+        let mailbox = dir.join("Maildir").join("acc1").join("INBOX");
+        fs::create_dir_all(&mailbox).unwrap();
+
+        let r = repair_generation(&mailbox, 42, &HashMap::new(), &HashMap::new(), &HashSet::new());
+        assert!(r.ran);
+        assert_eq!(r.errors, 0);
+        assert_eq!(read_generation(&mailbox), Some(42));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    // ── Standard layout ─────────────────────────────────────────────────────
+
+    fn names_in(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .map(|d| d.flatten().map(|e| e.file_name().to_string_lossy().to_string()).collect())
+            .unwrap_or_default();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_standard_layout_store_flags_and_delete() {
+        let dir = std::env::temp_dir().join("mailvault-test-standard-layout");
+        let _ = fs::remove_dir_all(&dir);
+        standard::set_default_layout(&dir.join("Maildir"), Layout::Standard).unwrap();
+        let mailbox = dir.join("Maildir").join("acc1").join("INBOX");
+        let cur = mailbox.join("cur");
+
+        // Newest first, as a backfill stores them.
+        let unread = store(&dir, "acc1", "INBOX", 5, &eml("five@host.test", "five"), &[]).unwrap();
+        let read = store(&dir, "acc1", "INBOX", 3, &eml("three@host.test", "three"), &["\\Seen".into()]).unwrap();
+        assert!(standard::is_standard(&mailbox));
+        assert_eq!(unread.parent().unwrap(), mailbox.join("new"));
+        assert!(read.starts_with(&cur) && read.to_string_lossy().ends_with(":2,S"));
+        assert!(names_in(&mailbox.join("tmp")).is_empty());
+        assert_eq!(list_uids(&dir, "acc1", "INBOX"), vec![3, 5]);
+        assert_eq!(read_raw(&dir, "acc1", "INBOX", 5).unwrap(), eml("five@host.test", "five"));
+        assert_eq!(read_light(&dir, "acc1", "INBOX", 3).unwrap().flags, vec!["\\Seen".to_string()]);
+
+        // Flagging moves it to cur/ under the same base; keywords get a letter.
+        set_flags(&dir, "acc1", "INBOX", 5, &["\\Flagged".into(), "archived".into()]).unwrap();
+        let moved = find_by_uid(&cur, 5).unwrap();
+        let name = moved.file_name().unwrap().to_string_lossy().to_string();
+        assert_eq!(name, format!("{}:2,Fa", unread.file_name().unwrap().to_string_lossy()));
+        assert_eq!(fs::read_to_string(mailbox.join(standard::KEYWORDS_FILE)).unwrap(), "0 archived\n");
+        assert_eq!(read_full(&dir, "acc1", "INBOX", 5).unwrap().flags, vec!["\\Flagged", "archived"]);
+
+        // Another program's delivery has no uid and is left alone.
+        fs::write(mailbox.join("new").join("1.M1P1Q1.elsewhere"), eml("x@host.test", "x")).unwrap();
+        delete(&dir, "acc1", "INBOX", 3).unwrap();
+        assert_eq!(list_uids(&dir, "acc1", "INBOX"), vec![5]);
+
+        // The out-of-order append and the deleted line are tidied away.
+        crate::uid_index::flush();
+        let list = fs::read_to_string(mailbox.join(standard::UIDLIST_FILE)).unwrap();
+        let lines: Vec<&str> = list.lines().collect();
+        assert!(lines[0].starts_with("3 V") && lines[0].ends_with(" N6"));
+        assert_eq!(lines[1..], [format!("5 :{}", standard::base_of(&name))]);
+        assert_eq!(storage_stats(&dir, "acc1").total_emails, 2);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_remap_follows_the_server_uids() {
+        let dir = std::env::temp_dir().join("mailvault-test-remap");
+        let _ = fs::remove_dir_all(&dir);
+        store(&dir, "acc1", "INBOX", 5, &eml("five@host.test", "five"), &["\\Seen".into()]).unwrap();
+        store(&dir, "acc1", "INBOX", 6, &eml("six@host.test", "six"), &[]).unwrap();
+
+        // MOVE: the file is renamed into Archive, flags and all.
+        assert_eq!(remap(&dir, "acc1", "INBOX", "Archive", &[(5, 100), (9, 101)], false).unwrap(), 1);
+        assert_eq!(list_uids(&dir, "acc1", "INBOX"), vec![6]);
+        assert_eq!(read_raw(&dir, "acc1", "Archive", 100).unwrap(), eml("five@host.test", "five"));
+        assert_eq!(read_light(&dir, "acc1", "Archive", 100).unwrap().flags, vec!["\\Seen".to_string()]);
+        let found = crate::message_index::locate(&dir, "five@host.test");
+        assert_eq!(found.iter().map(|l| (l.mailbox.as_str(), l.uid)).collect::<Vec<_>>(), [("Archive", 100)]);
+
+        // COPY keeps the original; APPEND of a local message renames in place.
+        assert_eq!(remap(&dir, "acc1", "INBOX", "Archive", &[(6, 200)], true).unwrap(), 1);
+        assert_eq!(remap(&dir, "acc1", "INBOX", "INBOX", &[(6, 7)], false).unwrap(), 1);
+        assert_eq!(list_uids(&dir, "acc1", "INBOX"), vec![7]);
+        assert_eq!(list_uids(&dir, "acc1", "Archive"), vec![100, 200]);
+        assert_eq!(read_raw(&dir, "acc1", "INBOX", 7).unwrap(), read_raw(&dir, "acc1", "Archive", 200).unwrap());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_convert_vault_round_trip() {
+        let dir = std::env::temp_dir().join("mailvault-test-convert-layout");
+        let _ = fs::remove_dir_all(&dir);
+        let flags = |f: &[&str]| f.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        store(&dir, "acc1", "INBOX", 1, &eml("one@host.test", "one"), &flags(&["\\Seen", "\\Answered"])).unwrap();
+        store(&dir, "acc1", "INBOX", 2, &eml("two@host.test", "two"), &[]).unwrap();
+        store(&dir, "acc1", "Archive/2025", 9, &eml("nine@host.test", "nine"), &flags(&["archived"])).unwrap();
+        let mailbox = dir.join("Maildir").join("acc1").join("INBOX");
+        let sorted_flags = |uid| {
+            let mut f = read_light(&dir, "acc1", "INBOX", uid).unwrap().flags;
+            f.sort();
+            f
+        };
+        let before = [sorted_flags(1), sorted_flags(2)];
+
+        let r = convert_vault(&dir, Layout::Standard).unwrap();
+        assert_eq!((r.mailboxes, r.renamed, r.errors), (2, 3, 0));
+        assert!(standard::is_standard(&mailbox));
+        assert!(names_in(&mailbox.join("cur")).iter().all(|n| vault_uid(n).is_none()));
+        assert_eq!(list_uids(&dir, "acc1", "INBOX"), vec![1, 2]);
+        assert_eq!(read_raw(&dir, "acc1", "INBOX", 2).unwrap(), eml("two@host.test", "two"));
+        assert_eq!(read_light(&dir, "acc1", "INBOX", 1).unwrap().flags, vec!["\\Answered", "\\Seen"]);
+        assert_eq!(read_light(&dir, "acc1", "Archive/2025", 9).unwrap().flags, vec!["Archived"]);
+        // Converting again finds nothing left to do.
+        assert_eq!(convert_vault(&dir, Layout::Standard).unwrap().renamed, 0);
+
+        let r = convert_vault(&dir, Layout::Vault).unwrap();
+        assert_eq!((r.renamed, r.errors), (3, 0));
+        assert!(!standard::is_standard(&mailbox));
+        assert_eq!(standard::default_layout(&dir.join("Maildir")), Layout::Vault);
+        assert_eq!(before, [sorted_flags(1), sorted_flags(2)]);
+        assert_eq!(read_light(&dir, "acc1", "Archive/2025", 9).unwrap().flags, vec!["\\Archived"]);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_repair_generation_in_standard_layout() {
+        let dir = std::env::temp_dir().join("mailvault-test-repair-standard");
+        let _ = fs::remove_dir_all(&dir);
+        standard::set_default_layout(&dir.join("Maildir"), Layout::Standard).unwrap();
+        let mailbox = dir.join("Maildir").join("acc1").join("INBOX");
+        store(&dir, "acc1", "INBOX", 1, &eml("moved@host.test", "one"), &["\\Seen".into()]).unwrap();
+        store(&dir, "acc1", "INBOX", 2, &eml("gone@host.test", "two"), &[]).unwrap();
+        write_generation(&mailbox, 100).unwrap();
+
+        let id_to_uid: HashMap<String, u32> = [("moved@host.test".to_string(), 40u32)].into_iter().collect();
+        let r = repair_generation(&mailbox, 200, &id_to_uid, &HashMap::new(), &HashSet::new());
+        assert_eq!((r.rebound.clone(), r.orphaned.clone(), r.errors), (vec![(1, 40)], vec![2], 0));
+
+        assert!(standard::is_standard(&mailbox));
+        assert_eq!(list_uids(&dir, "acc1", "INBOX"), vec![40]);
+        assert_eq!(read_light(&dir, "acc1", "INBOX", 40).unwrap().flags, vec!["\\Seen"]);
+        assert!(fs::read_to_string(mailbox.join(standard::UIDLIST_FILE)).unwrap().starts_with("3 V200 "));
+        assert_eq!(orphan_stats(&mailbox).count, 1);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    // ── Crash safety ────────────────────────────────────────────────────────
+
+    #[test]
+    fn test_store_is_atomic_and_recorded() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let raw = eml("whole@host.test", "all of it");
+        let path = store(dir, "acc1", "INBOX", 3, &raw, &[]).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), raw);
+        // The temp file went with the rename.
+        let mailbox = dir.join("Maildir").join("acc1").join("INBOX");
+        assert_eq!(fs::read_dir(mailbox.join("tmp")).unwrap().count(), 0);
+
+        let cur = cur_path(dir, "acc1", "INBOX");
+        let e = crate::uid_index::entry(&cur, 3).unwrap();
+        assert_eq!(e.size, raw.len() as u64);
+        assert_eq!(e.sha256.as_deref(), Some(integrity::sha256_hex(&raw).as_str()));
+        // A flag rename keeps the hash; the bytes didn't change.
+        set_flags(dir, "acc1", "INBOX", 3, &["\\Seen".into()]).unwrap();
+        assert_eq!(crate::uid_index::entry(&cur, 3).unwrap().sha256, e.sha256);
+
+        // Same in the standard layout.
+        standard::set_default_layout(&dir.join("Maildir"), Layout::Standard).unwrap();
+        store(dir, "acc1", "Sent", 4, &raw, &[]).unwrap();
+        let sent = cur_path(dir, "acc1", "Sent");
+        assert_eq!(crate::uid_index::entry(&sent, 4).unwrap().sha256, e.sha256);
+    }
+
+    #[test]
+    fn test_quarantine_scan_moves_cut_short_files() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        store(dir, "acc1", "INBOX", 1, &eml("good@host.test", "fine"), &[]).unwrap();
+        let altered = store(dir, "acc1", "INBOX", 2, &eml("altered@host.test", "long body"), &[]).unwrap();
+        let cur = cur_path(dir, "acc1", "INBOX");
+        // Legacy writes, in place and with no recorded hash.
+        fs::write(cur.join("5:2,S.eml"), b"").unwrap();
+        fs::write(
+            cur.join("6:2,.eml"),
+            b"Subject: parts\r\nContent-Type: multipart/mixed; boundary=\"XX\"\r\n\r\n--XX\r\n\r\nfirst\r\n--XX\r\nContent-Ty",
+        )
+        .unwrap();
+        fs::write(cur.join("7:2,.eml"), b"Subject: cut mid-head\r\nFrom: a@").unwrap();
+        fs::write(
+            cur.join("8:2,.eml"),
+            b"Subject: parts\r\nContent-Type: multipart/mixed; boundary=XX\r\n\r\n--XX\r\n\r\nonly\r\n--XX--\r\n",
+        )
+        .unwrap();
+        // Shortened in place after a checked write.
+        fs::write(&altered, eml("altered@host.test", "l")).unwrap();
+
+        let report = integrity::quarantine_scan(dir);
+        assert_eq!((report.mailboxes, report.quarantined, report.errors), (1, 4, 0));
+        assert_eq!(list_uids(dir, "acc1", "INBOX"), vec![1, 8]);
+        assert!(!email_exists(dir, "acc1", "INBOX", 5));
+        let mut kept = names_in(&cur.with_file_name(QUARANTINE_DIR));
+        kept.retain(|n| vault_uid(n) != Some(2));
+        assert_eq!(kept, vec!["5:2,S.eml", "6:2,.eml", "7:2,.eml"]);
+
+        // Nothing left to find the second time round.
+        let again = integrity::quarantine_scan(dir);
+        assert_eq!(again.quarantined, 0);
+        // And the re-fetch stores it again.
+        store(dir, "acc1", "INBOX", 5, &eml("empty@host.test", "back"), &[]).unwrap();
+        assert!(email_exists(dir, "acc1", "INBOX", 5));
+    }
+
+    // ── Compression ─────────────────────────────────────────────────────────
+
+    #[test]
+    fn test_compressed_store_reads_back() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        compression::set_default_compression(&dir.join("Maildir"), Compression::Zstd).unwrap();
+        let raw = eml("packed@host.test", &"compressible text ".repeat(200));
+        let path = store(dir, "acc1", "INBOX", 5, &raw, &[]).unwrap();
+        let name = path.file_name().unwrap().to_string_lossy().to_string();
+        assert!(name.ends_with(".eml.zst"), "{}", name);
+        let on_disk = fs::read(&path).unwrap();
+        assert_eq!(Compression::detect(&on_disk), Compression::Zstd);
+        assert!(on_disk.len() < raw.len() / 3);
+
+        assert_eq!(read_raw(dir, "acc1", "INBOX", 5).unwrap(), raw);
+        assert_eq!(read_full(dir, "acc1", "INBOX", 5).unwrap().message_id.as_deref(), Some("<packed@host.test>"));
+        let cur = cur_path(dir, "acc1", "INBOX");
+        let e = crate::uid_index::entry(&cur, 5).unwrap();
+        assert_eq!(e.message_id.as_deref(), Some("packed@host.test"));
+        assert_eq!(e.size, on_disk.len() as u64);
+        assert_eq!(e.sha256.as_deref(), Some(integrity::sha256_hex(&raw).as_str()));
+
+        // A flag rename keeps the suffix; verify checks the message, not the file.
+        set_flags(dir, "acc1", "INBOX", 5, &["\\Seen".into()]).unwrap();
+        let renamed = find_by_uid(&cur, 5).unwrap();
+        assert!(renamed.to_string_lossy().ends_with(".eml.zst"));
+        let report = verify::verify_vault(dir, None, None);
+        assert_eq!((report.checked, report.unrecorded, report.errors), (1, 0, 0));
+        assert!(report.corrupt.is_empty());
+        assert_eq!(integrity::quarantine_scan(dir).quarantined, 0);
+    }
+
+    #[test]
+    fn test_convert_compression_round_trip() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        for uid in 1..=3 {
+            store(dir, "acc1", "INBOX", uid, &eml("c@host.test", &"body ".repeat(100 * uid as usize)), &[]).unwrap();
+        }
+        let cur = cur_path(dir, "acc1", "INBOX");
+        // Written by the app, by hand, with no hash.
+        fs::write(cur.join("9:2,S.eml"), eml("app@host.test", "from the app")).unwrap();
+
+        let report = compression::convert_compression(dir, Compression::Gzip).unwrap();
+        assert_eq!((report.mailboxes, report.converted, report.errors), (1, 4, 0));
+        assert!(report.bytes_after < report.bytes_before);
+        assert!(!compression::conversion_pending(dir));
+        let mut names = names_in(&cur);
+        names.retain(|n| !n.starts_with('.'));
+        assert!(names.iter().all(|n| n.ends_with(".gz")), "{:?}", names);
+        assert!(names.contains(&"9:2,S.eml.gz".to_string()));
+        assert_eq!(read_raw(dir, "acc1", "INBOX", 9).unwrap(), eml("app@host.test", "from the app"));
+        assert_eq!(list_uids(dir, "acc1", "INBOX"), vec![1, 2, 3, 9]);
+        // Nothing left to do the second time.
+        assert_eq!(compression::convert_compression(dir, Compression::Gzip).unwrap().converted, 0);
+
+        // The standard layout is for other programs: converting decompresses.
+        convert_vault(dir, Layout::Standard).unwrap();
+        for uid in [1, 2, 3, 9] {
+            let path = find_by_uid(&cur, uid).unwrap();
+            assert_eq!(compression::compression_of_file(&path), Compression::None);
+        }
+        assert_eq!(read_raw(dir, "acc1", "INBOX", 2).unwrap(), eml("c@host.test", &"body ".repeat(200)));
+        let report = verify::verify_vault(dir, None, None);
+        assert_eq!((report.checked, report.errors), (4, 0));
+        assert!(report.corrupt.is_empty() && report.missing.is_empty());
+    }
+
+    // ── Encryption ──────────────────────────────────────────────────────────
+
+    #[test]
+    fn test_encrypted_vault_seals_rotates_and_decrypts() {
+        use crate::header_store::HeaderStore;
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let raw = eml("secret@host.test", "the launch code is 0000");
+        store(dir, "acc1", "INBOX", 1, &raw, &[]).unwrap();
+        let cache = dir.join("email_cache").join("acc1_INBOX");
+        HeaderStore::open(&cache).unwrap().put(&[serde_json::json!({"uid": 1, "subject": "Launch codes"})]).unwrap();
+
+        assert!(encryption::enable(dir, None, None).is_err(), "a key nothing can unlock");
+        encryption::enable(dir, Some("correct horse"), None).unwrap();
+        assert!(encryption::is_encrypted(dir) && compression::conversion_pending(dir));
+        let report = compression::convert_pending(dir, |_| ()).unwrap();
+        // The header store log, the uid index and the manifest.
+        assert_eq!((report.converted, report.sidecars, report.errors), (1, 3, 0));
+        assert!(!compression::conversion_pending(dir));
+
+        let cur = cur_path(dir, "acc1", "INBOX");
+        store(dir, "acc1", "INBOX", 2, &eml("later@host.test", "also secret"), &[]).unwrap();
+        for uid in [1, 2] {
+            let on_disk = fs::read(find_by_uid(&cur, uid).unwrap()).unwrap();
+            assert!(encryption::sealed_header(&on_disk).is_some());
+            assert!(!String::from_utf8_lossy(&on_disk).contains("secret"));
+        }
+        let log = fs::read(cache.join(crate::header_store::LOG_FILE)).unwrap();
+        assert!(!String::from_utf8_lossy(&log).contains("Launch"));
+        let mailbox_dir = cur.parent().unwrap();
+        crate::uid_index::flush();
+        let index = fs::read(mailbox_dir.join(crate::uid_index::INDEX_FILE)).unwrap();
+        assert!(encryption::sealed_header(&index).is_some());
+        assert!(!String::from_utf8_lossy(&index).contains("secret@host.test"));
+        let sha256 = integrity::sha256_hex(&raw);
+        assert!(!fs::read_to_string(mailbox_dir.join(manifest::MANIFEST_FILE)).unwrap().contains(&sha256));
+        assert_eq!(read_raw(dir, "acc1", "INBOX", 1).unwrap(), raw);
+        assert_eq!(HeaderStore::open(&cache).unwrap().get(1).unwrap()["subject"], "Launch codes");
+        let report = verify::verify_vault(dir, None, None);
+        assert_eq!((report.checked, report.errors), (2, 0));
+        assert!(report.corrupt.is_empty());
+
+        // A mirror copy is sealed too, and outlives the key it was sealed under.
+        let mirror = dir.join("mirror-1.eml");
+        compression::copy_to_mirror(&find_by_uid(&cur, 1).unwrap(), &mirror).unwrap();
+        assert!(encryption::sealed_header_of_file(&mirror).is_some());
+
+        // Locked: nothing reads, nothing is written in the clear, nothing is
+        // mistaken for damage.
+        encryption::lock(dir);
+        assert!(!encryption::is_unlocked(dir));
+        assert!(read_raw(dir, "acc1", "INBOX", 1).unwrap_err().contains(encryption::LOCKED));
+        assert!(HeaderStore::open(&cache).is_err());
+        assert!(store(dir, "acc1", "INBOX", 3, &eml("x@host.test", "x"), &[]).is_err());
+        assert_eq!(integrity::quarantine_scan(dir).quarantined, 0);
+        assert_eq!(encryption::unlock_with_passphrase(dir, "wrong").unwrap_err(), "Wrong passphrase");
+        encryption::unlock_with_passphrase(dir, "correct horse").unwrap();
+        assert_eq!(read_raw(dir, "acc1", "INBOX", 2).unwrap(), eml("later@host.test", "also secret"));
+
+        // Rotation re-seals everything under a new key and a new passphrase.
+        let old_key = encryption::sealed_header_of_file(&mirror).unwrap().0;
+        assert!(encryption::rotate(dir, None, None).is_err(), "the new key needs a passphrase");
+        encryption::rotate(dir, Some("battery staple"), None).unwrap();
+        let report = compression::convert_pending(dir, |_| ()).unwrap();
+        assert_eq!((report.converted, report.errors), (2, 0));
+        let new_key = encryption::sealed_header_of_file(&find_by_uid(&cur, 1).unwrap()).unwrap().0;
+        assert_ne!(new_key, old_key);
+        assert!(compression::mirror_copy_stale(dir, &mirror));
+        encryption::lock(dir);
+        assert!(encryption::unlock_with_passphrase(dir, "correct horse").is_err());
+        encryption::unlock_with_passphrase(dir, "battery staple").unwrap();
+        assert_eq!(compression::read_message(&mirror).unwrap(), raw);
+        assert_eq!(HeaderStore::open(&cache).unwrap().len(), 1);
+
+        // Turning it off decrypts everything and drops the key file.
+        assert!(convert_vault(dir, Layout::Standard).is_err());
+        encryption::disable(dir).unwrap();
+        let report = compression::convert_pending(dir, |_| ()).unwrap();
+        assert_eq!((report.converted, report.errors), (2, 0));
+        assert!(!dir.join("Maildir").join(encryption::KEY_FILE).exists());
+        assert_eq!(fs::read(find_by_uid(&cur, 1).unwrap()).unwrap(), raw);
+        assert!(String::from_utf8_lossy(&fs::read(cache.join(crate::header_store::LOG_FILE)).unwrap()).contains("Launch"));
+        assert_eq!(manifest::read(mailbox_dir)[&1].sha256, sha256);
+        let report = verify::verify_vault(dir, None, None);
+        assert_eq!((report.checked, report.unrecorded, report.errors), (2, 0, 0));
+    }
+
+    #[test]
+    fn test_keyring_key_unlocks_and_rotates_out() {
+        use std::cell::RefCell;
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let keyring: RefCell<Vec<(String, String)>> = RefCell::new(Vec::new());
+        let put = |account: &str, secret: &str| {
+            keyring.borrow_mut().push((account.to_string(), secret.to_string()));
+            Ok(())
+        };
+        let first = encryption::enable(dir, None, Some(&put)).unwrap();
+        let raw = eml("kept@host.test", "in the keyring");
+        store(dir, "acc1", "INBOX", 1, &raw, &[]).unwrap();
+        assert_eq!(encryption::keyring_keys(dir), vec![first.key_id.clone()]);
+        let (account, secret) = keyring.borrow()[0].clone();
+        assert_eq!(account, encryption::keyring_account(&first.key_id));
+
+        encryption::lock(dir);
+        assert!(encryption::unlock_with_secret(dir, &first.key_id, "AAAA").is_err());
+        encryption::unlock_with_secret(dir, &first.key_id, &secret).unwrap();
+        assert_eq!(read_raw(dir, "acc1", "INBOX", 1).unwrap(), raw);
+
+        // The old key leaves the keyring; the new one opens both.
+        let second = encryption::rotate(dir, None, Some(&put)).unwrap();
+        assert_eq!(second.out_of_keyring, vec![first.key_id.clone()]);
+        assert_eq!(encryption::keyring_keys(dir), vec![second.key_id.clone()]);
+        let secret = keyring.borrow()[1].1.clone();
+        encryption::lock(dir);
+        encryption::unlock_with_secret(dir, &second.key_id, &secret).unwrap();
+        assert!(encryption::is_unlocked(dir));
+        assert_eq!(read_raw(dir, "acc1", "INBOX", 1).unwrap(), raw);
+
+        let mut done = Vec::new();
+        encryption::disable(dir).unwrap();
+        compression::convert_pending(dir, |ids| done = ids);
+        assert_eq!(done, vec![second.key_id]);
+    }
+
+    // ── Verification ────────────────────────────────────────────────────────
+
+    #[test]
+    fn test_verify_vault_finds_and_restores_damage() {
+        use verify::{verify_vault, BackupMirror, MessageRef};
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let at = |uid| MessageRef { account_id: "acc1".into(), mailbox: "INBOX".into(), uid };
+        for uid in 1..=4 {
+            store(dir, "acc1", "INBOX", uid, &eml("v@host.test", &format!("body {}", uid)), &[]).unwrap();
+        }
+        let cur = cur_path(dir, "acc1", "INBOX");
+        // A mirror with a good copy of 2, a bad copy of 3 and nothing for 4.
+        let mirror_cur = dir.join("mirror").join("v@host.test").join("INBOX").join("cur");
+        fs::create_dir_all(&mirror_cur).unwrap();
+        fs::write(mirror_cur.join("1:2,S.eml"), eml("v@host.test", "body 1")).unwrap();
+        fs::write(mirror_cur.join("2:2,S.eml"), eml("v@host.test", "body 2")).unwrap();
+        fs::write(mirror_cur.join("3.eml"), eml("v@host.test", "body X")).unwrap();
+
+        // Bit rot in 2 and 3, same size; 4 deleted properly; a legacy file 9.
+        fs::write(find_by_uid(&cur, 2).unwrap(), eml("v@host.test", "bodY 2")).unwrap();
+        fs::write(find_by_uid(&cur, 3).unwrap(), eml("v@host.test", "bodY 3")).unwrap();
+        delete(dir, "acc1", "INBOX", 4).unwrap();
+        fs::write(cur.join("9:2,.eml"), eml("legacy@host.test", "old")).unwrap();
+
+        let mirror = BackupMirror {
+            root: dir.join("mirror"),
+            emails: HashMap::from([("acc1".to_string(), "v@host.test".to_string())]),
+        };
+        let report = verify_vault(dir, None, Some(&mirror));
+        assert_eq!((report.mailboxes, report.checked, report.unrecorded, report.errors), (1, 4, 1, 0));
+        assert_eq!(report.corrupt, vec![at(2), at(3)]);
+        assert_eq!(report.restored, vec![at(2)]);
+        assert!(report.missing.is_empty());
+        let backup = report.backup.as_ref().unwrap();
+        assert_eq!(backup.corrupt, vec![at(3)]);
+        assert_eq!(backup.missing, vec![at(9)]);
+        assert_eq!(report.to_refetch().into_iter().collect::<Vec<_>>(), vec![(("acc1".into(), "INBOX".into()), vec![3])]);
+        assert_eq!(read_raw(dir, "acc1", "INBOX", 2).unwrap(), eml("v@host.test", "body 2"));
+        assert!(!email_exists(dir, "acc1", "INBOX", 3));
+        assert_eq!(names_in(&cur.with_file_name(QUARANTINE_DIR)).len(), 2);
+
+        // A file removed behind the vault's back is reported, not refetched —
+        // as is 3 until a re-fetch stores it again.
+        fs::remove_file(find_by_uid(&cur, 1).unwrap()).unwrap();
+        let again = verify_vault(dir, Some("acc1"), None);
+        assert_eq!((again.checked, again.unrecorded, again.errors), (2, 0, 0));
+        assert!(again.corrupt.is_empty());
+        assert_eq!(again.missing, vec![at(1), at(3)]);
+        assert!(again.to_refetch().is_empty());
+        assert!(verify_vault(dir, Some("acc2"), None).mailboxes == 0);
+    }
+
+    // ── Deduplication ───────────────────────────────────────────────────────
+
+    #[test]
+    fn test_dedup_shares_attachments_and_rebuilds_exactly() {
+        use base64::Engine;
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        let deck: Vec<u8> = (0..100_000u32).map(|i| (i * 7 % 251) as u8).collect();
+        let b64 = base64::engine::general_purpose::STANDARD.encode(&deck);
+        let with_deck = |to: &str, wrap: usize, eol: &str| -> Vec<u8> {
+            let body: Vec<&str> = b64.as_bytes().chunks(wrap).map(|c| std::str::from_utf8(c).unwrap()).collect();
+            format!(
+                "From: a@host.test{eol}To: {to}{eol}Subject: Deck{eol}Content-Type: multipart/mixed; boundary=\"b\"{eol}{eol}\
+                 --b{eol}Content-Type: text/plain{eol}{eol}See attached.{eol}\
+                 --b{eol}Content-Type: application/pdf{eol}Content-Transfer-Encoding: base64{eol}{eol}{}{eol}--b--{eol}",
+                body.join(eol),
+            )
+            .into_bytes()
+        };
+        let first = with_deck("b@host.test", 76, "\r\n");
+        let forwarded = with_deck("c@host.test", 72, "\n");
+        store(dir, "acc1", "INBOX", 1, &first, &[]).unwrap();
+
+        // Turning it on moves existing attachments out; new mail goes straight in.
+        dedup::set_enabled(dir, true).unwrap();
+        let report = compression::convert_pending(dir, |_| ()).unwrap();
+        assert_eq!((report.converted, report.errors), (1, 0));
+        store(dir, "acc1", "Sent", 1, &forwarded, &[]).unwrap();
+        store(dir, "acc2", "INBOX", 1, &eml("small@host.test", "nothing to share"), &[]).unwrap();
+        assert_eq!(read_raw(dir, "acc1", "INBOX", 1).unwrap(), first);
+        assert_eq!(read_raw(dir, "acc1", "Sent", 1).unwrap(), forwarded);
+        assert!(!compression::is_plain(&find_by_uid(&cur_path(dir, "acc1", "Sent"), 1).unwrap()));
+        assert!(compression::is_plain(&find_by_uid(&cur_path(dir, "acc2", "INBOX"), 1).unwrap()));
+        let report = verify::verify_vault(dir, None, None);
+        assert_eq!((report.checked, report.errors), (3, 0));
+
+        let swept = dedup::sweep(dir);
+        assert_eq!((swept.parts, swept.errors), (1, 0));
+        // Counted as the base64 the messages would otherwise hold.
+        assert!(swept.referenced_bytes > 2 * b64.len() as u64);
+        let stats = storage_stats(dir, "acc1");
+        assert_eq!(stats.attachment_bytes, swept.referenced_bytes);
+        assert_eq!(stats.dedup_saved_bytes, swept.referenced_bytes - deck.len() as u64);
+        assert!(stats.total_size < deck.len() as u64);
+        assert_eq!(storage_stats(dir, "acc2").attachment_bytes, 0);
+
+        // A part nothing refers to any more goes at the next sweep.
+        delete(dir, "acc1", "INBOX", 1).unwrap();
+        delete(dir, "acc1", "Sent", 1).unwrap();
+        assert_eq!(dedup::sweep(dir).removed, 1);
+        store(dir, "acc1", "INBOX", 2, &first, &[]).unwrap();
+
+        // Compressing and sealing covers the store too.
+        compression::request_compression(dir, Compression::Zstd).unwrap();
+        encryption::enable(dir, Some("pass"), None).unwrap();
+        let report = compression::convert_pending(dir, |_| ()).unwrap();
+        assert_eq!((report.parts, report.errors), (1, 0));
+        assert_eq!(read_raw(dir, "acc1", "INBOX", 2).unwrap(), first);
+        encryption::disable(dir).unwrap();
+        compression::request_compression(dir, Compression::None).unwrap();
+        compression::convert_pending(dir, |_| ()).unwrap();
+
+        // Turning it off puts every attachment back and empties the store.
+        dedup::set_enabled(dir, false).unwrap();
+        let report = compression::convert_pending(dir, |_| ()).unwrap();
+        assert_eq!((report.converted, report.errors), (1, 0));
+        assert_eq!(fs::read(find_by_uid(&cur_path(dir, "acc1", "INBOX"), 2).unwrap()).unwrap(), first);
+        assert!(!dedup::store_exists(dir));
+    }
+}
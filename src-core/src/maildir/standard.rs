@@ -0,0 +1,630 @@
+//! Standard Maildir layout, for vaults other mail programs read directly.
+//!
+//! The vault's own layout writes `{uid}:{flags}:{timestamp}.eml` straight into
+//! `cur/`: the uid is in the name, so a lookup needs nothing else, but mutt,
+//! notmuch, Dovecot or offlineimap can't safely consume it. A mailbox in the
+//! standard layout is one they can: each message is written in `tmp/` and
+//! renamed into `new/` (no flags yet) or `cur/` as `{base}:2,{letters}`, and
+//! the uid of each base name lives in `dovecot-uidlist` beside them, in the
+//! version 3 format Dovecot reads.
+//!
+//! A mailbox is standard when it has a `dovecot-uidlist`. A mailbox that
+//! doesn't exist yet takes the vault-wide default in `Maildir/.maildir_layout`;
+//! `maildir::convert_mailbox` moves an existing one between the two. Keywords —
+//! anything that isn't one of the six system flags — get a lowercase letter
+//! from `dovecot-keywords`, the way Dovecot assigns them.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::{info, warn};
+
+use super::integrity;
+
+pub const UIDLIST_FILE: &str = "dovecot-uidlist";
+pub const KEYWORDS_FILE: &str = "dovecot-keywords";
+pub const LAYOUT_FILE: &str = ".maildir_layout";
+const LOCK_FILE: &str = "dovecot-uidlist.lock";
+
+/// A dotlock older than this was left by a process that died holding it.
+const STALE_LOCK: Duration = Duration::from_secs(30);
+const LOCK_WAIT: Duration = Duration::from_secs(10);
+
+/// Info letters for the system flags, in the ASCII order the spec wants them
+/// written: the letter, the flag readers get back, and every spelling a
+/// caller may hand in — IMAP's and the app's own (`replied`, `trashed`).
+const SYSTEM_FLAGS: [(char, &str, &[&str]); 6] = [
+    ('D', "\\Draft", &["draft"]),
+    ('F', "\\Flagged", &["flagged"]),
+    ('P', "$Forwarded", &["$forwarded", "forwarded", "passed"]),
+    ('R', "\\Answered", &["answered", "replied"]),
+    ('S', "\\Seen", &["seen"]),
+    ('T', "\\Deleted", &["deleted", "trashed"]),
+];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Layout {
+    /// `{uid}:{flags}:{timestamp}.eml`, written straight into `cur/`.
+    #[default]
+    Vault,
+    /// `tmp/` → `new/`/`cur/` delivery with `:2,` info and a `dovecot-uidlist`.
+    Standard,
+}
+
+impl Layout {
+    pub fn parse(s: &str) -> Option<Layout> {
+        match s {
+            "vault" => Some(Layout::Vault),
+            "standard" => Some(Layout::Standard),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Layout::Vault => "vault",
+            Layout::Standard => "standard",
+        }
+    }
+}
+
+pub fn is_standard(mailbox_dir: &Path) -> bool {
+    mailbox_dir.join(UIDLIST_FILE).is_file()
+}
+
+/// The layout new mailboxes get, from `{maildir_root}/.maildir_layout`.
+pub fn default_layout(maildir_root: &Path) -> Layout {
+    fs::read_to_string(maildir_root.join(LAYOUT_FILE))
+        .ok()
+        .and_then(|s| Layout::parse(s.trim()))
+        .unwrap_or_default()
+}
+
+pub fn set_default_layout(maildir_root: &Path, layout: Layout) -> Result<(), String> {
+    fs::create_dir_all(maildir_root).map_err(|e| format!("Failed to create Maildir: {}", e))?;
+    fs::write(maildir_root.join(LAYOUT_FILE), layout.as_str())
+        .map_err(|e| format!("Failed to write {}: {}", LAYOUT_FILE, e))
+}
+
+/// The layout a write to this mailbox uses: the one it is in, or the vault
+/// default when it doesn't exist yet.
+pub fn layout_of(mailbox_dir: &Path) -> Layout {
+    if is_standard(mailbox_dir) {
+        Layout::Standard
+    } else if mailbox_dir.join("cur").is_dir() {
+        Layout::Vault
+    } else {
+        mailbox_dir
+            .ancestors()
+            .find(|a| a.file_name().is_some_and(|n| n == "Maildir"))
+            .map(default_layout)
+            .unwrap_or_default()
+    }
+}
+
+/// A standard filename without its `:2,` info — what the uidlist records, and
+/// what stays put when the flags change.
+pub fn base_of(name: &str) -> &str {
+    name.split_once(':').map_or(name, |(base, _)| base)
+}
+
+fn hostname() -> &'static str {
+    static HOST: OnceLock<String> = OnceLock::new();
+    HOST.get_or_init(|| {
+        let raw = std::env::var("HOSTNAME")
+            .or_else(|_| std::env::var("COMPUTERNAME"))
+            .ok()
+            .or_else(|| fs::read_to_string("/etc/hostname").ok())
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+            .unwrap_or_else(|| "localhost".into());
+        // The two characters a base name can't carry, escaped as the spec says.
+        raw.replace('/', "\\057").replace(':', "\\072")
+    })
+}
+
+/// `{secs}.M{usecs}P{pid}Q{n}.{host}` — unique across processes and hosts
+/// without looking at the directory.
+pub(super) fn new_base() -> String {
+    static DELIVERIES: AtomicU64 = AtomicU64::new(0);
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    format!(
+        "{}.M{}P{}Q{}.{}",
+        now.as_secs(),
+        now.subsec_micros(),
+        std::process::id(),
+        DELIVERIES.fetch_add(1, Ordering::Relaxed),
+        hostname(),
+    )
+}
+
+/// `dovecot-keywords`: keyword `n` is info letter `'a' + n`.
+#[derive(Debug, Clone, Default)]
+pub struct Keywords {
+    names: Vec<String>,
+    changed: bool,
+}
+
+impl Keywords {
+    pub fn load(mailbox_dir: &Path) -> Keywords {
+        let mut names: Vec<String> = Vec::new();
+        if let Ok(text) = fs::read_to_string(mailbox_dir.join(KEYWORDS_FILE)) {
+            for line in text.lines() {
+                let Some((n, name)) = line.split_once(' ') else { continue };
+                let Ok(n) = n.parse::<usize>() else { continue };
+                if n < 26 {
+                    if names.len() <= n {
+                        names.resize(n + 1, String::new());
+                    }
+                    names[n] = name.trim().to_string();
+                }
+            }
+        }
+        Keywords { names, changed: false }
+    }
+
+    fn name_of(&self, letter: char) -> Option<&str> {
+        let n = (letter as u32).checked_sub('a' as u32)? as usize;
+        self.names.get(n).map(|s| s.as_str()).filter(|s| !s.is_empty())
+    }
+
+    /// The letter for `name`, assigning the next free one. `None` once all 26
+    /// are taken — Dovecot drops the keyword from the filename then too.
+    fn letter_for(&mut self, name: &str) -> Option<char> {
+        let n = match self.names.iter().position(|k| k.eq_ignore_ascii_case(name)) {
+            Some(n) => n,
+            None => {
+                let n = self.names.iter().position(|k| k.is_empty()).unwrap_or(self.names.len());
+                if n >= 26 {
+                    return None;
+                }
+                if n == self.names.len() {
+                    self.names.push(String::new());
+                }
+                self.names[n] = name.to_string();
+                self.changed = true;
+                n
+            }
+        };
+        char::from_u32('a' as u32 + n as u32)
+    }
+
+    fn save(&mut self, mailbox_dir: &Path) -> Result<(), String> {
+        if !self.changed {
+            return Ok(());
+        }
+        let text: String = self
+            .names
+            .iter()
+            .enumerate()
+            .filter(|(_, k)| !k.is_empty())
+            .map(|(n, k)| format!("{} {}\n", n, k))
+            .collect();
+        replace_file(&mailbox_dir.join(KEYWORDS_FILE), text.as_bytes())?;
+        self.changed = false;
+        Ok(())
+    }
+}
+
+/// `:2,` and the letters for `flags`, system letters first, each group in
+/// ASCII order.
+fn info(flags: &[String], keywords: &mut Keywords) -> String {
+    let mut system: Vec<char> = Vec::new();
+    let mut custom: Vec<char> = Vec::new();
+    for flag in flags {
+        let token = flag.trim_start_matches('\\').to_lowercase();
+        match SYSTEM_FLAGS.iter().find(|(_, _, names)| names.contains(&token.as_str())) {
+            Some((letter, ..)) => system.push(*letter),
+            None if token.is_empty() || token == "recent" => {}
+            None => custom.extend(keywords.letter_for(flag.trim_start_matches('\\'))),
+        }
+    }
+    system.sort_unstable();
+    system.dedup();
+    custom.sort_unstable();
+    custom.dedup();
+    format!(":2,{}{}", system.into_iter().collect::<String>(), custom.into_iter().collect::<String>())
+}
+
+/// The flags a standard filename carries. A name still in `new/` has no info
+/// and so no flags.
+pub fn flags_of(name: &str, keywords: &Keywords) -> Vec<String> {
+    let Some((_, letters)) = name.split_once(":2,") else { return Vec::new() };
+    letters
+        .chars()
+        .filter_map(|c| match c {
+            'a'..='z' => keywords.name_of(c).map(String::from),
+            _ => SYSTEM_FLAGS.iter().find(|(l, ..)| *l == c).map(|(_, flag, _)| flag.to_string()),
+        })
+        .collect()
+}
+
+/// A parsed `dovecot-uidlist`.
+#[derive(Debug, Default)]
+pub struct Uidlist {
+    pub validity: u32,
+    pub next_uid: u32,
+    /// Base name → uid. A base listed twice keeps its last line.
+    pub uids: HashMap<String, u32>,
+    lines: usize,
+    ascending: bool,
+}
+
+impl Uidlist {
+    pub fn read(mailbox_dir: &Path) -> Option<Uidlist> {
+        fs::read_to_string(mailbox_dir.join(UIDLIST_FILE)).ok().map(|t| Uidlist::parse(&t))
+    }
+
+    /// Versions 3 (`3 V{validity} N{next}`, then `{uid} [ext…] :{base}`) and
+    /// 1 (`1 {validity} {next}`, then `{uid} {base}`).
+    fn parse(text: &str) -> Uidlist {
+        let mut list = Uidlist { ascending: true, ..Default::default() };
+        let mut lines = text.lines();
+        if let Some(header) = lines.next() {
+            let mut fields = header.split_whitespace();
+            match fields.next() {
+                Some("1") => {
+                    list.validity = fields.next().and_then(|v| v.parse().ok()).unwrap_or(0);
+                    list.next_uid = fields.next().and_then(|v| v.parse().ok()).unwrap_or(0);
+                }
+                _ => {
+                    for field in fields {
+                        if let Some(v) = field.strip_prefix('V') {
+                            list.validity = v.parse().unwrap_or(0);
+                        } else if let Some(n) = field.strip_prefix('N') {
+                            list.next_uid = n.parse().unwrap_or(0);
+                        }
+                    }
+                }
+            }
+        }
+        let mut last = 0u32;
+        for line in lines {
+            let Some((uid, rest)) = line.split_once(' ') else { continue };
+            let Ok(uid) = uid.parse::<u32>() else { continue };
+            // v3 puts the name after ` :`; v1 has nothing but the name.
+            let name = match rest.strip_prefix(':').or_else(|| rest.split_once(" :").map(|(_, n)| n)) {
+                Some(name) => name,
+                None => rest.trim(),
+            };
+            let base = base_of(name);
+            if base.is_empty() {
+                continue;
+            }
+            list.lines += 1;
+            let repeated = list.uids.insert(base.to_string(), uid).is_some();
+            if uid <= last || repeated {
+                list.ascending = false;
+            }
+            last = last.max(uid);
+            list.next_uid = list.next_uid.max(uid.saturating_add(1));
+        }
+        list
+    }
+
+    /// One line per base, in uid order — what Dovecot expects to find.
+    pub fn is_tidy(&self) -> bool {
+        self.ascending && self.lines == self.uids.len()
+    }
+
+    pub fn lines(&self) -> usize {
+        self.lines
+    }
+}
+
+/// Replace `path` whole, fsynced: a uidlist cut short would lose the uid of
+/// every message after the cut.
+fn replace_file(path: &Path, bytes: &[u8]) -> Result<(), String> {
+    let tmp = path.with_extension(format!("{}.tmp", std::process::id()));
+    let written = integrity::write_synced(&tmp, bytes)
+        .and_then(|()| fs::rename(&tmp, path).map_err(|e| format!("Failed to write {:?}: {}", path, e)));
+    if written.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    written?;
+    integrity::sync_dir(path.parent().unwrap_or(path))
+}
+
+/// Write the whole uidlist. `entries` may come in any order and may repeat a
+/// uid — the first base for a uid keeps it.
+fn write_uidlist(mailbox_dir: &Path, next_uid: u32, entries: &mut Vec<(u32, String)>) -> Result<(), String> {
+    entries.sort_unstable();
+    entries.dedup_by_key(|e| e.0);
+    let validity = super::read_generation(mailbox_dir)
+        .filter(|v| *v != 0)
+        .unwrap_or_else(|| SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() as u32).unwrap_or(1));
+    let next = entries.last().map_or(1, |e| e.0.saturating_add(1)).max(next_uid);
+    let mut text = format!("3 V{} N{}\n", validity, next);
+    for (uid, base) in entries.iter() {
+        text.push_str(&format!("{} :{}\n", uid, base));
+    }
+    replace_file(&mailbox_dir.join(UIDLIST_FILE), text.as_bytes())
+}
+
+/// Run `f` holding Dovecot's own lock on the uidlist, a dotlock made with
+/// `O_EXCL`. It covers every change to the uidlist and every rename of a
+/// listed message, so whoever holds it sees the two agree.
+fn locked<T>(mailbox_dir: &Path, f: impl FnOnce() -> Result<T, String>) -> Result<T, String> {
+    let lock = mailbox_dir.join(LOCK_FILE);
+    let started = Instant::now();
+    loop {
+        match OpenOptions::new().write(true).create_new(true).open(&lock) {
+            Ok(mut file) => {
+                let _ = write!(file, "{}", std::process::id());
+                break;
+            }
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                let stale = fs::metadata(&lock)
+                    .and_then(|m| m.modified())
+                    .ok()
+                    .and_then(|t| t.elapsed().ok())
+                    .is_some_and(|age| age > STALE_LOCK);
+                if stale {
+                    warn!("maildir: breaking stale lock {:?}", lock);
+                    let _ = fs::remove_file(&lock);
+                    continue;
+                }
+                if started.elapsed() > LOCK_WAIT {
+                    return Err(format!("Timed out waiting for {:?}", lock));
+                }
+                std::thread::sleep(Duration::from_millis(20));
+            }
+            Err(e) => return Err(format!("Failed to lock {:?}: {}", lock, e)),
+        }
+    }
+    let out = f();
+    let _ = fs::remove_file(&lock);
+    out
+}
+
+/// `tmp/`, `new/`, `cur/` and an empty uidlist — the uidlist is what makes
+/// the mailbox standard.
+pub(crate) fn create(mailbox_dir: &Path) -> Result<(), String> {
+    for sub in ["tmp", "new", "cur"] {
+        fs::create_dir_all(mailbox_dir.join(sub)).map_err(|e| format!("Failed to create Maildir: {}", e))?;
+    }
+    if is_standard(mailbox_dir) {
+        return Ok(());
+    }
+    locked(mailbox_dir, || {
+        if is_standard(mailbox_dir) {
+            return Ok(());
+        }
+        write_uidlist(mailbox_dir, 1, &mut Vec::new())
+    })
+}
+
+/// Deliver a message under `uid`: written and fsynced in `tmp/`, listed in
+/// the uidlist, then renamed into `new/` — or straight into `cur/` when it
+/// already has flags, as a client that downloaded it would.
+pub(crate) fn deliver(mailbox_dir: &Path, uid: u32, raw: &[u8], flags: &[String]) -> Result<PathBuf, String> {
+    create(mailbox_dir)?;
+    let base = new_base();
+    let tmp = mailbox_dir.join("tmp").join(&base);
+    if let Err(e) = integrity::write_synced(&tmp, raw) {
+        let _ = fs::remove_file(&tmp);
+        return Err(e);
+    }
+    let delivered = locked(mailbox_dir, || {
+        let dest = if flags.is_empty() {
+            mailbox_dir.join("new").join(&base)
+        } else {
+            let mut keywords = Keywords::load(mailbox_dir);
+            let name = format!("{}{}", base, info(flags, &mut keywords));
+            keywords.save(mailbox_dir)?;
+            mailbox_dir.join("cur").join(name)
+        };
+        let mut list = OpenOptions::new()
+            .append(true)
+            .open(mailbox_dir.join(UIDLIST_FILE))
+            .map_err(|e| format!("Failed to open {}: {}", UIDLIST_FILE, e))?;
+        writeln!(list, "{} :{}", uid, base)
+            .and_then(|()| list.sync_data())
+            .map_err(|e| format!("Failed to append to {}: {}", UIDLIST_FILE, e))?;
+        fs::rename(&tmp, &dest).map_err(|e| format!("Failed to deliver {:?}: {}", dest, e))?;
+        integrity::sync_dir(dest.parent().unwrap_or(mailbox_dir))?;
+        Ok(dest)
+    });
+    if delivered.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    let dest = delivered?;
+    integrity::check_size(&dest, raw.len())?;
+    Ok(dest)
+}
+
+/// Rename a message into `cur/` under new flags. The base, and with it the
+/// uidlist line, stays.
+pub(crate) fn set_flags(mailbox_dir: &Path, path: &Path, flags: &[String]) -> Result<PathBuf, String> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+    let base = base_of(&name).to_string();
+    locked(mailbox_dir, || {
+        let mut keywords = Keywords::load(mailbox_dir);
+        let dest = mailbox_dir.join("cur").join(format!("{}{}", base, info(flags, &mut keywords)));
+        keywords.save(mailbox_dir)?;
+        if dest != path {
+            fs::rename(path, &dest).map_err(|e| format!("Failed to rename for flag update: {}", e))?;
+            integrity::sync_dir(&mailbox_dir.join("cur"))?;
+        }
+        Ok(dest)
+    })
+}
+
+/// Base names present in `new/` and `cur/`. `new/` is listed first: a message
+/// moving to `cur/` meanwhile is then seen in one or the other.
+fn present_bases(mailbox_dir: &Path) -> HashSet<String> {
+    let mut bases = HashSet::new();
+    for sub in ["new", "cur"] {
+        if let Ok(dir) = fs::read_dir(mailbox_dir.join(sub)) {
+            for entry in dir.flatten() {
+                bases.insert(base_of(&entry.file_name().to_string_lossy()).to_string());
+            }
+        }
+    }
+    bases
+}
+
+/// Rewrite the uidlist in uid order, without lines for messages that are
+/// gone. A delivery appends whatever uid the server gave, which during a
+/// backfill runs newest first, and a delete leaves its line behind; Dovecot
+/// wants neither. True when the file was rewritten.
+pub fn tidy(mailbox_dir: &Path) -> Result<bool, String> {
+    locked(mailbox_dir, || {
+        let Some(list) = Uidlist::read(mailbox_dir) else { return Ok(false) };
+        let present = present_bases(mailbox_dir);
+        let mut live: Vec<(u32, String)> = list
+            .uids
+            .iter()
+            .filter(|(base, _)| present.contains(*base))
+            .map(|(base, uid)| (*uid, base.clone()))
+            .collect();
+        if list.is_tidy() && live.len() == list.lines {
+            return Ok(false);
+        }
+        write_uidlist(mailbox_dir, list.next_uid, &mut live)?;
+        Ok(true)
+    })
+}
+
+/// Move a vault-layout mailbox to the standard layout: every `{uid}:` file in
+/// `cur/` gets a standard name. The uidlist is written before anything is
+/// renamed, so after a crash the mailbox is standard with some files still
+/// under their vault names — which every reader still resolves by the uid
+/// prefix — and running this again finishes the job.
+pub(crate) fn to_standard(mailbox_dir: &Path) -> Result<u64, String> {
+    create(mailbox_dir)?;
+    let cur = mailbox_dir.join("cur");
+    locked(mailbox_dir, || {
+        let list = Uidlist::read(mailbox_dir).unwrap_or_default();
+        let present = present_bases(mailbox_dir);
+        let mut entries: Vec<(u32, String)> = list
+            .uids
+            .iter()
+            .filter(|(base, _)| present.contains(*base))
+            .map(|(base, uid)| (*uid, base.clone()))
+            .collect();
+        let mut taken: HashSet<u32> = entries.iter().map(|e| e.0).collect();
+        let mut keywords = Keywords::load(mailbox_dir);
+        let mut moves: Vec<(PathBuf, PathBuf)> = Vec::new();
+        for entry in fs::read_dir(&cur).map_err(|e| format!("Failed to read {:?}: {}", cur, e))?.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if !entry.file_type().map(|t| t.is_file()).unwrap_or(false) || name.ends_with(super::REGEN_SUFFIX) {
+                continue;
+            }
+            let Some(uid) = super::vault_uid(&name) else { continue };
+            if !taken.insert(uid) {
+                warn!("maildir: {:?} repeats uid {}, left under its vault name", entry.path(), uid);
+                continue;
+            }
+            let base = new_base();
+            let flags = super::extract_flags_from_filename(&name);
+            moves.push((entry.path(), cur.join(format!("{}{}", base, info(&flags, &mut keywords)))));
+            entries.push((uid, base));
+        }
+        keywords.save(mailbox_dir)?;
+        write_uidlist(mailbox_dir, list.next_uid, &mut entries)?;
+
+        let mut moved = 0u64;
+        for (from, to) in moves {
+            match fs::rename(&from, &to) {
+                Ok(()) => moved += 1,
+                Err(e) => warn!("maildir: rename {:?} failed: {}", from, e),
+            }
+        }
+        integrity::sync_dir(&cur)?;
+        info!("maildir: {:?} → standard layout, {} files renamed", mailbox_dir, moved);
+        Ok(moved)
+    })
+}
+
+/// Move a standard mailbox back to the vault layout: every listed message to
+/// `cur/{uid}:{flags}:{timestamp}.eml`, then the uidlist goes. A message the
+/// uidlist doesn't list — delivered by some other program — has no uid to
+/// take, so it stays where it is and so does the uidlist; nothing is made
+/// unreachable.
+pub(crate) fn to_vault(mailbox_dir: &Path) -> Result<u64, String> {
+    if !is_standard(mailbox_dir) {
+        return Ok(0);
+    }
+    let cur = mailbox_dir.join("cur");
+    locked(mailbox_dir, || {
+        let list = Uidlist::read(mailbox_dir).unwrap_or_default();
+        let keywords = Keywords::load(mailbox_dir);
+        let mut moved = 0u64;
+        let mut left = 0u64;
+        for sub in ["new", "cur"] {
+            let Ok(dir) = fs::read_dir(mailbox_dir.join(sub)) else { continue };
+            for entry in dir.flatten() {
+                let name = entry.file_name().to_string_lossy().to_string();
+                if !entry.file_type().map(|t| t.is_file()).unwrap_or(false) || super::vault_uid(&name).is_some() {
+                    continue;
+                }
+                let Some(&uid) = list.uids.get(base_of(&name)) else {
+                    left += 1;
+                    continue;
+                };
+                let dest = cur.join(super::build_filename(uid, &flags_of(&name, &keywords)));
+                if dest.exists() {
+                    warn!("maildir: {:?} already exists, keeping {:?}", dest, entry.path());
+                    left += 1;
+                    continue;
+                }
+                match fs::rename(entry.path(), &dest) {
+                    Ok(()) => moved += 1,
+                    Err(e) => {
+                        warn!("maildir: rename {:?} failed: {}", entry.path(), e);
+                        left += 1;
+                    }
+                }
+            }
+        }
+        integrity::sync_dir(&cur)?;
+        if left == 0 {
+            for file in [UIDLIST_FILE, KEYWORDS_FILE] {
+                let _ = fs::remove_file(mailbox_dir.join(file));
+            }
+        } else {
+            warn!("maildir: {:?} kept its uidlist — {} files could not take a vault name", mailbox_dir, left);
+        }
+        info!("maildir: {:?} → vault layout, {} files renamed", mailbox_dir, moved);
+        Ok(moved)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uidlist_parses_both_versions_and_spots_disorder() {
+        let v3 = Uidlist::parse("3 V1700000000 N10 Gabc\n3 :a.M1\n5 W120 :b.M2\n4 :c.M3\n");
+        assert_eq!((v3.validity, v3.next_uid), (1700000000, 10));
+        assert_eq!(v3.uids.get("b.M2"), Some(&5));
+        assert!(!v3.is_tidy());
+
+        let v1 = Uidlist::parse("1 42 3\n1 a.M1\n2 b.M2:2,S\n");
+        assert_eq!((v1.validity, v1.next_uid), (42, 3));
+        assert_eq!(v1.uids.get("b.M2"), Some(&2));
+        assert!(v1.is_tidy());
+    }
+
+    #[test]
+    fn info_letters_round_trip() {
+        let mut keywords = Keywords::default();
+        let flags: Vec<String> =
+            ["\\Seen", "\\Flagged", "replied", "archived", "$Forwarded", "\\Recent"].iter().map(|s| s.to_string()).collect();
+        let info = info(&flags, &mut keywords);
+        assert_eq!(info, ":2,FPRSa");
+        assert_eq!(
+            flags_of(&format!("1.M1P1Q1.host{}", info), &keywords),
+            vec!["\\Flagged", "$Forwarded", "\\Answered", "\\Seen", "archived"]
+        );
+        assert!(flags_of("1.M1P1Q1.host", &keywords).is_empty());
+    }
+}
@@ -0,0 +1,319 @@
+//! Vault verification: re-hash every stored message against its mailbox
+//! manifest, so bit rot and damage done behind the vault's back are caught
+//! while the server — or the backup mirror — may still have a good copy.
+//!
+//! A file whose bytes no longer match is moved to `quarantine/`, the same as a
+//! write a crash cut short, and put back from the backup mirror when the
+//! mirror's copy hashes to what was recorded; what's left is for the caller
+//! to re-fetch from the server (`to_refetch`). A recorded message with no file
+//! at all is only reported missing — a delete that bypassed `maildir::delete`
+//! looks the same, and re-fetching would bring it back. A message stored
+//! before manifests existed is hashed as it is and recorded from then on.
+
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+use super::encryption::{self, Sealer};
+use super::{compression, integrity, manifest};
+
+/// The external backup mirror `backup::run_account_backup` keeps, laid out
+/// `{root}/{email}/{mailbox}/cur/{uid}…`.
+pub struct BackupMirror {
+    pub root: PathBuf,
+    /// Account id → the address the mirror files that account under.
+    pub emails: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageRef {
+    pub account_id: String,
+    pub mailbox: String,
+    pub uid: u32,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MirrorCheck {
+    /// Mirror copies hashed against the vault's record.
+    pub checked: u64,
+    /// Recorded messages the mirror has no copy of.
+    pub missing: Vec<MessageRef>,
+    /// Mirror copies that don't match the vault's record.
+    pub corrupt: Vec<MessageRef>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VaultVerification {
+    pub mailboxes: u64,
+    /// Files hashed.
+    pub checked: u64,
+    /// Files with no record yet, recorded as they are now.
+    pub unrecorded: u64,
+    /// Files that no longer match their record; moved to `quarantine/`.
+    pub corrupt: Vec<MessageRef>,
+    /// Recorded messages with no file.
+    pub missing: Vec<MessageRef>,
+    /// Of `corrupt`, those put back from the backup mirror.
+    pub restored: Vec<MessageRef>,
+    /// `None` when no mirror was given.
+    pub backup: Option<MirrorCheck>,
+    pub errors: u64,
+}
+
+impl VaultVerification {
+    /// Corrupt messages the mirror couldn't put back, by account and mailbox.
+    pub fn to_refetch(&self) -> BTreeMap<(String, String), Vec<u32>> {
+        let restored: HashSet<&MessageRef> = self.restored.iter().collect();
+        let mut out: BTreeMap<(String, String), Vec<u32>> = BTreeMap::new();
+        for m in self.corrupt.iter().filter(|m| !restored.contains(m)) {
+            out.entry((m.account_id.clone(), m.mailbox.clone())).or_default().push(m.uid);
+        }
+        out
+    }
+}
+
+/// The uid a mirror filename belongs to. The mirror has used
+/// `<uid>:2,<flags>.eml`, `<uid>.eml` and `<uid>_<flags>.eml`; keep in step
+/// with `mirror_uid_of` in the app's backup module.
+fn mirror_uid_of(name: &str) -> Option<u32> {
+    name.split([':', '.', '_']).next()?.parse().ok()
+}
+
+fn mirror_files(dir: &Path) -> HashMap<u32, PathBuf> {
+    let mut out = HashMap::new();
+    if let Ok(entries) = fs::read_dir(dir) {
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if let Some(uid) = mirror_uid_of(&name) {
+                out.entry(uid).or_insert_with(|| entry.path());
+            }
+        }
+    }
+    out
+}
+
+/// Verify every mailbox in the vault, or one account's with `account_id`, and
+/// cross-check `mirror` when one is given.
+pub fn verify_vault(data_dir: &Path, account_id: Option<&str>, mirror: Option<&BackupMirror>) -> VaultVerification {
+    let root = data_dir.join("Maildir");
+    let mut report = VaultVerification {
+        backup: mirror.map(|_| MirrorCheck::default()),
+        ..Default::default()
+    };
+    for mailbox_dir in super::mailbox_dirs(&root) {
+        let Some((account, mailbox)) = super::mailbox_key(&root, &mailbox_dir) else { continue };
+        if account_id.is_some_and(|id| id != account) {
+            continue;
+        }
+        report.mailboxes += 1;
+        let at = |uid| MessageRef { account_id: account.clone(), mailbox: mailbox.clone(), uid };
+        let corrupt = verify_mailbox(&root, &mailbox_dir, &at, &mut report);
+
+        // Only accounts the mirror has a folder for; one never backed up
+        // isn't missing everything.
+        let mirror_dir = mirror
+            .and_then(|m| Some(m.root.join(m.emails.get(&account)?)))
+            .filter(|dir| dir.is_dir())
+            .map(|dir| dir.join(&mailbox).join("cur"));
+        if let Some(mirror_dir) = mirror_dir {
+            cross_check(data_dir, &mailbox_dir, &mirror_dir, &corrupt, &at, &mut report);
+        }
+    }
+    report.corrupt.sort();
+    report.missing.sort();
+    info!(
+        "verify_vault: {} mailboxes, {} files checked, {} newly recorded, {} corrupt, {} missing, {} restored from backup, {} errors",
+        report.mailboxes, report.checked, report.unrecorded, report.corrupt.len(), report.missing.len(),
+        report.restored.len(), report.errors,
+    );
+    report
+}
+
+/// Hash one mailbox against its manifest, quarantining what doesn't match,
+/// and compact the manifest. Returns the corrupt uids.
+fn verify_mailbox(
+    root: &Path,
+    mailbox_dir: &Path,
+    at: &dyn Fn(u32) -> MessageRef,
+    report: &mut VaultVerification,
+) -> HashSet<u32> {
+    let cur_dir = mailbox_dir.join("cur");
+    let vault = compression::vault_of(mailbox_dir);
+    let sealer = match vault.map(encryption::sealer).transpose() {
+        Ok(sealer) => sealer.unwrap_or_else(Sealer::plain),
+        Err(e) => {
+            warn!("verify_vault: {:?}: {}", mailbox_dir, e);
+            report.errors += 1;
+            return HashSet::new();
+        }
+    };
+    let mut recorded = manifest::read(mailbox_dir);
+    let mut corrupt = HashSet::new();
+    let mut entries = crate::uid_index::entries(&cur_dir);
+    entries.sort_unstable_by_key(|e| e.0);
+    let mut present: HashSet<u32> = entries.iter().map(|e| e.0).collect();
+
+    for (uid, entry) in entries {
+        let path = entry.path(&cur_dir);
+        let (size, sha256) = match integrity::hash_message(&path) {
+            Ok(h) => h,
+            // Removed behind the index's back.
+            Err(_) if !path.exists() => {
+                present.remove(&uid);
+                continue;
+            }
+            Err(e) => {
+                warn!("verify_vault: {}", e);
+                report.errors += 1;
+                continue;
+            }
+        };
+        report.checked += 1;
+        let held = recorded.get(&uid).map(|r| (r.size == size, encryption::digest_matches(vault, &r.sha256, &sha256)));
+        let matches = match held {
+            Some((same_size, Some(same_hash))) => same_size && same_hash,
+            // Stored before manifests, but after the uid index took a hash.
+            None => match entry.sha256 {
+                Some(indexed) if indexed != sha256 => {
+                    recorded.insert(uid, manifest::Recorded { size: entry.size, sha256: sealer.digest(&indexed) });
+                    false
+                }
+                _ => {
+                    recorded.insert(uid, manifest::Recorded { size, sha256: sealer.digest(&sha256) });
+                    report.unrecorded += 1;
+                    true
+                }
+            },
+            // Keyed under a key that's gone: nothing left to hold it to.
+            Some((_, None)) => {
+                recorded.insert(uid, manifest::Recorded { size, sha256: sealer.digest(&sha256) });
+                report.unrecorded += 1;
+                true
+            }
+        };
+        if matches {
+            continue;
+        }
+        warn!("verify_vault: UID {} in {:?} no longer matches its hash", uid, mailbox_dir);
+        corrupt.insert(uid);
+        report.corrupt.push(at(uid));
+        if let Err(e) = integrity::quarantine(root, mailbox_dir, uid, &path) {
+            warn!("verify_vault: {}", e);
+            report.errors += 1;
+        }
+    }
+    for &uid in recorded.keys().filter(|uid| !present.contains(uid)) {
+        report.missing.push(at(uid));
+    }
+
+    if !recorded.is_empty() {
+        if let Err(e) = manifest::write(mailbox_dir, &recorded) {
+            warn!("verify_vault: {}", e);
+            report.errors += 1;
+        }
+    }
+    corrupt
+}
+
+/// Check the mirror's copy of every recorded message, and put back each
+/// corrupt one whose mirror copy still matches.
+fn cross_check(
+    data_dir: &Path,
+    mailbox_dir: &Path,
+    mirror_dir: &Path,
+    corrupt: &HashSet<u32>,
+    at: &dyn Fn(u32) -> MessageRef,
+    report: &mut VaultVerification,
+) {
+    let cur_dir = mailbox_dir.join("cur");
+    let copies = mirror_files(mirror_dir);
+    let Some(mut check) = report.backup.take() else { return };
+    for (uid, r) in manifest::read(mailbox_dir) {
+        let Some(copy) = copies.get(&uid) else {
+            check.missing.push(at(uid));
+            continue;
+        };
+        check.checked += 1;
+        let Ok(bytes) = super::compression::read_message(copy) else {
+            report.errors += 1;
+            continue;
+        };
+        let same_hash = encryption::digest_matches(Some(data_dir), &r.sha256, &integrity::sha256_hex(&bytes));
+        if bytes.len() as u64 != r.size || same_hash == Some(false) {
+            check.corrupt.push(at(uid));
+            continue;
+        }
+        // No key to check it with, so not a copy to restore from either.
+        if same_hash.is_none() {
+            continue;
+        }
+        if !corrupt.contains(&uid) {
+            continue;
+        }
+        let name = copy.file_name().unwrap_or_default().to_string_lossy().to_string();
+        let flags = super::extract_flags_from_filename(&name);
+        match super::store_in(&cur_dir, uid, &bytes, &flags) {
+            Ok(_) => {
+                let m = at(uid);
+                crate::search::journal_store(data_dir, &m.account_id, &m.mailbox, uid);
+                report.restored.push(m);
+            }
+            Err(e) => {
+                warn!("verify_vault: restoring UID {} from backup: {}", uid, e);
+                report.errors += 1;
+            }
+        }
+    }
+    report.backup = Some(check);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::maildir::{cur_path, find_by_uid, store};
+
+    fn at(uid: u32) -> MessageRef {
+        MessageRef { account_id: "acc1".into(), mailbox: "INBOX".into(), uid }
+    }
+
+    #[test]
+    fn mirror_names_of_every_vintage_give_their_uid() {
+        assert_eq!(mirror_uid_of("12:2,S.eml"), Some(12));
+        assert_eq!(mirror_uid_of("12.eml"), Some(12));
+        assert_eq!(mirror_uid_of("12_S.eml"), Some(12));
+        assert_eq!(mirror_uid_of(".DS_Store"), None);
+    }
+
+    #[test]
+    fn only_what_the_mirror_didnt_restore_is_refetched() {
+        let report = VaultVerification { corrupt: vec![at(1), at(2), at(5)], restored: vec![at(2)], ..Default::default() };
+        assert_eq!(report.to_refetch().into_iter().collect::<Vec<_>>(), vec![(("acc1".into(), "INBOX".into()), vec![1, 5])]);
+    }
+
+    #[test]
+    fn keyed_digests_hold_an_encrypted_vault_to_its_messages() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("Maildir")).unwrap();
+        encryption::enable(dir.path(), Some("pass"), None).unwrap();
+        for uid in 1..=2 {
+            store(dir.path(), "acc1", "INBOX", uid, format!("Subject: {}\r\n\r\nbody\r\n", uid).as_bytes(), &[]).unwrap();
+        }
+        let report = verify_vault(dir.path(), None, None);
+        assert_eq!((report.checked, report.unrecorded, report.errors), (2, 0, 0));
+
+        // Re-sealed under the same key but holding other bytes: the seal is
+        // fine, the digest isn't.
+        let cur = cur_path(dir.path(), "acc1", "INBOX");
+        let sealer = encryption::sealer(dir.path()).unwrap();
+        let other = sealer.seal(b"Subject: 2\r\n\r\nbodY\r\n", compression::Compression::None).unwrap();
+        fs::write(find_by_uid(&cur, 2).unwrap(), other).unwrap();
+        let report = verify_vault(dir.path(), None, None);
+        assert_eq!((report.checked, report.errors), (2, 0));
+        assert_eq!(report.corrupt, vec![at(2)]);
+    }
+}
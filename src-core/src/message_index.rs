@@ -0,0 +1,317 @@
+//! Vault-wide Message-ID index: every place a message is stored, across
+//! accounts and folders.
+//!
+//! The same message turns up in more than one mailbox — INBOX and All Mail,
+//! a copy filed by a rule, the Sent copy of a reply-all — and migration,
+//! restore and threading all need to ask "where else is this?". This keeps
+//! normalized Message-ID → (account, mailbox, uid) for the whole vault, in
+//! memory and persisted at `Maildir/.message_index.json`.
+//!
+//! It is built from the per-mailbox uid indexes (see `crate::uid_index`),
+//! which already know each file's Message-ID, and never reads a message
+//! itself. Each mailbox records the uid index stamp it was last pulled at; a
+//! lookup re-pulls the mailboxes whose stamp moved. A write through the uid
+//! index — every `maildir::store`, delete and quarantine — patches the entry
+//! in the same step, so a sync doesn't leave the index to catch up.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use tracing::{info, warn};
+
+use crate::maildir::{self, compression, encryption};
+
+pub const INDEX_FILE: &str = ".message_index.json";
+
+/// One stored copy of a message.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Location {
+    pub account_id: String,
+    pub mailbox: String,
+    pub uid: u32,
+    pub path: PathBuf,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Mailbox {
+    account_id: String,
+    mailbox: String,
+    /// The uid index stamp (`uid_index::stamp`) the ids were pulled at.
+    stamp: u64,
+    ids: HashMap<u32, String>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct Index {
+    /// Keyed by the mailbox's path under the Maildir root.
+    mailboxes: BTreeMap<String, Mailbox>,
+    /// Message-ID → (mailbox key, uid). Rebuilt on load.
+    #[serde(skip)]
+    by_id: HashMap<String, Vec<(String, u32)>>,
+    #[serde(skip)]
+    dirty: bool,
+}
+
+impl Index {
+    fn build_by_id(&mut self) {
+        self.by_id.clear();
+        for (key, mailbox) in &self.mailboxes {
+            for (&uid, id) in &mailbox.ids {
+                self.by_id.entry(id.clone()).or_default().push((key.clone(), uid));
+            }
+        }
+    }
+
+    fn unlink(&mut self, key: &str, uid: u32, id: &str) {
+        if let Some(postings) = self.by_id.get_mut(id) {
+            postings.retain(|(k, u)| !(k == key && *u == uid));
+            if postings.is_empty() {
+                self.by_id.remove(id);
+            }
+        }
+    }
+
+    fn set(&mut self, key: &str, uid: u32, id: Option<String>) {
+        let Some(mailbox) = self.mailboxes.get_mut(key) else { return };
+        let old = match &id {
+            Some(id) => mailbox.ids.insert(uid, id.clone()),
+            None => mailbox.ids.remove(&uid),
+        };
+        if old == id {
+            return;
+        }
+        if let Some(old) = old {
+            self.unlink(key, uid, &old);
+        }
+        if let Some(id) = id {
+            self.by_id.entry(id).or_default().push((key.to_string(), uid));
+        }
+        self.dirty = true;
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some(old) = self.mailboxes.remove(key) {
+            for (uid, id) in old.ids {
+                self.unlink(key, uid, &id);
+            }
+            self.dirty = true;
+        }
+    }
+
+    fn replace(&mut self, key: String, mailbox: Mailbox) {
+        self.remove(&key);
+        for (&uid, id) in &mailbox.ids {
+            self.by_id.entry(id.clone()).or_default().push((key.clone(), uid));
+        }
+        self.mailboxes.insert(key, mailbox);
+        self.dirty = true;
+    }
+}
+
+/// One index per vault, loaded on first use.
+fn indexes() -> &'static Mutex<HashMap<PathBuf, Index>> {
+    static INDEXES: OnceLock<Mutex<HashMap<PathBuf, Index>>> = OnceLock::new();
+    INDEXES.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+fn index_path(data_dir: &Path) -> PathBuf {
+    data_dir.join("Maildir").join(INDEX_FILE)
+}
+
+/// The persisted index, or an empty one — the next reconcile fills it either
+/// way, so an unreadable file costs a pull of every uid index, not an error.
+fn load(data_dir: &Path) -> Index {
+    let mut index = encryption::read_sidecar(data_dir, &index_path(data_dir))
+        .ok()
+        .and_then(|b| serde_json::from_slice::<Index>(&b).ok())
+        .unwrap_or_default();
+    index.build_by_id();
+    index
+}
+
+fn save(data_dir: &Path, index: &mut Index) {
+    let path = index_path(data_dir);
+    let result = serde_json::to_vec(&*index)
+        .map_err(|e| e.to_string())
+        .and_then(|bytes| encryption::write_sidecar(data_dir, &path, &bytes));
+    match result {
+        Ok(()) => index.dirty = false,
+        Err(e) => warn!("message_index: failed to save {:?}: {}", path, e),
+    }
+}
+
+/// Re-pull every mailbox whose uid index moved on since it was last pulled,
+/// and drop the ones that are gone. The uid indexes are read without this
+/// index's lock held: a write holds its uid index and then patches this one.
+fn reconcile(data_dir: &Path) {
+    let root = data_dir.join("Maildir");
+    let listed: Vec<(String, String, String, PathBuf, u64)> = maildir::mailbox_dirs(&root)
+        .into_iter()
+        .filter_map(|mailbox_dir| {
+            let (account_id, mailbox) = maildir::mailbox_key(&root, &mailbox_dir)?;
+            let cur_dir = mailbox_dir.join("cur");
+            let stamp = crate::uid_index::stamp(&cur_dir)?;
+            Some((format!("{}/{}", account_id, mailbox), account_id, mailbox, cur_dir, stamp))
+        })
+        .collect();
+
+    let stale: Vec<_> = {
+        let mut all = indexes().lock().unwrap_or_else(|e| e.into_inner());
+        let index = all.entry(data_dir.to_path_buf()).or_insert_with(|| load(data_dir));
+        let gone: Vec<String> = index
+            .mailboxes
+            .keys()
+            .filter(|k| !listed.iter().any(|(key, ..)| key == *k))
+            .cloned()
+            .collect();
+        for key in gone {
+            index.remove(&key);
+        }
+        listed
+            .into_iter()
+            .filter(|(key, .., stamp)| index.mailboxes.get(key).is_none_or(|m| m.stamp != *stamp))
+            .collect()
+    };
+
+    let mut pulled = Vec::new();
+    for (key, account_id, mailbox, cur_dir, stamp) in stale {
+        let ids = crate::uid_index::entries(&cur_dir)
+            .into_iter()
+            .filter_map(|(uid, e)| Some((uid, maildir::normalize_message_id(&e.message_id?))))
+            .filter(|(_, id)| !id.is_empty())
+            .collect();
+        pulled.push((key, Mailbox { account_id, mailbox, stamp, ids }));
+    }
+
+    let mut all = indexes().lock().unwrap_or_else(|e| e.into_inner());
+    let index = all.entry(data_dir.to_path_buf()).or_insert_with(|| load(data_dir));
+    let count = pulled.len();
+    for (key, mailbox) in pulled {
+        index.replace(key, mailbox);
+    }
+    if count > 0 {
+        info!("message_index: pulled {} mailboxes, {} Message-IDs in the vault", count, index.by_id.len());
+    }
+    if index.dirty {
+        save(data_dir, index);
+    }
+}
+
+/// Every stored copy of each of `message_ids`, keyed by the id as given. An id
+/// with no copy in the vault maps to an empty list.
+pub fn locate_many(data_dir: &Path, message_ids: &[String]) -> HashMap<String, Vec<Location>> {
+    reconcile(data_dir);
+    // Paths are resolved after the lock is let go: a uid index lookup takes
+    // the mailbox's lock, and a write holds that while it patches this index.
+    let mut found: HashMap<String, Vec<Location>> = {
+        let all = indexes().lock().unwrap_or_else(|e| e.into_inner());
+        let Some(index) = all.get(data_dir) else { return HashMap::new() };
+        message_ids
+            .iter()
+            .map(|raw| {
+                let postings = index.by_id.get(&maildir::normalize_message_id(raw)).into_iter().flatten();
+                let copies = postings
+                    .filter_map(|(key, uid)| {
+                        let m = index.mailboxes.get(key)?;
+                        Some(Location { account_id: m.account_id.clone(), mailbox: m.mailbox.clone(), uid: *uid, path: PathBuf::new() })
+                    })
+                    .collect();
+                (raw.clone(), copies)
+            })
+            .collect()
+    };
+    for locations in found.values_mut() {
+        // A copy renamed or removed since the last pull isn't one.
+        locations.retain_mut(|l| {
+            let cur_dir = maildir::cur_path(data_dir, &l.account_id, &l.mailbox);
+            crate::uid_index::lookup(&cur_dir, l.uid).map(|path| l.path = path).is_some()
+        });
+        locations.sort_by(|a, b| (&a.account_id, &a.mailbox, a.uid).cmp(&(&b.account_id, &b.mailbox, b.uid)));
+    }
+    found
+}
+
+/// Every stored copy of `message_id`.
+pub fn locate(data_dir: &Path, message_id: &str) -> Vec<Location> {
+    let id = message_id.to_string();
+    locate_many(data_dir, std::slice::from_ref(&id)).remove(&id).unwrap_or_default()
+}
+
+/// Called by the uid index after a change to `uid` in `cur_dir`: the file now
+/// carries `message_id`, or is gone when that's `None`. `before` and `after`
+/// are the mailbox's stamps either side of the change; an index that was
+/// current before is current after. A mailbox it hasn't pulled yet, or a
+/// vault whose index isn't loaded, is left to the next reconcile.
+pub(crate) fn changed(cur_dir: &Path, uid: u32, message_id: Option<String>, before: u64, after: u64) {
+    let Some(data_dir) = compression::vault_of(cur_dir) else { return };
+    let Some(mailbox_dir) = cur_dir.parent() else { return };
+    let Some((account_id, mailbox)) = maildir::mailbox_key(&data_dir.join("Maildir"), mailbox_dir) else { return };
+    let key = format!("{}/{}", account_id, mailbox);
+    let mut all = indexes().lock().unwrap_or_else(|e| e.into_inner());
+    let Some(index) = all.get_mut(data_dir) else { return };
+    let mailbox = index
+        .mailboxes
+        .entry(key.clone())
+        .or_insert_with(|| Mailbox { account_id, mailbox, ..Default::default() });
+    if mailbox.stamp == before {
+        mailbox.stamp = after;
+    }
+    let id = message_id.map(|id| maildir::normalize_message_id(&id)).filter(|id| !id.is_empty());
+    index.set(&key, uid, id);
+    index.dirty = true;
+}
+
+/// Write every index with unsaved changes. Cheap when there are none.
+pub fn flush() {
+    let mut all = indexes().lock().unwrap_or_else(|e| e.into_inner());
+    for (data_dir, index) in all.iter_mut() {
+        if index.dirty {
+            save(data_dir, index);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn raw(id: &str) -> Vec<u8> {
+        format!("Message-ID: <{}>\r\nSubject: s\r\n\r\nbody", id).into_bytes()
+    }
+
+    #[test]
+    fn test_locates_copies_across_accounts_and_follows_changes() {
+        let dir = std::env::temp_dir().join("mailvault-test-message-index");
+        let _ = fs::remove_dir_all(&dir);
+        maildir::store(&dir, "acc1", "INBOX", 5, &raw("shared@x"), &[]).unwrap();
+        maildir::store(&dir, "acc1", "Archive/2024", 9, &raw("shared@x"), &[]).unwrap();
+        maildir::store(&dir, "acc2", "INBOX", 1, &raw("other@x"), &[]).unwrap();
+
+        let found = locate(&dir, "<shared@x>");
+        let at: Vec<_> = found.iter().map(|l| (l.account_id.as_str(), l.mailbox.as_str(), l.uid)).collect();
+        assert_eq!(at, [("acc1", "Archive/2024", 9), ("acc1", "INBOX", 5)]);
+        assert!(found.iter().all(|l| l.path.exists()));
+        assert!(locate(&dir, "missing@x").is_empty());
+
+        // Loaded now: a store or delete patches it in place.
+        maildir::store(&dir, "acc2", "Sent", 3, &raw("shared@x"), &[]).unwrap();
+        maildir::delete(&dir, "acc1", "INBOX", 5).unwrap();
+        let found = locate_many(&dir, &["shared@x".to_string(), "other@x".to_string()]);
+        let at: Vec<_> = found["shared@x"].iter().map(|l| (l.account_id.as_str(), l.uid)).collect();
+        assert_eq!(at, [("acc1", 9), ("acc2", 3)]);
+        assert_eq!(found["other@x"].len(), 1);
+
+        // A mailbox removed behind its back drops out at the next lookup, and
+        // a fresh process starts from what was saved.
+        flush();
+        fs::remove_dir_all(dir.join("Maildir").join("acc2").join("Sent")).unwrap();
+        indexes().lock().unwrap().remove(&dir);
+        assert!(index_path(&dir).exists());
+        assert_eq!(locate(&dir, "shared@x").len(), 1);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,169 @@
+//! How full the server mailbox is — the target archive-then-delete works to.
+//!
+//! An IMAP server with QUOTA (RFC 2087/9208) reports usage and a limit per
+//! quota root. Graph reports how much its folders hold but no limit a user
+//! token can read, so a Graph quota carries usage alone unless the caller
+//! knows the mailbox's size from elsewhere (see `with_limit`).
+
+use serde::{Deserialize, Serialize};
+
+/// One resource of a quota root as the server reported it. STORAGE is in
+/// units of 1024 octets, MESSAGE in messages.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QuotaResource {
+    pub name: String,
+    pub usage: u64,
+    /// 0 is no limit.
+    pub limit: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QuotaRoot {
+    pub name: String,
+    pub resources: Vec<QuotaResource>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MailboxQuota {
+    /// `"imap"` or `"graph"`.
+    pub source: String,
+    /// `None` when the server counts messages only.
+    pub used_bytes: Option<u64>,
+    /// `None` when storage is unlimited, or the server won't say.
+    pub limit_bytes: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message_count: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message_limit: Option<u64>,
+    /// IMAP: every root INBOX falls under, as sent.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub roots: Vec<QuotaRoot>,
+}
+
+impl MailboxQuota {
+    /// The quota of INBOX's roots. With several roots limiting the same
+    /// resource, the one closest to its limit is the one that bites. `None`
+    /// when no root limits STORAGE or MESSAGE.
+    pub fn from_imap_roots(roots: Vec<QuotaRoot>) -> Option<Self> {
+        let tightest = |name: &str| {
+            roots
+                .iter()
+                .flat_map(|r| r.resources.iter())
+                .filter(|r| r.name.eq_ignore_ascii_case(name))
+                .max_by(|a, b| ratio(a.usage, a.limit).total_cmp(&ratio(b.usage, b.limit)))
+                .map(|r| (r.usage, (r.limit > 0).then_some(r.limit)))
+        };
+        let storage = tightest("STORAGE");
+        let messages = tightest("MESSAGE");
+        if storage.is_none() && messages.is_none() {
+            return None;
+        }
+        Some(Self {
+            source: "imap".into(),
+            used_bytes: storage.map(|(used, _)| used.saturating_mul(1024)),
+            limit_bytes: storage.and_then(|(_, limit)| limit).map(|l| l.saturating_mul(1024)),
+            message_count: messages.map(|(used, _)| used),
+            message_limit: messages.and_then(|(_, limit)| limit),
+            roots,
+        })
+    }
+
+    /// Graph: what the mailbox's folders hold, with no limit.
+    pub fn from_graph_usage(used_bytes: u64) -> Self {
+        Self {
+            source: "graph".into(),
+            used_bytes: Some(used_bytes),
+            limit_bytes: None,
+            message_count: None,
+            message_limit: None,
+            roots: Vec::new(),
+        }
+    }
+
+    /// Fill in a storage limit the server didn't report.
+    pub fn with_limit(mut self, limit_bytes: Option<u64>) -> Self {
+        if self.limit_bytes.is_none() {
+            self.limit_bytes = limit_bytes.filter(|l| *l > 0);
+        }
+        self
+    }
+
+    /// How full, in percent, by whichever of storage and message count is
+    /// closer to its limit. `None` without a limit to measure against.
+    pub fn percent_used(&self) -> Option<f64> {
+        let storage = self.used_bytes.zip(self.limit_bytes).map(|(u, l)| ratio(u, l));
+        let messages = self.message_count.zip(self.message_limit).map(|(u, l)| ratio(u, l));
+        match (storage, messages) {
+            (Some(s), Some(m)) => Some(s.max(m) * 100.0),
+            (s, m) => s.or(m).map(|r| r * 100.0),
+        }
+    }
+
+    /// Bytes to delete from the server to bring storage down to `percent` of
+    /// the limit — 0 when it's already there. `None` without a storage limit.
+    pub fn bytes_to_free(&self, percent: u8) -> Option<u64> {
+        let (used, limit) = self.used_bytes.zip(self.limit_bytes)?;
+        let target = (limit as u128 * percent as u128 / 100) as u64;
+        Some(used.saturating_sub(target))
+    }
+}
+
+fn ratio(usage: u64, limit: u64) -> f64 {
+    if limit == 0 {
+        0.0
+    } else {
+        usage as f64 / limit as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn root(name: &str, resources: &[(&str, u64, u64)]) -> QuotaRoot {
+        QuotaRoot {
+            name: name.into(),
+            resources: resources
+                .iter()
+                .map(|(n, usage, limit)| QuotaResource { name: n.to_string(), usage: *usage, limit: *limit })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn the_tightest_root_sets_the_figures() {
+        let quota = MailboxQuota::from_imap_roots(vec![
+            root("", &[("STORAGE", 500, 1000)]),
+            root("shared", &[("STORAGE", 900, 1000), ("MESSAGE", 10, 100)]),
+        ])
+        .unwrap();
+        assert_eq!(quota.used_bytes, Some(900 * 1024));
+        assert_eq!(quota.limit_bytes, Some(1000 * 1024));
+        assert_eq!(quota.percent_used(), Some(90.0));
+        // Down to 80% of 1000 KiB: 100 KiB to go.
+        assert_eq!(quota.bytes_to_free(80), Some(100 * 1024));
+        assert_eq!(quota.bytes_to_free(95), Some(0));
+
+        // The message count can be what's nearly full.
+        let quota = MailboxQuota::from_imap_roots(vec![root("", &[("STORAGE", 1, 1000), ("MESSAGE", 99, 100)])]).unwrap();
+        assert_eq!(quota.percent_used(), Some(99.0));
+
+        assert_eq!(MailboxQuota::from_imap_roots(vec![root("", &[("X-ANNOTATIONS", 1, 2)])]), None);
+    }
+
+    #[test]
+    fn usage_without_a_limit_measures_nothing() {
+        let quota = MailboxQuota::from_graph_usage(5 << 30);
+        assert_eq!(quota.percent_used(), None);
+        assert_eq!(quota.bytes_to_free(80), None);
+
+        let quota = quota.with_limit(Some(10 << 30));
+        assert_eq!(quota.percent_used(), Some(50.0));
+        // A limit of 0 is none at all.
+        let quota = MailboxQuota::from_imap_roots(vec![root("", &[("STORAGE", 5, 0)])]).unwrap();
+        assert_eq!(quota.limit_bytes, None);
+    }
+}
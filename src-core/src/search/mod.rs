@@ -0,0 +1,1077 @@
+//! Local full-text search over the Maildir vault.
+//!
+//! Server-side search (`imap::search_emails`) only sees what is still on the
+//! server, and only its last 200 hits. This index covers every message the
+//! vault holds, across accounts, including mail the server has long deleted.
+//!
+//! An inverted index — term → the messages that contain it, with positions —
+//! persisted to `{data_dir}/search_index/index.json`. A save writes only what
+//! changed since the last one, as a numbered `segment-N.json` beside the
+//! snapshot; the segments are folded into a new snapshot once they add up to
+//! a quarter of the index, so a sync that touches ten messages writes ten
+//! messages, not the vault. It stays current two ways:
+//!
+//! - `maildir::store` / `maildir::delete` append to `journal.jsonl` beside it,
+//!   and `refresh` applies the journal before a query. Cheap, and what the
+//!   daemon's own writes go through.
+//! - `reconcile` diffs the index against the Maildir file names and indexes
+//!   or drops the difference. The app writes Maildir with its own code, and
+//!   `repair_generation` renumbers files; this is what catches those. It only
+//!   parses files it hasn't seen, so a pass over an unchanged vault is a
+//!   directory listing.
+//!
+//! The snapshot and segments hold every message's words, so an encrypted
+//! vault seals them like a message; the journal only names messages and stays
+//! plain.
+//!
+//! Queries are the search box language in `query`, the same one server search
+//! compiles to IMAP. Free-text terms are looked up in the index — a word,
+//! `"a phrase"` or `prefix*` — and rank hits by BM25, with subject and address
+//! matches counting for more than body matches. Every other term filters on
+//! what each entry records about its message.
+
+pub mod query;
+
+use crate::maildir;
+use crate::uid_index;
+use crate::types::EmailAddress;
+use query::{Candidate, Query, Term};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+pub const INDEX_DIR: &str = "search_index";
+const SNAPSHOT_FILE: &str = "index.json";
+const JOURNAL_FILE: &str = "journal.jsonl";
+const SEGMENT_PREFIX: &str = "segment-";
+
+/// Segments loaded on top of the snapshot at most; the save that would go
+/// past this folds them in instead.
+const MAX_SEGMENTS: usize = 64;
+
+/// Journal files one `apply_journal` works through. A writer appending as
+/// fast as this applies must not keep a query waiting.
+const MAX_JOURNAL_PASSES: usize = 4;
+
+/// How stale the reconcile pass may get before `refresh` runs it again.
+const RECONCILE_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// Body text past this is not indexed — a mailing-list digest or a pasted log
+/// would otherwise dominate the index.
+const MAX_BODY_CHARS: usize = 256 * 1024;
+const MAX_TERM_LEN: usize = 64;
+const MIN_PREFIX_LEN: usize = 2;
+
+/// Positions are `field * FIELD_STRIDE + offset`, so a phrase can never match
+/// across the end of one field into the next, and a position says which field
+/// it came from.
+const FIELD_STRIDE: u32 = 1 << 24;
+const FIELD_SUBJECT: u32 = 0;
+const FIELD_ADDRESS: u32 = 1;
+const FIELD_BODY: u32 = 2;
+const FIELD_ATTACHMENT: u32 = 3;
+
+fn field_weight(pos: u32) -> f32 {
+    match pos / FIELD_STRIDE {
+        FIELD_SUBJECT => 3.0,
+        FIELD_ADDRESS => 2.0,
+        FIELD_ATTACHMENT => 1.5,
+        _ => 1.0,
+    }
+}
+
+// BM25 constants, the usual defaults.
+const BM25_K1: f32 = 1.2;
+const BM25_B: f32 = 0.75;
+
+/// One message in the vault.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DocKey {
+    pub account_id: String,
+    pub mailbox: String,
+    pub uid: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Doc {
+    key: DocKey,
+    /// The timestamp part of the file name: a file renumbered onto this uid
+    /// by `repair_generation` carries a different one.
+    stamp: String,
+    /// From the file name, kept current without a re-parse.
+    flags: Vec<String>,
+    subject: String,
+    from: String,
+    to: String,
+    cc: String,
+    date: String,
+    /// `date`, parsed. `None` if it didn't parse.
+    timestamp: Option<i64>,
+    size: u64,
+    has_attachment: bool,
+    /// Token count, for BM25 length normalisation.
+    len: u32,
+    /// Distinct terms, so removal doesn't scan the whole vocabulary.
+    terms: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Posting {
+    doc: u32,
+    /// Sorted.
+    pos: Vec<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchHit {
+    pub account_id: String,
+    pub mailbox: String,
+    pub uid: u32,
+    pub score: f32,
+    pub subject: String,
+    pub from: String,
+    pub date: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "camelCase")]
+enum JournalEntry {
+    Store(DocKey),
+    Delete(DocKey),
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SearchIndex {
+    docs: HashMap<u32, Doc>,
+    /// Ordered so a prefix query is a range scan.
+    postings: BTreeMap<String, Vec<Posting>>,
+    next_doc: u32,
+    total_len: u64,
+    /// Segments numbered from here on hold changes the snapshot lacks.
+    #[serde(default)]
+    next_segment: u64,
+    #[serde(skip)]
+    by_key: HashMap<DocKey, u32>,
+    /// Changes not yet saved.
+    #[serde(skip)]
+    pending: Pending,
+    /// Segments on disk past the snapshot, and the changes they hold.
+    #[serde(skip)]
+    segments: usize,
+    #[serde(skip)]
+    segment_changes: usize,
+    /// The segments on disk can't be trusted: fold at the next save.
+    #[serde(skip)]
+    fold: bool,
+    #[serde(skip)]
+    last_reconcile: Option<Instant>,
+}
+
+/// Doc ids changed since the last save. A doc added and removed again in
+/// between never reaches disk.
+#[derive(Debug, Default)]
+struct Pending {
+    added: BTreeSet<u32>,
+    /// Saved docs whose flags changed.
+    flagged: BTreeSet<u32>,
+    /// Saved docs removed.
+    removed: BTreeSet<u32>,
+}
+
+impl Pending {
+    fn len(&self) -> usize {
+        self.added.len() + self.flagged.len() + self.removed.len()
+    }
+}
+
+/// One save's worth of changes, applied over the snapshot in number order.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Segment {
+    next_doc: u32,
+    #[serde(default)]
+    removed: Vec<u32>,
+    #[serde(default)]
+    added: Vec<SegmentDoc>,
+    #[serde(default)]
+    flags: Vec<(u32, Vec<String>)>,
+}
+
+impl Segment {
+    fn len(&self) -> usize {
+        self.removed.len() + self.added.len() + self.flags.len()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SegmentDoc {
+    id: u32,
+    doc: Doc,
+    /// The positions of each of `doc.terms`, in order.
+    pos: Vec<Vec<u32>>,
+}
+
+fn index_dir(data_dir: &Path) -> PathBuf {
+    data_dir.join(INDEX_DIR)
+}
+
+/// Record a Maildir write for the index to pick up. Nothing is recorded until
+/// an index exists — a vault nobody searches shouldn't grow a journal.
+pub(crate) fn journal_store(data_dir: &Path, account_id: &str, mailbox: &str, uid: u32) {
+    append_journal(data_dir, &JournalEntry::Store(key(account_id, mailbox, uid)));
+}
+
+pub(crate) fn journal_delete(data_dir: &Path, account_id: &str, mailbox: &str, uid: u32) {
+    append_journal(data_dir, &JournalEntry::Delete(key(account_id, mailbox, uid)));
+}
+
+fn key(account_id: &str, mailbox: &str, uid: u32) -> DocKey {
+    DocKey { account_id: account_id.to_string(), mailbox: mailbox.to_string(), uid }
+}
+
+fn append_journal(data_dir: &Path, entry: &JournalEntry) {
+    let dir = index_dir(data_dir);
+    if !dir.exists() {
+        return;
+    }
+    let line = match serde_json::to_string(entry) {
+        Ok(l) => l,
+        Err(_) => return,
+    };
+    let result = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(dir.join(JOURNAL_FILE))
+        .and_then(|mut f| writeln!(f, "{}", line));
+    // Not fatal: the next reconcile finds what the journal missed.
+    if let Err(e) = result {
+        warn!("[search] Failed to journal {:?}: {}", entry, e);
+    }
+}
+
+impl SearchIndex {
+    /// Load the persisted index — the snapshot, then its segments — or start
+    /// an empty one. An unreadable index is rebuilt by the next `reconcile`,
+    /// not an error; so is whatever an unreadable segment held.
+    pub fn load(data_dir: &Path) -> SearchIndex {
+        let dir = index_dir(data_dir);
+        let path = dir.join(SNAPSHOT_FILE);
+        let snapshot = maildir::encryption::read_sidecar(data_dir, &path)
+            .and_then(|raw| serde_json::from_slice::<SearchIndex>(&raw).map_err(|e| e.to_string()));
+        let mut index = match snapshot {
+            Ok(index) => index,
+            Err(e) => {
+                if path.exists() {
+                    warn!("[search] Discarding unreadable index {:?}: {}", path, e);
+                }
+                // Segments only mean something over their snapshot.
+                return SearchIndex { fold: true, ..Default::default() };
+            }
+        };
+        index.by_key = index.docs.iter().map(|(id, d)| (d.key.clone(), *id)).collect();
+        let first = index.next_segment;
+        for (n, path) in segment_files(&dir).into_iter().filter(|(n, _)| *n >= first) {
+            let segment = maildir::encryption::read_sidecar(data_dir, &path)
+                .and_then(|raw| serde_json::from_slice::<Segment>(&raw).map_err(|e| e.to_string()));
+            match segment {
+                Ok(segment) => {
+                    index.segments += 1;
+                    index.segment_changes += segment.len();
+                    index.next_segment = n + 1;
+                    index.apply_segment(segment);
+                }
+                // Later segments build on this one: stop here, and let
+                // reconcile find what they held.
+                Err(e) => {
+                    warn!("[search] Ignoring unreadable segment {:?} and those after it: {}", path, e);
+                    index.fold = true;
+                    break;
+                }
+            }
+        }
+        index
+    }
+
+    /// Write the changes since the last save: as a new segment, or folded
+    /// with the segments before it into a new snapshot once they add up to a
+    /// quarter of the index.
+    pub fn save(&mut self, data_dir: &Path) -> Result<(), String> {
+        if self.pending.len() == 0 {
+            return Ok(());
+        }
+        let dir = index_dir(data_dir);
+        fs::create_dir_all(&dir).map_err(|e| format!("Failed to create {:?}: {}", dir, e))?;
+        let changes = self.segment_changes + self.pending.len();
+        if self.fold || self.segments + 1 >= MAX_SEGMENTS || changes * 4 > self.docs.len() {
+            self.write_snapshot(data_dir, &dir)?;
+        } else {
+            self.write_segment(data_dir, &dir)?;
+        }
+        self.pending = Pending::default();
+        Ok(())
+    }
+
+    fn write_snapshot(&mut self, data_dir: &Path, dir: &Path) -> Result<(), String> {
+        // Number past every segment on disk, readable or not, so none of them
+        // is ever applied over this snapshot.
+        let segments = segment_files(dir);
+        if let Some((last, _)) = segments.last() {
+            self.next_segment = self.next_segment.max(last + 1);
+        }
+        let json = serde_json::to_vec(self).map_err(|e| format!("Failed to serialize search index: {}", e))?;
+        maildir::encryption::write_sidecar(data_dir, &dir.join(SNAPSHOT_FILE), &json)?;
+        for (_, path) in segments {
+            let _ = fs::remove_file(path);
+        }
+        self.segments = 0;
+        self.segment_changes = 0;
+        self.fold = false;
+        Ok(())
+    }
+
+    fn write_segment(&mut self, data_dir: &Path, dir: &Path) -> Result<(), String> {
+        let mut segment = Segment { next_doc: self.next_doc, ..Default::default() };
+        segment.removed = self.pending.removed.iter().copied().collect();
+        for &id in &self.pending.added {
+            let Some(doc) = self.docs.get(&id) else { continue };
+            let pos = doc
+                .terms
+                .iter()
+                .map(|term| {
+                    let list = self.postings.get(term).map(Vec::as_slice).unwrap_or_default();
+                    match list.binary_search_by_key(&id, |p| p.doc) {
+                        Ok(i) => list[i].pos.clone(),
+                        Err(_) => Vec::new(),
+                    }
+                })
+                .collect();
+            segment.added.push(SegmentDoc { id, doc: doc.clone(), pos });
+        }
+        for &id in &self.pending.flagged {
+            if let Some(doc) = self.docs.get(&id) {
+                segment.flags.push((id, doc.flags.clone()));
+            }
+        }
+        let json = serde_json::to_vec(&segment).map_err(|e| format!("Failed to serialize search segment: {}", e))?;
+        let path = dir.join(format!("{}{:06}.json", SEGMENT_PREFIX, self.next_segment));
+        maildir::encryption::write_sidecar(data_dir, &path, &json)?;
+        self.next_segment += 1;
+        self.segments += 1;
+        self.segment_changes += segment.len();
+        Ok(())
+    }
+
+    fn apply_segment(&mut self, segment: Segment) {
+        for id in segment.removed {
+            self.drop_doc(id);
+        }
+        for SegmentDoc { id, doc, pos } in segment.added {
+            self.drop_doc(id);
+            for (term, pos) in doc.terms.iter().zip(pos) {
+                // Ids only grow, so pushing keeps every list sorted by doc.
+                self.postings.entry(term.clone()).or_default().push(Posting { doc: id, pos });
+            }
+            self.total_len += doc.len as u64;
+            self.by_key.insert(doc.key.clone(), id);
+            self.docs.insert(id, doc);
+        }
+        for (id, flags) in segment.flags {
+            if let Some(doc) = self.docs.get_mut(&id) {
+                doc.flags = flags;
+            }
+        }
+        self.next_doc = self.next_doc.max(segment.next_doc);
+    }
+
+    pub fn len(&self) -> usize {
+        self.docs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.docs.is_empty()
+    }
+
+    /// Bring the index up to date before a query: apply the journal, and run
+    /// `reconcile` if this process hasn't in a while.
+    pub fn refresh(&mut self, data_dir: &Path) {
+        let _ = fs::create_dir_all(index_dir(data_dir));
+        if let Err(e) = self.apply_journal(data_dir) {
+            warn!("[search] {}", e);
+        }
+        if self.last_reconcile.is_none_or(|t| t.elapsed() >= RECONCILE_INTERVAL) {
+            self.reconcile(data_dir);
+        }
+    }
+
+    /// Apply and clear the journal. The file is renamed away first, so a
+    /// write journalled meanwhile lands in a fresh file — applied here too,
+    /// up to `MAX_JOURNAL_PASSES` files, and otherwise next time.
+    pub fn apply_journal(&mut self, data_dir: &Path) -> Result<(), String> {
+        let dir = index_dir(data_dir);
+        let live = dir.join(JOURNAL_FILE);
+        let applying = dir.join(format!("{}.applying", JOURNAL_FILE));
+        for _ in 0..MAX_JOURNAL_PASSES {
+            // A leftover from an interrupted run is applied before the live one.
+            if !applying.exists() {
+                if !live.exists() {
+                    return Ok(());
+                }
+                fs::rename(&live, &applying).map_err(|e| format!("Failed to take {:?}: {}", live, e))?;
+            }
+            let file = fs::File::open(&applying).map_err(|e| format!("Failed to open {:?}: {}", applying, e))?;
+            let mut latest: HashMap<DocKey, bool> = HashMap::new();
+            for line in BufReader::new(file).lines().map_while(Result::ok) {
+                match serde_json::from_str::<JournalEntry>(&line) {
+                    Ok(JournalEntry::Store(k)) => { latest.insert(k, true); }
+                    Ok(JournalEntry::Delete(k)) => { latest.insert(k, false); }
+                    Err(_) => {}
+                }
+            }
+            for (key, stored) in latest {
+                self.remove(&key);
+                if stored {
+                    self.index_file(data_dir, key);
+                }
+            }
+            // Left in place, it would be applied again on every call.
+            fs::remove_file(&applying).map_err(|e| format!("Failed to clear {:?}: {}", applying, e))?;
+        }
+        Ok(())
+    }
+
+    /// Diff the index against the Maildir files on disk: index what it is
+    /// missing, drop what is gone.
+    pub fn reconcile(&mut self, data_dir: &Path) {
+        let on_disk = scan_vault(data_dir);
+        let stale: Vec<DocKey> = self
+            .docs
+            .values()
+            .filter(|d| on_disk.get(&d.key).is_none_or(|e| file_stamp(&e.file) != d.stamp))
+            .map(|d| d.key.clone())
+            .collect();
+        for key in &stale {
+            self.remove(key);
+        }
+        let mut added = 0;
+        for (key, entry) in on_disk {
+            match self.by_key.get(&key) {
+                Some(id) => self.set_flags(*id, entry.flags),
+                None => {
+                    if self.index_file(data_dir, key) {
+                        added += 1;
+                    }
+                }
+            }
+        }
+        if added > 0 || !stale.is_empty() {
+            info!("[search] Reconciled: {} indexed, {} dropped, {} total", added, stale.len(), self.docs.len());
+        }
+        self.last_reconcile = Some(Instant::now());
+    }
+
+    fn index_file(&mut self, data_dir: &Path, key: DocKey) -> bool {
+        let cur = maildir::cur_path(data_dir, &key.account_id, &key.mailbox);
+        let Some(entry) = uid_index::entry(&cur, key.uid) else { return false };
+        let path = entry.path(&cur);
+        let stamp = file_stamp(&entry.file);
+        let flags = entry.flags;
+        // Same file, renamed for a flag change: nothing to re-parse.
+        if let Some(&id) = self.by_key.get(&key) {
+            if self.docs.get(&id).is_some_and(|d| d.stamp == stamp) {
+                self.set_flags(id, flags);
+                return true;
+            }
+        }
+        match maildir::compression::read_message(&path) {
+            Ok(raw) => {
+                self.add(key, stamp, flags, &raw);
+                true
+            }
+            Err(e) => {
+                warn!("[search] Failed to read {:?}: {}", path, e);
+                false
+            }
+        }
+    }
+
+    fn set_flags(&mut self, id: u32, flags: Vec<String>) {
+        if let Some(doc) = self.docs.get_mut(&id) {
+            if doc.flags != flags {
+                doc.flags = flags;
+                if !self.pending.added.contains(&id) {
+                    self.pending.flagged.insert(id);
+                }
+            }
+        }
+    }
+
+    /// Index one message, replacing whatever was indexed under its key.
+    pub fn add(&mut self, key: DocKey, stamp: String, flags: Vec<String>, raw: &[u8]) {
+        self.remove(&key);
+        let email = match maildir::parse_full(raw, key.uid) {
+            Ok(e) => e,
+            Err(e) => {
+                warn!("[search] Skipping {}/{}/{}: {}", key.account_id, key.mailbox, key.uid, e);
+                return;
+            }
+        };
+
+        let mut positions: HashMap<String, Vec<u32>> = HashMap::new();
+        let mut len = 0u32;
+        let mut push = |field: u32, text: &str| {
+            for (i, term) in tokenize(text).enumerate() {
+                let Ok(i) = u32::try_from(i) else { break };
+                if i >= FIELD_STRIDE {
+                    break;
+                }
+                positions.entry(term).or_default().push(field * FIELD_STRIDE + i);
+                len += 1;
+            }
+        };
+
+        push(FIELD_SUBJECT, &email.subject);
+        let addresses: Vec<String> = email
+            .from
+            .iter()
+            .chain(&email.to)
+            .chain(&email.cc)
+            .map(|a| format!("{} {}", a.name.as_deref().unwrap_or(""), a.address))
+            .collect();
+        push(FIELD_ADDRESS, &addresses.join(" "));
+        let body = match (&email.text, &email.html) {
+            (Some(text), _) if !text.trim().is_empty() => text.clone(),
+            (_, Some(html)) => html_text(html),
+            _ => String::new(),
+        };
+        let body: String = body.chars().take(MAX_BODY_CHARS).collect();
+        push(FIELD_BODY, &body);
+        let names: Vec<&str> = email.attachments.iter().map(|a| a.filename.as_str()).collect();
+        push(FIELD_ATTACHMENT, &names.join(" "));
+
+        let id = self.next_doc;
+        self.next_doc += 1;
+        let mut terms = Vec::with_capacity(positions.len());
+        for (term, pos) in positions {
+            self.postings.entry(term.clone()).or_default().push(Posting { doc: id, pos });
+            terms.push(term);
+        }
+        let doc = Doc {
+            key: key.clone(),
+            stamp,
+            flags,
+            from: email.from.as_ref().map(display_address).unwrap_or_default(),
+            to: email.to.iter().map(display_address).collect::<Vec<_>>().join(", "),
+            cc: email.cc.iter().map(display_address).collect::<Vec<_>>().join(", "),
+            timestamp: mailparse::dateparse(&email.date).ok(),
+            date: email.date,
+            subject: email.subject,
+            size: raw.len() as u64,
+            has_attachment: !email.attachments.is_empty(),
+            len,
+            terms,
+        };
+        self.total_len += len as u64;
+        self.by_key.insert(key, id);
+        self.docs.insert(id, doc);
+        self.pending.added.insert(id);
+    }
+
+    pub fn remove(&mut self, key: &DocKey) -> bool {
+        let Some(&id) = self.by_key.get(key) else { return false };
+        if !self.drop_doc(id) {
+            return false;
+        }
+        self.pending.flagged.remove(&id);
+        if !self.pending.added.remove(&id) {
+            self.pending.removed.insert(id);
+        }
+        true
+    }
+
+    /// Take doc `id` out of every structure that holds it.
+    fn drop_doc(&mut self, id: u32) -> bool {
+        let Some(doc) = self.docs.remove(&id) else { return false };
+        if self.by_key.get(&doc.key) == Some(&id) {
+            self.by_key.remove(&doc.key);
+        }
+        for term in &doc.terms {
+            if let Some(list) = self.postings.get_mut(term) {
+                list.retain(|p| p.doc != id);
+                if list.is_empty() {
+                    self.postings.remove(term);
+                }
+            }
+        }
+        self.total_len = self.total_len.saturating_sub(doc.len as u64);
+        true
+    }
+
+    /// Hits for `query`, best first, limited to `account_ids` when given. A
+    /// query that matches without free text (`is:unread from:bob`) lists
+    /// newest first. An empty query matches nothing.
+    pub fn search(&self, query: &str, account_ids: Option<&[String]>, limit: usize) -> Result<Vec<SearchHit>, String> {
+        let query = Query::parse(query)?;
+        if query.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut hits: Vec<(u32, f32)> = self
+            .eval(&query)
+            .into_iter()
+            .filter(|(doc, _)| account_ids.is_none_or(|ids| ids.iter().any(|a| *a == self.docs[doc].key.account_id)))
+            .collect();
+        hits.sort_by(|a, b| {
+            b.1.total_cmp(&a.1)
+                .then(self.docs[&b.0].timestamp.cmp(&self.docs[&a.0].timestamp))
+                .then(b.0.cmp(&a.0))
+        });
+        Ok(hits
+            .into_iter()
+            .take(limit)
+            .map(|(doc, score)| {
+                let d = &self.docs[&doc];
+                SearchHit {
+                    account_id: d.key.account_id.clone(),
+                    mailbox: d.key.mailbox.clone(),
+                    uid: d.key.uid,
+                    score,
+                    subject: d.subject.clone(),
+                    from: d.from.clone(),
+                    date: d.date.clone(),
+                }
+            })
+            .collect())
+    }
+
+    /// Matching documents with their score. Only free text scores; `OR`
+    /// adds the scores of the branches that matched.
+    fn eval(&self, query: &Query) -> HashMap<u32, f32> {
+        match query {
+            Query::And(items) => {
+                let mut acc: Option<HashMap<u32, f32>> = None;
+                for item in items {
+                    let next = self.eval(item);
+                    acc = Some(match acc {
+                        None => next,
+                        Some(prev) => prev
+                            .into_iter()
+                            .filter_map(|(doc, s)| next.get(&doc).map(|n| (doc, s + n)))
+                            .collect(),
+                    });
+                }
+                acc.unwrap_or_default()
+            }
+            Query::Or(items) => {
+                let mut acc: HashMap<u32, f32> = HashMap::new();
+                for item in items {
+                    for (doc, s) in self.eval(item) {
+                        *acc.entry(doc).or_default() += s;
+                    }
+                }
+                acc
+            }
+            Query::Not(inner) => {
+                let excluded = self.eval(inner);
+                self.docs.keys().filter(|d| !excluded.contains_key(d)).map(|d| (*d, 0.0)).collect()
+            }
+            Query::Term(term @ (Term::Text(_) | Term::Phrase(_))) => self.score_text(term),
+            Query::Term(term) => self
+                .docs
+                .iter()
+                .filter(|(_, d)| term.matches(&d.candidate()))
+                .map(|(id, _)| (*id, 0.0))
+                .collect(),
+        }
+    }
+
+    /// BM25 over the index for one free-text term. A word that tokenizes to
+    /// several terms (`alice@example.com`) must match them as a phrase.
+    fn score_text(&self, term: &Term) -> HashMap<u32, f32> {
+        let clause = match term {
+            Term::Text(word) => {
+                let terms: Vec<String> = tokenize(word).collect();
+                match terms.as_slice() {
+                    [stem] if word.ends_with('*') && stem.chars().count() >= MIN_PREFIX_LEN => {
+                        Some(Clause::Prefix(stem.clone()))
+                    }
+                    _ => words_clause(terms),
+                }
+            }
+            Term::Phrase(phrase) => words_clause(tokenize(phrase).collect()),
+            _ => None,
+        };
+        let Some(clause) = clause else { return HashMap::new() };
+        let matches = self.match_clause(&clause);
+        let idf = self.idf(matches.len());
+        matches.into_iter().map(|(doc, tf)| (doc, idf * self.saturate(doc, tf))).collect()
+    }
+
+    fn idf(&self, df: usize) -> f32 {
+        let n = self.docs.len() as f32;
+        let df = df as f32;
+        (1.0 + (n - df + 0.5) / (df + 0.5)).ln()
+    }
+
+    fn saturate(&self, doc: u32, tf: f32) -> f32 {
+        let avg = (self.total_len as f32 / self.docs.len().max(1) as f32).max(1.0);
+        let len = self.docs.get(&doc).map_or(avg, |d| d.len as f32);
+        tf * (BM25_K1 + 1.0) / (tf + BM25_K1 * (1.0 - BM25_B + BM25_B * len / avg))
+    }
+
+    /// Field-weighted term frequency of `clause` in every document it matches.
+    fn match_clause(&self, clause: &Clause) -> HashMap<u32, f32> {
+        let mut out: HashMap<u32, f32> = HashMap::new();
+        match clause {
+            Clause::Term(term) => {
+                for p in self.postings.get(term).into_iter().flatten() {
+                    out.insert(p.doc, p.pos.iter().map(|&x| field_weight(x)).sum());
+                }
+            }
+            Clause::Prefix(stem) => {
+                for (_, list) in self.postings.range(stem.clone()..).take_while(|(t, _)| t.starts_with(stem.as_str())) {
+                    for p in list {
+                        *out.entry(p.doc).or_default() += p.pos.iter().map(|&x| field_weight(x)).sum::<f32>();
+                    }
+                }
+            }
+            Clause::Phrase(words) => {
+                let lists: Option<Vec<HashMap<u32, &Vec<u32>>>> = words
+                    .iter()
+                    .map(|w| self.postings.get(w).map(|l| l.iter().map(|p| (p.doc, &p.pos)).collect()))
+                    .collect();
+                let Some(lists) = lists else { return out };
+                let Some((first, rest)) = lists.split_first() else { return out };
+                for (doc, starts) in first {
+                    let tf: f32 = starts
+                        .iter()
+                        .filter(|&&start| {
+                            rest.iter().enumerate().all(|(i, list)| {
+                                list.get(doc)
+                                    .is_some_and(|pos| pos.binary_search(&(start + i as u32 + 1)).is_ok())
+                            })
+                        })
+                        .map(|&start| field_weight(start))
+                        .sum();
+                    if tf > 0.0 {
+                        out.insert(*doc, tf);
+                    }
+                }
+            }
+        }
+        out
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Clause {
+    Term(String),
+    Prefix(String),
+    Phrase(Vec<String>),
+}
+
+fn words_clause(mut terms: Vec<String>) -> Option<Clause> {
+    match terms.len() {
+        0 => None,
+        1 => terms.pop().map(Clause::Term),
+        _ => Some(Clause::Phrase(terms)),
+    }
+}
+
+/// Lowercased alphanumeric runs. Everything else separates terms.
+fn tokenize(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|t| !t.is_empty() && t.len() <= MAX_TERM_LEN)
+        .map(str::to_lowercase)
+}
+
+/// The visible text of an HTML body: tags dropped, `<style>` and `<script>`
+/// contents skipped, the common entities decoded.
+fn html_text(html: &str) -> String {
+    let mut out = String::with_capacity(html.len() / 2);
+    let mut rest = html;
+    while let Some(open) = rest.find('<') {
+        out.push_str(&rest[..open]);
+        out.push(' ');
+        let tag = &rest[open + 1..];
+        let Some(close) = tag.find('>') else { break };
+        let name: String = tag[..close]
+            .trim_start()
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric())
+            .collect::<String>()
+            .to_ascii_lowercase();
+        rest = &tag[close + 1..];
+        if name == "style" || name == "script" {
+            let end = format!("</{}", name);
+            let lower = rest.to_ascii_lowercase();
+            rest = match lower.find(&end) {
+                Some(i) => &rest[i..],
+                None => "",
+            };
+        }
+    }
+    out.push_str(rest);
+    out.replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+}
+
+impl Doc {
+    fn candidate(&self) -> Candidate<'_> {
+        Candidate {
+            mailbox: &self.key.mailbox,
+            from: &self.from,
+            to: &self.to,
+            cc: &self.cc,
+            subject: &self.subject,
+            // Free text is answered by the index, never by this.
+            body: "",
+            flags: &self.flags,
+            size: self.size,
+            has_attachment: self.has_attachment,
+            date: self
+                .timestamp
+                .and_then(|t| chrono::DateTime::from_timestamp(t, 0))
+                .map(|t| t.date_naive()),
+        }
+    }
+}
+
+fn display_address(a: &EmailAddress) -> String {
+    match &a.name {
+        Some(name) if !name.is_empty() => format!("{} <{}>", name, a.address),
+        _ => a.address.clone(),
+    }
+}
+
+/// The part of a filename a flag change keeps: `timestamp` of
+/// `{uid}:{flags}:{timestamp}.eml`, or the base of a standard-layout name.
+fn file_stamp(name: &str) -> String {
+    // Compressing a file doesn't change the message in it.
+    let name = maildir::compression::strip_suffix(name);
+    if !name.contains(":2,") {
+        if let Some(ts) = name.splitn(3, ':').nth(2) {
+            return ts.to_string();
+        }
+    }
+    maildir::standard::base_of(name).to_string()
+}
+
+/// The segment files in `dir`, by number.
+fn segment_files(dir: &Path) -> Vec<(u64, PathBuf)> {
+    let mut out: Vec<(u64, PathBuf)> = fs::read_dir(dir)
+        .map(|d| d.flatten().collect::<Vec<_>>())
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let n = name.strip_prefix(SEGMENT_PREFIX)?.strip_suffix(".json")?.parse().ok()?;
+            Some((n, entry.path()))
+        })
+        .collect();
+    out.sort();
+    out
+}
+
+/// Every message in the vault, keyed like the index. A mailbox is any
+/// directory holding a `cur/`, named by its path under the account; its
+/// messages come from the uid index, so either Maildir layout reads the same.
+fn scan_vault(data_dir: &Path) -> HashMap<DocKey, uid_index::Entry> {
+    let mut out = HashMap::new();
+    let root = data_dir.join("Maildir");
+    let Ok(accounts) = fs::read_dir(&root) else { return out };
+    for account in accounts.flatten() {
+        if !account.path().is_dir() {
+            continue;
+        }
+        let account_id = account.file_name().to_string_lossy().to_string();
+        for entry in walkdir::WalkDir::new(account.path()).min_depth(2).into_iter().flatten() {
+            if !entry.file_type().is_dir() || entry.file_name() != "cur" {
+                continue;
+            }
+            let Some(mailbox_dir) = entry.path().parent() else { continue };
+            let Ok(rel) = mailbox_dir.strip_prefix(account.path()) else { continue };
+            let mailbox = rel.to_string_lossy().replace('\\', "/");
+            for (uid, e) in uid_index::entries(entry.path()) {
+                out.insert(key(&account_id, &mailbox, uid), e);
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eml(subject: &str, from: &str, body: &str) -> Vec<u8> {
+        format!("From: {}\r\nTo: me@example.com\r\nSubject: {}\r\nDate: Mon, 6 Apr 2026 10:00:00 +0000\r\n\r\n{}\r\n", from, subject, body)
+            .into_bytes()
+    }
+
+    fn search(index: &SearchIndex, q: &str) -> Vec<u32> {
+        index.search(q, None, 10).unwrap().iter().map(|h| h.uid).collect()
+    }
+
+    #[test]
+    fn ranks_phrases_and_prefixes() {
+        let mut index = SearchIndex::default();
+        let k = |uid| key("acc1", "INBOX", uid);
+        index.add(k(1), "1".into(), vec![], &eml("Quarterly report", "Alice <alice@example.com>", "Numbers attached."));
+        index.add(k(2), "2".into(), vec![], &eml("Lunch", "bob@example.com", "The quarterly report is late, report soon."));
+        index.add(k(3), "3".into(), vec![], &eml("Re: plans", "carol@example.com", "report quarterly figures"));
+        index.add(k(4), "4".into(), vec![], &eml("Reporting", "dan@example.com", "<unrelated>"));
+
+        // A subject match outranks a body match.
+        assert_eq!(search(&index, "quarterly report"), vec![1, 2, 3]);
+        // Word order matters in a phrase.
+        assert_eq!(search(&index, "\"quarterly report\""), vec![1, 2]);
+        assert_eq!(search(&index, "report*").len(), 4);
+        assert_eq!(search(&index, "alice@example.com"), vec![1]);
+        assert!(search(&index, "report* missing").is_empty());
+        assert!(search(&index, "\"\"").is_empty());
+        assert!(search(&index, "q*").is_empty(), "one-letter prefixes are not expanded");
+
+        assert!(index.remove(&k(1)));
+        assert_eq!(search(&index, "\"quarterly report\""), vec![2]);
+        assert!(!index.postings.contains_key("numbers"));
+    }
+
+    #[test]
+    fn filters_with_the_query_language() {
+        let mut index = SearchIndex::default();
+        let seen = vec!["\\Seen".to_string()];
+        index.add(key("acc1", "INBOX", 1), "1".into(), vec![], &eml("Invoice", "Alice <alice@example.com>", "due"));
+        index.add(key("acc1", "Sent", 2), "2".into(), seen.clone(), &eml("Re: Invoice", "me@example.com", "paid"));
+        index.add(key("acc1", "INBOX", 3), "3".into(), seen, &eml("Lunch", "bob@example.com", "invoice attached"));
+
+        assert_eq!(search(&index, "invoice is:unread"), vec![1]);
+        assert_eq!(search(&index, "invoice -in:inbox"), vec![2]);
+        assert_eq!(search(&index, "from:alice OR from:bob"), vec![3, 1], "no free text: newest first");
+        assert_eq!(search(&index, "(from:alice OR from:bob) invoice"), vec![1, 3], "free text still ranks");
+        assert_eq!(search(&index, "is:read before:2026/04/07 after:2026/04/06"), vec![3, 2]);
+        assert!(search(&index, "after:2026/04/07").is_empty());
+        assert!(index.search("from:", None, 10).is_err());
+    }
+
+    #[test]
+    fn saves_write_segments_and_fold_them_into_the_snapshot() {
+        let dir = tempfile::tempdir().unwrap();
+        let data = dir.path();
+        let k = |uid| key("acc1", "INBOX", uid);
+        let mut index = SearchIndex::default();
+        for uid in 1..=20 {
+            index.add(k(uid), uid.to_string(), vec![], &eml(&format!("Report {}", uid), "a@example.com", "numbers"));
+        }
+        index.save(data).unwrap();
+        let snapshot = index_dir(data).join(SNAPSHOT_FILE);
+        let written = fs::read(&snapshot).unwrap();
+        assert!(segment_files(&index_dir(data)).is_empty(), "a first save is a snapshot");
+
+        // A small change is a segment; the snapshot stays as it was.
+        index.remove(&k(1));
+        index.add(k(21), "21".into(), vec![], &eml("Budget", "b@example.com", "draft"));
+        index.add(k(22), "22".into(), vec![], &eml("Gone soon", "b@example.com", "draft"));
+        index.remove(&k(22));
+        let id = index.by_key[&k(2)];
+        index.set_flags(id, vec!["\\Seen".to_string()]);
+        index.save(data).unwrap();
+        assert_eq!(fs::read(&snapshot).unwrap(), written);
+        assert_eq!(segment_files(&index_dir(data)).len(), 1);
+
+        let loaded = SearchIndex::load(data);
+        assert_eq!(loaded.len(), 20);
+        assert_eq!(search(&loaded, "budget"), vec![21]);
+        assert!(search(&loaded, "gone").is_empty());
+        assert_eq!(search(&loaded, "report is:read"), vec![2]);
+        assert_eq!(loaded.search("report", None, 100).unwrap().len(), 19);
+        assert_eq!(loaded.total_len, index.total_len);
+
+        // Enough change folds every segment back into the snapshot.
+        let mut index = loaded;
+        for uid in 2..=10 {
+            index.remove(&k(uid));
+        }
+        index.save(data).unwrap();
+        assert!(segment_files(&index_dir(data)).is_empty());
+        let loaded = SearchIndex::load(data);
+        assert_eq!(loaded.len(), 11);
+        assert_eq!(search(&loaded, "budget"), vec![21]);
+        assert_eq!(loaded.next_doc, index.next_doc);
+    }
+
+    /// A journal that can't be cleared is an error, not a loop: left in
+    /// place, it used to be applied again, and again, until the stack gave.
+    #[test]
+    fn a_journal_that_cannot_be_cleared_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let data = dir.path();
+        fs::create_dir_all(index_dir(data).join(format!("{}.applying", JOURNAL_FILE))).unwrap();
+        maildir::store(data, "acc1", "INBOX", 1, &eml("Invoice", "a@example.com", "pay"), &[]).unwrap();
+        assert!(index_dir(data).join(JOURNAL_FILE).exists());
+
+        let mut index = SearchIndex::default();
+        assert!(index.apply_journal(data).is_err());
+        assert!(index.is_empty());
+        // refresh carries on to reconcile regardless.
+        index.refresh(data);
+        assert_eq!(search(&index, "invoice"), vec![1]);
+    }
+
+    #[test]
+    fn strips_html_bodies() {
+        let text = html_text("<style>p { color: red }</style><p>Hello&nbsp;<b>world</b> &amp; more</p>");
+        assert_eq!(tokenize(&text).collect::<Vec<_>>(), vec!["hello", "world", "more"]);
+    }
+
+    #[test]
+    fn follows_maildir_writes_and_reconciles_foreign_ones() {
+        let dir = tempfile::tempdir().unwrap();
+        let data = dir.path();
+
+        // Existing vault, no index yet: the first refresh builds it.
+        maildir::store(data, "acc1", "INBOX", 1, &eml("Invoice 42", "a@example.com", "pay"), &[]).unwrap();
+        assert!(!index_dir(data).join(JOURNAL_FILE).exists());
+        let mut index = SearchIndex::load(data);
+        index.refresh(data);
+        assert_eq!(search(&index, "invoice"), vec![1]);
+        index.save(data).unwrap();
+
+        // Writes through maildir are journalled and picked up without a rescan.
+        maildir::store(data, "acc2", "Archive/2025", 7, &eml("Old invoice", "b@example.com", "paid"), &[]).unwrap();
+        maildir::delete(data, "acc1", "INBOX", 1).unwrap();
+        maildir::set_flags(data, "acc2", "Archive/2025", 7, &["\\Seen".to_string()]).unwrap();
+        let mut index = SearchIndex::load(data);
+        index.last_reconcile = Some(Instant::now());
+        index.refresh(data);
+        let hits = index.search("invoice is:read", None, 10).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!((hits[0].account_id.as_str(), hits[0].mailbox.as_str()), ("acc2", "Archive/2025"));
+        assert!(index.search("invoice", Some(&["acc1".to_string()]), 10).unwrap().is_empty());
+        assert!(!index_dir(data).join(JOURNAL_FILE).exists());
+
+        // A file written behind maildir's back is found by reconcile, and so
+        // is a flag change made by renaming it.
+        let cur = maildir::cur_path(data, "acc1", "Sent");
+        fs::create_dir_all(&cur).unwrap();
+        fs::write(cur.join("3::1700000000.eml"), eml("Invoice reply", "me@example.com", "thanks")).unwrap();
+        index.reconcile(data);
+        assert_eq!(search(&index, "invoice is:unread"), vec![3]);
+        fs::rename(cur.join("3::1700000000.eml"), cur.join("3:seen:1700000000.eml")).unwrap();
+        index.reconcile(data);
+        assert!(search(&index, "invoice is:unread").is_empty());
+        fs::remove_file(cur.join("3:seen:1700000000.eml")).unwrap();
+        index.reconcile(data);
+        assert_eq!(index.len(), 1);
+    }
+}
@@ -0,0 +1,494 @@
+//! The search box language, shared by server and vault search.
+//!
+//! Gmail-like: `from:`, `to:`, `cc:`, `subject:`, `has:attachment`,
+//! `larger:5M` / `smaller:100K`, `is:unread` / `is:read` / `is:starred`,
+//! `in:folder`, `before:` / `after:` (`2026/04/01` or `2026-04-01`). Terms
+//! next to each other must all match; `OR`, `NOT` (or a leading `-`) and
+//! parentheses combine them. Anything else is free text.
+//!
+//! `Query::parse` builds the tree once. `to_imap` compiles it to UID SEARCH
+//! criteria and `matches` evaluates it over a local message, with the same
+//! meaning on both sides: header terms are case-insensitive substring
+//! matches, `after:` includes its day and `before:` excludes it, sizes are
+//! whole-message bytes. The one thing IMAP can't say is `in:` below the top
+//! level — a SEARCH runs in one mailbox — so the server side reads a top-level
+//! `in:` as the mailbox to search and rejects any other.
+
+use chrono::NaiveDate;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Query {
+    /// Every item must match. Empty: the query had no terms.
+    And(Vec<Query>),
+    Or(Vec<Query>),
+    Not(Box<Query>),
+    Term(Term),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Term {
+    /// A bare word. A trailing `*` asks for a prefix match.
+    Text(String),
+    /// A `"quoted phrase"`.
+    Phrase(String),
+    From(String),
+    To(String),
+    Cc(String),
+    Subject(String),
+    HasAttachment,
+    /// Bytes.
+    Larger(u64),
+    Smaller(u64),
+    Unread,
+    Read,
+    Starred,
+    In(String),
+    /// Before this day.
+    Before(NaiveDate),
+    /// On or after this day.
+    After(NaiveDate),
+}
+
+/// A local message as the matcher sees it.
+#[derive(Debug, Clone, Default)]
+pub struct Candidate<'a> {
+    pub mailbox: &'a str,
+    pub from: &'a str,
+    pub to: &'a str,
+    pub cc: &'a str,
+    pub subject: &'a str,
+    /// Body text, searched by free-text terms along with the headers above.
+    pub body: &'a str,
+    pub flags: &'a [String],
+    pub size: u64,
+    pub has_attachment: bool,
+    pub date: Option<NaiveDate>,
+}
+
+// ── Parsing ─────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    Neg,
+    Word { text: String, quoted: bool },
+}
+
+fn lex(input: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' => {
+                chars.next();
+                tokens.push(Token::Open);
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::Close);
+            }
+            '-' => {
+                chars.next();
+                if chars.peek().is_some_and(|n| !n.is_whitespace()) {
+                    tokens.push(Token::Neg);
+                }
+            }
+            _ => {
+                // A word runs to whitespace or a parenthesis; a quoted run
+                // inside it (`subject:"two words"`) may hold either.
+                let mut text = String::new();
+                let mut quoted = false;
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || c == '(' || c == ')' {
+                        break;
+                    }
+                    chars.next();
+                    if c == '"' {
+                        quoted = true;
+                        loop {
+                            match chars.next() {
+                                Some('"') => break,
+                                Some(c) => text.push(c),
+                                None => return Err("Unterminated quote".to_string()),
+                            }
+                        }
+                    } else {
+                        text.push(c);
+                    }
+                }
+                tokens.push(Token::Word { text, quoted });
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn is_keyword(&self, kw: &str) -> bool {
+        matches!(self.peek(), Some(Token::Word { text, quoted: false }) if text == kw)
+    }
+
+    /// or := and ("OR" and)*
+    fn or(&mut self) -> Result<Query, String> {
+        let mut items = vec![self.and()?];
+        while self.is_keyword("OR") {
+            self.pos += 1;
+            let next = self.and()?;
+            if next.is_empty() {
+                return Err("Expected a search term after OR".to_string());
+            }
+            items.push(next);
+        }
+        Ok(if items.len() == 1 { items.pop().unwrap_or(Query::And(Vec::new())) } else { Query::Or(items) })
+    }
+
+    /// and := unary*, up to `)`, `OR` or the end.
+    fn and(&mut self) -> Result<Query, String> {
+        let mut items = Vec::new();
+        loop {
+            match self.peek() {
+                None | Some(Token::Close) => break,
+                _ if self.is_keyword("OR") => break,
+                _ if self.is_keyword("AND") => self.pos += 1,
+                _ => items.push(self.unary()?),
+            }
+        }
+        if items.is_empty() && self.pos < self.tokens.len() {
+            return Err("Expected a search term before OR or ')'".to_string());
+        }
+        Ok(if items.len() == 1 { items.pop().unwrap_or(Query::And(Vec::new())) } else { Query::And(items) })
+    }
+
+    fn unary(&mut self) -> Result<Query, String> {
+        if self.is_keyword("NOT") || self.peek() == Some(&Token::Neg) {
+            self.pos += 1;
+            if self.peek().is_none() {
+                return Err("Expected a search term after NOT".to_string());
+            }
+            return Ok(Query::Not(Box::new(self.unary()?)));
+        }
+        match self.tokens.get(self.pos).cloned() {
+            Some(Token::Open) => {
+                self.pos += 1;
+                let inner = self.or()?;
+                if self.peek() != Some(&Token::Close) {
+                    return Err("Expected ')'".to_string());
+                }
+                self.pos += 1;
+                Ok(inner)
+            }
+            Some(Token::Word { text, quoted }) => {
+                self.pos += 1;
+                term(&text, quoted).map(Query::Term)
+            }
+            Some(Token::Close) => Err("Unexpected ')'".to_string()),
+            Some(Token::Neg) | None => Err("Expected a search term".to_string()),
+        }
+    }
+}
+
+fn term(word: &str, quoted: bool) -> Result<Term, String> {
+    if quoted && !word.contains(':') {
+        return Ok(Term::Phrase(word.to_string()));
+    }
+    let Some((field, value)) = word.split_once(':') else {
+        return Ok(Term::Text(word.to_string()));
+    };
+    let need_value = || {
+        if value.is_empty() {
+            Err(format!("'{}:' needs a value", field))
+        } else {
+            Ok(value.to_string())
+        }
+    };
+    match field.to_ascii_lowercase().as_str() {
+        "from" => Ok(Term::From(need_value()?)),
+        "to" => Ok(Term::To(need_value()?)),
+        "cc" => Ok(Term::Cc(need_value()?)),
+        "subject" => Ok(Term::Subject(need_value()?)),
+        "in" => Ok(Term::In(need_value()?)),
+        "has" => match value.to_ascii_lowercase().as_str() {
+            "attachment" | "attachments" => Ok(Term::HasAttachment),
+            _ => Err(format!("Unknown has: value '{}'", value)),
+        },
+        "is" => match value.to_ascii_lowercase().as_str() {
+            "unread" => Ok(Term::Unread),
+            "read" => Ok(Term::Read),
+            "starred" | "flagged" => Ok(Term::Starred),
+            _ => Err(format!("Unknown is: value '{}'", value)),
+        },
+        "larger" => parse_size(value).map(Term::Larger),
+        "smaller" => parse_size(value).map(Term::Smaller),
+        "before" => parse_date(value).map(Term::Before),
+        "after" => parse_date(value).map(Term::After),
+        // Not a field we know (`http://…`, `re:`): plain text.
+        _ => Ok(if quoted { Term::Phrase(word.to_string()) } else { Term::Text(word.to_string()) }),
+    }
+}
+
+/// `5M`, `100K`, `1G` or plain bytes. K/M/G are binary multiples.
+fn parse_size(value: &str) -> Result<u64, String> {
+    let v = value.trim().to_ascii_uppercase();
+    let v = v.strip_suffix('B').unwrap_or(&v);
+    let (digits, mult) = match v.chars().last() {
+        Some('K') => (&v[..v.len() - 1], 1u64 << 10),
+        Some('M') => (&v[..v.len() - 1], 1 << 20),
+        Some('G') => (&v[..v.len() - 1], 1 << 30),
+        _ => (v, 1),
+    };
+    digits
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(mult))
+        .ok_or_else(|| format!("Invalid size '{}'", value))
+}
+
+fn parse_date(value: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(value, "%Y/%m/%d")
+        .or_else(|_| NaiveDate::parse_from_str(value, "%Y-%m-%d"))
+        .map_err(|_| format!("Invalid date '{}' (use YYYY/MM/DD)", value))
+}
+
+impl Query {
+    pub fn parse(input: &str) -> Result<Query, String> {
+        let mut parser = Parser { tokens: lex(input)?, pos: 0 };
+        let query = parser.or()?;
+        if parser.pos < parser.tokens.len() {
+            return Err("Unexpected ')'".to_string());
+        }
+        Ok(query)
+    }
+
+    /// No terms at all — an empty or blank search box.
+    pub fn is_empty(&self) -> bool {
+        matches!(self, Query::And(items) if items.is_empty())
+    }
+
+    /// Both queries must match.
+    pub fn and(self, other: Query) -> Query {
+        match (self, other) {
+            (q, other) if q.is_empty() => other,
+            (q, other) if other.is_empty() => q,
+            (Query::And(mut items), other) => {
+                items.push(other);
+                Query::And(items)
+            }
+            (q, other) => Query::And(vec![q, other]),
+        }
+    }
+
+    /// Top-level conjuncts: the items of an `And`, or the query itself.
+    fn conjuncts(&self) -> &[Query] {
+        match self {
+            Query::And(items) => items,
+            q => std::slice::from_ref(q),
+        }
+    }
+
+    /// The mailbox a top-level `in:` names, if any.
+    pub fn folder(&self) -> Option<&str> {
+        self.conjuncts().iter().find_map(|q| match q {
+            Query::Term(Term::In(f)) => Some(f.as_str()),
+            _ => None,
+        })
+    }
+
+    /// UID SEARCH criteria, without the mailbox a top-level `in:` selects.
+    pub fn to_imap(&self) -> Result<String, String> {
+        let mut keys = Vec::new();
+        for q in self.conjuncts() {
+            if !matches!(q, Query::Term(Term::In(_))) {
+                keys.push(q.imap_key()?);
+            }
+        }
+        let criteria = if keys.is_empty() { "ALL".to_string() } else { keys.join(" ") };
+        Ok(if criteria.is_ascii() { criteria } else { format!("CHARSET UTF-8 {}", criteria) })
+    }
+
+    fn imap_key(&self) -> Result<String, String> {
+        match self {
+            Query::And(items) if items.is_empty() => Ok("ALL".to_string()),
+            Query::And(items) => {
+                let keys: Result<Vec<String>, String> = items.iter().map(Query::imap_key).collect();
+                Ok(format!("({})", keys?.join(" ")))
+            }
+            // OR is binary in IMAP: a OR b OR c is `OR a OR b c`.
+            Query::Or(items) => {
+                let mut keys: Vec<String> = items.iter().map(Query::imap_key).collect::<Result<_, _>>()?;
+                let mut key = keys.pop().unwrap_or_else(|| "ALL".to_string());
+                while let Some(left) = keys.pop() {
+                    key = format!("OR {} {}", left, key);
+                }
+                Ok(key)
+            }
+            Query::Not(inner) => Ok(format!("NOT {}", inner.imap_key()?)),
+            Query::Term(t) => t.imap_key(),
+        }
+    }
+
+    /// Evaluate over a local message.
+    pub fn matches(&self, m: &Candidate) -> bool {
+        match self {
+            Query::And(items) => items.iter().all(|q| q.matches(m)),
+            Query::Or(items) => items.iter().any(|q| q.matches(m)),
+            Query::Not(inner) => !inner.matches(m),
+            Query::Term(t) => t.matches(m),
+        }
+    }
+}
+
+fn imap_quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn imap_date(d: &NaiveDate) -> String {
+    d.format("%d-%b-%Y").to_string()
+}
+
+fn contains(haystack: &str, needle: &str) -> bool {
+    haystack.to_lowercase().contains(&needle.to_lowercase())
+}
+
+impl Term {
+    fn imap_key(&self) -> Result<String, String> {
+        Ok(match self {
+            Term::Text(w) => format!("TEXT {}", imap_quote(w.trim_end_matches('*'))),
+            Term::Phrase(p) => format!("TEXT {}", imap_quote(p)),
+            Term::From(v) => format!("FROM {}", imap_quote(v)),
+            Term::To(v) => format!("TO {}", imap_quote(v)),
+            Term::Cc(v) => format!("CC {}", imap_quote(v)),
+            Term::Subject(v) => format!("SUBJECT {}", imap_quote(v)),
+            // No SEARCH key for attachments; a multipart/mixed body is the
+            // usual sign of one.
+            Term::HasAttachment => "HEADER Content-Type \"multipart/mixed\"".to_string(),
+            Term::Larger(n) => format!("LARGER {}", n),
+            Term::Smaller(n) => format!("SMALLER {}", n),
+            Term::Unread => "UNSEEN".to_string(),
+            Term::Read => "SEEN".to_string(),
+            Term::Starred => "FLAGGED".to_string(),
+            Term::Before(d) => format!("BEFORE {}", imap_date(d)),
+            Term::After(d) => format!("SINCE {}", imap_date(d)),
+            Term::In(f) => return Err(format!("in:{} can only narrow the whole search, not part of it", f)),
+        })
+    }
+
+    pub fn matches(&self, m: &Candidate) -> bool {
+        let has_flag = |flag: &str| m.flags.iter().any(|f| f.eq_ignore_ascii_case(flag));
+        match self {
+            Term::Text(w) => {
+                let w = w.trim_end_matches('*');
+                [m.subject, m.from, m.to, m.cc, m.body].iter().any(|h| contains(h, w))
+            }
+            Term::Phrase(p) => [m.subject, m.from, m.to, m.cc, m.body].iter().any(|h| contains(h, p)),
+            Term::From(v) => contains(m.from, v),
+            Term::To(v) => contains(m.to, v),
+            Term::Cc(v) => contains(m.cc, v),
+            Term::Subject(v) => contains(m.subject, v),
+            Term::HasAttachment => m.has_attachment,
+            Term::Larger(n) => m.size > *n,
+            Term::Smaller(n) => m.size < *n,
+            Term::Unread => !has_flag("\\Seen"),
+            Term::Read => has_flag("\\Seen"),
+            Term::Starred => has_flag("\\Flagged"),
+            Term::In(f) => mailbox_matches(m.mailbox, f),
+            Term::Before(d) => m.date.is_some_and(|date| date < *d),
+            Term::After(d) => m.date.is_some_and(|date| date >= *d),
+        }
+    }
+}
+
+/// `in:sent` names a mailbox by its full path or its last segment, either
+/// case: `Sent`, `[Gmail]/Sent`.
+fn mailbox_matches(path: &str, want: &str) -> bool {
+    path.eq_ignore_ascii_case(want) || path.rsplit('/').next().is_some_and(|leaf| leaf.eq_ignore_ascii_case(want))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn imap(q: &str) -> String {
+        Query::parse(q).unwrap().to_imap().unwrap()
+    }
+
+    #[test]
+    fn parses_fields_and_boolean_structure() {
+        let q = Query::parse("from:alice (subject:\"q3 report\" OR has:attachment) -is:read in:Archive").unwrap();
+        assert_eq!(
+            q,
+            Query::And(vec![
+                Query::Term(Term::From("alice".into())),
+                Query::Or(vec![
+                    Query::Term(Term::Subject("q3 report".into())),
+                    Query::Term(Term::HasAttachment),
+                ]),
+                Query::Not(Box::new(Query::Term(Term::Read))),
+                Query::Term(Term::In("Archive".into())),
+            ])
+        );
+        assert_eq!(q.folder(), Some("Archive"));
+        assert_eq!(
+            Query::parse("larger:5M smaller:100k").unwrap(),
+            Query::And(vec![Query::Term(Term::Larger(5 << 20)), Query::Term(Term::Smaller(100 << 10))])
+        );
+        assert_eq!(Query::parse("re: http://x").unwrap(), Query::And(vec![
+            Query::Term(Term::Text("re:".into())),
+            Query::Term(Term::Text("http://x".into())),
+        ]));
+        assert!(Query::parse("   ").unwrap().is_empty());
+
+        for bad in ["(from:a", "from:a)", "is:bogus", "before:yesterday", "NOT", "a OR", "\"open"] {
+            assert!(Query::parse(bad).is_err(), "{bad} should not parse");
+        }
+    }
+
+    #[test]
+    fn compiles_to_imap_search() {
+        assert_eq!(imap("hello \"two words\""), "TEXT \"hello\" TEXT \"two words\"");
+        assert_eq!(imap("a OR b OR c"), "OR TEXT \"a\" OR TEXT \"b\" TEXT \"c\"");
+        assert_eq!(imap("NOT (from:x is:unread)"), "NOT (FROM \"x\" UNSEEN)");
+        assert_eq!(imap("after:2026/04/01 before:2026-05-01 is:starred"), "SINCE 01-Apr-2026 BEFORE 01-May-2026 FLAGGED");
+        assert_eq!(imap("in:Sent"), "ALL");
+        assert_eq!(imap("subject:Grüße"), "CHARSET UTF-8 SUBJECT \"Grüße\"");
+        assert_eq!(imap("subject:\"say \\\""), "SUBJECT \"say \\\\\"");
+        assert!(Query::parse("in:Sent OR in:Inbox").unwrap().to_imap().is_err());
+    }
+
+    #[test]
+    fn matches_local_messages_like_the_server_would() {
+        let flags = vec!["\\Seen".to_string()];
+        let m = Candidate {
+            mailbox: "[Gmail]/Sent",
+            from: "Alice Smith <alice@example.com>",
+            to: "bob@example.com",
+            subject: "Q3 Report",
+            body: "numbers inside",
+            flags: &flags,
+            size: 6 << 20,
+            has_attachment: true,
+            date: NaiveDate::from_ymd_opt(2026, 4, 1),
+            ..Default::default()
+        };
+        let yes = |q: &str| Query::parse(q).unwrap().matches(&m);
+        assert!(yes("from:ALICE subject:\"q3 rep\" has:attachment larger:5M in:sent"));
+        assert!(yes("after:2026/04/01 before:2026/04/02 is:read"));
+        assert!(!yes("before:2026/04/01"));
+        assert!(yes("numb* -is:unread"));
+        assert!(yes("is:unread OR (to:bob NOT is:starred)"));
+        assert!(!yes("from:carol OR smaller:1M"));
+        assert!(!yes("in:Inbox"));
+    }
+}
@@ -0,0 +1,1091 @@
+//! Conversation threading over a whole account (JWZ).
+//!
+//! The app used to thread in the frontend, over whatever headers it had paged
+//! in, so a conversation split whenever its older half wasn't loaded. This
+//! keeps every message an account holds — each folder's cached headers and
+//! the vault's own files, including mail the server no longer has — and
+//! threads them all, so a thread is the same whichever folder it's opened
+//! from.
+//!
+//! Per account, the Message-ID, References, In-Reply-To, subject and date of
+//! each message are kept in memory and written to
+//! `thread_index/{account}.json` by `flush`. Sync feeds it the headers it
+//! caches and the uids the server expunges (`observe_headers`, `forget`);
+//! vault mailboxes are caught up at lookup by their uid index stamp, reading
+//! only the headers of files it hasn't seen. A first lookup builds it from the
+//! header caches.
+//!
+//! Threads are worked out from that on demand, and after a change only for
+//! the References components and subjects it touched, after Jamie Zawinski's
+//! algorithm: each message's References chain links its ancestors, its own
+//! last reference is its parent, a referenced message the account doesn't
+//! hold stands in as an empty node, and reply-like messages with no threading
+//! headers at all join the thread their subject names. A thread's id is the
+//! Message-ID at its root, angle brackets stripped.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use tracing::{info, warn};
+
+use crate::header_store::{self, HeaderStore};
+use crate::maildir::{self, encryption};
+
+pub const THREAD_DIR: &str = "thread_index";
+
+/// Which copy of a message a change is about: the header a sync cached, or
+/// the file in the vault. A message stays threaded while either remains.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    Cache,
+    Vault,
+}
+
+/// (mailbox, uid).
+type Key = (String, u32);
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Record {
+    mailbox: String,
+    uid: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    message_id: Option<String>,
+    /// References, then In-Reply-To: the message's ancestors, oldest first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    ancestry: Vec<String>,
+    #[serde(default)]
+    subject: String,
+    /// Epoch ms; 0 when the Date header didn't parse.
+    #[serde(default)]
+    date: i64,
+    #[serde(default)]
+    cached: bool,
+    #[serde(default)]
+    stored: bool,
+}
+
+impl Record {
+    fn new(
+        mailbox: &str,
+        uid: u32,
+        message_id: Option<&str>,
+        in_reply_to: Option<&str>,
+        references: &[String],
+        subject: &str,
+        date: Option<&str>,
+    ) -> Record {
+        let message_id = message_id.and_then(|v| ids_in(v).into_iter().next());
+        let mut ancestry = Vec::new();
+        for id in references.iter().flat_map(|r| ids_in(r)).chain(in_reply_to.map(ids_in).unwrap_or_default()) {
+            if Some(&id) != message_id.as_ref() && !ancestry.contains(&id) {
+                ancestry.push(id);
+            }
+        }
+        Record {
+            mailbox: mailbox.to_string(),
+            uid,
+            message_id,
+            ancestry,
+            subject: subject.to_string(),
+            date: date.and_then(|d| mailparse::dateparse(d).ok()).map(|s| s * 1000).unwrap_or(0),
+            cached: false,
+            stored: false,
+        }
+    }
+
+    /// A header as the header store holds it — written by the sync engine or
+    /// by the app, so either spelling of each field.
+    fn from_cached(mailbox: &str, value: &Value) -> Option<Record> {
+        let uid = value.get("uid").and_then(Value::as_u64)? as u32;
+        let text = |a: &str, b: &str| value.get(a).or_else(|| value.get(b)).and_then(Value::as_str);
+        let references: Vec<String> = match value.get("references") {
+            Some(Value::Array(refs)) => refs.iter().filter_map(Value::as_str).map(str::to_string).collect(),
+            Some(Value::String(refs)) => vec![refs.clone()],
+            _ => Vec::new(),
+        };
+        Some(Record::new(
+            mailbox,
+            uid,
+            text("messageId", "message_id"),
+            text("inReplyTo", "in_reply_to"),
+            &references,
+            text("subject", "subject").unwrap_or_default(),
+            text("date", "internalDate"),
+        ))
+    }
+
+    /// The headers of a vault file.
+    fn from_file(mailbox: &str, uid: u32, path: &Path) -> Option<Record> {
+        let head = maildir::read_head(path)?;
+        let (headers, _) = mailparse::parse_headers(&head).ok()?;
+        let get = |name: &str| maildir::get_header(&headers, name);
+        let references: Vec<String> = get("References").into_iter().collect();
+        Some(Record::new(
+            mailbox,
+            uid,
+            get("Message-ID").as_deref(),
+            get("In-Reply-To").as_deref(),
+            &references,
+            &get("Subject").unwrap_or_default(),
+            get("Date").as_deref(),
+        ))
+    }
+
+    /// Whether threading would place `other` the same as this.
+    fn threads_like(&self, other: &Record) -> bool {
+        (&self.message_id, &self.ancestry, &self.subject, self.date)
+            == (&other.message_id, &other.ancestry, &other.subject, other.date)
+    }
+
+    /// The node the message hangs at: its Message-ID, or one of its own when
+    /// it has none — nothing can reference that.
+    fn node(&self) -> String {
+        self.message_id.clone().unwrap_or_else(|| format!("uid:{}:{}", self.mailbox, self.uid))
+    }
+}
+
+/// Message-IDs in a References or In-Reply-To value, normalized. Takes the
+/// `<...>` tokens where there are any — an In-Reply-To often trails a comment
+/// — and whitespace-separated words otherwise.
+fn ids_in(value: &str) -> Vec<String> {
+    let bracketed: Vec<&str> = value.split('<').skip(1).filter_map(|s| s.split_once('>').map(|(id, _)| id)).collect();
+    let tokens = if bracketed.is_empty() { value.split_whitespace().collect() } else { bracketed };
+    let mut ids = Vec::new();
+    for token in tokens {
+        let id = maildir::normalize_message_id(token);
+        if !id.is_empty() && !ids.contains(&id) {
+            ids.push(id);
+        }
+    }
+    ids
+}
+
+/// A subject without its `Re:`/`Fwd:` prefixes, for grouping replies with no
+/// threading headers. The same prefixes the frontend strips.
+fn normalize_subject(subject: &str) -> (String, bool) {
+    let mut rest = subject.trim();
+    let mut prefixed = false;
+    loop {
+        let lower = rest.to_ascii_lowercase();
+        let cut = ["re:", "fwd:", "fw:"].iter().find(|p| lower.starts_with(*p)).map(|p| p.len()).or_else(|| {
+            // `Re[2]:`
+            let digits = lower.strip_prefix("re[")?;
+            let close = digits.find("]:")?;
+            digits[..close].chars().all(|c| c.is_ascii_digit()).then_some(3 + close + 2)
+        });
+        match cut {
+            Some(n) => {
+                rest = rest[n..].trim_start();
+                prefixed = true;
+            }
+            None => return (rest.trim().to_string(), prefixed),
+        }
+    }
+}
+
+// ── Threads ─────────────────────────────────────────────────────────────────
+
+/// One copy of a message, in one folder.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageCopy {
+    pub mailbox: String,
+    pub uid: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ThreadNode {
+    /// `None` for a message without one.
+    pub message_id: Option<String>,
+    pub subject: String,
+    /// Epoch ms.
+    pub date: i64,
+    /// Where the message is. Empty for a message the thread refers to that
+    /// the account doesn't hold, kept to join its replies.
+    pub copies: Vec<MessageCopy>,
+    pub children: Vec<ThreadNode>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Thread {
+    pub thread_id: String,
+    pub subject: String,
+    /// Messages held, each counted once however many folders it's in.
+    pub message_count: usize,
+    pub first_date: i64,
+    pub last_date: i64,
+    /// The root, then replies joined by subject alone, oldest first.
+    pub messages: Vec<ThreadNode>,
+}
+
+#[derive(Default)]
+struct Threads {
+    children: HashMap<String, Vec<String>>,
+    /// Node → the records holding it.
+    copies: HashMap<String, Vec<Key>>,
+    /// Thread id → top-level nodes.
+    roots: HashMap<String, Vec<String>>,
+    /// Node → thread id.
+    thread_of: HashMap<String, String>,
+    /// Node → the component it's in: the nodes some message's References
+    /// tie together, named by one of them. How one message's chain links can
+    /// depend on any other's in the same component, and on nothing outside it.
+    component_of: HashMap<String, String>,
+    components: HashMap<String, Vec<String>>,
+    /// Tree root → the tree, before subjects join.
+    trees: HashMap<String, Tree>,
+    /// Normalized subject → roots of the trees that have it. Trees without a
+    /// subject join nothing and aren't listed.
+    by_subject: HashMap<String, BTreeSet<String>>,
+}
+
+/// One tree JWZ linked.
+struct Tree {
+    nodes: Vec<String>,
+    /// Date of its earliest message held.
+    first: i64,
+    /// Normalized and lowercased.
+    subject: String,
+    /// A lone reply with no threading headers, to join by subject.
+    orphan: bool,
+    /// The thread it was joined into.
+    thread: Option<String>,
+}
+
+/// What changed since threads were last worked out: records put or dropped,
+/// and every node their old and new versions named.
+#[derive(Default)]
+struct Touched {
+    keys: HashSet<Key>,
+    nodes: HashSet<String>,
+}
+
+impl Touched {
+    fn add(&mut self, record: &Record) {
+        self.keys.insert((record.mailbox.clone(), record.uid));
+        self.nodes.extend(record.ancestry.iter().cloned());
+        self.nodes.insert(record.node());
+    }
+}
+
+/// The set `id` is in, over a union-find of node ids.
+fn find(sets: &mut HashMap<String, String>, id: &str) -> String {
+    let mut root = id;
+    while let Some(up) = sets.get(root) {
+        root = up;
+    }
+    let root = root.to_string();
+    let mut at = id.to_string();
+    while let Some(up) = sets.get_mut(&at) {
+        let next = std::mem::replace(up, root.clone());
+        at = next;
+    }
+    root
+}
+
+/// Whether walking up from `from` reaches `to`.
+fn reaches(parent: &HashMap<String, String>, from: &str, to: &str) -> bool {
+    let mut at = from;
+    for _ in 0..=parent.len() {
+        if at == to {
+            return true;
+        }
+        match parent.get(at) {
+            Some(p) => at = p,
+            None => return false,
+        }
+    }
+    true
+}
+
+fn compute(records: &HashMap<Key, Record>) -> Threads {
+    let mut t = Threads::default();
+    t.update(records, Touched { keys: records.keys().cloned().collect(), nodes: HashSet::new() });
+    t
+}
+
+impl Threads {
+    /// Work threads out again where `touched` changed them: every component
+    /// its nodes were in is taken apart and linked again from its records,
+    /// then each subject its trees had or now have is joined again.
+    /// Components it didn't reach stay as they were.
+    fn update(&mut self, records: &HashMap<Key, Record>, touched: Touched) {
+        let mut keys: HashSet<Key> = touched.keys.into_iter().filter(|k| records.contains_key(k)).collect();
+        let mut subjects = HashSet::new();
+        let stale: HashSet<String> = touched.nodes.iter().filter_map(|n| self.component_of.get(n).cloned()).collect();
+        let nodes: Vec<String> = stale.iter().filter_map(|c| self.components.remove(c)).flatten().collect();
+        for node in nodes {
+            keys.extend(self.copies.remove(&node).into_iter().flatten().filter(|k| records.contains_key(k)));
+            self.children.remove(&node);
+            self.component_of.remove(&node);
+            self.thread_of.remove(&node);
+            let Some(tree) = self.trees.remove(&node) else { continue };
+            if let Some(thread) = &tree.thread {
+                self.unplace(&node, thread);
+            }
+            if let Some(roots) = self.by_subject.get_mut(&tree.subject) {
+                roots.remove(&node);
+                if roots.is_empty() {
+                    self.by_subject.remove(&tree.subject);
+                }
+            }
+            subjects.insert(tree.subject);
+        }
+
+        let order: Vec<&Record> = keys.iter().filter_map(|k| records.get(k)).collect();
+        for root in self.link(records, order) {
+            let subject = self.trees[&root].subject.clone();
+            if subject.is_empty() {
+                self.place(&root, root.clone());
+            } else {
+                self.by_subject.entry(subject.clone()).or_default().insert(root);
+                subjects.insert(subject);
+            }
+        }
+        for subject in subjects.iter().filter(|s| !s.is_empty()) {
+            self.join(subject);
+        }
+    }
+
+    /// Link `order` — whole components' worth of records — into trees, after
+    /// JWZ. Returns the roots of the trees it made.
+    fn link(&mut self, records: &HashMap<Key, Record>, mut order: Vec<&Record>) -> Vec<String> {
+        order.sort_by(|a, b| (a.date, &a.mailbox, a.uid).cmp(&(b.date, &b.mailbox, b.uid)));
+
+        let mut nodes = Vec::new();
+        let mut seen = HashSet::new();
+        let mut sets: HashMap<String, String> = HashMap::new();
+        let mut parent: HashMap<String, String> = HashMap::new();
+        // Each chain links the ancestors it names, where nothing linked them yet.
+        for r in &order {
+            let own = r.node();
+            let set = find(&mut sets, &own);
+            for id in r.ancestry.iter().chain(std::iter::once(&own)) {
+                if seen.insert(id.clone()) {
+                    nodes.push(id.clone());
+                }
+                let other = find(&mut sets, id);
+                if other != set {
+                    sets.insert(other, set.clone());
+                }
+            }
+            self.copies.entry(own).or_default().push((r.mailbox.clone(), r.uid));
+            for pair in r.ancestry.windows(2) {
+                if !parent.contains_key(&pair[1]) && !reaches(&parent, &pair[0], &pair[1]) {
+                    parent.insert(pair[1].clone(), pair[0].clone());
+                }
+            }
+        }
+        // A message's own last reference is its parent, whatever others implied;
+        // one with no references is a root.
+        for r in &order {
+            let own = r.node();
+            let before = parent.remove(&own);
+            match r.ancestry.last() {
+                Some(last) if !reaches(&parent, last, &own) => {
+                    parent.insert(own, last.clone());
+                }
+                Some(_) => {
+                    if let Some(p) = before {
+                        parent.insert(own, p);
+                    }
+                }
+                None => {}
+            }
+        }
+
+        let mut members: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for id in &nodes {
+            let mut at = id;
+            while let Some(p) = parent.get(at) {
+                at = p;
+            }
+            members.entry(at.clone()).or_default().push(id.clone());
+            let component = find(&mut sets, id);
+            self.components.entry(component.clone()).or_default().push(id.clone());
+            self.component_of.insert(id.clone(), component);
+            if let Some(p) = parent.get(id) {
+                self.children.entry(p.clone()).or_default().push(id.clone());
+            }
+        }
+
+        let date_of = |copies: &HashMap<String, Vec<Key>>, node: &str| {
+            copies.get(node).into_iter().flatten().filter_map(|k| records.get(k)).map(|r| r.date).min()
+        };
+        let mut roots = Vec::new();
+        for (root, ids) in members {
+            let held = ids.iter().filter(|id| self.copies.contains_key(*id));
+            let Some((first, first_id)) = held.filter_map(|id| date_of(&self.copies, id).map(|d| (d, id))).min() else { continue };
+            let record = self.copies[first_id].iter().filter_map(|k| records.get(k)).next();
+            let (subject, prefixed) = normalize_subject(record.map(|r| r.subject.as_str()).unwrap_or(""));
+            let orphan = ids.len() == 1
+                && prefixed
+                && self.copies[&root].iter().filter_map(|k| records.get(k)).all(|r| r.ancestry.is_empty());
+            let tree = Tree { nodes: ids, first, subject: subject.to_lowercase(), orphan, thread: None };
+            self.trees.insert(root.clone(), tree);
+            roots.push(root);
+        }
+        roots
+    }
+
+    /// Join the trees with `subject` into threads. A reply with no threading
+    /// headers joins the earliest thread its subject names, or the earliest
+    /// such reply when no thread does.
+    fn join(&mut self, subject: &str) {
+        let Some(roots) = self.by_subject.get(subject) else { return };
+        let mut group: Vec<(i64, String, bool)> =
+            roots.iter().map(|root| (self.trees[root].first, root.clone(), self.trees[root].orphan)).collect();
+        group.sort();
+        let mut joined = group.iter().find(|(_, _, orphan)| !orphan).map(|(_, root, _)| root.clone());
+        for (_, root, orphan) in group {
+            let target = match &joined {
+                Some(target) if orphan => target.clone(),
+                _ => {
+                    if orphan {
+                        joined = Some(root.clone());
+                    }
+                    root.clone()
+                }
+            };
+            self.place(&root, target);
+        }
+    }
+
+    /// Put the tree at `root` into the thread `target`.
+    fn place(&mut self, root: &str, target: String) {
+        let Some(tree) = self.trees.get_mut(root) else { return };
+        if tree.thread.as_ref() == Some(&target) {
+            return;
+        }
+        let old = tree.thread.replace(target.clone());
+        for node in &tree.nodes {
+            self.thread_of.insert(node.clone(), target.clone());
+        }
+        if let Some(old) = old {
+            self.unplace(root, &old);
+        }
+        self.roots.entry(target).or_default().push(root.to_string());
+    }
+
+    fn unplace(&mut self, root: &str, thread: &str) {
+        if let Some(roots) = self.roots.get_mut(thread) {
+            roots.retain(|r| r != root);
+            if roots.is_empty() {
+                self.roots.remove(thread);
+            }
+        }
+    }
+}
+
+impl Threads {
+    /// The node `id` as it's shown: an empty node stands in for a missing
+    /// message only where it joins replies at the top; elsewhere its children
+    /// take its place.
+    fn build(&self, records: &HashMap<Key, Record>, id: &str, top: bool) -> Vec<ThreadNode> {
+        let mut children: Vec<ThreadNode> = self
+            .children
+            .get(id)
+            .into_iter()
+            .flatten()
+            .flat_map(|c| self.build(records, c, false))
+            .collect();
+        children.sort_by(|a, b| (a.date, &a.message_id).cmp(&(b.date, &b.message_id)));
+        let held: Vec<&Record> = self.copies.get(id).into_iter().flatten().filter_map(|k| records.get(k)).collect();
+        if held.is_empty() && !(top && children.len() > 1) {
+            return children;
+        }
+        let first = held.iter().min_by_key(|r| r.date);
+        let date = match first {
+            Some(r) => r.date,
+            None => children.iter().map(|c| c.date).min().unwrap_or(0),
+        };
+        let mut copies: Vec<MessageCopy> = held.iter().map(|r| MessageCopy { mailbox: r.mailbox.clone(), uid: r.uid }).collect();
+        copies.sort_by(|a, b| (&a.mailbox, a.uid).cmp(&(&b.mailbox, b.uid)));
+        vec![ThreadNode {
+            message_id: if held.is_empty() { Some(id.to_string()) } else { first.and_then(|r| r.message_id.clone()) },
+            subject: first.map(|r| r.subject.clone()).unwrap_or_default(),
+            date,
+            copies,
+            children,
+        }]
+    }
+
+    fn thread(&self, records: &HashMap<Key, Record>, thread_id: &str) -> Option<Thread> {
+        let roots = self.roots.get(thread_id)?;
+        let mut messages: Vec<ThreadNode> = roots.iter().flat_map(|r| self.build(records, r, true)).collect();
+        messages.sort_by(|a, b| (a.date, &a.message_id).cmp(&(b.date, &b.message_id)));
+        let mut held = Vec::new();
+        let mut stack: Vec<&ThreadNode> = messages.iter().collect();
+        while let Some(node) = stack.pop() {
+            if !node.copies.is_empty() {
+                held.push(node);
+            }
+            stack.extend(node.children.iter());
+        }
+        let first = held.iter().min_by_key(|n| n.date)?;
+        Some(Thread {
+            thread_id: thread_id.to_string(),
+            subject: normalize_subject(&first.subject).0,
+            message_count: held.len(),
+            first_date: first.date,
+            last_date: held.iter().map(|n| n.date).max().unwrap_or(first.date),
+            messages,
+        })
+    }
+}
+
+// ── The index ───────────────────────────────────────────────────────────────
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Persisted {
+    records: Vec<Record>,
+    /// Uid index stamp each vault mailbox was last pulled at.
+    stamps: BTreeMap<String, u64>,
+    /// `email_cache/` directory → the mailbox sync named it for.
+    folders: BTreeMap<String, String>,
+}
+
+#[derive(Default)]
+struct AccountIndex {
+    records: HashMap<Key, Record>,
+    stamps: BTreeMap<String, u64>,
+    folders: BTreeMap<String, String>,
+    threads: Option<Threads>,
+    /// Changes `threads` hasn't taken in yet.
+    touched: Touched,
+    dirty: bool,
+}
+
+impl AccountIndex {
+    fn put(&mut self, mut record: Record, source: Source) {
+        let key = (record.mailbox.clone(), record.uid);
+        // Nothing to follow until threads have been worked out once.
+        let track = self.threads.is_some();
+        match self.records.get(&key) {
+            Some(old) => {
+                record.cached |= old.cached;
+                record.stored |= old.stored;
+                if track && !old.threads_like(&record) {
+                    self.touched.add(old);
+                    self.touched.add(&record);
+                }
+            }
+            None if track => self.touched.add(&record),
+            None => {}
+        }
+        match source {
+            Source::Cache => record.cached = true,
+            Source::Vault => record.stored = true,
+        }
+        self.records.insert(key, record);
+        self.dirty = true;
+    }
+
+    fn drop_source(&mut self, key: &Key, source: Source) {
+        let Some(record) = self.records.get_mut(key) else { return };
+        match source {
+            Source::Cache => record.cached = false,
+            Source::Vault => record.stored = false,
+        }
+        if !record.cached && !record.stored {
+            if let Some(record) = self.records.remove(key).filter(|_| self.threads.is_some()) {
+                self.touched.add(&record);
+            }
+        }
+        self.dirty = true;
+    }
+
+    /// Move `old`'s messages to `new`, merging with any already filed there.
+    fn rename(&mut self, old: &str, new: &str) {
+        let track = self.threads.is_some();
+        let keys: Vec<Key> = self.records.keys().filter(|k| k.0 == old).cloned().collect();
+        for key in keys {
+            let Some(mut record) = self.records.remove(&key) else { continue };
+            if track {
+                self.touched.add(&record);
+            }
+            record.mailbox = new.to_string();
+            if let Some(there) = self.records.get(&(new.to_string(), key.1)) {
+                record.cached |= there.cached;
+                record.stored |= there.stored;
+                if track {
+                    self.touched.add(there);
+                }
+            }
+            if track {
+                self.touched.add(&record);
+            }
+            self.records.insert((new.to_string(), key.1), record);
+        }
+        if let Some(stamp) = self.stamps.remove(old) {
+            self.stamps.insert(new.to_string(), stamp);
+        }
+        self.dirty = true;
+    }
+
+    /// The account's threads, worked out again only where they changed.
+    fn threads(&mut self) -> &Threads {
+        let touched = std::mem::take(&mut self.touched);
+        if let Some(threads) = self.threads.as_mut() {
+            threads.update(&self.records, touched);
+        }
+        self.threads.get_or_insert_with(|| compute(&self.records))
+    }
+}
+
+/// Each account's index, loaded at first use, behind a lock of its own: one
+/// account's lookup doesn't wait on another's sync.
+type Slot = Arc<Mutex<Option<AccountIndex>>>;
+
+fn indexes() -> &'static Mutex<HashMap<(PathBuf, String), Slot>> {
+    static INDEXES: OnceLock<Mutex<HashMap<(PathBuf, String), Slot>>> = OnceLock::new();
+    INDEXES.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+fn slot(data_dir: &Path, account_id: &str) -> Slot {
+    let mut all = indexes().lock().unwrap_or_else(|e| e.into_inner());
+    all.entry((data_dir.to_path_buf(), account_id.to_string())).or_default().clone()
+}
+
+fn index_path(data_dir: &Path, account_id: &str) -> PathBuf {
+    let safe = account_id.replace(|c: char| !c.is_alphanumeric() && c != '-' && c != '_', "_");
+    data_dir.join(THREAD_DIR).join(format!("{}.json", safe))
+}
+
+fn save(data_dir: &Path, account_id: &str, index: &mut AccountIndex) {
+    let path = index_path(data_dir, account_id);
+    let mut records: Vec<&Record> = index.records.values().collect();
+    records.sort_by(|a, b| (&a.mailbox, a.uid).cmp(&(&b.mailbox, b.uid)));
+    let persisted = serde_json::json!({ "records": records, "stamps": index.stamps, "folders": index.folders });
+    let result = fs::create_dir_all(data_dir.join(THREAD_DIR))
+        .map_err(|e| e.to_string())
+        .and_then(|()| serde_json::to_vec(&persisted).map_err(|e| e.to_string()))
+        .and_then(|bytes| encryption::write_sidecar(data_dir, &path, &bytes));
+    match result {
+        Ok(()) => index.dirty = false,
+        Err(e) => warn!("threading: failed to save {:?}: {}", path, e),
+    }
+}
+
+/// The vault mailboxes of an account, by name.
+fn vault_mailboxes(data_dir: &Path, account_id: &str) -> Vec<(String, PathBuf)> {
+    let root = data_dir.join("Maildir");
+    maildir::mailbox_dirs(&root)
+        .into_iter()
+        .filter_map(|dir| match maildir::mailbox_key(&root, &dir) {
+            Some((account, mailbox)) if account == account_id => Some((mailbox, dir)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Seed an account from every header cache it has. The mailbox a cache
+/// directory is for comes from the vault or from what sync recorded; failing
+/// both, from the directory name.
+fn cold_build(data_dir: &Path, account_id: &str, index: &mut AccountIndex) {
+    let mut names: HashMap<String, String> = index.folders.iter().map(|(d, m)| (d.clone(), m.clone())).collect();
+    for (mailbox, _) in vault_mailboxes(data_dir, account_id) {
+        names.insert(header_store::cache_base_name(account_id, &mailbox), mailbox);
+    }
+    let prefix = header_store::cache_base_name(account_id, "");
+    let Ok(dirs) = fs::read_dir(data_dir.join("email_cache")) else { return };
+    let mut total = 0;
+    for dir in dirs.flatten() {
+        let name = dir.file_name().to_string_lossy().to_string();
+        let Some(suffix) = name.strip_prefix(&prefix) else { continue };
+        if !header_store::exists(&dir.path()) {
+            continue;
+        }
+        let mailbox = names.get(&name).cloned().unwrap_or_else(|| suffix.to_string());
+        match HeaderStore::open(&dir.path()) {
+            Ok(store) => {
+                for record in store.headers().filter_map(|h| Record::from_cached(&mailbox, h)) {
+                    index.put(record, Source::Cache);
+                    total += 1;
+                }
+            }
+            Err(e) => warn!("threading: {:?}: {}", dir.path(), e),
+        }
+    }
+    info!("threading: built {} from {} cached headers", account_id, total);
+}
+
+/// Bring the vault's side up to date: mailboxes whose uid index moved on are
+/// compared uid by uid, and only files not seen before are read.
+fn reconcile_vault(data_dir: &Path, account_id: &str, index: &mut AccountIndex) {
+    let mut seen = HashSet::new();
+    for (mailbox, mailbox_dir) in vault_mailboxes(data_dir, account_id) {
+        seen.insert(mailbox.clone());
+        let cur_dir = mailbox_dir.join("cur");
+        let Some(stamp) = crate::uid_index::stamp(&cur_dir) else { continue };
+        if index.stamps.get(&mailbox) == Some(&stamp) {
+            continue;
+        }
+        let entries = crate::uid_index::entries(&cur_dir);
+        let live: HashSet<u32> = entries.iter().map(|(uid, _)| *uid).collect();
+        let gone: Vec<Key> = index
+            .records
+            .iter()
+            .filter(|(k, r)| k.0 == mailbox && r.stored && !live.contains(&k.1))
+            .map(|(k, _)| k.clone())
+            .collect();
+        for key in &gone {
+            index.drop_source(key, Source::Vault);
+        }
+        for (uid, entry) in entries {
+            match index.records.get_mut(&(mailbox.clone(), uid)) {
+                Some(r) if r.stored => {}
+                // The cached header already says what the file would.
+                Some(r) => {
+                    r.stored = true;
+                    index.dirty = true;
+                }
+                None => {
+                    if let Some(record) = Record::from_file(&mailbox, uid, &entry.path(&cur_dir)) {
+                        index.put(record, Source::Vault);
+                    }
+                }
+            }
+        }
+        index.stamps.insert(mailbox, stamp);
+        index.dirty = true;
+    }
+    let removed: Vec<String> = index.stamps.keys().filter(|m| !seen.contains(*m)).cloned().collect();
+    for mailbox in removed {
+        let keys: Vec<Key> = index.records.keys().filter(|k| k.0 == mailbox).cloned().collect();
+        for key in &keys {
+            index.drop_source(key, Source::Vault);
+        }
+        index.stamps.remove(&mailbox);
+        index.dirty = true;
+    }
+}
+
+/// The account's index as last saved, or built from the header caches when
+/// there's none to read.
+fn load(data_dir: &Path, account_id: &str) -> AccountIndex {
+    let path = index_path(data_dir, account_id);
+    let loaded = encryption::read_sidecar(data_dir, &path)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice::<Persisted>(&bytes).ok());
+    match loaded {
+        Some(p) => AccountIndex {
+            records: p.records.into_iter().map(|r| ((r.mailbox.clone(), r.uid), r)).collect(),
+            stamps: p.stamps,
+            folders: p.folders,
+            ..Default::default()
+        },
+        None => {
+            let mut index = AccountIndex::default();
+            cold_build(data_dir, account_id, &mut index);
+            index
+        }
+    }
+}
+
+/// Run `f` over the account's index for a lookup, loading or building it
+/// first and catching the vault up. Changes are left for `flush` to write.
+fn with_index<T>(data_dir: &Path, account_id: &str, f: impl FnOnce(&mut AccountIndex) -> T) -> T {
+    let slot = slot(data_dir, account_id);
+    let mut guard = slot.lock().unwrap_or_else(|e| e.into_inner());
+    let index = guard.get_or_insert_with(|| load(data_dir, account_id));
+    reconcile_vault(data_dir, account_id, index);
+    f(index)
+}
+
+/// Run `f` over the account's index if it's in memory or on disk. One that
+/// doesn't exist yet is built from scratch at its first lookup, so there's
+/// nothing to keep current. The vault isn't caught up here — the next lookup
+/// does that — and nothing is written until `flush`.
+fn with_existing(data_dir: &Path, account_id: &str, f: impl FnOnce(&mut AccountIndex)) {
+    let slot = slot(data_dir, account_id);
+    let mut guard = slot.lock().unwrap_or_else(|e| e.into_inner());
+    if guard.is_none() && index_path(data_dir, account_id).exists() {
+        *guard = Some(load(data_dir, account_id));
+    }
+    if let Some(index) = guard.as_mut() {
+        f(index);
+    }
+}
+
+// ── Keeping it current ──────────────────────────────────────────────────────
+
+/// Headers a sync just cached for `mailbox`.
+pub fn observe_headers(data_dir: &Path, account_id: &str, mailbox: &str, headers: &[crate::imap::EmailHeader]) {
+    if headers.is_empty() {
+        return;
+    }
+    with_existing(data_dir, account_id, |index| {
+        index.folders.insert(header_store::cache_base_name(account_id, mailbox), mailbox.to_string());
+        for h in headers {
+            let record = Record::new(
+                mailbox,
+                h.uid,
+                h.message_id.as_deref(),
+                h.in_reply_to.as_deref(),
+                h.references.as_deref().unwrap_or_default(),
+                &h.subject,
+                h.date.as_deref().or(h.internal_date.as_deref()),
+            );
+            index.put(record, Source::Cache);
+        }
+    });
+}
+
+/// Headers a sync just cached, as the header store holds them.
+pub fn observe_cached(data_dir: &Path, account_id: &str, mailbox: &str, headers: &[Value]) {
+    if headers.is_empty() {
+        return;
+    }
+    with_existing(data_dir, account_id, |index| {
+        index.folders.insert(header_store::cache_base_name(account_id, mailbox), mailbox.to_string());
+        for record in headers.iter().filter_map(|h| Record::from_cached(mailbox, h)) {
+            index.put(record, Source::Cache);
+        }
+    });
+}
+
+/// `uids` are gone from `mailbox`'s `source`: expunged on the server, or
+/// deleted from the vault.
+pub fn forget(data_dir: &Path, account_id: &str, mailbox: &str, uids: &[u32], source: Source) {
+    if uids.is_empty() {
+        return;
+    }
+    with_existing(data_dir, account_id, |index| {
+        for &uid in uids {
+            index.drop_source(&(mailbox.to_string(), uid), source);
+        }
+    });
+}
+
+/// Everything `mailbox`'s `source` held is gone — a header cache dropped for
+/// a new UIDVALIDITY.
+pub fn forget_mailbox(data_dir: &Path, account_id: &str, mailbox: &str, source: Source) {
+    with_existing(data_dir, account_id, |index| {
+        let keys: Vec<Key> = index.records.keys().filter(|k| k.0 == mailbox).cloned().collect();
+        for key in &keys {
+            index.drop_source(key, source);
+        }
+    });
+}
+
+/// A folder was renamed on the server and its cache and vault directory
+/// moved with it.
+pub fn rename_mailbox(data_dir: &Path, account_id: &str, old: &str, new: &str) {
+    with_existing(data_dir, account_id, |index| {
+        index.rename(old, new);
+        index.folders.insert(header_store::cache_base_name(account_id, new), new.to_string());
+    });
+}
+
+/// Throw the account's index away and build it again from the header caches
+/// and the vault. Returns the messages it holds.
+pub fn rebuild(data_dir: &Path, account_id: &str) -> usize {
+    let slot = slot(data_dir, account_id);
+    let mut guard = slot.lock().unwrap_or_else(|e| e.into_inner());
+    let _ = fs::remove_file(index_path(data_dir, account_id));
+    let mut index = AccountIndex::default();
+    cold_build(data_dir, account_id, &mut index);
+    reconcile_vault(data_dir, account_id, &mut index);
+    guard.insert(index).records.len()
+}
+
+/// Write every index with unsaved changes. Cheap when there are none.
+pub fn flush() {
+    let slots: Vec<((PathBuf, String), Slot)> =
+        indexes().lock().unwrap_or_else(|e| e.into_inner()).iter().map(|(k, s)| (k.clone(), s.clone())).collect();
+    for ((data_dir, account_id), slot) in slots {
+        let mut guard = slot.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(index) = guard.as_mut().filter(|index| index.dirty) {
+            save(&data_dir, &account_id, index);
+        }
+    }
+}
+
+// ── Lookups ─────────────────────────────────────────────────────────────────
+
+/// The thread each of `uids` in `mailbox` belongs to. Uids the account
+/// doesn't hold are left out.
+pub fn thread_ids(data_dir: &Path, account_id: &str, mailbox: &str, uids: &[u32]) -> BTreeMap<u32, String> {
+    with_index(data_dir, account_id, |index| {
+        let nodes: Vec<(u32, String)> = uids
+            .iter()
+            .filter_map(|&uid| Some((uid, index.records.get(&(mailbox.to_string(), uid))?.node())))
+            .collect();
+        let threads = index.threads();
+        nodes.into_iter().filter_map(|(uid, node)| Some((uid, threads.thread_of.get(&node)?.clone()))).collect()
+    })
+}
+
+/// A whole thread, as a tree.
+pub fn thread(data_dir: &Path, account_id: &str, thread_id: &str) -> Option<Thread> {
+    with_index(data_dir, account_id, |index| {
+        index.threads();
+        let threads = index.threads.as_ref()?;
+        threads.thread(&index.records, thread_id)
+    })
+}
+
+/// The thread `uid` in `mailbox` belongs to, as a tree.
+pub fn thread_of(data_dir: &Path, account_id: &str, mailbox: &str, uid: u32) -> Option<Thread> {
+    let thread_id = thread_ids(data_dir, account_id, mailbox, &[uid]).remove(&uid)?;
+    thread(data_dir, account_id, &thread_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(mailbox: &str, uid: u32, id: &str, refs: &[&str], subject: &str, date: &str) -> Record {
+        let refs: Vec<String> = refs.iter().map(|r| format!("<{}>", r)).collect();
+        let mut r = Record::new(mailbox, uid, Some(&format!("<{}>", id)), None, &refs, subject, Some(date));
+        r.cached = true;
+        r
+    }
+
+    fn ids(node: &ThreadNode) -> String {
+        let own = node.message_id.clone().unwrap_or_default();
+        if node.children.is_empty() {
+            return own;
+        }
+        format!("{}({})", own, node.children.iter().map(ids).collect::<Vec<_>>().join(" "))
+    }
+
+    #[test]
+    fn test_jwz_links_chains_across_folders_and_gaps() {
+        let records: HashMap<Key, Record> = [
+            record("INBOX", 1, "a", &[], "Plans", "Mon, 1 Jan 2024 10:00:00 +0000"),
+            // A reply whose parent the account never got.
+            record("INBOX", 2, "c", &["a", "b"], "Re: Plans", "Mon, 1 Jan 2024 12:00:00 +0000"),
+            // Our own reply, filed in Sent, and the same message in Archive.
+            record("Sent", 7, "d", &["a", "b", "c"], "Re: Plans", "Mon, 1 Jan 2024 13:00:00 +0000"),
+            record("Archive", 3, "d", &["a", "b", "c"], "Re: Plans", "Mon, 1 Jan 2024 13:00:00 +0000"),
+            // A reply from a client that sent no threading headers.
+            record("INBOX", 4, "e", &[], "RE: Re: plans", "Tue, 2 Jan 2024 09:00:00 +0000"),
+            record("INBOX", 5, "f", &[], "Re: Something else", "Tue, 2 Jan 2024 09:00:00 +0000"),
+            // Nothing but a matching subject doesn't join: no reply prefix.
+            record("INBOX", 6, "g", &[], "Plans", "Wed, 3 Jan 2024 09:00:00 +0000"),
+        ]
+        .into_iter()
+        .map(|r| ((r.mailbox.clone(), r.uid), r))
+        .collect();
+
+        let t = compute(&records);
+        assert_eq!(t.thread_of["d"], "a");
+        assert_eq!(t.thread_of["e"], "a");
+        assert_eq!(t.thread_of["f"], "f");
+        assert_eq!(t.thread_of["g"], "g");
+
+        let thread = t.thread(&records, "a").unwrap();
+        assert_eq!(thread.message_count, 4);
+        assert_eq!(thread.subject, "Plans");
+        // The missing `b` isn't shown; its reply hangs under `a` instead.
+        let shown: Vec<String> = thread.messages.iter().map(ids).collect();
+        assert_eq!(shown, ["a(c(d))", "e"]);
+        let d = &thread.messages[0].children[0].children[0];
+        assert_eq!(d.copies, [MessageCopy { mailbox: "Archive".into(), uid: 3 }, MessageCopy { mailbox: "Sent".into(), uid: 7 }]);
+
+        // The message's own last reference wins over a chain that guessed,
+        // and a reference loop doesn't hang.
+        let looped: HashMap<Key, Record> = [
+            record("INBOX", 1, "x", &["y"], "Re: loop", "Mon, 1 Jan 2024 10:00:00 +0000"),
+            record("INBOX", 2, "y", &["x"], "Re: loop", "Mon, 1 Jan 2024 11:00:00 +0000"),
+        ]
+        .into_iter()
+        .map(|r| ((r.mailbox.clone(), r.uid), r))
+        .collect();
+        let t = compute(&looped);
+        assert_eq!(t.thread_of["x"], t.thread_of["y"]);
+    }
+
+    /// Thread of each node, then each thread's top-level nodes and each
+    /// node's children.
+    type Shape = (BTreeMap<String, String>, BTreeMap<String, Vec<String>>, BTreeMap<String, Vec<String>>);
+
+    /// What a lookup can see of `t`, in a fixed order.
+    fn shape(t: &Threads) -> Shape {
+        let sorted = |m: &HashMap<String, Vec<String>>| {
+            m.iter().map(|(k, v)| (k.clone(), { let mut v = v.clone(); v.sort(); v })).collect()
+        };
+        (t.thread_of.iter().map(|(k, v)| (k.clone(), v.clone())).collect(), sorted(&t.roots), sorted(&t.children))
+    }
+
+    #[test]
+    fn test_changes_rework_only_what_they_touch_and_match_a_full_pass() {
+        let mut index = AccountIndex::default();
+        for r in [
+            record("INBOX", 1, "a", &[], "Plans", "Mon, 1 Jan 2024 10:00:00 +0000"),
+            record("INBOX", 2, "c", &["a", "b"], "Re: Plans", "Mon, 1 Jan 2024 12:00:00 +0000"),
+            record("INBOX", 4, "e", &[], "Re: plans", "Tue, 2 Jan 2024 09:00:00 +0000"),
+            record("INBOX", 5, "f", &[], "Lunch", "Tue, 2 Jan 2024 09:00:00 +0000"),
+            record("INBOX", 6, "g", &[], "Re: Lunch", "Wed, 3 Jan 2024 09:00:00 +0000"),
+        ] {
+            index.put(r, Source::Cache);
+        }
+        index.threads();
+        assert_eq!(index.threads.as_ref().unwrap().thread_of["e"], "a");
+
+        // A reply arrives joining two threads, the root goes, a header is
+        // seen again unchanged, and a folder is renamed.
+        index.put(record("Sent", 7, "h", &["f", "c"], "Re: Plans", "Thu, 4 Jan 2024 09:00:00 +0000"), Source::Cache);
+        index.drop_source(&("INBOX".to_string(), 1), Source::Cache);
+        index.put(record("INBOX", 6, "g", &[], "Re: Lunch", "Wed, 3 Jan 2024 09:00:00 +0000"), Source::Vault);
+        assert!(!index.touched.keys.contains(&("INBOX".to_string(), 6)));
+        index.rename("Sent", "Sent Items");
+        // Only the components those changes reached are linked again.
+        assert!(!index.touched.nodes.contains("g"));
+
+        let incremental = shape(index.threads());
+        let full = shape(&compute(&index.records));
+        assert_eq!(incremental, full);
+        assert_eq!(incremental.0["h"], incremental.0["c"]);
+        // The reply without headers still joins the thread its subject names.
+        assert_eq!(incremental.0["g"], incremental.0["f"]);
+    }
+
+    #[test]
+    fn test_index_follows_sync_and_vault_across_restarts() {
+        let dir = std::env::temp_dir().join("mailvault-test-threading");
+        let _ = fs::remove_dir_all(&dir);
+        let cache = dir.join("email_cache").join(header_store::cache_base_name("acc1", "INBOX"));
+        HeaderStore::open(&cache)
+            .unwrap()
+            .put(&[serde_json::json!({"uid": 1, "messageId": "<root@x>", "subject": "Trip", "date": "Mon, 1 Jan 2024 10:00:00 +0000"})])
+            .unwrap();
+        // Vault-only: the server dropped it, the archive kept it.
+        let reply = b"Message-ID: <reply@x>\r\nIn-Reply-To: <root@x> (sent by Bob)\r\nSubject: Re: Trip\r\nDate: Mon, 1 Jan 2024 11:00:00 +0000\r\n\r\nok";
+        maildir::store(&dir, "acc1", "Archive", 40, reply, &[]).unwrap();
+
+        let ids = thread_ids(&dir, "acc1", "Archive", &[40, 41]);
+        assert_eq!(ids.get(&40).map(String::as_str), Some("root@x"));
+        assert!(!ids.contains_key(&41));
+
+        // Sync adds a reply and the thread grows; the vault file goes and the
+        // reply drops out.
+        observe_cached(&dir, "acc1", "INBOX", &[serde_json::json!({
+            "uid": 2, "messageId": "<second@x>", "references": ["<root@x>", "<reply@x>"],
+            "subject": "Re: Trip", "date": "Mon, 1 Jan 2024 12:00:00 +0000"
+        })]);
+        let t = thread_of(&dir, "acc1", "INBOX", 2).unwrap();
+        assert_eq!((t.thread_id.as_str(), t.message_count), ("root@x", 3));
+        maildir::delete(&dir, "acc1", "Archive", 40).unwrap();
+        assert_eq!(thread(&dir, "acc1", "root@x").unwrap().message_count, 2);
+
+        // A new process starts from what was saved, and sees the server drop
+        // the root.
+        flush();
+        indexes().lock().unwrap().clear();
+        forget(&dir, "acc1", "INBOX", &[1], Source::Cache);
+        assert_eq!(thread(&dir, "acc1", "root@x").unwrap().message_count, 1);
+        // Rebuilding goes back to the header cache, which still has it.
+        assert_eq!(rebuild(&dir, "acc1"), 1);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
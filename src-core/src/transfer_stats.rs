@@ -14,6 +14,9 @@
 //! own pool and write their own file (`{account_id}.app.json` /
 //! `{account_id}.daemon.json`); readers sum the two. Losing the last flush
 //! interval on a crash is fine — these are statistics, not accounting records.
+//!
+//! Graph accounts have no IMAP stream; their body downloads are added by hand
+//! with `Counters::count_down`.
 
 use std::collections::{BTreeMap, HashMap};
 use std::fs;
@@ -44,6 +47,11 @@ pub struct Counters {
 }
 
 impl Counters {
+    /// Add bytes received outside a `CountingStream`, e.g. over Graph.
+    pub fn count_down(&self, n: u64) {
+        self.down.fetch_add(n, Ordering::Relaxed);
+    }
+
     /// Bytes not yet written to disk.
     fn pending(&self) -> DayBucket {
         DayBucket {
@@ -0,0 +1,578 @@
+//! Per-mailbox uid → file index, so a lookup doesn't list `cur/`.
+//!
+//! A Maildir filename carries its uid, but the only way to find a uid's file
+//! is to list the directory — O(n) per message and O(n²) per page once a
+//! mailbox holds 100k messages. This keeps uid → filename, size, flags and
+//! Message-ID per mailbox, in memory and persisted at
+//! `{mailbox}/.uid_index.json` beside `cur/` — sealed, in an encrypted vault,
+//! like the vault's other sidecars.
+//!
+//! The directory stays the truth. The index records the `cur/` mtime it last
+//! matched, and a write that didn't come through here — the app's own Maildir
+//! code, a restore, another process — moves that mtime, so the next lookup
+//! rescans. A rescan keeps what it knew about filenames that haven't changed,
+//! so it costs a directory listing, not a read of every message.
+//!
+//! A standard-layout mailbox (`maildir::standard`) is indexed the same way,
+//! with `new/` listed beside `cur/` and uids read from its `dovecot-uidlist`;
+//! both of those feed the mtime too.
+//!
+//! On a server with OBJECTID an entry also keeps the message's EMAILID and
+//! THREADID. Those aren't in the file, so they follow the uid through a
+//! rescan or a flag rename for as long as the Message-ID does.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant, UNIX_EPOCH};
+use tracing::{info, warn};
+
+use crate::maildir::compression::{self, Compression};
+use crate::maildir::encryption::{self, KeyId};
+use crate::maildir::standard;
+
+pub const INDEX_FILE: &str = ".uid_index.json";
+
+/// How stale the on-disk copy may get while a sync is storing messages. It is
+/// only a head start for the next process — a lost write costs a rescan.
+const SAVE_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Entry {
+    /// Filename inside `cur/`, or `new/` when `new` is set.
+    pub file: String,
+    pub size: u64,
+    pub flags: Vec<String>,
+    pub message_id: Option<String>,
+    /// Delivered to a standard mailbox's `new/` and not flagged since.
+    #[serde(default)]
+    pub new: bool,
+    /// SHA-256 (hex) of the bytes written, once the file read back at `size`.
+    /// `None` for a file that didn't come through `maildir::store_in`.
+    #[serde(default)]
+    pub sha256: Option<String>,
+    /// EMAILID / THREADID (RFC 8474), once sync has noted them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thread_id: Option<String>,
+}
+
+impl Entry {
+    pub fn path(&self, cur_dir: &Path) -> PathBuf {
+        if self.new {
+            cur_dir.with_file_name("new").join(&self.file)
+        } else {
+            cur_dir.join(&self.file)
+        }
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Index {
+    /// `cur/` mtime (ns since the epoch) the entries were last known to match.
+    dir_mtime: u64,
+    entries: HashMap<u32, Entry>,
+    #[serde(skip)]
+    dirty: bool,
+    #[serde(skip)]
+    saved_at: Option<Instant>,
+    /// A standard mailbox's uidlist took a line out of uid order, or kept one
+    /// for a deleted message; `flush` rewrites it.
+    #[serde(skip)]
+    untidy: bool,
+}
+
+type Shared = Arc<Mutex<Option<Index>>>;
+
+/// One slot per `cur/` directory. The map lock is only held to find the slot,
+/// so a rescan of one mailbox doesn't stall lookups in the others.
+fn slots() -> &'static Mutex<HashMap<PathBuf, Shared>> {
+    static SLOTS: OnceLock<Mutex<HashMap<PathBuf, Shared>>> = OnceLock::new();
+    SLOTS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+fn slot(cur_dir: &Path) -> Shared {
+    let mut slots = slots().lock().unwrap_or_else(|e| e.into_inner());
+    slots.entry(cur_dir.to_path_buf()).or_default().clone()
+}
+
+fn index_path(cur_dir: &Path) -> Option<PathBuf> {
+    cur_dir.parent().map(|m| m.join(INDEX_FILE))
+}
+
+fn mtime_ns(path: &Path) -> Option<u64> {
+    let modified = fs::metadata(path).ok()?.modified().ok()?;
+    Some(modified.duration_since(UNIX_EPOCH).ok()?.as_nanos() as u64)
+}
+
+/// The `cur/` mtime, mixed with those of `new/` and the uidlist where they
+/// exist. `None` when `cur/` doesn't.
+fn dir_mtime(cur_dir: &Path) -> Option<u64> {
+    let mut stamp = mtime_ns(cur_dir)?;
+    if let Some(mailbox_dir) = cur_dir.parent() {
+        let others = [mailbox_dir.join("new"), mailbox_dir.join(standard::UIDLIST_FILE)];
+        for (i, path) in others.iter().enumerate() {
+            if let Some(t) = mtime_ns(path) {
+                stamp ^= t.rotate_left(21 * (i as u32 + 1));
+            }
+        }
+    }
+    Some(stamp)
+}
+
+fn uid_of(name: &str) -> Option<u32> {
+    crate::maildir::vault_uid(name)
+}
+
+fn load(cur_dir: &Path) -> Index {
+    // One this process can't open — sealed while the vault is locked — is
+    // rebuilt like a missing one.
+    index_path(cur_dir)
+        .and_then(|p| fs::read(p).ok())
+        .and_then(|b| encryption::open(compression::vault_of(cur_dir), &b).ok().map(|b| b.into_owned()))
+        .and_then(|b| serde_json::from_slice(&b).ok())
+        .unwrap_or_default()
+}
+
+fn write(cur_dir: &Path, index: &Index) -> Result<(), String> {
+    let Some(path) = index_path(cur_dir) else { return Ok(()) };
+    let bytes = serde_json::to_vec(index).map_err(|e| e.to_string())?;
+    let bytes = match compression::vault_of(cur_dir) {
+        Some(vault) => encryption::sealer(vault)?.seal(&bytes, Compression::None)?.into_owned(),
+        None => bytes,
+    };
+    // Per-process temp name: the app and the daemon both keep this file.
+    let tmp = path.with_extension(format!("json.{}.tmp", std::process::id()));
+    let result = fs::write(&tmp, bytes).and_then(|()| fs::rename(&tmp, &path));
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result.map_err(|e| format!("{:?}: {}", path, e))
+}
+
+fn save(cur_dir: &Path, index: &mut Index) {
+    match write(cur_dir, index) {
+        Ok(()) => {
+            index.dirty = false;
+            index.saved_at = Some(Instant::now());
+        }
+        Err(e) => warn!("uid_index: failed to save {:?}: {}", cur_dir, e),
+    }
+}
+
+fn maybe_save(cur_dir: &Path, index: &mut Index) {
+    if index.dirty && index.saved_at.is_none_or(|t| t.elapsed() >= SAVE_INTERVAL) {
+        save(cur_dir, index);
+    }
+}
+
+/// Rebuild the entries from a listing of `cur/` (and `new/`, in a standard
+/// mailbox), reusing `old` for every file whose name hasn't changed.
+///
+/// A file's uid is its `{uid}:` prefix, else its uidlist line. A standard
+/// mailbox can hold both while a conversion is under way; a file with
+/// neither — delivered by another program — has no uid and isn't indexed.
+fn rescan(cur_dir: &Path, mut old: Index, mut mtime: u64) -> Index {
+    let object_ids: HashMap<u32, (Option<String>, String, Option<String>)> = old
+        .entries
+        .iter()
+        .filter_map(|(uid, e)| Some((*uid, (e.message_id.clone(), e.email_id.clone()?, e.thread_id.clone()))))
+        .collect();
+    let mut known: HashMap<String, Entry> = old.entries.drain().map(|(_, e)| (e.file.clone(), e)).collect();
+    let mut entries = HashMap::new();
+    let mut read = 0usize;
+    let mailbox_dir = cur_dir.parent();
+    let list = mailbox_dir.and_then(standard::Uidlist::read);
+    let keywords = match (&list, mailbox_dir) {
+        (Some(_), Some(m)) => standard::Keywords::load(m),
+        _ => standard::Keywords::default(),
+    };
+    // `new/` first: a message moving to `cur/` meanwhile is seen in one or the other.
+    let mut dirs = vec![(cur_dir.to_path_buf(), false)];
+    if let (Some(_), Some(m)) = (&list, mailbox_dir) {
+        dirs.insert(0, (m.join("new"), true));
+    }
+    let mut listed = 0usize;
+    for (dir, new) in dirs {
+        let Ok(dir) = fs::read_dir(&dir) else { continue };
+        for entry in dir.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let uid = match uid_of(&name) {
+                Some(uid) => uid,
+                None => match list.as_ref().and_then(|l| l.uids.get(standard::base_of(&name))) {
+                    Some(&uid) => {
+                        listed += 1;
+                        uid
+                    }
+                    None => continue,
+                },
+            };
+            if !entry.file_type().map(|t| t.is_file()).unwrap_or(false) {
+                continue;
+            }
+            let e = match known.remove(&name).filter(|e| e.new == new) {
+                Some(e) => e,
+                None => {
+                    read += 1;
+                    let message_id = crate::maildir::read_message_id(&entry.path());
+                    // Renamed, not replaced: the server's ids still apply.
+                    let (email_id, thread_id) = match object_ids.get(&uid) {
+                        Some((m, e, t)) if *m == message_id => (Some(e.clone()), t.clone()),
+                        _ => (None, None),
+                    };
+                    Entry {
+                        size: entry.metadata().map(|m| m.len()).unwrap_or(0),
+                        flags: crate::maildir::flags_of(&name, &keywords),
+                        message_id,
+                        file: name,
+                        new,
+                        sha256: None,
+                        email_id,
+                        thread_id,
+                    }
+                }
+            };
+            entries.insert(uid, e);
+        }
+    }
+    // The listing is in hand, so this is the moment to drop dead uidlist lines.
+    if let (Some(list), Some(m)) = (&list, mailbox_dir) {
+        if !list.is_tidy() || list.lines() > listed {
+            match standard::tidy(m) {
+                Ok(true) => mtime = dir_mtime(cur_dir).unwrap_or(mtime),
+                Ok(false) => {}
+                Err(e) => warn!("uid_index: {}", e),
+            }
+        }
+    }
+    info!("uid_index: rescanned {:?} — {} entries, {} new", cur_dir, entries.len(), read);
+    Index { dir_mtime: mtime, entries, dirty: true, saved_at: None, untidy: false }
+}
+
+/// Run `f` over the mailbox's index, rescanning first if `cur/` moved on
+/// without it. `None` when `cur/` doesn't exist.
+fn with_index<T>(cur_dir: &Path, force_rescan: bool, f: impl FnOnce(&mut Index) -> T) -> Option<T> {
+    let slot = slot(cur_dir);
+    let mut guard = slot.lock().unwrap_or_else(|e| e.into_inner());
+    let Some(mtime) = dir_mtime(cur_dir) else {
+        *guard = None;
+        return None;
+    };
+    let current = guard.take().unwrap_or_else(|| load(cur_dir));
+    let mut index = if !force_rescan && current.dir_mtime == mtime {
+        current
+    } else {
+        let mut fresh = rescan(cur_dir, current, mtime);
+        // A rebuild is the expensive part; don't make the next process repeat it.
+        save(cur_dir, &mut fresh);
+        fresh
+    };
+    let out = f(&mut index);
+    *guard = Some(index);
+    Some(out)
+}
+
+/// The mailbox's change stamp, as its index compares it: moves whenever
+/// `cur/` (or `new/`, or the uidlist) does. `None` when `cur/` doesn't exist.
+pub fn stamp(cur_dir: &Path) -> Option<u64> {
+    dir_mtime(cur_dir)
+}
+
+/// The file holding `uid`, if the mailbox has one.
+pub fn lookup(cur_dir: &Path, uid: u32) -> Option<PathBuf> {
+    entry(cur_dir, uid).map(|e| e.path(cur_dir))
+}
+
+/// Everything the index knows about `uid`.
+pub fn entry(cur_dir: &Path, uid: u32) -> Option<Entry> {
+    let found = with_index(cur_dir, false, |idx| idx.entries.get(&uid).cloned())?;
+    match found {
+        // Renamed or removed inside the mtime's resolution: trust the disk.
+        Some(e) if !e.path(cur_dir).exists() => {
+            with_index(cur_dir, true, |idx| idx.entries.get(&uid).cloned()).flatten()
+        }
+        found => found,
+    }
+}
+
+/// Every uid in the mailbox, ascending.
+pub fn uids(cur_dir: &Path) -> Vec<u32> {
+    let mut uids = with_index(cur_dir, false, |idx| idx.entries.keys().copied().collect::<Vec<_>>())
+        .unwrap_or_default();
+    uids.sort_unstable();
+    uids
+}
+
+/// Every entry in the mailbox, in no particular order.
+pub fn entries(cur_dir: &Path) -> Vec<(u32, Entry)> {
+    with_index(cur_dir, false, |idx| idx.entries.iter().map(|(u, e)| (*u, e.clone())).collect())
+        .unwrap_or_default()
+}
+
+/// Make a change to `cur/` and record it in the same step. `op` does the
+/// filesystem work and returns where `uid` lives afterwards — the new path
+/// after a write or a flag rename, `None` after a delete.
+///
+/// Going through here is what keeps a write from reading as somebody else's:
+/// the index is brought up to date *before* `op` runs, so the mtime it records
+/// afterwards covers only this change.
+pub fn change(
+    cur_dir: &Path,
+    uid: u32,
+    op: impl FnOnce() -> Result<Option<PathBuf>, String>,
+) -> Result<Option<PathBuf>, String> {
+    let mut op = Some(op);
+    let mut noted = None;
+    let applied = with_index(cur_dir, false, |idx| {
+        let before = idx.dir_mtime;
+        let below = !idx.entries.contains_key(&uid) && idx.entries.keys().any(|&u| u > uid);
+        let result = (op.take().expect("op runs once"))();
+        if let Ok(now) = &result {
+            match now {
+                Some(path) => {
+                    record(idx, uid, path);
+                    // A standard delivery appended its uidlist line out of order.
+                    if below && idx.entries.get(&uid).is_some_and(|e| uid_of(&e.file).is_none()) {
+                        idx.untidy = true;
+                    }
+                }
+                None => {
+                    // Its uidlist line is dead now.
+                    if idx.entries.remove(&uid).is_some_and(|e| uid_of(&e.file).is_none()) {
+                        idx.untidy = true;
+                    }
+                }
+            }
+            touched(cur_dir, idx);
+            noted = Some((before, idx.dir_mtime, idx.entries.get(&uid).and_then(|e| e.message_id.clone())));
+        }
+        result
+    });
+    // Outside the lock: the Message-ID index takes its own.
+    if let Some((before, after, message_id)) = noted {
+        crate::message_index::changed(cur_dir, uid, message_id, before, after);
+    }
+    match applied {
+        Some(result) => result,
+        // No `cur/` yet: nothing to keep in step with.
+        None => (op.take().expect("op runs once"))(),
+    }
+}
+
+fn record(idx: &mut Index, uid: u32, path: &Path) {
+    let Some(name) = path.file_name().map(|n| n.to_string_lossy().to_string()) else { return };
+    let size = fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+    // A flag rename keeps the content, and with it the Message-ID and hash.
+    let old = idx.entries.get(&uid);
+    let (message_id, sha256) = match old {
+        Some(old) if old.size == size => (old.message_id.clone(), old.sha256.clone()),
+        _ => (crate::maildir::read_message_id(path), None),
+    };
+    // The server's ids belong to the message, whatever the file went through.
+    let (email_id, thread_id) = match old {
+        Some(old) if old.message_id == message_id => (old.email_id.clone(), old.thread_id.clone()),
+        _ => (None, None),
+    };
+    let new = path.parent().and_then(|p| p.file_name()).is_some_and(|n| n == "new");
+    let flags = crate::maildir::message_flags(path);
+    idx.entries.insert(uid, Entry { file: name, size, flags, message_id, new, sha256, email_id, thread_id });
+}
+
+/// Record the hash of what a write just put at `uid`. Dropped if the file on
+/// disk isn't `size` bytes — the entry then stays unverified.
+pub fn verified(cur_dir: &Path, uid: u32, size: u64, sha256: String) {
+    with_index(cur_dir, false, |idx| {
+        if let Some(e) = idx.entries.get_mut(&uid).filter(|e| e.size == size) {
+            e.sha256 = Some(sha256);
+            idx.dirty = true;
+            maybe_save(cur_dir, idx);
+        }
+    });
+}
+
+/// Note the server's EMAILID and THREADID for uids the mailbox holds. Uids
+/// without a file are skipped. Returns how many entries changed.
+pub fn note_object_ids(cur_dir: &Path, ids: &[(u32, String, Option<String>)]) -> usize {
+    with_index(cur_dir, false, |idx| {
+        let mut changed = 0;
+        for (uid, email_id, thread_id) in ids {
+            let Some(e) = idx.entries.get_mut(uid) else { continue };
+            if e.email_id.as_ref() != Some(email_id) || e.thread_id != *thread_id {
+                e.email_id = Some(email_id.clone());
+                e.thread_id = thread_id.clone();
+                changed += 1;
+            }
+        }
+        if changed > 0 {
+            idx.dirty = true;
+            maybe_save(cur_dir, idx);
+        }
+        changed
+    })
+    .unwrap_or(0)
+}
+
+/// Uids with no EMAILID noted yet, ascending.
+pub fn without_object_ids(cur_dir: &Path) -> Vec<u32> {
+    let mut uids = with_index(cur_dir, false, |idx| {
+        idx.entries.iter().filter(|(_, e)| e.email_id.is_none()).map(|(u, _)| *u).collect::<Vec<_>>()
+    })
+    .unwrap_or_default();
+    uids.sort_unstable();
+    uids
+}
+
+/// Rewrite the on-disk index sealed under `key`, the vault's current one, if
+/// it isn't already. Returns whether it was rewritten.
+pub fn reseal(cur_dir: &Path, key: Option<KeyId>) -> Result<bool, String> {
+    let Some(path) = index_path(cur_dir) else { return Ok(false) };
+    let slot = slot(cur_dir);
+    let mut guard = slot.lock().unwrap_or_else(|e| e.into_inner());
+    let Ok(bytes) = fs::read(&path) else { return Ok(false) };
+    if encryption::sealed_header(&bytes).map(|h| h.0) == key {
+        return Ok(false);
+    }
+    let mut index = guard.take().unwrap_or_else(|| load(cur_dir));
+    let result = write(cur_dir, &index);
+    if result.is_ok() {
+        index.dirty = false;
+        index.saved_at = Some(Instant::now());
+    }
+    *guard = Some(index);
+    result.map(|()| true)
+}
+
+/// Drop the index for `cur_dir`, memory and disk, so the next lookup rebuilds
+/// it. For bulk renames like a generation repair, where patching entry by
+/// entry would cost more than the rescan.
+pub fn invalidate(cur_dir: &Path) {
+    let slot = slot(cur_dir);
+    let mut guard = slot.lock().unwrap_or_else(|e| e.into_inner());
+    *guard = None;
+    if let Some(path) = index_path(cur_dir) {
+        let _ = fs::remove_file(path);
+    }
+}
+
+/// Write every index with unsaved changes, and tidy any uidlist a delivery or
+/// a delete left out of shape. Cheap when there are none.
+pub fn flush() {
+    let all: Vec<(PathBuf, Shared)> = {
+        let slots = slots().lock().unwrap_or_else(|e| e.into_inner());
+        slots.iter().map(|(k, v)| (k.clone(), v.clone())).collect()
+    };
+    for (cur_dir, slot) in all {
+        let mut guard = slot.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(idx) = guard.as_mut() else { continue };
+        if idx.untidy {
+            idx.untidy = false;
+            // Only claim the new mtime if nobody else changed the mailbox first.
+            let current = dir_mtime(&cur_dir) == Some(idx.dir_mtime);
+            match cur_dir.parent().map(standard::tidy) {
+                Some(Ok(true)) if current => touched(&cur_dir, idx),
+                Some(Err(e)) => warn!("uid_index: {}", e),
+                _ => {}
+            }
+        }
+        if idx.dirty {
+            save(&cur_dir, idx);
+        }
+    }
+}
+
+/// After a change made through here: the entries match `cur/` as it is now.
+fn touched(cur_dir: &Path, idx: &mut Index) {
+    if let Some(mtime) = dir_mtime(cur_dir) {
+        idx.dir_mtime = mtime;
+    }
+    idx.dirty = true;
+    maybe_save(cur_dir, idx);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::maildir;
+
+    fn raw(id: &str) -> Vec<u8> {
+        format!("Message-ID: <{}>\r\nSubject: s\r\n\r\nbody", id).into_bytes()
+    }
+
+    #[test]
+    fn follows_core_writes_and_persists() {
+        let dir = std::env::temp_dir().join("mailvault-test-uid-index");
+        let _ = fs::remove_dir_all(&dir);
+        let cur = maildir::cur_path(&dir, "acc1", "INBOX");
+
+        for uid in 1..=3 {
+            maildir::store(&dir, "acc1", "INBOX", uid, &raw(&format!("m{}@x", uid)), &[]).unwrap();
+        }
+        maildir::set_flags(&dir, "acc1", "INBOX", 2, &["\\Seen".into()]).unwrap();
+        maildir::delete(&dir, "acc1", "INBOX", 3).unwrap();
+
+        let e = entry(&cur, 2).unwrap();
+        assert_eq!(e.flags, vec!["\\Seen".to_string()]);
+        assert_eq!(e.message_id.as_deref(), Some("m2@x"));
+        assert_eq!(e.size, raw("m2@x").len() as u64);
+        assert!(cur.join(&e.file).exists());
+        assert_eq!(uids(&cur), vec![1, 2]);
+        assert!(!maildir::email_exists(&dir, "acc1", "INBOX", 3));
+
+        flush();
+        let on_disk: Index = serde_json::from_slice(&fs::read(index_path(&cur).unwrap()).unwrap()).unwrap();
+        assert_eq!(on_disk.entries.len(), 2);
+        assert_eq!(on_disk.entries[&2], e);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn rebuilds_when_missing_or_stale() {
+        let dir = std::env::temp_dir().join("mailvault-test-uid-index-stale");
+        let _ = fs::remove_dir_all(&dir);
+        let cur = maildir::cur_path(&dir, "acc1", "INBOX");
+        maildir::store(&dir, "acc1", "INBOX", 1, &raw("a@x"), &[]).unwrap();
+        assert!(maildir::find_by_uid(&cur, 1).is_some());
+
+        // Written behind the index's back, the way the app's own Maildir code does.
+        fs::write(cur.join("7:seen:1700000000.eml"), raw("b@x")).unwrap();
+        assert_eq!(entry(&cur, 7).unwrap().message_id.as_deref(), Some("b@x"));
+        let old = maildir::find_by_uid(&cur, 1).unwrap();
+        fs::rename(&old, cur.join("1:flagged:1700000000.eml")).unwrap();
+        assert_eq!(entry(&cur, 1).unwrap().flags, vec!["\\Flagged".to_string()]);
+
+        // A persisted index that lies about the directory is rebuilt, not trusted.
+        let bogus = Index {
+            dir_mtime: 1,
+            entries: HashMap::from([(
+                9,
+                Entry {
+                    file: "9::1.eml".into(),
+                    size: 0,
+                    flags: vec![],
+                    message_id: None,
+                    new: false,
+                    sha256: None,
+                    email_id: None,
+                    thread_id: None,
+                },
+            )]),
+            ..Default::default()
+        };
+        invalidate(&cur);
+        fs::write(index_path(&cur).unwrap(), serde_json::to_vec(&bogus).unwrap()).unwrap();
+        assert_eq!(uids(&cur), vec![1, 7]);
+
+        // Missing entirely: same.
+        invalidate(&cur);
+        assert!(!index_path(&cur).unwrap().exists());
+        assert_eq!(maildir::list_uids(&dir, "acc1", "INBOX"), vec![1, 7]);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -166,3 +166,72 @@ async fn a_response_split_across_tcp_writes_still_parses() {
     let uids = search_all_uids(&mut sess, "INBOX", false).await.expect("enumerate");
     assert_eq!(uids.len(), 20);
 }
+
+/// QRESYNC answers the expunge question in the SELECT itself: the vanished UIDs
+/// and the flag changes since the given modseq, nothing older.
+#[async_std::test]
+async fn qresync_select_reports_vanished_uids_and_flag_changes() {
+    let mut mb = mailbox_with_modseqs();
+    mb.expunge(&[2]); // modseq 41
+    mb.by_uid_mut(4).unwrap().modseq = 42;
+    mb.highest_modseq = 42;
+    let server = MockImap::start(Scenario::new().with_cap("QRESYNC").mailbox(mb));
+    let mut sess = session(&server).await;
+    assert_eq!(server.count_commands("ENABLE QRESYNC"), 1, "enabled right after login");
+
+    let q = select_qresync(&mut sess, "INBOX", 1, 40, &[1, 2, 3, 4])
+        .await
+        .expect("select")
+        .expect("server accepted QRESYNC");
+
+    assert_eq!(q.vanished, vec![2]);
+    assert_eq!(q.changed_flags.iter().map(|(u, _)| *u).collect::<Vec<_>>(), vec![4]);
+    assert_eq!(q.mailbox.exists, 3);
+    assert_eq!(q.mailbox.highest_modseq, Some(42));
+}
+
+/// A sparse cache can't go on the SELECT line whole: the known-uids set is
+/// dropped rather than sent hundreds of KB long, and the server then reports
+/// every expunge since the modseq — the caller drops the ones it never had.
+#[async_std::test]
+async fn qresync_select_leaves_out_a_known_uid_set_too_long_for_one_line() {
+    let mut mb = mailbox_with_modseqs();
+    mb.expunge(&[2]);
+    let server = MockImap::start(Scenario::new().with_cap("QRESYNC").mailbox(mb));
+    let mut sess = session(&server).await;
+
+    let sparse: Vec<u32> = (1..=4).chain((10..200_000).step_by(2)).collect();
+    let q = select_qresync(&mut sess, "INBOX", 1, 40, &sparse).await.unwrap().unwrap();
+    assert_eq!(q.vanished, vec![2]);
+    let select = server.commands().into_iter().find(|c| c.contains("SELECT")).expect("SELECT sent");
+    assert!(select.len() < 200, "SELECT line is {} bytes", select.len());
+}
+
+/// After a UIDVALIDITY change the server ignores the QRESYNC parameters. An
+/// empty VANISHED there must not be read as "nothing was deleted" — the caller
+/// sees the new UIDVALIDITY and resyncs in full.
+#[async_std::test]
+async fn qresync_select_with_stale_uidvalidity_reports_nothing() {
+    let mut mb = mailbox_with_modseqs();
+    mb.expunge(&[1]);
+    let server = MockImap::start(Scenario::new().with_cap("QRESYNC").mailbox(mb));
+    let mut sess = session(&server).await;
+
+    let q = select_qresync(&mut sess, "INBOX", 999, 1, &[]).await.unwrap().unwrap();
+    assert!(q.vanished.is_empty());
+    assert_eq!(q.mailbox.uid_validity, Some(1));
+}
+
+/// Without ENABLE the server refuses the parameter. That refusal is a clean
+/// `None`, and the session is still usable for the plain fallback.
+#[async_std::test]
+async fn qresync_select_refused_leaves_the_session_clean() {
+    let server = MockImap::start(Scenario::new().mailbox(mailbox_with_modseqs()));
+    let mut sess = session(&server).await;
+    assert_eq!(server.count_commands("ENABLE"), 0, "not advertised, not enabled");
+
+    let refused = select_qresync(&mut sess, "INBOX", 1, 10, &[]).await.expect("no transport error");
+    assert!(refused.is_none());
+    let uids = search_all_uids(&mut sess, "INBOX", false).await.expect("usable session");
+    assert_eq!(uids.len(), 4);
+}
@@ -5,7 +5,7 @@ mod common;
 use common::{config_for, eml, pool, session};
 use mailvault_core::imap::*;
 use mock_imap::state::{synthetic_mailbox, Mailbox, Message};
-use mock_imap::{MockImap, Scenario};
+use mock_imap::{Action, MockImap, Scenario, Trigger};
 
 #[async_std::test]
 async fn connects_lists_and_fetches_a_small_inbox() {
@@ -146,6 +146,25 @@ async fn fetches_a_full_message_body_by_uid() {
     assert!(email.text.unwrap_or_default().contains("Body text here"));
 }
 
+/// Background archiving must not mark anything read, and must not invent an
+/// entry for a UID the server doesn't have.
+#[async_std::test]
+async fn fetches_raw_bodies_by_uid_without_setting_seen() {
+    let server = MockImap::start(Scenario::new().mailbox(synthetic_mailbox("INBOX", 25)));
+    let mut sess = session(&server).await;
+
+    let uids: Vec<u32> = (1..=25).chain([99]).collect();
+    let raw = fetch_raw_by_uids(&mut sess, "INBOX", &uids).await.expect("raw fetch");
+    assert_eq!(raw.len(), 25, "uid 99 does not exist");
+    let first = raw.iter().find(|m| m.uid == 7).unwrap();
+    assert!(String::from_utf8_lossy(&first.raw).contains("Body of message 7."));
+    assert!(!first.flags.iter().any(|f| f == "\\Seen"));
+    assert_eq!(server.count_commands("BODY.PEEK[]"), 2, "two chunks of at most 20");
+
+    let sizes = fetch_sizes(&mut sess, "INBOX", &[7, 99]).await.expect("sizes");
+    assert_eq!(sizes, vec![(7, first.raw.len() as u32)]);
+}
+
 #[async_std::test]
 async fn paginates_a_large_mailbox_newest_first() {
     let server = MockImap::start(Scenario::new().mailbox(synthetic_mailbox("INBOX", 250)));
@@ -198,3 +217,198 @@ async fn reports_a_useful_error_when_login_is_rejected() {
         .expect_err("login must fail");
     assert!(err.contains("Login failed"), "got: {err}");
 }
+
+#[async_std::test]
+async fn fetches_gmail_ids_and_labels() {
+    let mut all = Mailbox::new("[Gmail]/All Mail").with_attrs(&["\\HasNoChildren", "\\All"]);
+    all.add(
+        Message::new(1, eml("Trip", "a@example.com", "b"))
+            .with_gmail(1_278_455_344_230_334_865, 1_278_455_344_230_334_865, &["\\Inbox", "Entw&APw-rfe"])
+            .with_modseq(5),
+    );
+    all.add(Message::new(2, eml("Old", "b@example.com", "b")).with_gmail(7, 3, &[]).with_modseq(2));
+    let server = MockImap::start(
+        Scenario::new().with_cap("X-GM-EXT-1").mailbox(synthetic_mailbox("INBOX", 1)).mailbox(all),
+    );
+    let mut sess = session(&server).await;
+
+    let mailboxes = list_mailboxes(&mut sess).await.unwrap();
+    assert_eq!(gmail::all_mail_path(&mailboxes).as_deref(), Some("[Gmail]/All Mail"));
+
+    let meta = gmail::fetch_gmail_meta(&mut sess, "[Gmail]/All Mail", None).await.expect("fetch");
+    assert_eq!(meta.len(), 2);
+    assert_eq!(meta[0].msg_id, 1_278_455_344_230_334_865);
+    assert_eq!(meta[0].labels, vec!["\\Inbox", "Entwürfe"]);
+    assert_eq!(meta[1].thread_id, Some(3));
+    assert!(meta[1].labels.is_empty());
+
+    let changed = gmail::fetch_gmail_meta(&mut sess, "[Gmail]/All Mail", Some(3)).await.expect("changed");
+    assert_eq!(changed.iter().map(|m| m.uid).collect::<Vec<_>>(), vec![1]);
+}
+
+/// EMAILID and THREADID are read off the wire beside async-imap, and the
+/// session carries on as if nothing had happened.
+#[async_std::test]
+async fn fetches_object_ids_and_leaves_the_session_usable() {
+    let mut inbox = Mailbox::new("INBOX");
+    inbox.add(Message::new(1, eml("One", "a@example.com", "b")).with_object_ids("M1a", Some("T9")));
+    inbox.add(Message::new(2, eml("Two", "a@example.com", "b")).with_object_ids("M2b", None));
+    inbox.add(Message::new(3, eml("Three", "a@example.com", "b")).with_object_ids("M3c", Some("T9")));
+    let server = MockImap::start(Scenario::new().with_cap("OBJECTID").mailbox(inbox));
+    let mut sess = session(&server).await;
+
+    let ids = objectid::fetch_object_ids(&mut sess, "INBOX", "1:2").await.expect("fetch");
+    assert_eq!(
+        ids,
+        vec![
+            ObjectIds { uid: 1, email_id: "M1a".into(), thread_id: Some("T9".into()) },
+            ObjectIds { uid: 2, email_id: "M2b".into(), thread_id: None },
+        ]
+    );
+
+    let (mut headers, _) = fetch_headers_by_uids(&mut sess, "INBOX", &[1, 3]).await.expect("headers after");
+    let all = objectid::fetch_object_ids(&mut sess, "INBOX", "1:*").await.expect("fetch all");
+    assert_eq!(objectid::apply(&mut headers, &all), 2);
+    let three = headers.iter().find(|h| h.uid == 3).unwrap();
+    assert_eq!(three.email_id.as_deref(), Some("M3c"));
+    assert_eq!(three.object_thread_id.as_deref(), Some("T9"));
+    assert_eq!(objectid::find_by_email_id(&mut sess, "M2b").await.unwrap(), Some(2));
+}
+
+/// A short mail carrying a large attachment, to read and save part by part.
+fn with_large_attachment(attachment: &[u8]) -> String {
+    use base64::Engine;
+    let encoded = base64::engine::general_purpose::STANDARD.encode(attachment);
+    let lines: Vec<&str> = encoded
+        .as_bytes()
+        .chunks(76)
+        .map(|l| std::str::from_utf8(l).unwrap())
+        .collect();
+    format!(
+        "From: a@example.com\r\n\
+         To: user@example.com\r\n\
+         Subject: Scans\r\n\
+         Date: Thu, 01 Jan 2026 12:00:00 +0000\r\n\
+         Message-ID: <scans@example.com>\r\n\
+         MIME-Version: 1.0\r\n\
+         Content-Type: multipart/mixed; boundary=\"b1\"\r\n\
+         \r\n\
+         --b1\r\n\
+         Content-Type: multipart/alternative; boundary=\"b2\"\r\n\
+         \r\n\
+         --b2\r\n\
+         Content-Type: text/plain; charset=UTF-8\r\n\
+         Content-Transfer-Encoding: quoted-printable\r\n\
+         \r\n\
+         Gr=C3=BC=C3=9Fe, the scans are attached.\r\n\
+         --b2\r\n\
+         Content-Type: text/html; charset=UTF-8\r\n\
+         \r\n\
+         <p>The scans</p>\r\n\
+         --b2--\r\n\
+         --b1\r\n\
+         Content-Type: application/pdf; name=\"scans.pdf\"\r\n\
+         Content-Disposition: attachment; filename=\"scans.pdf\"\r\n\
+         Content-Transfer-Encoding: base64\r\n\
+         \r\n\
+         {}\r\n\
+         --b1--\r\n",
+        lines.join("\r\n")
+    )
+}
+
+/// Reading fetches the text parts alone; saving fetches the attachment in
+/// `<offset.length>` chunks — decoded here without BINARY, by the server
+/// with it — and archiving the message as stored. The session carries on
+/// afterwards.
+#[async_std::test]
+async fn fetches_single_parts_and_streams_large_ones_to_disk() {
+    let attachment: Vec<u8> = (0..(2 * parts::CHUNK_SIZE + 12345) as u32).map(|i| (i * 7 % 251) as u8).collect();
+    let raw = with_large_attachment(&attachment);
+    let dir = tempfile::tempdir().unwrap();
+
+    for caps in [false, true] {
+        let scenario = Scenario::new().mailbox(Mailbox::new("INBOX").push(raw.as_str()));
+        let server = MockImap::start(if caps { scenario.with_cap(parts::BINARY) } else { scenario });
+        let mut sess = session(&server).await;
+
+        let structure = parts::fetch_structure(&mut sess, "INBOX", 1).await.unwrap().expect("structure");
+        assert_eq!(structure.size as usize, raw.len());
+        let sections: Vec<&str> = structure.parts.iter().map(|p| p.section.as_str()).collect();
+        assert_eq!(sections, ["1.1", "1.2", "2"]);
+        let pdf = &structure.parts[2];
+        assert_eq!((pdf.content_type.as_str(), pdf.encoding.as_str()), ("application/pdf", "base64"));
+        assert_eq!(pdf.filename.as_deref(), Some("scans.pdf"));
+        assert!(pdf.is_attachment() && !structure.parts[0].is_attachment());
+
+        let read = parts::fetch_email_for_reading(&mut sess, "INBOX", 1, caps).await.unwrap().expect("email");
+        assert_eq!(read.email.subject, "Scans");
+        assert_eq!(read.email.text.as_deref().map(str::trim_end), Some("Grüße, the scans are attached."));
+        assert_eq!(read.email.html.as_deref().map(str::trim_end), Some("<p>The scans</p>"));
+        assert_eq!(read.email.attachments.len(), 1);
+        assert_eq!(read.email.attachments[0].filename.as_deref(), Some("scans.pdf"));
+        assert!(read.email.raw_source_bytes.is_empty());
+
+        let saved = dir.path().join("scans.pdf");
+        let written = parts::download(&mut sess, "INBOX", 1, Some(pdf), caps, &saved).await.unwrap();
+        assert_eq!(written as usize, attachment.len());
+        assert!(std::fs::read(&saved).unwrap() == attachment, "attachment differs (binary={})", caps);
+
+        let archived = dir.path().join("1.eml");
+        parts::download(&mut sess, "INBOX", 1, None, caps, &archived).await.unwrap();
+        assert!(std::fs::read(&archived).unwrap() == raw.as_bytes());
+
+        let missing = parts::MimePart { section: "9".into(), ..pdf.clone() };
+        assert!(parts::download(&mut sess, "INBOX", 1, Some(&missing), caps, &saved).await.is_err());
+        let light = fetch_email_by_uid_light(&mut sess, "INBOX", 1).await.unwrap().expect("after");
+        assert_eq!(light.attachments[0].size, attachment.len());
+    }
+}
+
+/// A quoted-printable part of exactly `CHUNK_SIZE` bytes whose last line has
+/// no CRLF: the decoder holds that line back until the end, and a server that
+/// answers NIL past the end — rather than an empty string — must not lose it.
+#[async_std::test]
+async fn a_part_ending_without_crlf_keeps_its_last_line() {
+    let line = "abcdefgh=3D\r\n";
+    let tail = "the end caf=C3=A9";
+    let lines = (parts::CHUNK_SIZE - tail.len()) / line.len();
+    let pad = "x".repeat((parts::CHUNK_SIZE - tail.len()) % line.len());
+    let content = format!("{}{}{}", line.repeat(lines), pad, tail);
+    assert_eq!(content.len(), parts::CHUNK_SIZE);
+    let raw = format!(
+        "From: a@example.com\r\n\
+         To: user@example.com\r\n\
+         Subject: Log\r\n\
+         Message-ID: <log@example.com>\r\n\
+         MIME-Version: 1.0\r\n\
+         Content-Type: multipart/mixed; boundary=\"b1\"\r\n\
+         \r\n\
+         --b1\r\n\
+         Content-Type: text/plain; charset=UTF-8\r\n\
+         Content-Transfer-Encoding: quoted-printable\r\n\
+         \r\n\
+         {}\r\n\
+         --b1--\r\n",
+        content
+    );
+    let past_end = format!("<{}.", parts::CHUNK_SIZE);
+    let server = MockImap::start(
+        Scenario::new().mailbox(Mailbox::new("INBOX").push(raw.as_str())).fault(
+            Trigger::with("FETCH", &past_end),
+            Action::RespondRaw("* 1 FETCH (UID 1 BODY[1] NIL)\r\n{tag} OK FETCH completed\r\n".into()),
+        ),
+    );
+    let mut sess = session(&server).await;
+
+    let structure = parts::fetch_structure(&mut sess, "INBOX", 1).await.unwrap().expect("structure");
+    let text = &structure.parts[0];
+    assert_eq!(text.encoding, "quoted-printable");
+    let dir = tempfile::tempdir().unwrap();
+    let saved = dir.path().join("log.txt");
+    let written = parts::download(&mut sess, "INBOX", 1, Some(text), false, &saved).await.unwrap();
+
+    let expected = format!("{}{}the end café", "abcdefgh=\r\n".repeat(lines), pad);
+    assert_eq!(written as usize, expected.len());
+    assert!(std::fs::read(&saved).unwrap() == expected.as_bytes(), "last line lost");
+}
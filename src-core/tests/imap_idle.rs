@@ -0,0 +1,93 @@
+//! Push notifications: IDLE rounds and the NOOP-polling fallback.
+//!
+//! The mock snapshots the selected mailbox at SELECT; `MockImap::update` then
+//! plays the part of another client delivering, expunging or flagging mail, and
+//! the idling session has to notice.
+
+mod common;
+
+use std::time::Duration;
+
+use common::session;
+use mailvault_core::imap::*;
+use mock_imap::state::synthetic_mailbox;
+use mock_imap::{Message, MockImap, Scenario};
+
+fn deliver(server: &MockImap, uid: u32) {
+    server.update(|st| {
+        st.find_mut("INBOX").unwrap().add(Message::new(
+            uid,
+            format!("Subject: New {uid}\r\nMessage-ID: <new-{uid}@example.com>\r\n\r\nhi\r\n"),
+        ));
+    });
+}
+
+#[async_std::test]
+async fn idle_wakes_when_mail_arrives() {
+    let server = MockImap::start(Scenario::new().mailbox(synthetic_mailbox("INBOX", 2)));
+    let mut sess = session(&server).await;
+    select_mailbox(&mut sess, "INBOX").await.unwrap();
+
+    deliver(&server, 3);
+    let (mut sess, event) = idle_wait(sess, Duration::from_secs(10)).await.expect("idle");
+    assert_eq!(event, IdleEvent::Changed);
+
+    // DONE was sent and acknowledged: the session carries commands again.
+    assert_eq!(server.count_commands("DONE"), 1);
+    let uids = search_all_uids(&mut sess, "INBOX", false).await.expect("usable after IDLE");
+    assert_eq!(uids.len(), 3);
+}
+
+#[async_std::test]
+async fn idle_wakes_on_expunge_and_flag_changes() {
+    let server = MockImap::start(Scenario::new().mailbox(synthetic_mailbox("INBOX", 3)));
+    let mut sess = session(&server).await;
+    select_mailbox(&mut sess, "INBOX").await.unwrap();
+
+    server.update(|st| st.find_mut("INBOX").unwrap().messages.retain(|m| m.uid != 2));
+    let (sess, event) = idle_wait(sess, Duration::from_secs(10)).await.unwrap();
+    assert_eq!(event, IdleEvent::Changed);
+
+    server.update(|st| {
+        let msg = st.find_mut("INBOX").unwrap().by_uid_mut(3).unwrap();
+        msg.flags.push("\\Seen".to_string());
+        msg.modseq += 1;
+    });
+    let (_sess, event) = idle_wait(sess, Duration::from_secs(10)).await.unwrap();
+    assert_eq!(event, IdleEvent::Changed);
+}
+
+/// The round is bounded by `max_wait` even when nothing happens — that bound is
+/// what lets the caller re-issue IDLE before the server's 30-minute logout.
+#[async_std::test]
+async fn idle_round_ends_quiet_after_max_wait() {
+    let server = MockImap::start(Scenario::new().mailbox(synthetic_mailbox("INBOX", 2)));
+    let mut sess = session(&server).await;
+    select_mailbox(&mut sess, "INBOX").await.unwrap();
+
+    let (sess, event) = idle_wait(sess, Duration::from_millis(300)).await.expect("idle");
+    assert_eq!(event, IdleEvent::Quiet);
+
+    // And a second round on the same session still works.
+    deliver(&server, 3);
+    let (_sess, event) = idle_wait(sess, Duration::from_secs(10)).await.expect("re-idle");
+    assert_eq!(event, IdleEvent::Changed);
+    assert_eq!(server.count_commands("IDLE"), 2);
+}
+
+#[async_std::test]
+async fn noop_poll_reports_changes_only_once() {
+    let server = MockImap::start(
+        Scenario::new()
+            .without_cap("IDLE")
+            .mailbox(synthetic_mailbox("INBOX", 2)),
+    );
+    let mut sess = session(&server).await;
+    select_mailbox(&mut sess, "INBOX").await.unwrap();
+
+    assert_eq!(noop_poll(&mut sess).await.unwrap(), IdleEvent::Quiet);
+
+    deliver(&server, 3);
+    assert_eq!(noop_poll(&mut sess).await.unwrap(), IdleEvent::Changed);
+    assert_eq!(noop_poll(&mut sess).await.unwrap(), IdleEvent::Quiet);
+}
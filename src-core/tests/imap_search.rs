@@ -172,3 +172,61 @@ async fn search_filters_by_from_and_subject() {
     assert_eq!(by_subject.len(), 1);
     assert_eq!(by_subject[0].subject, "Lunch");
 }
+
+/// The search box language means the same thing on the server and in the
+/// vault: each query finds the same messages both ways.
+#[async_std::test]
+async fn structured_queries_agree_between_server_and_vault() {
+    use mailvault_core::search::{DocKey, SearchIndex};
+
+    let with_attachment = "From: friend@example.com\r\n\
+        To: user@example.com\r\n\
+        Subject: Lunch\r\n\
+        Date: Tue, 10 Feb 2026 12:00:00 +0000\r\n\
+        Content-Type: multipart/mixed; boundary=\"b\"\r\n\r\n\
+        --b\r\nContent-Type: text/plain\r\n\r\ninvoice attached\r\n\
+        --b\r\nContent-Type: application/pdf\r\nContent-Disposition: attachment; filename=\"i.pdf\"\r\n\r\nJVBERi0=\r\n\
+        --b--\r\n"
+        .to_string();
+    let large = format!(
+        "From: acct@vendor.com\r\nTo: user@example.com\r\nSubject: Re: Invoice\r\n\
+         Date: Sun, 01 Mar 2026 12:00:00 +0000\r\n\r\n{}\r\n",
+        "overdue ".repeat(400)
+    );
+    let messages = [
+        (1, eml("Invoice March", "acct@vendor.com", "pay now"), vec!["\\Seen"], "01-Jan-2026 12:00:00 +0000"),
+        (2, with_attachment, vec![], "10-Feb-2026 12:00:00 +0000"),
+        (3, large, vec!["\\Flagged"], "01-Mar-2026 12:00:00 +0000"),
+    ];
+
+    let mut mb = Mailbox::new("INBOX");
+    let mut index = SearchIndex::default();
+    for (uid, raw, flags, date) in &messages {
+        mb.add(mock_imap::Message::new(*uid, raw.clone()).with_flags(flags).with_internal_date(date));
+        let key = DocKey { account_id: "acc1".into(), mailbox: "INBOX".into(), uid: *uid };
+        index.add(key, uid.to_string(), flags.iter().map(|f| f.to_string()).collect(), raw.as_bytes());
+    }
+    let server = MockImap::start(Scenario::new().mailbox(mb));
+    let mut sess = session(&server).await;
+
+    for (query, expected) in [
+        ("invoice", vec![1, 2, 3]),
+        ("from:vendor.com is:unread", vec![3]),
+        ("has:attachment OR is:starred", vec![2, 3]),
+        ("invoice -from:vendor.com", vec![2]),
+        ("after:2026/02/01 before:2026/03/01", vec![2]),
+        ("larger:2K", vec![3]),
+        ("subject:invoice (is:read OR is:starred)", vec![1, 3]),
+        ("in:INBOX NOT (has:attachment)", vec![1, 3]),
+    ] {
+        let (emails, _) = search_emails(&mut sess, "INBOX", Some(query), None, None, None, None)
+            .await
+            .unwrap_or_else(|e| panic!("server search {query}: {e}"));
+        let mut on_server: Vec<u32> = emails.iter().map(|e| e.uid).collect();
+        on_server.sort();
+        let mut in_vault: Vec<u32> = index.search(query, None, 50).unwrap().iter().map(|h| h.uid).collect();
+        in_vault.sort();
+        assert_eq!(on_server, expected, "server: {query}");
+        assert_eq!(in_vault, expected, "vault: {query}");
+    }
+}
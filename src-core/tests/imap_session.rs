@@ -1,4 +1,4 @@
-//! Session lifecycle: greeting, auth, COMPRESS negotiation, and pooling.
+//! Session lifecycle: greeting, auth, COMPRESS negotiation, pooling, and QUOTA.
 //!
 //! The pooling tests are the important ones. A command that fails mid-parse
 //! leaves unread bytes in the socket; re-pooling that session makes the *next*
@@ -202,3 +202,56 @@ async fn the_pool_caps_concurrent_sessions_per_account() {
     );
     pool.return_background(&config, g).await;
 }
+
+#[async_std::test]
+async fn reads_the_quota_of_the_inbox_root() {
+    // 3 KiB across two mailboxes, against a 10 KiB / 5-message root.
+    let big = "x".repeat(1024);
+    let server = MockImap::start(
+        Scenario::new()
+            .mailbox(mock_imap::Mailbox::new("INBOX").push(big.clone()).push(big.clone()))
+            .mailbox(mock_imap::Mailbox::new("Archive").push(big))
+            .quota("STORAGE", 10)
+            .quota("MESSAGE", 5),
+    );
+    let mut sess = session(&server).await;
+
+    let q = quota::get_quota(&mut sess).await.expect("quota").expect("a root");
+    assert_eq!(q.used_bytes, Some(3 * 1024));
+    assert_eq!(q.limit_bytes, Some(10 * 1024));
+    assert_eq!((q.message_count, q.message_limit), (Some(3), Some(5)));
+    assert_eq!(q.percent_used(), Some(60.0));
+    // The session reads on after it.
+    assert_eq!(list_mailboxes(&mut sess).await.unwrap().len(), 2);
+
+    let server = MockImap::start(Scenario::new().with_cap("QUOTA").mailbox(synthetic_mailbox("INBOX", 1)));
+    let mut sess = session(&server).await;
+    assert_eq!(quota::get_quota(&mut sess).await.expect("no roots"), None);
+}
+
+/// Label folders map onto All Mail from the very first sync, whichever entry
+/// point it came through, so All Mail is resolved when the session opens.
+#[async_std::test]
+async fn gmail_all_mail_is_resolved_with_the_first_session() {
+    let server = MockImap::start(
+        Scenario::new()
+            .with_cap("X-GM-EXT-1")
+            .mailbox(synthetic_mailbox("INBOX", 1))
+            .mailbox(synthetic_mailbox("[Gmail]/All Mail", 1).with_attrs(&["\\All"]))
+            .mailbox(synthetic_mailbox("[Gmail]/Spam", 0).with_attrs(&["\\Junk"]))
+            .mailbox(synthetic_mailbox("[Gmail]/Trash", 0).with_attrs(&["\\Trash"])),
+    );
+    let config = config_for(&server);
+    let pool = pool();
+    let _sess = create_imap_session(&config, &pool).await.expect("session");
+
+    let storage = pool.gmail_storage(&config).await.expect("All Mail resolved");
+    assert_eq!(storage.all_mail, "[Gmail]/All Mail");
+    assert_eq!(storage.folder_for("INBOX"), "[Gmail]/All Mail");
+    assert_eq!(storage.folder_for("[Gmail]/Trash"), "[Gmail]/Trash");
+    assert_eq!(storage.folder_for("[Gmail]/Spam"), "[Gmail]/Spam");
+
+    // Known now: a second session doesn't LIST again.
+    let _again = create_imap_session(&config, &pool).await.expect("session");
+    assert_eq!(server.count_commands("LIST"), 1);
+}
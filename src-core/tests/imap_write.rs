@@ -153,11 +153,90 @@ async fn append_stores_the_message_and_reports_the_new_uid() {
     let mut sess = session(&server).await;
 
     let raw = eml("Sent from MailVault", "user@example.com", "hello").into_bytes();
-    append_email(&mut sess, "Sent", &raw, "\\Seen").await.expect("append");
+    let uid = append_email(&mut sess, "Sent", &raw, "\\Seen").await.expect("append");
 
     let sent = server.state().find("Sent").unwrap().clone();
     assert_eq!(sent.messages.len(), 1);
     assert!(String::from_utf8_lossy(&sent.messages[0].raw).contains("Sent from MailVault"));
+    let appended = uid.expect("APPENDUID not read back");
+    assert_eq!((appended.uid_validity, appended.uid), (sent.uid_validity, sent.messages[0].uid));
+}
+
+/// UIDPLUS tells us where a move put each message, so the vault copy can
+/// follow it instead of being downloaded again.
+#[async_std::test]
+async fn move_reports_the_new_uids() {
+    let server = MockImap::start(Scenario::new().mailbox(inbox_with(4)).mailbox(Mailbox::new("Archive")));
+    let mut sess = session(&server).await;
+
+    let moved = move_emails(&mut sess, "INBOX", "Archive", &[2, 3], true, false).await.expect("move");
+
+    let state = server.state();
+    let archive = state.find("Archive").unwrap();
+    let mapping = moved.expect("COPYUID not read");
+    assert_eq!(mapping.uid_validity, archive.uid_validity);
+    let new_uids: Vec<u32> = archive.messages.iter().map(|m| m.uid).collect();
+    assert_eq!(mapping.uids, vec![(2, new_uids[0]), (3, new_uids[1])]);
+    assert_eq!(state.find("INBOX").unwrap().messages.len(), 2);
+}
+
+#[async_std::test]
+async fn move_without_the_move_extension_still_reports_the_new_uids() {
+    let server = MockImap::start(
+        Scenario::new().without_cap("MOVE").mailbox(inbox_with(2)).mailbox(Mailbox::new("Archive")),
+    );
+    let mut sess = session(&server).await;
+
+    let moved = move_emails(&mut sess, "INBOX", "Archive", &[1], false, false).await.expect("move");
+
+    let state = server.state();
+    let archive_uid = state.find("Archive").unwrap().messages[0].uid;
+    assert_eq!(moved.map(|m| m.uids), Some(vec![(1, archive_uid)]));
+    assert!(state.find("INBOX").unwrap().by_uid(1).is_none(), "source left behind");
+}
+
+/// Without UIDPLUS nothing reports the new UIDs, but EMAILID survives the
+/// move and finds them.
+#[async_std::test]
+async fn move_finds_the_new_uids_by_emailid_without_uidplus() {
+    let mut inbox = Mailbox::new("INBOX");
+    for (uid, id) in [(1, "Maaa"), (2, "Mbbb"), (3, "Mccc")] {
+        inbox.add(mock_imap::Message::new(uid, eml("s", "a@example.com", "b")).with_object_ids(id, Some("T1")));
+    }
+    let mut archive = Mailbox::new("Archive");
+    archive.add(mock_imap::Message::new(1, eml("older", "a@example.com", "b")).with_object_ids("Mzzz", None));
+    let server = MockImap::start(
+        Scenario::new().without_cap("UIDPLUS").with_cap("OBJECTID").mailbox(inbox).mailbox(archive),
+    );
+    let mut sess = session(&server).await;
+
+    let moved = move_emails(&mut sess, "INBOX", "Archive", &[1, 3], true, true).await.expect("move");
+
+    let state = server.state();
+    let archive = state.find("Archive").unwrap();
+    let uid_of = |id: &str| archive.messages.iter().find(|m| m.email_id.as_deref() == Some(id)).unwrap().uid;
+    let mapping = moved.expect("no mapping");
+    assert_eq!(mapping.uid_validity, archive.uid_validity);
+    assert_eq!(mapping.uids, vec![(1, uid_of("Maaa")), (3, uid_of("Mccc"))]);
+}
+
+/// Migration and restore re-append with the original arrival time and every
+/// keyword, not just the system flags.
+#[async_std::test]
+async fn append_carries_keywords_and_the_internal_date() {
+    let server = MockImap::start(Scenario::new().mailbox(Mailbox::new("Archive")));
+    let mut sess = session(&server).await;
+
+    let date = chrono::DateTime::parse_from_rfc3339("2019-03-07T09:05:00+01:00").unwrap();
+    let raw = eml("Old news", "user@example.com", "hello").into_bytes();
+    append_email_at(&mut sess, "Archive", &raw, "\\Seen $Forwarded $Label1", Some(date))
+        .await
+        .expect("append");
+
+    let msg = server.state().find("Archive").unwrap().messages[0].clone();
+    assert_eq!(msg.internal_date, "07-Mar-2019 09:05:00 +0100");
+    assert!(msg.has_flag("\\Seen"));
+    assert!(msg.has_flag("$Forwarded") && msg.has_flag("$Label1"), "keywords lost: {:?}", msg.flags);
 }
 
 /// Hostinger went silent for 15+ seconds after an SMTP send before answering
@@ -197,3 +276,50 @@ async fn append_to_a_missing_mailbox_reports_the_failure() {
         .expect_err("append to a missing mailbox must fail");
     assert!(err.to_lowercase().contains("append"), "unhelpful error: {err}");
 }
+
+/// Names travel as modified UTF-7: LIST hands back the decoded name and every
+/// command that takes one encodes it again.
+#[async_std::test]
+async fn non_ascii_mailbox_names_are_decoded_and_re_encoded() {
+    let server = MockImap::start(
+        Scenario::new()
+            .mailbox(inbox_with(1))
+            .mailbox(Mailbox::new("&BB4EQgQ,BEAEMAQyBDsENQQ9BD0ESwQ1-")),
+    );
+    let mut sess = session(&server).await;
+
+    let names: Vec<String> = list_mailboxes(&mut sess).await.unwrap().into_iter().map(|m| m.path).collect();
+    assert!(names.contains(&"Отправленные".to_string()), "LIST not decoded: {names:?}");
+
+    let raw = eml("Привет", "user@example.com", "hello").into_bytes();
+    append_email(&mut sess, "Отправленные", &raw, "\\Seen").await.expect("append");
+    let mbox = select_mailbox(&mut sess, "Отправленные").await.expect("select");
+    assert_eq!(mbox.exists, 1);
+
+    let state = server.state();
+    assert_eq!(state.find("&BB4EQgQ,BEAEMAQyBDsENQQ9BD0ESwQ1-").unwrap().messages.len(), 1);
+}
+
+/// With UTF8=ACCEPT enabled the server sends names as raw UTF-8 quoted
+/// strings, and expects them back the same way — no modified UTF-7 either way.
+#[async_std::test]
+async fn utf8_accept_is_enabled_when_offered() {
+    let server = MockImap::start(
+        Scenario::new()
+            .with_cap("UTF8=ACCEPT")
+            .mailbox(inbox_with(1))
+            .mailbox(Mailbox::new("Entwürfe")),
+    );
+    let mut sess = session(&server).await;
+    assert_eq!(server.count_commands("ENABLE UTF8=ACCEPT"), 1, "{:?}", server.commands());
+    assert!(utf8_accept::enabled(&mut sess));
+
+    let names: Vec<String> = list_mailboxes(&mut sess).await.unwrap().into_iter().map(|m| m.path).collect();
+    assert!(names.contains(&"Entwürfe".to_string()), "{names:?}");
+
+    let raw = eml("Grüße", "user@example.com", "hallo").into_bytes();
+    append_email(&mut sess, "Entwürfe", &raw, "\\Seen").await.expect("append");
+    let mbox = select_mailbox(&mut sess, "Entwürfe").await.expect("select");
+    assert_eq!(mbox.exists, 1);
+    assert!(!server.commands().iter().any(|c| c.contains("Entw&APw-rfe")));
+}
@@ -0,0 +1,211 @@
+//! Archive Policy — which message bodies the sync tick stores on its own.
+//!
+//! Header sync only writes sidecars, so without a policy a body reaches the
+//! Maildir vault only when the user opens or archives the message. With one,
+//! each sync of a folder also fetches `BODY.PEEK[]` for cached messages the
+//! vault lacks and the folder's rule accepts — newest first, a bounded batch
+//! per tick, and never past the account's daily transfer cap.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// Persisted under the app data dir, next to the sync schedule.
+const POLICY_FILE: &str = "archive_policy.json";
+
+const MB: u64 = 1024 * 1024;
+
+/// What one folder archives. `Off` is the default: the vault fills only when
+/// the user asks, as before.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "camelCase")]
+pub enum ArchiveRule {
+    #[default]
+    Off,
+    /// Every message.
+    All,
+    /// Messages whose RFC822.SIZE is under `max_mb`.
+    #[serde(rename_all = "camelCase")]
+    UnderSize { max_mb: u32 },
+    /// Messages received in the last `days` days.
+    #[serde(rename_all = "camelCase")]
+    NewerThan { days: u32 },
+}
+
+impl ArchiveRule {
+    /// Unknown size or date never qualifies for a rule that needs it — a
+    /// sidecar missing `size` could be a 50 MB attachment.
+    pub fn accepts(&self, size: Option<u32>, received: Option<DateTime<Utc>>, now: DateTime<Utc>) -> bool {
+        match self {
+            ArchiveRule::Off => false,
+            ArchiveRule::All => true,
+            ArchiveRule::UnderSize { max_mb } => size.is_some_and(|s| (s as u64) < *max_mb as u64 * MB),
+            ArchiveRule::NewerThan { days } => {
+                received.is_some_and(|d| now.signed_duration_since(d).num_days() < *days as i64)
+            }
+        }
+    }
+}
+
+/// Rules per account and folder. Written by `sync.archive_policy_set`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArchivePolicy {
+    /// Applies to any folder of any account not listed below.
+    #[serde(default)]
+    pub default_rule: ArchiveRule,
+    /// `account_id` → folder → rule. Folder `*` covers the account's other folders.
+    #[serde(default)]
+    pub accounts: HashMap<String, HashMap<String, ArchiveRule>>,
+}
+
+impl ArchivePolicy {
+    pub fn rule_for(&self, account_id: &str, folder: &str) -> &ArchiveRule {
+        let folders = self.accounts.get(account_id);
+        folders
+            .and_then(|f| f.get(folder).or_else(|| f.get("*")))
+            .unwrap_or(&self.default_rule)
+    }
+}
+
+/// Reject rules that can never match anything: a 0 MB size limit or a
+/// 0-day window is almost certainly a form left blank, not a wish for `Off`.
+pub fn validate(policy: &ArchivePolicy) -> Result<(), String> {
+    let check = |rule: &ArchiveRule, what: &str| match rule {
+        ArchiveRule::UnderSize { max_mb: 0 } => Err(format!("{}: maxMb must be at least 1", what)),
+        ArchiveRule::NewerThan { days: 0 } => Err(format!("{}: days must be at least 1", what)),
+        _ => Ok(()),
+    };
+    check(&policy.default_rule, "defaultRule")?;
+    for (account_id, folders) in &policy.accounts {
+        for (folder, rule) in folders {
+            check(rule, &format!("{} / {}", account_id, folder))?;
+        }
+    }
+    Ok(())
+}
+
+/// The saved policy, or all-`Off` when there is none (or it doesn't parse).
+pub fn load(app_dir: &Path) -> ArchivePolicy {
+    fs::read_to_string(app_dir.join(POLICY_FILE))
+        .ok()
+        .and_then(|raw| serde_json::from_str(&raw).ok())
+        .unwrap_or_default()
+}
+
+pub fn save(app_dir: &Path, policy: &ArchivePolicy) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(policy)
+        .map_err(|e| format!("Failed to serialize archive policy: {}", e))?;
+    fs::write(app_dir.join(POLICY_FILE), json)
+        .map_err(|e| format!("Failed to write {}: {}", POLICY_FILE, e))
+}
+
+/// Where each size-limited folder's next size probe starts: uids below this.
+const CURSOR_FILE: &str = "archive_cursors.json";
+
+type Cursors = HashMap<String, HashMap<String, u32>>;
+
+fn load_cursors(app_dir: &Path) -> Cursors {
+    fs::read_to_string(app_dir.join(CURSOR_FILE))
+        .ok()
+        .and_then(|raw| serde_json::from_str(&raw).ok())
+        .unwrap_or_default()
+}
+
+/// The uid a folder's last size probe got down to, if it has been probed.
+pub fn cursor(app_dir: &Path, account_id: &str, folder: &str) -> Option<u32> {
+    load_cursors(app_dir).get(account_id)?.get(folder).copied()
+}
+
+pub fn set_cursor(app_dir: &Path, account_id: &str, folder: &str, below: u32) -> Result<(), String> {
+    let mut cursors = load_cursors(app_dir);
+    cursors.entry(account_id.to_string()).or_default().insert(folder.to_string(), below);
+    let json = serde_json::to_string(&cursors)
+        .map_err(|e| format!("Failed to serialize archive cursors: {}", e))?;
+    fs::write(app_dir.join(CURSOR_FILE), json)
+        .map_err(|e| format!("Failed to write {}: {}", CURSOR_FILE, e))
+}
+
+/// The next `window` candidates (newest first) a size probe looks at: those
+/// below `cursor`, so one probe after another walks down the whole folder
+/// instead of re-asking about the same newest messages. Past the bottom it
+/// starts again from the newest.
+pub fn probe_window(candidates: &[u32], cursor: Option<u32>, window: usize) -> Vec<u32> {
+    let below: Vec<u32> = match cursor {
+        Some(c) => candidates.iter().copied().filter(|uid| *uid < c).collect(),
+        None => Vec::new(),
+    };
+    let mut picked = if below.is_empty() { candidates.to_vec() } else { below };
+    picked.truncate(window);
+    picked
+}
+
+/// When a message arrived, from a sidecar: INTERNALDATE (RFC 3339) first,
+/// the Date header (RFC 2822) when the server sent none.
+pub fn received_at(internal_date: Option<&str>, date: Option<&str>) -> Option<DateTime<Utc>> {
+    internal_date
+        .and_then(|d| DateTime::parse_from_rfc3339(d).ok())
+        .or_else(|| date.and_then(|d| DateTime::parse_from_rfc2822(d.trim()).ok()))
+        .map(|d| d.with_timezone(&Utc))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rules_fall_back_from_folder_to_account_to_default() {
+        let policy: ArchivePolicy = serde_json::from_value(serde_json::json!({
+            "defaultRule": { "mode": "all" },
+            "accounts": { "acc1": {
+                "INBOX": { "mode": "underSize", "maxMb": 5 },
+                "*": { "mode": "off" },
+            }}
+        }))
+        .unwrap();
+
+        assert_eq!(policy.rule_for("acc1", "INBOX"), &ArchiveRule::UnderSize { max_mb: 5 });
+        assert_eq!(policy.rule_for("acc1", "Sent"), &ArchiveRule::Off);
+        assert_eq!(policy.rule_for("acc2", "INBOX"), &ArchiveRule::All);
+        assert!(validate(&policy).is_ok());
+
+        let blank = ArchivePolicy { default_rule: ArchiveRule::NewerThan { days: 0 }, ..Default::default() };
+        assert!(validate(&blank).is_err());
+    }
+
+    #[test]
+    fn size_probes_walk_down_and_start_over_at_the_bottom() {
+        let candidates: Vec<u32> = (1..=10).rev().collect();
+        assert_eq!(probe_window(&candidates, None, 4), vec![10, 9, 8, 7]);
+        assert_eq!(probe_window(&candidates, Some(7), 4), vec![6, 5, 4, 3]);
+        assert_eq!(probe_window(&candidates, Some(3), 4), vec![2, 1]);
+        assert_eq!(probe_window(&candidates, Some(1), 4), vec![10, 9, 8, 7]);
+
+        let dir = std::env::temp_dir().join(format!("mv_archive_cursor_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        assert_eq!(cursor(&dir, "acc1", "INBOX"), None);
+        set_cursor(&dir, "acc1", "INBOX", 42).unwrap();
+        set_cursor(&dir, "acc1", "Sent", 7).unwrap();
+        assert_eq!(cursor(&dir, "acc1", "INBOX"), Some(42));
+        assert_eq!(cursor(&dir, "acc2", "INBOX"), None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn size_and_age_rules_need_the_fact_they_test() {
+        let now = Utc::now();
+        let under = ArchiveRule::UnderSize { max_mb: 1 };
+        assert!(under.accepts(Some(1000), None, now));
+        assert!(!under.accepts(Some(2 * MB as u32), None, now));
+        assert!(!under.accepts(None, None, now), "unknown size is not small");
+
+        let recent = ArchiveRule::NewerThan { days: 7 };
+        let received = received_at(None, Some("Thu, 01 Jan 2026 12:00:00 +0000"));
+        assert!(received.is_some());
+        assert!(recent.accepts(None, Some(now - chrono::Duration::days(2)), now));
+        assert!(!recent.accepts(None, Some(now - chrono::Duration::days(30)), now));
+        assert!(!recent.accepts(None, None, now));
+    }
+}
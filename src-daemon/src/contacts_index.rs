@@ -114,8 +114,8 @@ impl ContactsState {
         let path = self.path_for(account_id);
         let mut entries: HashMap<String, ContactEntry> = HashMap::new();
         if path.exists() {
-            match fs::read_to_string(&path) {
-                Ok(json) => match serde_json::from_str::<Vec<ContactEntry>>(&json) {
+            match mailvault_core::maildir::encryption::read_sidecar(&self.data_dir, &path) {
+                Ok(json) => match serde_json::from_slice::<Vec<ContactEntry>>(&json) {
                     Ok(list) => {
                         for e in list {
                             entries.insert(e.address.clone(), e);
@@ -228,7 +228,7 @@ impl ContactsState {
             let path = self.path_for(&account_id);
             match serde_json::to_string(&entries) {
                 Ok(json) => {
-                    if let Err(e) = fs::write(&path, json) {
+                    if let Err(e) = mailvault_core::maildir::encryption::write_sidecar(&self.data_dir, &path, json.as_bytes()) {
                         warn!("[contacts_index] write {}: {}", path.display(), e);
                     }
                 }
@@ -443,6 +443,11 @@ mod tests {
             list_unsubscribe: None,
             list_id: None,
             precedence: None,
+            labels: vec![],
+            gmail_msg_id: None,
+            gmail_thread_id: None,
+            email_id: None,
+            object_thread_id: None,
         }
     }
 
@@ -0,0 +1,450 @@
+//! Graph Sync — background sync for accounts on Microsoft Graph.
+//!
+//! Graph has no UIDs and no IMAP. `/messages/delta` stands in for
+//! CONDSTORE/QRESYNC: the first round lists the whole folder, and each later
+//! round, started from the saved `@odata.deltaLink`, returns only what was
+//! added, changed (read state, flag, categories) or removed since.
+//!
+//! The result lands in the same sidecar cache IMAP sync writes. Uids come from
+//! the app's allocation ledger, `graph_id_map.json` beside the sidecars (see
+//! `_allocateGraphUids` in cacheManager.js). The daemon allocates from that
+//! same ledger, under the same rules: a Graph id keeps its number for good,
+//! new ids take the next one, and the ledger is written before any sidecar
+//! that uses a new number.
+//!
+//! Folder ids come from a walk of the whole tree — Graph lists only the top
+//! level, subfolders hang off `childFolders`. The walk is kept per account in
+//! `email_cache/<account>.graph_folders.json`, so a sync round goes straight
+//! to its folder and only an unknown or stale id lists folders again.
+
+use crate::graph::{self, GraphClient, GraphMailFolder, GraphMessage};
+use crate::imap::{ImapConfig, MailboxInfo};
+use mailvault_core::header_store::HeaderStore;
+use mailvault_core::threading::{self, Source};
+use crate::sync_engine::{count_sidecars, prune_sidecars, remove_sidecars, tauri_cache_dir, write_cache_meta};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+/// The app's uid ledger: `{"<uid>": "<graph id>"}`. Same name as in main.rs.
+const GRAPH_ID_MAP_FILE: &str = "graph_id_map.json";
+
+/// Where the folder's last delta round ended.
+const DELTA_STATE_FILE: &str = "graph_delta.json";
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DeltaState {
+    /// The delta link belongs to this folder id; a folder recreated under
+    /// the same name starts over.
+    folder_id: String,
+    delta_link: Option<String>,
+}
+
+/// What one delta round changed in the cache.
+pub struct GraphSyncOutcome {
+    pub new_emails: usize,
+    pub updated_flags: usize,
+    pub total_emails: u32,
+}
+
+/// A folder from the walk, under the app path it is filed by.
+struct ListedFolder {
+    path: String,
+    top_level: bool,
+    folder: GraphMailFolder,
+}
+
+/// Accounts the app signed in through Graph rather than IMAP.
+pub fn is_graph(config: &ImapConfig) -> bool {
+    config.oauth2_transport.as_deref() == Some("graph")
+}
+
+/// The account's folders, subfolders included, in the shape `list_mailboxes`
+/// returns and under the names the app files them by. Refreshes the cached
+/// folder ids `sync_folder` goes by.
+pub async fn list_mailboxes(client: &GraphClient, data_dir: &Path, account_id: &str) -> Result<Vec<MailboxInfo>, String> {
+    let listed = walk_folders(client).await?;
+    write_folder_ids(data_dir, account_id, &listed);
+    Ok(listed
+        .into_iter()
+        .map(|l| MailboxInfo {
+            name: l.path.rsplit('/').next().unwrap_or(&l.path).to_string(),
+            special_use: graph::special_use(&l.folder.display_name)
+                .filter(|_| l.top_level)
+                .map(str::to_string),
+            path: l.path,
+            flags: Vec::new(),
+            delimiter: Some("/".to_string()),
+            noselect: false,
+            children: Vec::new(),
+        })
+        .collect())
+}
+
+/// Every folder of the account. Top-level folders go by `app_folder_name`;
+/// a subfolder is `<parent path>/<display name>`, the way IMAP servers with
+/// a `/` delimiter name them.
+async fn walk_folders(client: &GraphClient) -> Result<Vec<ListedFolder>, String> {
+    let mut listed: Vec<ListedFolder> = client
+        .list_folders()
+        .await?
+        .into_iter()
+        .map(|folder| ListedFolder { path: graph::app_folder_name(&folder.display_name), top_level: true, folder })
+        .collect();
+    let mut i = 0;
+    while i < listed.len() {
+        if listed[i].folder.child_folder_count > 0 {
+            let parent = listed[i].path.clone();
+            for folder in client.list_child_folders(&listed[i].folder.id).await? {
+                let path = format!("{}/{}", parent, folder.display_name);
+                listed.push(ListedFolder { path, top_level: false, folder });
+            }
+        }
+        i += 1;
+    }
+    Ok(listed)
+}
+
+/// Run one delta round on `mailbox` and apply it to the sidecar cache.
+///
+/// The folder id comes from the cached walk. The folders are listed again
+/// only when the mailbox isn't in it, or Graph no longer knows the id.
+pub async fn sync_folder(
+    client: &GraphClient,
+    data_dir: &Path,
+    account_id: &str,
+    mailbox: &str,
+) -> Result<GraphSyncOutcome, String> {
+    let mut ids = read_folder_ids(data_dir, account_id);
+    let mut relisted = false;
+    if !ids.contains_key(mailbox) {
+        ids = relist_folder_ids(client, data_dir, account_id).await?;
+        relisted = true;
+    }
+    loop {
+        let folder_id = ids
+            .get(mailbox)
+            .ok_or_else(|| format!("Graph folder '{}' not found", mailbox))?
+            .clone();
+        match sync_folder_id(client, data_dir, account_id, mailbox, &folder_id).await {
+            Err(e) if !relisted && GraphClient::is_not_found(&e) => {
+                info!("[graph-sync] Folder id for {} is stale, relisting: {}", mailbox, e);
+                ids = relist_folder_ids(client, data_dir, account_id).await?;
+                relisted = true;
+            }
+            other => return other,
+        }
+    }
+}
+
+async fn sync_folder_id(
+    client: &GraphClient,
+    data_dir: &Path,
+    account_id: &str,
+    mailbox: &str,
+    folder_id: &str,
+) -> Result<GraphSyncOutcome, String> {
+    let cache_dir = tauri_cache_dir(data_dir, account_id, mailbox);
+    let mut state = read_delta_state(&cache_dir);
+    if state.folder_id != folder_id {
+        state = DeltaState { folder_id: folder_id.to_string(), delta_link: None };
+    }
+
+    let round = match fetch_round(client, folder_id, state.delta_link.as_deref()).await {
+        Err(e) if state.delta_link.is_some() && GraphClient::is_sync_reset(&e) => {
+            info!("[graph-sync] Delta link for {} expired, relisting: {}", mailbox, e);
+            fetch_round(client, folder_id, None).await?
+        }
+        other => other?,
+    };
+
+    let outcome = apply_round(data_dir, account_id, mailbox, round.messages, &round.removed, round.full)?;
+    state.delta_link = Some(round.delta_link);
+    write_delta_state(&cache_dir, &state)?;
+    Ok(outcome)
+}
+
+/// Every page of one delta round, merged.
+struct Round {
+    /// Latest version of each changed message — a message can show up on
+    /// more than one page of a round.
+    messages: Vec<GraphMessage>,
+    removed: Vec<String>,
+    delta_link: String,
+    /// Started without a delta link: `messages` is the whole folder.
+    full: bool,
+}
+
+async fn fetch_round(client: &GraphClient, folder_id: &str, link: Option<&str>) -> Result<Round, String> {
+    let full = link.is_none();
+    let mut link = link.map(str::to_string);
+    let mut by_id: HashMap<String, GraphMessage> = HashMap::new();
+    let mut removed = Vec::new();
+    loop {
+        let page = client.messages_delta(folder_id, link.as_deref()).await?;
+        for id in page.removed {
+            by_id.remove(&id);
+            removed.push(id);
+        }
+        for msg in page.messages {
+            removed.retain(|r| *r != msg.id);
+            by_id.insert(msg.id.clone(), msg);
+        }
+        match (page.next_link, page.delta_link) {
+            (Some(next), _) => link = Some(next),
+            (None, Some(delta_link)) => {
+                return Ok(Round { messages: by_id.into_values().collect(), removed, delta_link, full });
+            }
+            (None, None) => return Err("Graph delta page had neither nextLink nor deltaLink".to_string()),
+        }
+    }
+}
+
+/// Write changed messages as sidecars, drop removed ones, and — after a full
+/// round — drop sidecars for anything the folder no longer holds.
+fn apply_round(
+    data_dir: &Path,
+    account_id: &str,
+    mailbox: &str,
+    mut messages: Vec<GraphMessage>,
+    removed: &[String],
+    full: bool,
+) -> Result<GraphSyncOutcome, String> {
+    let cache_dir = &tauri_cache_dir(data_dir, account_id, mailbox);
+    fs::create_dir_all(cache_dir).map_err(|e| format!("Failed to create cache dir: {}", e))?;
+    let mut ledger = read_ledger(cache_dir)?;
+    let mut uid_of: HashMap<String, u32> = ledger.iter().map(|(uid, id)| (id.clone(), *uid)).collect();
+    let mut next_uid = ledger.keys().next_back().copied().unwrap_or(0);
+
+    // Oldest first, so new numbers rise with arrival the way IMAP UIDs do.
+    messages.sort_by(|a, b| a.received_date_time.cmp(&b.received_date_time));
+    let before = ledger.len();
+    for msg in &messages {
+        if !uid_of.contains_key(&msg.id) {
+            next_uid += 1;
+            uid_of.insert(msg.id.clone(), next_uid);
+            ledger.insert(next_uid, msg.id.clone());
+        }
+    }
+    if ledger.len() > before {
+        write_ledger(cache_dir, &ledger)?;
+    }
+
+    let mut store = HeaderStore::open(cache_dir)?;
+    let mut new_emails = 0;
+    let mut updated_flags = 0;
+    let mut rows = Vec::with_capacity(messages.len());
+    for msg in &messages {
+        let uid = uid_of[&msg.id];
+        let header = msg.to_email_header(uid);
+        match store.get(uid) {
+            Some(old) => {
+                if old.get("flags") != Some(&serde_json::json!(header.flags)) {
+                    updated_flags += 1;
+                }
+            }
+            None => new_emails += 1,
+        }
+        let mut json = serde_json::to_value(&header).map_err(|e| format!("Serialize email {}: {}", uid, e))?;
+        // Self-describing row, as the app writes it: a cached header carries its own id.
+        json["_graphId"] = serde_json::json!(msg.id);
+        rows.push(json);
+    }
+    store.put(&rows)?;
+    threading::observe_cached(data_dir, account_id, mailbox, &rows);
+
+    let gone: Vec<u32> = removed.iter().filter_map(|id| uid_of.get(id).copied()).collect();
+    let mut dropped = remove_sidecars(cache_dir, &gone);
+    threading::forget(data_dir, account_id, mailbox, &gone, Source::Cache);
+    if full {
+        let live: Vec<u32> = messages.iter().map(|m| uid_of[&m.id]).collect();
+        let pruned = prune_sidecars(cache_dir, &live);
+        threading::forget(data_dir, account_id, mailbox, &pruned, Source::Cache);
+        dropped += pruned.len();
+    }
+
+    let total_emails = count_sidecars(cache_dir) as u32;
+    write_cache_meta(cache_dir, total_emails, None, None, None)?;
+    info!(
+        "[graph-sync] {:?}: {} new, {} changed, {} removed, {} total",
+        cache_dir.file_name().unwrap_or_default(),
+        new_emails,
+        updated_flags,
+        dropped,
+        total_emails
+    );
+    Ok(GraphSyncOutcome { new_emails, updated_flags, total_emails })
+}
+
+/// The uid ledger, empty if there is none yet. One that exists but can't be
+/// read is an error, never "empty": allocating from nothing would hand uid 1
+/// to a message while another still owns it in the vault.
+fn read_ledger(cache_dir: &Path) -> Result<BTreeMap<u32, String>, String> {
+    let path = cache_dir.join(GRAPH_ID_MAP_FILE);
+    if !path.exists() {
+        return Ok(BTreeMap::new());
+    }
+    let raw = fs::read_to_string(&path).map_err(|e| format!("Failed to read {:?}: {}", path, e))?;
+    let map: HashMap<String, String> =
+        serde_json::from_str(&raw).map_err(|e| format!("Failed to parse {:?}: {}", path, e))?;
+    map.into_iter()
+        .map(|(uid, id)| {
+            uid.parse::<u32>()
+                .map(|uid| (uid, id))
+                .map_err(|_| format!("Bad uid {:?} in {:?}", uid, path))
+        })
+        .collect()
+}
+
+fn write_ledger(cache_dir: &Path, ledger: &BTreeMap<u32, String>) -> Result<(), String> {
+    let path = cache_dir.join(GRAPH_ID_MAP_FILE);
+    let json = serde_json::to_string(ledger).map_err(|e| format!("Failed to serialize uid ledger: {}", e))?;
+    fs::write(&path, json).map_err(|e| format!("Failed to write {:?}: {}", path, e))
+}
+
+fn folder_ids_path(data_dir: &Path, account_id: &str) -> PathBuf {
+    data_dir.join("email_cache").join(format!(
+        "{}.graph_folders.json",
+        account_id.replace(|c: char| !c.is_alphanumeric(), "_")
+    ))
+}
+
+/// Graph folder ids by app path, from the last walk. Empty when there is none.
+fn read_folder_ids(data_dir: &Path, account_id: &str) -> HashMap<String, String> {
+    fs::read_to_string(folder_ids_path(data_dir, account_id))
+        .ok()
+        .and_then(|raw| serde_json::from_str(&raw).ok())
+        .unwrap_or_default()
+}
+
+/// Best-effort: without the file the next round just walks again.
+fn write_folder_ids(data_dir: &Path, account_id: &str, listed: &[ListedFolder]) {
+    let ids: BTreeMap<&str, &str> = listed.iter().map(|l| (l.path.as_str(), l.folder.id.as_str())).collect();
+    let path = folder_ids_path(data_dir, account_id);
+    let written = fs::create_dir_all(data_dir.join("email_cache"))
+        .and_then(|_| fs::write(&path, serde_json::to_string(&ids).unwrap_or_default()));
+    if let Err(e) = written {
+        warn!("[graph-sync] Failed to write {:?}: {}", path, e);
+    }
+}
+
+async fn relist_folder_ids(client: &GraphClient, data_dir: &Path, account_id: &str) -> Result<HashMap<String, String>, String> {
+    let listed = walk_folders(client).await?;
+    write_folder_ids(data_dir, account_id, &listed);
+    Ok(listed.into_iter().map(|l| (l.path, l.folder.id)).collect())
+}
+
+fn read_delta_state(cache_dir: &Path) -> DeltaState {
+    fs::read_to_string(cache_dir.join(DELTA_STATE_FILE))
+        .ok()
+        .and_then(|raw| serde_json::from_str(&raw).ok())
+        .unwrap_or_default()
+}
+
+fn write_delta_state(cache_dir: &Path, state: &DeltaState) -> Result<(), String> {
+    let path = cache_dir.join(DELTA_STATE_FILE);
+    let json = serde_json::to_string(state).map_err(|e| format!("Failed to serialize delta state: {}", e))?;
+    fs::write(&path, json).map_err(|e| format!("Failed to write {:?}: {}", path, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::scratch_dir;
+    use mock_graph::{state::synthetic_folder, Folder, GraphState, MockGraph};
+
+    #[tokio::test]
+    async fn delta_rounds_follow_next_links_and_resume_from_the_delta_token() {
+        let server = MockGraph::start(GraphState::new().folder(synthetic_folder("inbox", "Inbox", 5)).with_page_size(2));
+        let client = GraphClient::with_base("token", &server.base_url());
+
+        let round = fetch_round(&client, "inbox", None).await.unwrap();
+        assert!(round.full);
+        assert_eq!(round.messages.len(), 5);
+        assert_eq!(server.count_requests("$skiptoken="), 2, "five messages at two a page");
+        assert!(round.delta_link.contains("$deltatoken="));
+
+        server.update(|st| {
+            let inbox = st.find_mut("Inbox").unwrap();
+            inbox.set_read("inbox-1", true);
+            inbox.remove("inbox-2");
+        });
+        let next = fetch_round(&client, "inbox", Some(&round.delta_link)).await.unwrap();
+        assert!(!next.full);
+        assert_eq!(next.messages.iter().map(|m| m.id.as_str()).collect::<Vec<_>>(), ["inbox-1"]);
+        assert_eq!(next.removed, ["inbox-2"]);
+
+        // Nothing changed since: the round is empty and hands back a new token.
+        let idle = fetch_round(&client, "inbox", Some(&next.delta_link)).await.unwrap();
+        assert!(idle.messages.is_empty() && idle.removed.is_empty());
+
+        server.update(|st| st.find_mut("Inbox").unwrap().expire_delta_links());
+        let err = fetch_round(&client, "inbox", Some(&idle.delta_link)).await.err().unwrap();
+        assert!(GraphClient::is_sync_reset(&err), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn subfolders_sync_from_the_cached_walk_without_relisting() {
+        let dir = scratch_dir("subfolders");
+        let server = MockGraph::start(
+            GraphState::new()
+                .folder(synthetic_folder("inbox", "Inbox", 2))
+                .folder(synthetic_folder("projects", "Projects", 1).under("inbox"))
+                .folder(Folder::new("y2026", "Archive").under("projects")),
+        );
+        let client = GraphClient::with_base("token", &server.base_url());
+
+        let listed = list_mailboxes(&client, &dir, "acc1").await.unwrap();
+        let paths: Vec<(&str, Option<&str>)> =
+            listed.iter().map(|m| (m.path.as_str(), m.special_use.as_deref())).collect();
+        assert_eq!(
+            paths,
+            [("INBOX", Some("\\Inbox")), ("INBOX/Projects", None), ("INBOX/Projects/Archive", None)],
+            "a subfolder named like a special folder is not one"
+        );
+        let lists = server.count_requests("/me/mailFolders?") + server.count_requests("/childFolders");
+
+        for _ in 0..2 {
+            let outcome = sync_folder(&client, &dir, "acc1", "INBOX/Projects").await.unwrap();
+            assert_eq!(outcome.total_emails, 1);
+        }
+        assert_eq!(
+            server.count_requests("/me/mailFolders?") + server.count_requests("/childFolders"),
+            lists,
+            "rounds go by the cached folder ids"
+        );
+        let cache = tauri_cache_dir(&dir, "acc1", "INBOX/Projects");
+        assert_eq!(count_sidecars(&cache), 1);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn an_unknown_or_stale_folder_id_walks_the_folders_again() {
+        let dir = scratch_dir("stale_ids");
+        let server = MockGraph::start(GraphState::new().folder(synthetic_folder("inbox", "Inbox", 2)));
+        let client = GraphClient::with_base("token", &server.base_url());
+
+        // No cached walk yet: the first round lists the folders itself.
+        sync_folder(&client, &dir, "acc1", "INBOX").await.unwrap();
+        assert_eq!(server.count_requests("/me/mailFolders?"), 1);
+
+        // The folder was recreated under a new id.
+        server.update(|st| {
+            let inbox = st.find_mut("Inbox").unwrap();
+            inbox.id = "inbox-new".to_string();
+        });
+        let outcome = sync_folder(&client, &dir, "acc1", "INBOX").await.unwrap();
+        assert_eq!(outcome.total_emails, 2);
+        assert_eq!(server.count_requests("/me/mailFolders?"), 2);
+        assert_eq!(read_folder_ids(&dir, "acc1")["INBOX"], "inbox-new");
+
+        let err = sync_folder(&client, &dir, "acc1", "Nowhere").await.err().unwrap();
+        assert!(err.contains("not found"), "{}", err);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,19 +1,26 @@
+mod archive_policy;
 mod auth;
 pub mod classification;
 pub mod contacts_index;
 // imap/graph/oauth2/dns now live in mailvault_core (shared with src-tauri).
 pub use mailvault_core::dns;
 pub use mailvault_core::graph;
+mod graph_sync;
 pub use mailvault_core::imap;
 mod inference;
 mod ipc;
 mod learning;
 pub mod llm;
 pub use mailvault_core::oauth2;
+mod quota_alerts;
+mod scheduler;
+pub use mailvault_core::search;
 mod server;
 mod smtp;
 mod snapshot;
 pub mod sync_engine;
+#[cfg(test)]
+mod test_support;
 
 // Note: backup, migration, archive, external_location modules require
 // tauri::AppHandle for data dirs and event emission. They remain in
@@ -193,6 +200,17 @@ async fn main() {
             mig.renamed, mig.already_ok, mig.skipped_non_message, mig.errors
         );
     }
+    // Mailbox directories named with raw modified UTF-7 move to the decoded
+    // names that sync writes now.
+    let names = mailvault_core::maildir::migrate_decode_mailbox_names(&mail_dir);
+    if names.renamed > 0 || names.errors > 0 {
+        info!("Maildir mailbox name migration: renamed={} errors={}", names.renamed, names.errors);
+    }
+    // Their header caches follow, so the app doesn't start them over.
+    let caches = sync_engine::migrate_decode_cache_dirs(&data_dir, &mail_dir);
+    if caches.renamed > 0 || caches.errors > 0 {
+        info!("Header cache name migration: renamed={} errors={}", caches.renamed, caches.errors);
+    }
 
     // Load or generate auth token — use the shared token path (same location as socket)
     let token_path = get_token_path();
@@ -217,6 +235,19 @@ async fn main() {
         Arc::clone(&contacts),
     ));
 
+    // Periodic sync lives here, not in the app window: the app's saved
+    // accounts are loaded now and keep syncing while the window is closed.
+    let scheduler = Arc::new(scheduler::SyncScheduler::new(Arc::clone(&sync_eng), data_dir.clone()));
+    {
+        let scheduler = Arc::clone(&scheduler);
+        let app_dir = data_dir.clone();
+        tokio::spawn(async move {
+            let credentials = tokio::task::spawn_blocking(server::read_saved_credentials).await.unwrap_or_default();
+            scheduler.adopt(scheduler::saved_accounts(&app_dir, &credentials)).await;
+        });
+    }
+    scheduler.start();
+
     let state = Arc::new(server::DaemonState {
         token,
         data_dir: mail_dir.clone(),
@@ -229,12 +260,55 @@ async fn main() {
         imap_pool,
         _oauth2_manager: oauth2::OAuth2Manager::new(),
         sync_engine: sync_eng,
+        scheduler,
         contacts: Arc::clone(&contacts),
+        search: Arc::new(std::sync::Mutex::new(None)),
+        compressing: std::sync::atomic::AtomicBool::new(false),
+        last_compression: std::sync::Mutex::new(None),
     });
 
     // Start background classification queue worker
     server::start_classification_worker(Arc::clone(&state));
 
+    // An encrypted vault kept in the keyring opens now; one wrapped only by a
+    // passphrase waits for `vault.unlock`.
+    if mail_dir_ok {
+        server::unlock_from_keyring(&mail_dir);
+    }
+
+    // Move aside messages a crash left empty or cut short, so the next sync
+    // fetches them again instead of counting them as archived.
+    if mail_dir_ok {
+        let mail_dir = mail_dir.clone();
+        tokio::task::spawn_blocking(move || mailvault_core::maildir::integrity::quarantine_scan(&mail_dir));
+    }
+
+    // Finish a compression or encryption conversion a restart cut short.
+    if mail_dir_ok
+        && mailvault_core::maildir::compression::conversion_pending(&mail_dir)
+        && mailvault_core::maildir::encryption::is_unlocked(&mail_dir)
+    {
+        server::start_compression_converter(&state);
+    }
+
+    // Drop stored attachments nothing refers to any more and refresh the
+    // bytes-saved figures. A pending conversion sweeps when it finishes.
+    if mail_dir_ok
+        && mailvault_core::maildir::dedup::store_exists(&mail_dir)
+        && mailvault_core::maildir::encryption::is_unlocked(&mail_dir)
+        && !mailvault_core::maildir::compression::conversion_pending(&mail_dir)
+    {
+        let mail_dir = mail_dir.clone();
+        tokio::task::spawn_blocking(move || mailvault_core::maildir::dedup::sweep(&mail_dir));
+    }
+
+    // Build or catch up the local search index now, so the first
+    // `search.query` doesn't pay for a vault-wide scan.
+    if mail_dir_ok {
+        let state = Arc::clone(&state);
+        tokio::task::spawn_blocking(move || server::with_search_index(&state, |index| index.len()));
+    }
+
     // Debounced flush of the contacts index to disk (every 30s).
     {
         let contacts = Arc::clone(&contacts);
@@ -262,12 +336,30 @@ async fn main() {
         });
     }
 
+    // Per-mailbox uid indexes save at most every few seconds while a sync is
+    // storing, and the Message-ID index only when looked up; write out
+    // whatever the last burst left dirty.
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(std::time::Duration::from_secs(30));
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            let _ = tokio::task::spawn_blocking(|| {
+                mailvault_core::uid_index::flush();
+                mailvault_core::message_index::flush();
+                mailvault_core::threading::flush();
+            })
+            .await;
+        }
+    });
+
     let socket_path = get_socket_path(&data_dir);
 
     // Handle graceful shutdown on SIGINT (ctrl_c) and SIGTERM (service stop / kill)
     let data_dir_cleanup = data_dir.clone();
     let socket_cleanup = socket_path.clone();
     let pool_cleanup = Arc::clone(&state.imap_pool);
+    let sync_cleanup = Arc::clone(&state.sync_engine);
     tokio::spawn(async move {
         let ctrl_c = tokio::signal::ctrl_c();
 
@@ -288,6 +380,9 @@ async fn main() {
             info!("Received shutdown signal");
         }
 
+        // INBOX watchers hold their own connections outside the pool.
+        sync_cleanup.unwatch_all().await;
+
         // LOGOUT every pooled IMAP session so servers drop them now instead of
         // holding them open until their idle timeout. Bounded — a hung server
         // must not stop us from exiting, and the app that spawned us only waits
@@ -298,6 +393,9 @@ async fn main() {
         ).await;
 
         mailvault_core::transfer_stats::global().flush(&data_dir_cleanup, "daemon");
+        mailvault_core::uid_index::flush();
+        mailvault_core::message_index::flush();
+        mailvault_core::threading::flush();
         cleanup_pid_file(&data_dir_cleanup);
         let _ = std::fs::remove_file(&socket_cleanup);
         info!("Cleanup complete, exiting");
@@ -0,0 +1,139 @@
+//! Quota Alerts — when to tell the user their server mailbox is filling up.
+//!
+//! After a sync the engine asks the server how full the account is (at most
+//! once per `checkIntervalSecs`) and raises an alert the first time usage
+//! reaches each threshold. An alert carries how much has to go to get back
+//! under the lowest threshold — the target for archive-then-delete. Usage that
+//! drops below a threshold arms it again.
+
+use mailvault_core::quota::MailboxQuota;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// Persisted under the app data dir, next to the archive policy.
+const SETTINGS_FILE: &str = "quota_alerts.json";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct QuotaAlertSettings {
+    /// Percentages of the limit, each alerting once on the way up. Empty
+    /// turns alerts off.
+    pub thresholds: Vec<u8>,
+    /// `account_id` → mailbox size in bytes, for servers that report usage
+    /// but no limit (Graph).
+    pub limits: HashMap<String, u64>,
+    pub check_interval_secs: u64,
+}
+
+impl Default for QuotaAlertSettings {
+    fn default() -> Self {
+        Self {
+            thresholds: vec![80, 90, 95],
+            limits: HashMap::new(),
+            check_interval_secs: 3600,
+        }
+    }
+}
+
+impl QuotaAlertSettings {
+    /// The highest threshold `percent` has reached, 0 for none.
+    pub fn level(&self, percent: f64) -> u8 {
+        self.thresholds
+            .iter()
+            .copied()
+            .filter(|t| percent >= *t as f64)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// The threshold an alert should go out for, moving from `previous` level
+    /// to `percent` — only when a higher one has been reached.
+    pub fn crossed(&self, previous: u8, percent: f64) -> Option<u8> {
+        let level = self.level(percent);
+        (level > previous).then_some(level)
+    }
+
+    /// Where archive-then-delete should bring usage: under the lowest threshold.
+    pub fn target_percent(&self) -> Option<u8> {
+        self.thresholds.iter().copied().min()
+    }
+}
+
+/// A threshold reached. `seq` counts up from 1 over the daemon's life, so
+/// `quota.wait` can ask for anything newer than what it last saw.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QuotaAlert {
+    pub seq: u64,
+    pub account_id: String,
+    pub threshold: u8,
+    pub percent_used: f64,
+    /// Bytes to delete from the server to get back under the lowest threshold.
+    pub bytes_to_free: Option<u64>,
+    pub quota: MailboxQuota,
+}
+
+/// Thresholds must be percentages; a zero limit is a form left blank.
+pub fn validate(settings: &QuotaAlertSettings) -> Result<(), String> {
+    if let Some(t) = settings.thresholds.iter().find(|t| **t == 0 || **t > 100) {
+        return Err(format!("threshold {} must be between 1 and 100", t));
+    }
+    if let Some((account_id, _)) = settings.limits.iter().find(|(_, l)| **l == 0) {
+        return Err(format!("{}: limit must be at least 1 byte", account_id));
+    }
+    if settings.check_interval_secs < 60 {
+        return Err("checkIntervalSecs must be at least 60".to_string());
+    }
+    Ok(())
+}
+
+/// The saved settings, or the defaults when there are none (or they don't parse).
+pub fn load(app_dir: &Path) -> QuotaAlertSettings {
+    fs::read_to_string(app_dir.join(SETTINGS_FILE))
+        .ok()
+        .and_then(|raw| serde_json::from_str(&raw).ok())
+        .unwrap_or_default()
+}
+
+pub fn save(app_dir: &Path, settings: &QuotaAlertSettings) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(settings)
+        .map_err(|e| format!("Failed to serialize quota alert settings: {}", e))?;
+    fs::write(app_dir.join(SETTINGS_FILE), json)
+        .map_err(|e| format!("Failed to write {}: {}", SETTINGS_FILE, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn each_threshold_alerts_once_on_the_way_up() {
+        let settings = QuotaAlertSettings::default();
+        assert_eq!(settings.crossed(0, 50.0), None);
+        assert_eq!(settings.crossed(0, 82.5), Some(80));
+        assert_eq!(settings.crossed(80, 85.0), None);
+        // Jumping two thresholds at once is one alert, for the higher.
+        assert_eq!(settings.crossed(80, 96.0), Some(95));
+        // Back under 80 (archived and deleted), then over it again.
+        assert_eq!(settings.level(70.0), 0);
+        assert_eq!(settings.crossed(0, 81.0), Some(80));
+
+        assert_eq!(QuotaAlertSettings { thresholds: vec![], ..Default::default() }.crossed(0, 100.0), None);
+        assert_eq!(settings.target_percent(), Some(80));
+    }
+
+    #[test]
+    fn validate_rejects_what_cannot_be_meant() {
+        assert!(validate(&QuotaAlertSettings::default()).is_ok());
+        assert!(validate(&QuotaAlertSettings { thresholds: vec![0], ..Default::default() }).is_err());
+        assert!(validate(&QuotaAlertSettings { thresholds: vec![101], ..Default::default() }).is_err());
+        let mut settings = QuotaAlertSettings::default();
+        settings.limits.insert("acc1".into(), 0);
+        assert!(validate(&settings).is_err());
+
+        let parsed: QuotaAlertSettings = serde_json::from_str(r#"{"thresholds":[75]}"#).unwrap();
+        assert_eq!(parsed.check_interval_secs, 3600);
+    }
+}
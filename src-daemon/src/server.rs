@@ -1,3 +1,4 @@
+use crate::archive_policy;
 use crate::auth;
 use crate::classification;
 use crate::contacts_index;
@@ -7,8 +8,14 @@ use crate::ipc::{self, AuthHandshake, RpcRequest, RpcResponse};
 use crate::learning;
 use crate::llm;
 use crate::oauth2;
+use crate::quota_alerts;
+use crate::scheduler;
+use crate::search;
 use crate::snapshot;
 use crate::sync_engine;
+use mailvault_core::maildir::compression::{self, Compression, CompressionConversion};
+use mailvault_core::maildir::{dedup, encryption};
+use mailvault_core::threading;
 use serde_json::Value;
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
@@ -33,7 +40,14 @@ pub struct DaemonState {
     pub imap_pool: Arc<imap::ImapPool>,
     pub _oauth2_manager: oauth2::OAuth2Manager,
     pub sync_engine: Arc<sync_engine::SyncEngine>,
+    pub scheduler: Arc<scheduler::SyncScheduler>,
     pub contacts: Arc<contacts_index::ContactsState>,
+    /// Local full-text index over the vault. Loaded on first use.
+    pub search: Arc<std::sync::Mutex<Option<search::SearchIndex>>>,
+    /// Set while the compression converter runs in the background.
+    pub compressing: std::sync::atomic::AtomicBool,
+    /// What the converter's last run did.
+    pub last_compression: std::sync::Mutex<Option<CompressionConversion>>,
 }
 
 /// Start the daemon socket server.
@@ -155,7 +169,9 @@ async fn handle_request(state: &Arc<DaemonState>, req: RpcRequest) -> RpcRespons
         && (req.method.starts_with("maildir.")
             || req.method.starts_with("sync.")
             || req.method.starts_with("snapshot.")
-            || req.method.starts_with("contacts."))
+            || req.method.starts_with("contacts.")
+            || req.method.starts_with("threads.")
+            || req.method.starts_with("search."))
     {
         return RpcResponse::error(
             id,
@@ -193,13 +209,38 @@ async fn handle_request(state: &Arc<DaemonState>, req: RpcRequest) -> RpcRespons
         "imap.fetch_headers_by_uids" => handle_imap_with_pool(&state, req.params, id, imap_op_fetch_headers).await,
         "imap.get_email" => handle_imap_with_pool(&state, req.params, id, imap_op_get_email).await,
         "imap.get_email_light" => handle_imap_with_pool(&state, req.params, id, imap_op_get_email_light).await,
+        "imap.get_structure" => handle_imap_with_pool(&state, req.params, id, imap_op_get_structure).await,
+        "imap.get_email_parts" => {
+            let has_binary = has_binary(&state, &req.params).await;
+            handle_imap_with_pool(&state, req.params, id, move |s, p| imap_op_get_email_parts(s, p, has_binary)).await
+        }
+        "imap.save_part" => {
+            let has_binary = has_binary(&state, &req.params).await;
+            handle_imap_with_pool(&state, req.params, id, move |s, p| imap_op_save_part(s, p, has_binary)).await
+        }
         "imap.set_flags" => handle_imap_with_pool(&state, req.params, id, imap_op_set_flags).await,
         "imap.delete_email" => handle_imap_with_pool(&state, req.params, id, imap_op_delete_email).await,
         "imap.fetch_raw" => handle_imap_with_pool(&state, req.params, id, imap_op_fetch_raw).await,
-        "imap.append_email" => handle_imap_with_pool(&state, req.params, id, imap_op_append_email).await,
+        "imap.append_email" => {
+            let data_dir = state.data_dir.clone();
+            handle_imap_with_pool(&state, req.params, id, move |s, p| imap_op_append_email(s, p, data_dir)).await
+        }
         "imap.search_emails" => handle_imap_with_pool(&state, req.params, id, imap_op_search_emails).await,
         "imap.disconnect" => handle_imap_disconnect(&state, req.params, id).await,
-        "imap.move_emails" => handle_imap_with_pool(&state, req.params, id, imap_op_move_emails).await,
+        "imap.move_emails" => {
+            let (has_move, has_objectid) = match parse_account(&req.params) {
+                Ok(account) => (
+                    state.imap_pool.has_capability(&account, "MOVE").await,
+                    state.imap_pool.has_capability(&account, imap::objectid::OBJECTID).await,
+                ),
+                Err(_) => (false, false),
+            };
+            let data_dir = state.data_dir.clone();
+            handle_imap_with_pool(&state, req.params, id, move |s, p| {
+                imap_op_move_emails(s, p, has_move, has_objectid, data_dir)
+            })
+            .await
+        }
 
         // ── SMTP (Phase 3) ──────────────────────────────────────────
         "smtp.send_email" => handle_smtp_send(req.params, id).await,
@@ -221,6 +262,16 @@ async fn handle_request(state: &Arc<DaemonState>, req: RpcRequest) -> RpcRespons
         "sync.now" => handle_sync_now(Arc::clone(state), req.params, id).await,
         "sync.wait" => handle_sync_wait(Arc::clone(&state.sync_engine), req.params, id).await,
         "sync.status" => handle_sync_status(&state.sync_engine, req.params, id).await,
+        "sync.unwatch" => handle_sync_unwatch(state, req.params, id).await,
+        "sync.folders" => handle_sync_folders(&state.sync_engine, req.params, id),
+        "sync.labels" => handle_sync_labels(&state.sync_engine, req.params, id),
+        "sync.archive_policy_get" => RpcResponse::success(
+            id,
+            serde_json::to_value(archive_policy::load(&state.app_dir)).unwrap(),
+        ),
+        "sync.archive_policy_set" => handle_sync_archive_policy_set(&state.app_dir, req.params, id),
+        "sync.schedule_get" => handle_sync_schedule_get(&state.scheduler, id).await,
+        "sync.schedule_set" => handle_sync_schedule_set(&state.scheduler, req.params, id).await,
 
         // ── Credentials (via keyring — Phase 4) ─────────────────────
         "credentials.store" => handle_credentials_store(req.params, id),
@@ -239,7 +290,34 @@ async fn handle_request(state: &Arc<DaemonState>, req: RpcRequest) -> RpcRespons
         "maildir.exists" => handle_maildir_exists(&state.data_dir, req.params, id),
         "maildir.delete" => handle_maildir_delete(&state.data_dir, req.params, id),
         "maildir.set_flags" => handle_maildir_set_flags(&state.data_dir, req.params, id),
+        "maildir.locate" => handle_maildir_locate(&state.data_dir, req.params, id),
+        "threads.get" => handle_threads_get(&state.data_dir, req.params, id),
+        "threads.membership" => handle_threads_membership(&state.data_dir, req.params, id),
+        "threads.rebuild" => handle_threads_rebuild(&state.data_dir, req.params, id),
         "maildir.storage_stats" => handle_maildir_storage_stats(&state.data_dir, req.params, id),
+        "quota.get" => handle_quota_get(state, req.params, id).await,
+        "quota.wait" => handle_quota_wait(Arc::clone(&state.sync_engine), req.params, id).await,
+        "quota.alerts_get" => RpcResponse::success(
+            id,
+            serde_json::to_value(quota_alerts::load(&state.app_dir)).unwrap(),
+        ),
+        "quota.alerts_set" => handle_quota_alerts_set(&state.app_dir, req.params, id),
+        "maildir.layout_get" => handle_maildir_layout_get(&state.data_dir, id),
+        "maildir.layout_set" => handle_maildir_layout_set(&state.data_dir, req.params, id).await,
+        "maildir.compression_get" => handle_maildir_compression_get(state, id),
+        "maildir.compression_set" => handle_maildir_compression_set(state, req.params, id),
+        "maildir.dedup_get" => handle_maildir_dedup_get(state, id),
+        "maildir.dedup_set" => handle_maildir_dedup_set(state, req.params, id),
+        "vault.encryption_get" => handle_vault_encryption_get(state, id),
+        "vault.encryption_enable" => handle_vault_encryption_enable(state, req.params, id),
+        "vault.encryption_rotate" => handle_vault_encryption_rotate(state, req.params, id),
+        "vault.encryption_disable" => handle_vault_encryption_disable(state, id),
+        "vault.unlock" => handle_vault_unlock(state, req.params, id),
+        "vault.lock" => {
+            encryption::lock(&state.data_dir);
+            RpcResponse::success(id, serde_json::json!({"locked": true}))
+        }
+        "vault.verify" => handle_vault_verify(Arc::clone(state), req.params, id).await,
 
         // Cache / local index / Graph ID map RPCs removed: they were backed by
         // mailvault_core::cache, a second cache format at a different path that
@@ -276,6 +354,8 @@ async fn handle_request(state: &Arc<DaemonState>, req: RpcRequest) -> RpcRespons
         "contacts_index.get" => handle_contacts_index_get(Arc::clone(&state.contacts), req.params, id),
         "contacts_index.flush" => handle_contacts_index_flush(Arc::clone(&state.contacts), id),
 
+        "search.query" => handle_search_query(Arc::clone(state), req.params, id).await,
+
         _ => RpcResponse::error(id, ipc::METHOD_NOT_FOUND, format!("Unknown method: {}", req.method)),
     }
 }
@@ -307,35 +387,48 @@ fn handle_contacts_index_flush(
     RpcResponse::success(id, serde_json::json!({"ok": true}))
 }
 
-/// Proof-of-concept: list UIDs in a Maildir folder.
-fn handle_maildir_list(data_dir: &Path, params: Value, id: Value) -> RpcResponse {
-    let account_id = params.get("accountId").and_then(|v| v.as_str()).unwrap_or("");
-    let mailbox = params.get("mailbox").and_then(|v| v.as_str()).unwrap_or("INBOX");
-
-    let maildir_path = data_dir
-        .join("Maildir")
-        .join(account_id)
-        .join(mailbox)
-        .join("cur");
-
-    if !maildir_path.exists() {
-        return RpcResponse::success(id, serde_json::json!({"uids": [], "count": 0}));
+/// Run `f` on the search index, loading it on first use and bringing it up to
+/// date first. Blocking — parses every message the index hasn't seen.
+pub fn with_search_index<T>(state: &DaemonState, f: impl FnOnce(&search::SearchIndex) -> T) -> T {
+    let mut guard = state.search.lock().unwrap_or_else(|e| e.into_inner());
+    let index = guard.get_or_insert_with(|| search::SearchIndex::load(&state.data_dir));
+    index.refresh(&state.data_dir);
+    if let Err(e) = index.save(&state.data_dir) {
+        warn!("[search] {}", e);
     }
+    f(index)
+}
 
-    let mut uids = Vec::new();
-    if let Ok(entries) = std::fs::read_dir(&maildir_path) {
-        for entry in entries.flatten() {
-            let name = entry.file_name().to_string_lossy().to_string();
-            // Extract UID from filename (format: {uid}:{flags}:{timestamp})
-            if let Some(uid_str) = name.split(':').next() {
-                if let Ok(uid) = uid_str.parse::<u64>() {
-                    uids.push(uid);
-                }
-            }
-        }
+/// Full-text search over every account's vault, including mail the server no
+/// longer has. Params: `{query, accountIds?, limit?}`, `query` in the search
+/// box language (`search::query`); hits come best first.
+async fn handle_search_query(state: Arc<DaemonState>, params: Value, id: Value) -> RpcResponse {
+    let query = match params.get("query").and_then(|v| v.as_str()) {
+        Some(q) => q.to_string(),
+        None => return RpcResponse::error(id, ipc::INVALID_PARAMS, "Missing query"),
+    };
+    let account_ids: Option<Vec<String>> = params.get("accountIds").and_then(|v| v.as_array()).map(|arr| {
+        arr.iter().filter_map(|v| v.as_str().map(|s| s.to_string())).collect()
+    });
+    let limit = params.get("limit").and_then(|v| v.as_u64()).unwrap_or(200) as usize;
+
+    let hits = tokio::task::spawn_blocking(move || {
+        with_search_index(&state, |index| index.search(&query, account_ids.as_deref(), limit))
+    })
+    .await;
+    match hits {
+        Ok(Ok(hits)) => RpcResponse::success(id, serde_json::json!({ "hits": hits })),
+        Ok(Err(e)) => RpcResponse::error(id, ipc::INVALID_PARAMS, e),
+        Err(e) => RpcResponse::error(id, ipc::INTERNAL_ERROR, format!("Search failed: {}", e)),
     }
+}
 
-    uids.sort();
+/// List UIDs in a Maildir folder, in either layout.
+fn handle_maildir_list(data_dir: &Path, params: Value, id: Value) -> RpcResponse {
+    let account_id = params.get("accountId").and_then(|v| v.as_str()).unwrap_or("");
+    let mailbox = params.get("mailbox").and_then(|v| v.as_str()).unwrap_or("INBOX");
+
+    let uids = mailvault_core::maildir::list_uids(data_dir, account_id, mailbox);
     let count = uids.len();
     RpcResponse::success(id, serde_json::json!({"uids": uids, "count": count}))
 }
@@ -408,6 +501,354 @@ fn handle_maildir_storage_stats(data_dir: &Path, params: Value, id: Value) -> Rp
     RpcResponse::success(id, serde_json::to_value(stats).unwrap())
 }
 
+/// How full the account is on the server, beside what the vault holds:
+/// `{quota, percentUsed, targetPercent, bytesToFree, vault}`. `quota` is null
+/// when the server reports none. `bytesToFree` is what has to go to get under
+/// the lowest alert threshold.
+async fn handle_quota_get(state: &Arc<DaemonState>, params: Value, id: Value) -> RpcResponse {
+    let account: sync_engine::SyncAccount = match params.get("account").and_then(|v| serde_json::from_value(v.clone()).ok()) {
+        Some(a) => a,
+        None => return RpcResponse::error(id, ipc::INVALID_PARAMS, "Missing account"),
+    };
+    let quota = match state.sync_engine.quota(&account).await {
+        Ok(q) => q,
+        Err(e) => return RpcResponse::error(id, ipc::INTERNAL_ERROR, e),
+    };
+    let target = quota_alerts::load(&state.app_dir).target_percent();
+    let percent_used = quota.as_ref().and_then(|q| q.percent_used());
+    let bytes_to_free = quota.as_ref().zip(target).and_then(|(q, t)| q.bytes_to_free(t));
+    let vault = mailvault_core::maildir::storage_stats(&state.data_dir, &account.id);
+    RpcResponse::success(
+        id,
+        serde_json::json!({
+            "quota": quota,
+            "percentUsed": percent_used,
+            "targetPercent": target,
+            "bytesToFree": bytes_to_free,
+            "vault": vault,
+        }),
+    )
+}
+
+/// Long-poll for quota alerts newer than `afterSeq` (default 0): every one
+/// still held, oldest first. Empty when `timeoutMs` passes without one.
+async fn handle_quota_wait(engine: Arc<sync_engine::SyncEngine>, params: Value, id: Value) -> RpcResponse {
+    let after_seq = params.get("afterSeq").and_then(|v| v.as_u64()).unwrap_or(0);
+    let timeout_ms = params.get("timeoutMs").and_then(|v| v.as_u64()).unwrap_or(30_000);
+    let alerts = engine.wait_for_quota_alerts(after_seq, timeout_ms).await;
+    RpcResponse::success(id, serde_json::to_value(alerts).unwrap())
+}
+
+fn handle_quota_alerts_set(app_dir: &Path, params: Value, id: Value) -> RpcResponse {
+    let settings: quota_alerts::QuotaAlertSettings = match serde_json::from_value(params) {
+        Ok(s) => s,
+        Err(e) => return RpcResponse::error(id, ipc::INVALID_PARAMS, format!("Invalid quota alert settings: {}", e)),
+    };
+    if let Err(e) = quota_alerts::validate(&settings) {
+        return RpcResponse::error(id, ipc::INVALID_PARAMS, e);
+    }
+    match quota_alerts::save(app_dir, &settings) {
+        Ok(()) => RpcResponse::success(id, serde_json::json!({"saved": true})),
+        Err(e) => RpcResponse::error(id, ipc::INTERNAL_ERROR, e),
+    }
+}
+
+/// The layout new mailboxes are created in: `"vault"` or `"standard"`.
+fn handle_maildir_layout_get(data_dir: &Path, id: Value) -> RpcResponse {
+    let layout = mailvault_core::maildir::standard::default_layout(&data_dir.join("Maildir"));
+    RpcResponse::success(id, serde_json::json!({ "layout": layout }))
+}
+
+/// Switch the vault to `layout` and convert every existing mailbox. Renames
+/// every message file, so it runs off the RPC thread.
+async fn handle_maildir_layout_set(data_dir: &Path, params: Value, id: Value) -> RpcResponse {
+    let Some(layout) = params.get("layout").and_then(|v| v.as_str()).and_then(mailvault_core::maildir::Layout::parse)
+    else {
+        return RpcResponse::error(id, ipc::INVALID_PARAMS, "layout must be \"vault\" or \"standard\"");
+    };
+    let data_dir = data_dir.to_path_buf();
+    let result = tokio::task::spawn_blocking(move || mailvault_core::maildir::convert_vault(&data_dir, layout)).await;
+    match result {
+        Ok(Ok(report)) => RpcResponse::success(id, serde_json::to_value(report).unwrap()),
+        Ok(Err(e)) => RpcResponse::error(id, ipc::INTERNAL_ERROR, e),
+        Err(e) => RpcResponse::error(id, ipc::INTERNAL_ERROR, format!("Layout conversion failed: {}", e)),
+    }
+}
+
+fn handle_maildir_compression_get(state: &Arc<DaemonState>, id: Value) -> RpcResponse {
+    let compression = compression::default_compression(&state.data_dir.join("Maildir"));
+    let last = state.last_compression.lock().unwrap_or_else(|e| e.into_inner()).clone();
+    RpcResponse::success(id, serde_json::json!({
+        "compression": compression,
+        "converting": state.compressing.load(std::sync::atomic::Ordering::SeqCst),
+        "pending": compression::conversion_pending(&state.data_dir),
+        "lastConversion": last,
+    }))
+}
+
+/// Store new messages in `compression` from now on, and convert the existing
+/// ones in the background. Returns at once; `maildir.compression_get` reports
+/// progress.
+fn handle_maildir_compression_set(state: &Arc<DaemonState>, params: Value, id: Value) -> RpcResponse {
+    let Some(compression) = params.get("compression").and_then(|v| v.as_str()).and_then(Compression::parse) else {
+        return RpcResponse::error(id, ipc::INVALID_PARAMS, "compression must be \"none\", \"zstd\" or \"gzip\"");
+    };
+    if let Err(e) = compression::request_compression(&state.data_dir, compression) {
+        return RpcResponse::error(id, ipc::INTERNAL_ERROR, e);
+    }
+    start_compression_converter(state);
+    RpcResponse::success(id, serde_json::json!({ "compression": compression, "converting": true }))
+}
+
+/// Convert the vault's existing messages to its compression mode, off the
+/// RPC threads, unless a run is already going — that one picks up a newly
+/// requested mode before it finishes.
+pub fn start_compression_converter(state: &Arc<DaemonState>) {
+    use std::sync::atomic::Ordering;
+    if state.compressing.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    let state = Arc::clone(state);
+    tokio::task::spawn_blocking(move || loop {
+        let report = compression::convert_pending(&state.data_dir, |ids| delete_vault_keys(&ids));
+        let failed = report.as_ref().is_some_and(|r| r.errors > 0);
+        if report.is_some() {
+            *state.last_compression.lock().unwrap_or_else(|e| e.into_inner()) = report;
+        }
+        state.compressing.store(false, Ordering::SeqCst);
+        // A request that landed after the last check, and found this run
+        // still going, is this run's to finish.
+        if failed || !compression::conversion_pending(&state.data_dir) || state.compressing.swap(true, Ordering::SeqCst) {
+            break;
+        }
+    });
+}
+
+// ── Attachment dedup ────────────────────────────────────────────────────────
+
+fn handle_maildir_dedup_get(state: &Arc<DaemonState>, id: Value) -> RpcResponse {
+    RpcResponse::success(id, serde_json::json!({
+        "enabled": dedup::is_enabled(&state.data_dir.join("Maildir")),
+        "stats": dedup::stats(&state.data_dir),
+        "converting": state.compressing.load(std::sync::atomic::Ordering::SeqCst),
+        "pending": compression::conversion_pending(&state.data_dir),
+    }))
+}
+
+/// Turn attachment dedup on or off. Existing messages are converted by the
+/// same background run as a compression change.
+fn handle_maildir_dedup_set(state: &Arc<DaemonState>, params: Value, id: Value) -> RpcResponse {
+    let Some(enabled) = params.get("enabled").and_then(|v| v.as_bool()) else {
+        return RpcResponse::error(id, ipc::INVALID_PARAMS, "Missing enabled");
+    };
+    if let Err(e) = dedup::set_enabled(&state.data_dir, enabled) {
+        return RpcResponse::error(id, ipc::INTERNAL_ERROR, e);
+    }
+    start_compression_converter(state);
+    RpcResponse::success(id, serde_json::json!({ "enabled": enabled, "converting": true }))
+}
+
+// ── Encryption at rest ──────────────────────────────────────────────────────
+
+fn handle_vault_encryption_get(state: &Arc<DaemonState>, id: Value) -> RpcResponse {
+    match encryption::status(&state.data_dir) {
+        Ok(status) => {
+            let mut value = serde_json::to_value(status).unwrap();
+            if let Some(obj) = value.as_object_mut() {
+                let converting = state.compressing.load(std::sync::atomic::Ordering::SeqCst);
+                obj.insert("converting".to_string(), serde_json::json!(converting));
+                obj.insert("pending".to_string(), serde_json::json!(compression::conversion_pending(&state.data_dir)));
+            }
+            RpcResponse::success(id, value)
+        }
+        Err(e) => RpcResponse::error(id, ipc::INTERNAL_ERROR, e),
+    }
+}
+
+/// Store a vault key in the OS keyring.
+fn store_vault_key(account: &str, secret: &str) -> Result<(), String> {
+    keyring::Entry::new(KEYRING_SERVICE, account)
+        .and_then(|entry| entry.set_password(secret))
+        .map_err(|e| format!("Keyring set failed: {}", e))
+}
+
+/// Drop retired vault keys from the OS keyring. Best effort: a leftover entry
+/// only holds a key nothing is sealed under any more.
+fn delete_vault_keys(ids: &[String]) {
+    for key_id in ids {
+        let account = encryption::keyring_account(key_id);
+        match keyring::Entry::new(KEYRING_SERVICE, &account).and_then(|entry| entry.delete_credential()) {
+            Ok(()) | Err(keyring::Error::NoEntry) => {}
+            Err(e) => warn!("[encryption] Failed to delete keyring entry {}: {}", account, e),
+        }
+    }
+}
+
+/// Unlock the vault with whatever keys the OS keyring holds for it. Called at
+/// startup, so a vault kept in the keyring never needs a passphrase.
+pub fn unlock_from_keyring(data_dir: &Path) {
+    if encryption::is_unlocked(data_dir) {
+        return;
+    }
+    for key_id in encryption::keyring_keys(data_dir) {
+        let entry = keyring::Entry::new(KEYRING_SERVICE, &encryption::keyring_account(&key_id));
+        match entry.and_then(|entry| entry.get_password()) {
+            Ok(secret) => {
+                if let Err(e) = encryption::unlock_with_secret(data_dir, &key_id, &secret) {
+                    warn!("[encryption] {}", e);
+                }
+            }
+            Err(keyring::Error::NoEntry) => warn!("[encryption] Vault key {} is missing from the keyring", key_id),
+            Err(e) => warn!("[encryption] Keyring get failed for vault key {}: {}", key_id, e),
+        }
+    }
+    if !encryption::is_unlocked(data_dir) {
+        info!("[encryption] Vault is locked until vault.unlock is called with its passphrase");
+    }
+}
+
+/// The app's saved accounts, from the one keyring entry it keeps them all in:
+/// `account_id` → the account as JSON (or, from old installs, a bare
+/// password). Read at startup so the scheduler syncs without waiting for the
+/// app. Empty when the entry is missing or the keyring won't answer.
+pub fn read_saved_credentials() -> HashMap<String, String> {
+    let json = match keyring::Entry::new(KEYRING_SERVICE, CREDENTIALS_KEY).and_then(|entry| entry.get_password()) {
+        Ok(json) => json,
+        Err(keyring::Error::NoEntry) => return HashMap::new(),
+        Err(e) => {
+            warn!("[scheduler] Keyring get failed for saved accounts: {}", e);
+            return HashMap::new();
+        }
+    };
+    serde_json::from_str(&json).unwrap_or_else(|e| {
+        warn!("[scheduler] Saved accounts in the keyring don't parse: {}", e);
+        HashMap::new()
+    })
+}
+
+/// `{passphrase?, keyring?}` — at least one of them, so the key can be
+/// unlocked again.
+fn key_params(params: &Value) -> (Option<&str>, bool) {
+    let passphrase = params.get("passphrase").and_then(|v| v.as_str()).filter(|p| !p.is_empty());
+    let keyring = params.get("keyring").and_then(|v| v.as_bool()).unwrap_or(false);
+    (passphrase, keyring)
+}
+
+/// Turn encryption on and seal the existing files in the background;
+/// `vault.encryption_get` reports progress.
+fn handle_vault_encryption_enable(state: &Arc<DaemonState>, params: Value, id: Value) -> RpcResponse {
+    let (passphrase, keyring) = key_params(&params);
+    let store: encryption::KeyringStore = &store_vault_key;
+    match encryption::enable(&state.data_dir, passphrase, keyring.then_some(store)) {
+        Ok(new_key) => {
+            delete_vault_keys(&new_key.out_of_keyring);
+            start_compression_converter(state);
+            RpcResponse::success(id, serde_json::json!({"keyId": new_key.key_id, "converting": true}))
+        }
+        Err(e) => RpcResponse::error(id, ipc::INTERNAL_ERROR, e),
+    }
+}
+
+/// Replace the vault key and re-seal every file under the new one in the
+/// background. The new key stays in the keyring if the old one was.
+fn handle_vault_encryption_rotate(state: &Arc<DaemonState>, params: Value, id: Value) -> RpcResponse {
+    let (passphrase, _) = key_params(&params);
+    let store: encryption::KeyringStore = &store_vault_key;
+    match encryption::rotate(&state.data_dir, passphrase, Some(store)) {
+        Ok(new_key) => {
+            delete_vault_keys(&new_key.out_of_keyring);
+            start_compression_converter(state);
+            RpcResponse::success(id, serde_json::json!({"keyId": new_key.key_id, "converting": true}))
+        }
+        Err(e) => RpcResponse::error(id, ipc::INTERNAL_ERROR, e),
+    }
+}
+
+/// Turn encryption off and decrypt the vault in the background.
+fn handle_vault_encryption_disable(state: &Arc<DaemonState>, id: Value) -> RpcResponse {
+    match encryption::disable(&state.data_dir) {
+        Ok(()) => {
+            start_compression_converter(state);
+            RpcResponse::success(id, serde_json::json!({"converting": true}))
+        }
+        Err(e) => RpcResponse::error(id, ipc::INTERNAL_ERROR, e),
+    }
+}
+
+/// `{passphrase}` — unlock the vault for this daemon, and finish a
+/// conversion that was waiting on it.
+fn handle_vault_unlock(state: &Arc<DaemonState>, params: Value, id: Value) -> RpcResponse {
+    let Some(passphrase) = params.get("passphrase").and_then(|v| v.as_str()) else {
+        return RpcResponse::error(id, ipc::INVALID_PARAMS, "Missing passphrase");
+    };
+    if let Err(e) = encryption::unlock_with_passphrase(&state.data_dir, passphrase) {
+        return RpcResponse::error(id, ipc::INTERNAL_ERROR, e);
+    }
+    if compression::conversion_pending(&state.data_dir) {
+        start_compression_converter(state);
+    }
+    RpcResponse::success(id, serde_json::json!({"unlocked": encryption::is_unlocked(&state.data_dir)}))
+}
+
+/// Re-hash the vault (or one account's part of it) against its manifests,
+/// cross-check the backup mirror at `backupPath` if given, and re-fetch from
+/// the server what came back corrupt and the mirror couldn't put back.
+/// `refetch: false` only reports.
+async fn handle_vault_verify(state: Arc<DaemonState>, params: Value, id: Value) -> RpcResponse {
+    let account_id = params.get("accountId").and_then(|v| v.as_str()).map(String::from);
+    let refetch = params.get("refetch").and_then(|v| v.as_bool()).unwrap_or(true);
+    let mirror = params.get("backupPath").and_then(|v| v.as_str()).filter(|p| !p.is_empty()).map(|root| {
+        mailvault_core::maildir::verify::BackupMirror {
+            root: PathBuf::from(root),
+            emails: scheduler::read_account_emails(&state.app_dir),
+        }
+    });
+
+    let data_dir = state.data_dir.clone();
+    let report = match tokio::task::spawn_blocking(move || {
+        mailvault_core::maildir::verify::verify_vault(&data_dir, account_id.as_deref(), mirror.as_ref())
+    })
+    .await
+    {
+        Ok(report) => report,
+        Err(e) => return RpcResponse::error(id, ipc::INTERNAL_ERROR, format!("Vault verification failed: {}", e)),
+    };
+
+    // Whatever the server no longer has stays in quarantine/.
+    let mut refetched = Vec::new();
+    let mut not_on_server = Vec::new();
+    let mut skipped = Vec::new();
+    for ((account_id, mailbox), uids) in report.to_refetch() {
+        let refs = |uids: &[u32]| -> Vec<Value> {
+            uids.iter()
+                .map(|uid| serde_json::json!({ "accountId": account_id, "mailbox": mailbox, "uid": uid }))
+                .collect()
+        };
+        let account = if refetch { state.scheduler.account(&account_id).await } else { None };
+        let Some(account) = account else {
+            skipped.extend(refs(&uids));
+            continue;
+        };
+        match state.sync_engine.refetch(&account, &mailbox, &uids).await {
+            Ok(stored) => {
+                let gone: Vec<u32> = uids.iter().copied().filter(|u| !stored.contains(u)).collect();
+                refetched.extend(refs(&stored));
+                not_on_server.extend(refs(&gone));
+            }
+            Err(e) => {
+                warn!("[vault.verify] Re-fetch failed for {} ({}): {}", account.email, mailbox, e);
+                skipped.extend(refs(&uids));
+            }
+        }
+    }
+
+    let mut result = serde_json::to_value(&report).unwrap();
+    result["refetched"] = Value::Array(refetched);
+    result["notOnServer"] = Value::Array(not_on_server);
+    result["refetchSkipped"] = Value::Array(skipped);
+    RpcResponse::success(id, result)
+}
+
 // ── New Maildir handlers (Phase 2) ──────────────────────────────────────────
 
 fn handle_maildir_read_full(data_dir: &Path, params: Value, id: Value) -> RpcResponse {
@@ -482,6 +923,63 @@ fn handle_maildir_set_flags(data_dir: &Path, params: Value, id: Value) -> RpcRes
     }
 }
 
+/// Every stored copy of each Message-ID, across accounts and folders:
+/// `{messageId}` or `{messageIds: [...]}`, answered as `{locations: {id: [...]}}`.
+fn handle_maildir_locate(data_dir: &Path, params: Value, id: Value) -> RpcResponse {
+    let mut message_ids: Vec<String> = params.get("messageIds")
+        .and_then(|v| serde_json::from_value(v.clone()).ok())
+        .unwrap_or_default();
+    if let Some(one) = params.get("messageId").and_then(|v| v.as_str()) {
+        message_ids.push(one.to_string());
+    }
+    if message_ids.is_empty() {
+        return RpcResponse::error(id, ipc::INVALID_PARAMS, "Missing messageId or messageIds");
+    }
+    let locations = mailvault_core::message_index::locate_many(data_dir, &message_ids);
+    RpcResponse::success(id, serde_json::json!({ "locations": locations }))
+}
+
+/// A whole conversation as a tree: `{accountId, threadId}`, or
+/// `{accountId, mailbox, uid}` for the thread a message is in. `{thread: null}`
+/// when there's no such thread.
+fn handle_threads_get(data_dir: &Path, params: Value, id: Value) -> RpcResponse {
+    let Some(account_id) = params.get("accountId").and_then(|v| v.as_str()) else {
+        return RpcResponse::error(id, ipc::INVALID_PARAMS, "Missing accountId");
+    };
+    let thread = if let Some(thread_id) = params.get("threadId").and_then(|v| v.as_str()) {
+        threading::thread(data_dir, account_id, thread_id)
+    } else {
+        let mailbox = params.get("mailbox").and_then(|v| v.as_str());
+        let uid = params.get("uid").and_then(|v| v.as_u64());
+        match (mailbox, uid) {
+            (Some(mailbox), Some(uid)) => threading::thread_of(data_dir, account_id, mailbox, uid as u32),
+            _ => return RpcResponse::error(id, ipc::INVALID_PARAMS, "Missing threadId, or mailbox and uid"),
+        }
+    };
+    RpcResponse::success(id, serde_json::json!({ "thread": thread }))
+}
+
+/// The thread each message is in: `{accountId, mailbox, uids}`, answered as
+/// `{threads: {uid: threadId}}`.
+fn handle_threads_membership(data_dir: &Path, params: Value, id: Value) -> RpcResponse {
+    let account_id = params.get("accountId").and_then(|v| v.as_str());
+    let mailbox = params.get("mailbox").and_then(|v| v.as_str());
+    let uids: Option<Vec<u32>> = params.get("uids").and_then(|v| serde_json::from_value(v.clone()).ok());
+    let (Some(account_id), Some(mailbox), Some(uids)) = (account_id, mailbox, uids) else {
+        return RpcResponse::error(id, ipc::INVALID_PARAMS, "Missing accountId, mailbox or uids");
+    };
+    let threads = threading::thread_ids(data_dir, account_id, mailbox, &uids);
+    RpcResponse::success(id, serde_json::json!({ "threads": threads }))
+}
+
+fn handle_threads_rebuild(data_dir: &Path, params: Value, id: Value) -> RpcResponse {
+    let Some(account_id) = params.get("accountId").and_then(|v| v.as_str()) else {
+        return RpcResponse::error(id, ipc::INVALID_PARAMS, "Missing accountId");
+    };
+    let messages = threading::rebuild(data_dir, account_id);
+    RpcResponse::success(id, serde_json::json!({ "messages": messages }))
+}
+
 // ── IMAP handlers (Phase 3) ─────────────────────────────────────────────────
 
 use crate::graph;
@@ -620,6 +1118,57 @@ async fn imap_op_get_email_light(mut session: ImapSession, params: Value) -> Res
     Ok((serde_json::to_value(result).unwrap(), session, Some(mailbox.to_string())))
 }
 
+/// Whether the account's server decodes parts itself (BINARY).
+async fn has_binary(state: &Arc<DaemonState>, params: &Value) -> bool {
+    match parse_account(params) {
+        Ok(account) => state.imap_pool.has_capability(&account, imap::parts::BINARY).await,
+        Err(_) => false,
+    }
+}
+
+async fn imap_op_get_structure(mut session: ImapSession, params: Value) -> Result<(Value, ImapSession, Option<String>), String> {
+    let mailbox = params.get("mailbox").and_then(|v| v.as_str()).unwrap_or("INBOX");
+    let uid = params.get("uid").and_then(|v| v.as_u64()).unwrap_or(0) as u32;
+    let result = imap::parts::fetch_structure(&mut session, mailbox, uid).await?;
+    Ok((serde_json::to_value(result).unwrap(), session, Some(mailbox.to_string())))
+}
+
+/// The email with its text parts only — attachments stay on the server.
+async fn imap_op_get_email_parts(mut session: ImapSession, params: Value, has_binary: bool) -> Result<(Value, ImapSession, Option<String>), String> {
+    let mailbox = params.get("mailbox").and_then(|v| v.as_str()).unwrap_or("INBOX");
+    let uid = params.get("uid").and_then(|v| v.as_u64()).unwrap_or(0) as u32;
+    let result = imap::parts::fetch_email_for_reading(&mut session, mailbox, uid, has_binary).await?;
+    Ok((serde_json::to_value(result).unwrap(), session, Some(mailbox.to_string())))
+}
+
+/// Stream one part (`section`), or the whole message without one, to `destPath`.
+async fn imap_op_save_part(mut session: ImapSession, params: Value, has_binary: bool) -> Result<(Value, ImapSession, Option<String>), String> {
+    let mailbox = params.get("mailbox").and_then(|v| v.as_str()).unwrap_or("INBOX");
+    let uid = params.get("uid").and_then(|v| v.as_u64()).unwrap_or(0) as u32;
+    let section = params.get("section").and_then(|v| v.as_str());
+    let dest = params
+        .get("destPath")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| "Missing destPath".to_string())?;
+
+    let part = match section {
+        Some(section) => {
+            let structure = imap::parts::fetch_structure(&mut session, mailbox, uid)
+                .await?
+                .ok_or_else(|| format!("UID {} not found in {}", uid, mailbox))?;
+            let part = structure
+                .parts
+                .into_iter()
+                .find(|p| p.section == section)
+                .ok_or_else(|| format!("UID {} has no part {}", uid, section))?;
+            Some(part)
+        }
+        None => None,
+    };
+    let bytes = imap::parts::download(&mut session, mailbox, uid, part.as_ref(), has_binary, std::path::Path::new(dest)).await?;
+    Ok((serde_json::json!({"path": dest, "bytes": bytes}), session, Some(mailbox.to_string())))
+}
+
 async fn imap_op_set_flags(mut session: ImapSession, params: Value) -> Result<(Value, ImapSession, Option<String>), String> {
     let mailbox = params.get("mailbox").and_then(|v| v.as_str()).unwrap_or("INBOX");
     let uid = params.get("uid").and_then(|v| v.as_u64()).unwrap_or(0) as u32;
@@ -644,15 +1193,25 @@ async fn imap_op_fetch_raw(mut session: ImapSession, params: Value) -> Result<(V
     Ok((serde_json::json!({"rawSource": raw_b64}), session, Some(mailbox.to_string())))
 }
 
-async fn imap_op_append_email(mut session: ImapSession, params: Value) -> Result<(Value, ImapSession, Option<String>), String> {
+/// `localUid` names the vault copy of a message that so far only existed
+/// here; with `accountId` it's renamed to the UID the server gave it.
+async fn imap_op_append_email(mut session: ImapSession, params: Value, data_dir: PathBuf) -> Result<(Value, ImapSession, Option<String>), String> {
     let mailbox = params.get("mailbox").and_then(|v| v.as_str()).unwrap_or("INBOX");
     let raw_b64 = params.get("rawBase64").and_then(|v| v.as_str()).unwrap_or("");
     use base64::Engine;
     let raw = base64::engine::general_purpose::STANDARD.decode(raw_b64)
         .map_err(|e| format!("Invalid base64: {}", e))?;
     let flags = params.get("flags").and_then(|v| v.as_str()).unwrap_or("");
-    imap::append_email(&mut session, mailbox, &raw, flags).await?;
-    Ok((serde_json::json!({"appended": true}), session, None))
+    let uid = imap::append_email(&mut session, mailbox, &raw, flags).await?;
+
+    let account_id = params.get("accountId").and_then(|v| v.as_str());
+    let local_uid = params.get("localUid").and_then(|v| v.as_u64()).map(|u| u as u32);
+    let mut remapped = 0;
+    if let (Some(appended), Some(account_id), Some(local_uid)) = (uid, account_id, local_uid) {
+        let pairs = vec![(local_uid, appended.uid)];
+        remapped = remap_local(data_dir, account_id, mailbox, mailbox, appended.uid_validity, pairs).await;
+    }
+    Ok((serde_json::json!({"appended": true, "uid": uid.map(|a| a.uid), "remapped": remapped}), session, None))
 }
 
 async fn imap_op_search_emails(mut session: ImapSession, params: Value) -> Result<(Value, ImapSession, Option<String>), String> {
@@ -666,15 +1225,73 @@ async fn imap_op_search_emails(mut session: ImapSession, params: Value) -> Resul
     Ok((serde_json::to_value(result).unwrap(), session, Some(mailbox.to_string())))
 }
 
-async fn imap_op_move_emails(mut session: ImapSession, params: Value) -> Result<(Value, ImapSession, Option<String>), String> {
+/// With `accountId`, the vault copies and cached headers of the moved
+/// messages follow them to the UIDs the server reports.
+async fn imap_op_move_emails(
+    mut session: ImapSession,
+    params: Value,
+    has_move: bool,
+    has_objectid: bool,
+    data_dir: PathBuf,
+) -> Result<(Value, ImapSession, Option<String>), String> {
     let from = params.get("fromMailbox").and_then(|v| v.as_str()).unwrap_or("INBOX");
     let to = params.get("toMailbox").and_then(|v| v.as_str()).unwrap_or("");
+    if to.is_empty() {
+        return Err("Missing toMailbox".to_string());
+    }
     let uids: Vec<u32> = params.get("uids").and_then(|v| serde_json::from_value(v.clone()).ok()).unwrap_or_default();
-    // Move each email: copy to destination then delete from source
-    for &uid in &uids {
-        imap::delete_email(&mut session, from, uid, false).await?;
+    let mapping = imap::move_emails(&mut session, from, to, &uids, has_move, has_objectid).await?;
+
+    let mut remapped = 0;
+    if let (Some(mapping), Some(account_id)) = (&mapping, params.get("accountId").and_then(|v| v.as_str())) {
+        remapped = remap_local(data_dir, account_id, from, to, mapping.uid_validity, mapping.uids.clone()).await;
     }
-    Ok((serde_json::json!({"moved": true, "count": uids.len()}), session, Some(from.to_string())))
+    let uid_map: Option<std::collections::BTreeMap<u32, u32>> = mapping.map(|m| m.uids.into_iter().collect());
+    // A lookup by EMAILID leaves the target selected.
+    let selected = if has_objectid { None } else { Some(from.to_string()) };
+    Ok((
+        serde_json::json!({"moved": true, "count": uids.len(), "uidMap": uid_map, "remapped": remapped}),
+        session,
+        selected,
+    ))
+}
+
+/// Carry vault files and cached headers from `from` to `to` at the UIDs the
+/// server reported (UIDPLUS). Best effort: the server side already happened,
+/// and anything left behind is fetched again by the next sync — as is
+/// everything when `uid_validity` isn't the generation the vault's copy of
+/// `to` is keyed under, since the reported UIDs belong to another one.
+async fn remap_local(
+    data_dir: PathBuf,
+    account_id: &str,
+    from: &str,
+    to: &str,
+    uid_validity: u32,
+    pairs: Vec<(u32, u32)>,
+) -> usize {
+    let (account_id, from, to) = (account_id.to_string(), from.to_string(), to.to_string());
+    tokio::task::spawn_blocking(move || {
+        let to_cur = mailvault_core::maildir::cur_path(&data_dir, &account_id, &to);
+        let generation = to_cur.parent().and_then(mailvault_core::maildir::read_generation);
+        if generation != Some(uid_validity) {
+            info!(
+                "[remap] {} is at generation {:?}, server reported {}; leaving {} messages to the next sync",
+                to, generation, uid_validity, pairs.len(),
+            );
+            return 0;
+        }
+        let from_cache = sync_engine::tauri_cache_dir(&data_dir, &account_id, &from);
+        let to_cache = sync_engine::tauri_cache_dir(&data_dir, &account_id, &to);
+        if let Err(e) = mailvault_core::header_store::remap(&from_cache, &to_cache, &pairs, false) {
+            warn!("[remap] Headers {} → {}: {}", from, to, e);
+        }
+        mailvault_core::maildir::remap(&data_dir, &account_id, &from, &to, &pairs, false).unwrap_or_else(|e| {
+            warn!("[remap] Vault {} → {}: {}", from, to, e);
+            0
+        })
+    })
+    .await
+    .unwrap_or(0)
 }
 
 // ── SMTP handler ────────────────────────────────────────────────────────────
@@ -784,15 +1401,31 @@ async fn handle_sync_now(state: Arc<DaemonState>, params: Value, id: Value) -> R
         Some(a) => a,
         None => return RpcResponse::error(id, ipc::INVALID_PARAMS, "Missing account"),
     };
-    let mailbox = params.get("mailbox").and_then(|v| v.as_str()).unwrap_or("INBOX");
+    // `allFolders: true` syncs every folder on the account instead of one mailbox.
+    let all_folders = params.get("allFolders").and_then(|v| v.as_bool()).unwrap_or(false);
+    let mailbox = if all_folders {
+        sync_engine::ALL_FOLDERS
+    } else {
+        params.get("mailbox").and_then(|v| v.as_str()).unwrap_or("INBOX")
+    };
     let auto_classify = params.get("autoClassify").and_then(|v| v.as_bool()).unwrap_or(false);
 
+    // Keep INBOX pushed from here on; a running watcher just takes the fresh
+    // config (and with it the current OAuth token).
+    state.sync_engine.watch_account(&account).await;
+    // …and on the daemon's own schedule, with this run counting as the latest.
+    state.scheduler.register(&account, mailbox).await;
+
     // Spawn sync as background task so RPC returns immediately
     let account_id = account.id.clone();
     let response_account_id = account_id.clone();
     let mailbox_clone = mailbox.to_string();
     tokio::spawn(async move {
-        let result = state.sync_engine.sync_account(&account, &mailbox_clone).await;
+        let result = if all_folders {
+            state.sync_engine.sync_all_folders(&account).await
+        } else {
+            state.sync_engine.sync_account(&account, &mailbox_clone).await
+        };
 
         // Auto-trigger heuristic classification after successful sync (if enabled)
         if auto_classify && result.success && result.new_emails > 0 {
@@ -803,10 +1436,11 @@ async fn handle_sync_now(state: Arc<DaemonState>, params: Value, id: Value) -> R
         // Cold or partly-filled cache (a restored/migrated mailbox, or one the
         // app only ever paginated part of) — fill it here, once, instead of
         // letting the app re-page the whole mailbox off the server every launch.
-        if result.success {
-            let short = state.sync_engine.sidecar_shortfall(&account_id, &mailbox_clone, result.total_emails);
+        // On Gmail `result.mailbox` is All Mail when a label was asked for.
+        if result.success && !all_folders {
+            let short = state.sync_engine.sidecar_shortfall(&account_id, &result.mailbox, result.total_emails);
             if short > 0 {
-                state.sync_engine.backfill_mailbox(&account, &mailbox_clone).await;
+                state.sync_engine.backfill_mailbox(&account, &result.mailbox).await;
             }
         }
     });
@@ -830,15 +1464,20 @@ async fn handle_sync_wait(engine: Arc<sync_engine::SyncEngine>, params: Value, i
 async fn handle_sync_status(engine: &sync_engine::SyncEngine, params: Value, id: Value) -> RpcResponse {
     if let Some(account_id) = params.get("accountId").and_then(|v| v.as_str()) {
         let backfilling = engine.is_backfilling(account_id).await;
+        let watching = engine.is_watching(account_id).await;
         match engine.get_state(account_id).await {
             Some(state) => {
                 let mut value = serde_json::to_value(state).unwrap();
                 if let Some(obj) = value.as_object_mut() {
                     obj.insert("backfilling".to_string(), serde_json::json!(backfilling));
+                    obj.insert("watching".to_string(), serde_json::json!(watching));
                 }
                 RpcResponse::success(id, value)
             }
-            None => RpcResponse::success(id, serde_json::json!({"status": "unknown", "backfilling": backfilling})),
+            None => RpcResponse::success(
+                id,
+                serde_json::json!({"status": "unknown", "backfilling": backfilling, "watching": watching}),
+            ),
         }
     } else {
         let states = engine.get_states().await;
@@ -846,9 +1485,100 @@ async fn handle_sync_status(engine: &sync_engine::SyncEngine, params: Value, id:
     }
 }
 
+/// Stop the INBOX watcher and scheduled syncs `sync.now` started — the
+/// account was removed or signed out. The next `sync.now` starts both again.
+async fn handle_sync_unwatch(state: &DaemonState, params: Value, id: Value) -> RpcResponse {
+    let account_id = match params.get("accountId").and_then(|v| v.as_str()) {
+        Some(id) => id,
+        None => return RpcResponse::error(id, ipc::INVALID_PARAMS, "Missing accountId"),
+    };
+    state.scheduler.unregister(account_id).await;
+    let stopped = state.sync_engine.unwatch_account(account_id).await;
+    RpcResponse::success(id, serde_json::json!({"stopped": stopped}))
+}
+
+/// The folders the last all-folders sync saw, and those it found deleted on
+/// the server — their Maildir is still in the vault.
+fn handle_sync_folders(engine: &sync_engine::SyncEngine, params: Value, id: Value) -> RpcResponse {
+    let account_id = match params.get("accountId").and_then(|v| v.as_str()) {
+        Some(id) => id,
+        None => return RpcResponse::error(id, ipc::INVALID_PARAMS, "Missing accountId"),
+    };
+    RpcResponse::success(id, serde_json::to_value(engine.folder_registry(account_id)).unwrap())
+}
+
+/// A Gmail account's labels. `{accountId}` → each label with its message
+/// count; `{accountId, label}` → the All Mail UIDs carrying that label.
+fn handle_sync_labels(engine: &sync_engine::SyncEngine, params: Value, id: Value) -> RpcResponse {
+    let account_id = match params.get("accountId").and_then(|v| v.as_str()) {
+        Some(id) => id,
+        None => return RpcResponse::error(id, ipc::INVALID_PARAMS, "Missing accountId"),
+    };
+    let index = engine.label_index(account_id);
+    match params.get("label").and_then(|v| v.as_str()) {
+        Some(label) => RpcResponse::success(id, serde_json::json!({
+            "mailbox": index.mailbox,
+            "uids": index.uids_with(label),
+        })),
+        None => {
+            let labels: Vec<Value> = index
+                .counts()
+                .into_iter()
+                .map(|(label, count)| serde_json::json!({"label": label, "count": count}))
+                .collect();
+            RpcResponse::success(id, serde_json::json!({"mailbox": index.mailbox, "labels": labels}))
+        }
+    }
+}
+
+/// Replace the body archiving policy. Params are the policy itself:
+/// `{defaultRule, accounts: {accountId: {folder: rule}}}`, where a rule is
+/// `{mode: "off"|"all"}`, `{mode: "underSize", maxMb}` or `{mode: "newerThan", days}`.
+/// Takes effect on the next sync of each folder.
+fn handle_sync_archive_policy_set(app_dir: &Path, params: Value, id: Value) -> RpcResponse {
+    let policy: archive_policy::ArchivePolicy = match serde_json::from_value(params) {
+        Ok(p) => p,
+        Err(e) => return RpcResponse::error(id, ipc::INVALID_PARAMS, format!("Invalid archive policy: {}", e)),
+    };
+    if let Err(e) = archive_policy::validate(&policy) {
+        return RpcResponse::error(id, ipc::INVALID_PARAMS, e);
+    }
+    match archive_policy::save(app_dir, &policy) {
+        Ok(()) => RpcResponse::success(id, serde_json::json!({"saved": true})),
+        Err(e) => RpcResponse::error(id, ipc::INTERNAL_ERROR, e),
+    }
+}
+
+/// The sync schedule plus, per registered account and folder, when it runs
+/// next and how many times in a row it has failed.
+async fn handle_sync_schedule_get(scheduler: &scheduler::SyncScheduler, id: Value) -> RpcResponse {
+    let schedule = scheduler.schedule().await;
+    let status = scheduler.status().await;
+    RpcResponse::success(id, serde_json::json!({"schedule": schedule, "status": status}))
+}
+
+/// Replace the sync schedule. Params are the schedule itself:
+/// `{defaultIntervalSecs, accounts: {accountId: {folder: intervalSecs}}}`.
+async fn handle_sync_schedule_set(scheduler: &scheduler::SyncScheduler, params: Value, id: Value) -> RpcResponse {
+    let schedule: scheduler::Schedule = match serde_json::from_value(params) {
+        Ok(s) => s,
+        Err(e) => return RpcResponse::error(id, ipc::INVALID_PARAMS, format!("Invalid schedule: {}", e)),
+    };
+    if let Err(e) = scheduler::validate(&schedule) {
+        return RpcResponse::error(id, ipc::INVALID_PARAMS, e);
+    }
+    match scheduler.set_schedule(schedule).await {
+        Ok(()) => RpcResponse::success(id, serde_json::json!({"saved": true})),
+        Err(e) => RpcResponse::error(id, ipc::INTERNAL_ERROR, e),
+    }
+}
+
 /// Keyring service name — must match Tauri's KEYRING_SERVICE for shared access.
 const KEYRING_SERVICE: &str = "com.mailvault.app";
 
+/// The keyring entry the app stores every account's credentials under.
+const CREDENTIALS_KEY: &str = "credentials";
+
 fn handle_credentials_store(params: Value, id: Value) -> RpcResponse {
     let service = params.get("service").and_then(|v| v.as_str()).unwrap_or(KEYRING_SERVICE);
     let account = params.get("account").and_then(|v| v.as_str()).unwrap_or("");
@@ -1349,7 +2079,7 @@ fn load_emails_all_mailboxes(
     all_emails
 }
 
-/// Read cached email JSON files and convert to EmailForClassification.
+/// Read the mailbox's cached headers and convert to EmailForClassification.
 fn load_emails_for_classification(
     data_dir: &Path,
     account_id: &str,
@@ -1363,28 +2093,13 @@ fn load_emails_for_classification(
             mailbox.replace(|c: char| !c.is_alphanumeric(), "_"),
         ));
 
-    let entries = match std::fs::read_dir(&cache_dir) {
-        Ok(e) => e,
+    let store = match mailvault_core::header_store::HeaderStore::open(&cache_dir) {
+        Ok(s) => s,
         Err(_) => return Vec::new(),
     };
 
     let mut emails = Vec::new();
-    for entry in entries.flatten() {
-        let name = entry.file_name().to_string_lossy().to_string();
-        if !name.ends_with(".json") || name == "_meta.json" {
-            continue;
-        }
-
-        let json = match std::fs::read_to_string(entry.path()) {
-            Ok(j) => j,
-            Err(_) => continue,
-        };
-
-        let val: serde_json::Value = match serde_json::from_str(&json) {
-            Ok(v) => v,
-            Err(_) => continue,
-        };
-
+    for val in store.headers() {
         let uid = val.get("uid").and_then(|v| v.as_u64()).unwrap_or(0);
         let message_id = val.get("messageId").and_then(|v| v.as_str()).map(String::from);
         let subject = val.get("subject").and_then(|v| v.as_str()).unwrap_or("").to_string();
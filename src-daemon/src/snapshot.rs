@@ -86,10 +86,11 @@ pub fn create_snapshot(
         .finish()
         .map_err(|e| format!("Failed to finish gzip: {}", e))?;
 
-    fs::write(&filepath, &compressed)
+    // Sealed too when the vault is encrypted: a snapshot lists every subject.
+    mailvault_core::maildir::encryption::write_sidecar(data_dir, &filepath, &compressed)
         .map_err(|e| format!("Failed to write snapshot: {}", e))?;
 
-    let size_bytes = compressed.len() as u64;
+    let size_bytes = fs::metadata(&filepath).map(|m| m.len()).unwrap_or(compressed.len() as u64);
 
     info!(
         "Created snapshot {} for {} ({} emails, {} mailboxes, {} bytes compressed)",
@@ -127,7 +128,7 @@ pub fn list_snapshots(data_dir: &Path, account_id: &str) -> Result<Vec<SnapshotI
         let size_bytes = meta.map(|m| m.len()).unwrap_or(0);
 
         // Quick-read: decompress and parse just the top-level fields
-        match load_snapshot_info(&entry.path()) {
+        match load_snapshot_info(data_dir, &entry.path()) {
             Ok(mut info) => {
                 info.filename = name;
                 info.size_bytes = size_bytes;
@@ -145,8 +146,8 @@ pub fn list_snapshots(data_dir: &Path, account_id: &str) -> Result<Vec<SnapshotI
 }
 
 /// Read just the metadata from a snapshot file (without loading all emails).
-fn load_snapshot_info(path: &Path) -> Result<SnapshotInfo, String> {
-    let compressed = fs::read(path)
+fn load_snapshot_info(data_dir: &Path, path: &Path) -> Result<SnapshotInfo, String> {
+    let compressed = mailvault_core::maildir::encryption::read_sidecar(data_dir, path)
         .map_err(|e| format!("Failed to read snapshot: {}", e))?;
 
     let mut decoder = flate2::read::GzDecoder::new(&compressed[..]);
@@ -181,7 +182,7 @@ pub fn load_snapshot(
         return Err(format!("Snapshot not found: {}", filename));
     }
 
-    let compressed = fs::read(&path)
+    let compressed = mailvault_core::maildir::encryption::read_sidecar(data_dir, &path)
         .map_err(|e| format!("Failed to read snapshot: {}", e))?;
 
     let mut decoder = flate2::read::GzDecoder::new(&compressed[..]);
@@ -252,33 +253,25 @@ pub fn create_snapshot_from_maildir(
 
         let mut emails = Vec::new();
 
-        if let Ok(email_entries) = fs::read_dir(&cur_dir) {
-            for email_entry in email_entries.flatten() {
-                let fname = email_entry.file_name().to_string_lossy().to_string();
-                let size = email_entry.metadata().map(|m| m.len()).unwrap_or(0);
-
-                // Parse filename: {uid}:{flags}:{timestamp} or just {uid}
-                let parts: Vec<&str> = fname.splitn(3, ':').collect();
-                let uid = parts.first()
-                    .and_then(|s| s.parse::<u64>().ok())
-                    .unwrap_or(0);
-
-                let flags: Vec<String> = parts.get(1)
-                    .map(|f| f.split(',').filter(|s| !s.is_empty()).map(String::from).collect())
-                    .unwrap_or_default();
-
-                // We store minimal info — subject/from/date require parsing .eml
-                // For v1, store what we can extract from filename + metadata
-                emails.push(SnapshotEmail {
-                    uid,
-                    message_id: None,
-                    subject: String::new(), // Populated from cache if available
-                    from: String::new(),
-                    date: String::new(),
-                    flags,
-                    size,
-                });
-            }
+        // The uid index reads both Maildir layouts; a standard-layout name
+        // doesn't carry the uid.
+        for (uid, entry) in mailvault_core::uid_index::entries(&cur_dir) {
+            // Flags as the vault filename spells them: `seen`, not `\Seen`.
+            let flags: Vec<String> = entry.flags.iter()
+                .map(|f| f.trim_start_matches('\\').to_lowercase())
+                .collect();
+
+            // We store minimal info — subject/from/date require parsing .eml
+            // For v1, store what we can extract from filename + metadata
+            emails.push(SnapshotEmail {
+                uid: uid as u64,
+                message_id: entry.message_id.map(|id| format!("<{}>", id)),
+                subject: String::new(), // Populated from cache if available
+                from: String::new(),
+                date: String::new(),
+                flags,
+                size: entry.size,
+            });
         }
 
         emails.sort_by_key(|e| e.uid);
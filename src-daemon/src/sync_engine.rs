@@ -4,15 +4,23 @@
 //! The app never calls IMAP directly — it reads from local storage
 //! and listens for sync events.
 
+use crate::archive_policy::{self, ArchiveRule};
 use crate::contacts_index::ContactsState;
-use crate::imap::{self, ImapConfig, EmailHeader as ImapEmailHeader};
+use crate::graph;
+use crate::graph_sync;
+use crate::imap::{self, gmail, objectid, ImapConfig, EmailHeader as ImapEmailHeader};
 use crate::imap::pool::{ImapPool, PooledSessionGuard};
-use mailvault_core::transfer_stats;
+use crate::quota_alerts::{self, QuotaAlert};
+use mailvault_core::header_store::{cache_base_name, HeaderStore};
+use mailvault_core::quota::MailboxQuota;
+use mailvault_core::threading::{self, Source};
+use mailvault_core::{maildir, transfer_stats};
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::Mutex;
 use tracing::{info, warn, error};
 
@@ -46,6 +54,11 @@ pub struct SyncState {
     pub last_error: Option<String>,
     pub new_emails: usize,
     pub total_emails: u32,
+    /// All-folders runs only: the folder being synced right now, and how far
+    /// through the folder list the run is. `None` / 0 for a single mailbox.
+    pub current_folder: Option<String>,
+    pub folders_done: u32,
+    pub folders_total: u32,
 }
 
 #[derive(Debug, Clone, Serialize, PartialEq)]
@@ -78,6 +91,33 @@ pub struct SyncEngine {
     /// `account_id` → UTC day whose cap we already logged, so a capped account
     /// costs one log line a day instead of one per sync tick.
     cap_logged: Mutex<HashMap<String, String>>,
+    /// `account_id` → the INBOX watcher keeping an IDLE (or NOOP-polling)
+    /// connection open for that account. See `watch_account`.
+    idlers: Mutex<HashMap<String, IdleWatch>>,
+    /// Graph API root for Graph accounts — `graph::GRAPH_BASE` but in tests.
+    graph_base: String,
+    /// `account_id` → when its quota was last read, and the alert level it
+    /// stood at. See `check_quota`.
+    quota_seen: Mutex<HashMap<String, (std::time::Instant, u8)>>,
+    /// The last `QUOTA_ALERT_RING` quota alerts, oldest first. `quota.wait`
+    /// subscribes to this.
+    quota_alerts: tokio::sync::watch::Sender<VecDeque<QuotaAlert>>,
+    /// `account_id` → held around every load-modify-save of its folder
+    /// registry. The scheduler's all-folders run and a `sync.now` can reach
+    /// `reconcile_folders` for one account at once; unserialized, one run's
+    /// rename or remove would be written over by the other's stale copy.
+    registry_locks: Mutex<HashMap<String, Arc<Mutex<()>>>>,
+}
+
+/// Quota alerts kept for `quota.wait`: a caller that was busy while several
+/// accounts crossed thresholds still gets each one, not just the last.
+const QUOTA_ALERT_RING: usize = 32;
+
+/// A running INBOX watcher. `account` is refreshed on every `sync.now`, so a
+/// reconnect picks up the freshest OAuth token rather than the one it started with.
+struct IdleWatch {
+    account: SyncAccount,
+    task: tokio::task::JoinHandle<()>,
 }
 
 impl SyncEngine {
@@ -97,12 +137,32 @@ impl SyncEngine {
             backfilling: Mutex::new(HashSet::new()),
             backfill_gave_up: Mutex::new(HashSet::new()),
             cap_logged: Mutex::new(HashMap::new()),
+            idlers: Mutex::new(HashMap::new()),
+            graph_base: graph::GRAPH_BASE.to_string(),
+            quota_seen: Mutex::new(HashMap::new()),
+            quota_alerts: tokio::sync::watch::channel(VecDeque::new()).0,
+            registry_locks: Mutex::new(HashMap::new()),
         }
     }
 
+    /// Exclusive use of the account's folder registry until the guard drops.
+    async fn lock_registry(&self, account_id: &str) -> tokio::sync::OwnedMutexGuard<()> {
+        let lock = Arc::clone(self.registry_locks.lock().await.entry(account_id.to_string()).or_default());
+        lock.lock_owned().await
+    }
+
+    fn graph_client(&self, account: &SyncAccount) -> Result<graph::GraphClient, String> {
+        let token = account
+            .imap_config
+            .access_token
+            .as_deref()
+            .ok_or_else(|| "Graph account has no access token".to_string())?;
+        Ok(graph::GraphClient::with_base(token, &self.graph_base))
+    }
+
     /// `Some(reason)` when this account has spent its daily transfer allowance
     /// and must not sync again until the next UTC day. `None` = go ahead.
-    async fn transfer_cap_reached(&self, account: &SyncAccount) -> Option<String> {
+    pub(crate) async fn transfer_cap_reached(&self, account: &SyncAccount) -> Option<String> {
         let limits = read_transfer_limits(&self.app_dir, &account.id)?;
         if !limits.cap_enabled {
             return None;
@@ -229,6 +289,9 @@ impl SyncEngine {
                 last_error: None,
                 new_emails: 0,
                 total_emails: 0,
+                current_folder: None,
+                folders_done: 0,
+                folders_total: 0,
             });
         }
 
@@ -251,6 +314,9 @@ impl SyncEngine {
                 last_error: result.error.clone(),
                 new_emails: result.new_emails,
                 total_emails: result.total_emails,
+                current_folder: None,
+                folders_done: 0,
+                folders_total: 0,
             });
         }
 
@@ -271,9 +337,490 @@ impl SyncEngine {
             }
         }
 
+        if result.success {
+            self.check_quota(account).await;
+        }
+
         result
     }
 
+    /// How full the account's server mailbox is. `None` when the server has
+    /// no QUOTA, or no limit on INBOX. A limit from the alert settings fills
+    /// in for a server that reports usage alone.
+    pub async fn quota(&self, account: &SyncAccount) -> Result<Option<MailboxQuota>, String> {
+        let config = &account.imap_config;
+        let quota = if graph_sync::is_graph(config) {
+            let used = self.graph_client(account)?.mailbox_usage().await?;
+            Some(MailboxQuota::from_graph_usage(used))
+        } else if self.pool.has_capability(config, imap::quota::QUOTA).await {
+            let guard = self.pool.get_background(config).await?;
+            let PooledSessionGuard { mut session, last_selected, _permit } = guard;
+            let fetched = imap::quota::get_quota(&mut session).await;
+            let guard = PooledSessionGuard { session, last_selected, _permit };
+            match &fetched {
+                Ok(_) => self.pool.return_background(config, guard).await,
+                Err(_) => self.pool.discard(config, guard).await,
+            }
+            fetched?
+        } else {
+            None
+        };
+        let limit = quota_alerts::load(&self.app_dir).limits.get(&account.id).copied();
+        Ok(quota.map(|q| q.with_limit(limit)))
+    }
+
+    /// After a successful sync: read the quota, unless that was done within
+    /// the check interval, and raise an alert for a threshold newly reached.
+    async fn check_quota(&self, account: &SyncAccount) {
+        let settings = quota_alerts::load(&self.app_dir);
+        if settings.thresholds.is_empty() {
+            return;
+        }
+        let interval = Duration::from_secs(settings.check_interval_secs);
+        let previous = match self.quota_seen.lock().await.get(&account.id) {
+            Some((at, _)) if at.elapsed() < interval => return,
+            Some((_, level)) => *level,
+            None => 0,
+        };
+
+        let quota = self.quota(account).await;
+        let (level, percent) = match &quota {
+            Ok(Some(q)) => match q.percent_used() {
+                Some(p) => (settings.level(p), p),
+                None => (0, 0.0),
+            },
+            Ok(None) => (0, 0.0),
+            // Try again next interval rather than on every tick.
+            Err(e) => {
+                warn!("[quota] Failed to read quota for {}: {}", account.email, e);
+                (previous, 0.0)
+            }
+        };
+        self.quota_seen.lock().await.insert(account.id.clone(), (std::time::Instant::now(), level));
+
+        let (Ok(Some(quota)), Some(threshold)) = (quota, settings.crossed(previous, percent)) else {
+            return;
+        };
+        let bytes_to_free = settings.target_percent().and_then(|t| quota.bytes_to_free(t));
+        warn!(
+            "[quota] {} is {:.0}% full (threshold {}%), {:?} bytes to free",
+            account.email, percent, threshold, bytes_to_free
+        );
+        self.raise_quota_alert(QuotaAlert {
+            seq: 0,
+            account_id: account.id.clone(),
+            threshold,
+            percent_used: percent,
+            bytes_to_free,
+            quota,
+        });
+    }
+
+    /// Number `alert` after the last one raised and add it to the ring,
+    /// dropping the oldest once it holds `QUOTA_ALERT_RING`.
+    fn raise_quota_alert(&self, mut alert: QuotaAlert) {
+        self.quota_alerts.send_modify(|ring| {
+            alert.seq = ring.back().map_or(0, |a| a.seq) + 1;
+            if ring.len() == QUOTA_ALERT_RING {
+                ring.pop_front();
+            }
+            ring.push_back(alert);
+        });
+    }
+
+    /// Every quota alert still held that is newer than `after_seq`, oldest
+    /// first, waiting up to `timeout_ms` for the first. Empty when none came.
+    pub async fn wait_for_quota_alerts(&self, after_seq: u64, timeout_ms: u64) -> Vec<QuotaAlert> {
+        let mut rx = self.quota_alerts.subscribe();
+        let wait = rx.wait_for(|ring| ring.back().is_some_and(|a| a.seq > after_seq));
+        match tokio::time::timeout(Duration::from_millis(timeout_ms), wait).await {
+            Ok(Ok(ring)) => ring.iter().filter(|a| a.seq > after_seq).cloned().collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Delta-sync every selectable folder of the account: INBOX first, then
+    /// Sent, then the rest by how recently each last changed. Progress goes to
+    /// `SyncState` folder by folder; `sync.wait` wakes once, at the end, with
+    /// the totals (`mailbox` = `ALL_FOLDERS`).
+    ///
+    /// A folder the server no longer lists is reconciled against the previous
+    /// run before anything syncs — see `reconcile_folders`. No backfill here:
+    /// a cold folder gets its first page, and `sync.now` on that one mailbox
+    /// fills the rest when the user opens it.
+    pub async fn sync_all_folders(&self, account: &SyncAccount) -> SyncResult {
+        let account_id = &account.id;
+        let failed = |error: String| SyncResult {
+            account_id: account_id.clone(),
+            mailbox: ALL_FOLDERS.to_string(),
+            new_emails: 0, updated_flags: 0, total_emails: 0,
+            success: false, error: Some(error),
+        };
+
+        if let Some(reason) = self.transfer_cap_reached(account).await {
+            return failed(reason);
+        }
+
+        let registry_lock = self.lock_registry(account_id).await;
+        let mut registry = read_folder_registry(&self.data_dir, account_id);
+        let folders = match self.list_and_reconcile(account, &mut registry).await {
+            Ok(f) => f,
+            Err(e) => {
+                drop(registry_lock);
+                let result = failed(e);
+                self.finish_all_folders(account, &result).await;
+                return result;
+            }
+        };
+        if let Err(e) = write_folder_registry(&self.data_dir, account_id, &registry) {
+            warn!("[sync] {}", e);
+        }
+        drop(registry_lock);
+
+        info!("[sync] Syncing {} folders for {}", folders.len(), account.email);
+        let folders_total = folders.len() as u32;
+        let mut result = SyncResult {
+            account_id: account_id.clone(),
+            mailbox: ALL_FOLDERS.to_string(),
+            new_emails: 0, updated_flags: 0, total_emails: 0,
+            success: true, error: None,
+        };
+        let mut errors = Vec::new();
+        let mut changed = Vec::new();
+
+        for (done, folder) in folders.iter().enumerate() {
+            self.states.lock().await.insert(account_id.clone(), SyncState {
+                account_id: account_id.clone(),
+                status: SyncStatus::Syncing,
+                last_sync: None,
+                last_error: None,
+                new_emails: result.new_emails,
+                total_emails: result.total_emails,
+                current_folder: Some(folder.clone()),
+                folders_done: done as u32,
+                folders_total,
+            });
+
+            let r = self.do_sync(account, folder).await;
+            if !r.success {
+                errors.push(format!("{}: {}", folder, r.error.unwrap_or_default()));
+                continue;
+            }
+            if r.new_emails > 0 || r.updated_flags > 0 {
+                changed.push((folder, now_ms()));
+            }
+            result.new_emails += r.new_emails;
+            result.updated_flags += r.updated_flags;
+            result.total_emails += r.total_emails;
+        }
+
+        if !errors.is_empty() {
+            result.success = false;
+            result.error = Some(format!(
+                "{} of {} folders failed: {}",
+                errors.len(), folders_total, errors.join("; ")
+            ));
+        }
+        if !changed.is_empty() {
+            // Re-read: another run may have reconciled while these synced.
+            let _registry_lock = self.lock_registry(account_id).await;
+            let mut registry = read_folder_registry(&self.data_dir, account_id);
+            for (folder, at) in changed {
+                registry.touch(folder, at);
+            }
+            if let Err(e) = write_folder_registry(&self.data_dir, account_id, &registry) {
+                warn!("[sync] {}", e);
+            }
+        }
+
+        self.finish_all_folders(account, &result).await;
+        result
+    }
+
+    /// LIST the account's folders, reconcile them against the registry, and
+    /// return the selectable ones in sync order.
+    ///
+    /// On Gmail (X-GM-EXT-1) every label is a folder holding copies of mail
+    /// that is also in `[Gmail]/All Mail`, so only All Mail, Trash and Spam are
+    /// synced — each message once — and the labels come from the label index.
+    async fn list_and_reconcile(
+        &self,
+        account: &SyncAccount,
+        registry: &mut FolderRegistry,
+    ) -> Result<Vec<String>, String> {
+        let config = &account.imap_config;
+        let listed = if graph_sync::is_graph(config) {
+            // No UIDVALIDITY to tell a rename from a delete by: the folder
+            // list is simply what Graph has now.
+            let listed = graph_sync::list_mailboxes(&self.graph_client(account)?, &self.data_dir, &account.id).await?;
+            registry.set_present(listed.iter().map(|m| m.path.as_str()));
+            listed
+        } else {
+            let guard = self.pool.get_background(config).await?;
+            let PooledSessionGuard { mut session, last_selected, _permit } = guard;
+
+            let outcome = match imap::list_mailboxes(&mut session).await {
+                Ok(listed) => self
+                    .reconcile_folders(&mut session, account, &listed, registry)
+                    .await
+                    .map(|_| listed),
+                Err(e) => Err(e),
+            };
+
+            let guard = PooledSessionGuard { session, last_selected, _permit };
+            match &outcome {
+                Ok(_) => self.pool.return_background(config, guard).await,
+                Err(_) => self.pool.discard(config, guard).await,
+            }
+            outcome?
+        };
+        let mut folders: Vec<&imap::MailboxInfo> = listed.iter().filter(|m| !m.noselect).collect();
+
+        let storage = if self.pool.has_capability(config, gmail::GMAIL_EXT).await {
+            gmail::Storage::from_list(&listed)
+        } else {
+            None
+        };
+        let all_mail = storage.as_ref().map(|s| s.all_mail.clone());
+        registry.gmail_all_mail = None;
+        if let Some(storage) = storage {
+            folders.retain(|m| storage.stores(&m.path));
+            // The folders may have moved since the session resolved them.
+            self.pool.set_gmail_storage(config, storage.clone()).await;
+            let all_mail = &storage.all_mail;
+            registry.gmail_all_mail = Some(all_mail.clone());
+            let mut index = read_label_index(&self.data_dir, &account.id);
+            if index.mailbox != *all_mail {
+                index = LabelIndex { mailbox: all_mail.clone(), ..Default::default() };
+                write_label_index(&self.data_dir, &account.id, &index)?;
+            }
+        }
+
+        folders.sort_by_key(|m| {
+            let rank = if all_mail.as_deref() == Some(m.path.as_str()) || m.path.eq_ignore_ascii_case("INBOX") {
+                0
+            } else if m.special_use.as_deref() == Some("\\Sent") {
+                1
+            } else {
+                2
+            };
+            (rank, std::cmp::Reverse(registry.last_change_at(&m.path)), m.path.clone())
+        });
+        Ok(folders.into_iter().map(|m| m.path.clone()).collect())
+    }
+
+    /// Compare the server's folder list with what the previous all-folders run
+    /// saw. A folder that vanished while a new one appeared with the same
+    /// UIDVALIDITY was renamed: its sidecar cache and Maildir directory move to
+    /// the new name, so the next sync is a delta rather than a cold reload.
+    /// Anything else that vanished was deleted: its sidecar cache goes (it
+    /// mirrors a server folder that no longer exists) but its Maildir stays
+    /// where it is and is listed under `removed` — that mail is what the vault
+    /// is for.
+    async fn reconcile_folders(
+        &self,
+        session: &mut imap::ImapSession,
+        account: &SyncAccount,
+        listed: &[imap::MailboxInfo],
+        registry: &mut FolderRegistry,
+    ) -> Result<(), String> {
+        let account_id = &account.id;
+        let first_run = registry.folders.is_empty();
+        let present: HashSet<&str> = listed.iter().map(|m| m.path.as_str()).collect();
+        let known: HashSet<String> = registry.folders.iter().map(|f| f.path.clone()).collect();
+
+        let gone: Vec<String> = registry
+            .folders
+            .iter()
+            .map(|f| f.path.clone())
+            .filter(|p| !present.contains(p.as_str()))
+            .collect();
+        let appeared: Vec<&str> = listed
+            .iter()
+            .filter(|m| !m.noselect && !known.contains(&m.path))
+            .map(|m| m.path.as_str())
+            .collect();
+
+        // UIDVALIDITY of every newcomer — only worth a SELECT each when
+        // something also disappeared, i.e. when a rename is possible at all.
+        let mut appeared_validity: Vec<(&str, u32)> = Vec::new();
+        if !first_run && !gone.is_empty() {
+            for path in &appeared {
+                let (_, validity, _, _) = imap::check_mailbox_status(session, path, false).await?;
+                if let Some(v) = validity {
+                    appeared_validity.push((path, v));
+                }
+            }
+        }
+
+        for old in gone {
+            let old_validity = read_tauri_cache_meta(&tauri_cache_dir(&self.data_dir, account_id, &old))
+                .and_then(|m| m.uid_validity);
+            // Only a unique match is a rename — two folders created in the
+            // same second can share a timestamp-based UIDVALIDITY.
+            let matches: Vec<&str> = appeared_validity
+                .iter()
+                .filter(|(_, v)| Some(*v) == old_validity)
+                .map(|(p, _)| *p)
+                .collect();
+
+            if let [new] = matches[..] {
+                info!("[sync] Folder renamed on server for {}: {} → {}", account.email, old, new);
+                self.move_folder_storage(account_id, &old, new);
+                threading::rename_mailbox(&self.data_dir, account_id, &old, new);
+                registry.rename(&old, new);
+                appeared_validity.retain(|(p, _)| *p != new);
+            } else {
+                info!("[sync] Folder removed on server for {}: {}", account.email, old);
+                let _ = fs::remove_dir_all(tauri_cache_dir(&self.data_dir, account_id, &old));
+                threading::forget_mailbox(&self.data_dir, account_id, &old, Source::Cache);
+                registry.remove(&old, now_ms());
+            }
+        }
+
+        registry.set_present(listed.iter().filter(|m| !m.noselect).map(|m| m.path.as_str()));
+        Ok(())
+    }
+
+    /// Move a renamed folder's sidecar cache and Maildir directory. Never
+    /// overwrites: if the new name already has a Maildir (the app saved there
+    /// first), the old one stays put and both are kept.
+    fn move_folder_storage(&self, account_id: &str, old: &str, new: &str) {
+        let old_cache = tauri_cache_dir(&self.data_dir, account_id, old);
+        let new_cache = tauri_cache_dir(&self.data_dir, account_id, new);
+        if new_cache.exists() {
+            let _ = fs::remove_dir_all(&old_cache);
+        } else if let Err(e) = fs::rename(&old_cache, &new_cache) {
+            warn!("[sync] Failed to move cache {:?} → {:?}: {}", old_cache, new_cache, e);
+        }
+
+        let maildir_of = |mailbox: &str| {
+            let cur = maildir::cur_path(&self.data_dir, account_id, mailbox);
+            cur.parent().map(Path::to_path_buf).unwrap_or(cur)
+        };
+        let (old_dir, new_dir) = (maildir_of(old), maildir_of(new));
+        if !old_dir.exists() {
+            return;
+        }
+        if new_dir.exists() {
+            warn!("[sync] {:?} already exists — leaving {:?} in place", new_dir, old_dir);
+            return;
+        }
+        if let Some(parent) = new_dir.parent() {
+            let _ = fs::create_dir_all(parent);
+        }
+        if let Err(e) = fs::rename(&old_dir, &new_dir) {
+            warn!("[sync] Failed to move Maildir {:?} → {:?}: {}", old_dir, new_dir, e);
+        }
+    }
+
+    /// Record the outcome of an all-folders run and wake `sync.wait`.
+    async fn finish_all_folders(&self, account: &SyncAccount, result: &SyncResult) {
+        let folders_total = self
+            .states
+            .lock()
+            .await
+            .get(&account.id)
+            .map_or(0, |s| s.folders_total);
+        self.states.lock().await.insert(account.id.clone(), SyncState {
+            account_id: account.id.clone(),
+            status: if result.success { SyncStatus::Idle } else { SyncStatus::Error },
+            last_sync: Some(now_ms() / 1000),
+            last_error: result.error.clone(),
+            new_emails: result.new_emails,
+            total_emails: result.total_emails,
+            current_folder: None,
+            folders_done: folders_total,
+            folders_total,
+        });
+
+        if result.success {
+            info!(
+                "[sync] All folders synced for {}: {} new, {} flag updates, {} total",
+                account.email, result.new_emails, result.updated_flags, result.total_emails
+            );
+        } else {
+            warn!("[sync] All-folders sync for {} had errors: {:?}", account.email, result.error);
+        }
+
+        if let Some(tx) = self.watchers.lock().await.get(&account.id) {
+            let _ = tx.send(Some(result.clone()));
+        }
+
+        if result.success {
+            self.check_quota(account).await;
+        }
+    }
+
+    /// The folder list the last all-folders run saw, plus the folders it found
+    /// removed on the server (whose Maildir is still in the vault).
+    pub fn folder_registry(&self, account_id: &str) -> FolderRegistry {
+        read_folder_registry(&self.data_dir, account_id)
+    }
+
+    /// The account's Gmail label index, empty when it isn't Gmail or no
+    /// all-folders sync has run yet.
+    pub fn label_index(&self, account_id: &str) -> LabelIndex {
+        read_label_index(&self.data_dir, account_id)
+    }
+
+    /// Bring the label index up to date with All Mail and copy the labels
+    /// into its sidecars. Incremental (CHANGEDSINCE the last run — a label
+    /// change bumps MODSEQ) while the index covers every cached message under
+    /// the same UIDVALIDITY; a full read of the ids and labels otherwise.
+    async fn refresh_gmail_labels(&self, account: &SyncAccount, all_mail: &str) -> Result<(), String> {
+        let account_id = &account.id;
+        let config = &account.imap_config;
+        let cache_dir = tauri_cache_dir(&self.data_dir, account_id, all_mail);
+        let meta = read_tauri_cache_meta(&cache_dir);
+        let cached = cached_uids(&cache_dir);
+
+        let mut index = read_label_index(&self.data_dir, account_id);
+        if index.mailbox != all_mail || index.uid_validity != meta.as_ref().and_then(|m| m.uid_validity) {
+            index = LabelIndex {
+                mailbox: all_mail.to_string(),
+                uid_validity: meta.as_ref().and_then(|m| m.uid_validity),
+                ..Default::default()
+            };
+        }
+        let complete = cached.iter().all(|uid| index.messages.contains_key(uid));
+        let changed_since = if complete && self.pool.has_capability(config, "CONDSTORE").await {
+            index.highest_modseq
+        } else {
+            None
+        };
+
+        let guard = self.pool.get_background(config).await?;
+        let PooledSessionGuard { mut session, last_selected: _, _permit } = guard;
+        let fetched = gmail::fetch_gmail_meta(&mut session, all_mail, changed_since).await;
+        let guard = PooledSessionGuard { session, last_selected: Some(all_mail.to_string()), _permit };
+        match &fetched {
+            Ok(_) => self.pool.return_background(config, guard).await,
+            Err(_) => self.pool.discard(config, guard).await,
+        }
+
+        let mut changed = Vec::new();
+        for m in fetched? {
+            let entry = LabelEntry {
+                msg_id: m.msg_id.to_string(),
+                thread_id: m.thread_id.map(|t| t.to_string()),
+                labels: m.labels,
+            };
+            if index.messages.get(&m.uid) != Some(&entry) {
+                changed.push((m.uid, entry.clone()));
+            }
+            index.messages.insert(m.uid, entry);
+        }
+        index.messages.retain(|uid, _| cached.contains(uid));
+        index.highest_modseq = meta.and_then(|m| m.highest_modseq);
+
+        let patched = patch_sidecar_labels(&cache_dir, &changed);
+        if patched > 0 {
+            info!("[sync] Labels changed on {} messages for {}", patched, account.email);
+        }
+        write_label_index(&self.data_dir, account_id, &index)
+    }
+
     /// Internal sync implementation.
     async fn do_sync(
         &self,
@@ -283,6 +830,10 @@ impl SyncEngine {
         let account_id = &account.id;
         let config = &account.imap_config;
 
+        if graph_sync::is_graph(config) {
+            return self.do_graph_sync(account, mailbox).await;
+        }
+
         // Get a session from the pool
         let guard = match self.pool.get_background(config).await {
             Ok(g) => g,
@@ -296,8 +847,38 @@ impl SyncEngine {
 
         let PooledSessionGuard { mut session, last_selected: _, _permit } = guard;
         let has_condstore = self.pool.has_capability(config, "CONDSTORE").await;
+        // `create_imap_session` already ENABLEd it, or dropped the capability.
+        let has_qresync = self.pool.has_capability(config, "QRESYNC").await;
+
+        // On Gmail a label folder is a view of All Mail, which the session
+        // resolved on connect: sync (and archive) All Mail instead, so INBOX,
+        // IDLE and every scheduled folder keep each message once.
+        let storage = self.pool.gmail_storage(config).await;
+        let mailbox = match &storage {
+            Some(storage) if !storage.stores(mailbox) => {
+                info!("[sync] {} ({}) is a Gmail label — syncing {}", account.email, mailbox, storage.all_mail);
+                storage.all_mail.as_str()
+            }
+            _ => mailbox,
+        };
 
-        let outcome = self.sync_mailbox(&mut session, account, mailbox, has_condstore).await;
+        let mut outcome = self
+            .sync_mailbox(&mut session, account, mailbox, has_condstore, has_qresync)
+            .await;
+
+        // Bodies ride along on the same session once the headers are in. A
+        // failure here costs the session, never the sync that already landed.
+        if let Ok(delta) = &mut outcome {
+            if !delta.session_dirty {
+                if let Err(e) = self.archive_bodies(&mut session, account, mailbox).await {
+                    warn!("[archive] {} ({}): {}", account.email, mailbox, e);
+                    delta.session_dirty = true;
+                }
+            }
+            if self.pool.has_capability(config, objectid::OBJECTID).await {
+                self.note_vault_object_ids(account_id, mailbox);
+            }
+        }
 
         let guard = PooledSessionGuard {
             session,
@@ -312,6 +893,12 @@ impl SyncEngine {
             _ => self.pool.discard(config, guard).await,
         }
 
+        if outcome.is_ok() && storage.as_ref().is_some_and(|s| s.all_mail == mailbox) {
+            if let Err(e) = self.refresh_gmail_labels(account, mailbox).await {
+                outcome = Err(format!("labels: {}", e));
+            }
+        }
+
         match outcome {
             Ok(delta) => SyncResult {
                 account_id: account_id.clone(),
@@ -331,28 +918,82 @@ impl SyncEngine {
         }
     }
 
+    /// `do_sync` for a Graph account: one `/messages/delta` round, then the
+    /// bodies the archive policy asks for.
+    async fn do_graph_sync(&self, account: &SyncAccount, mailbox: &str) -> SyncResult {
+        let outcome = match self.graph_client(account) {
+            Ok(client) => {
+                let outcome = graph_sync::sync_folder(&client, &self.data_dir, &account.id, mailbox).await;
+                if outcome.is_ok() {
+                    // A failed archive pass leaves the bodies for the next
+                    // sync; the headers are in either way.
+                    if let Err(e) = self.archive_graph_bodies(&client, account, mailbox).await {
+                        warn!("[archive] {} ({}): {}", account.email, mailbox, e);
+                    }
+                }
+                outcome
+            }
+            Err(e) => Err(e),
+        };
+        match outcome {
+            Ok(o) => SyncResult {
+                account_id: account.id.clone(),
+                mailbox: mailbox.to_string(),
+                new_emails: o.new_emails,
+                updated_flags: o.updated_flags,
+                total_emails: o.total_emails,
+                success: true,
+                error: None,
+            },
+            Err(e) => SyncResult {
+                account_id: account.id.clone(),
+                mailbox: mailbox.to_string(),
+                new_emails: 0, updated_flags: 0, total_emails: 0,
+                success: false, error: Some(e),
+            },
+        }
+    }
+
     /// Delta sync for one mailbox.
     ///
     /// Cold cache (or UIDVALIDITY change) → one 500-header page fetch.
     /// Warm cache → STATUS + only the UIDs above the cached UIDNEXT, plus a
     /// CONDSTORE flag patch and an expunge prune when the counts disagree.
     /// A typical restart is therefore a handful of headers, not 500.
+    ///
+    /// With QRESYNC the SELECT itself reports flag changes and expunges
+    /// (`VANISHED (EARLIER)`), so neither CHANGEDSINCE nor the UID listing runs.
     async fn sync_mailbox(
         &self,
         session: &mut imap::ImapSession,
         account: &SyncAccount,
         mailbox: &str,
         has_condstore: bool,
+        has_qresync: bool,
     ) -> Result<SyncDelta, String> {
         let account_id = &account.id;
 
-        let (total, uid_validity, server_uid_next, highest_modseq) =
-            imap::check_mailbox_status(session, mailbox, has_condstore).await?;
-
         let cache_dir = tauri_cache_dir(&self.data_dir, account_id, mailbox);
         let cached = read_tauri_cache_meta(&cache_dir);
         let sidecar_count = count_sidecars(&cache_dir);
 
+        let qresync_state = cached
+            .as_ref()
+            .and_then(|c| Some((c.uid_validity?, c.highest_modseq?)))
+            .filter(|_| has_qresync && sidecar_count > 0);
+        let qresync = match qresync_state {
+            Some((validity, modseq)) => {
+                let known: Vec<u32> = cached_uids(&cache_dir).into_iter().collect();
+                imap::select_qresync(session, mailbox, validity, modseq, &known).await?
+            }
+            None => None,
+        };
+
+        let (total, uid_validity, server_uid_next, highest_modseq) = match &qresync {
+            Some(q) => (q.mailbox.exists, q.mailbox.uid_validity, q.mailbox.uid_next, q.mailbox.highest_modseq),
+            None => imap::check_mailbox_status(session, mailbox, has_condstore || has_qresync).await?,
+        };
+
         let uid_validity_ok = match (cached.as_ref().and_then(|c| c.uid_validity), uid_validity) {
             (Some(a), Some(b)) => a == b,
             _ => true, // unknown on either side — don't force a full reload over it
@@ -372,15 +1013,24 @@ impl SyncEngine {
                     account.email, mailbox, sidecar_count
                 );
                 let _ = fs::remove_dir_all(&cache_dir);
+                threading::forget_mailbox(&self.data_dir, account_id, mailbox, Source::Cache);
             }
-            let (headers, _total, _has_more, _skipped) =
+            let (mut headers, _total, _has_more, _skipped) =
                 imap::fetch_emails_page(session, mailbox, 1, 500).await?;
+            let session_dirty = match self.object_ids(session, account, mailbox, &mut headers).await {
+                Ok(()) => false,
+                Err(e) => {
+                    warn!("[sync] EMAILID fetch failed for {} ({}): {}", account.email, mailbox, e);
+                    true
+                }
+            };
             let new_emails = headers.len();
             write_cache_meta(&cache_dir, total, uid_validity, server_uid_next, highest_modseq)?;
             write_headers(&cache_dir, &headers)?;
             self.contacts.observe_headers(account_id, mailbox, &headers);
+            threading::observe_headers(&self.data_dir, account_id, mailbox, &headers);
             info!("[sync] Full page sync for {} ({}): {} headers", account.email, mailbox, new_emails);
-            return Ok(SyncDelta { new_emails, updated_flags: 0, total_emails: total, session_dirty: false });
+            return Ok(SyncDelta { new_emails, updated_flags: 0, total_emails: total, session_dirty });
         }
 
         // ── Delta path ──
@@ -404,16 +1054,18 @@ impl SyncEngine {
             }
         }
 
-        // 2. Flag changes — CONDSTORE tells us exactly which UIDs moved.
+        // 2. Flag changes — CONDSTORE tells us exactly which UIDs moved, and
+        //    QRESYNC already told us in the SELECT.
         let mut updated_flags = 0;
-        match (cached_modseq, highest_modseq) {
-            (Some(cached_modseq), Some(server_modseq)) if server_modseq != cached_modseq => {
+        match (&qresync, cached_modseq, highest_modseq) {
+            (Some(q), _, _) => updated_flags = patch_sidecar_flags(&cache_dir, &q.changed_flags),
+            (None, Some(cached_modseq), Some(server_modseq)) if server_modseq != cached_modseq => {
                 match imap::fetch_changed_flags(session, mailbox, cached_modseq).await {
                     Ok(changes) => updated_flags = patch_sidecar_flags(&cache_dir, &changes),
                     Err(e) => warn!("[sync] CHANGEDSINCE failed for {}: {}", account.email, e),
                 }
             }
-            (Some(_), Some(_)) => {} // modseq unchanged — no flags moved
+            (None, Some(_), Some(_)) => {} // modseq unchanged — no flags moved
             _ => {
                 // No CONDSTORE: nothing would ever refresh read/star state on
                 // already-cached messages. Re-read flags (no headers) for the
@@ -437,26 +1089,47 @@ impl SyncEngine {
         //    a deleted message would linger. So also reconcile on a timer — one
         //    UID SEARCH ALL per RECONCILE_INTERVAL_MS bounds how long any missed
         //    expunge can survive, at negligible cost.
-        let expected_total = cached_total + new_headers.len() as u32;
+        //
+        //    QRESYNC names the expunged UIDs outright, so that is both the prune
+        //    and the reconcile. The count gate still applies: VANISHED only
+        //    covers UIDs we passed as known, so an expunge of a message this
+        //    cache never had still falls through to the UID listing.
+        let mut reconciled_at = cached.as_ref().and_then(|c| c.last_reconcile);
+        let mut vanished = 0u32;
+        if let Some(q) = &qresync {
+            vanished = remove_sidecars(&cache_dir, &q.vanished) as u32;
+            threading::forget(&self.data_dir, account_id, mailbox, &q.vanished, Source::Cache);
+            reconciled_at = Some(now_ms());
+            if vanished > 0 {
+                info!("[sync] QRESYNC pruned {} vanished UIDs for {} ({})", vanished, account.email, mailbox);
+            }
+        }
+
+        let expected_total = (cached_total + new_headers.len() as u32).saturating_sub(vanished);
         let counts_disagree = total != expected_total;
-        let reconcile_due = cached
-            .as_ref()
-            .and_then(|c| c.last_reconcile)
-            .map_or(true, |t| now_ms().saturating_sub(t) > RECONCILE_INTERVAL_MS);
+        let reconcile_due = qresync.is_none()
+            && cached
+                .as_ref()
+                .and_then(|c| c.last_reconcile)
+                .map_or(true, |t| now_ms().saturating_sub(t) > RECONCILE_INTERVAL_MS);
 
-        let mut reconciled_at = cached.as_ref().and_then(|c| c.last_reconcile);
         let mut session_dirty = false;
-        if counts_disagree || reconcile_due {
+        if let Err(e) = self.object_ids(session, account, mailbox, &mut new_headers).await {
+            warn!("[sync] EMAILID fetch failed for {} ({}): {}", account.email, mailbox, e);
+            session_dirty = true;
+        }
+        if !session_dirty && (counts_disagree || reconcile_due) {
             match imap::search_all_uids(session, mailbox, false).await {
                 Ok(uids) if uids.is_empty() && total > 0 => {
                     warn!("[sync] UID SEARCH returned 0 but EXISTS={} — skipping prune", total);
                 }
                 Ok(uids) => {
                     let pruned = prune_sidecars(&cache_dir, &uids);
+                    threading::forget(&self.data_dir, account_id, mailbox, &pruned, Source::Cache);
                     reconciled_at = Some(now_ms());
                     info!(
                         "[sync] Reconciled {} ({}): {} server UIDs, {} pruned (counts_disagree={}, due={})",
-                        account.email, mailbox, uids.len(), pruned, counts_disagree, reconcile_due
+                        account.email, mailbox, uids.len(), pruned.len(), counts_disagree, reconcile_due
                     );
                 }
                 Err(e) => {
@@ -470,6 +1143,7 @@ impl SyncEngine {
         if !new_headers.is_empty() {
             write_headers(&cache_dir, &new_headers)?;
             self.contacts.observe_headers(account_id, mailbox, &new_headers);
+            threading::observe_headers(&self.data_dir, account_id, mailbox, &new_headers);
         }
 
         info!(
@@ -485,6 +1159,256 @@ impl SyncEngine {
         })
     }
 
+    /// Store full bodies for cached messages the archive policy selects and the
+    /// vault doesn't have yet: newest first, at most `ARCHIVE_BATCH` per sync,
+    /// so a freshly enabled policy works through a big mailbox over many ticks
+    /// instead of one long download. Returns how many were stored.
+    async fn archive_bodies(
+        &self,
+        session: &mut imap::ImapSession,
+        account: &SyncAccount,
+        mailbox: &str,
+    ) -> Result<usize, String> {
+        let policy = archive_policy::load(&self.app_dir);
+        let rule = policy.rule_for(&account.id, mailbox);
+        if *rule == ArchiveRule::Off || self.transfer_cap_reached(account).await.is_some() {
+            return Ok(0);
+        }
+
+        let cache_dir = tauri_cache_dir(&self.data_dir, &account.id, mailbox);
+        let stored: HashSet<u32> = maildir::list_uids(&self.data_dir, &account.id, mailbox)
+            .into_iter()
+            .collect();
+        let mut candidates: Vec<u32> = cached_uids(&cache_dir)
+            .into_iter()
+            .filter(|uid| !stored.contains(uid))
+            .collect();
+        candidates.sort_unstable_by(|a, b| b.cmp(a));
+
+        // A size limit needs RFC822.SIZE, which the list-view header fetch
+        // leaves out — ask the server for the next window's sizes, ~30 bytes
+        // each. The window walks down the folder from probe to probe, so
+        // messages too large to take don't hide the older mail beneath them.
+        let size_limited = matches!(rule, ArchiveRule::UnderSize { .. });
+        let mut sizes: HashMap<u32, u32> = HashMap::new();
+        if size_limited {
+            let cursor = archive_policy::cursor(&self.app_dir, &account.id, mailbox);
+            candidates = archive_policy::probe_window(&candidates, cursor, ARCHIVE_SIZE_PROBE);
+            sizes.extend(imap::fetch_sizes(session, mailbox, &candidates).await?);
+        }
+
+        let cached = HeaderStore::open(&cache_dir)?;
+        let now = chrono::Utc::now();
+        let mut picked = Vec::new();
+        let mut examined = None;
+        for uid in candidates {
+            examined = Some(uid);
+            let Some(facts) = cached.get(uid).and_then(sidecar_facts) else { continue };
+            let received = archive_policy::received_at(facts.internal_date.as_deref(), facts.date.as_deref());
+            let size = facts.size.or_else(|| sizes.get(&uid).copied());
+            if let Some(size) = size {
+                sizes.insert(uid, size);
+            }
+            if rule.accepts(size, received, now) {
+                picked.push(uid);
+                if picked.len() == ARCHIVE_BATCH {
+                    break;
+                }
+            } else if matches!(rule, ArchiveRule::NewerThan { .. }) && received.is_some() {
+                break; // newest first: everything after this is older still
+            }
+        }
+
+        // Chunks are sized by RFC822.SIZE, so the other rules need it too.
+        let unsized: Vec<u32> = picked.iter().copied().filter(|uid| !sizes.contains_key(uid)).collect();
+        if !unsized.is_empty() {
+            sizes.extend(imap::fetch_sizes(session, mailbox, &unsized).await?);
+        }
+
+        // The cap is checked again after every chunk: one batch of large
+        // bodies would otherwise run well past it.
+        let mut written = 0;
+        let mut fetched = 0;
+        let mut capped = false;
+        for chunk in archive_chunks(&picked, &sizes) {
+            let messages = imap::fetch_raw_by_uids(session, mailbox, chunk).await?;
+            for msg in &messages {
+                match maildir::store(&self.data_dir, &account.id, mailbox, msg.uid, &msg.raw, &msg.flags) {
+                    Ok(_) => written += 1,
+                    Err(e) => warn!("[archive] UID {} in {}: {}", msg.uid, mailbox, e),
+                }
+            }
+            fetched += chunk.len();
+            if fetched < picked.len() && self.transfer_cap_reached(account).await.is_some() {
+                capped = true;
+                break;
+            }
+        }
+
+        // The next probe goes on below what this one looked at — or, cut
+        // short by the cap, from the first body it didn't fetch.
+        if size_limited {
+            let next = if capped { picked.get(fetched).map(|uid| uid + 1) } else { examined };
+            if let Some(next) = next {
+                if let Err(e) = archive_policy::set_cursor(&self.app_dir, &account.id, mailbox, next) {
+                    warn!("[archive] {}", e);
+                }
+            }
+        }
+        if !picked.is_empty() {
+            info!("[archive] {} ({}): stored {} of {} bodies", account.email, mailbox, written, picked.len());
+        }
+        Ok(written)
+    }
+
+    /// `archive_bodies` for a Graph account: each body is the message's MIME
+    /// from `/messages/{id}/$value`, filed under the uid the ledger gave it.
+    /// Graph's delta leaves out the size, so an `underSize` rule takes nothing.
+    async fn archive_graph_bodies(
+        &self,
+        client: &graph::GraphClient,
+        account: &SyncAccount,
+        mailbox: &str,
+    ) -> Result<usize, String> {
+        let policy = archive_policy::load(&self.app_dir);
+        let rule = policy.rule_for(&account.id, mailbox);
+        if *rule == ArchiveRule::Off || self.transfer_cap_reached(account).await.is_some() {
+            return Ok(0);
+        }
+
+        let cache_dir = tauri_cache_dir(&self.data_dir, &account.id, mailbox);
+        let stored: HashSet<u32> = maildir::list_uids(&self.data_dir, &account.id, mailbox)
+            .into_iter()
+            .collect();
+        let mut candidates: Vec<u32> = cached_uids(&cache_dir)
+            .into_iter()
+            .filter(|uid| !stored.contains(uid))
+            .collect();
+        candidates.sort_unstable_by(|a, b| b.cmp(a));
+
+        let cached = HeaderStore::open(&cache_dir)?;
+        let now = chrono::Utc::now();
+        let mut picked = Vec::new();
+        for uid in candidates {
+            let Some(header) = cached.get(uid) else { continue };
+            let Some(facts) = sidecar_facts(header) else { continue };
+            let Some(graph_id) = header.get("_graphId").and_then(|v| v.as_str()) else { continue };
+            let received = archive_policy::received_at(facts.internal_date.as_deref(), facts.date.as_deref());
+            if rule.accepts(facts.size, received, now) {
+                let flags: Vec<String> = header
+                    .get("flags")
+                    .and_then(|f| serde_json::from_value(f.clone()).ok())
+                    .unwrap_or_default();
+                picked.push((uid, graph_id.to_string(), flags));
+                if picked.len() == ARCHIVE_BATCH {
+                    break;
+                }
+            } else if matches!(rule, ArchiveRule::NewerThan { .. }) && received.is_some() {
+                break; // newest first: everything after this is older still
+            }
+        }
+
+        // Graph bytes bypass the IMAP byte counters; count them here so the
+        // cap, checked again after every chunk, sees them.
+        let counters = transfer_stats::global().counters(&account.imap_config.email);
+        let mut written = 0;
+        for (i, (uid, graph_id, flags)) in picked.iter().enumerate() {
+            if i > 0 && i % ARCHIVE_CHUNK == 0 && self.transfer_cap_reached(account).await.is_some() {
+                break;
+            }
+            let raw = client.get_mime_content(graph_id).await?;
+            counters.count_down(raw.len() as u64);
+            match maildir::store(&self.data_dir, &account.id, mailbox, *uid, &raw, flags) {
+                Ok(_) => written += 1,
+                Err(e) => warn!("[archive] UID {} in {}: {}", uid, mailbox, e),
+            }
+        }
+        if !picked.is_empty() {
+            info!("[archive] {} ({}): stored {} of {} bodies", account.email, mailbox, written, picked.len());
+        }
+        Ok(written)
+    }
+
+    /// EMAILID / THREADID for freshly fetched headers when the server has
+    /// OBJECTID, noted on any vault copies of them as well. A no-op elsewhere.
+    /// `Err` leaves the session unusable; the headers just go without ids.
+    async fn object_ids(
+        &self,
+        session: &mut imap::ImapSession,
+        account: &SyncAccount,
+        mailbox: &str,
+        headers: &mut [ImapEmailHeader],
+    ) -> Result<(), String> {
+        if headers.is_empty() || !self.pool.has_capability(&account.imap_config, objectid::OBJECTID).await {
+            return Ok(());
+        }
+        let uids: Vec<u32> = headers.iter().map(|h| h.uid).collect();
+        let ids = objectid::fetch_object_ids(session, mailbox, &imap::compress_uid_ranges(&uids)).await?;
+        objectid::apply(headers, &ids);
+        let noted: Vec<(u32, String, Option<String>)> =
+            ids.into_iter().map(|i| (i.uid, i.email_id, i.thread_id)).collect();
+        maildir::note_object_ids(&self.data_dir, &account.id, mailbox, &noted);
+        Ok(())
+    }
+
+    /// Copy EMAILIDs from the sidecars onto vault copies stored without one —
+    /// by the app, or before the headers had them. Cheap once they all do.
+    fn note_vault_object_ids(&self, account_id: &str, mailbox: &str) {
+        let cur = maildir::cur_path(&self.data_dir, account_id, mailbox);
+        let missing = mailvault_core::uid_index::without_object_ids(&cur);
+        if missing.is_empty() {
+            return;
+        }
+        let Ok(store) = HeaderStore::open(&tauri_cache_dir(&self.data_dir, account_id, mailbox)) else { return };
+        let ids: Vec<(u32, String, Option<String>)> = missing
+            .into_iter()
+            .filter_map(|uid| {
+                let h = store.get(uid)?;
+                let email_id = h.get("emailId")?.as_str()?.to_string();
+                let thread_id = h.get("objectThreadId").and_then(|t| t.as_str()).map(str::to_string);
+                Some((uid, email_id, thread_id))
+            })
+            .collect();
+        if !ids.is_empty() {
+            let noted = maildir::note_object_ids(&self.data_dir, account_id, mailbox, &ids);
+            info!("[sync] Noted EMAILIDs on {} vault messages in {}", noted, mailbox);
+        }
+    }
+
+    /// Fetch `uids` from the server again and store them over whatever the
+    /// vault has — how `vault.verify` replaces a damaged copy. Returns the uids
+    /// stored; one the server no longer has is simply left out.
+    pub async fn refetch(&self, account: &SyncAccount, mailbox: &str, uids: &[u32]) -> Result<Vec<u32>, String> {
+        let config = &account.imap_config;
+        if graph_sync::is_graph(config) {
+            return Err("Re-fetching by UID isn't supported for Graph accounts".to_string());
+        }
+        let guard = self.pool.get_background(config).await?;
+        let PooledSessionGuard { mut session, last_selected: _, _permit } = guard;
+
+        let fetched = imap::fetch_raw_by_uids(&mut session, mailbox, uids).await;
+
+        let guard = PooledSessionGuard {
+            session,
+            last_selected: Some(mailbox.to_string()),
+            _permit,
+        };
+        match &fetched {
+            Ok(_) => self.pool.return_background(config, guard).await,
+            Err(_) => self.pool.discard(config, guard).await,
+        }
+
+        let mut stored = Vec::new();
+        for msg in fetched? {
+            match maildir::store(&self.data_dir, &account.id, mailbox, msg.uid, &msg.raw, &msg.flags) {
+                Ok(_) => stored.push(msg.uid),
+                Err(e) => warn!("[refetch] UID {} in {}: {}", msg.uid, mailbox, e),
+            }
+        }
+        info!("[refetch] {} ({}): stored {} of {} messages", account.email, mailbox, stored.len(), uids.len());
+        Ok(stored)
+    }
+
     /// Get current sync state for all accounts.
     pub async fn get_states(&self) -> Vec<SyncState> {
         self.states.lock().await.values().cloned().collect()
@@ -598,14 +1522,18 @@ impl SyncEngine {
 
         let mut written = 0usize;
         for chunk in missing.chunks(BACKFILL_CHUNK) {
-            let (headers, _total) = imap::fetch_headers_by_uids(session, mailbox, chunk).await?;
+            let (mut headers, _total) = imap::fetch_headers_by_uids(session, mailbox, chunk).await?;
             if headers.is_empty() {
                 warn!("[backfill] Empty response for a {}-UID chunk — stopping", chunk.len());
                 break;
             }
+            let ids = self.object_ids(session, account, mailbox, &mut headers).await;
             write_headers(&cache_dir, &headers)?;
             self.contacts.observe_headers(account.id.as_str(), mailbox, &headers);
+            threading::observe_headers(&self.data_dir, account.id.as_str(), mailbox, &headers);
             written += headers.len();
+            // What landed is kept; the session, after a failed raw read, isn't.
+            ids?;
             info!(
                 "[backfill] {} ({}): {}/{} headers cached",
                 account.email, mailbox, written, missing.len()
@@ -614,8 +1542,137 @@ impl SyncEngine {
 
         Ok(written)
     }
+
+    /// Keep a dedicated connection open on this account's INBOX and delta-sync
+    /// it whenever the server reports a change, so new mail lands without the
+    /// app having to poll. Idempotent: a running watcher just takes the fresh
+    /// account config.
+    ///
+    /// The connection is made directly, never checked out of the pool — an
+    /// idling session can't carry other commands, and holding a background
+    /// slot for half an hour would starve sync and backfill.
+    pub async fn watch_account(self: &Arc<Self>, account: &SyncAccount) {
+        // Graph has no IDLE; the scheduler's delta rounds keep it current.
+        if graph_sync::is_graph(&account.imap_config) {
+            return;
+        }
+        let mut idlers = self.idlers.lock().await;
+        if let Some(watch) = idlers.get_mut(&account.id) {
+            if !watch.task.is_finished() {
+                watch.account = account.clone();
+                return;
+            }
+        }
+        info!("[idle] Watching INBOX for {}", account.email);
+        let task = tokio::spawn(Arc::clone(self).idle_loop(account.id.clone()));
+        idlers.insert(account.id.clone(), IdleWatch { account: account.clone(), task });
+    }
+
+    /// Stop watching an account (removed, or the app asked). The connection is
+    /// dropped rather than logged out: it is mid-IDLE, and the server reaps it.
+    pub async fn unwatch_account(&self, account_id: &str) -> bool {
+        match self.idlers.lock().await.remove(account_id) {
+            Some(watch) => {
+                watch.task.abort();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Stop every watcher — daemon shutdown.
+    pub async fn unwatch_all(&self) {
+        for (_, watch) in self.idlers.lock().await.drain() {
+            watch.task.abort();
+        }
+    }
+
+    pub async fn is_watching(&self, account_id: &str) -> bool {
+        self.idlers
+            .lock()
+            .await
+            .get(account_id)
+            .is_some_and(|w| !w.task.is_finished())
+    }
+
+    /// The latest config for a watched account, or `None` once it's unwatched.
+    async fn watched_account(&self, account_id: &str) -> Option<SyncAccount> {
+        self.idlers.lock().await.get(account_id).map(|w| w.account.clone())
+    }
+
+    /// Connect, watch, and reconnect with backoff until unwatched.
+    async fn idle_loop(self: Arc<Self>, account_id: String) {
+        let mut backoff = IDLE_RETRY_MIN;
+        let mut first = true;
+        while let Some(account) = self.watched_account(&account_id).await {
+            // Anything that arrived while we were disconnected never produced
+            // an event — catch up before idling again. The first connect skips
+            // this: `sync.now` started a sync of its own alongside us.
+            let catch_up = !first;
+            first = false;
+            if let Err(e) = self.idle_session(&account, catch_up, &mut backoff).await {
+                warn!(
+                    "[idle] Watcher for {} dropped: {} — reconnecting in {}s",
+                    account.email, e, backoff.as_secs()
+                );
+            }
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(IDLE_RETRY_MAX);
+        }
+    }
+
+    /// One connection's worth of watching. Only returns on error.
+    async fn idle_session(
+        &self,
+        account: &SyncAccount,
+        catch_up: bool,
+        backoff: &mut Duration,
+    ) -> Result<(), String> {
+        let config = &account.imap_config;
+        let mut session = imap::create_imap_session(config, &self.pool).await?;
+        imap::select_mailbox(&mut session, "INBOX").await?;
+        *backoff = IDLE_RETRY_MIN;
+
+        if catch_up {
+            self.sync_on_change(&account.id).await;
+        }
+
+        if !self.pool.has_capability(config, "IDLE").await {
+            info!("[idle] {} has no IDLE — polling INBOX with NOOP", account.email);
+            loop {
+                tokio::time::sleep(NOOP_POLL_INTERVAL).await;
+                if imap::noop_poll(&mut session).await? == imap::IdleEvent::Changed {
+                    self.sync_on_change(&account.id).await;
+                }
+            }
+        }
+
+        loop {
+            let (next, event) = imap::idle_wait(session, imap::IDLE_REFRESH).await?;
+            session = next;
+            if event == imap::IdleEvent::Changed {
+                self.sync_on_change(&account.id).await;
+            }
+        }
+    }
+
+    /// Delta-sync INBOX after a push (All Mail on Gmail, as `do_sync` maps
+    /// it). Runs on the pool like any other sync;
+    /// the watcher's own session stays selected and goes straight back to idling.
+    async fn sync_on_change(&self, account_id: &str) {
+        let Some(account) = self.watched_account(account_id).await else { return };
+        info!("[idle] INBOX changed for {} — syncing", account.email);
+        self.sync_account(&account, "INBOX").await;
+    }
 }
 
+/// Reconnect backoff for a dropped watcher, doubling up to the cap.
+const IDLE_RETRY_MIN: Duration = Duration::from_secs(5);
+const IDLE_RETRY_MAX: Duration = Duration::from_secs(300);
+
+/// How often a watcher NOOPs when the server has no IDLE.
+const NOOP_POLL_INTERVAL: Duration = Duration::from_secs(120);
+
 // ── Soft daily transfer cap ─────────────────────────────────────────────────
 
 const MB: u64 = 1024 * 1024;
@@ -659,19 +1716,212 @@ fn read_transfer_limits(app_dir: &Path, account_id: &str) -> Option<TransferLimi
     serde_json::from_value(entry.clone()).ok()
 }
 
-// ── Tauri-compatible cache format ────────────────────────────────────────────
-// Matches the sidecar format used by save_email_cache / load_email_cache_partial
-// in src-tauri/src/main.rs so the app reads daemon-written cache natively.
+// ── Tauri-compatible cache format ────────────────────────────────────────────
+// Matches the sidecar format used by save_email_cache / load_email_cache_partial
+// in src-tauri/src/main.rs so the app reads daemon-written cache natively.
+
+pub(crate) fn tauri_cache_dir(data_dir: &Path, account_id: &str, mailbox: &str) -> PathBuf {
+    data_dir.join("email_cache").join(cache_base_name(account_id, mailbox))
+}
+
+/// `SyncResult.mailbox` for an all-folders run, and the scheduler's folder
+/// key for "every folder".
+pub const ALL_FOLDERS: &str = "*";
+
+/// What the last all-folders run saw for an account, kept next to its sidecar
+/// caches (and cleared with them) as `email_cache/<account>.folders.json`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FolderRegistry {
+    #[serde(default)]
+    pub folders: Vec<KnownFolder>,
+    /// Folders deleted on the server. Their Maildir is left untouched.
+    #[serde(default)]
+    pub removed: Vec<RemovedFolder>,
+    /// Gmail only: the All Mail folder, the one folder whose bodies are
+    /// archived and whose messages the label index describes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gmail_all_mail: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KnownFolder {
+    pub path: String,
+    /// Epoch ms of the last sync that found new mail or flag changes here.
+    #[serde(default)]
+    pub last_change_at: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RemovedFolder {
+    pub path: String,
+    pub removed_at: u64,
+}
+
+impl FolderRegistry {
+    fn last_change_at(&self, path: &str) -> Option<u64> {
+        self.folders.iter().find(|f| f.path == path).and_then(|f| f.last_change_at)
+    }
+
+    fn touch(&mut self, path: &str, at: u64) {
+        if let Some(f) = self.folders.iter_mut().find(|f| f.path == path) {
+            f.last_change_at = Some(at);
+        }
+    }
+
+    fn rename(&mut self, old: &str, new: &str) {
+        if let Some(f) = self.folders.iter_mut().find(|f| f.path == old) {
+            f.path = new.to_string();
+        }
+    }
+
+    fn remove(&mut self, path: &str, at: u64) {
+        self.folders.retain(|f| f.path != path);
+        self.removed.retain(|r| r.path != path);
+        self.removed.push(RemovedFolder { path: path.to_string(), removed_at: at });
+    }
+
+    /// Make the folder list exactly `paths`, keeping what is known about each.
+    /// A previously removed folder that is back is no longer removed.
+    fn set_present<'a>(&mut self, paths: impl Iterator<Item = &'a str>) {
+        let mut folders = Vec::new();
+        for path in paths {
+            let last_change_at = self.last_change_at(path);
+            folders.push(KnownFolder { path: path.to_string(), last_change_at });
+        }
+        self.removed.retain(|r| !folders.iter().any(|f| f.path == r.path));
+        self.folders = folders;
+    }
+}
+
+fn folder_registry_path(data_dir: &Path, account_id: &str) -> PathBuf {
+    data_dir.join("email_cache").join(format!(
+        "{}.folders.json",
+        account_id.replace(|c: char| !c.is_alphanumeric(), "_")
+    ))
+}
+
+fn read_folder_registry(data_dir: &Path, account_id: &str) -> FolderRegistry {
+    fs::read_to_string(folder_registry_path(data_dir, account_id))
+        .ok()
+        .and_then(|raw| serde_json::from_str(&raw).ok())
+        .unwrap_or_default()
+}
+
+fn write_folder_registry(data_dir: &Path, account_id: &str, registry: &FolderRegistry) -> Result<(), String> {
+    let path = folder_registry_path(data_dir, account_id);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| format!("Failed to create {:?}: {}", parent, e))?;
+    }
+    let json = serde_json::to_string_pretty(registry)
+        .map_err(|e| format!("Failed to serialize folder registry: {}", e))?;
+    fs::write(&path, json).map_err(|e| format!("Failed to write {:?}: {}", path, e))
+}
+
+/// Move header caches written under raw modified UTF-7 mailbox names to the
+/// decoded names sync uses now (see `header_store::migrate_decode_cache_dirs`),
+/// and decode the folder registries that still list the raw names. The
+/// mailboxes come from the vault and from those registries, which also know
+/// folders whose bodies were never archived.
+pub fn migrate_decode_cache_dirs(data_dir: &Path, mail_dir: &Path) -> maildir::NameMigrationStats {
+    let cache_root = data_dir.join("email_cache");
+    let mut mailboxes = maildir::mailbox_keys(mail_dir);
+    let registries = fs::read_dir(&cache_root).map(|d| d.flatten().collect::<Vec<_>>()).unwrap_or_default();
+    for entry in registries {
+        let file = entry.file_name().to_string_lossy().to_string();
+        let Some(account_id) = file.strip_suffix(".folders.json") else { continue };
+        let mut registry = read_folder_registry(data_dir, account_id);
+        mailboxes.extend(registry.folders.iter().map(|f| (account_id.to_string(), f.path.clone())));
+        if !registry.folders.iter().any(|f| imap::utf7::is_encoded(&f.path)) {
+            continue;
+        }
+        for f in &mut registry.folders {
+            f.path = imap::utf7::decode(&f.path);
+        }
+        for r in &mut registry.removed {
+            r.path = imap::utf7::decode(&r.path);
+        }
+        if let Some(all) = registry.gmail_all_mail.as_mut() {
+            *all = imap::utf7::decode(all);
+        }
+        if let Err(e) = write_folder_registry(data_dir, account_id, &registry) {
+            warn!("migrate_decode_cache_dirs: {}", e);
+        }
+    }
+    mailvault_core::header_store::migrate_decode_cache_dirs(&cache_root, &mailboxes)
+}
+
+/// Gmail labels of each cached All Mail message, by UID, kept next to the
+/// folder registry as `email_cache/<account>.labels.json`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LabelIndex {
+    #[serde(default)]
+    pub mailbox: String,
+    #[serde(default)]
+    pub uid_validity: Option<u32>,
+    /// All Mail's HIGHESTMODSEQ when the index was last refreshed.
+    #[serde(default)]
+    pub highest_modseq: Option<u64>,
+    #[serde(default)]
+    pub messages: BTreeMap<u32, LabelEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LabelEntry {
+    pub msg_id: String,
+    #[serde(default)]
+    pub thread_id: Option<String>,
+    #[serde(default)]
+    pub labels: Vec<String>,
+}
+
+impl LabelIndex {
+    /// How many messages carry each label, by label name.
+    pub fn counts(&self) -> BTreeMap<&str, usize> {
+        let mut counts = BTreeMap::new();
+        for entry in self.messages.values() {
+            for label in &entry.labels {
+                *counts.entry(label.as_str()).or_insert(0) += 1;
+            }
+        }
+        counts
+    }
+
+    /// All Mail UIDs of the messages carrying `label`.
+    pub fn uids_with(&self, label: &str) -> Vec<u32> {
+        self.messages
+            .iter()
+            .filter(|(_, e)| e.labels.iter().any(|l| l == label))
+            .map(|(uid, _)| *uid)
+            .collect()
+    }
+}
 
-fn cache_base_name(account_id: &str, mailbox: &str) -> String {
-    format!("{}_{}",
-        account_id.replace(|c: char| !c.is_alphanumeric(), "_"),
-        mailbox.replace(|c: char| !c.is_alphanumeric(), "_"),
-    )
+fn label_index_path(data_dir: &Path, account_id: &str) -> PathBuf {
+    data_dir.join("email_cache").join(format!(
+        "{}.labels.json",
+        account_id.replace(|c: char| !c.is_alphanumeric(), "_")
+    ))
 }
 
-fn tauri_cache_dir(data_dir: &Path, account_id: &str, mailbox: &str) -> PathBuf {
-    data_dir.join("email_cache").join(cache_base_name(account_id, mailbox))
+fn read_label_index(data_dir: &Path, account_id: &str) -> LabelIndex {
+    fs::read_to_string(label_index_path(data_dir, account_id))
+        .ok()
+        .and_then(|raw| serde_json::from_str(&raw).ok())
+        .unwrap_or_default()
+}
+
+fn write_label_index(data_dir: &Path, account_id: &str, index: &LabelIndex) -> Result<(), String> {
+    let path = label_index_path(data_dir, account_id);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| format!("Failed to create {:?}: {}", parent, e))?;
+    }
+    let json = serde_json::to_string(index).map_err(|e| format!("Failed to serialize label index: {}", e))?;
+    fs::write(&path, json).map_err(|e| format!("Failed to write {:?}: {}", path, e))
 }
 
 /// Sync metadata read back from the sidecar cache's _meta.json.
@@ -696,6 +1946,50 @@ struct SyncDelta {
     session_dirty: bool,
 }
 
+/// Bodies the archive policy fetches per folder per sync.
+const ARCHIVE_BATCH: usize = 100;
+
+/// At most this many bodies, and (past the first) this many bytes, per
+/// archive FETCH — what one chunk holds in memory before it is stored.
+const ARCHIVE_CHUNK: usize = 10;
+const ARCHIVE_CHUNK_BYTES: u64 = 8 * MB;
+
+/// `uids` cut into FETCH chunks by `sizes`. A body of unknown size fills a
+/// chunk alone, as does one over the byte budget.
+fn archive_chunks<'a>(uids: &'a [u32], sizes: &HashMap<u32, u32>) -> Vec<&'a [u32]> {
+    let mut chunks = Vec::new();
+    let (mut start, mut bytes) = (0, 0u64);
+    for (i, uid) in uids.iter().enumerate() {
+        let size = sizes.get(uid).map_or(ARCHIVE_CHUNK_BYTES, |s| *s as u64);
+        if i > start && (i - start == ARCHIVE_CHUNK || bytes + size > ARCHIVE_CHUNK_BYTES) {
+            chunks.push(&uids[start..i]);
+            (start, bytes) = (i, 0);
+        }
+        bytes += size;
+    }
+    if start < uids.len() {
+        chunks.push(&uids[start..]);
+    }
+    chunks
+}
+
+/// Candidates whose size a size-limited policy looks up per sync. Too-large
+/// messages stay candidates, so this bounds how far past them one tick looks.
+const ARCHIVE_SIZE_PROBE: usize = 1000;
+
+/// The sidecar fields the archive policy tests.
+#[derive(Deserialize)]
+struct SidecarFacts {
+    size: Option<u32>,
+    date: Option<String>,
+    #[serde(rename = "internalDate")]
+    internal_date: Option<String>,
+}
+
+fn sidecar_facts(header: &serde_json::Value) -> Option<SidecarFacts> {
+    SidecarFacts::deserialize(header).ok()
+}
+
 /// A UIDNEXT jump larger than this is cheaper to resolve with a page fetch
 /// than with a sparse UID range fetch.
 const MAX_DELTA_UID_GAP: u32 = 500;
@@ -730,27 +2024,18 @@ fn read_tauri_cache_meta(cache_dir: &Path) -> Option<CachedMeta> {
     })
 }
 
-fn count_sidecars(cache_dir: &Path) -> usize {
-    fs::read_dir(cache_dir).ok()
-        .map(|entries| entries.flatten().filter(|e| {
-            let name = e.file_name().to_string_lossy().to_string();
-            name.ends_with(".json") && name != "_meta.json"
-        }).count())
-        .unwrap_or(0)
+/// Headers in the mailbox's header store. `_meta.json` and, on Graph
+/// accounts, the uid ledger and delta state sit beside it and aren't counted.
+pub(crate) fn count_sidecars(cache_dir: &Path) -> usize {
+    HeaderStore::open(cache_dir).map(|s| s.len()).unwrap_or(0)
 }
 
-/// UIDs that already have a sidecar on disk.
+/// UIDs that already have a cached header.
 fn cached_uids(cache_dir: &Path) -> HashSet<u32> {
-    let Ok(entries) = fs::read_dir(cache_dir) else { return HashSet::new() };
-    entries.flatten()
-        .filter_map(|e| {
-            let name = e.file_name().to_string_lossy().to_string();
-            name.strip_suffix(".json").and_then(|u| u.parse::<u32>().ok())
-        })
-        .collect()
+    HeaderStore::open(cache_dir).map(|s| s.uids().collect()).unwrap_or_default()
 }
 
-fn write_cache_meta(
+pub(crate) fn write_cache_meta(
     cache_dir: &Path,
     total_emails: u32,
     uid_validity: Option<u32>,
@@ -782,58 +2067,81 @@ fn write_cache_meta_full(
     fs::write(cache_dir.join("_meta.json"), &meta_json).map_err(|e| format!("Write meta: {}", e))
 }
 
-/// Write per-UID header sidecars, overwriting existing ones — a freshly fetched
-/// header is authoritative, and on servers without CONDSTORE this is the only
-/// thing that refreshes flags on already-cached messages.
+/// Write headers to the header store, overwriting existing ones — a freshly
+/// fetched header is authoritative, and on servers without CONDSTORE this is
+/// the only thing that refreshes flags on already-cached messages.
 fn write_headers(cache_dir: &Path, headers: &[ImapEmailHeader]) -> Result<(), String> {
-    fs::create_dir_all(cache_dir).map_err(|e| format!("Failed to create cache dir: {}", e))?;
-    for header in headers {
-        let email_json = serde_json::to_string(header).map_err(|e| format!("Serialize email {}: {}", header.uid, e))?;
-        fs::write(cache_dir.join(format!("{}.json", header.uid)), &email_json)
-            .map_err(|e| format!("Write email {}: {}", header.uid, e))?;
-    }
+    let values = headers
+        .iter()
+        .map(|h| serde_json::to_value(h).map_err(|e| format!("Serialize email {}: {}", h.uid, e)))
+        .collect::<Result<Vec<_>, _>>()?;
+    HeaderStore::open(cache_dir)?.put(&values)?;
     info!("[sync] Cache written: {} headers", headers.len());
     Ok(())
 }
 
-/// Patch the `flags` field of existing sidecars in place. Returns how many changed.
-/// UIDs without a sidecar are ignored — they arrive via the new-header fetch.
+/// Replace the `flags` of cached headers. Returns how many changed.
+/// UIDs without a cached header are ignored — they arrive via the new-header fetch.
 fn patch_sidecar_flags(cache_dir: &Path, changes: &[(u32, Vec<String>)]) -> usize {
-    let mut patched = 0;
-    for (uid, flags) in changes {
-        let path = cache_dir.join(format!("{}.json", uid));
-        let Ok(data) = fs::read_to_string(&path) else { continue };
-        let Ok(mut email) = serde_json::from_str::<serde_json::Value>(&data) else { continue };
-        let new_flags = serde_json::json!(flags);
-        if email.get("flags") == Some(&new_flags) { continue }
-        let Some(obj) = email.as_object_mut() else { continue };
-        obj.insert("flags".to_string(), new_flags);
-        if let Ok(json) = serde_json::to_string(&email) {
-            if fs::write(&path, json).is_ok() { patched += 1; }
-        }
-    }
-    patched
+    HeaderStore::open(cache_dir)
+        .and_then(|mut store| store.set_flags(changes))
+        .unwrap_or_else(|e| {
+            warn!("[sync] Flag update failed in {:?}: {}", cache_dir, e);
+            0
+        })
 }
 
-/// Delete sidecars whose UID is no longer on the server. Returns how many.
-fn prune_sidecars(cache_dir: &Path, server_uids: &[u32]) -> usize {
-    let live: std::collections::HashSet<u32> = server_uids.iter().copied().collect();
-    let Ok(entries) = fs::read_dir(cache_dir) else { return 0 };
-    let mut pruned = 0;
-    for entry in entries.flatten() {
-        let name = entry.file_name().to_string_lossy().to_string();
-        if name == "_meta.json" { continue }
-        let Some(uid) = name.strip_suffix(".json").and_then(|u| u.parse::<u32>().ok()) else { continue };
-        if !live.contains(&uid) && fs::remove_file(entry.path()).is_ok() {
-            pruned += 1;
-        }
-    }
-    pruned
+/// Set `labels`, `gmailMsgId` and `gmailThreadId` on cached headers, as
+/// `EmailHeader` names them. Returns how many were written.
+fn patch_sidecar_labels(cache_dir: &Path, changes: &[(u32, LabelEntry)]) -> usize {
+    let fields: Vec<(u32, serde_json::Map<String, serde_json::Value>)> = changes
+        .iter()
+        .map(|(uid, entry)| {
+            let mut obj = serde_json::Map::new();
+            obj.insert("labels".to_string(), serde_json::json!(entry.labels));
+            obj.insert("gmailMsgId".to_string(), serde_json::json!(entry.msg_id));
+            obj.insert("gmailThreadId".to_string(), serde_json::json!(entry.thread_id));
+            (*uid, obj)
+        })
+        .collect();
+    HeaderStore::open(cache_dir)
+        .and_then(|mut store| store.merge(&fields))
+        .unwrap_or_else(|e| {
+            warn!("[sync] Label update failed in {:?}: {}", cache_dir, e);
+            0
+        })
+}
+
+/// Drop the cached headers for these UIDs — the server reported them
+/// expunged. Returns how many existed.
+pub(crate) fn remove_sidecars(cache_dir: &Path, uids: &[u32]) -> usize {
+    HeaderStore::open(cache_dir)
+        .and_then(|mut store| store.remove(uids))
+        .unwrap_or_else(|e| {
+            warn!("[sync] Header removal failed in {:?}: {}", cache_dir, e);
+            0
+        })
+}
+
+/// Drop cached headers whose UID is no longer on the server. Returns the
+/// UIDs dropped.
+pub(crate) fn prune_sidecars(cache_dir: &Path, server_uids: &[u32]) -> Vec<u32> {
+    let live: HashSet<u32> = server_uids.iter().copied().collect();
+    HeaderStore::open(cache_dir)
+        .and_then(|mut store| {
+            let gone: Vec<u32> = store.uids().filter(|uid| !live.contains(uid)).collect();
+            store.remove(&gone).map(|_| gone)
+        })
+        .unwrap_or_else(|e| {
+            warn!("[sync] Header prune failed in {:?}: {}", cache_dir, e);
+            Vec::new()
+        })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_support::{account_for, engine_for, scratch_dir};
 
     #[test]
     fn test_sync_result_serialization() {
@@ -851,11 +2159,9 @@ mod tests {
         assert!(json.contains("\"success\":true"));
     }
 
-    fn scratch_dir(name: &str) -> PathBuf {
-        let dir = std::env::temp_dir().join(format!("mv_sync_test_{}", name));
-        let _ = fs::remove_dir_all(&dir);
-        fs::create_dir_all(&dir).unwrap();
-        dir
+    /// A header as the mailbox's header store holds it.
+    fn cached_header(cache_dir: &Path, uid: u32) -> Option<serde_json::Value> {
+        HeaderStore::open(cache_dir).ok()?.get(uid).cloned()
     }
 
     #[test]
@@ -876,7 +2182,7 @@ mod tests {
         write_cache_meta_full(&dir, 42, Some(7), Some(101), Some(999), Some(1_700_000_000_000)).unwrap();
         assert_eq!(read_tauri_cache_meta(&dir).unwrap().last_reconcile, Some(1_700_000_000_000));
 
-        // Two sidecars, one seen and one unseen.
+        // Two legacy sidecars, one seen and one unseen — folded into the store on first open.
         fs::write(dir.join("10.json"), r#"{"uid":10,"flags":["\\Seen"],"subject":"a"}"#).unwrap();
         fs::write(dir.join("11.json"), r#"{"uid":11,"flags":[],"subject":"b"}"#).unwrap();
         assert_eq!(count_sidecars(&dir), 2);
@@ -888,8 +2194,7 @@ mod tests {
             (99, vec!["\\Seen".to_string()]),
         ]);
         assert_eq!(patched, 1);
-        let uid11: serde_json::Value =
-            serde_json::from_str(&fs::read_to_string(dir.join("11.json")).unwrap()).unwrap();
+        let uid11: serde_json::Value = cached_header(&dir, 11).unwrap();
         assert_eq!(uid11["flags"], serde_json::json!(["\\Seen", "\\Flagged"]));
         assert_eq!(uid11["subject"], "b"); // patch must not clobber other fields
 
@@ -903,9 +2208,9 @@ mod tests {
         assert_eq!(missing, vec![9, 12]);
 
         // Prune: uid 11 was expunged server-side, _meta.json must survive.
-        assert_eq!(prune_sidecars(&dir, &[10]), 1);
-        assert!(dir.join("10.json").exists());
-        assert!(!dir.join("11.json").exists());
+        assert_eq!(prune_sidecars(&dir, &[10]), vec![11]);
+        assert!(cached_header(&dir, 10).is_some());
+        assert!(cached_header(&dir, 11).is_none());
         assert!(dir.join("_meta.json").exists());
 
         fs::remove_dir_all(&dir).unwrap();
@@ -917,12 +2222,7 @@ mod tests {
     #[tokio::test]
     async fn test_gave_up_backfill_does_not_report_as_in_flight() {
         let dir = scratch_dir("backfill_flag");
-        let engine = SyncEngine::new(
-            Arc::new(imap::ImapPool::new()),
-            dir.clone(),
-            dir.clone(),
-            ContactsState::new(dir.clone()),
-        );
+        let engine = engine_for(&dir);
         let key = format!("acc1\u{1}INBOX");
 
         assert!(!engine.is_backfilling("acc1").await);
@@ -936,6 +2236,46 @@ mod tests {
         fs::remove_dir_all(&dir).unwrap();
     }
 
+    /// Alerts raised while nobody waits must all reach the next `quota.wait`,
+    /// not just the last one — two accounts crossing in one sync pass is the
+    /// ordinary case.
+    #[tokio::test]
+    async fn test_quota_wait_returns_every_alert_since_the_last_seen() {
+        let dir = scratch_dir("quota_ring");
+        let engine = engine_for(&dir);
+        let quota: MailboxQuota =
+            serde_json::from_value(serde_json::json!({"source": "imap", "usedBytes": 90, "limitBytes": 100})).unwrap();
+        let alert = |account_id: &str| QuotaAlert {
+            seq: 0,
+            account_id: account_id.to_string(),
+            threshold: 90,
+            percent_used: 90.0,
+            bytes_to_free: None,
+            quota: quota.clone(),
+        };
+
+        engine.raise_quota_alert(alert("acc1"));
+        engine.raise_quota_alert(alert("acc2"));
+        engine.raise_quota_alert(alert("acc3"));
+        let all = engine.wait_for_quota_alerts(0, 10).await;
+        let seen: Vec<(u64, &str)> = all.iter().map(|a| (a.seq, a.account_id.as_str())).collect();
+        assert_eq!(seen, vec![(1, "acc1"), (2, "acc2"), (3, "acc3")]);
+        let newer = engine.wait_for_quota_alerts(2, 10).await;
+        assert_eq!(newer.iter().map(|a| a.seq).collect::<Vec<_>>(), vec![3]);
+        assert!(engine.wait_for_quota_alerts(3, 10).await.is_empty());
+
+        // The ring is bounded: the oldest go, and numbering carries on.
+        for _ in 0..QUOTA_ALERT_RING {
+            engine.raise_quota_alert(alert("acc1"));
+        }
+        let kept = engine.wait_for_quota_alerts(0, 10).await;
+        assert_eq!(kept.len(), QUOTA_ALERT_RING);
+        assert_eq!(kept.first().map(|a| a.seq), Some(4));
+        assert_eq!(kept.last().map(|a| a.seq), Some(3 + QUOTA_ALERT_RING as u64));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
     /// The cap must read both the settings blob the app writes and the stat
     /// files, and must stay out of the way until it is switched on and spent.
     #[tokio::test]
@@ -1010,6 +2350,9 @@ mod tests {
             last_error: None,
             new_emails: 0,
             total_emails: 0,
+            current_folder: None,
+            folders_done: 0,
+            folders_total: 0,
         };
         let json = serde_json::to_string(&state).unwrap();
         assert!(json.contains("\"Syncing\""));
@@ -1025,30 +2368,6 @@ mod tests {
     use mock_imap::state::{synthetic_mailbox, Mailbox};
     use mock_imap::{Action, MockImap, Scenario, Trigger};
 
-    fn engine_for(dir: &Path) -> SyncEngine {
-        SyncEngine::new(
-            Arc::new(imap::ImapPool::new()),
-            dir.to_path_buf(),
-            dir.to_path_buf(),
-            ContactsState::new(dir.to_path_buf()),
-        )
-    }
-
-    fn account_for(server: &MockImap) -> SyncAccount {
-        std::env::set_var("MAILVAULT_IMAP_PLAINTEXT", "1");
-        serde_json::from_value(serde_json::json!({
-            "id": "acc1",
-            "email": "user@example.com",
-            "imapConfig": {
-                "email": "user@example.com",
-                "password": "hunter2",
-                "imapHost": server.host(),
-                "imapPort": server.port(),
-            }
-        }))
-        .expect("build SyncAccount")
-    }
-
     fn cache_dir_for(dir: &Path) -> PathBuf {
         tauri_cache_dir(dir, "acc1", "INBOX")
     }
@@ -1208,8 +2527,8 @@ mod tests {
         assert!(result.success, "sync failed: {:?}", result.error);
 
         assert_eq!(count_sidecars(&cache), 17, "expunged messages should be pruned");
-        assert!(!cache.join("6.json").exists());
-        assert!(cache.join("8.json").exists());
+        assert!(cached_header(&cache, 6).is_none());
+        assert!(cached_header(&cache, 8).is_some());
 
         fs::remove_dir_all(&dir).unwrap();
     }
@@ -1293,4 +2612,548 @@ mod tests {
 
         fs::remove_dir_all(&dir).unwrap();
     }
+
+    /// With QRESYNC, deletions come from the SELECT's VANISHED (EARLIER) — the
+    /// warm sync must prune them without a UID SEARCH ALL.
+    #[tokio::test]
+    async fn qresync_prunes_vanished_uids_without_a_uid_listing() {
+        let dir = scratch_dir("qresync_prune");
+        let server = MockImap::start(
+            Scenario::new().with_cap("QRESYNC").mailbox(synthetic_mailbox("INBOX", 10)),
+        );
+        let account = account_for(&server);
+        let engine = engine_for(&dir);
+        let cache = cache_dir_for(&dir);
+
+        engine.sync_account(&account, "INBOX").await;
+        assert_eq!(count_sidecars(&cache), 10);
+
+        server.update(|st| {
+            st.find_mut("INBOX").unwrap().expunge(&[3, 7]);
+        });
+        let before = server.commands().len();
+        let result = engine.sync_account(&account, "INBOX").await;
+        assert!(result.success, "sync failed: {:?}", result.error);
+        assert_eq!(count_sidecars(&cache), 8);
+        assert!(cached_header(&cache, 3).is_none() && cached_header(&cache, 7).is_none());
+
+        let second_pass = server.commands()[before..].join("\n").to_uppercase();
+        assert!(second_pass.contains("QRESYNC (1 "), "expected a QRESYNC SELECT:\n{second_pass}");
+        assert!(
+            !second_pass.contains("SEARCH") && !second_pass.contains("FETCH 1:* (UID)"),
+            "QRESYNC should make the UID listing unnecessary:\n{second_pass}"
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// New mail must reach the cache on its own — the watcher's IDLE sees the
+    /// EXISTS and runs the delta sync, with no `sync.now` from the app.
+    #[tokio::test(flavor = "multi_thread")]
+    async fn idle_watcher_syncs_new_mail_without_being_asked() {
+        let dir = scratch_dir("idle_watch");
+        let server = MockImap::start(Scenario::new().mailbox(synthetic_mailbox("INBOX", 5)));
+        let account = account_for(&server);
+        let engine = Arc::new(engine_for(&dir));
+        let cache = cache_dir_for(&dir);
+
+        engine.sync_account(&account, "INBOX").await;
+        assert_eq!(count_sidecars(&cache), 5);
+
+        engine.watch_account(&account).await;
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(10);
+        while server.count_commands("IDLE") == 0 {
+            assert!(tokio::time::Instant::now() < deadline, "watcher never started IDLE");
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert!(engine.is_watching("acc1").await);
+
+        server.update(|st| {
+            st.find_mut("INBOX").unwrap().add(mock_imap::Message::new(
+                6,
+                "Subject: Pushed\r\nMessage-ID: <pushed@example.com>\r\n\r\nhi\r\n",
+            ));
+        });
+        while count_sidecars(&cache) < 6 {
+            assert!(tokio::time::Instant::now() < deadline, "pushed message never synced");
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+
+        assert!(engine.unwatch_account("acc1").await);
+        assert!(!engine.is_watching("acc1").await);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn all_folders_server() -> MockImap {
+        let mut sent = synthetic_mailbox("Sent", 2).with_uid_validity(20);
+        sent.attrs = vec!["\\HasNoChildren".into(), "\\Sent".into()];
+        MockImap::start(
+            Scenario::new()
+                .mailbox(synthetic_mailbox("Archive", 3).with_uid_validity(10))
+                .mailbox(synthetic_mailbox("INBOX", 4))
+                .mailbox(Mailbox::new("Parent").with_attrs(&["\\Noselect", "\\HasChildren"]))
+                .mailbox(sent)
+                .mailbox(synthetic_mailbox("Work", 2).with_uid_validity(30)),
+        )
+    }
+
+    fn selects(server: &MockImap, from: usize) -> Vec<String> {
+        server.commands()[from..]
+            .iter()
+            .filter_map(|c| {
+                let upper = c.to_uppercase();
+                let at = upper.find(" SELECT ").or_else(|| upper.find(" EXAMINE "))?;
+                let arg = c[at..].trim_start().split_once(' ')?.1;
+                match arg.strip_prefix('"') {
+                    Some(quoted) => Some(quoted.split('"').next()?.to_string()),
+                    None => Some(arg.split_whitespace().next()?.to_string()),
+                }
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn all_folders_sync_inbox_then_sent_then_the_rest() {
+        let dir = scratch_dir("all_folders");
+        let server = all_folders_server();
+        let account = account_for(&server);
+        let engine = engine_for(&dir);
+
+        let result = engine.sync_all_folders(&account).await;
+        assert!(result.success, "sync failed: {:?}", result.error);
+        assert_eq!(result.mailbox, ALL_FOLDERS);
+        assert_eq!(result.total_emails, 4 + 2 + 3 + 2);
+
+        let mut order = selects(&server, 0);
+        order.dedup();
+        assert_eq!(order, vec!["INBOX", "Sent", "Archive", "Work"], "\\Noselect is skipped");
+        for folder in ["INBOX", "Sent", "Archive", "Work"] {
+            assert!(count_sidecars(&tauri_cache_dir(&dir, "acc1", folder)) > 0, "{folder} not cached");
+        }
+
+        let state = engine.get_state("acc1").await.unwrap();
+        assert_eq!((state.folders_done, state.folders_total), (4, 4));
+        assert_eq!(state.status, SyncStatus::Idle);
+
+        // Work gets new mail: next time it goes ahead of the idle Archive.
+        server.update(|st| {
+            st.find_mut("Work").unwrap().add(mock_imap::Message::new(
+                3,
+                "Subject: Busy\r\nMessage-ID: <busy@example.com>\r\n\r\nhi\r\n",
+            ));
+        });
+        engine.sync_all_folders(&account).await;
+        let before = server.commands().len();
+        engine.sync_all_folders(&account).await;
+        let mut order = selects(&server, before);
+        order.dedup();
+        assert_eq!(order, vec!["INBOX", "Sent", "Work", "Archive"]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn renamed_folder_takes_its_cache_and_maildir_along() {
+        let dir = scratch_dir("folder_rename");
+        let server = all_folders_server();
+        let account = account_for(&server);
+        let engine = engine_for(&dir);
+        engine.sync_all_folders(&account).await;
+
+        let old_maildir = mailvault_core::maildir::cur_path(&dir, "acc1", "Work");
+        fs::create_dir_all(&old_maildir).unwrap();
+        fs::write(old_maildir.join("1:2,S:0.eml"), "Subject: kept\r\n\r\n").unwrap();
+
+        server.update(|st| st.find_mut("Work").unwrap().name = "Projects".into());
+        let result = engine.sync_all_folders(&account).await;
+        assert!(result.success, "sync failed: {:?}", result.error);
+
+        assert!(!tauri_cache_dir(&dir, "acc1", "Work").exists());
+        assert_eq!(count_sidecars(&tauri_cache_dir(&dir, "acc1", "Projects")), 2);
+        assert!(!old_maildir.exists());
+        assert!(mailvault_core::maildir::cur_path(&dir, "acc1", "Projects").join("1:2,S:0.eml").exists());
+
+        let registry = engine.folder_registry("acc1");
+        assert!(registry.removed.is_empty(), "a rename is not a removal: {:?}", registry.removed);
+        assert!(registry.folders.iter().any(|f| f.path == "Projects"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn deleted_folder_drops_its_cache_but_keeps_the_mail() {
+        let dir = scratch_dir("folder_delete");
+        let server = all_folders_server();
+        let account = account_for(&server);
+        let engine = engine_for(&dir);
+        engine.sync_all_folders(&account).await;
+
+        let maildir = mailvault_core::maildir::cur_path(&dir, "acc1", "Archive");
+        fs::create_dir_all(&maildir).unwrap();
+        fs::write(maildir.join("1:2,S:0.eml"), "Subject: kept\r\n\r\n").unwrap();
+
+        server.update(|st| st.mailboxes.retain(|m| m.name != "Archive"));
+        let result = engine.sync_all_folders(&account).await;
+        assert!(result.success, "sync failed: {:?}", result.error);
+
+        assert!(!tauri_cache_dir(&dir, "acc1", "Archive").exists());
+        assert!(maildir.join("1:2,S:0.eml").exists(), "the vault never deletes mail");
+        let registry = engine.folder_registry("acc1");
+        assert_eq!(registry.removed.len(), 1);
+        assert_eq!(registry.removed[0].path, "Archive");
+        assert!(!registry.folders.iter().any(|f| f.path == "Archive"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn gmail_syncs_all_mail_once_with_a_label_index() {
+        let dir = scratch_dir("gmail_labels");
+        let mut all = Mailbox::new("[Gmail]/All Mail").with_attrs(&["\\HasNoChildren", "\\All"]);
+        all.add(
+            mock_imap::Message::new(1, "Subject: Trip\r\nMessage-ID: <trip@example.com>\r\n\r\nhi\r\n")
+                .with_gmail(1_278_455_344_230_334_865, 1_278_455_344_230_334_865, &["\\Inbox", "Work"])
+                .with_modseq(5),
+        );
+        all.add(
+            mock_imap::Message::new(2, "Subject: Old\r\nMessage-ID: <old@example.com>\r\n\r\nhi\r\n")
+                .with_gmail(7, 3, &["Work"])
+                .with_modseq(2),
+        );
+        let server = MockImap::start(
+            Scenario::new()
+                .with_cap("X-GM-EXT-1")
+                .mailbox(synthetic_mailbox("INBOX", 1))
+                .mailbox(synthetic_mailbox("Work", 2))
+                .mailbox(Mailbox::new("[Gmail]").with_attrs(&["\\Noselect", "\\HasChildren"]))
+                .mailbox(all)
+                .mailbox(synthetic_mailbox("[Gmail]/Starred", 1).with_attrs(&["\\Flagged"]))
+                .mailbox(synthetic_mailbox("[Gmail]/Trash", 1).with_attrs(&["\\Trash"])),
+        );
+        let account = account_for(&server);
+        let engine = engine_for(&dir);
+        let policy = serde_json::json!({ "defaultRule": { "mode": "all" } });
+        fs::write(dir.join("archive_policy.json"), policy.to_string()).unwrap();
+
+        let result = engine.sync_all_folders(&account).await;
+        assert!(result.success, "sync failed: {:?}", result.error);
+        let mut order = selects(&server, 0);
+        order.dedup();
+        assert_eq!(order, vec!["[Gmail]/All Mail", "[Gmail]/Trash"], "label folders are not synced");
+        assert_eq!(engine.folder_registry("acc1").gmail_all_mail.as_deref(), Some("[Gmail]/All Mail"));
+
+        let index = engine.label_index("acc1");
+        assert_eq!(index.uids_with("Work"), vec![1, 2]);
+        assert_eq!(index.counts().get("\\Inbox"), Some(&1));
+        let cache = tauri_cache_dir(&dir, "acc1", "[Gmail]/All Mail");
+        let sidecar: serde_json::Value = cached_header(&cache, 1).unwrap();
+        assert_eq!(sidecar["labels"], serde_json::json!(["\\Inbox", "Work"]));
+        assert_eq!(sidecar["gmailMsgId"], "1278455344230334865");
+        assert_eq!(maildir::list_uids(&dir, "acc1", "[Gmail]/All Mail"), vec![1, 2]);
+
+        // A single-folder sync of a label view syncs All Mail instead.
+        let result = engine.sync_account(&account, "INBOX").await;
+        assert_eq!(result.mailbox, "[Gmail]/All Mail");
+        assert!(maildir::list_uids(&dir, "acc1", "INBOX").is_empty());
+        assert!(!tauri_cache_dir(&dir, "acc1", "INBOX").exists());
+
+        // Relabelling bumps MODSEQ; the next run asks only for what changed.
+        server.update(|st| {
+            let all = st.find_mut("[Gmail]/All Mail").unwrap();
+            all.highest_modseq += 1;
+            let modseq = all.highest_modseq;
+            let msg = all.messages.iter_mut().find(|m| m.uid == 2).unwrap();
+            msg.modseq = modseq;
+            msg.gmail.as_mut().unwrap().labels = vec!["\\Starred".into()];
+        });
+        let result = engine.sync_all_folders(&account).await;
+        assert!(result.success, "sync failed: {:?}", result.error);
+        assert!(server.commands().iter().any(|c| c.contains("X-GM-LABELS) (CHANGEDSINCE 5)")));
+        let index = engine.label_index("acc1");
+        assert_eq!(index.uids_with("Work"), vec![1]);
+        assert_eq!(index.uids_with("\\Starred"), vec![2]);
+        let sidecar: serde_json::Value = cached_header(&cache, 2).unwrap();
+        assert_eq!(sidecar["labels"], serde_json::json!(["\\Starred"]));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn gmail_label_syncs_go_to_all_mail_before_any_all_folders_run() {
+        let dir = scratch_dir("gmail_first_inbox");
+        let mut all = Mailbox::new("[Gmail]/All Mail").with_attrs(&["\\HasNoChildren", "\\All"]);
+        all.add(
+            mock_imap::Message::new(1, "Subject: Hi\r\nMessage-ID: <hi@example.com>\r\n\r\nhi\r\n")
+                .with_gmail(11, 11, &["\\Inbox"])
+                .with_modseq(3),
+        );
+        let server = MockImap::start(
+            Scenario::new()
+                .with_cap("X-GM-EXT-1")
+                .mailbox(synthetic_mailbox("INBOX", 1))
+                .mailbox(all)
+                .mailbox(synthetic_mailbox("[Gmail]/Trash", 1).with_attrs(&["\\Trash"])),
+        );
+        let account = account_for(&server);
+        let engine = engine_for(&dir);
+        let policy = serde_json::json!({ "defaultRule": { "mode": "all" } });
+        fs::write(dir.join("archive_policy.json"), policy.to_string()).unwrap();
+
+        // What sync.now, the IDLE watcher and a scheduled INBOX slot all run.
+        let result = engine.sync_account(&account, "INBOX").await;
+        assert!(result.success, "sync failed: {:?}", result.error);
+        assert_eq!(result.mailbox, "[Gmail]/All Mail");
+        assert!(!selects(&server, 0).iter().any(|m| m == "INBOX"), "the label view is never selected");
+        assert!(!tauri_cache_dir(&dir, "acc1", "INBOX").exists());
+        assert_eq!(maildir::list_uids(&dir, "acc1", "[Gmail]/All Mail"), vec![1]);
+        assert_eq!(engine.label_index("acc1").uids_with("\\Inbox"), vec![1]);
+
+        // Trash holds its own messages and syncs as itself.
+        let result = engine.sync_account(&account, "[Gmail]/Trash").await;
+        assert_eq!(result.mailbox, "[Gmail]/Trash");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn graph_account() -> SyncAccount {
+        serde_json::from_value(serde_json::json!({
+            "id": "acc1",
+            "email": "user@outlook.com",
+            "imapConfig": {
+                "email": "user@outlook.com",
+                "imapHost": "outlook.office365.com",
+                "authType": "oauth2",
+                "oauth2AccessToken": "token",
+                "oauth2Transport": "graph",
+            }
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn graph_delta_sync_tracks_adds_changes_and_removals() {
+        use mock_graph::{state::synthetic_folder, GraphState, MockGraph};
+
+        let dir = scratch_dir("graph_delta");
+        let server = MockGraph::start(
+            GraphState::new()
+                .folder(synthetic_folder("inbox", "Inbox", 3))
+                .folder(synthetic_folder("sent", "Sent Items", 1))
+                .with_page_size(2),
+        );
+        let mut engine = engine_for(&dir);
+        engine.graph_base = server.base_url();
+        let account = graph_account();
+
+        // First round lists everything, over two pages, under app folder names.
+        let result = engine.sync_all_folders(&account).await;
+        assert!(result.success, "sync failed: {:?}", result.error);
+        assert_eq!(result.new_emails, 4);
+        assert_eq!(server.count_requests("$skiptoken="), 1);
+        let cache = tauri_cache_dir(&dir, "acc1", "INBOX");
+        assert_eq!(count_sidecars(&cache), 3);
+        assert_eq!(count_sidecars(&tauri_cache_dir(&dir, "acc1", "Sent")), 1);
+        let ledger: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(cache.join("graph_id_map.json")).unwrap()).unwrap();
+        assert_eq!(ledger, serde_json::json!({ "1": "inbox-1", "2": "inbox-2", "3": "inbox-3" }));
+        let sidecar: serde_json::Value = cached_header(&cache, 2).unwrap();
+        assert_eq!(sidecar["_graphId"], "inbox-2");
+
+        // Later rounds carry only what changed since the delta link.
+        server.update(|st| {
+            let inbox = st.find_mut("Inbox").unwrap();
+            inbox.set_read("inbox-1", true);
+            inbox.remove("inbox-2");
+            inbox.add(mock_graph::Message::new("inbox-4", "New").with_received("2026-02-01T12:00:00Z"));
+        });
+        let result = engine.sync_account(&account, "INBOX").await;
+        assert!(result.success, "sync failed: {:?}", result.error);
+        assert_eq!((result.new_emails, result.updated_flags, result.total_emails), (1, 1, 3));
+        assert!(server.count_requests("$deltatoken=") >= 1);
+        assert!(cached_header(&cache, 2).is_none());
+        let sidecar: serde_json::Value = cached_header(&cache, 4).unwrap();
+        assert_eq!(sidecar["_graphId"], "inbox-4", "new ids take the next uid, never a freed one");
+
+        // An expired delta link means a full relist, not a failed sync.
+        server.update(|st| {
+            let inbox = st.find_mut("Inbox").unwrap();
+            inbox.expire_delta_links();
+            inbox.remove("inbox-3");
+        });
+        let result = engine.sync_account(&account, "INBOX").await;
+        assert!(result.success, "sync failed: {:?}", result.error);
+        assert_eq!(result.total_emails, 2);
+        assert!(cached_header(&cache, 3).is_none(), "full round prunes what the folder dropped");
+        assert!(cached_header(&cache, 1).is_some());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn set_archive_policy(dir: &Path, rule: serde_json::Value) {
+        let policy = serde_json::json!({ "accounts": { "acc1": { "INBOX": rule } } });
+        fs::write(dir.join("archive_policy.json"), policy.to_string()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn graph_sync_archives_the_bodies_the_policy_selects() {
+        use mock_graph::{state::synthetic_folder, GraphState, MockGraph};
+
+        let dir = scratch_dir("graph_archive");
+        let server = MockGraph::start(GraphState::new().folder(synthetic_folder("inbox", "Inbox", 3)));
+        let mut engine = engine_for(&dir);
+        engine.graph_base = server.base_url();
+        let account = graph_account();
+
+        // No policy: headers only.
+        let result = engine.sync_account(&account, "INBOX").await;
+        assert!(result.success, "sync failed: {:?}", result.error);
+        assert!(maildir::list_uids(&dir, "acc1", "INBOX").is_empty());
+        assert_eq!(server.count_requests("/$value"), 0);
+
+        set_archive_policy(&dir, serde_json::json!({ "mode": "all" }));
+        let result = engine.sync_account(&account, "INBOX").await;
+        assert!(result.success, "sync failed: {:?}", result.error);
+        assert_eq!(maildir::list_uids(&dir, "acc1", "INBOX"), vec![1, 2, 3]);
+        let raw = maildir::read_raw(&dir, "acc1", "INBOX", 2).unwrap();
+        assert!(String::from_utf8_lossy(&raw).contains("Body of inbox-2."), "uid 2 is the ledger's inbox-2");
+
+        // Already in the vault: the next round fetches nothing.
+        let before = server.count_requests("/$value");
+        engine.sync_account(&account, "INBOX").await;
+        assert_eq!(server.count_requests("/$value"), before);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn archive_policy_stores_bodies_without_marking_them_read() {
+        let dir = scratch_dir("archive_all");
+        let server = MockImap::start(Scenario::new().mailbox(synthetic_mailbox("INBOX", 5)));
+        let account = account_for(&server);
+        let engine = engine_for(&dir);
+
+        // No policy: headers only, as always.
+        engine.sync_account(&account, "INBOX").await;
+        assert!(maildir::list_uids(&dir, "acc1", "INBOX").is_empty());
+        assert_eq!(server.count_commands("BODY.PEEK[]"), 0);
+
+        set_archive_policy(&dir, serde_json::json!({ "mode": "all" }));
+        let result = engine.sync_account(&account, "INBOX").await;
+        assert!(result.success, "sync failed: {:?}", result.error);
+        assert_eq!(maildir::list_uids(&dir, "acc1", "INBOX"), vec![1, 2, 3, 4, 5]);
+        let raw = maildir::read_raw(&dir, "acc1", "INBOX", 3).unwrap();
+        assert!(String::from_utf8_lossy(&raw).contains("Body of message 3."));
+        server.update(|st| {
+            let inbox = st.find_mut("INBOX").unwrap();
+            assert!(inbox.messages.iter().all(|m| !m.flags.iter().any(|f| f == "\\Seen")));
+        });
+
+        // Already in the vault: the next tick fetches nothing.
+        let before = server.count_commands("BODY.PEEK[]");
+        engine.sync_account(&account, "INBOX").await;
+        assert_eq!(server.count_commands("BODY.PEEK[]"), before);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn archive_policy_honours_size_age_and_the_transfer_cap() {
+        let dir = scratch_dir("archive_rules");
+        let big = format!("Subject: Big\r\nMessage-ID: <big@example.com>\r\n\r\n{}\r\n", "x".repeat(1100 * 1024));
+        let mut recent = mock_imap::Message::new(3, "Subject: Recent\r\nMessage-ID: <recent@example.com>\r\n\r\nhi\r\n");
+        recent.internal_date = chrono::Utc::now().format("%d-%b-%Y %H:%M:%S +0000").to_string();
+        let server = MockImap::start(
+            Scenario::new().mailbox(synthetic_mailbox("INBOX", 1).push(big).push_msg(recent)),
+        );
+        let account = account_for(&server);
+        let engine = engine_for(&dir);
+
+        set_archive_policy(&dir, serde_json::json!({ "mode": "underSize", "maxMb": 1 }));
+        engine.sync_account(&account, "INBOX").await;
+        assert_eq!(maildir::list_uids(&dir, "acc1", "INBOX"), vec![1, 3], "the 1.1 MB message is skipped");
+
+        let _ = fs::remove_dir_all(dir.join("Maildir"));
+        set_archive_policy(&dir, serde_json::json!({ "mode": "newerThan", "days": 7 }));
+        engine.sync_account(&account, "INBOX").await;
+        assert_eq!(maildir::list_uids(&dir, "acc1", "INBOX"), vec![3], "January mail is too old");
+
+        // Over the daily cap: headers may still be refused, bodies certainly are.
+        let _ = fs::remove_dir_all(dir.join("Maildir"));
+        set_archive_policy(&dir, serde_json::json!({ "mode": "all" }));
+        let today = chrono::Utc::now().format("%Y-%m-%d").to_string();
+        fs::create_dir_all(dir.join("transfer_stats")).unwrap();
+        fs::write(
+            dir.join("transfer_stats").join("acc1.daemon.json"),
+            format!(r#"{{"days":{{"{}":{{"down":209715200,"up":0}}}}}}"#, today),
+        )
+        .unwrap();
+        fs::write(
+            dir.join("frontend-settings.json"),
+            serde_json::json!({ "mailvault-settings": { "state": { "transferLimits": { "acc1": {
+                "capEnabled": true, "dailyDownLimitBytes": 100 * MB,
+            }}}}})
+            .to_string(),
+        )
+        .unwrap();
+        engine.sync_account(&account, "INBOX").await;
+        assert!(maildir::list_uids(&dir, "acc1", "INBOX").is_empty());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn archive_chunks_are_bounded_by_count_and_bytes() {
+        let uids: Vec<u32> = (1..=25).collect();
+        let small: HashMap<u32, u32> = uids.iter().map(|u| (*u, 1000)).collect();
+        let lens: Vec<usize> = archive_chunks(&uids, &small).iter().map(|c| c.len()).collect();
+        assert_eq!(lens, vec![10, 10, 5]);
+
+        // 3 MB each: two fit the budget. Unknown and oversized bodies go alone.
+        let sizes = HashMap::from([(1, 3 * MB as u32), (2, 3 * MB as u32), (3, 3 * MB as u32), (5, 20 * MB as u32), (6, 10)]);
+        let chunks: Vec<Vec<u32>> = archive_chunks(&[1, 2, 3, 4, 5, 6], &sizes).iter().map(|c| c.to_vec()).collect();
+        assert_eq!(chunks, vec![vec![1, 2], vec![3], vec![4], vec![5], vec![6]]);
+    }
+
+    #[tokio::test]
+    async fn archive_stops_at_the_cap_between_chunks_and_walks_down_the_folder() {
+        let dir = scratch_dir("archive_chunks");
+        let body = |n: u32| format!("Subject: Scan {}\r\nMessage-ID: <scan{}@example.com>\r\n\r\n{}\r\n", n, n, "x".repeat(3 * MB as usize));
+        let mut inbox = Mailbox::new("INBOX");
+        for n in 1..=6 {
+            inbox = inbox.push(body(n));
+        }
+        let server = MockImap::start(Scenario::new().mailbox(inbox));
+        // Its own address, so the live byte counters are this test's alone.
+        let mut account = account_for(&server);
+        account.email = "capped@example.com".into();
+        account.imap_config.email = account.email.clone();
+        let engine = engine_for(&dir);
+        fs::write(dir.join("accounts.json"), r#"[{"id": "acc1", "email": "capped@example.com"}]"#).unwrap();
+        fs::write(
+            dir.join("frontend-settings.json"),
+            serde_json::json!({ "mailvault-settings": { "state": { "transferLimits": { "acc1": {
+                "capEnabled": true, "dailyDownLimitBytes": 5 * MB,
+            }}}}})
+            .to_string(),
+        )
+        .unwrap();
+
+        // Two 3 MB bodies to a chunk: the first chunk passes 5 MB, so that is all.
+        set_archive_policy(&dir, serde_json::json!({ "mode": "all" }));
+        engine.sync_account(&account, "INBOX").await;
+        assert_eq!(maildir::list_uids(&dir, "acc1", "INBOX"), vec![5, 6]);
+
+        // A size probe starts below its cursor, and past the bottom from the top.
+        fs::remove_file(dir.join("frontend-settings.json")).unwrap();
+        let _ = fs::remove_dir_all(dir.join("Maildir"));
+        archive_policy::set_cursor(&dir, "acc1", "INBOX", 3).unwrap();
+        set_archive_policy(&dir, serde_json::json!({ "mode": "underSize", "maxMb": 4 }));
+        engine.sync_account(&account, "INBOX").await;
+        assert_eq!(maildir::list_uids(&dir, "acc1", "INBOX"), vec![1, 2]);
+        assert_eq!(archive_policy::cursor(&dir, "acc1", "INBOX"), Some(1));
+        engine.sync_account(&account, "INBOX").await;
+        assert_eq!(maildir::list_uids(&dir, "acc1", "INBOX"), vec![1, 2, 3, 4, 5, 6]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -0,0 +1,47 @@
+//! Fixtures shared by the daemon's unit tests: a scratch directory per test,
+//! and an engine and account pointed at a mock IMAP server.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use mock_imap::MockImap;
+
+use crate::contacts_index::ContactsState;
+use crate::imap;
+use crate::sync_engine::{SyncAccount, SyncEngine};
+
+/// An empty directory under the system temp dir. `name` must be unique across
+/// the daemon's tests — they run in parallel in one process.
+pub fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("mv_daemon_test_{}_{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// An engine keeping both its data and its app files in `dir`.
+pub fn engine_for(dir: &Path) -> SyncEngine {
+    SyncEngine::new(
+        Arc::new(imap::ImapPool::new()),
+        dir.to_path_buf(),
+        dir.to_path_buf(),
+        ContactsState::new(dir.to_path_buf()),
+    )
+}
+
+/// Account `acc1` signing in to `server` over plaintext.
+pub fn account_for(server: &MockImap) -> SyncAccount {
+    std::env::set_var("MAILVAULT_IMAP_PLAINTEXT", "1");
+    serde_json::from_value(serde_json::json!({
+        "id": "acc1",
+        "email": "user@example.com",
+        "imapConfig": {
+            "email": "user@example.com",
+            "password": "hunter2",
+            "imapHost": server.host(),
+            "imapPort": server.port(),
+        }
+    }))
+    .expect("build SyncAccount")
+}
@@ -0,0 +1,295 @@
+//! Scriptable mock Microsoft Graph mail server for MailVault tests.
+//!
+//! Plain HTTP on loopback, one request per connection (`Connection: close`).
+//! Point a `GraphClient::with_base` at `server.base_url()`.
+//!
+//! Same shape as `mock-imap`: blocking `std::net` and a thread per connection,
+//! so it serves `tokio` tests without sharing their runtime. Covers what the
+//! sync engine uses — `/me/mailFolders` and its `childFolders`,
+//! `/mailFolders/{id}/messages/delta` with paging, `@removed` entries and
+//! expiring delta links, and `/messages/{id}/$value` for bodies.
+//!
+//! ```no_run
+//! use mock_graph::{MockGraph, GraphState, state::synthetic_folder};
+//! let server = MockGraph::start(GraphState::new().folder(synthetic_folder("inbox", "Inbox", 3)));
+//! // GraphClient::with_base(token, &server.base_url())
+//! ```
+
+pub mod state;
+
+pub use state::{Folder, GraphState, Message};
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+pub struct MockGraph {
+    addr: SocketAddr,
+    state: Arc<Mutex<GraphState>>,
+    stop: Arc<AtomicBool>,
+    /// `METHOD /path?query` of every request, in order.
+    log: Arc<Mutex<Vec<String>>>,
+}
+
+impl MockGraph {
+    pub fn start(state: GraphState) -> MockGraph {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("bind mock graph");
+        let addr = listener.local_addr().expect("local_addr");
+        let state = Arc::new(Mutex::new(state));
+        let stop = Arc::new(AtomicBool::new(false));
+        let log = Arc::new(Mutex::new(Vec::new()));
+
+        {
+            let (state, stop, log) = (state.clone(), stop.clone(), log.clone());
+            let base = format!("http://{}/v1.0", addr);
+            std::thread::spawn(move || {
+                for conn in listener.incoming() {
+                    if stop.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    let Ok(conn) = conn else { continue };
+                    let (state, log, base) = (state.clone(), log.clone(), base.clone());
+                    std::thread::spawn(move || {
+                        let _ = handle_conn(conn, &state, &log, &base);
+                    });
+                }
+            });
+        }
+
+        MockGraph { addr, state, stop, log }
+    }
+
+    /// What `GraphClient::with_base` takes: `http://127.0.0.1:N/v1.0`.
+    pub fn base_url(&self) -> String {
+        format!("http://{}/v1.0", self.addr)
+    }
+
+    pub fn state(&self) -> GraphState {
+        self.state.lock().unwrap().clone()
+    }
+
+    /// Change server state mid-test, as another client would.
+    pub fn update(&self, f: impl FnOnce(&mut GraphState)) {
+        f(&mut self.state.lock().unwrap());
+    }
+
+    pub fn requests(&self) -> Vec<String> {
+        self.log.lock().unwrap().clone()
+    }
+
+    /// Requests whose `METHOD /path?query` line contains `needle`.
+    pub fn count_requests(&self, needle: &str) -> usize {
+        self.log.lock().unwrap().iter().filter(|l| l.contains(needle)).count()
+    }
+}
+
+impl Drop for MockGraph {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        let _ = TcpStream::connect(self.addr);
+    }
+}
+
+// ── request handling ────────────────────────────────────────────────────────
+
+fn handle_conn(
+    conn: TcpStream,
+    state: &Mutex<GraphState>,
+    log: &Mutex<Vec<String>>,
+    base: &str,
+) -> std::io::Result<()> {
+    let mut out = conn.try_clone()?;
+    let mut reader = BufReader::new(conn);
+
+    let mut request_line = String::new();
+    if reader.read_line(&mut request_line)? == 0 {
+        return Ok(());
+    }
+    let mut headers = HashMap::new();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.insert(name.trim().to_lowercase(), value.trim().to_string());
+        }
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or("").to_string();
+    let target = parts.next().unwrap_or("").to_string();
+    log.lock().unwrap().push(format!("{} {}", method, target));
+
+    let (status, reply) = route(&method, &target, &headers, &state.lock().unwrap(), base);
+    let (content_type, body) = match reply {
+        Reply::Json(json) => ("application/json", json.to_string()),
+        Reply::Mime(mime) => ("message/rfc822", mime),
+    };
+    write!(
+        out,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )?;
+    out.flush()
+}
+
+/// A response body: JSON for the API, the raw message for `$value`.
+enum Reply {
+    Json(serde_json::Value),
+    Mime(String),
+}
+
+fn route(
+    method: &str,
+    target: &str,
+    headers: &HashMap<String, String>,
+    state: &GraphState,
+    base: &str,
+) -> (&'static str, Reply) {
+    if let Some(token) = &state.access_token {
+        if headers.get("authorization") != Some(&format!("Bearer {}", token)) {
+            return ("401 Unauthorized", error("InvalidAuthenticationToken", "Access token is empty or invalid."));
+        }
+    }
+
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let query: HashMap<String, String> = query
+        .split('&')
+        .filter_map(|kv| kv.split_once('='))
+        .map(|(k, v)| (percent_decode(k), percent_decode(v)))
+        .collect();
+    let segments: Vec<String> = path
+        .trim_start_matches("/v1.0/")
+        .split('/')
+        .map(percent_decode)
+        .collect();
+    let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+
+    match (method, segments.as_slice()) {
+        ("GET", ["me", "mailFolders"]) => ("200 OK", folder_list(state, None)),
+        ("GET", ["me", "mailFolders", id, "childFolders"]) => {
+            if state.folders.iter().any(|f| f.id == *id) {
+                ("200 OK", folder_list(state, Some(id)))
+            } else {
+                ("404 Not Found", error("ErrorItemNotFound", "The specified folder could not be found."))
+            }
+        }
+        ("GET", ["me", "mailFolders", id, "messages", "delta"]) => {
+            match state.folders.iter().find(|f| f.id == *id) {
+                Some(folder) => delta(folder, &query, state.page_size, base),
+                None => ("404 Not Found", error("ErrorItemNotFound", "The specified folder could not be found.")),
+            }
+        }
+        ("GET", ["me", "messages", id, "$value"]) => {
+            match state.folders.iter().flat_map(|f| &f.messages).find(|m| m.id == *id) {
+                Some(msg) => ("200 OK", Reply::Mime(msg.to_mime())),
+                None => ("404 Not Found", error("ErrorItemNotFound", "The specified object was not found in the store.")),
+            }
+        }
+        _ => ("404 Not Found", error("ResourceNotFound", "Resource not found for the segment.")),
+    }
+}
+
+/// The folders directly under `parent`, or the top level for `None`.
+fn folder_list(state: &GraphState, parent: Option<&str>) -> Reply {
+    let value: Vec<_> = state
+        .folders
+        .iter()
+        .filter(|f| f.parent.as_deref() == parent)
+        .map(|f| {
+            serde_json::json!({
+                "id": f.id,
+                "displayName": f.display_name,
+                "totalItemCount": f.messages.len(),
+                "unreadItemCount": f.unread(),
+                "childFolderCount": state.children(&f.id).count(),
+            })
+        })
+        .collect();
+    Reply::Json(serde_json::json!({ "value": value }))
+}
+
+/// One delta page. `$skiptoken` is `since.end.offset` within a round;
+/// `$deltatoken` is the version the previous round ended at.
+fn delta(
+    folder: &Folder,
+    query: &HashMap<String, String>,
+    page_size: usize,
+    base: &str,
+) -> (&'static str, Reply) {
+    let (since, end, offset) = if let Some(skip) = query.get("$skiptoken") {
+        let n: Vec<u64> = skip.split('.').filter_map(|p| p.parse().ok()).collect();
+        match n[..] {
+            [since, end, offset] => (since, end, offset as usize),
+            _ => return ("400 Bad Request", error("BadRequest", "Malformed skip token.")),
+        }
+    } else if let Some(token) = query.get("$deltatoken") {
+        let since: u64 = token.parse().unwrap_or(0);
+        if since <= folder.expired_through && folder.expired_through > 0 {
+            return ("410 Gone", error("SyncStateNotFound", "The sync state generation is no longer valid."));
+        }
+        (since, folder.version, 0)
+    } else {
+        (0, folder.version, 0)
+    };
+
+    let mut items: Vec<(u64, serde_json::Value)> = folder
+        .messages
+        .iter()
+        .filter(|m| (since == 0 || m.version > since) && m.version <= end)
+        .map(|m| (m.version, m.to_json()))
+        .collect();
+    if since > 0 {
+        items.extend(
+            folder
+                .removed
+                .iter()
+                .filter(|(_, v)| *v > since && *v <= end)
+                .map(|(id, v)| (*v, serde_json::json!({ "id": id, "@removed": { "reason": "deleted" } }))),
+        );
+    }
+    items.sort_by_key(|(v, _)| *v);
+
+    let page: Vec<serde_json::Value> = items.iter().skip(offset).take(page_size).map(|(_, j)| j.clone()).collect();
+    let link = format!("{}/me/mailFolders/{}/messages/delta", base, folder.id);
+    let body = if offset + page_size < items.len() {
+        serde_json::json!({
+            "value": page,
+            "@odata.nextLink": format!("{}?$skiptoken={}.{}.{}", link, since, end, offset + page_size),
+        })
+    } else {
+        serde_json::json!({
+            "value": page,
+            "@odata.deltaLink": format!("{}?$deltatoken={}", link, end),
+        })
+    };
+    ("200 OK", Reply::Json(body))
+}
+
+fn error(code: &str, message: &str) -> Reply {
+    Reply::Json(serde_json::json!({ "error": { "code": code, "message": message } }))
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            if let Some(b) = s.get(i + 1..i + 3).and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                out.push(b);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
@@ -0,0 +1,218 @@
+//! Folder / message state for the mock Graph server.
+//!
+//! Every change bumps the folder's `version` and stamps it on what changed —
+//! the message, or the removal record. A delta token is simply the version a
+//! round ended at, so "what changed since" is a filter on the stamp.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Message {
+    pub id: String,
+    pub subject: String,
+    pub from: String,
+    /// RFC 3339, as Graph's `receivedDateTime`.
+    pub received: String,
+    pub is_read: bool,
+    pub internet_message_id: Option<String>,
+    /// Folder version of the last change to this message.
+    pub version: u64,
+}
+
+impl Default for Message {
+    fn default() -> Self {
+        Message {
+            id: String::new(),
+            subject: String::new(),
+            from: "sender@example.com".to_string(),
+            received: "2026-01-01T12:00:00Z".to_string(),
+            is_read: false,
+            internet_message_id: None,
+            version: 0,
+        }
+    }
+}
+
+impl Message {
+    pub fn new(id: &str, subject: &str) -> Self {
+        Message {
+            id: id.to_string(),
+            subject: subject.to_string(),
+            internet_message_id: Some(format!("<{}@example.com>", id)),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_read(mut self, read: bool) -> Self {
+        self.is_read = read;
+        self
+    }
+
+    pub fn with_received(mut self, received: &str) -> Self {
+        self.received = received.to_string();
+        self
+    }
+
+    /// The message as `/messages` and `/messages/delta` return it.
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "id": self.id,
+            "subject": self.subject,
+            "from": { "emailAddress": { "name": null, "address": self.from } },
+            "toRecipients": [{ "emailAddress": { "name": null, "address": "user@example.com" } }],
+            "receivedDateTime": self.received,
+            "isRead": self.is_read,
+            "hasAttachments": false,
+            "internetMessageId": self.internet_message_id,
+        })
+    }
+
+    /// The message as `/messages/{id}/$value` returns it.
+    pub fn to_mime(&self) -> String {
+        let date = rfc2822_date(&self.received);
+        let mut mime = format!("From: {}\r\nTo: user@example.com\r\nSubject: {}\r\nDate: {}\r\n", self.from, self.subject, date);
+        if let Some(id) = &self.internet_message_id {
+            mime.push_str(&format!("Message-ID: {}\r\n", id));
+        }
+        mime.push_str(&format!("Content-Type: text/plain; charset=utf-8\r\n\r\nBody of {}.\r\n", self.id));
+        mime
+    }
+}
+
+/// `2026-01-01T12:00:00Z` as `01 Jan 2026 12:00:00 +0000`; anything else
+/// unchanged. The day of the week is optional in RFC 5322.
+fn rfc2822_date(rfc3339: &str) -> String {
+    const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+    let parts = rfc3339.get(..10).zip(rfc3339.get(11..19));
+    let Some((date, time)) = parts else { return rfc3339.to_string() };
+    let ymd: Vec<&str> = date.split('-').collect();
+    match ymd[..] {
+        [y, m, d] => match m.parse::<usize>().ok().and_then(|m| MONTHS.get(m.wrapping_sub(1))) {
+            Some(month) => format!("{} {} {} {} +0000", d, month, y, time),
+            None => rfc3339.to_string(),
+        },
+        _ => rfc3339.to_string(),
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Folder {
+    pub id: String,
+    pub display_name: String,
+    /// Id of the folder this one sits under; `None` at the top level.
+    pub parent: Option<String>,
+    pub messages: Vec<Message>,
+    /// `(id, version)` of every message deleted or moved out.
+    pub removed: Vec<(String, u64)>,
+    pub version: u64,
+    /// Delta tokens up to this version answer 410 Gone, as Graph does once
+    /// it has dropped a client's sync state.
+    pub expired_through: u64,
+}
+
+impl Folder {
+    pub fn new(id: &str, display_name: &str) -> Self {
+        Folder { id: id.to_string(), display_name: display_name.to_string(), ..Default::default() }
+    }
+
+    /// Make this a child folder of `parent_id`.
+    pub fn under(mut self, parent_id: &str) -> Self {
+        self.parent = Some(parent_id.to_string());
+        self
+    }
+
+    pub fn add(&mut self, mut msg: Message) {
+        self.version += 1;
+        msg.version = self.version;
+        self.removed.retain(|(id, _)| *id != msg.id);
+        self.messages.retain(|m| m.id != msg.id);
+        self.messages.push(msg);
+    }
+
+    pub fn remove(&mut self, id: &str) -> bool {
+        let before = self.messages.len();
+        self.messages.retain(|m| m.id != id);
+        if self.messages.len() == before {
+            return false;
+        }
+        self.version += 1;
+        self.removed.push((id.to_string(), self.version));
+        true
+    }
+
+    pub fn set_read(&mut self, id: &str, read: bool) -> bool {
+        let Some(msg) = self.messages.iter_mut().find(|m| m.id == id) else { return false };
+        self.version += 1;
+        msg.is_read = read;
+        msg.version = self.version;
+        true
+    }
+
+    /// Invalidate every delta link handed out so far.
+    pub fn expire_delta_links(&mut self) {
+        self.expired_through = self.version;
+    }
+
+    pub fn unread(&self) -> usize {
+        self.messages.iter().filter(|m| !m.is_read).count()
+    }
+}
+
+/// A folder of `n` synthetic messages, ids `{id}-1`..`{id}-n`, oldest first.
+pub fn synthetic_folder(id: &str, display_name: &str, n: u32) -> Folder {
+    let mut folder = Folder::new(id, display_name);
+    for i in 1..=n {
+        folder.add(
+            Message::new(&format!("{}-{}", id, i), &format!("Message {}", i))
+                .with_received(&format!("2026-01-{:02}T12:00:00Z", i.min(28))),
+        );
+    }
+    folder
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GraphState {
+    pub folders: Vec<Folder>,
+    /// Items per delta page. Small values exercise `@odata.nextLink`.
+    pub page_size: usize,
+    /// Bearer token the server accepts. `None` accepts anything.
+    pub access_token: Option<String>,
+}
+
+impl Default for GraphState {
+    fn default() -> Self {
+        GraphState { folders: Vec::new(), page_size: 200, access_token: None }
+    }
+}
+
+impl GraphState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn folder(mut self, folder: Folder) -> Self {
+        self.folders.push(folder);
+        self
+    }
+
+    pub fn with_page_size(mut self, n: usize) -> Self {
+        self.page_size = n.max(1);
+        self
+    }
+
+    pub fn find(&self, display_name: &str) -> Option<&Folder> {
+        self.folders.iter().find(|f| f.display_name == display_name)
+    }
+
+    pub fn find_mut(&mut self, display_name: &str) -> Option<&mut Folder> {
+        self.folders.iter_mut().find(|f| f.display_name == display_name)
+    }
+
+    /// The folders directly under `parent_id`.
+    pub fn children<'a>(&'a self, parent_id: &'a str) -> impl Iterator<Item = &'a Folder> + 'a {
+        self.folders.iter().filter(move |f| f.parent.as_deref() == Some(parent_id))
+    }
+}
@@ -42,6 +42,12 @@ pub struct Session {
     pub authenticated: bool,
     pub selected: Option<String>,
     pub read_only: bool,
+    /// `(uid, modseq)` of the selected mailbox as this client last saw it.
+    /// NOOP and IDLE report the difference — which is how a test edits the
+    /// mailbox from outside and proves the client noticed.
+    pub seen: Vec<(u32, u64)>,
+    /// ENABLE QRESYNC was sent: expunges are reported as VANISHED.
+    pub qresync: bool,
 }
 
 // ── argument scanning ───────────────────────────────────────────────────────
@@ -165,7 +171,9 @@ pub fn dispatch(
     match cmd.name.as_str() {
         "CAPABILITY" => Response::ok("CAPABILITY completed")
             .line(format!("* CAPABILITY {}", state.capabilities.join(" "))),
-        "NOOP" => Response::ok("NOOP completed"),
+        "NOOP" => pending_updates(state, sess)
+            .into_iter()
+            .fold(Response::ok("NOOP completed"), Response::line),
         "LOGOUT" => {
             let mut r = Response::ok("LOGOUT completed").line("* BYE MockIMAP signing off");
             r.close_after = true;
@@ -174,6 +182,7 @@ pub fn dispatch(
         "LOGIN" => do_login(cmd, state, sess),
         "AUTHENTICATE" => Response::bad("AUTHENTICATE handled inline"),
         _ if !sess.authenticated => Response::no("Not authenticated"),
+        "ENABLE" => do_enable(cmd, state, sess),
         "LIST" | "LSUB" => do_list(cmd, state),
         "SELECT" | "EXAMINE" => do_select(cmd, state, sess, faults),
         "STATUS" => do_status(cmd, state),
@@ -186,6 +195,7 @@ pub fn dispatch(
         "MOVE" => do_copy(cmd, state, sess, true),
         "EXPUNGE" => do_expunge(cmd, state, sess),
         "APPEND" => do_append(cmd, state),
+        "GETQUOTAROOT" | "GETQUOTA" if state.has_cap("QUOTA") => do_quota(cmd, state),
         "COMPRESS" => Response::no("COMPRESS not supported by mock"),
         other => Response::bad(&format!("Unknown command {}", other)),
     }
@@ -270,12 +280,56 @@ fn do_select(cmd: &Command, state: &ServerState, sess: &mut Session, faults: &[A
     .line(format!("* OK [UIDVALIDITY {}] UIDs valid", uid_validity))
     .line(format!("* OK [UIDNEXT {}] Predicted next UID", uid_next));
 
-    if state.has_cap("CONDSTORE") {
+    if state.has_cap("CONDSTORE") || state.has_cap("QRESYNC") {
         r = r.line(format!(
             "* OK [HIGHESTMODSEQ {}] Highest modseq",
             mb.highest_modseq
         ));
     }
+
+    // `(QRESYNC (uidvalidity modseq [known-uids]))` — RFC 7162 §3.2.5.
+    let params = next_group(&mut args).unwrap_or_default();
+    if let Some(qr) = params.trim().strip_prefix("QRESYNC") {
+        if !sess.qresync {
+            return Response::bad("QRESYNC not enabled");
+        }
+        let inner = next_group(&mut qr.trim_start()).unwrap_or_default();
+        let mut it = inner.split_whitespace();
+        let their_validity: u32 = it.next().and_then(|v| v.parse().ok()).unwrap_or(0);
+        let since: u64 = it.next().and_then(|v| v.parse().ok()).unwrap_or(0);
+        let all_uids: Vec<u32> = mb
+            .vanished
+            .iter()
+            .map(|(u, _)| *u)
+            .chain(mb.messages.iter().map(|m| m.uid))
+            .collect();
+        let known: Option<Vec<u32>> = it.next().map(|set| expand_set(set, &all_uids));
+
+        // A UIDVALIDITY mismatch means the client's state is worthless: the
+        // server ignores the parameters and the client must resync in full.
+        if their_validity == uid_validity {
+            let vanished: Vec<u32> = mb
+                .vanished
+                .iter()
+                .filter(|(u, m)| *m > since && known.as_ref().is_none_or(|k| k.contains(u)))
+                .map(|(u, _)| *u)
+                .collect();
+            if !vanished.is_empty() {
+                r = r.line(format!("* VANISHED (EARLIER) {}", join_uids(&vanished)));
+            }
+            for (i, msg) in mb.messages.iter().enumerate() {
+                if msg.modseq > since {
+                    r = r.line(format!(
+                        "* {} FETCH (UID {} FLAGS ({}) MODSEQ ({}))",
+                        i + 1,
+                        msg.uid,
+                        msg.flags.join(" "),
+                        msg.modseq
+                    ));
+                }
+            }
+        }
+    }
     r
 }
 
@@ -310,6 +364,36 @@ fn do_status(cmd: &Command, state: &ServerState) -> Response {
     ))
 }
 
+/// GETQUOTAROOT answers with the root and its QUOTA; GETQUOTA with the QUOTA
+/// of the root named.
+fn do_quota(cmd: &Command, state: &ServerState) -> Response {
+    let mut args = cmd.args.as_str();
+    let name = next_arg(&mut args).unwrap_or_default();
+    let quota = || {
+        let resources: Vec<String> = state
+            .quota
+            .iter()
+            .map(|(r, limit)| format!("{} {} {}", r.to_uppercase(), state.quota_usage(r), limit))
+            .collect();
+        format!("* QUOTA \"\" ({})", resources.join(" "))
+    };
+    if cmd.name == "GETQUOTA" {
+        if !name.is_empty() || state.quota.is_empty() {
+            return Response::no("[NONEXISTENT] No such quota root");
+        }
+        return Response::ok("GETQUOTA completed").line(quota());
+    }
+    let Some(mb) = state.find(&name) else {
+        return Response::no("[NONEXISTENT] Mailbox does not exist");
+    };
+    if state.quota.is_empty() {
+        return Response::ok("GETQUOTAROOT completed").line(format!("* QUOTAROOT {}", quoted(&mb.name)));
+    }
+    Response::ok("GETQUOTAROOT completed")
+        .line(format!("* QUOTAROOT {} \"\"", quoted(&mb.name)))
+        .line(quota())
+}
+
 fn do_create(cmd: &Command, state: &mut ServerState) -> Response {
     let mut args = cmd.args.as_str();
     let name = next_arg(&mut args).unwrap_or_default();
@@ -331,6 +415,60 @@ fn do_delete(cmd: &Command, state: &mut ServerState) -> Response {
     Response::ok("DELETE completed")
 }
 
+/// Record the selected mailbox as the client now sees it. Called after every
+/// command, so only changes made by *other* connections show up as pending.
+pub fn snapshot_selected(state: &ServerState, sess: &mut Session) {
+    sess.seen = selected(state, sess)
+        .map(|mb| mb.messages.iter().map(|m| (m.uid, m.modseq)).collect())
+        .unwrap_or_default();
+}
+
+/// Untagged EXPUNGE / FETCH / EXISTS lines for everything that changed in the
+/// selected mailbox since the last snapshot, and take a fresh one.
+pub fn pending_updates(state: &ServerState, sess: &mut Session) -> Vec<String> {
+    let Some(mb) = selected(state, sess) else {
+        return Vec::new();
+    };
+    let mut lines = Vec::new();
+
+    // Highest sequence first, so each number is still valid when it is read.
+    let gone: Vec<u32> = sess
+        .seen
+        .iter()
+        .map(|(uid, _)| *uid)
+        .filter(|uid| mb.by_uid(*uid).is_none())
+        .collect();
+    if sess.qresync {
+        if !gone.is_empty() {
+            lines.push(format!("* VANISHED {}", join_uids(&gone)));
+        }
+    } else {
+        for (i, (uid, _)) in sess.seen.iter().enumerate().rev() {
+            if gone.contains(uid) {
+                lines.push(format!("* {} EXPUNGE", i + 1));
+            }
+        }
+    }
+    let survivors = sess.seen.iter().filter(|(uid, _)| mb.by_uid(*uid).is_some()).count();
+    for (uid, modseq) in &sess.seen {
+        let Some(msg) = mb.by_uid(*uid) else { continue };
+        if msg.modseq != *modseq {
+            lines.push(format!(
+                "* {} FETCH (UID {} FLAGS ({}))",
+                mb.seq_of(*uid).unwrap_or(0),
+                uid,
+                msg.flags.join(" ")
+            ));
+        }
+    }
+    if mb.messages.len() != survivors {
+        lines.push(format!("* {} EXISTS", mb.messages.len()));
+    }
+
+    snapshot_selected(state, sess);
+    lines
+}
+
 fn selected<'a>(state: &'a ServerState, sess: &Session) -> Option<&'a Mailbox> {
     sess.selected.as_ref().and_then(|n| state.find(n))
 }
@@ -349,75 +487,94 @@ fn header_value(raw: &[u8], name: &str) -> Option<String> {
 }
 
 fn matches_criteria(msg: &Message, criteria: &str) -> bool {
-    let mut rest = criteria.trim();
-    if rest.is_empty() || rest.eq_ignore_ascii_case("ALL") {
-        return true;
-    }
     let text = String::from_utf8_lossy(&msg.raw).to_lowercase();
+    let mut s = criteria.trim();
+    // Top-level keys are ANDed. Every key is consumed even after a miss, so
+    // the parse never desyncs.
+    let mut all = true;
+    while !s.trim().is_empty() {
+        all &= match_key(msg, &text, &mut s);
+    }
+    all
+}
 
-    // All criteria are ANDed, matching how the client builds them.
-    let mut s = rest;
-    loop {
-        let Some(key) = next_arg(&mut s) else { break };
-        let ok = match key.to_uppercase().as_str() {
-            "ALL" => true,
-            // Clients prepend `CHARSET <name>` for non-ASCII values; matching is
-            // byte-oriented here, so consume and ignore it rather than silently
-            // matching nothing.
-            "CHARSET" => {
-                next_arg(&mut s);
-                true
-            }
-            "UNSEEN" => !msg.has_flag("\\Seen"),
-            "SEEN" => msg.has_flag("\\Seen"),
-            "HEADER" => {
-                let name = next_arg(&mut s).unwrap_or_default();
-                let want = next_arg(&mut s).unwrap_or_default();
-                header_value(&msg.raw, &name)
-                    .map(|v| v.to_lowercase().contains(&want.to_lowercase()))
-                    .unwrap_or(false)
-            }
-            "TEXT" => {
-                let want = next_arg(&mut s).unwrap_or_default();
-                text.contains(&want.to_lowercase())
-            }
-            "FROM" | "TO" | "CC" | "SUBJECT" => {
-                let want = next_arg(&mut s).unwrap_or_default();
-                header_value(&msg.raw, &key)
-                    .map(|v| v.to_lowercase().contains(&want.to_lowercase()))
-                    .unwrap_or(false)
+/// Evaluate one search key, consuming it (and its arguments) from `s`.
+fn match_key(msg: &Message, text: &str, s: &mut &str) -> bool {
+    if let Some(group) = next_group(s) {
+        return matches_criteria(msg, &group);
+    }
+    let Some(key) = next_arg(s) else { return true };
+    match key.to_uppercase().as_str() {
+        "ALL" => true,
+        // Clients prepend `CHARSET <name>` for non-ASCII values; matching is
+        // byte-oriented here, so consume and ignore it rather than silently
+        // matching nothing.
+        "CHARSET" => {
+            next_arg(s);
+            true
+        }
+        "OR" => {
+            let a = match_key(msg, text, s);
+            let b = match_key(msg, text, s);
+            a || b
+        }
+        "NOT" => !match_key(msg, text, s),
+        "UNSEEN" => !msg.has_flag("\\Seen"),
+        "SEEN" => msg.has_flag("\\Seen"),
+        "FLAGGED" => msg.has_flag("\\Flagged"),
+        "UNFLAGGED" => !msg.has_flag("\\Flagged"),
+        "LARGER" | "SMALLER" => {
+            let n: usize = next_arg(s).and_then(|n| n.parse().ok()).unwrap_or(0);
+            if key.eq_ignore_ascii_case("LARGER") {
+                msg.raw.len() > n
+            } else {
+                msg.raw.len() < n
             }
-            "SINCE" | "BEFORE" => {
-                let when = next_arg(&mut s).unwrap_or_default();
-                match (
-                    chrono::NaiveDate::parse_from_str(&when, "%d-%b-%Y"),
-                    chrono::NaiveDate::parse_from_str(
-                        msg.internal_date.split(' ').next().unwrap_or(""),
-                        "%d-%b-%Y",
-                    ),
-                ) {
-                    (Ok(bound), Ok(actual)) => {
-                        if key.eq_ignore_ascii_case("SINCE") {
-                            actual >= bound
-                        } else {
-                            actual < bound
-                        }
+        }
+        "HEADER" => {
+            let name = next_arg(s).unwrap_or_default();
+            let want = next_arg(s).unwrap_or_default();
+            header_value(&msg.raw, &name)
+                .map(|v| v.to_lowercase().contains(&want.to_lowercase()))
+                .unwrap_or(false)
+        }
+        "EMAILID" | "THREADID" => {
+            let want = next_arg(s).unwrap_or_default();
+            let have = if key.eq_ignore_ascii_case("EMAILID") { &msg.email_id } else { &msg.thread_id };
+            have.as_deref() == Some(want.as_str())
+        }
+        "TEXT" => {
+            let want = next_arg(s).unwrap_or_default();
+            text.contains(&want.to_lowercase())
+        }
+        "FROM" | "TO" | "CC" | "SUBJECT" => {
+            let want = next_arg(s).unwrap_or_default();
+            header_value(&msg.raw, &key)
+                .map(|v| v.to_lowercase().contains(&want.to_lowercase()))
+                .unwrap_or(false)
+        }
+        "SINCE" | "BEFORE" => {
+            let when = next_arg(s).unwrap_or_default();
+            match (
+                chrono::NaiveDate::parse_from_str(&when, "%d-%b-%Y"),
+                chrono::NaiveDate::parse_from_str(
+                    msg.internal_date.split(' ').next().unwrap_or(""),
+                    "%d-%b-%Y",
+                ),
+            ) {
+                (Ok(bound), Ok(actual)) => {
+                    if key.eq_ignore_ascii_case("SINCE") {
+                        actual >= bound
+                    } else {
+                        actual < bound
                     }
-                    _ => true,
                 }
+                _ => true,
             }
-            // Unknown key: do not silently pass — a test relying on it should fail loudly.
-            _ => false,
-        };
-        if !ok {
-            return false;
-        }
-        rest = s;
-        if rest.trim().is_empty() {
-            break;
         }
+        // Unknown key: do not silently pass — a test relying on it should fail loudly.
+        _ => false,
     }
-    true
 }
 
 fn do_search(cmd: &Command, state: &ServerState, sess: &Session, faults: &[Action]) -> Response {
@@ -465,6 +622,9 @@ fn do_fetch(cmd: &Command, state: &ServerState, sess: &Session, faults: &[Action
     if changed_since.is_some() && !items.contains(&FetchItem::ModSeq) {
         items.push(FetchItem::ModSeq);
     }
+    if !state.has_cap("BINARY") && items.iter().any(|i| matches!(i, FetchItem::BodySection { binary: true, .. })) {
+        return Response::bad("BINARY not supported");
+    }
 
     let universe: Vec<u32> = if cmd.is_uid {
         mb.messages.iter().map(|m| m.uid).collect()
@@ -617,6 +777,7 @@ fn do_copy(cmd: &Command, state: &mut ServerState, sess: &Session, is_move: bool
     let src_validity = src.uid_validity;
     let src_name = src.name.clone();
 
+    let uidplus = state.has_cap("UIDPLUS");
     let dest = state.find_mut(&dest_name).unwrap();
     let dest_validity = dest.uid_validity;
     let mut new_uids = Vec::new();
@@ -626,23 +787,19 @@ fn do_copy(cmd: &Command, state: &mut ServerState, sess: &Session, is_move: bool
         dest.add(m);
     }
 
-    let mut r = Response::ok(if is_move { "MOVE completed" } else { "COPY completed" }).line(
-        format!(
+    let mut r = Response::ok(if is_move { "MOVE completed" } else { "COPY completed" });
+    if uidplus {
+        r = r.line(format!(
             "* OK [COPYUID {} {} {}] Copied",
             dest_validity,
             targets.iter().map(|u| u.to_string()).collect::<Vec<_>>().join(","),
             new_uids.iter().map(|u| u.to_string()).collect::<Vec<_>>().join(","),
-        ),
-    );
+        ));
+    }
 
     if is_move {
         let src = state.find_mut(&src_name).unwrap();
-        let mut seqs: Vec<u32> = targets.iter().filter_map(|u| src.seq_of(*u)).collect();
-        seqs.sort_unstable_by(|a, b| b.cmp(a)); // descending — sequence numbers shift
-        src.messages.retain(|m| !targets.contains(&m.uid));
-        for seq in seqs {
-            r = r.line(format!("* {} EXPUNGE", seq));
-        }
+        r = expunge_lines(r, src, &targets, sess);
     }
     let _ = src_validity;
     r
@@ -669,23 +826,63 @@ fn do_expunge(cmd: &Command, state: &mut ServerState, sess: &Session) -> Respons
         .map(|m| m.uid)
         .collect();
 
-    let mut seqs: Vec<u32> = doomed.iter().filter_map(|u| mb.seq_of(*u)).collect();
-    seqs.sort_unstable_by(|a, b| b.cmp(a));
-    mb.messages.retain(|m| !doomed.contains(&m.uid));
+    expunge_lines(Response::ok("EXPUNGE completed"), mb, &doomed, sess)
+}
 
-    let mut r = Response::ok("EXPUNGE completed");
-    for seq in seqs {
-        r = r.line(format!("* {} EXPUNGE", seq));
+/// Expunge `uids` from `mb` and report it: one `* n EXPUNGE` per message,
+/// highest sequence first so the numbers stay valid — or, once the client has
+/// enabled QRESYNC, a single `* VANISHED` with the UIDs (RFC 7162 §3.2.10).
+fn expunge_lines(mut r: Response, mb: &mut Mailbox, uids: &[u32], sess: &Session) -> Response {
+    let mut seqs: Vec<u32> = uids.iter().filter_map(|u| mb.seq_of(*u)).collect();
+    seqs.sort_unstable_by(|a, b| b.cmp(a));
+    let gone = mb.expunge(uids);
+    if sess.qresync {
+        if !gone.is_empty() {
+            r = r.line(format!("* VANISHED {}", join_uids(&gone)));
+        }
+    } else {
+        for seq in seqs {
+            r = r.line(format!("* {} EXPUNGE", seq));
+        }
     }
     r
 }
 
+fn join_uids(uids: &[u32]) -> String {
+    uids.iter().map(|u| u.to_string()).collect::<Vec<_>>().join(",")
+}
+
+/// ENABLE (RFC 5161). QRESYNC changes how expunges are reported; UTF8=ACCEPT
+/// changes nothing here — mailbox names are sent as stored either way.
+fn do_enable(cmd: &Command, state: &ServerState, sess: &mut Session) -> Response {
+    if sess.selected.is_some() {
+        return Response::bad("ENABLE is only valid in the authenticated state");
+    }
+    let mut enabled = Vec::new();
+    for ext in cmd.args.split_whitespace() {
+        if ext.eq_ignore_ascii_case("QRESYNC") && state.has_cap("QRESYNC") {
+            sess.qresync = true;
+            enabled.push("QRESYNC");
+        } else if ext.eq_ignore_ascii_case("UTF8=ACCEPT") && state.has_cap("UTF8=ACCEPT") {
+            enabled.push("UTF8=ACCEPT");
+        }
+    }
+    let line = if enabled.is_empty() { "* ENABLED".to_string() } else { format!("* ENABLED {}", enabled.join(" ")) };
+    Response::ok("ENABLE completed").line(line)
+}
+
 fn do_append(cmd: &Command, state: &mut ServerState) -> Response {
     let mut args = cmd.args.as_str();
     let name = next_arg(&mut args).unwrap_or_default();
     // Optional flag list and optional date-time precede the literal.
-    let _flags = next_group(&mut args);
-
+    let flags = next_group(&mut args);
+    let date = args.trim_start().strip_prefix('"').and_then(|d| d.split_once('"')).map(|(d, _)| d.to_string());
+
+    let uidplus = state.has_cap("UIDPLUS");
+    let email_id = state.has_cap("OBJECTID").then(|| {
+        state.next_email_id += 1;
+        format!("M{:012x}", state.next_email_id - 1)
+    });
     let Some(mb) = state.find_mut(&name) else {
         return Response::no("[TRYCREATE] Mailbox does not exist");
     };
@@ -694,8 +891,19 @@ fn do_append(cmd: &Command, state: &mut ServerState) -> Response {
     let modseq = mb.highest_modseq + 1;
     let mut msg = Message::new(uid, cmd.literal.clone());
     msg.modseq = modseq;
+    if let Some(flags) = flags {
+        msg.flags = flags.split_whitespace().map(str::to_string).collect();
+    }
+    if let Some(date) = date {
+        msg.internal_date = date;
+    }
+    msg.email_id = email_id;
     mb.add(msg);
     mb.highest_modseq = modseq;
 
-    Response::ok(&format!("[APPENDUID {} {}] APPEND completed", validity, uid))
+    if uidplus {
+        Response::ok(&format!("[APPENDUID {} {}] APPEND completed", validity, uid))
+    } else {
+        Response::ok("APPEND completed")
+    }
 }
@@ -303,10 +303,19 @@ pub enum FetchItem {
     Rfc822Size,
     BodyStructure,
     ModSeq,
+    GmailMsgId,
+    GmailThrId,
+    GmailLabels,
+    EmailId,
+    ThreadId,
     /// BODY[] / BODY.PEEK[] — whole message
     BodyFull { peek: bool },
     /// BODY[HEADER.FIELDS (...)]
     BodyHeaderFields { peek: bool, fields: Vec<String> },
+    /// BODY[1.2], BODY[HEADER], BINARY[2] or any of them with `<offset.length>`.
+    /// `section` is empty for the whole message. BINARY answers with the
+    /// part's content decoded (RFC 3516).
+    BodySection { binary: bool, section: String, partial: Option<(usize, usize)> },
 }
 
 /// Parse a FETCH spec such as
@@ -354,17 +363,31 @@ fn parse_fetch_item(token: &str) -> Option<FetchItem> {
         "RFC822.SIZE" => return Some(FetchItem::Rfc822Size),
         "BODYSTRUCTURE" | "BODY" => return Some(FetchItem::BodyStructure),
         "MODSEQ" => return Some(FetchItem::ModSeq),
+        "X-GM-MSGID" => return Some(FetchItem::GmailMsgId),
+        "X-GM-THRID" => return Some(FetchItem::GmailThrId),
+        "X-GM-LABELS" => return Some(FetchItem::GmailLabels),
+        "EMAILID" => return Some(FetchItem::EmailId),
+        "THREADID" => return Some(FetchItem::ThreadId),
         _ => {}
     }
 
     let peek = upper.starts_with("BODY.PEEK[");
-    if !peek && !upper.starts_with("BODY[") {
+    let binary = upper.starts_with("BINARY[") || upper.starts_with("BINARY.PEEK[");
+    if !peek && !binary && !upper.starts_with("BODY[") {
         return None;
     }
     let inner_start = token.find('[')? + 1;
     let inner_end = token.rfind(']')?;
     let section = &token[inner_start..inner_end];
-
+    let partial = token[inner_end + 1..]
+        .strip_prefix('<')
+        .and_then(|p| p.strip_suffix('>'))
+        .and_then(|p| p.split_once('.'))
+        .and_then(|(o, l)| Some((o.parse().ok()?, l.parse().ok()?)));
+
+    if binary || partial.is_some() || section.eq_ignore_ascii_case("HEADER") || section.starts_with(|c: char| c.is_ascii_digit()) {
+        return Some(FetchItem::BodySection { binary, section: section.to_uppercase(), partial });
+    }
     if section.trim().is_empty() {
         return Some(FetchItem::BodyFull { peek });
     }
@@ -382,6 +405,32 @@ fn parse_fetch_item(token: &str) -> Option<FetchItem> {
     Some(FetchItem::BodyFull { peek })
 }
 
+/// The bytes of `section` of a message: the whole of it, its header block, or
+/// a numbered part — transfer-encoded as stored, or decoded for BINARY.
+/// `None` when the message has no such part.
+fn section_bytes(raw: &[u8], parsed: Option<&ParsedMail>, section: &str, binary: bool) -> Option<Vec<u8>> {
+    match section {
+        "" => return Some(raw.to_vec()),
+        "HEADER" => return Some(split_headers(raw).0.to_vec()),
+        _ => {}
+    }
+    let mut part = parsed?;
+    for n in section.split('.') {
+        let n: usize = n.parse().ok()?;
+        part = if part.subparts.is_empty() {
+            // A single-part body is its own part 1.
+            (n == 1).then_some(part)?
+        } else {
+            part.subparts.get(n.checked_sub(1)?)?
+        };
+    }
+    if binary {
+        part.get_body_raw().ok()
+    } else {
+        Some(body_bytes(part).to_vec())
+    }
+}
+
 /// Render the data list for one message: `UID 3 FLAGS (\Seen) ...`
 /// Returns bytes because BODY[...] items are IMAP literals containing raw email.
 pub fn render_items(msg: &Message, items: &[FetchItem], force_uid: bool) -> Vec<u8> {
@@ -414,6 +463,42 @@ pub fn render_items(msg: &Message, items: &[FetchItem], force_uid: bool) -> Vec<
             FetchItem::ModSeq => {
                 push(&mut out, &format!("MODSEQ ({})", msg.modseq));
             }
+            // Only a message with Gmail attributes answers these, like a
+            // non-Gmail server that ignores them.
+            FetchItem::GmailMsgId => {
+                if let Some(gm) = &msg.gmail {
+                    push(&mut out, &format!("X-GM-MSGID {}", gm.msg_id));
+                }
+            }
+            FetchItem::GmailThrId => {
+                if let Some(gm) = &msg.gmail {
+                    push(&mut out, &format!("X-GM-THRID {}", gm.thread_id));
+                }
+            }
+            FetchItem::GmailLabels => {
+                if let Some(gm) = &msg.gmail {
+                    let labels: Vec<String> = gm
+                        .labels
+                        .iter()
+                        .map(|l| if l.starts_with('\\') { l.clone() } else { quoted(l) })
+                        .collect();
+                    push(&mut out, &format!("X-GM-LABELS ({})", labels.join(" ")));
+                }
+            }
+            // Likewise for OBJECTID: only a message with an EMAILID answers.
+            FetchItem::EmailId => {
+                if let Some(id) = &msg.email_id {
+                    push(&mut out, &format!("EMAILID ({})", id));
+                }
+            }
+            FetchItem::ThreadId => {
+                if msg.email_id.is_some() {
+                    match &msg.thread_id {
+                        Some(id) => push(&mut out, &format!("THREADID ({})", id)),
+                        None => push(&mut out, "THREADID NIL"),
+                    }
+                }
+            }
             FetchItem::Envelope => {
                 let v = parsed.as_ref().map(envelope).unwrap_or_else(|| b"NIL".to_vec());
                 push(&mut out, "ENVELOPE ");
@@ -430,6 +515,25 @@ pub fn render_items(msg: &Message, items: &[FetchItem], force_uid: bool) -> Vec<
                 push(&mut out, &format!("BODY[] {{{}}}\r\n", msg.raw.len()));
                 out.extend_from_slice(&msg.raw);
             }
+            FetchItem::BodySection { binary, section, partial } => {
+                let name = format!("{}[{}]", if *binary { "BINARY" } else { "BODY" }, section);
+                let Some(mut data) = section_bytes(&msg.raw, parsed.as_ref(), section, *binary) else {
+                    push(&mut out, &format!("{} NIL", name));
+                    continue;
+                };
+                let origin = match partial {
+                    Some((offset, length)) => {
+                        let start = (*offset).min(data.len());
+                        let end = start.saturating_add(*length).min(data.len());
+                        data = data[start..end].to_vec();
+                        format!("<{}>", offset)
+                    }
+                    None => String::new(),
+                };
+                let literal = if *binary { "~" } else { "" };
+                push(&mut out, &format!("{}{} {}{{{}}}\r\n", name, origin, literal, data.len()));
+                out.extend_from_slice(&data);
+            }
             FetchItem::BodyHeaderFields { fields, .. } => {
                 let data = header_fields(&msg.raw, fields);
                 let names = fields.join(" ");
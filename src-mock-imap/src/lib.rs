@@ -25,7 +25,7 @@ pub use state::{Mailbox, Message, ServerState};
 
 use commands::{Command, Response, Session};
 use std::collections::HashMap;
-use std::io::{BufRead, BufReader, Read, Write};
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
 use std::net::{SocketAddr, TcpListener, TcpStream};
 use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
@@ -104,6 +104,12 @@ impl MockImap {
         self.state.lock().unwrap().clone()
     }
 
+    /// Change server state mid-test — deliver or expunge mail, flip flags — as
+    /// another client would. Idling and NOOPing sessions report the change.
+    pub fn update(&self, f: impl FnOnce(&mut ServerState)) {
+        f(&mut self.state.lock().unwrap());
+    }
+
     /// Every command line received, in order.
     pub fn commands(&self) -> Vec<String> {
         self.log.lock().unwrap().clone()
@@ -218,9 +224,17 @@ fn handle_conn(
             }
         }
 
+        if cmd.name == "IDLE" {
+            idle(&cmd, &mut reader, &mut out, &state, &mut sess, &log)?;
+            responses_sent += 1;
+            continue;
+        }
+
         let response = {
             let mut st = state.lock().unwrap();
-            commands::dispatch(&cmd, &mut st, &mut sess, &actions)
+            let response = commands::dispatch(&cmd, &mut st, &mut sess, &actions);
+            commands::snapshot_selected(&st, &mut sess);
+            response
         };
         let close_after = response.close_after;
 
@@ -239,6 +253,52 @@ fn handle_conn(
     }
 }
 
+/// How often an idling connection looks for changes to push.
+const IDLE_TICK: Duration = Duration::from_millis(50);
+
+/// RFC 2177 IDLE: `+ idling`, then push whatever other connections (or
+/// `MockImap::update`) change in the selected mailbox until the client sends
+/// DONE. Handled here rather than in `dispatch` because it is the one command
+/// that keeps writing while it waits for input.
+fn idle(
+    cmd: &Command,
+    reader: &mut BufReader<TcpStream>,
+    out: &mut TcpStream,
+    state: &Arc<Mutex<ServerState>>,
+    sess: &mut Session,
+    log: &Arc<Mutex<Vec<String>>>,
+) -> std::io::Result<()> {
+    write_line(out, b"+ idling")?;
+    reader.get_ref().set_read_timeout(Some(IDLE_TICK))?;
+
+    let mut buf = Vec::new();
+    let read = loop {
+        match reader.read_until(b'\n', &mut buf) {
+            Ok(n) => break Ok(n),
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                let lines = commands::pending_updates(&state.lock().unwrap(), sess);
+                for line in lines {
+                    write_line(out, line.as_bytes())?;
+                }
+            }
+            Err(e) => break Err(e),
+        }
+    };
+    reader.get_ref().set_read_timeout(None)?;
+    if read? == 0 {
+        return Ok(());
+    }
+
+    let line = String::from_utf8_lossy(&buf).trim_end_matches(['\r', '\n']).to_string();
+    log.lock().unwrap().push(line.clone());
+    let reply = if line.eq_ignore_ascii_case("DONE") {
+        format!("{} OK IDLE terminated", cmd.tag)
+    } else {
+        format!("{} BAD Expected DONE", cmd.tag)
+    };
+    write_line(out, reply.as_bytes())
+}
+
 /// If the command line ends in `{n}` or `{n+}`, read the literal.
 /// Sync literals get a `+` continuation first; LITERAL+ (`{n+}`) does not.
 fn read_literal(
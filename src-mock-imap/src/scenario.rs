@@ -129,12 +129,28 @@ impl Scenario {
         self
     }
 
+    /// Advertise one more capability on top of the defaults.
+    pub fn with_cap(mut self, cap: &str) -> Self {
+        if !self.state.has_cap(cap) {
+            self.state.capabilities.push(cap.to_string());
+        }
+        self
+    }
+
     /// Drop a capability — the point is exercising our fallback paths.
     pub fn without_cap(mut self, cap: &str) -> Self {
         self.state.capabilities.retain(|c| !c.eq_ignore_ascii_case(cap));
         self
     }
 
+    /// Advertise QUOTA with one root limiting `resource` (STORAGE in KiB,
+    /// MESSAGE in messages). Call again for a second resource.
+    pub fn quota(mut self, resource: &str, limit: u64) -> Self {
+        self = self.with_cap("QUOTA");
+        self.state.quota.push((resource.to_string(), limit));
+        self
+    }
+
     pub fn greeting(mut self, g: &str) -> Self {
         self.greeting = Some(g.to_string());
         self
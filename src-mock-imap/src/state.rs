@@ -14,6 +14,23 @@ pub struct Message {
     /// are all derived from this — fixtures stay readable .eml text.
     #[serde(with = "raw_bytes")]
     pub raw: Vec<u8>,
+    /// X-GM-MSGID / X-GM-THRID / X-GM-LABELS, for Gmail scenarios. Labels are
+    /// kept in wire form: system labels as `\Inbox`, user labels as sent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gmail: Option<GmailAttrs>,
+    /// EMAILID / THREADID (RFC 8474). Kept through COPY and MOVE, as the RFC
+    /// asks; APPEND hands out a fresh EMAILID once OBJECTID is advertised.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thread_id: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GmailAttrs {
+    pub msg_id: u64,
+    pub thread_id: u64,
+    pub labels: Vec<String>,
 }
 
 mod raw_bytes {
@@ -38,6 +55,9 @@ impl Default for Message {
             internal_date: "01-Jan-2026 12:00:00 +0000".to_string(),
             modseq: 1,
             raw: Vec::new(),
+            gmail: None,
+            email_id: None,
+            thread_id: None,
         }
     }
 }
@@ -69,6 +89,21 @@ impl Message {
         self
     }
 
+    pub fn with_gmail(mut self, msg_id: u64, thread_id: u64, labels: &[&str]) -> Self {
+        self.gmail = Some(GmailAttrs {
+            msg_id,
+            thread_id,
+            labels: labels.iter().map(|s| s.to_string()).collect(),
+        });
+        self
+    }
+
+    pub fn with_object_ids(mut self, email_id: &str, thread_id: Option<&str>) -> Self {
+        self.email_id = Some(email_id.to_string());
+        self.thread_id = thread_id.map(str::to_string);
+        self
+    }
+
     pub fn has_flag(&self, flag: &str) -> bool {
         self.flags.iter().any(|f| f.eq_ignore_ascii_case(flag))
     }
@@ -84,6 +119,8 @@ pub struct Mailbox {
     pub uid_next: u32,
     pub highest_modseq: u64,
     pub messages: Vec<Message>,
+    /// `(uid, modseq)` of every expunge, for QRESYNC's `VANISHED (EARLIER)`.
+    pub vanished: Vec<(u32, u64)>,
 }
 
 // Likewise: a derived Default would give uid_validity 0 and uid_next 0, and a
@@ -103,6 +140,7 @@ impl Mailbox {
             uid_next: 1,
             highest_modseq: 1,
             messages: vec![],
+            vanished: vec![],
         }
     }
 
@@ -140,6 +178,26 @@ impl Mailbox {
         uid
     }
 
+    /// Remove messages as an EXPUNGE would: bump MODSEQ and remember the UIDs,
+    /// so a QRESYNC client can ask what vanished since. Returns the expunged
+    /// UIDs that existed.
+    pub fn expunge(&mut self, uids: &[u32]) -> Vec<u32> {
+        let gone: Vec<u32> = self
+            .messages
+            .iter()
+            .map(|m| m.uid)
+            .filter(|u| uids.contains(u))
+            .collect();
+        if gone.is_empty() {
+            return gone;
+        }
+        self.highest_modseq += 1;
+        let modseq = self.highest_modseq;
+        self.messages.retain(|m| !gone.contains(&m.uid));
+        self.vanished.extend(gone.iter().map(|u| (*u, modseq)));
+        gone
+    }
+
     /// 1-based sequence number of a UID.
     pub fn seq_of(&self, uid: u32) -> Option<u32> {
         self.messages.iter().position(|m| m.uid == uid).map(|i| i as u32 + 1)
@@ -188,6 +246,12 @@ pub struct ServerState {
     pub capabilities: Vec<String>,
     /// Credentials the server accepts. `None` accepts anything.
     pub expect_login: Option<(String, String)>,
+    /// Counter behind the EMAILIDs APPEND hands out.
+    pub next_email_id: u64,
+    /// Limits of the one quota root, `""`, as `(resource, limit)` — STORAGE in
+    /// KiB, MESSAGE in messages. Usage is counted over every mailbox. Served
+    /// once QUOTA is advertised; empty means no quota root at all.
+    pub quota: Vec<(String, u64)>,
 }
 
 impl Default for ServerState {
@@ -200,10 +264,13 @@ impl Default for ServerState {
                 "UIDPLUS".to_string(),
                 "MOVE".to_string(),
                 "CONDSTORE".to_string(),
+                "IDLE".to_string(),
                 "SPECIAL-USE".to_string(),
                 "AUTH=XOAUTH2".to_string(),
             ],
             expect_login: None,
+            next_email_id: 1,
+            quota: Vec::new(),
         }
     }
 }
@@ -220,4 +287,14 @@ impl ServerState {
     pub fn has_cap(&self, cap: &str) -> bool {
         self.capabilities.iter().any(|c| c.eq_ignore_ascii_case(cap))
     }
+
+    /// Current usage of a quota resource across all mailboxes.
+    pub fn quota_usage(&self, resource: &str) -> u64 {
+        let messages = self.mailboxes.iter().flat_map(|m| m.messages.iter());
+        match resource.to_uppercase().as_str() {
+            "STORAGE" => messages.map(|m| m.raw.len() as u64).sum::<u64>().div_ceil(1024),
+            "MESSAGE" => messages.count() as u64,
+            _ => 0,
+        }
+    }
 }
@@ -183,30 +183,14 @@ pub async fn run_with_backup(
         if let Ok(data_dir) = crate::vault::root(&app_handle) {
             let dir_path = data_dir.join("maildir").join(&account_id).join(&mailbox);
             let index_path = dir_path.join("local-index.json");
-
-            let mut existing: Vec<serde_json::Value> = if index_path.exists() {
-                tokio::fs::read_to_string(&index_path).await.ok()
-                    .and_then(|c| serde_json::from_str(&c).ok())
-                    .unwrap_or_default()
-            } else {
-                Vec::new()
-            };
-
-            let new_uids: std::collections::HashSet<u64> = index_entries.iter()
-                .filter_map(|e| e.get("uid").and_then(|u| u.as_u64()))
-                .collect();
-            existing.retain(|e| {
-                e.get("uid").and_then(|u| u.as_u64()).map_or(true, |uid| !new_uids.contains(&uid))
-            });
-            existing.extend(index_entries);
-
-            if let Ok(data) = serde_json::to_string(&existing) {
-                let tmp_path = index_path.with_extension("json.tmp");
-                if tokio::fs::write(&tmp_path, &data).await.is_ok() {
-                    let _ = tokio::fs::rename(&tmp_path, &index_path).await;
-                }
+            let count = index_entries.len();
+            let written = tokio::fs::create_dir_all(&dir_path).await
+                .map_err(|e| format!("mkdir: {}", e))
+                .and_then(|()| crate::append_local_index(&data_dir, &index_path, index_entries));
+            match written {
+                Ok(()) => info!("archive_emails: wrote {} entries to local-index.json", count),
+                Err(e) => warn!("archive_emails: local-index.json not updated: {}", e),
             }
-            info!("archive_emails: wrote {} entries to local-index.json", new_uids.len());
         }
     }
 
@@ -271,12 +255,6 @@ async fn fetch_and_store(
 
     let flags = ["archived".to_string(), "seen".to_string()];
     let cur_dir = super::maildir_cur_path(app_handle, account_id, mailbox)?;
-    fs::create_dir_all(&cur_dir).map_err(|e| format!("mkdir: {}", e))?;
-
-    if let Some(existing) = super::find_file_by_uid(&cur_dir, uid) {
-        let _ = fs::remove_file(&existing);
-    }
-
     let filename = super::build_maildir_filename(uid, &flags);
     let raw_bytes = base64::engine::general_purpose::STANDARD
         .decode(&email.raw_source)
@@ -291,7 +269,7 @@ async fn fetch_and_store(
         .chars().take(150).collect::<String>()
         .replace('\n', " ").replace('\r', "");
 
-    fs::write(cur_dir.join(&filename), &raw_bytes)
+    let stored = super::replace_message(&cur_dir, uid, &raw_bytes, &flags)
         .map_err(|e| format!("write .eml: {}", e))?;
 
     // Also write to backup location if configured
@@ -308,7 +286,7 @@ async fn fetch_and_store(
                 let eml_name = format!("{}.eml", filename);
                 let dst = backup_dir.join(&eml_name);
                 if super::find_msg_file_by_uid(&backup_dir, uid).is_none() {
-                    if let Err(e) = fs::write(&dst, &raw_bytes) {
+                    if let Err(e) = mailvault_core::maildir::compression::copy_to_mirror(&stored, &dst) {
                         warn!("archive_emails: external copy failed for UID {}: {}", uid, e);
                         external_copy_failed = true;
                     }
@@ -835,13 +835,18 @@ async fn run_imap_backup_inner(
 
 /// Sync files between app Maildir and backup location (bidirectional).
 /// - App dir files missing from backup → copy to backup keeping the Maildir
-///   name (`<uid>:2,<flags>.eml`) so flags survive the round trip
+///   name (`<uid>:2,<flags>.eml`) so flags survive the round trip, decompressed
+///   and, in an encrypted vault, sealed; a copy sealed under a key since
+///   rotated out (or left sealed after encryption was turned off) is replaced
 /// - Backup files missing from app dir → copy to app dir, restoring the flags
-///   encoded in the backup filename (legacy `<uid>.eml` copies have none)
+///   encoded in the backup filename (legacy `<uid>.eml` copies have none),
+///   stored the way the vault stores new messages
 /// Returns total files synced.
 fn sync_locations(app_dir: &std::path::Path, backup_dir: &std::path::Path) -> usize {
+    use mailvault_core::maildir::compression;
     use std::fs;
     let mut synced = 0;
+    let vault = compression::vault_of(app_dir);
 
     // Ensure both dirs exist; if backup dir can't be created (disconnected drive), skip
     let _ = fs::create_dir_all(app_dir);
@@ -857,9 +862,17 @@ fn sync_locations(app_dir: &std::path::Path, backup_dir: &std::path::Path) -> us
             let name = entry.file_name().to_string_lossy().to_string();
             let uid_str = name.split(|c: char| c == ':' || c == '.' || c == '_').next().unwrap_or(&name);
             let uid: u32 = match uid_str.parse() { Ok(u) => u, Err(_) => continue };
-            if super::find_msg_file_by_uid(backup_dir, uid).is_some() { continue; }
-            let dst_name = if name.ends_with(".eml") { name.clone() } else { format!("{}.eml", name) };
-            if fs::copy(entry.path(), backup_dir.join(&dst_name)).is_ok() { synced += 1; }
+            if let Some(existing) = super::find_msg_file_by_uid(backup_dir, uid) {
+                let stale = vault.is_some_and(|vault| compression::mirror_copy_stale(vault, &existing));
+                if !stale { continue; }
+                let _ = fs::remove_file(&existing);
+            }
+            // The mirror holds `.eml` files, whatever the vault compresses.
+            let plain = compression::strip_suffix(&name);
+            let dst_name = if plain.ends_with(".eml") { plain.to_string() } else { format!("{}.eml", plain) };
+            if compression::copy_to_mirror(&entry.path(), &backup_dir.join(&dst_name)).is_ok() {
+                synced += 1;
+            }
         }
     }
 
@@ -874,8 +887,11 @@ fn sync_locations(app_dir: &std::path::Path, backup_dir: &std::path::Path) -> us
             let uid: u32 = match uid_str.parse() { Ok(u) => u, Err(_) => continue };
             if super::find_file_by_uid(app_dir, uid).is_some() { continue; }
             let flags = super::parse_flags_from_filename(&name);
-            let dst = app_dir.join(format!("{}.eml", super::build_maildir_filename(uid, &flags)));
-            if fs::copy(entry.path(), &dst).is_ok() { synced += 1; }
+            let stored = compression::read_message(&entry.path())
+                .and_then(|raw| compression::encode_message(app_dir.parent().unwrap_or(app_dir), &raw));
+            let Ok((bytes, suffix)) = stored else { continue };
+            let dst = app_dir.join(format!("{}.eml{}", super::build_maildir_filename(uid, &flags), suffix));
+            if fs::write(&dst, bytes).is_ok() { synced += 1; }
         }
     }
 
@@ -976,16 +992,20 @@ async fn run_graph_backup(
                     Ok(raw_bytes) => {
                         let cur_dir =
                             crate::maildir_cur_path(&app_handle, &account_id, &mailbox_path)?;
-                        std::fs::create_dir_all(&cur_dir)
-                            .map_err(|e| format!("mkdir: {}", e))?;
 
                         if crate::find_file_by_uid(&cur_dir, uid_counter).is_none() {
                             let filename = crate::build_maildir_filename(
                                 uid_counter,
                                 &[] as &[String],
                             );
-                            std::fs::write(cur_dir.join(&filename), &raw_bytes)
-                                .map_err(|e| format!("write .eml: {}", e))?;
+                            // Compressed and sealed as the vault is set to.
+                            let stored = crate::store_new_message(
+                                &cur_dir,
+                                uid_counter,
+                                &raw_bytes,
+                                &[] as &[String],
+                            )
+                            .map_err(|e| format!("write .eml: {}", e))?;
 
                             // Also write to external backup if configured
                             if let Some(ref custom_path) = backup_path {
@@ -997,7 +1017,7 @@ async fn run_graph_backup(
                                     Ok(()) => {
                                         let dst = backup_dir.join(format!("{}.eml", filename));
                                         if crate::find_msg_file_by_uid(&backup_dir, uid_counter).is_none() {
-                                            if let Err(e) = std::fs::write(&dst, &raw_bytes) {
+                                            if let Err(e) = mailvault_core::maildir::compression::copy_to_mirror(&stored, &dst) {
                                                 warn!("backup(graph): external write failed: {}", e);
                                                 total_ext_failures += 1;
                                             }
@@ -1177,6 +1197,85 @@ pub async fn backup_scan_uids(
     Ok(Some(uids.into_iter().collect()))
 }
 
+/// Re-hash the vault against its manifests and cross-check the external
+/// mirror (when one is configured and reachable). Corrupt messages the
+/// mirror couldn't put back are fetched again from `account_json`'s server
+/// when `refetch` is set — the other accounts' are only reported, since
+/// this command only has credentials for the one.
+pub async fn verify_vault(
+    app_handle: tauri::AppHandle,
+    account_id: Option<String>,
+    account_json: Option<String>,
+    refetch: bool,
+) -> Result<serde_json::Value, String> {
+    let root = crate::vault::root(&app_handle)?;
+    let account: Option<ImapConfig> = match account_json.as_deref() {
+        Some(json) => Some(serde_json::from_str(json).map_err(|e| format!("Bad account JSON: {}", e))?),
+        None => None,
+    };
+
+    let mut emails: std::collections::HashMap<String, String> = crate::read_accounts_json(&app_handle)
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|a| Some((a.id, a.email?)))
+        .collect();
+    if let (Some(id), Some(account)) = (&account_id, &account) {
+        emails.insert(id.clone(), account.email.clone());
+    }
+
+    let configured = app_handle.path().app_data_dir().is_ok_and(|dd| {
+        external_location::get_external_location(&dd, external_location::SLOT_EXTERNAL_BACKUP).status
+            != "not_configured"
+    });
+    let (resolved, needs_release) = if configured { resolve_backup_path(&app_handle, None) } else { (None, false) };
+    let mirror = resolved.as_ref().map(|r| mailvault_core::maildir::verify::BackupMirror {
+        root: std::path::PathBuf::from(r),
+        emails,
+    });
+
+    let only = account_id.clone();
+    let report = tokio::task::spawn_blocking(move || {
+        mailvault_core::maildir::verify::verify_vault(&root, only.as_deref(), mirror.as_ref())
+    })
+    .await
+    .map_err(|e| format!("Vault verification failed: {}", e));
+    if needs_release {
+        if let Some(ref r) = resolved { release_backup_path(r); }
+    }
+    let report = report?;
+
+    let mut refetched = 0usize;
+    let mut refetch_failed = 0usize;
+    let mut refetch_skipped = 0usize;
+    for ((acc, mailbox), uids) in report.to_refetch() {
+        let can_refetch = refetch
+            && account_id.as_deref() == Some(acc.as_str())
+            && account.as_ref().is_some_and(|a| a.oauth2_transport.as_deref() != Some("graph"));
+        let Some(json) = account_json.clone().filter(|_| can_refetch) else {
+            refetch_skipped += uids.len();
+            continue;
+        };
+        info!("vault_verify: re-fetching {} corrupt messages in {}", uids.len(), mailbox);
+        let cancel = Arc::new(AtomicBool::new(false));
+        match crate::archive::run_with_backup(app_handle.clone(), acc, json, mailbox, uids.clone(), cancel, None, None).await {
+            Ok(progress) => {
+                refetched += progress.completed;
+                refetch_failed += progress.errors;
+            }
+            Err(e) => {
+                warn!("vault_verify: re-fetch failed: {}", e);
+                refetch_failed += uids.len();
+            }
+        }
+    }
+
+    let mut result = serde_json::to_value(&report).map_err(|e| format!("Serialization error: {}", e))?;
+    result["refetched"] = serde_json::json!(refetched);
+    result["refetchFailed"] = serde_json::json!(refetch_failed);
+    result["refetchSkipped"] = serde_json::json!(refetch_skipped);
+    Ok(result)
+}
+
 #[cfg(test)]
 mod tests {
     use super::sync_locations;
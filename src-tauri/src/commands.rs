@@ -418,6 +418,87 @@ pub async fn imap_get_email_light(
     }
 }
 
+/// The email for the reading pane without its attachments: BODYSTRUCTURE,
+/// header and the text parts only. Nothing goes to the vault — there is no
+/// whole message to store.
+#[tauri::command]
+pub async fn imap_get_email_parts(
+    pool: tauri::State<'_, ImapPool>,
+    account: ImapConfig,
+    uid: u32,
+    mailbox: Option<String>,
+) -> Result<serde_json::Value, String> {
+    let mailbox = mailbox.unwrap_or_else(|| "INBOX".to_string());
+    let has_binary = pool.has_capability(&account, imap::parts::BINARY).await;
+    let started = std::time::Instant::now();
+
+    let mb = mailbox.clone();
+    let fetch = with_priority(&pool, &account, move |mut session| async move {
+        let result = imap::parts::fetch_email_for_reading(&mut session, &mb, uid, has_binary).await
+            .map_err(|e| format!("Failed to fetch email: {}", e))?;
+        Ok((result, session, Some(mb)))
+    });
+    let email = match tokio::time::timeout(BODY_FETCH_TIMEOUT, fetch).await {
+        Ok(result) => result?,
+        Err(_) => {
+            return Err(format!(
+                "Timed out after {}s fetching message UID {} from {}",
+                BODY_FETCH_TIMEOUT.as_secs(), uid, mailbox
+            ))
+        }
+    };
+
+    info!(
+        "[CMD] imap_get_email_parts: uid={} mailbox={} binary={} found={} in {}ms",
+        uid, mailbox, has_binary, email.is_some(), started.elapsed().as_millis()
+    );
+    match email {
+        Some(e) => Ok(serde_json::json!({ "success": true, "email": e })),
+        None => Err("Email not found".to_string()),
+    }
+}
+
+/// Stream one MIME part (`section`, from `imap_get_email_parts`) — or the
+/// whole message without one — to `dest_path` in chunks. On the background
+/// lane with no timeout: a large attachment takes as long as it takes, and
+/// shouldn't hold a permit the reading pane needs.
+#[tauri::command]
+pub async fn imap_save_part(
+    pool: tauri::State<'_, ImapPool>,
+    account: ImapConfig,
+    uid: u32,
+    mailbox: Option<String>,
+    section: Option<String>,
+    dest_path: String,
+) -> Result<serde_json::Value, String> {
+    let mailbox = mailbox.unwrap_or_else(|| "INBOX".to_string());
+    let has_binary = pool.has_capability(&account, imap::parts::BINARY).await;
+
+    let (mb, dest) = (mailbox.clone(), dest_path.clone());
+    let bytes = with_background(&pool, &account, move |mut session| async move {
+        let part = match section {
+            Some(section) => {
+                let structure = imap::parts::fetch_structure(&mut session, &mb, uid)
+                    .await?
+                    .ok_or_else(|| format!("UID {} not found in {}", uid, mb))?;
+                let part = structure
+                    .parts
+                    .into_iter()
+                    .find(|p| p.section == section)
+                    .ok_or_else(|| format!("UID {} has no part {}", uid, section))?;
+                Some(part)
+            }
+            None => None,
+        };
+        let bytes = imap::parts::download(&mut session, &mb, uid, part.as_ref(), has_binary, std::path::Path::new(&dest)).await?;
+        Ok((bytes, session, Some(mb)))
+    })
+    .await?;
+
+    info!("[CMD] imap_save_part: uid={} mailbox={} {} bytes to {}", uid, mailbox, bytes, dest_path);
+    Ok(serde_json::json!({ "success": true, "path": dest_path, "bytes": bytes }))
+}
+
 // ── Set flags ───────────────────────────────────────────────────────────────
 
 #[tauri::command]
@@ -904,14 +985,8 @@ pub async fn graph_cache_mime(
 
     // Save to Maildir
     let cur_dir = crate::maildir_cur_path(&app_handle, &account_id, &mailbox)?;
-    std::fs::create_dir_all(&cur_dir)
-        .map_err(|e| format!("Failed to create Maildir directory: {}", e))?;
-
     if crate::find_file_by_uid(&cur_dir, uid).is_none() {
-        let filename = crate::build_maildir_filename(uid, &[] as &[String]);
-        let file_path = cur_dir.join(&filename);
-        std::fs::write(&file_path, &raw_bytes)
-            .map_err(|e| format!("Failed to write .eml file: {}", e))?;
+        let file_path = crate::store_new_message(&cur_dir, uid, &raw_bytes, &[])?;
         info!("Graph: cached UID {} to {:?} ({} bytes)", uid, file_path, raw_bytes.len());
     }
 
@@ -971,30 +1046,66 @@ pub async fn graph_move_emails(
 
 #[tauri::command]
 pub async fn imap_move_emails(
+    app_handle: tauri::AppHandle,
     pool: tauri::State<'_, ImapPool>,
     account: ImapConfig,
+    account_id: Option<String>,
     uids: Vec<u32>,
     source_mailbox: String,
     target_mailbox: String,
 ) -> Result<serde_json::Value, String> {
     let has_move = pool.has_capability(&account, "MOVE").await;
-
-    let moved = with_priority(&pool, &account, |mut session| async move {
-        let result = crate::move_emails::move_emails(
-            &mut session,
-            &source_mailbox,
-            &target_mailbox,
-            &uids,
-            has_move,
-        )
-        .await?;
-        Ok((result, session, Some(source_mailbox)))
+    let has_objectid = pool.has_capability(&account, imap::objectid::OBJECTID).await;
+    let moved = uids.len();
+
+    let (from, to) = (source_mailbox.clone(), target_mailbox.clone());
+    let mapping = with_priority(&pool, &account, |mut session| async move {
+        let result = imap::move_emails(&mut session, &from, &to, &uids, has_move, has_objectid).await?;
+        // A lookup by EMAILID leaves the target selected.
+        let selected = if has_objectid { None } else { Some(from) };
+        Ok((result, session, selected))
     })
     .await?;
 
+    // UIDPLUS said where each message went: the vault copy and the cached
+    // header follow it, so neither is fetched again. Not when the target's
+    // UIDVALIDITY isn't the generation the vault's copy is keyed under — those
+    // UIDs mean nothing there, and the next sync fetches the messages instead.
+    let mut remapped = 0;
+    if let (Some(mapping), Some(account_id)) = (&mapping, account_id) {
+        let root = crate::vault::root(&app_handle)?;
+        let (pairs, uid_validity) = (mapping.uids.clone(), mapping.uid_validity);
+        remapped = tokio::task::spawn_blocking(move || {
+            let target_cur = mailvault_core::maildir::cur_path(&root, &account_id, &target_mailbox);
+            let generation = target_cur.parent().and_then(mailvault_core::maildir::read_generation);
+            if generation != Some(uid_validity) {
+                info!(
+                    "[move] {} is at generation {:?}, server reported {}; leaving the moved messages to the next sync",
+                    target_mailbox, generation, uid_validity,
+                );
+                return 0;
+            }
+            let cache = |mailbox: &str| {
+                root.join("email_cache").join(mailvault_core::header_store::cache_base_name(&account_id, mailbox))
+            };
+            if let Err(e) = mailvault_core::header_store::remap(&cache(&source_mailbox), &cache(&target_mailbox), &pairs, false) {
+                tracing::warn!("[move] Header remap failed: {}", e);
+            }
+            mailvault_core::maildir::remap(&root, &account_id, &source_mailbox, &target_mailbox, &pairs, false)
+                .unwrap_or_else(|e| {
+                    tracing::warn!("[move] Vault remap failed: {}", e);
+                    0
+                })
+        })
+        .await
+        .map_err(|e| format!("Task join error: {}", e))?;
+    }
+
     Ok(serde_json::json!({
         "success": true,
-        "moved": moved
+        "moved": moved,
+        "uidMap": mapping.map(|m| m.uids.into_iter().collect::<std::collections::BTreeMap<u32, u32>>()),
+        "remapped": remapped,
     }))
 }
 
@@ -1045,6 +1156,29 @@ pub async fn backup_status(
     backup::get_backup_status(app_handle, account_id, account_json, backup_path).await
 }
 
+#[tauri::command]
+pub async fn vault_verify(
+    app_handle: tauri::AppHandle,
+    account_id: Option<String>,
+    account_json: Option<String>,
+    refetch: Option<bool>,
+) -> Result<serde_json::Value, String> {
+    backup::verify_vault(app_handle, account_id, account_json, refetch.unwrap_or(true)).await
+}
+
+/// Unlock an encrypted vault in the app process with its passphrase. The
+/// daemon is unlocked separately, through `vault.unlock`.
+#[tauri::command]
+pub async fn vault_unlock(app_handle: tauri::AppHandle, passphrase: String) -> Result<bool, String> {
+    let root = crate::vault::root(&app_handle)?;
+    tokio::task::spawn_blocking(move || {
+        mailvault_core::maildir::encryption::unlock_with_passphrase(&root, &passphrase)?;
+        Ok(mailvault_core::maildir::encryption::is_unlocked(&root))
+    })
+    .await
+    .map_err(|e| format!("Unlock task failed: {}", e))?
+}
+
 #[tauri::command]
 pub async fn backup_cancel(
     cancel_token: tauri::State<'_, backup::BackupCancelToken>,
@@ -1353,6 +1487,7 @@ pub async fn get_folder_mappings(
     dest_account: String,
     source_transport: String,
     dest_transport: String,
+    label_mapping: Option<imap::gmail::LabelMapping>,
 ) -> Result<Vec<migration::FolderMapping>, String> {
     let source_config: ImapConfig = serde_json::from_str(&source_account)
         .map_err(|e| format!("Bad source account JSON: {}", e))?;
@@ -1425,9 +1560,18 @@ pub async fn get_folder_mappings(
         .and_then(|f| f.delimiter.as_deref())
         .unwrap_or("/");
 
-    let mappings =
+    let mut mappings =
         migration::build_folder_mappings(&source_folders, &dest_folders, Some(src_delim), Some(dst_delim));
 
+    // Both lists came from live sessions, so both capability sets are cached.
+    if source_transport == "imap"
+        && dest_transport == "imap"
+        && pool.has_capability(&source_config, imap::gmail::GMAIL_EXT).await
+        && !pool.has_capability(&dest_config, imap::gmail::GMAIL_EXT).await
+    {
+        migration::apply_gmail_label_mapping(&mut mappings, &source_folders, label_mapping.unwrap_or_default());
+    }
+
     Ok(mappings)
 }
 
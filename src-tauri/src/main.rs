@@ -15,6 +15,7 @@ use tracing::{info, warn, error, Level};
 use tracing_subscriber::fmt::writer::MakeWriterExt;
 use tracing_appender::rolling::{RollingFileAppender, Rotation};
 use walkdir::WalkDir;
+use mailvault_core::header_store::HeaderStore;
 
 mod archive;
 mod backup;
@@ -27,7 +28,6 @@ pub use mailvault_core::graph;
 mod iap;
 pub use mailvault_core::imap;
 mod migration;
-mod move_emails;
 mod pending_delete;
 mod restore;
 pub use mailvault_core::oauth2;
@@ -445,6 +445,25 @@ async fn get_credentials() -> Result<serde_json::Value, String> {
     }
 }
 
+/// Unlock an encrypted vault with the keys the OS keyring holds for it. The
+/// daemon does the same for itself; each process reads the keyring on its own.
+pub fn unlock_vault_from_keyring(root: &Path) {
+    use mailvault_core::maildir::encryption;
+    if encryption::is_unlocked(root) {
+        return;
+    }
+    for key_id in encryption::keyring_keys(root) {
+        match Entry::new(KEYRING_SERVICE, &encryption::keyring_account(&key_id)).and_then(|e| e.get_password()) {
+            Ok(secret) => {
+                if let Err(e) = encryption::unlock_with_secret(root, &key_id, &secret) {
+                    warn!("unlock_vault_from_keyring: {}", e);
+                }
+            }
+            Err(e) => warn!("unlock_vault_from_keyring: vault key {}: {}", key_id, e),
+        }
+    }
+}
+
 // Legacy function - store single password (kept for migration)
 #[tauri::command]
 fn store_password(account_id: String, password: String) -> Result<(), String> {
@@ -752,9 +771,10 @@ fn send_notification(app_handle: tauri::AppHandle, title: String, body: String)
     Ok(())
 }
 
-// Email cache — per-email JSON sidecars
-// Directory structure: email_cache/<accountId>_<mailbox>/_meta.json + <uid>.json per email
-// Old monolithic format (single .json file) is auto-migrated on first save.
+// Email cache — per-mailbox header store (see `mailvault_core::header_store`)
+// Directory structure: email_cache/<accountId>_<mailbox>/_meta.json + headers.log/headers.flags
+// Old per-email `<uid>.json` sidecars are folded into the store on first open;
+// the old monolithic format (single .json file) is auto-migrated on first save.
 
 fn cache_base_name(account_id: &str, mailbox: &str) -> String {
     format!("{}_{}",
@@ -802,38 +822,30 @@ async fn save_email_cache(app_handle: tauri::AppHandle, account_id: String, mail
     fs::write(&meta_path, meta_json)
         .map_err(|e| format!("save_email_cache: failed to write _meta.json: {}", e))?;
 
-    // Write individual email files. Overwrite: the caller's copy carries the
-    // current flags, and skipping existing files meant a read/star/unread
-    // change never reached disk.
+    let mut store = HeaderStore::open(&sidecar_dir)
+        .map_err(|e| format!("save_email_cache: {}", e))?;
+
+    // Write the headers. Overwrite: the caller's copy carries the current
+    // flags, and skipping cached ones meant a read/star/unread change never
+    // reached disk.
     if let Some(emails) = parsed.get("emails").and_then(|e| e.as_array()) {
-        let mut written = 0usize;
-        for email in emails {
-            if let Some(uid) = email.get("uid").and_then(|u| u.as_u64()) {
-                let email_json = serde_json::to_string(email)
-                    .map_err(|e| format!("save_email_cache: failed to serialize email {}: {}", uid, e))?;
-                fs::write(sidecar_dir.join(format!("{}.json", uid)), email_json)
-                    .map_err(|e| format!("save_email_cache: failed to write email {}: {}", uid, e))?;
-                written += 1;
-            }
-        }
-        info!("Email cache saved: {} files in {}", written, base_name);
+        let written = store.put(emails)
+            .map_err(|e| format!("save_email_cache: {}", e))?;
+        info!("Email cache saved: {} headers in {}", written, base_name);
     }
 
     // Remove only UIDs the caller explicitly says are gone.
     //
-    // This used to delete every sidecar not present in `emails` — but the store
+    // This used to delete every header not present in `emails` — but the store
     // holds ~500 headers while the cache holds the whole mailbox, so an ordinary
     // save truncated a 14k-message cache to 500. The list was then re-downloaded
     // from the server page by page, which is what made restarts slow.
     if let Some(removed) = parsed.get("removedUids").and_then(|v| v.as_array()) {
-        let mut deleted = 0usize;
-        for uid in removed.iter().filter_map(|v| v.as_u64()) {
-            if fs::remove_file(sidecar_dir.join(format!("{}.json", uid))).is_ok() {
-                deleted += 1;
-            }
-        }
+        let uids: Vec<u32> = removed.iter().filter_map(|v| v.as_u64()).map(|u| u as u32).collect();
+        let deleted = store.remove(&uids)
+            .map_err(|e| format!("save_email_cache: {}", e))?;
         if deleted > 0 {
-            info!("Email cache: removed {} expunged sidecars in {}", deleted, base_name);
+            info!("Email cache: removed {} expunged headers in {}", deleted, base_name);
         }
     }
 
@@ -898,10 +910,10 @@ fn delete_mailbox_cache(app_handle: tauri::AppHandle, account_id: String) -> Res
 
 // ── Graph ID map cache (UID → Graph message ID) ────────────────────────
 
-/// Lives in the mailbox's sidecar directory alongside the `<uid>.json` files.
+/// Lives in the mailbox's cache directory alongside its header store.
 /// Its presence is what tells a reader that this mailbox's UIDs were allocated
 /// by us over a date-ordered Graph listing rather than issued by an IMAP server
-/// in arrival order — see `load_from_sidecars`.
+/// in arrival order — see `load_from_store`.
 const GRAPH_ID_MAP_FILE: &str = "graph_id_map.json";
 
 #[tauri::command]
@@ -949,9 +961,9 @@ fn load_email_cache(app_handle: tauri::AppHandle, account_id: String, mailbox: S
     let sidecar_dir = base_dir.join(&base_name);
     let meta_file = sidecar_dir.join("_meta.json");
 
-    // Try sidecar format first
+    // Try the header store first
     if meta_file.exists() {
-        return load_from_sidecars(&sidecar_dir, &meta_file, None);
+        return load_from_store(&sidecar_dir, &meta_file, None);
     }
 
     // Fall back to old monolithic format
@@ -966,7 +978,7 @@ fn load_email_cache(app_handle: tauri::AppHandle, account_id: String, mailbox: S
     Ok(None)
 }
 
-/// Load only the N most recent emails from sidecar cache (fast initial display)
+/// Load only the N most recent emails from the header store (fast initial display)
 #[tauri::command]
 async fn load_email_cache_partial(app_handle: tauri::AppHandle, account_id: String, mailbox: String, limit: usize) -> Result<Option<String>, String> {
     tokio::task::spawn_blocking(move || {
@@ -977,9 +989,9 @@ async fn load_email_cache_partial(app_handle: tauri::AppHandle, account_id: Stri
         let sidecar_dir = base_dir.join(&base_name);
         let meta_file = sidecar_dir.join("_meta.json");
 
-        // Try sidecar format first
+        // Try the header store first
         if meta_file.exists() {
-            return load_from_sidecars(&sidecar_dir, &meta_file, Some(limit));
+            return load_from_store(&sidecar_dir, &meta_file, Some(limit));
         }
 
         // Fall back to old monolithic format (parse and truncate in memory)
@@ -1012,8 +1024,8 @@ async fn load_email_cache_partial(app_handle: tauri::AppHandle, account_id: Stri
     }).await.map_err(|e| format!("Task join error: {}", e))?
 }
 
-/// Load email headers from sidecar cache for specific UIDs only.
-/// Much faster than parsing .eml files — reads pre-cached JSON sidecars.
+/// Load email headers from the header store for specific UIDs only.
+/// Much faster than parsing .eml files — reads the pre-cached headers.
 #[tauri::command]
 async fn load_email_cache_by_uids(
     app_handle: tauri::AppHandle,
@@ -1029,35 +1041,26 @@ async fn load_email_cache_by_uids(
         let sidecar_dir = base_dir.join(&base_name);
 
         if !sidecar_dir.exists() {
-            info!("load_email_cache_by_uids: sidecar dir does not exist");
+            info!("load_email_cache_by_uids: cache dir does not exist");
             return Ok(Vec::new());
         }
 
-        let mut emails: Vec<serde_json::Value> = Vec::with_capacity(uids.len());
-        let mut found = 0usize;
-        for uid in &uids {
-            let file_path = sidecar_dir.join(format!("{}.json", uid));
-            if let Ok(data) = fs::read_to_string(&file_path) {
-                if let Ok(email) = serde_json::from_str(&data) {
-                    emails.push(email);
-                    found += 1;
-                }
-            }
-        }
+        let store = HeaderStore::open(&sidecar_dir)?;
+        let emails: Vec<serde_json::Value> = uids.iter()
+            .filter_map(|uid| store.get(*uid).cloned())
+            .collect();
 
-        info!("load_email_cache_by_uids: found {}/{} UIDs in sidecar cache", found, uids.len());
+        info!("load_email_cache_by_uids: found {}/{} UIDs in header store", emails.len(), uids.len());
         Ok(emails)
     }).await.map_err(|e| format!("Task join error: {}", e))?
 }
 
-/// List the UIDs a mailbox has sidecars for, plus which of them were written
+/// List the UIDs a mailbox has cached headers for, plus which of them changed
 /// after `since_ms`.
 ///
-/// Readdir only — no file is opened and nothing is parsed, so this costs one
-/// directory scan regardless of mailbox size. That's what lets a caller holding
-/// a stale in-memory header set re-read only the handful of messages that moved
-/// instead of all 15,000 (`load_from_sidecars` is one read + one parse PER
-/// message).
+/// One read of the header store, which records when each header or its flags
+/// last changed. That's what lets a caller holding a stale in-memory header
+/// set re-read only the handful of messages that moved instead of all 15,000.
 #[tauri::command]
 async fn list_cached_uids(
     app_handle: tauri::AppHandle,
@@ -1074,57 +1077,21 @@ async fn list_cached_uids(
             return Ok(serde_json::json!({ "uids": [], "changed": [] }));
         }
 
-        let mut uids: Vec<u64> = Vec::new();
-        let mut changed: Vec<u64> = Vec::new();
-
-        if let Ok(entries) = fs::read_dir(&sidecar_dir) {
-            for entry in entries.flatten() {
-                let name = entry.file_name().to_string_lossy().to_string();
-                if name == "_meta.json" { continue; }
-                let Some(uid) = name.strip_suffix(".json").and_then(|s| s.parse::<u64>().ok())
-                else { continue };
-                uids.push(uid);
-
-                // `DirEntry::metadata` is a stat the readdir usually already
-                // primed — cheap next to opening the file.
-                if let Some(since) = since_ms {
-                    let mtime_ms = entry
-                        .metadata()
-                        .ok()
-                        .and_then(|m| m.modified().ok())
-                        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
-                        .map(|d| d.as_millis() as f64);
-                    // Unreadable mtime counts as changed — an extra read is
-                    // always safer than serving a header we can't vouch for.
-                    if mtime_ms.map_or(true, |m| m > since) {
-                        changed.push(uid);
-                    }
-                }
-            }
-        }
+        let store = HeaderStore::open(&sidecar_dir)?;
+        let uids: Vec<u32> = store.uids().collect();
+        let changed: Vec<u32> = match since_ms {
+            Some(since) => store.changed_since(since.max(0.0) as u64),
+            None => Vec::new(),
+        };
 
         info!(
-            "list_cached_uids: {} sidecars, {} changed since {:?}",
+            "list_cached_uids: {} headers, {} changed since {:?}",
             uids.len(), changed.len(), since_ms
         );
         Ok(serde_json::json!({ "uids": uids, "changed": changed }))
     }).await.map_err(|e| format!("Task join error: {}", e))?
 }
 
-/// Read and parse the named sidecars, skipping any that are missing or corrupt.
-fn read_sidecars(sidecar_dir: &Path, uids: &[u64]) -> Vec<serde_json::Value> {
-    let mut emails = Vec::with_capacity(uids.len());
-    for uid in uids {
-        let file_path = sidecar_dir.join(format!("{}.json", uid));
-        if let Ok(data) = fs::read_to_string(&file_path) {
-            if let Ok(email) = serde_json::from_str(&data) {
-                emails.push(email);
-            }
-        }
-    }
-    emails
-}
-
 /// A cached header's received time in epoch milliseconds, or `i64::MIN` when it
 /// carries no date we can parse. Undated rows sort last: a header we can't place
 /// in time must never displace one we can.
@@ -1145,12 +1112,12 @@ fn header_uid(email: &serde_json::Value) -> u64 {
     email.get("uid").and_then(|u| u.as_u64()).unwrap_or(0)
 }
 
-/// Read `limit` sidecars' worth of the newest cached headers, or all of them
-/// when `limit` is None.
+/// Read `limit` of the newest cached headers, or all of them when `limit` is
+/// None.
 ///
 /// "Newest" is the N highest UIDs for IMAP, where the server issues UIDs in
-/// arrival order — the readdir alone picks them, and no file is opened that
-/// isn't returned.
+/// arrival order — the store keeps them sorted, so the pick is a walk from
+/// the top.
 ///
 /// Graph offers no such guarantee, so those mailboxes are ordered by the header
 /// date instead. Its listing is `receivedDateTime desc`, so the seed handed uid
@@ -1160,61 +1127,35 @@ fn header_uid(email: &serde_json::Value) -> u64 {
 /// sorting by it served up the OLDEST cached messages. `graph_id_map.json` is
 /// the marker: it is written by the same allocator that hands out those uids,
 /// so it exists for exactly the mailboxes whose uids can't be trusted to sort.
-///
-/// ponytail: the date path reads every sidecar, not `limit` of them — one read
-/// per cached message on a Graph mailbox. If that shows up on the startup path,
-/// index uid → date in `_meta.json` on write and sort from that instead.
-fn load_from_sidecars(sidecar_dir: &Path, meta_file: &Path, limit: Option<usize>) -> Result<Option<String>, String> {
+fn load_from_store(sidecar_dir: &Path, meta_file: &Path, limit: Option<usize>) -> Result<Option<String>, String> {
     // Read metadata
     let meta_data = fs::read_to_string(meta_file)
         .map_err(|e| format!("Failed to read _meta.json: {}", e))?;
     let meta: serde_json::Value = serde_json::from_str(&meta_data)
         .map_err(|e| format!("Failed to parse _meta.json: {}", e))?;
 
-    // List all UID files, parse UIDs as numbers for sorting
-    let mut uids: Vec<u64> = Vec::new();
-    if let Ok(entries) = fs::read_dir(sidecar_dir) {
-        for entry in entries.flatten() {
-            let name = entry.file_name().to_string_lossy().to_string();
-            if name == "_meta.json" { continue; }
-            if let Some(uid_str) = name.strip_suffix(".json") {
-                if let Ok(uid) = uid_str.parse::<u64>() {
-                    uids.push(uid);
-                }
-            }
-        }
-    }
-
-    let total_cached = uids.len();
+    let store = HeaderStore::open(sidecar_dir)?;
+    let total_cached = store.len();
     let uid_tracks_arrival = !sidecar_dir.join(GRAPH_ID_MAP_FILE).exists();
 
     let emails: Vec<serde_json::Value> = if uid_tracks_arrival {
-        // Highest UIDs are the newest — pick them off the readdir, then read
-        // only those files.
-        uids.sort_unstable_by(|a, b| b.cmp(a));
-        if let Some(limit) = limit {
-            uids.truncate(limit);
-        }
-        read_sidecars(sidecar_dir, &uids)
+        // Highest UIDs are the newest.
+        store.headers().rev().take(limit.unwrap_or(usize::MAX)).cloned().collect()
     } else {
-        // UID says nothing about age here, so every header has to be read
-        // before the newest can be named. Ties break on UID only to keep the
-        // result stable across filesystems — same-second arrivals have no
-        // meaningful order.
-        let mut all = read_sidecars(sidecar_dir, &uids);
+        // UID says nothing about age here, so every header has to be looked
+        // at before the newest can be named. Ties break on UID only to keep
+        // the result stable — same-second arrivals have no meaningful order.
+        let mut all: Vec<&serde_json::Value> = store.headers().collect();
         all.sort_by(|a, b| {
             header_date_ms(b)
                 .cmp(&header_date_ms(a))
                 .then_with(|| header_uid(b).cmp(&header_uid(a)))
         });
-        if let Some(limit) = limit {
-            all.truncate(limit);
-        }
-        all
+        all.into_iter().take(limit.unwrap_or(usize::MAX)).cloned().collect()
     };
 
     info!(
-        "Sidecar cache loaded: {} of {} emails (limit: {:?}, order: {})",
+        "Header store loaded: {} of {} emails (limit: {:?}, order: {})",
         emails.len(), total_cached, limit,
         if uid_tracks_arrival { "uid" } else { "date" }
     );
@@ -1234,7 +1175,7 @@ fn load_from_sidecars(sidecar_dir: &Path, meta_file: &Path, limit: Option<usize>
 
     serde_json::to_string(&result)
         .map(|s| Some(s))
-        .map_err(|e| format!("Failed to serialize sidecar cache: {}", e))
+        .map_err(|e| format!("Failed to serialize header cache: {}", e))
 }
 
 /// Load only cache metadata (no emails) — fast, for delta-sync parameters
@@ -1247,22 +1188,16 @@ fn load_email_cache_meta(app_handle: tauri::AppHandle, account_id: String, mailb
     let sidecar_dir = base_dir.join(&base_name);
     let meta_file = sidecar_dir.join("_meta.json");
 
-    // Try sidecar format
+    // Try the header store
     if meta_file.exists() {
         let meta_data = fs::read_to_string(&meta_file)
             .map_err(|e| format!("Failed to read _meta.json: {}", e))?;
         let mut meta: serde_json::Value = serde_json::from_str(&meta_data)
             .map_err(|e| format!("Failed to parse _meta.json: {}", e))?;
 
-        // Count message sidecars only. `_meta.json` and `graph_id_map.json` sit
-        // in the same directory and are not messages; counting them reported a
-        // mailbox with nothing cached as holding one.
-        let total_cached = fs::read_dir(&sidecar_dir)
-            .map(|entries| entries.flatten().filter(|e| {
-                let name = e.file_name().to_string_lossy().to_string();
-                name.strip_suffix(".json").is_some_and(|s| s.parse::<u64>().is_ok())
-            }).count())
-            .unwrap_or(0);
+        // Count cached headers only. `_meta.json` and `graph_id_map.json` sit
+        // in the same directory and are not messages.
+        let total_cached = HeaderStore::open(&sidecar_dir).map(|s| s.len()).unwrap_or(0);
 
         meta.as_object_mut().map(|o| o.insert("totalCached".to_string(), serde_json::json!(total_cached)));
 
@@ -1880,6 +1815,28 @@ pub(crate) fn parse_flags_from_filename(filename: &str) -> Vec<String> {
     }
 }
 
+/// Core's flag names — `\Seen`, `\Answered`, keywords as stored — in the
+/// words this file uses. For messages in a standard-layout mailbox, whose
+/// names `parse_flags_from_filename` can't read.
+fn app_flags(flags: &[String]) -> Vec<String> {
+    flags
+        .iter()
+        .map(|f| match f.as_str() {
+            "\\Answered" => "replied".to_string(),
+            "\\Deleted" => "trashed".to_string(),
+            other => other.trim_start_matches('\\').to_lowercase(),
+        })
+        .collect()
+}
+
+/// Whether writes to this mailbox go through core's standard layout: it is in
+/// it, or it doesn't exist yet and that is the vault's default.
+fn is_standard_mailbox(cur_dir: &Path) -> bool {
+    cur_dir.parent().is_some_and(|m| {
+        mailvault_core::maildir::standard::layout_of(m) == mailvault_core::maildir::Layout::Standard
+    })
+}
+
 pub fn build_maildir_filename(uid: u32, flags: &[String]) -> String {
     let mut flag_chars: Vec<char> = Vec::new();
     for f in flags {
@@ -1915,66 +1872,88 @@ pub fn find_msg_file_by_uid(dir: &Path, uid: u32) -> Option<PathBuf> {
     None
 }
 
+/// The vault file for `uid`, through core's per-mailbox uid index — no
+/// directory listing per lookup.
 pub fn find_file_by_uid(dir: &Path, uid: u32) -> Option<PathBuf> {
-    let prefix = format!("{}:", uid);
-    if let Ok(entries) = fs::read_dir(dir) {
-        for entry in entries.flatten() {
-            let name = entry.file_name().to_string_lossy().to_string();
-            if name.starts_with(&prefix) {
-                return Some(entry.path());
-            }
-        }
-    }
-    None
+    mailvault_core::maildir::find_by_uid(dir, uid)
 }
 
 /// Delete every Maildir file in `cur_dir` whose uid is in `uids`.
-/// One directory pass — the per-uid `find_file_by_uid` rescans the whole
-/// directory each call, which is quadratic over a bulk selection.
-/// Vault filenames are always `<uid>:<flags>` (see `build_maildir_filename`),
-/// so the uid is the run of digits before the first ':'.
+/// Each uid is an index lookup, so a bulk selection costs its own size, not
+/// the mailbox's.
 pub fn delete_maildir_files(cur_dir: &Path, uids: &std::collections::HashSet<u32>) -> usize {
     let mut removed = 0usize;
-    if let Ok(entries) = fs::read_dir(cur_dir) {
-        for entry in entries.flatten() {
-            let name = entry.file_name().to_string_lossy().to_string();
-            let uid = match name.split(':').next().and_then(|s| s.parse::<u32>().ok()) {
-                Some(u) => u,
-                None => continue,
-            };
-            if !uids.contains(&uid) {
-                continue;
-            }
-            match fs::remove_file(entry.path()) {
-                Ok(()) => removed += 1,
-                Err(e) => warn!("maildir purge: failed to remove {:?}: {}", entry.path(), e),
-            }
+    for &uid in uids {
+        let Some(path) = find_file_by_uid(cur_dir, uid) else { continue };
+        let result = mailvault_core::uid_index::change(cur_dir, uid, || {
+            fs::remove_file(&path).map_err(|e| e.to_string())?;
+            Ok(None)
+        });
+        match result {
+            Ok(_) => removed += 1,
+            Err(e) => warn!("maildir purge: failed to remove {:?}: {}", path, e),
         }
     }
+    if let Some(mailbox_dir) = cur_dir.parent() {
+        let uids: Vec<u32> = uids.iter().copied().collect();
+        let _ = mailvault_core::maildir::manifest::forget(mailbox_dir, &uids);
+    }
     removed
 }
 
 /// Drop `uids` from a mailbox's `local-index.json` in a single read-modify-write.
 /// A missing index is not an error — nothing was ever indexed.
 pub fn prune_local_index(
+    data_dir: &Path,
     index_path: &Path,
     uids: &std::collections::HashSet<u32>,
 ) -> Result<(), String> {
     if !index_path.exists() {
         return Ok(());
     }
-    let content = fs::read_to_string(index_path)
-        .map_err(|e| format!("Failed to read local index: {}", e))?;
-    let mut entries: Vec<serde_json::Value> = serde_json::from_str(&content).unwrap_or_default();
+    let mut entries = read_local_index(data_dir, index_path)?;
     entries.retain(|e| {
         e.get("uid")
             .and_then(|u| u.as_u64())
             .map(|u| !uids.contains(&(u as u32)))
             .unwrap_or(true)
     });
-    let data = serde_json::to_string(&entries)
+    write_local_index(data_dir, index_path, &entries)
+}
+
+/// A mailbox's `local-index.json` entries, none if it has no index. The index
+/// holds subjects and snippets, so it is sealed like the vault's other
+/// sidecars whenever the vault is encrypted.
+pub(crate) fn read_local_index(data_dir: &Path, index_path: &Path) -> Result<Vec<serde_json::Value>, String> {
+    if !index_path.exists() {
+        return Ok(Vec::new());
+    }
+    let bytes = mailvault_core::maildir::encryption::read_sidecar(data_dir, index_path)?;
+    Ok(serde_json::from_slice(&bytes).unwrap_or_default())
+}
+
+pub(crate) fn write_local_index(data_dir: &Path, index_path: &Path, entries: &[serde_json::Value]) -> Result<(), String> {
+    let data = serde_json::to_vec(entries)
         .map_err(|e| format!("Failed to serialize local index: {}", e))?;
-    fs::write(index_path, &data).map_err(|e| format!("Failed to write local index: {}", e))
+    mailvault_core::maildir::encryption::write_sidecar(data_dir, index_path, &data)
+}
+
+/// Add `new_entries` to a mailbox's `local-index.json`, replacing any entries
+/// it already has for the same uids.
+pub(crate) fn append_local_index(
+    data_dir: &Path,
+    index_path: &Path,
+    new_entries: Vec<serde_json::Value>,
+) -> Result<(), String> {
+    let mut existing = read_local_index(data_dir, index_path)?;
+    let new_uids: std::collections::HashSet<u64> = new_entries.iter()
+        .filter_map(|e| e.get("uid").and_then(|u| u.as_u64()))
+        .collect();
+    existing.retain(|e| {
+        e.get("uid").and_then(|u| u.as_u64()).map_or(true, |uid| !new_uids.contains(&uid))
+    });
+    existing.extend(new_entries);
+    write_local_index(data_dir, index_path, &existing)
 }
 
 fn parse_address_str(header_value: &str) -> Vec<MaildirAddress> {
@@ -2288,6 +2267,12 @@ fn parse_eml_bytes(raw: &[u8], uid: u32, flags: Vec<String>) -> Result<ParsedEma
     })
 }
 
+/// `raw_bytes` as the vault stores them in `cur_dir`'s mailbox — compressed
+/// and sealed as it is set to — and the filename suffix that goes with it.
+fn compress_for(cur_dir: &Path, raw_bytes: &[u8]) -> Result<(Vec<u8>, &'static str), String> {
+    mailvault_core::maildir::compression::encode_message(cur_dir.parent().unwrap_or(cur_dir), raw_bytes)
+}
+
 /// Store an .eml file to Maildir — callable from commands.rs
 /// Only writes if the file doesn't already exist for this UID.
 pub fn maildir_store_raw(
@@ -2301,28 +2286,73 @@ pub fn maildir_store_raw(
     use base64::Engine;
 
     let cur_dir = maildir_cur_path(app_handle, account_id, mailbox)?;
-    fs::create_dir_all(&cur_dir)
-        .map_err(|e| format!("Failed to create Maildir directory: {}", e))?;
 
     // Skip if already cached on disk
     if find_file_by_uid(&cur_dir, uid).is_some() {
         return Ok(());
     }
 
-    let filename = build_maildir_filename(uid, flags);
-    let file_path = cur_dir.join(&filename);
-
     let raw_bytes = base64::engine::general_purpose::STANDARD
         .decode(raw_source_base64)
         .map_err(|e| format!("Failed to decode base64: {}", e))?;
 
-    fs::write(&file_path, &raw_bytes)
-        .map_err(|e| format!("Failed to write .eml file: {}", e))?;
-
+    let file_path = store_new_message(&cur_dir, uid, &raw_bytes, flags)?;
     info!("Stored email UID {} to {:?} ({} bytes)", uid, file_path, raw_bytes.len());
     Ok(())
 }
 
+/// Write a message the vault doesn't have yet into the mailbox at `cur_dir`:
+/// compressed and sealed as the vault is set to, under the app's filename,
+/// and recorded in the uid index. A standard-layout mailbox gets core's
+/// delivery. The caller checks for an existing copy first.
+pub(crate) fn store_new_message(cur_dir: &Path, uid: u32, raw_bytes: &[u8], flags: &[String]) -> Result<PathBuf, String> {
+    if is_standard_mailbox(cur_dir) {
+        return mailvault_core::maildir::store_in(cur_dir, uid, raw_bytes, flags);
+    }
+    fs::create_dir_all(cur_dir)
+        .map_err(|e| format!("Failed to create Maildir directory: {}", e))?;
+    let (stored, suffix) = compress_for(cur_dir, raw_bytes)?;
+    let file_path = cur_dir.join(format!("{}{}", build_maildir_filename(uid, flags), suffix));
+    mailvault_core::uid_index::change(cur_dir, uid, || {
+        mailvault_core::maildir::integrity::write_atomic(&cur_dir.with_file_name("tmp"), &file_path, &stored)?;
+        Ok(Some(file_path.clone()))
+    })?;
+    mailvault_core::maildir::record_written(cur_dir, uid, raw_bytes, stored.len() as u64);
+    Ok(file_path)
+}
+
+/// Write a message into the mailbox at `cur_dir`, replacing any copy of `uid`
+/// already there: the new copy goes down atomically, compressed and sealed as
+/// the vault is set to, and is recorded in the uid index and manifest before
+/// the old one is removed.
+pub(crate) fn replace_message(cur_dir: &Path, uid: u32, raw_bytes: &[u8], flags: &[String]) -> Result<PathBuf, String> {
+    fs::create_dir_all(cur_dir)
+        .map_err(|e| format!("Failed to create Maildir directory: {}", e))?;
+    let existing = find_file_by_uid(cur_dir, uid);
+    if is_standard_mailbox(cur_dir) {
+        // A new delivery under a new name; the old copy's uidlist line is
+        // dropped when the uidlist is next tidied.
+        if let Some(existing) = existing {
+            mailvault_core::uid_index::change(cur_dir, uid, || {
+                fs::remove_file(&existing).map_err(|e| format!("Failed to replace .eml file: {}", e))?;
+                Ok(None)
+            })?;
+        }
+        return mailvault_core::maildir::store_in(cur_dir, uid, raw_bytes, flags);
+    }
+    let (stored, suffix) = compress_for(cur_dir, raw_bytes)?;
+    let file_path = cur_dir.join(format!("{}{}", build_maildir_filename(uid, flags), suffix));
+    mailvault_core::uid_index::change(cur_dir, uid, || {
+        mailvault_core::maildir::integrity::write_atomic(&cur_dir.with_file_name("tmp"), &file_path, &stored)?;
+        if let Some(existing) = existing.as_ref().filter(|p| **p != file_path) {
+            let _ = fs::remove_file(existing);
+        }
+        Ok(Some(file_path.clone()))
+    })?;
+    mailvault_core::maildir::record_written(cur_dir, uid, raw_bytes, stored.len() as u64);
+    Ok(file_path)
+}
+
 #[tauri::command]
 fn maildir_store(
     app_handle: tauri::AppHandle,
@@ -2335,24 +2365,12 @@ fn maildir_store(
     use base64::Engine;
 
     let cur_dir = maildir_cur_path(&app_handle, &account_id, &mailbox)?;
-    fs::create_dir_all(&cur_dir)
-        .map_err(|e| format!("Failed to create Maildir directory: {}", e))?;
-
-    // Remove existing file for this UID if any (maildir_store always overwrites)
-    if let Some(existing) = find_file_by_uid(&cur_dir, uid) {
-        let _ = fs::remove_file(&existing);
-    }
-
-    let filename = build_maildir_filename(uid, &flags);
-    let file_path = cur_dir.join(&filename);
-
     let raw_bytes = base64::engine::general_purpose::STANDARD
         .decode(&raw_source_base64)
         .map_err(|e| format!("Failed to decode base64: {}", e))?;
 
-    fs::write(&file_path, &raw_bytes)
-        .map_err(|e| format!("Failed to write .eml file: {}", e))?;
-
+    // maildir_store always overwrites an existing copy of the UID.
+    let file_path = replace_message(&cur_dir, uid, &raw_bytes, &flags)?;
     info!("Stored email UID {} to {:?} ({} bytes)", uid, file_path, raw_bytes.len());
     Ok(())
 }
@@ -2370,13 +2388,15 @@ async fn local_index_read(
     mailbox: String,
 ) -> Result<Option<String>, String> {
     let data_dir = vault::root(&app_handle)?;
-    let index_path = data_dir.join("maildir").join(&account_id).join(&mailbox).join("local-index.json");
+    let index_path = local_index_path(&app_handle, &account_id, &mailbox)?;
 
     if !index_path.exists() {
         return Ok(None);
     }
 
-    let content = tokio::fs::read_to_string(&index_path).await
+    let bytes = mailvault_core::maildir::encryption::read_sidecar(&data_dir, &index_path)
+        .map_err(|e| format!("Failed to read local-index.json: {}", e))?;
+    let content = String::from_utf8(bytes)
         .map_err(|e| format!("Failed to read local-index.json: {}", e))?;
     Ok(Some(content))
 }
@@ -2389,38 +2409,18 @@ async fn local_index_append(
     entries_json: String,
 ) -> Result<(), String> {
     let data_dir = vault::root(&app_handle)?;
-    let dir_path = data_dir.join("maildir").join(&account_id).join(&mailbox);
-    tokio::fs::create_dir_all(&dir_path).await
-        .map_err(|e| format!("Failed to create dir: {}", e))?;
-    let index_path = dir_path.join("local-index.json");
+    let index_path = local_index_path(&app_handle, &account_id, &mailbox)?;
+    if let Some(dir_path) = index_path.parent() {
+        tokio::fs::create_dir_all(dir_path).await
+            .map_err(|e| format!("Failed to create dir: {}", e))?;
+    }
 
     let new_entries: Vec<serde_json::Value> = serde_json::from_str(&entries_json)
         .map_err(|e| format!("Failed to parse entries: {}", e))?;
 
-    let mut existing: Vec<serde_json::Value> = if index_path.exists() {
-        let content = tokio::fs::read_to_string(&index_path).await.unwrap_or_default();
-        serde_json::from_str(&content).unwrap_or_default()
-    } else {
-        Vec::new()
-    };
-
-    let new_uids: std::collections::HashSet<u64> = new_entries.iter()
-        .filter_map(|e| e.get("uid").and_then(|u| u.as_u64()))
-        .collect();
-    existing.retain(|e| {
-        e.get("uid").and_then(|u| u.as_u64()).map_or(true, |uid| !new_uids.contains(&uid))
-    });
-    existing.extend(new_entries);
-
-    let tmp_path = index_path.with_extension("json.tmp");
-    let data = serde_json::to_string(&existing)
-        .map_err(|e| format!("Failed to serialize: {}", e))?;
-    tokio::fs::write(&tmp_path, &data).await
-        .map_err(|e| format!("Failed to write tmp: {}", e))?;
-    tokio::fs::rename(&tmp_path, &index_path).await
-        .map_err(|e| format!("Failed to rename: {}", e))?;
-
-    Ok(())
+    // ponytail: sync fs calls in an async command — fine for a small JSON
+    // file, same tradeoff local_index_remove makes.
+    append_local_index(&data_dir, &index_path, new_entries)
 }
 
 #[tauri::command]
@@ -2430,10 +2430,11 @@ async fn local_index_remove(
     mailbox: String,
     uid: u32,
 ) -> Result<(), String> {
+    let data_dir = vault::root(&app_handle)?;
     let index_path = local_index_path(&app_handle, &account_id, &mailbox)?;
     // ponytail: sync fs call in an async command — fine for a small JSON file,
     // same tradeoff maildir_delete_many already makes.
-    prune_local_index(&index_path, &std::collections::HashSet::from([uid]))
+    prune_local_index(&data_dir, &index_path, &std::collections::HashSet::from([uid]))
 }
 
 // ── Vault generation (UIDVALIDITY) ──────────────────────────────────────────
@@ -2450,45 +2451,35 @@ fn maildir_mailbox_path(app_handle: &tauri::AppHandle, account_id: &str, mailbox
         .ok_or_else(|| "Maildir path has no parent".to_string())
 }
 
-/// Message-ID → uid for the mailbox's *current* generation, read from the
-/// sidecar cache the sync engine already maintains.
+/// Message-ID → uid and EMAILID → uid for the mailbox's *current* generation,
+/// read from the header cache the sync engine already maintains. The EMAILID
+/// map is empty unless the server has OBJECTID.
 ///
-/// The sidecars are the only complete, already-on-disk picture of what the
+/// The cached headers are the only complete, already-on-disk picture of what the
 /// server holds right now; asking the server instead would put a full header
 /// fetch in front of every mailbox open. A message the cache hasn't reached is
 /// read as absent, which is the safe direction — `orphaned/` keeps the file
 /// either way, and the next repair after a fuller sync re-binds it.
-fn sidecar_message_id_map(
+#[allow(clippy::type_complexity)]
+fn sidecar_id_maps(
     app_handle: &tauri::AppHandle,
     account_id: &str,
     mailbox: &str,
-) -> (std::collections::HashMap<String, u32>, u64) {
+) -> (std::collections::HashMap<String, u32>, std::collections::HashMap<String, u32>, u64) {
     let mut map = std::collections::HashMap::new();
+    let mut by_email_id = std::collections::HashMap::new();
     let mut sidecars = 0u64;
     let dir = match vault::root(app_handle) {
         Ok(root) => root.join("email_cache").join(cache_base_name(account_id, mailbox)),
-        Err(_) => return (map, 0),
+        Err(_) => return (map, by_email_id, 0),
     };
-    let entries = match fs::read_dir(&dir) {
-        Ok(e) => e,
-        Err(_) => return (map, 0),
+    let store = match HeaderStore::open(&dir) {
+        Ok(s) => s,
+        Err(_) => return (map, by_email_id, 0),
     };
-    for entry in entries.flatten() {
-        let name = entry.file_name().to_string_lossy().to_string();
-        // `_meta.json` and anything else that isn't `{uid}.json`.
-        let uid: u32 = match name.strip_suffix(".json").and_then(|s| s.parse().ok()) {
-            Some(u) => u,
-            None => continue,
-        };
+    for (uid, value) in store.uids().zip(store.headers()) {
         sidecars += 1;
-        let value: serde_json::Value = match fs::read_to_string(entry.path())
-            .ok()
-            .and_then(|s| serde_json::from_str(&s).ok())
-        {
-            Some(v) => v,
-            None => continue,
-        };
-        // Sidecars written by the frontend carry `messageId`; ones serialized
+        // Headers written by the frontend carry `messageId`; ones serialized
         // from `EmailHeader` carry `message_id`.
         let raw = value.get("messageId").or_else(|| value.get("message_id"))
             .and_then(|v| v.as_str());
@@ -2498,21 +2489,20 @@ fn sidecar_message_id_map(
                 map.insert(id, uid);
             }
         }
+        if let Some(email_id) = value.get("emailId").and_then(|v| v.as_str()) {
+            by_email_id.insert(email_id.to_string(), uid);
+        }
     }
-    (map, sidecars)
+    (map, by_email_id, sidecars)
 }
 
 /// Uids in this mailbox that the server never issued — messages composed here
 /// that live only in the vault (`local_sent`, `local_draft`). A UID reissue
 /// says nothing about them, and a repair that moved them aside for "not on the
 /// server" would hide the user's own sent mail and drafts.
-fn locally_created_uids(index_path: &Path) -> std::collections::HashSet<u32> {
+fn locally_created_uids(data_dir: &Path, index_path: &Path) -> Result<std::collections::HashSet<u32>, String> {
     let mut uids = std::collections::HashSet::new();
-    let entries: Vec<serde_json::Value> = fs::read_to_string(index_path)
-        .ok()
-        .and_then(|s| serde_json::from_str(&s).ok())
-        .unwrap_or_default();
-    for entry in entries {
+    for entry in read_local_index(data_dir, index_path)? {
         let source = entry.get("source").and_then(|v| v.as_str()).unwrap_or("");
         if source == "local_sent" || source == "local_draft" {
             if let Some(uid) = entry.get("uid").and_then(|u| u.as_u64()) {
@@ -2520,7 +2510,7 @@ fn locally_created_uids(index_path: &Path) -> std::collections::HashSet<u32> {
             }
         }
     }
-    uids
+    Ok(uids)
 }
 
 /// Follow a repair through `local-index.json`: rebound uids are rewritten,
@@ -2532,15 +2522,14 @@ fn locally_created_uids(index_path: &Path) -> std::collections::HashSet<u32> {
 /// uids whose files just moved — the same "claims a row is archived when the
 /// archived thing is a different message" the repair exists to end.
 fn remap_local_index(
+    data_dir: &Path,
     index_path: &Path,
     report: &mailvault_core::maildir::GenerationRepair,
 ) -> Result<(), String> {
     if !index_path.exists() {
         return Ok(());
     }
-    let content = fs::read_to_string(index_path)
-        .map_err(|e| format!("Failed to read local index: {}", e))?;
-    let mut entries: Vec<serde_json::Value> = serde_json::from_str(&content).unwrap_or_default();
+    let mut entries = read_local_index(data_dir, index_path)?;
 
     let moved: std::collections::HashMap<u64, u64> =
         report.rebound.iter().map(|(o, n)| (*o as u64, *n as u64)).collect();
@@ -2559,11 +2548,7 @@ fn remap_local_index(
         }
     }
 
-    let data = serde_json::to_string(&entries)
-        .map_err(|e| format!("Failed to serialize local index: {}", e))?;
-    let tmp = index_path.with_extension("json.tmp");
-    fs::write(&tmp, &data).map_err(|e| format!("Failed to write local index tmp: {}", e))?;
-    fs::rename(&tmp, index_path).map_err(|e| format!("Failed to replace local index: {}", e))
+    write_local_index(data_dir, index_path, &entries)
 }
 
 /// What the sync engine last recorded for this mailbox: the UIDVALIDITY its
@@ -2632,7 +2617,7 @@ async fn maildir_repair_generation(
         // message in the vault would read as gone. Nothing runs until the cache
         // covers the mailbox; until then the vault stays as it is, which is no
         // worse than before, and `_readVerifiedLocal` still guards what opens.
-        let (id_to_uid, sidecars) = sidecar_message_id_map(&app_handle, &account_id, &mailbox);
+        let (id_to_uid, email_id_to_uid, sidecars) = sidecar_id_maps(&app_handle, &account_id, &mailbox);
         let total = cached_total.unwrap_or(0);
         if total == 0 || sidecars < total {
             info!(
@@ -2642,15 +2627,18 @@ async fn maildir_repair_generation(
             return Ok(mailvault_core::maildir::GenerationRepair::default());
         }
 
+        let data_dir = vault::root(&app_handle)?;
         let index_path = local_index_path(&app_handle, &account_id, &mailbox)?;
-        let protected = locally_created_uids(&index_path);
+        // An index that can't be read (a locked vault) would leave locally
+        // created mail unprotected, so the repair waits for it.
+        let protected = locally_created_uids(&data_dir, &index_path)?;
 
         let report = mailvault_core::maildir::repair_generation(
-            &mailbox_dir, uid_validity, &id_to_uid, &protected,
+            &mailbox_dir, uid_validity, &id_to_uid, &email_id_to_uid, &protected,
         );
 
         if !report.rebound.is_empty() || !report.orphaned.is_empty() {
-            if let Err(e) = remap_local_index(&index_path, &report) {
+            if let Err(e) = remap_local_index(&data_dir, &index_path, &report) {
                 warn!("maildir_repair_generation: local index remap failed: {}", e);
             }
         }
@@ -2892,7 +2880,7 @@ fn maildir_read(
         .unwrap_or_default();
     let flags = parse_flags_from_filename(&filename);
 
-    let raw = fs::read(&file_path)
+    let raw = mailvault_core::maildir::compression::read_message(&file_path)
         .map_err(|e| format!("Failed to read .eml file: {}", e))?;
 
     let email = parse_eml_bytes(&raw, uid, flags)?;
@@ -2918,7 +2906,7 @@ fn maildir_read_light(
         .unwrap_or_default();
     let flags = parse_flags_from_filename(&filename);
 
-    let raw = fs::read(&file_path)
+    let raw = mailvault_core::maildir::compression::read_message(&file_path)
         .map_err(|e| format!("Failed to read .eml file: {}", e))?;
 
     let email = parse_eml_bytes_light(&raw, uid, flags)?;
@@ -2943,7 +2931,7 @@ async fn maildir_read_light_batch(
                         .map(|n| n.to_string_lossy().to_string())
                         .unwrap_or_default();
                     let flags = parse_flags_from_filename(&filename);
-                    match fs::read(&file_path) {
+                    match mailvault_core::maildir::compression::read_message(&file_path) {
                         Ok(raw) => match parse_eml_bytes_light(&raw, *uid, flags) {
                             Ok(email) => results.push(Some(email)),
                             Err(_) => results.push(None),
@@ -2973,7 +2961,7 @@ fn maildir_read_attachment(
     let file_path = find_file_by_uid(&cur_dir, uid)
         .ok_or_else(|| format!("Email UID {} not found", uid))?;
 
-    let raw = fs::read(&file_path)
+    let raw = mailvault_core::maildir::compression::read_message(&file_path)
         .map_err(|e| format!("Failed to read .eml file: {}", e))?;
 
     let parsed = mailparse::parse_mail(&raw)
@@ -3004,7 +2992,7 @@ fn maildir_read_raw_source(
     let file_path = find_file_by_uid(&cur_dir, uid)
         .ok_or_else(|| format!("Email UID {} not found", uid))?;
 
-    let raw = fs::read(&file_path)
+    let raw = mailvault_core::maildir::compression::read_message(&file_path)
         .map_err(|e| format!("Failed to read .eml file: {}", e))?;
 
     Ok(base64::engine::general_purpose::STANDARD.encode(&raw))
@@ -3113,6 +3101,19 @@ async fn maildir_list(
             return Ok(Vec::new());
         }
 
+        // Standard names don't carry the uid; the core index has them.
+        if is_standard_mailbox(&cur_dir) {
+            let results: Vec<MaildirEmailSummary> = mailvault_core::uid_index::entries(&cur_dir)
+                .into_iter()
+                .map(|(uid, e)| {
+                    let flags = app_flags(&e.flags);
+                    MaildirEmailSummary { uid, is_archived: flags.iter().any(|f| f == "archived"), flags, size: e.size }
+                })
+                .filter(|s| require_flag.as_ref().is_none_or(|r| s.flags.contains(r)))
+                .collect();
+            return Ok(results);
+        }
+
         let entries = fs::read_dir(&cur_dir)
             .map_err(|e| format!("Failed to read Maildir: {}", e))?;
 
@@ -3160,8 +3161,14 @@ fn maildir_delete(
 ) -> Result<(), String> {
     let cur_dir = maildir_cur_path(&app_handle, &account_id, &mailbox)?;
     if let Some(path) = find_file_by_uid(&cur_dir, uid) {
-        fs::remove_file(&path)
-            .map_err(|e| format!("Failed to delete .eml file: {}", e))?;
+        mailvault_core::uid_index::change(&cur_dir, uid, || {
+            fs::remove_file(&path)
+                .map_err(|e| format!("Failed to delete .eml file: {}", e))?;
+            Ok(None)
+        })?;
+        if let Some(mailbox_dir) = cur_dir.parent() {
+            let _ = mailvault_core::maildir::manifest::forget(mailbox_dir, &[uid]);
+        }
         info!("Deleted email UID {} from {:?}", uid, path);
     }
     Ok(())
@@ -3179,7 +3186,8 @@ fn maildir_delete_many(
     let removed = delete_maildir_files(&cur_dir, &uid_set);
 
     let index_path = local_index_path(&app_handle, &account_id, &mailbox)?;
-    if let Err(e) = prune_local_index(&index_path, &uid_set) {
+    let pruned = vault::root(&app_handle).and_then(|data_dir| prune_local_index(&data_dir, &index_path, &uid_set));
+    if let Err(e) = pruned {
         warn!("maildir_delete_many: index prune failed: {}", e);
     }
 
@@ -3199,17 +3207,29 @@ fn maildir_set_flags(
     flags: Vec<String>,
 ) -> Result<(), String> {
     let cur_dir = maildir_cur_path(&app_handle, &account_id, &mailbox)?;
+    if is_standard_mailbox(&cur_dir) {
+        mailvault_core::maildir::set_flags_in(&cur_dir, uid, &flags)?;
+        info!("Updated flags for UID {}: {:?}", uid, flags);
+        return Ok(());
+    }
     let old_path = match find_file_by_uid(&cur_dir, uid) {
         Some(p) => p,
         None => return Err(format!("Email UID {} not found in Maildir", uid)),
     };
 
-    let new_filename = build_maildir_filename(uid, &flags);
+    // A compressed file keeps its suffix.
+    let old_name = old_path.file_name().unwrap_or_default().to_string_lossy().to_string();
+    let suffix = &old_name[mailvault_core::maildir::compression::strip_suffix(&old_name).len()..];
+    let new_filename = format!("{}{}", build_maildir_filename(uid, &flags), suffix);
     let new_path = cur_dir.join(&new_filename);
 
     if old_path != new_path {
-        fs::rename(&old_path, &new_path)
-            .map_err(|e| format!("Failed to rename file: {}", e))?;
+        mailvault_core::uid_index::change(&cur_dir, uid, || {
+            fs::rename(&old_path, &new_path)
+                .map_err(|e| format!("Failed to rename file: {}", e))?;
+            mailvault_core::maildir::integrity::sync_dir(&cur_dir)?;
+            Ok(Some(new_path.clone()))
+        })?;
         info!("Updated flags for UID {}: {:?} -> {:?}", uid, old_path.file_name(), new_path.file_name());
     }
     Ok(())
@@ -3273,13 +3293,18 @@ fn maildir_clear_cache(
         // the current server does not have, so this copy may be the only one.
         // "Clear cached emails" promises saved mail survives it, and a file in
         // here whose flags happen to be empty would otherwise read as cache.
-        if entry.path().components().any(|c| c.as_os_str() == mailvault_core::maildir::ORPHAN_DIR) {
+        // `quarantine/` is the same: a cut-short copy may be all that's left.
+        if entry.path().components().any(|c| {
+            c.as_os_str() == mailvault_core::maildir::ORPHAN_DIR
+                || c.as_os_str() == mailvault_core::maildir::QUARANTINE_DIR
+        }) {
             continue;
         }
         if entry.file_type().is_file() {
             let name = entry.file_name().to_string_lossy().to_string();
             if name.contains(":2,") {
-                let flags = parse_flags_from_filename(&name);
+                // Through core, so a standard-layout name's keyword letters count.
+                let flags = app_flags(&mailvault_core::maildir::message_flags(entry.path()));
                 if flags.iter().any(|f| f == "archived") {
                     skipped_archived += 1;
                 } else {
@@ -3287,6 +3312,11 @@ fn maildir_clear_cache(
                         warn!("Failed to delete cached email {:?}: {}", entry.path(), e);
                     } else {
                         deleted_count += 1;
+                        // A cleared body is gone on purpose; don't let verify call it missing.
+                        let mailbox_dir = entry.path().parent().and_then(Path::parent);
+                        if let (Some(mailbox_dir), Some(uid)) = (mailbox_dir, mailvault_core::maildir::uid_of_file(entry.path())) {
+                            let _ = mailvault_core::maildir::manifest::forget(mailbox_dir, &[uid]);
+                        }
                     }
                 }
             }
@@ -3371,7 +3401,7 @@ fn maildir_migrate_json_to_eml(
             let eml_filename = build_maildir_filename(uid, &["archived".to_string(), "seen".to_string()]);
             let eml_path = cur_dir.join(&eml_filename);
 
-            match fs::write(&eml_path, &raw_bytes) {
+            match mailvault_core::maildir::integrity::write_atomic(&cur_dir.with_file_name("tmp"), &eml_path, &raw_bytes) {
                 Ok(_) => {
                     let _ = fs::remove_file(&path);
                     migrated += 1;
@@ -3670,12 +3700,13 @@ async fn export_backup(
                                     }
                                 }
 
+                                // The export holds plain messages, whatever the vault compresses.
                                 let zip_path = format!(
                                     "mailvault-backup/emails/{}/{}/{}",
-                                    email_addr, mailbox_name, filename
+                                    email_addr, mailbox_name, mailvault_core::maildir::compression::strip_suffix(&filename)
                                 );
 
-                                let content = match fs::read(file_entry.path()) {
+                                let content = match mailvault_core::maildir::compression::read_message(&file_entry.path()) {
                                     Ok(c) => c,
                                     Err(e) => {
                                         warn!("Failed to read {}: {}", file_entry.path().display(), e);
@@ -3853,23 +3884,16 @@ async fn import_backup(
 
         let safe_mailbox = sanitize_mailbox_name(mailbox);
         let cur_dir = maildir_base.join(&account_id).join(&safe_mailbox).join("cur");
-        fs::create_dir_all(&cur_dir)
-            .map_err(|e| format!("Failed to create directory: {}", e))?;
-
-        let dest_path = cur_dir.join(filename);
-
-        // Skip if file already exists (idempotent)
-        if dest_path.exists() {
-            info!("Skipping existing file: {:?}", dest_path);
-            continue;
-        }
 
         let mut content = Vec::new();
         entry.read_to_end(&mut content)
             .map_err(|e| format!("Failed to read .eml from ZIP: {}", e))?;
 
-        fs::write(&dest_path, &content)
-            .map_err(|e| format!("Failed to write .eml file: {}", e))?;
+        // Skip messages the vault already has (idempotent)
+        if !import_backup_message(&cur_dir, filename, &content)? {
+            info!("Skipping existing message: {}", entry_name);
+            continue;
+        }
 
         email_count += 1;
 
@@ -3895,6 +3919,21 @@ async fn import_backup(
     })
 }
 
+/// Store one message from a backup ZIP, named `<uid>:2,<flags>`, the way the
+/// vault stores everything. `false` when the vault already has that uid.
+fn import_backup_message(cur_dir: &Path, filename: &str, content: &[u8]) -> Result<bool, String> {
+    let uid: u32 = filename
+        .split(|c: char| c == ':' || c == '.' || c == '_')
+        .next()
+        .and_then(|u| u.parse().ok())
+        .ok_or_else(|| format!("No uid in backup file name {:?}", filename))?;
+    if find_file_by_uid(cur_dir, uid).is_some() {
+        return Ok(false);
+    }
+    store_new_message(cur_dir, uid, content, &parse_flags_from_filename(filename))?;
+    Ok(true)
+}
+
 // ── MBOX Export / Import ────────────────────────────────────────────────────
 
 /// Escape "From " at the start of lines in an email body for mbox format.
@@ -4037,7 +4076,7 @@ async fn export_mbox(
     }));
 
     for entry in &entries {
-        let raw = match fs::read(entry.path()) {
+        let raw = match mailvault_core::maildir::compression::read_message(&entry.path()) {
             Ok(c) => c,
             Err(e) => {
                 warn!("Failed to read {}: {}", entry.path().display(), e);
@@ -4125,7 +4164,7 @@ async fn export_mbox_all(
                                 }
                             }
 
-                            let raw = match fs::read(file_entry.path()) {
+                            let raw = match mailvault_core::maildir::compression::read_message(&file_entry.path()) {
                                 Ok(c) => c,
                                 Err(e) => {
                                     warn!("Failed to read {}: {}", file_entry.path().display(), e);
@@ -4189,30 +4228,39 @@ async fn import_mbox(
 
     let safe_mailbox = sanitize_mailbox_name(&mailbox);
     let cur_dir = base.join("Maildir").join(&account_id).join(&safe_mailbox).join("cur");
-    fs::create_dir_all(&cur_dir)
-        .map_err(|e| format!("Failed to create maildir: {}", e))?;
 
+    let email_count = import_mbox_into(&cur_dir, &data, |completed, total| {
+        let _ = app_handle.emit("mbox-import-progress", serde_json::json!({
+            "total": total, "completed": completed, "active": true
+        }));
+    })?;
+
+    let _ = app_handle.emit("mbox-import-progress", serde_json::json!({
+        "total": email_count, "completed": email_count, "active": false
+    }));
+
+    info!("MBOX imported: {} emails into {}/{}", email_count, account_id, mailbox);
+
+    Ok(MboxImportResult {
+        email_count,
+        account_id,
+        mailbox,
+    })
+}
+
+/// Store each message of an mbox file in the mailbox at `cur_dir`, on uids
+/// after the highest it has, the way the vault stores everything. Reports
+/// `(completed, total)` every 50 messages and at the end.
+fn import_mbox_into(cur_dir: &Path, data: &[u8], mut progress: impl FnMut(u32, u32)) -> Result<u32, String> {
     // Find the highest existing UID in this mailbox to continue from
-    let mut max_uid: u32 = 0;
-    if let Ok(files) = fs::read_dir(&cur_dir) {
-        for f in files.flatten() {
-            let fname = f.file_name().to_string_lossy().to_string();
-            if let Some(uid_str) = fname.split(':').next() {
-                if let Ok(uid) = uid_str.parse::<u32>() {
-                    if uid > max_uid { max_uid = uid; }
-                }
-            }
-        }
-    }
+    let mut max_uid = mailvault_core::uid_index::uids(cur_dir).into_iter().max().unwrap_or(0);
 
     // Split mbox into individual messages
     // Mbox messages start with "From " at the beginning of a line (after a blank line)
-    let messages = split_mbox(&data);
+    let messages = split_mbox(data);
 
     let total = messages.len() as u32;
-    let _ = app_handle.emit("mbox-import-progress", serde_json::json!({
-        "total": total, "completed": 0, "active": true
-    }));
+    progress(0, total);
 
     let mut email_count: u32 = 0;
 
@@ -4220,40 +4268,18 @@ async fn import_mbox(
         let unescaped = mbox_unescape_from(msg_raw);
 
         max_uid += 1;
-        let filename = format!("{}:2,", max_uid);
-        let dest = cur_dir.join(&filename);
-
-        if dest.exists() {
+        while find_file_by_uid(cur_dir, max_uid).is_some() {
             max_uid += 1;
-            let filename2 = format!("{}:2,", max_uid);
-            let dest2 = cur_dir.join(&filename2);
-            fs::write(&dest2, &unescaped)
-                .map_err(|e| format!("Failed to write .eml: {}", e))?;
-        } else {
-            fs::write(&dest, &unescaped)
-                .map_err(|e| format!("Failed to write .eml: {}", e))?;
         }
+        store_new_message(cur_dir, max_uid, &unescaped, &[])?;
 
         email_count += 1;
 
         if email_count % 50 == 0 || email_count == total {
-            let _ = app_handle.emit("mbox-import-progress", serde_json::json!({
-                "total": total, "completed": email_count, "active": true
-            }));
+            progress(email_count, total);
         }
     }
-
-    let _ = app_handle.emit("mbox-import-progress", serde_json::json!({
-        "total": total, "completed": email_count, "active": false
-    }));
-
-    info!("MBOX imported: {} emails into {}/{}", email_count, account_id, mailbox);
-
-    Ok(MboxImportResult {
-        email_count,
-        account_id,
-        mailbox,
-    })
+    Ok(email_count)
 }
 
 /// Split raw mbox data into individual email messages.
@@ -4965,6 +4991,8 @@ fn main() {
             commands::imap_fetch_headers_by_uids,
             commands::imap_get_email,
             commands::imap_get_email_light,
+            commands::imap_get_email_parts,
+            commands::imap_save_part,
             commands::imap_set_flags,
             commands::imap_delete_email,
             commands::smtp_send_email,
@@ -4985,6 +5013,8 @@ fn main() {
             commands::resolve_email_settings,
             commands::dns_mail_health,
             commands::backup_run_account,
+            commands::vault_verify,
+            commands::vault_unlock,
             commands::backup_status,
             commands::backup_cancel,
             commands::backup_save_external_location,
@@ -5068,6 +5098,12 @@ fn main() {
                 vault_status.status
             );
 
+            // An encrypted vault kept in the keyring opens in this process too;
+            // one wrapped only by a passphrase waits for `vault_unlock`.
+            if let Ok(root) = vault::root(&app.handle()) {
+                tauri::async_runtime::spawn_blocking(move || unlock_vault_from_keyring(&root));
+            }
+
             // --- Set up app menu ---
             // No "Check for Updates" on MAS builds — the App Store handles updates.
             #[cfg(any(not(target_os = "macos"), feature = "sparkle"))]
@@ -5763,6 +5799,46 @@ iVBORw0KGgo=\r\n\
         let email = parse_eml_bytes_light(raw, 1, vec![]).unwrap();
         assert!(email.has_attachments, "Email with real PDF attachment should set has_attachments");
     }
+
+    // -- Imports --
+
+    #[test]
+    fn test_imports_into_an_encrypted_vault_are_sealed() {
+        use mailvault_core::maildir::{compression, encryption};
+        let tmp = tempfile::tempdir().unwrap();
+        let vault = tmp.path();
+        fs::create_dir_all(vault.join("Maildir")).unwrap();
+        encryption::enable(vault, Some("pass"), None).unwrap();
+        let cur = vault.join("Maildir").join("acc1").join("INBOX").join("cur");
+
+        let mbox = b"From alice@example.com Thu Feb 19 10:00:00 2026
+From: alice@example.com
+Subject: First
+
+the mbox body
+
+From bob@example.com Thu Feb 19 11:00:00 2026
+From: bob@example.com
+Subject: Second
+
+another mbox body
+";
+        assert_eq!(import_mbox_into(&cur, mbox, |_, _| ()).unwrap(), 2);
+        assert!(import_backup_message(&cur, "7:2,S", PLAIN_EMAIL).unwrap());
+        assert!(!import_backup_message(&cur, "7:2,S", PLAIN_EMAIL).unwrap(), "an existing uid is skipped");
+
+        let files: Vec<PathBuf> = fs::read_dir(&cur).unwrap().flatten().map(|e| e.path()).collect();
+        assert_eq!(files.len(), 3);
+        for path in &files {
+            let bytes = fs::read(path).unwrap();
+            assert!(encryption::sealed_header(&bytes).is_some(), "{:?} is not sealed", path);
+            let text = String::from_utf8_lossy(&bytes);
+            assert!(!text.contains("mbox body") && !text.contains("Hello, World"), "{:?} is readable", path);
+        }
+        let seen = find_file_by_uid(&cur, 7).unwrap();
+        assert_eq!(parse_flags_from_filename(&seen.file_name().unwrap().to_string_lossy()), ["seen"]);
+        assert_eq!(compression::read_message(&seen).unwrap(), PLAIN_EMAIL);
+    }
 }
 
 #[cfg(test)]
@@ -5810,7 +5886,7 @@ mod purge_tests {
         let mut uids = HashSet::new();
         uids.insert(102u32);
 
-        prune_local_index(&index, &uids).unwrap();
+        prune_local_index(tmp.path(), &index, &uids).unwrap();
 
         let left: Vec<serde_json::Value> =
             serde_json::from_str(&std::fs::read_to_string(&index).unwrap()).unwrap();
@@ -5823,7 +5899,7 @@ mod purge_tests {
         let tmp = tempfile::tempdir().unwrap();
         let mut uids = HashSet::new();
         uids.insert(1u32);
-        assert!(prune_local_index(&tmp.path().join("nope.json"), &uids).is_ok());
+        assert!(prune_local_index(tmp.path(), &tmp.path().join("nope.json"), &uids).is_ok());
     }
 }
 
@@ -5866,7 +5942,7 @@ mod sidecar_order_tests {
         write_header(dir, 2, "2026-02-01T00:00:00Z");
         write_header(dir, 3, "2026-03-01T00:00:00Z");
 
-        let out = load_from_sidecars(dir, &dir.join("_meta.json"), Some(2))
+        let out = load_from_store(dir, &dir.join("_meta.json"), Some(2))
             .unwrap()
             .unwrap();
 
@@ -5889,7 +5965,7 @@ mod sidecar_order_tests {
         write_header(dir, 4, "2026-05-01T00:00:00Z"); // oldest at seed time
         write_header(dir, 5, "2026-08-15T00:00:00Z"); // arrived after the seed
 
-        let out = load_from_sidecars(dir, &dir.join("_meta.json"), Some(3))
+        let out = load_from_store(dir, &dir.join("_meta.json"), Some(3))
             .unwrap()
             .unwrap();
 
@@ -5906,7 +5982,7 @@ mod sidecar_order_tests {
         fs::write(dir.join(GRAPH_ID_MAP_FILE), br#"{"1":"AAA"}"#).unwrap();
         write_header(dir, 1, "2026-08-01T00:00:00Z");
 
-        let out = load_from_sidecars(dir, &dir.join("_meta.json"), None)
+        let out = load_from_store(dir, &dir.join("_meta.json"), None)
             .unwrap()
             .unwrap();
         let parsed: serde_json::Value = serde_json::from_str(&out).unwrap();
@@ -5927,7 +6003,7 @@ mod sidecar_order_tests {
         write_header(dir, 1, "2026-08-01T00:00:00Z");
         write_header(dir, 2, "2026-07-01T00:00:00Z");
 
-        let out = load_from_sidecars(dir, &dir.join("_meta.json"), Some(2))
+        let out = load_from_store(dir, &dir.join("_meta.json"), Some(2))
             .unwrap()
             .unwrap();
 
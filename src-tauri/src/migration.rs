@@ -8,7 +8,7 @@ use tokio::sync::Semaphore;
 use tracing::{info, warn};
 
 use crate::graph::GraphClient;
-use crate::imap::{self, ImapConfig, ImapPool, ImapSession, MailboxInfo};
+use crate::imap::{self, gmail, ImapConfig, ImapPool, ImapSession, MailboxInfo};
 
 // ── Data structures ─────────────────────────────────────────────────────────
 
@@ -25,6 +25,15 @@ pub struct FolderMapping {
     pub failed: u32,
     #[serde(default)]
     pub failed_uids: Vec<u32>,
+    /// Set when a Gmail source goes to a non-Gmail IMAP server: how this
+    /// folder's messages carry their labels (see `apply_gmail_label_mapping`).
+    #[serde(default)]
+    pub gmail_labels: Option<gmail::LabelMapping>,
+    /// EMAILIDs of the source messages this folder has delivered, when the
+    /// source has OBJECTID. A resumed run skips them without fetching a body,
+    /// whether or not they carry a Message-ID. Cleared once the folder completes.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub migrated_email_ids: Vec<String>,
 }
 
 #[derive(Clone, Debug, Serialize)]
@@ -172,12 +181,35 @@ pub fn build_folder_mappings(
             skipped: 0,
             failed: 0,
             failed_uids: Vec::new(),
+            gmail_labels: None,
+            migrated_email_ids: Vec::new(),
         });
     }
 
     mappings
 }
 
+/// Gmail source, non-Gmail destination. Gmail lists each label as a folder,
+/// so migrating every folder would copy a message once per label. Drop the
+/// folders `mapping` carries as flags instead (Starred, Important, and with
+/// `Keywords` every user label), and mark the rest so `run_migration` asks
+/// for X-GM-LABELS and leaves out All Mail copies another folder delivers.
+pub fn apply_gmail_label_mapping(
+    mappings: &mut Vec<FolderMapping>,
+    source_folders: &[MailboxInfo],
+    mapping: gmail::LabelMapping,
+) {
+    let skipped: HashSet<&str> = flatten_mailboxes(source_folders)
+        .into_iter()
+        .filter(|m| gmail::skip_folder_in_migration(m, mapping))
+        .map(|m| m.path.as_str())
+        .collect();
+    mappings.retain(|m| !skipped.contains(m.source_path.as_str()));
+    for m in mappings.iter_mut() {
+        m.gmail_labels = Some(mapping);
+    }
+}
+
 // ── Destination folder creation ─────────────────────────────────────────────
 
 /// Ensure an IMAP destination folder exists (CREATE, ignore "already exists").
@@ -185,7 +217,8 @@ pub async fn ensure_dest_folder_imap(
     session: &mut ImapSession,
     folder_path: &str,
 ) -> Result<(), String> {
-    match session.create(folder_path).await {
+    let wire = imap::utf8_accept::wire_name(session, folder_path);
+    match session.create(&wire).await {
         Ok(_) => {
             info!("[migration] Created IMAP folder: {}", folder_path);
             Ok(())
@@ -400,7 +433,7 @@ pub async fn migrate_email_imap_to_imap(
     let _mbox = imap::select_mailbox(source_session, source_mailbox).await?;
 
     let fetch_stream = source_session
-        .uid_fetch(uid.to_string(), "(UID FLAGS BODY.PEEK[])")
+        .uid_fetch(uid.to_string(), "(UID FLAGS INTERNALDATE BODY.PEEK[])")
         .await
         .map_err(|e| format!("UID FETCH {} failed: {}", uid, e))?;
 
@@ -419,26 +452,12 @@ pub async fn migrate_email_imap_to_imap(
         .body()
         .ok_or_else(|| format!("No body for UID {}", uid))?;
 
-    // Extract flags — convert async-imap Flag enum to IMAP flag strings
-    let flags: Vec<String> = fetch
-        .flags()
-        .filter_map(|f| {
-            use async_imap::types::Flag;
-            match f {
-                Flag::Seen => Some("\\Seen".to_string()),
-                Flag::Answered => Some("\\Answered".to_string()),
-                Flag::Flagged => Some("\\Flagged".to_string()),
-                Flag::Deleted => Some("\\Deleted".to_string()),
-                Flag::Draft => Some("\\Draft".to_string()),
-                Flag::Recent => None, // Recent is server-only, skip
-                _ => None,
-            }
-        })
-        .collect();
+    // Flags and keywords, and the original arrival time, go along with it.
+    let flags: Vec<String> = fetch.flags().filter_map(|f| imap::flag_for_append(&f)).collect();
     let flags_str = flags.join(" ");
 
     // Append to destination
-    imap::append_email(dest_session, dest_mailbox, mime_bytes, &flags_str).await?;
+    imap::append_email_at(dest_session, dest_mailbox, mime_bytes, &flags_str, fetch.internal_date()).await?;
 
     Ok(true)
 }
@@ -449,13 +468,14 @@ pub async fn migrate_email_graph_to_imap(
     dest_session: &mut ImapSession,
     message_id: &str,
     dest_mailbox: &str,
-    is_read: bool,
+    flags: &str,
+    received: Option<&str>,
 ) -> Result<bool, String> {
     let mime_bytes = client.get_mime_content(message_id).await?;
 
-    let flags_str = if is_read { "\\Seen".to_string() } else { String::new() };
-
-    imap::append_email(dest_session, dest_mailbox, &mime_bytes, &flags_str).await?;
+    // receivedDateTime is the Graph side's INTERNALDATE.
+    let internal_date = received.and_then(|r| chrono::DateTime::parse_from_rfc3339(r).ok());
+    imap::append_email_at(dest_session, dest_mailbox, &mime_bytes, flags, internal_date).await?;
 
     Ok(true)
 }
@@ -542,6 +562,23 @@ fn extract_sender_subject(mime_bytes: &[u8]) -> (String, String) {
 
 // ── Main migration runner ───────────────────────────────────────────────────
 
+/// One message to migrate out of a source folder. IMAP sources know only the
+/// UID up front — flags, INTERNALDATE and the body come with the fetch — so
+/// everything past `uid` is filled in for Graph sources alone.
+#[derive(Debug, Default)]
+struct SourceItem {
+    /// The IMAP UID, or the message's index in a Graph folder.
+    uid: u32,
+    graph_id: Option<String>,
+    is_read: bool,
+    internet_message_id: Option<String>,
+    sender: String,
+    subject: String,
+    /// IMAP flags to APPEND a Graph message with, space-separated.
+    append_flags: String,
+    received: Option<String>,
+}
+
 #[allow(clippy::too_many_arguments)]
 pub async fn run_migration(
     app_handle: tauri::AppHandle,
@@ -792,9 +829,27 @@ pub async fn run_migration(
             info!("[migration] Found {} existing messages in dest '{}' for dedup", dest_message_ids.len(), dst_path);
         }
 
+        // Source EMAILIDs (OBJECTID), by UID: a stable identity to dedup a
+        // resumed folder by before falling back to the Message-ID.
+        let mut source_email_ids: HashMap<u32, String> = HashMap::new();
+        if source_transport == "imap" && pool.has_capability(&source_config, imap::objectid::OBJECTID).await {
+            let mut guard = pool.get_priority(&source_config).await?;
+            match imap::objectid::fetch_object_ids(&mut guard.session, &src_path, "1:*").await {
+                Ok(ids) => {
+                    source_email_ids = ids.into_iter().map(|i| (i.uid, i.email_id)).collect();
+                    guard.last_selected = Some(src_path.clone());
+                    pool.return_priority(&source_config, guard).await;
+                }
+                Err(e) => {
+                    warn!("[migration] Failed to fetch source EMAILIDs for {}: {}", src_path, e);
+                    pool.discard(&source_config, guard).await;
+                }
+            }
+        }
+        let migrated_email_ids: HashSet<String> = folder_mappings[folder_idx].migrated_email_ids.iter().cloned().collect();
+
         // Fetch source UIDs / message IDs
-        // Tuple: (uid_or_index, graph_msg_id, is_read, internet_message_id, sender, subject)
-        let source_items: Vec<(u32, Option<String>, bool, Option<String>, String, String)>;
+        let source_items: Vec<SourceItem>;
 
         if source_transport == "imap" {
             let mut guard = pool.get_priority(&source_config).await?;
@@ -802,7 +857,7 @@ pub async fn run_migration(
             guard.last_selected = Some(src_path.clone());
             pool.return_priority(&source_config, guard).await;
 
-            source_items = uids.into_iter().map(|uid| (uid, None, false, None, String::new(), String::new())).collect();
+            source_items = uids.into_iter().map(|uid| SourceItem { uid, ..Default::default() }).collect();
         } else {
             // Graph source — collect internetMessageId for dedup
             if let Some(ref token) = source_config.access_token {
@@ -819,13 +874,19 @@ pub async fn run_migration(
                     let (messages, next_link) =
                         client.list_messages(&folder.id, 100, skip).await?;
                     for (i, msg) in messages.iter().enumerate() {
-                        let is_read = msg.is_read.unwrap_or(false);
-                        let inet_msg_id = msg.internet_message_id.clone();
                         let sender = msg.from.as_ref()
                             .and_then(|f| f.email_address.address.clone())
                             .unwrap_or_else(|| "unknown".to_string());
-                        let subject = msg.subject.clone().unwrap_or_default();
-                        items.push(((skip + i as u32), Some(msg.id.clone()), is_read, inet_msg_id, sender, subject));
+                        items.push(SourceItem {
+                            uid: skip + i as u32,
+                            graph_id: Some(msg.id.clone()),
+                            is_read: msg.is_read.unwrap_or(false),
+                            internet_message_id: msg.internet_message_id.clone(),
+                            sender,
+                            subject: msg.subject.clone().unwrap_or_default(),
+                            append_flags: msg.append_flags().join(" "),
+                            received: msg.received_date_time.clone(),
+                        });
                     }
                     if next_link.is_none() || messages.is_empty() {
                         break;
@@ -838,6 +899,14 @@ pub async fn run_migration(
             }
         }
 
+        let gmail_labels = folder_mappings[folder_idx].gmail_labels;
+        let from_all_mail = folder_mappings[folder_idx].source_special_use.as_deref() == Some("\\All");
+        let fetch_spec = if gmail_labels.is_some() {
+            "(UID FLAGS INTERNALDATE X-GM-LABELS BODY.PEEK[])"
+        } else {
+            "(UID FLAGS INTERNALDATE BODY.PEEK[])"
+        };
+
         let folder_total = source_items.len() as u32;
         folder_mappings[folder_idx].email_count = folder_total;
         let mut folder_migrated: u32 = 0;
@@ -850,7 +919,17 @@ pub async fn run_migration(
                 break;
             }
 
-            for (uid, graph_id, is_read, src_inet_msg_id, item_sender, item_subject) in batch {
+            for item in batch {
+                let SourceItem {
+                    uid,
+                    graph_id,
+                    is_read,
+                    internet_message_id: src_inet_msg_id,
+                    sender: item_sender,
+                    subject: item_subject,
+                    append_flags: graph_flags,
+                    received: graph_received,
+                } = item;
                 let uid = *uid;
                 let is_read = *is_read;
                 if cancel.load(Ordering::Relaxed) {
@@ -869,27 +948,29 @@ pub async fn run_migration(
                 let mut email_sender = item_sender.clone();
                 let mut email_subject = item_subject.clone();
 
-                // Dedup check for Graph source (has internetMessageId already)
-                if let Some(ref inet_id) = src_inet_msg_id {
-                    if !dest_message_ids.is_empty() && dest_message_ids.contains(inet_id) {
-                        folder_skipped += 1;
-                        skipped_total += 1;
-                        let log_entry = MigrationLogEntry {
-                            timestamp: chrono::Local::now().format("%H:%M:%S").to_string(),
-                            sender: email_sender.clone(),
-                            subject: email_subject.clone(),
-                            status: "skipped".to_string(),
-                        };
-                        emit_progress(
-                            "running", Some(src_path.clone()),
-                            Some(format!("{}/{}", folder_migrated + folder_skipped + folder_failed, folder_total)),
-                            migrated_total, skipped_total, failed_total,
-                            &folder_mappings, &started_at, start_instant.elapsed().as_secs(),
-                            &source_email, &dest_email, total_emails, &app_handle,
-                            Some(log_entry), None,
-                        );
-                        continue;
-                    }
+                // Dedup check for IMAP source by EMAILID, and for Graph source
+                // (has internetMessageId already)
+                let email_id = source_email_ids.get(&uid);
+                let delivered = email_id.is_some_and(|id| migrated_email_ids.contains(id))
+                    || src_inet_msg_id.as_ref().is_some_and(|inet_id| dest_message_ids.contains(inet_id));
+                if delivered {
+                    folder_skipped += 1;
+                    skipped_total += 1;
+                    let log_entry = MigrationLogEntry {
+                        timestamp: chrono::Local::now().format("%H:%M:%S").to_string(),
+                        sender: email_sender.clone(),
+                        subject: email_subject.clone(),
+                        status: "skipped".to_string(),
+                    };
+                    emit_progress(
+                        "running", Some(src_path.clone()),
+                        Some(format!("{}/{}", folder_migrated + folder_skipped + folder_failed, folder_total)),
+                        migrated_total, skipped_total, failed_total,
+                        &folder_mappings, &started_at, start_instant.elapsed().as_secs(),
+                        &source_email, &dest_email, total_emails, &app_handle,
+                        Some(log_entry), None,
+                    );
+                    continue;
                 }
 
                 let _permit = sem.acquire().await.unwrap();
@@ -906,7 +987,7 @@ pub async fn run_migration(
                             use futures::StreamExt;
                             let _mbox = imap::select_mailbox(&mut src_guard.session, &src_path).await?;
                             let fetch_stream = src_guard.session
-                                .uid_fetch(uid.to_string(), "(UID FLAGS BODY.PEEK[])")
+                                .uid_fetch(uid.to_string(), fetch_spec)
                                 .await
                                 .map_err(|e| format!("UID FETCH {} failed: {}", uid, e))?;
                             let fetches: Vec<_> = fetch_stream
@@ -927,55 +1008,60 @@ pub async fn run_migration(
                                     email_sender = s;
                                     email_subject = subj;
                                 }
-                                // Dedup check via Message-ID extraction from MIME
-                                if !dest_message_ids.is_empty() {
-                                    if let Some(body) = fetch.body() {
-                                        if let Some(msg_id) = extract_message_id(body) {
-                                            if dest_message_ids.contains(&msg_id) {
-                                                src_guard.last_selected = Some(src_path.clone());
-                                                pool.return_priority(&source_config, src_guard).await;
-                                                pool.return_priority(&dest_config, dst_guard).await;
-                                                folder_skipped += 1;
-                                                skipped_total += 1;
-                                                // Emit progress and continue to next email
-                                                let skip_log = MigrationLogEntry {
-                                                    timestamp: chrono::Local::now().format("%H:%M:%S").to_string(),
-                                                    sender: email_sender.clone(),
-                                                    subject: email_subject.clone(),
-                                                    status: "skipped".to_string(),
-                                                };
-                                                emit_progress(
-                                                    "running", Some(src_path.clone()),
-                                                    Some(format!("{}/{}", folder_migrated + folder_skipped + folder_failed, folder_total)),
-                                                    migrated_total, skipped_total, failed_total,
-                                                    &folder_mappings, &started_at, start_instant.elapsed().as_secs(),
-                                                    &source_email, &dest_email, total_emails, &app_handle,
-                                                    Some(skip_log), None,
-                                                );
-                                                continue;
-                                            }
-                                        }
+                                // Gmail: the extra flags its labels become, or None for
+                                // an All Mail copy another folder already delivers.
+                                let label_flags = match gmail_labels {
+                                    Some(mapping) => {
+                                        let labels: Vec<String> = fetch
+                                            .gmail_labels()
+                                            .map(|l| l.iter().map(|s| gmail::decode_label(s)).collect())
+                                            .unwrap_or_default();
+                                        gmail::migration_flags(&labels, from_all_mail, mapping)
                                     }
+                                    None => Some(Vec::new()),
+                                };
+                                // Dedup check via Message-ID extraction from MIME
+                                let duplicate = !dest_message_ids.is_empty()
+                                    && fetch
+                                        .body()
+                                        .and_then(extract_message_id)
+                                        .is_some_and(|msg_id| dest_message_ids.contains(&msg_id));
+                                if duplicate || label_flags.is_none() {
+                                    src_guard.last_selected = Some(src_path.clone());
+                                    pool.return_priority(&source_config, src_guard).await;
+                                    pool.return_priority(&dest_config, dst_guard).await;
+                                    folder_skipped += 1;
+                                    skipped_total += 1;
+                                    // Emit progress and continue to next email
+                                    let skip_log = MigrationLogEntry {
+                                        timestamp: chrono::Local::now().format("%H:%M:%S").to_string(),
+                                        sender: email_sender.clone(),
+                                        subject: email_subject.clone(),
+                                        status: "skipped".to_string(),
+                                    };
+                                    emit_progress(
+                                        "running", Some(src_path.clone()),
+                                        Some(format!("{}/{}", folder_migrated + folder_skipped + folder_failed, folder_total)),
+                                        migrated_total, skipped_total, failed_total,
+                                        &folder_mappings, &started_at, start_instant.elapsed().as_secs(),
+                                        &source_email, &dest_email, total_emails, &app_handle,
+                                        Some(skip_log), None,
+                                    );
+                                    continue;
                                 }
 
                                 // Not a duplicate — append to destination
                                 let r = if let Some(mime_bytes) = fetch.body() {
-                                    let flags: Vec<String> = fetch.flags()
-                                        .filter_map(|f| {
-                                            use async_imap::types::Flag;
-                                            match f {
-                                                Flag::Seen => Some("\\Seen".to_string()),
-                                                Flag::Answered => Some("\\Answered".to_string()),
-                                                Flag::Flagged => Some("\\Flagged".to_string()),
-                                                Flag::Deleted => Some("\\Deleted".to_string()),
-                                                Flag::Draft => Some("\\Draft".to_string()),
-                                                Flag::Recent => None,
-                                                _ => None,
-                                            }
-                                        })
+                                    let mut flags: Vec<String> = fetch.flags()
+                                        .filter_map(|f| imap::flag_for_append(&f))
                                         .collect();
+                                    for extra in label_flags.unwrap_or_default() {
+                                        if !flags.contains(&extra) {
+                                            flags.push(extra);
+                                        }
+                                    }
                                     let flags_str = flags.join(" ");
-                                    imap::append_email(&mut dst_guard.session, &dst_path, mime_bytes, &flags_str).await
+                                    imap::append_email_at(&mut dst_guard.session, &dst_path, mime_bytes, &flags_str, fetch.internal_date()).await
                                 } else {
                                     Err(format!("No body for UID {}", uid))
                                 };
@@ -1007,7 +1093,8 @@ pub async fn run_migration(
                                 &mut dst_guard.session,
                                 gid,
                                 &dst_path,
-                                is_read,
+                                graph_flags,
+                                graph_received.as_deref(),
                             )
                             .await;
                             dst_guard.last_selected = Some(dst_path.clone());
@@ -1170,6 +1257,9 @@ pub async fn run_migration(
                         folder_migrated += 1;
                         migrated_total += 1;
                         email_status = "ok";
+                        if let Some(id) = email_id {
+                            folder_mappings[folder_idx].migrated_email_ids.push(id.clone());
+                        }
                     }
                     Err(ref first_err) if imap::is_bandwidth_limited(first_err) => {
                         // Account suspended server-side — retrying is pointless
@@ -1205,7 +1295,7 @@ pub async fn run_migration(
                                     let t = source_config.access_token.as_deref().ok_or("No source access token")?;
                                     let c = GraphClient::new(t);
                                     let mut dg = pool.get_priority(&dest_config).await?;
-                                    let r = migrate_email_graph_to_imap(&c, &mut dg.session, gid, &dst_path, is_read).await;
+                                    let r = migrate_email_graph_to_imap(&c, &mut dg.session, gid, &dst_path, graph_flags, graph_received.as_deref()).await;
                                     dg.last_selected = Some(dst_path.clone());
                                     pool.return_priority(&dest_config, dg).await;
                                     r
@@ -1244,6 +1334,9 @@ pub async fn run_migration(
                                 migrated_total += 1;
                                 email_status = "ok";
                                 info!("[migration] Email UID {} succeeded on retry", uid);
+                                if let Some(id) = email_id {
+                                    folder_mappings[folder_idx].migrated_email_ids.push(id.clone());
+                                }
                             }
                             Err(e) => {
                                 warn!("migration: email UID {} failed twice, skipping: {}", uid, e);
@@ -1303,6 +1396,9 @@ pub async fn run_migration(
         } else {
             "completed".to_string()
         };
+        if folder_mappings[folder_idx].status == "completed" {
+            folder_mappings[folder_idx].migrated_email_ids.clear();
+        }
 
         // Save checkpoint after each folder
         let checkpoint_state = MigrationState {
@@ -6,6 +6,8 @@ use std::path::PathBuf;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
 
+use chrono::{DateTime, FixedOffset};
+use mailparse::MailHeaderMap;
 use serde::Serialize;
 use tauri::{Emitter, Manager};
 use tracing::{info, warn};
@@ -46,15 +48,20 @@ pub struct LocalMsg {
 ///
 /// Tauri format:  `{uid}:2,{LETTERS}.eml`  (letters: S F R D A T)
 /// Core format:   `{uid}:{word,word}:{ts}.eml`  (words: seen,flagged,replied,draft,...)
+///
+/// Any other word in the core format is a keyword the server had set
+/// (`$forwarded`, `$label1`, `nonjunk`) and is restored as one. The daemon
+/// stores them lower-cased; IMAP keywords are case-insensitive.
 pub fn parse_local_flags(filename: &str) -> String {
-    let name = filename.strip_suffix(".eml").unwrap_or(filename);
+    let name = mailvault_core::maildir::compression::strip_suffix(filename);
+    let name = name.strip_suffix(".eml").unwrap_or(name);
 
     let mut out: Vec<&str> = Vec::new();
-    let push = |out: &mut Vec<&str>, flag: &'static str| {
+    fn push<'a>(out: &mut Vec<&'a str>, flag: &'a str) {
         if !out.contains(&flag) {
             out.push(flag);
         }
-    };
+    }
 
     if let Some(letters) = name.split(":2,").nth(1) {
         let letters = letters.split(':').next().unwrap_or("");
@@ -76,7 +83,9 @@ pub fn parse_local_flags(filename: &str) -> String {
                     "flagged" => push(&mut out, "\\Flagged"),
                     "replied" | "answered" => push(&mut out, "\\Answered"),
                     "draft" => push(&mut out, "\\Draft"),
-                    _ => {}
+                    // Local-only, or server state that can't be appended.
+                    "archived" | "trashed" | "deleted" | "recent" | "maycreate" => {}
+                    _ => push(&mut out, w),
                 }
             }
         }
@@ -84,6 +93,35 @@ pub fn parse_local_flags(filename: &str) -> String {
     out.join(" ")
 }
 
+/// The INTERNALDATE to restore a message with: its `Date:` header, offset
+/// and all. The core filename's `{ts}` is only when the vault stored the
+/// message — for an archive taken years after the mail arrived that is the
+/// wrong year — so it is the fallback for mail without a usable header.
+/// `None` lets the server stamp it — only for mail with neither.
+pub fn restore_internal_date(filename: &str, raw: &[u8]) -> Option<DateTime<FixedOffset>> {
+    if let Some(date) = header_date(raw) {
+        return Some(date);
+    }
+    let name = mailvault_core::maildir::compression::strip_suffix(filename);
+    let name = name.strip_suffix(".eml").unwrap_or(name);
+    if name.contains(":2,") {
+        return None;
+    }
+    let ts = name.splitn(3, ':').nth(2)?.parse::<i64>().ok()?;
+    DateTime::from_timestamp(ts, 0).map(|d| d.fixed_offset())
+}
+
+fn header_date(raw: &[u8]) -> Option<DateTime<FixedOffset>> {
+    let (headers, _) = mailparse::parse_headers(raw).ok()?;
+    let date = headers.get_first_value("Date")?;
+    // Keep the sender's offset when the header is strict RFC 5322; mailparse
+    // copes with the sloppier ones, in UTC.
+    DateTime::parse_from_rfc2822(date.trim()).ok().or_else(|| {
+        let ts = mailparse::dateparse(&date).ok()?;
+        DateTime::from_timestamp(ts, 0).map(|d| d.fixed_offset())
+    })
+}
+
 /// List uploadable messages in a Maildir `cur` directory. Skips trashed
 /// messages and non-message files. Returns messages sorted by UID.
 pub fn list_messages_in_dir(cur_dir: &std::path::Path) -> Vec<LocalMsg> {
@@ -211,6 +249,7 @@ pub async fn run_restore(
             .unwrap_or_default();
 
         let folder_total = msgs.len();
+        let mut appended: Vec<(u32, imap::AppendUid)> = Vec::new();
         for (idx, msg) in msgs.iter().enumerate() {
             if cancel.load(Ordering::Relaxed) {
                 pool.return_priority(&account, guard).await;
@@ -218,7 +257,7 @@ pub async fn run_restore(
                 return Ok(());
             }
 
-            let raw = match std::fs::read(&msg.path) {
+            let raw = match mailvault_core::maildir::compression::read_message(&msg.path) {
                 Ok(b) => b,
                 Err(e) => {
                     warn!("[restore] read {:?} failed: {}", msg.path, e);
@@ -234,9 +273,14 @@ pub async fn run_restore(
                 }
             }
 
-            let append = imap::append_email(&mut guard.session, folder, &raw, &msg.imap_flags);
+            let name = msg.path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+            let internal_date = restore_internal_date(&name, &raw);
+            let append = imap::append_email_at(&mut guard.session, folder, &raw, &msg.imap_flags, internal_date);
             match tokio::time::timeout(std::time::Duration::from_secs(30), append).await {
-                Ok(Ok(())) => uploaded += 1,
+                Ok(Ok(new_uid)) => {
+                    uploaded += 1;
+                    appended.extend(new_uid.map(|a| (msg.uid, a)));
+                }
                 Ok(Err(e)) => {
                     warn!("[restore] APPEND uid {} to {} failed: {}", msg.uid, folder, e);
                     failed += 1;
@@ -261,6 +305,16 @@ pub async fn run_restore(
         }
 
         pool.return_priority(&account, guard).await;
+
+        if !appended.is_empty() {
+            let cur_dir = msgs[0].path.parent().map(|p| p.to_path_buf()).unwrap_or_default();
+            let (root, account_id, folder) = (crate::vault::root(&app_handle)?, account_id.clone(), folder.clone());
+            let followed =
+                tokio::task::spawn_blocking(move || follow_appended(&root, &account_id, &folder, &cur_dir, &appended))
+                    .await
+                    .unwrap_or(0);
+            info!("[restore] {}: {} vault copies moved to their new UIDs", folder, followed);
+        }
     }
 
     info!(
@@ -271,6 +325,48 @@ pub async fn run_restore(
     Ok(())
 }
 
+/// Move the vault copies of `appended` — their old uid in `cur_dir`, and
+/// where APPEND put them — and their cached headers onto the new UIDs, as a
+/// move does, so the next sync doesn't download them again. Only when the
+/// server's UIDVALIDITY is the generation `cur_dir` is keyed under; otherwise
+/// the next sync's generation repair rebinds them. Returns how many moved.
+fn follow_appended(
+    root: &std::path::Path,
+    account_id: &str,
+    folder: &str,
+    cur_dir: &std::path::Path,
+    appended: &[(u32, imap::AppendUid)],
+) -> usize {
+    let Some(mailbox_dir) = cur_dir.parent() else { return 0 };
+    let generation = mailvault_core::maildir::read_generation(mailbox_dir);
+    let pairs: Vec<(u32, u32)> = appended
+        .iter()
+        .filter(|(_, a)| Some(a.uid_validity) == generation)
+        .map(|(old_uid, a)| (*old_uid, a.uid))
+        .collect();
+    if pairs.len() < appended.len() {
+        info!(
+            "[restore] {}: server UIDVALIDITY differs from the vault's {:?}; leaving {} messages to the next sync",
+            folder, generation, appended.len() - pairs.len(),
+        );
+    }
+    if pairs.is_empty() {
+        return 0;
+    }
+    let cache = root.join("email_cache").join(mailvault_core::header_store::cache_base_name(account_id, folder));
+    if mailvault_core::header_store::exists(&cache) {
+        if let Err(e) = mailvault_core::header_store::remap(&cache, &cache, &pairs, false) {
+            warn!("[restore] Header remap in {} failed: {}", folder, e);
+        }
+    }
+    // The vault's directory name, which may be a sanitized form of `folder`.
+    let on_disk = mailbox_dir.file_name().unwrap_or_default().to_string_lossy().to_string();
+    mailvault_core::maildir::remap(root, account_id, &on_disk, &on_disk, &pairs, false).unwrap_or_else(|e| {
+        warn!("[restore] Vault remap in {} failed: {}", folder, e);
+        0
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -290,6 +386,40 @@ mod tests {
         assert_eq!(parse_local_flags("123::1700000000.eml"), "");
     }
 
+    #[test]
+    fn test_parse_local_flags_keeps_keywords() {
+        assert_eq!(
+            parse_local_flags("7:seen,$forwarded,archived,$label1:1700000000.eml"),
+            "\\Seen $forwarded $label1"
+        );
+        assert_eq!(parse_local_flags("7:recent,deleted:1700000000.eml"), "");
+    }
+
+    #[test]
+    fn test_restore_internal_date() {
+        let raw = b"Date: Thu, 01 Jan 2026 12:00:00 +0000\r\nSubject: x\r\n\r\nbody";
+        let from_header = restore_internal_date("7:2,S.eml", raw).unwrap();
+        assert_eq!(from_header.timestamp(), 1_767_268_800);
+        let no_date = b"Subject: x\r\n\r\nbody";
+        let from_ts = restore_internal_date("7:seen:1700000000.eml", no_date).unwrap();
+        assert_eq!(from_ts.timestamp(), 1_700_000_000);
+        let compressed = restore_internal_date("7:seen:1700000000.eml.zst", no_date).unwrap();
+        assert_eq!(compressed.timestamp(), 1_700_000_000);
+        assert!(restore_internal_date("7:2,S.eml", no_date).is_none());
+    }
+
+    #[test]
+    fn test_restore_internal_date_of_mail_archived_years_later() {
+        // Sent in 2019, first archived in 2026: the filename carries 2026.
+        let raw = b"Date: Tue, 05 Mar 2019 09:30:00 +0100\r\nSubject: old\r\n\r\nbody";
+        let date = restore_internal_date("7:seen:1790000000.eml", raw).unwrap();
+        assert_eq!(date.to_rfc3339(), "2019-03-05T09:30:00+01:00");
+        // A header chrono's strict parser refuses still beats the filename.
+        let sloppy = b"Date: Tue,  5 Mar 2019 9:30:00 +0100\r\n\r\nbody";
+        let date = restore_internal_date("7:seen:1790000000.eml", sloppy).unwrap();
+        assert_eq!(date.timestamp(), 1_551_774_600);
+    }
+
     #[test]
     fn test_parse_local_flags_none() {
         assert_eq!(parse_local_flags("123.eml"), "");
@@ -315,4 +445,22 @@ mod tests {
 
         let _ = std::fs::remove_dir_all(&dir);
     }
+
+    #[test]
+    fn test_follow_appended_only_within_the_vaults_generation() {
+        let tmp = tempfile::tempdir().unwrap();
+        let mailbox_dir = tmp.path().join("Maildir").join("acc1").join("INBOX");
+        let cur = mailbox_dir.join("cur");
+        std::fs::create_dir_all(&cur).unwrap();
+        std::fs::write(cur.join("5:2,S.eml"), b"Message-ID: <a@host.test>\r\n\r\nbody").unwrap();
+        std::fs::write(cur.join("6:2,.eml"), b"Message-ID: <b@host.test>\r\n\r\nbody").unwrap();
+        mailvault_core::maildir::write_generation(&mailbox_dir, 7).unwrap();
+
+        let at = |uid_validity, uid| imap::AppendUid { uid_validity, uid };
+        let appended = [(5, at(7, 12)), (6, at(8, 13))];
+        assert_eq!(follow_appended(tmp.path(), "acc1", "INBOX", &cur, &appended), 1);
+        assert!(cur.join("12:2,S.eml").exists() && !cur.join("5:2,S.eml").exists());
+        // Another generation's UID means nothing here; the file stays put.
+        assert!(cur.join("6:2,.eml").exists());
+    }
 }
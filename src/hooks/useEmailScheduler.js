@@ -3,6 +3,7 @@ import { useMailStore } from '../stores/mailStore';
 import { useAccountStore } from '../stores/accountStore';
 import { useMessageListStore } from '../stores/messageListStore';
 import { useSettingsStore } from '../stores/settingsStore';
+import { getDaemonHealth } from '../services/transport';
 
 // Tauri invoke for notifications and badge
 const invoke = window.__TAURI__?.core?.invoke;
@@ -171,7 +172,10 @@ export function useEmailScheduler() {
     const intervalMs = refreshInterval * 60 * 1000;
     console.log(`[scheduler] Setting up refresh interval: ${refreshInterval} minutes`);
 
+    // While the daemon is up it syncs every account on its own schedule; a
+    // second loop here would only sync everything twice.
     intervalRef.current = setInterval(() => {
+      if (getDaemonHealth().alive) return;
       console.log('[scheduler] Interval triggered');
       doRefresh();
     }, intervalMs);
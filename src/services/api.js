@@ -208,6 +208,26 @@ export async function fetchEmailLight(account, uid, mailbox = 'INBOX', accountId
   return email;
 }
 
+/**
+ * The email for reading without downloading its attachments: header, text
+ * parts, and `parts` — the MIME parts left on the server, by `section`.
+ * Tauri only; there is no whole message here to cache.
+ */
+export async function fetchEmailParts(account, uid, mailbox = 'INBOX') {
+  const data = await tauriInvoke('imap_get_email_parts', { account, uid, mailbox });
+  return data.email;
+}
+
+/**
+ * Save one MIME part (a `section` from `fetchEmailParts`), or the whole
+ * message when `section` is null, to `destPath`, streamed in chunks.
+ *
+ * @returns {Promise<{ success: boolean, path: string, bytes: number }>}
+ */
+export async function savePartToFile(account, uid, section, destPath, mailbox = 'INBOX') {
+  return tauriInvoke('imap_save_part', { account, uid, mailbox, section, destPath });
+}
+
 export async function updateEmailFlags(account, uid, flags, action = 'add', mailbox = 'INBOX') {
   if (IS_TAURI) {
     return tauriInvoke('imap_set_flags', { account, uid, mailbox, flags, action });
@@ -276,7 +296,7 @@ export async function disconnect(account) {
 
 export async function moveEmails(account, uids, sourceMailbox, targetMailbox) {
   if (IS_TAURI) {
-    return tauriInvoke('imap_move_emails', { account, uids, sourceMailbox, targetMailbox });
+    return tauriInvoke('imap_move_emails', { account, accountId: account.id, uids, sourceMailbox, targetMailbox });
   }
   return httpRequest('/move-emails', {
     method: 'POST',
@@ -512,6 +532,14 @@ export async function backupCancel() {
   return tauriInvoke('backup_cancel', {});
 }
 
+export async function vaultVerify(accountId = null, accountJson = null, refetch = true) {
+  return tauriInvoke('vault_verify', { accountId, accountJson, refetch });
+}
+
+export async function vaultUnlock(passphrase) {
+  return tauriInvoke('vault_unlock', { passphrase });
+}
+
 export async function sendNotification(title, body) {
   return tauriInvoke('send_notification', { title, body });
 }
@@ -568,11 +596,14 @@ export async function clearMigrationState() {
   return await tauriInvoke('clear_migration_state_cmd');
 }
 
-export async function getFolderMappings(sourceAccount, destAccount, sourceTransport, destTransport) {
+// labelMapping applies to Gmail → non-Gmail IMAP only: 'folders' (default)
+// copies each label as a folder, 'keywords' copies each message once with its
+// labels as keywords.
+export async function getFolderMappings(sourceAccount, destAccount, sourceTransport, destTransport, labelMapping = 'folders') {
   return await tauriInvoke('get_folder_mappings', {
     sourceAccount: JSON.stringify(sourceAccount),
     destAccount: JSON.stringify(destAccount),
-    sourceTransport, destTransport
+    sourceTransport, destTransport, labelMapping
   });
 }
 
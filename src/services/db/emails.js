@@ -223,8 +223,8 @@ export async function getLocalIndexProvenance(accountId, mailbox) {
  * Load archived email headers for instant display.
  *
  * Strategy (fast path first):
- * 1. Try sidecar cache (email_cache/{uid}.json) — already populated by IMAP sync.
- *    Reads only the specific UID files we need. Instant for most archived emails.
+ * 1. Try the header cache (email_cache/…/headers.log) — already populated by IMAP sync.
+ *    Returns only the specific UIDs we need. Instant for most archived emails.
  * 2. For UIDs not in sidecar: try archived_headers.json (populated after first full load)
  * 3. Last resort: batch-load from .eml files (slow — MIME parsing)
  * 4. Save results to archived_headers.json for next time
@@ -23,6 +23,238 @@ export async function syncNow(account, mailbox = 'INBOX') {
   return daemonCall('sync.now', { account, mailbox, autoClassify });
 }
 
+/**
+ * Trigger a sync of every folder on an account: INBOX, Sent, then the rest by
+ * recent activity. Progress shows in `getSyncStatus` as `current_folder`,
+ * `folders_done` and `folders_total`.
+ *
+ * @param {object} account - same shape as for `syncNow`
+ * @returns {Promise<{ started: boolean, accountId: string, mailbox: '*' }>}
+ */
+export async function syncAllFolders(account) {
+  const autoClassify = hasPremiumAccess(useSettingsStore.getState().billingProfile);
+  return daemonCall('sync.now', { account, allFolders: true, autoClassify });
+}
+
+/**
+ * Folders the last all-folders sync saw, and those it found deleted on the
+ * server. A deleted folder's mail stays in the local vault.
+ *
+ * On Gmail, `gmailAllMail` names the one folder that holds every message;
+ * label folders are listed but not synced (see `getLabels`).
+ *
+ * @param {string} accountId
+ * @returns {Promise<{
+ *   folders: Array<{ path: string, lastChangeAt: number|null }>,
+ *   removed: Array<{ path: string, removedAt: number }>,
+ *   gmailAllMail?: string
+ * }>}
+ */
+export async function getSyncedFolders(accountId) {
+  return daemonCall('sync.folders', { accountId });
+}
+
+/**
+ * Gmail labels from the daemon's label index. Gmail accounts sync each message
+ * once, under `mailbox` (All Mail); a label is a filter over it, not a folder.
+ * Without `label`: every label with its message count. With `label`: the All
+ * Mail UIDs carrying it. Empty for non-Gmail accounts.
+ *
+ * @param {string} accountId
+ * @param {string} [label] - e.g. `'\\Inbox'`, `'\\Starred'` or a user label
+ * @returns {Promise<{ mailbox: string, labels?: Array<{ label: string, count: number }>, uids?: number[] }>}
+ */
+export async function getLabels(accountId, label) {
+  return daemonCall('sync.labels', label ? { accountId, label } : { accountId });
+}
+
+/**
+ * Full-text search over the local vault — every account, including mail
+ * deleted from the server. Same syntax as server search: words, `"phrases"`,
+ * `prefix*`, `from:` `to:` `cc:` `subject:` `has:attachment` `larger:5M`
+ * `is:unread` `in:folder` `before:`/`after:`, with OR, NOT/`-` and
+ * parentheses. Best match first; rejects a query that doesn't parse.
+ *
+ * @param {string} query
+ * @param {{ accountIds?: string[], limit?: number }} [options]
+ * @returns {Promise<{ hits: Array<{ accountId: string, mailbox: string, uid: number, score: number, subject: string, from: string, date: string }> }>}
+ */
+export async function searchLocal(query, { accountIds, limit } = {}) {
+  return daemonCall('search.query', { query, accountIds, limit });
+}
+
+/**
+ * The Maildir layout new mailboxes are created in: `'vault'`, the app's own
+ * `{uid}:{flags}:{timestamp}.eml` names, or `'standard'` — `tmp/`→`new/`/`cur/`
+ * delivery with `:2,` flags and a `dovecot-uidlist`, which mutt, notmuch,
+ * Dovecot and offlineimap can read.
+ *
+ * @returns {Promise<{ layout: 'vault'|'standard' }>}
+ */
+export async function getMaildirLayout() {
+  return daemonCall('maildir.layout_get', {});
+}
+
+/**
+ * Switch the vault to `layout` and convert every existing mailbox. Renames
+ * every message file; safe to run again if it was interrupted.
+ *
+ * @param {'vault'|'standard'} layout
+ * @returns {Promise<{ layout: string, mailboxes: number, renamed: number, errors: number }>}
+ */
+export async function setMaildirLayout(layout) {
+  return daemonCall('maildir.layout_set', { layout });
+}
+
+/**
+ * How new messages are stored: `'none'`, or compressed as `.eml.zst`/`.eml.gz`.
+ * `converting` is set while existing messages are being rewritten to match.
+ *
+ * @returns {Promise<{ compression: 'none'|'zstd'|'gzip', converting: boolean, pending: boolean, lastConversion: object|null }>}
+ */
+export async function getMaildirCompression() {
+  return daemonCall('maildir.compression_get', {});
+}
+
+/**
+ * Store new messages in `compression` and convert the existing ones in the
+ * background. Returns at once; the vault stays readable throughout.
+ *
+ * @param {'none'|'zstd'|'gzip'} compression
+ * @returns {Promise<{ compression: string, converting: boolean }>}
+ */
+export async function setMaildirCompression(compression) {
+  return daemonCall('maildir.compression_set', { compression });
+}
+
+/**
+ * Every stored copy of each Message-ID across all accounts and folders, for
+ * "where else is this message" and cross-folder threads. Ids are matched with
+ * or without angle brackets; one with no copy maps to an empty list.
+ *
+ * @param {string[]} messageIds
+ * @returns {Promise<{ locations: Object<string, Array<{ accountId: string, mailbox: string, uid: number, path: string }>> }>}
+ */
+export async function locateMessages(messageIds) {
+  return daemonCall('maildir.locate', { messageIds });
+}
+
+/**
+ * A whole conversation across every folder and the vault, as a tree — by
+ * thread id, or by one of its messages. `thread` is null when there is none.
+ *
+ * @param {string} accountId
+ * @param {{ threadId: string } | { mailbox: string, uid: number }} which
+ * @returns {Promise<{ thread: { threadId: string, subject: string, messageCount: number, firstDate: number, lastDate: number, messages: object[] } | null }>}
+ */
+export async function getThread(accountId, which) {
+  return daemonCall('threads.get', { accountId, ...which });
+}
+
+/**
+ * The thread each of `uids` in `mailbox` belongs to.
+ *
+ * @param {string} accountId
+ * @param {string} mailbox
+ * @param {number[]} uids
+ * @returns {Promise<{ threads: Object<string, string> }>}
+ */
+export async function getThreadMembership(accountId, mailbox, uids) {
+  return daemonCall('threads.membership', { accountId, mailbox, uids });
+}
+
+/**
+ * Rebuild an account's thread index from its header caches and the vault.
+ *
+ * @param {string} accountId
+ * @returns {Promise<{ messages: number }>}
+ */
+export async function rebuildThreads(accountId) {
+  return daemonCall('threads.rebuild', { accountId });
+}
+
+/**
+ * Whether large attachments are kept once in a shared store instead of in
+ * every message, with the last sweep's figures (`stats.referencedBytes`,
+ * `stats.storeBytes`) — null before the first one.
+ *
+ * @returns {Promise<{ enabled: boolean, stats: object|null, converting: boolean, pending: boolean }>}
+ */
+export async function getMaildirDedup() {
+  return daemonCall('maildir.dedup_get', {});
+}
+
+/**
+ * Turn attachment dedup on or off. Existing messages are converted in the
+ * background, as with a compression change.
+ *
+ * @param {boolean} enabled
+ */
+export async function setMaildirDedup(enabled) {
+  return daemonCall('maildir.dedup_set', { enabled });
+}
+
+/**
+ * Whether the vault is encrypted at rest, whether the daemon has it unlocked,
+ * and how its key can be unlocked. `converting` is set while files are being
+ * sealed, re-sealed after a rotation, or decrypted.
+ *
+ * @returns {Promise<{ encrypted: boolean, unlocked: boolean, keyId?: string, keyCreatedAt?: number, passphrase: boolean, keyring: boolean, keys: number, converting: boolean, pending: boolean }>}
+ */
+export async function getVaultEncryption() {
+  return daemonCall('vault.encryption_get', {});
+}
+
+/**
+ * Turn encryption at rest on. The vault key is wrapped with `passphrase`,
+ * kept in the OS keyring, or both; existing files are sealed in the background.
+ *
+ * @param {{ passphrase?: string, keyring?: boolean }} options
+ * @returns {Promise<{ keyId: string, converting: boolean }>}
+ */
+export async function enableVaultEncryption({ passphrase, keyring = false } = {}) {
+  return daemonCall('vault.encryption_enable', { passphrase, keyring });
+}
+
+/**
+ * Replace the vault key and re-seal every file under the new one. A
+ * passphrase-wrapped key needs a (possibly new) passphrase.
+ *
+ * @param {string} [passphrase]
+ * @returns {Promise<{ keyId: string, converting: boolean }>}
+ */
+export async function rotateVaultKey(passphrase) {
+  return daemonCall('vault.encryption_rotate', { passphrase });
+}
+
+/** Turn encryption at rest off and decrypt the vault in the background. */
+export async function disableVaultEncryption() {
+  return daemonCall('vault.encryption_disable', {});
+}
+
+/**
+ * Unlock a passphrase-protected vault in the daemon.
+ *
+ * @param {string} passphrase
+ * @returns {Promise<{ unlocked: boolean }>}
+ */
+export async function unlockVault(passphrase) {
+  return daemonCall('vault.unlock', { passphrase });
+}
+
+/**
+ * Re-hash every stored message against its mailbox manifest. Corrupt files
+ * are moved to `quarantine/`, put back from the backup mirror at
+ * `backupPath` when its copy matches, and otherwise re-fetched from the
+ * server unless `refetch` is false. Missing files are only reported.
+ *
+ * @param {{ accountId?: string, backupPath?: string, refetch?: boolean }} [options]
+ * @returns {Promise<{ mailboxes, checked, unrecorded, corrupt, missing, restored, backup, errors, refetched, notOnServer, refetchSkipped }>}
+ */
+export async function verifyVault({ accountId, backupPath, refetch = true } = {}) {
+  return daemonCall('vault.verify', { accountId, backupPath, refetch });
+}
+
 /**
  * Wait for a sync to complete. The daemon holds the connection open
  * until sync finishes or times out — no polling needed.
@@ -35,14 +267,110 @@ export async function waitForSync(accountId, timeoutMs = 30000) {
   return daemonCall('sync.wait', { accountId, timeoutMs });
 }
 
+/**
+ * How full the account is on the server (IMAP QUOTA, or Graph folder sizes),
+ * beside what the vault holds. `quota` is null when the server reports none;
+ * `bytesToFree` is what has to go to get under the lowest alert threshold.
+ *
+ * @param {object} account - same shape as for `syncNow`
+ * @returns {Promise<{ quota: { source: string, usedBytes: number|null, limitBytes: number|null } | null, percentUsed: number|null, targetPercent: number|null, bytesToFree: number|null, vault: object }>}
+ */
+export async function getQuota(account) {
+  return daemonCall('quota.get', { account });
+}
+
+/**
+ * Wait for quota alerts newer than `afterSeq`: raised after a sync the first
+ * time usage reaches each threshold. Resolves every one the daemon still
+ * holds, oldest first; empty on timeout.
+ *
+ * @param {number} [afterSeq=0] - `seq` of the last alert seen
+ * @param {number} [timeoutMs=30000]
+ * @returns {Promise<Array<{ seq: number, accountId: string, threshold: number, percentUsed: number, bytesToFree: number|null, quota: object }>>}
+ */
+export async function waitForQuotaAlert(afterSeq = 0, timeoutMs = 30000) {
+  return daemonCall('quota.wait', { afterSeq, timeoutMs });
+}
+
+/**
+ * Quota alert settings: `{ thresholds: number[], limits: { [accountId]: bytes }, checkIntervalSecs }`.
+ * `limits` supplies the mailbox size for servers that report usage only (Graph).
+ */
+export async function getQuotaAlerts() {
+  return daemonCall('quota.alerts_get', {});
+}
+
+export async function setQuotaAlerts(settings) {
+  return daemonCall('quota.alerts_set', settings);
+}
+
 /**
  * Current sync state for an account, including `backfilling` — true while the
- * daemon is still filling a partly-cached mailbox from the server.
+ * daemon is still filling a partly-cached mailbox from the server — and
+ * `watching` — true while it holds an IDLE connection on the account's INBOX.
  *
  * @param {string} accountId
- * @returns {Promise<{ status, backfilling: boolean, total_emails?: number }>}
+ * @returns {Promise<{ status, backfilling: boolean, watching: boolean, total_emails?: number }>}
  */
 export async function getSyncStatus(accountId) {
   return daemonCall('sync.status', { accountId });
 }
 
+/**
+ * Stop the daemon's INBOX watcher and scheduled syncs for an account (account
+ * removed or signed out). `syncNow` starts both again.
+ *
+ * @param {string} accountId
+ * @returns {Promise<{ stopped: boolean }>}
+ */
+export async function stopWatching(accountId) {
+  return daemonCall('sync.unwatch', { accountId });
+}
+
+/**
+ * Get the daemon's sync schedule and, per registered account/folder, when it
+ * runs next and its consecutive failure count.
+ *
+ * @returns {Promise<{
+ *   schedule: { defaultIntervalSecs: number, accounts: Object<string, Object<string, number>> },
+ *   status: Array<{ accountId: string, folder: string, intervalSecs: number, nextRunInSecs: number, failures: number }>
+ * }>}
+ */
+export async function getSyncSchedule() {
+  return daemonCall('sync.schedule_get', {});
+}
+
+/**
+ * Replace the daemon's sync schedule. `accounts` maps accountId → folder →
+ * interval in seconds (0 = off, otherwise at least 60). Accounts without an
+ * entry sync INBOX every `defaultIntervalSecs`.
+ *
+ * @param {{ defaultIntervalSecs: number, accounts?: Object<string, Object<string, number>> }} schedule
+ * @returns {Promise<{ saved: boolean }>}
+ */
+export async function setSyncSchedule(schedule) {
+  return daemonCall('sync.schedule_set', schedule);
+}
+
+/**
+ * Get the daemon's body archiving policy — which messages each sync stores in
+ * full in the local vault without the user opening them.
+ *
+ * @returns {Promise<{ defaultRule: object, accounts: Object<string, Object<string, object>> }>}
+ */
+export async function getArchivePolicy() {
+  return daemonCall('sync.archive_policy_get', {});
+}
+
+/**
+ * Replace the body archiving policy. A rule is `{ mode: 'off' }`,
+ * `{ mode: 'all' }`, `{ mode: 'underSize', maxMb }` or
+ * `{ mode: 'newerThan', days }`. Per account, folder `'*'` covers every
+ * folder not listed; accounts not listed use `defaultRule`.
+ *
+ * @param {{ defaultRule?: object, accounts?: Object<string, Object<string, object>> }} policy
+ * @returns {Promise<{ saved: boolean }>}
+ */
+export async function setArchivePolicy(policy) {
+  return daemonCall('sync.archive_policy_set', policy);
+}
@@ -4,6 +4,7 @@ import * as db from '../db';
 import * as api from '../api';
 import { useSettingsStore } from '../../stores/settingsStore';
 import { isGraphAccount } from '../graphConfig';
+import { stopWatching } from '../syncService';
 import { invalidateRestoreDescriptors as _invalidateRestore, clearGraphIdMap as _clearGraphIdMap } from '../cacheManager';
 
 
@@ -20,6 +21,12 @@ export async function removeAccount(accountId) {
     } catch (e) {
       // Ignore disconnect errors
     }
+    // The daemon keeps an IDLE connection on INBOX until told otherwise.
+    try {
+      await stopWatching(accountId);
+    } catch (e) {
+      // Daemon not running — nothing to stop
+    }
   }
 
   await db.deleteAccount(accountId);